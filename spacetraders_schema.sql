@@ -74,6 +74,22 @@ CREATE TABLE public.market_trades (
 
 ALTER TABLE public.market_trades OWNER TO postgres;
 
+--
+-- Name: construction_deliveries; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.construction_deliveries (
+    reset_id text NOT NULL,
+    "timestamp" timestamp with time zone NOT NULL,
+    waypoint_symbol text NOT NULL,
+    ship_symbol text NOT NULL,
+    trade_symbol text NOT NULL,
+    units integer NOT NULL
+);
+
+
+ALTER TABLE public.construction_deliveries OWNER TO postgres;
+
 --
 -- Name: general_lookup; Type: TABLE; Schema: public; Owner: postgres
 --
@@ -88,6 +104,22 @@ CREATE TABLE public.general_lookup (
 
 ALTER TABLE public.general_lookup OWNER TO postgres;
 
+--
+-- Name: job_assignments; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.job_assignments (
+    reset_id text NOT NULL,
+    callsign text NOT NULL,
+    ship_symbol text NOT NULL,
+    job_id text NOT NULL,
+    assigned_at timestamp with time zone NOT NULL,
+    unassigned_at timestamp with time zone
+);
+
+
+ALTER TABLE public.job_assignments OWNER TO postgres;
+
 --
 -- Name: jumpgate_connections; Type: TABLE; Schema: public; Owner: postgres
 --
@@ -104,6 +136,67 @@ CREATE TABLE public.jumpgate_connections (
 
 ALTER TABLE public.jumpgate_connections OWNER TO postgres;
 
+--
+-- Name: net_worth_history; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.net_worth_history (
+    reset_id text NOT NULL,
+    callsign text NOT NULL,
+    "timestamp" timestamp with time zone NOT NULL,
+    credits bigint NOT NULL,
+    cargo_value bigint NOT NULL,
+    ship_value bigint NOT NULL,
+    total bigint NOT NULL
+);
+
+
+ALTER TABLE public.net_worth_history OWNER TO postgres;
+
+--
+-- Name: ship_models; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.ship_models (
+    reset_id text NOT NULL,
+    ship_type text NOT NULL,
+    frame text NOT NULL,
+    reactor text NOT NULL,
+    engine text NOT NULL,
+    modules text[] NOT NULL,
+    mounts text[] NOT NULL,
+    cargo_capacity integer NOT NULL,
+    fuel_capacity integer NOT NULL,
+    speed integer NOT NULL,
+    min_price integer NOT NULL,
+    max_price integer NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+
+ALTER TABLE public.ship_models OWNER TO postgres;
+
+--
+-- Name: shipyard_listings; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.shipyard_listings (
+    "timestamp" timestamp with time zone NOT NULL,
+    shipyard_symbol text NOT NULL,
+    ship_type text NOT NULL,
+    supply text NOT NULL,
+    activity text,
+    purchase_price integer NOT NULL,
+    frame text NOT NULL,
+    reactor text NOT NULL,
+    engine text NOT NULL,
+    modules text[] NOT NULL,
+    mounts text[] NOT NULL
+);
+
+
+ALTER TABLE public.shipyard_listings OWNER TO postgres;
+
 --
 -- Name: market_trades_id_seq; Type: SEQUENCE; Schema: public; Owner: postgres
 --
@@ -125,6 +218,21 @@ ALTER SEQUENCE public.market_trades_id_seq OWNER TO postgres;
 ALTER SEQUENCE public.market_trades_id_seq OWNED BY public.market_trades.id;
 
 
+--
+-- Name: survey_consumption; Type: TABLE; Schema: public; Owner: postgres
+--
+
+CREATE TABLE public.survey_consumption (
+    reset_id text NOT NULL,
+    owner text NOT NULL,
+    consumer text NOT NULL,
+    extractions bigint NOT NULL,
+    units bigint NOT NULL
+);
+
+
+ALTER TABLE public.survey_consumption OWNER TO postgres;
+
 --
 -- Name: surveys; Type: TABLE; Schema: public; Owner: postgres
 --
@@ -135,7 +243,8 @@ CREATE TABLE public.surveys (
     survey json NOT NULL,
     asteroid_symbol text NOT NULL,
     inserted_at timestamp with time zone NOT NULL,
-    expires_at timestamp with time zone NOT NULL
+    expires_at timestamp with time zone NOT NULL,
+    owner text DEFAULT ''::text NOT NULL
 );
 
 
@@ -193,7 +302,11 @@ CREATE TABLE public.waypoint_details (
     is_uncharted boolean NOT NULL,
     created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
     updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
-    is_under_construction boolean NOT NULL
+    is_under_construction boolean NOT NULL,
+    modifiers text[] DEFAULT '{}'::text[] NOT NULL,
+    orbits text,
+    chart_submitted_by text,
+    chart_submitted_on timestamp with time zone
 );
 
 
@@ -287,6 +400,14 @@ ALTER TABLE ONLY public.waypoint_details ALTER COLUMN id SET DEFAULT nextval('pu
 ALTER TABLE ONLY public.waypoints ALTER COLUMN id SET DEFAULT nextval('public.waypoints_id_seq'::regclass);
 
 
+--
+-- Name: construction_deliveries construction_deliveries_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.construction_deliveries
+    ADD CONSTRAINT construction_deliveries_pkey PRIMARY KEY (reset_id, ship_symbol, "timestamp");
+
+
 --
 -- Name: general_lookup general_lookup_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
 --
@@ -295,6 +416,14 @@ ALTER TABLE ONLY public.general_lookup
     ADD CONSTRAINT general_lookup_pkey PRIMARY KEY (reset_id, key);
 
 
+--
+-- Name: job_assignments job_assignments_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.job_assignments
+    ADD CONSTRAINT job_assignments_pkey PRIMARY KEY (reset_id, callsign, ship_symbol, assigned_at);
+
+
 --
 -- Name: jumpgate_connections jumpgate_connections_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
 --
@@ -316,7 +445,39 @@ ALTER TABLE ONLY public.market_trades
 --
 
 ALTER TABLE ONLY public.market_transactions
-    ADD CONSTRAINT market_transactions_pkey PRIMARY KEY (market_symbol, "timestamp");
+    ADD CONSTRAINT market_transactions_pkey PRIMARY KEY (market_symbol, "timestamp", ship_symbol);
+
+
+--
+-- Name: net_worth_history net_worth_history_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.net_worth_history
+    ADD CONSTRAINT net_worth_history_pkey PRIMARY KEY (reset_id, callsign, "timestamp");
+
+
+--
+-- Name: ship_models ship_models_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.ship_models
+    ADD CONSTRAINT ship_models_pkey PRIMARY KEY (reset_id, ship_type);
+
+
+--
+-- Name: shipyard_listings shipyard_listings_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.shipyard_listings
+    ADD CONSTRAINT shipyard_listings_pkey PRIMARY KEY (shipyard_symbol, ship_type, "timestamp");
+
+
+--
+-- Name: survey_consumption survey_consumption_pkey; Type: CONSTRAINT; Schema: public; Owner: postgres
+--
+
+ALTER TABLE ONLY public.survey_consumption
+    ADD CONSTRAINT survey_consumption_pkey PRIMARY KEY (reset_id, owner, consumer);
 
 
 --
@@ -351,6 +512,20 @@ ALTER TABLE ONLY public.waypoints
     ADD CONSTRAINT waypoints_pkey PRIMARY KEY (id);
 
 
+--
+-- Name: job_assignments_open_idx; Type: INDEX; Schema: public; Owner: postgres
+--
+
+CREATE UNIQUE INDEX job_assignments_open_idx ON public.job_assignments USING btree (reset_id, callsign, ship_symbol) WHERE (unassigned_at IS NULL);
+
+
+--
+-- Name: market_trades_market_symbol_idx; Type: INDEX; Schema: public; Owner: postgres
+--
+
+CREATE INDEX market_trades_market_symbol_idx ON public.market_trades USING btree (market_symbol, symbol, "timestamp");
+
+
 --
 -- Name: market_trades_timestamp_idx; Type: INDEX; Schema: public; Owner: postgres
 --
@@ -358,6 +533,13 @@ ALTER TABLE ONLY public.waypoints
 CREATE INDEX market_trades_timestamp_idx ON public.market_trades USING btree ("timestamp" DESC);
 
 
+--
+-- Name: market_transactions_market_symbol_idx; Type: INDEX; Schema: public; Owner: postgres
+--
+
+CREATE INDEX market_transactions_market_symbol_idx ON public.market_transactions USING btree (market_symbol, symbol, "timestamp");
+
+
 --
 -- Name: market_transactions_timestamp_idx; Type: INDEX; Schema: public; Owner: postgres
 --
@@ -365,6 +547,13 @@ CREATE INDEX market_trades_timestamp_idx ON public.market_trades USING btree ("t
 CREATE INDEX market_transactions_timestamp_idx ON public.market_transactions USING btree ("timestamp" DESC);
 
 
+--
+-- Name: shipyard_listings_ship_type_idx; Type: INDEX; Schema: public; Owner: postgres
+--
+
+CREATE INDEX shipyard_listings_ship_type_idx ON public.shipyard_listings USING btree (ship_type, "timestamp" DESC);
+
+
 --
 -- Name: systems_unique_idx; Type: INDEX; Schema: public; Owner: postgres
 --
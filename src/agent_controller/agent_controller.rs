@@ -1,43 +1,90 @@
-use super::ledger::Ledger;
+use super::arrival_scheduler::ArrivalScheduler;
+use super::chart_queue::ChartQueue;
+use super::convoy::{route_groups, ConvoyArrival, ConvoyShip, DEPARTURE_STAGGER_MS};
+use super::credit_forecast::{affordable_times, CreditForecast, PurchaseForecast};
+use super::event_bus::{EventBus, EventSubscriber, SubscriberMetrics, EVENT_CAPACITY};
+use super::expansion::{
+    estimate_candidate, rank_candidates, select_expansion, ExpansionCandidate, ExpansionOverride,
+    OnboardingProgress, OnboardingStep, MAX_CANDIDATES, MAX_EXPANSIONS,
+};
+use super::goals::{merge_default_goals, Goal, GoalStatus, STARTING_SYSTEM_CREDITS_GOAL};
+use super::hauler_autoscaler::{HaulerAutoscaler, TaskBacklog};
+use super::job_filter::JobFilter;
+use super::ledger::{
+    crossed_thresholds, new_milestones, Ledger, LedgerSnapshot, NetWorth, SHIP_SPEND,
+};
+use super::ship_price_watch::price_threshold;
+use super::ship_status::{skip_reason, ShipState, ShipStateUpdate, ShipStatus, ShipStatusUpdate};
+use super::ship_updates::{ShipDiff, ShipUpdateCoalescer, SHIP_UPDATE_WINDOW};
+use super::shipyard_cover::{cover_assignments, RoamingProbe};
+use super::strategy::Strategy;
+use super::wind_down::{self, WindDown, WindDownStatus};
+use crate::alerts::ALERTS;
 use crate::api_client::api_models::WaypointDetailed;
+use crate::api_client::rate_limiter::RateLimitMetrics;
 use crate::broker::{CargoBroker, TransferActor};
+use crate::cargo_valuer::{value_cargo, CargoValuer};
 use crate::config::CONFIG;
 use crate::models::{ShipNavStatus::*, *};
+use crate::ops_report::OpsReport;
 use crate::ship_config::{
-    ship_config_capital_system, ship_config_lategame, ship_config_no_gate,
+    autoscaled_haulers, ship_config_capital_system, ship_config_lategame, ship_config_no_gate,
     ship_config_starter_system,
 };
+use crate::ship_scripts::custom::ShipScriptRegistry;
 use crate::survey_manager::SurveyManager;
+use crate::universe::ship_catalog::ShipCatalog;
 use crate::universe::WaypointFilter;
+use crate::util::panic_message;
 use crate::{
-    api_client::ApiClient,
-    db::DbClient,
+    api_client::{ApiClient, ApiError},
+    db::{ok_or_warn, DbClient},
     models::{Agent, Ship, ShipBehaviour, ShipConfig, SystemSymbol, WaypointSymbol},
+    pathfinding::Urgency,
     ship_controller::ShipController,
     ship_scripts,
     tasks::LogisticTaskManager,
-    universe::Universe,
+    universe::UniverseHandle,
 };
+use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
-use futures::future::BoxFuture;
+use futures::future::{join_all, BoxFuture};
 use futures::stream::FuturesUnordered;
 use log::*;
 use pathfinding::directed::dijkstra::dijkstra_all;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::ops::Deref;
+use std::cmp::min;
+use std::collections::BTreeMap;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use strum::EnumString;
-use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+
+const NET_WORTH_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+// Upcoming purchases covered by the credit forecast
+const FORECAST_PURCHASES: usize = 5;
+// Pause before restarting a ship whose script failed on an API error
+const SCRIPT_RESTART_SECS: u64 = 60;
+// Held in place of the job id by unassigned ships travelling in a convoy
+const CONVOY_JOB_ID: &str = "convoy";
 
 #[derive(Clone, Debug)]
 pub enum Event {
-    ShipUpdate(Ship),
+    // changed fields only, coalesced per ship over SHIP_UPDATE_WINDOW
+    ShipUpdate(Arc<ShipDiff>),
     AgentUpdate(Agent),
+    GoalCompleted(Goal),
+    NetWorthMilestone(i64),
+    OpsReport(Arc<OpsReport>),
+    ShipStatus(ShipStatusUpdate),
+    ShipState(ShipStateUpdate),
+    Onboarding(OnboardingProgress),
+    ConvoyArrived(ConvoyArrival),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum BuyShipResult {
     Bought(String),
     FailedNeverPurchase,
@@ -47,6 +94,24 @@ enum BuyShipResult {
     // we can return a waypoint symbol to indicate a task should be created
     // to go there
     FailedNoPurchaser(Option<WaypointSymbol>),
+    // the API rejected the purchase, even after refreshing the shipyard
+    FailedRejected,
+    // the price is above its recent percentile, wait for it to drop
+    FailedPriceHigh,
+    // a request failed, e.g. the server is down
+    FailedApiError,
+}
+
+// Shipyard prices and stock drift, so old data is refreshed before buying
+fn is_shipyard_stale(
+    refreshed: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    stale_mins: i64,
+) -> bool {
+    match refreshed {
+        Some(refreshed) => now - refreshed >= chrono::Duration::try_minutes(stale_mins).unwrap(),
+        None => true,
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, EnumString)]
@@ -77,34 +142,188 @@ impl Default for AgentState {
     }
 }
 
+// Era transition given the current goal state
+fn next_era(current_era: AgentEra, goals: &[GoalStatus], strategy: Strategy) -> Option<AgentEra> {
+    let is_complete = |goal: &Goal| goals.iter().any(|g| g.goal == *goal && g.is_complete());
+    match current_era {
+        // Conditions for going to mid:
+        // - 800k credits available
+        AgentEra::StartingSystem1 => is_complete(&Goal::ReachCredits(STARTING_SYSTEM_CREDITS_GOAL))
+            .then_some(AgentEra::StartingSystem2),
+        // - jump gate finished, unless the strategy stays in the starting system
+        AgentEra::StartingSystem2 => (!strategy.no_gate() && is_complete(&Goal::FinishJumpGate))
+            .then_some(AgentEra::InterSystem1),
+        AgentEra::InterSystem1 => None,
+        AgentEra::InterSystem2 => None,
+    }
+}
+
+// First unassigned job matching the ship model
+fn select_job<'a>(
+    ship_model: &str,
+    ship_config: &'a [ShipConfig],
+    is_assigned: impl Fn(&str) -> bool,
+) -> Option<&'a ShipConfig> {
+    ship_config
+        .iter()
+        .find(|job| !is_assigned(&job.id) && job.ship_model == ship_model)
+}
+
+// Where a ship goes once its current script reaches a safe point
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferTarget {
+    Job(String),
+    Salvage,
+    // unassigned, no script
+    Idle,
+}
+
+// New targets for running ships whose job no longer exists
+fn plan_rebalance(
+    stale: &[(String, String)],
+    ship_config: &[ShipConfig],
+    is_assigned: impl Fn(&str) -> bool,
+    scrap_unassigned: bool,
+) -> Vec<(String, TransferTarget)> {
+    let mut claimed: Vec<String> = vec![];
+    stale
+        .iter()
+        .map(|(ship_symbol, ship_model)| {
+            let job = select_job(ship_model, ship_config, |job_id| {
+                is_assigned(job_id) || claimed.iter().any(|c| c == job_id)
+            });
+            let target = match job {
+                Some(job) => {
+                    claimed.push(job.id.clone());
+                    TransferTarget::Job(job.id.clone())
+                }
+                None if scrap_unassigned => TransferTarget::Salvage,
+                None => TransferTarget::Idle,
+            };
+            (ship_symbol.clone(), target)
+        })
+        .collect()
+}
+
+// A ship off a stale job can only be unassigned on the spot if that can't strand its cargo,
+// an arrival or a transfer
+fn safe_to_unassign(ship: &Ship, transfer_pending: bool, now: DateTime<Utc>) -> bool {
+    ship.cargo.units == 0 && ship.nav.route.arrival < now && !transfer_pending
+}
+
+fn job_credit_reservation(job: &ShipConfig, catalog: &ShipCatalog) -> i64 {
+    match &job.behaviour {
+        ShipBehaviour::Logistics(_) => catalog.cargo_capacity(&job.ship_model).unwrap_or(0) * 5000,
+        _ => 0,
+    }
+}
+
+// Decide where (and by which ship) a ship for the job should be bought.
+// `shipyards` must be sorted by price. Returns (shipyard, purchaser) or the reason we can't buy.
+fn plan_ship_purchase(
+    job: &ShipConfig,
+    catalog: &ShipCatalog,
+    shipyards: &[(WaypointSymbol, i64)],
+    current_credits: i64,
+    purchaser_at: impl Fn(&WaypointSymbol) -> Option<String>,
+) -> Result<(WaypointSymbol, String), BuyShipResult> {
+    let purchase_criteria = &job.purchase_criteria;
+    if purchase_criteria.never_purchase {
+        return Err(BuyShipResult::FailedNeverPurchase);
+    }
+    if shipyards.is_empty() {
+        return Err(BuyShipResult::FailedNoShipyards);
+    }
+    let job_credit_reservation = job_credit_reservation(job, catalog);
+    let cheapest_shipard = shipyards[0].0.clone();
+    let can_afford_cheapest = current_credits >= shipyards[0].1 + job_credit_reservation;
+    debug!("try_buy_ship Credits available: {}", current_credits);
+    debug!(
+        "try_buy_ship Extra credits for job reservation: {}",
+        job_credit_reservation
+    );
+
+    for (shipyard, cost) in shipyards {
+        if current_credits < cost + job_credit_reservation {
+            break; // no point looking at more expensive shipyards
+        }
+        match purchaser_at(shipyard) {
+            Some(ship_symbol) => return Ok((shipyard.clone(), ship_symbol)),
+            None => {
+                // this 'no purchaser' case is the only one where we iterate through the other shipyards
+                if purchase_criteria.require_cheapest {
+                    break;
+                } else {
+                    continue;
+                }
+            }
+        }
+    }
+    if !can_afford_cheapest {
+        return Err(BuyShipResult::FailedLowCredits);
+    }
+    if purchase_criteria.allow_logistic_task {
+        Err(BuyShipResult::FailedNoPurchaser(Some(cheapest_shipard)))
+    } else {
+        Err(BuyShipResult::FailedNoPurchaser(None))
+    }
+}
+
+// symbol, ship, job id, state, status
+pub type ShipSummary = (String, Ship, String, Option<ShipState>, Option<ShipStatus>);
+
 #[derive(Clone)]
 pub struct AgentController {
-    universe: Arc<Universe>,
+    universe: UniverseHandle,
     api_client: ApiClient,
     db: DbClient,
 
-    listeners: Arc<Mutex<Vec<Sender<Event>>>>,
+    events: Arc<EventBus>,
     callsign: String,
     state: Arc<Mutex<AgentState>>,
+    goals: Arc<Mutex<Vec<GoalStatus>>>,
     agent: Arc<Mutex<Agent>>,
-    ships: Arc<DashMap<String, Arc<Mutex<Ship>>>>,
+    ships: Arc<DashMap<String, Arc<RwLock<Ship>>>>,
 
     ship_config: Arc<Mutex<Vec<ShipConfig>>>,
     job_assignments: Arc<DashMap<String, String>>,
     job_assignments_rev: Arc<DashMap<String, String>>,
-    ship_state_description: Arc<DashMap<String, String>>,
+    ship_states: Arc<DashMap<String, ShipState>>,
     probe_jumpgate_reservations: Arc<DashMap<String, WaypointSymbol>>,
     explorer_reservations: Arc<DashMap<String, SystemSymbol>>,
+    // roaming probe -> shipyard it covers while the shipyard has no probe
+    probe_shipyard_reservations: Arc<Mutex<BTreeMap<String, WaypointSymbol>>>,
+    // capital system onboarding, started with the InterSystem1 era
+    onboarding: Arc<Mutex<Option<OnboardingProgress>>>,
+    // ship -> job id of the script currently running
+    running_scripts: Arc<DashMap<String, String>>,
+    ship_statuses: Arc<DashMap<String, ShipStatus>>,
+    // ship -> pending handover, applied when the running script reaches a safe point
+    transfer_requests: Arc<DashMap<String, TransferTarget>>,
+    job_filter: Arc<Mutex<JobFilter>>,
+    wind_downs: Arc<Mutex<Vec<WindDown>>>,
 
     hdls: Arc<JoinHandles>,
+    // cancelled at shutdown, ship scripts are dropped wherever they're waiting
+    shutdown: CancellationToken,
     pub task_manager: Arc<LogisticTaskManager>,
     pub survey_manager: Arc<SurveyManager>,
     pub cargo_broker: Arc<CargoBroker>,
     pub ledger: Arc<Ledger>,
+    pub arrival_scheduler: Arc<ArrivalScheduler>,
+    pub script_registry: ShipScriptRegistry,
+    pub chart_queue: Arc<ChartQueue>,
+    pub hauler_autoscaler: Arc<HaulerAutoscaler>,
+    charts_submitted: Arc<AtomicI64>,
+    cooldowns: Arc<DashMap<String, DateTime<Utc>>>,
+    ship_updates: Arc<ShipUpdateCoalescer>,
+    // wakes the loop that re-evaluates the era and refreshes the ship config
+    era_reevaluation: Arc<tokio::sync::Notify>,
 
     try_buy_ships_mutex_guard: Arc<tokio::sync::Mutex<()>>,
     probe_reserve_mutex_guard: Arc<tokio::sync::Mutex<()>>,
     explorer_reserve_mutex_guard: Arc<tokio::sync::Mutex<()>>,
+    assignment_mutex_guard: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl TransferActor for AgentController {
@@ -117,9 +336,27 @@ impl TransferActor for AgentController {
     ) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
         let self_clone = self.clone();
         Box::pin(async move {
-            self_clone
-                .transfer_cargo(src_ship_symbol, dest_ship_symbol, good, units)
+            let transfer = self_clone
+                .transfer_cargo(
+                    src_ship_symbol.clone(),
+                    dest_ship_symbol.clone(),
+                    good,
+                    units,
+                )
                 .await;
+            if let Err(e) = transfer {
+                // the broker carries on as if the transfer happened, both ships resync their cargo
+                warn!(
+                    "Transfer {} -> {} failed: {}",
+                    src_ship_symbol, dest_ship_symbol, e
+                );
+                for ship_symbol in [src_ship_symbol, dest_ship_symbol] {
+                    let ship = self_clone.ship_controller(&ship_symbol);
+                    if let Err(e) = ship.refresh_cargo().await {
+                        warn!("Failed to refresh cargo of {}: {}", ship_symbol, e);
+                    }
+                }
+            }
         })
     }
 }
@@ -131,7 +368,138 @@ impl AgentController {
     pub fn state(&self) -> AgentState {
         self.state.lock().unwrap().clone()
     }
-    pub fn ships(&self) -> Vec<(String, Ship, String, String)> {
+    pub fn ship(&self, ship_symbol: &str) -> Option<Ship> {
+        self.ships
+            .get(ship_symbol)
+            .map(|ship| ship.read().unwrap().clone())
+    }
+    // Credits plus the value of cargo and ships across the fleet. Ships in transit are valued at their destination system.
+    pub async fn net_worth(&self) -> NetWorth {
+        let valuer = CargoValuer::new(self.universe.reader());
+        let mut sell_prices = BTreeMap::new();
+        let mut ship_prices = BTreeMap::new();
+        let mut cargo_value = 0;
+        let mut ship_value = 0;
+        for (_, ship, _, _, _) in self.ships() {
+            let system = ship.nav.route.destination.system_symbol.clone();
+            if ship.cargo.units != 0 {
+                if !sell_prices.contains_key(&system) {
+                    let prices = valuer.best_sell_prices(&system).await;
+                    sell_prices.insert(system.clone(), prices);
+                }
+                cargo_value += value_cargo(&ship.cargo, &sell_prices[&system]).total;
+            }
+            if !ship_prices.contains_key(&system) {
+                let prices = self.ship_listing_prices(&system).await;
+                ship_prices.insert(system.clone(), prices);
+            }
+            if let Ok(model) = self.universe.ship_catalog().identify(&ship) {
+                ship_value += ship_prices[&system].get(&model).cloned().unwrap_or(0);
+            }
+        }
+        self.ledger.net_worth(cargo_value, ship_value)
+    }
+
+    // model -> cheapest known purchase price in the system
+    async fn ship_listing_prices(&self, system: &SystemSymbol) -> BTreeMap<String, i64> {
+        let mut prices: BTreeMap<String, i64> = BTreeMap::new();
+        for (_, shipyard) in self.universe.get_system_shipyards(system).await {
+            let shipyard = match shipyard {
+                Some(shipyard) => shipyard,
+                None => continue,
+            };
+            for listing in &shipyard.data.ships {
+                let price = prices.entry(listing.ship_type.clone()).or_insert(i64::MAX);
+                *price = min(*price, listing.purchase_price);
+            }
+        }
+        prices
+    }
+
+    pub async fn record_net_worth(&self) {
+        let net_worth = self.net_worth().await;
+        ok_or_warn(
+            self.db.insert_net_worth(&self.callsign, &net_worth).await,
+            "insert net worth",
+        );
+
+        let key = format!("{}/net_worth_milestones", self.callsign);
+        // on a read error, try again next time rather than announce every milestone again
+        let Some(reached) = ok_or_warn(self.db.get_value(&key).await, "load net worth milestones")
+        else {
+            return;
+        };
+        let mut reached: Vec<i64> = reached.unwrap_or_default();
+        let milestones = new_milestones(&reached, net_worth.total);
+        if milestones.is_empty() {
+            return;
+        }
+        reached.extend(milestones.iter());
+        ok_or_warn(
+            self.db.set_value(&key, &reached).await,
+            "save net worth milestones",
+        );
+        for milestone in milestones {
+            info!(
+                "Agent {} reached net worth milestone ${}",
+                self.callsign, milestone
+            );
+            self.emit_event(&Event::NetWorthMilestone(milestone)).await;
+        }
+    }
+
+    // Submitted charts count towards the leaderboard's chart metric
+    pub async fn record_chart(&self, waypoint: &WaypointSymbol) {
+        let count = self.charts_submitted.fetch_add(1, Ordering::SeqCst) + 1;
+        info!("Charted {} ({} charts submitted)", waypoint, count);
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/charts_submitted", self.callsign), &count)
+                .await,
+            "save charts submitted",
+        );
+    }
+
+    pub fn charts_submitted(&self) -> i64 {
+        self.charts_submitted.load(Ordering::SeqCst)
+    }
+
+    // Called by the task manager after each planning cycle
+    pub async fn record_task_backlog(&self, system_symbol: &SystemSymbol, backlog: TaskBacklog) {
+        let Some(extra_haulers) = self.hauler_autoscaler.observe(system_symbol, backlog) else {
+            return;
+        };
+        ok_or_warn(
+            self.db
+                .set_value(
+                    &format!("{}/autoscaled_haulers", self.callsign),
+                    &self.hauler_autoscaler.extra_haulers(),
+                )
+                .await,
+            "save autoscaled haulers",
+        );
+        self.schedule_era_reevaluation(&format!(
+            "{} extra haulers for task backlog in {}",
+            extra_haulers, system_symbol
+        ));
+    }
+
+    // Persist cooldown expirations so they survive a restart
+    pub async fn record_cooldown(&self, ship_symbol: &str, expiration: Option<DateTime<Utc>>) {
+        let now = self.universe.now();
+        if let Some(expiration) = expiration {
+            self.cooldowns.insert(ship_symbol.to_string(), expiration);
+        }
+        self.cooldowns.retain(|_, expiration| *expiration > now);
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/cooldowns", self.callsign), &*self.cooldowns)
+                .await,
+            "save cooldowns",
+        );
+    }
+
+    pub fn ships(&self) -> Vec<ShipSummary> {
         // self.ships
         //     .iter()
         //     .map(|x| x.value().lock().unwrap().clone())
@@ -140,41 +508,64 @@ impl AgentController {
             .iter()
             .map(|x| {
                 let ship_symbol = x.key().clone();
-                let ship = x.value().lock().unwrap().clone();
+                let ship = x.value().read().unwrap().clone();
                 let job_id = self
                     .job_assignments_rev
                     .get(&ship_symbol)
                     .map(|x| x.value().clone())
                     .unwrap_or_default();
-                let descr = self
-                    .ship_state_description
+                let state = self
+                    .ship_states
                     .get(&ship_symbol)
-                    .map(|x| x.value().clone())
-                    .unwrap_or_default();
-                (ship_symbol, ship, job_id, descr)
+                    .map(|x| x.value().clone());
+                let status = self.ship_status(&ship_symbol);
+                (ship_symbol, ship, job_id, state, status)
             })
             .collect()
     }
 
-    pub fn add_event_listener(&self, listener: Sender<Event>) {
-        let mut listeners = self.listeners.lock().unwrap();
-        listeners.push(listener);
-        info!("Added event listener");
-        // web api should only require one listener, although we could support multiple
-        assert!(listeners.len() <= 1);
+    // The subscriber gets complete ship updates next, rather than diffs against updates it never saw
+    pub fn subscribe_events(&self, name: &str) -> EventSubscriber {
+        let subscriber = self.events.subscribe(name);
+        self.ship_updates
+            .resync(self.ships.iter().map(|x| x.key().clone()));
+        subscriber
     }
 
-    // definitely causing issues
-    // pub fn emit_event_blocking(&self, event: &Event) {
-    //     let listeners = { self.listeners.lock().unwrap().clone() };
-    //     for listener in listeners.iter() {
-    //         listener.blocking_send(event.clone()).unwrap();
-    //     }
-    // }
+    pub fn event_metrics(&self) -> Vec<SubscriberMetrics> {
+        self.events.metrics()
+    }
+
+    pub fn rate_limit_metrics(&self) -> RateLimitMetrics {
+        self.api_client.rate_limit_metrics()
+    }
+
+    pub fn has_event_listeners(&self) -> bool {
+        self.events.has_subscribers()
+    }
+
+    // Never waits on subscribers, a slow one loses low priority events instead
     pub async fn emit_event(&self, event: &Event) {
-        let listeners = { self.listeners.lock().unwrap().clone() };
-        for listener in listeners.iter() {
-            listener.send(event.clone()).await.unwrap();
+        self.events.emit(event);
+    }
+
+    // Queue the ship for the next coalesced update. Every change to a ship passes through here
+    pub fn mark_ship_dirty(&self, ship_symbol: &str) {
+        ALERTS.record_ship_activity(ship_symbol);
+        if self.has_event_listeners() {
+            self.ship_updates.mark_dirty(ship_symbol);
+        }
+    }
+
+    async fn flush_ship_updates(&self) {
+        for ship_symbol in self.ship_updates.take_dirty() {
+            let ship = match self.ships.get(&ship_symbol) {
+                Some(ship) => ship.read().unwrap().clone(),
+                None => continue,
+            };
+            if let Some(diff) = self.ship_updates.diff(&ship) {
+                self.emit_event(&Event::ShipUpdate(Arc::new(diff))).await;
+            }
         }
     }
 
@@ -184,7 +575,7 @@ impl AgentController {
         dest_ship_symbol: String,
         good: String,
         units: i64,
-    ) {
+    ) -> Result<(), ApiError> {
         debug!("agent_controller::transfer_cargo");
 
         self.debug(&format!(
@@ -197,13 +588,13 @@ impl AgentController {
             "tradeSymbol": &good,
             "units": &units,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
-        let (src_ship, dest_ship) = {
+        {
             let src_ship = self.ships.get(&src_ship_symbol).unwrap();
             let dest_ship = self.ships.get(&dest_ship_symbol).unwrap();
-            let mut src_ship = src_ship.lock().unwrap();
-            let mut dest_ship = dest_ship.lock().unwrap();
+            let mut src_ship = src_ship.write().unwrap();
+            let mut dest_ship = dest_ship.write().unwrap();
             let transferred: ShipCargoItem = {
                 let mut x = src_ship
                     .cargo
@@ -217,39 +608,64 @@ impl AgentController {
             };
             src_ship.cargo = cargo;
             dest_ship.incr_cargo(transferred);
-            (src_ship.clone(), dest_ship.clone())
-        };
-        self.emit_event(&Event::ShipUpdate(src_ship)).await;
-        self.emit_event(&Event::ShipUpdate(dest_ship)).await;
+        }
+        self.mark_ship_dirty(&src_ship_symbol);
+        self.mark_ship_dirty(&dest_ship_symbol);
         debug!("agent_controller::transfer_cargo done");
+        Ok(())
     }
 
     pub async fn new(
         api_client: &ApiClient,
         db: &DbClient,
-        universe: &Arc<Universe>,
+        universe: &UniverseHandle,
         callsign: &str,
     ) -> Self {
         // Load agent + ships
         let agent: Arc<Mutex<Agent>> = {
-            let agent = api_client.get_agent().await;
+            let agent = api_client
+                .get_agent()
+                .await
+                .expect("Failed to load agent at startup");
             assert_eq!(agent.symbol, callsign);
             Arc::new(Mutex::new(agent))
         };
-        let ships: Arc<DashMap<String, Arc<Mutex<Ship>>>> = {
-            let ships_vec: Vec<Ship> = api_client.get_all_ships().await;
+        // A failed load here aborts startup on purpose, running with partial state would drop
+        // assignments and reservations the next save then overwrites
+        let cooldowns: DashMap<String, DateTime<Utc>> = db
+            .get_value(&format!("{}/cooldowns", callsign))
+            .await
+            .expect("Failed to load cooldowns at startup")
+            .unwrap_or_default();
+        let ship_states: DashMap<String, ShipState> = db
+            .get_value(&format!("{}/ship_states", callsign))
+            .await
+            .expect("Failed to load ship states at startup")
+            .unwrap_or_default();
+        let ships: Arc<DashMap<String, Arc<RwLock<Ship>>>> = {
+            let ships_vec: Vec<Ship> = api_client
+                .get_all_ships()
+                .await
+                .expect("Failed to load ships at startup");
             let ships = Arc::new(DashMap::new());
-            for ship in ships_vec {
-                ships.insert(ship.symbol.clone(), Arc::new(Mutex::new(ship)));
+            let now = universe.now();
+            for mut ship in ships_vec {
+                if let Some(expiration) = cooldowns.get(&ship.symbol) {
+                    ship.cooldown.restore(*expiration, now);
+                }
+                ships.insert(ship.symbol.clone(), Arc::new(RwLock::new(ship)));
             }
             ships
         };
 
         let system_symbol = agent.lock().unwrap().headquarters.system();
-        let job_assignments: DashMap<String, String> = db
-            .get_value(&format!("{}/ship_assignments", callsign))
+        db.migrate_legacy_job_assignments(callsign)
             .await
-            .unwrap_or_default();
+            .expect("Failed to migrate legacy job assignments at startup");
+        let job_assignments = db
+            .get_job_assignments(callsign)
+            .await
+            .expect("Failed to load job assignments at startup");
         let job_assignments_rev = job_assignments
             .iter()
             .map(|x| {
@@ -257,10 +673,33 @@ impl AgentController {
                 (v.clone(), k.clone())
             })
             .collect();
-        let probe_jumpgate_reservations = db.get_probe_jumpgate_reservations(&callsign).await;
-        let explorer_reservations = db.get_explorer_reservations(&callsign).await;
-        let task_manager = LogisticTaskManager::new(universe, db, &system_symbol).await;
-        let survey_manager = SurveyManager::new(db).await;
+        let probe_jumpgate_reservations = db
+            .get_probe_jumpgate_reservations(&callsign)
+            .await
+            .expect("Failed to load probe jumpgate reservations at startup");
+        let explorer_reservations = db
+            .get_explorer_reservations(&callsign)
+            .await
+            .expect("Failed to load explorer reservations at startup");
+        let probe_shipyard_reservations = db
+            .get_probe_shipyard_reservations(callsign)
+            .await
+            .expect("Failed to load probe shipyard reservations at startup");
+        let onboarding: Option<OnboardingProgress> = db
+            .get_value(&format!("{}/onboarding", callsign))
+            .await
+            .expect("Failed to load onboarding progress at startup");
+        let task_manager = LogisticTaskManager::new(universe, db, callsign, &system_symbol)
+            .await
+            .expect("Failed to load task manager state");
+        let survey_manager = SurveyManager::new(db, callsign)
+            .await
+            .expect("Failed to load surveys");
+        let wind_downs: Vec<WindDown> = db
+            .get_value(&format!("{}/wind_downs", callsign))
+            .await
+            .expect("Failed to load wind downs at startup")
+            .unwrap_or_default();
 
         let initial_credits = {
             let agent = agent.lock().unwrap();
@@ -270,35 +709,82 @@ impl AgentController {
         let state: AgentState = db
             .get_value(&format!("{}/state", callsign))
             .await
+            .expect("Failed to load agent state at startup")
+            .unwrap_or_default();
+        let mut goals: Vec<GoalStatus> = db
+            .get_value(&format!("{}/goals", callsign))
+            .await
+            .expect("Failed to load goals at startup")
+            .unwrap_or_default();
+        merge_default_goals(&mut goals);
+        let charts_submitted: i64 = db
+            .get_value(&format!("{}/charts_submitted", callsign))
+            .await
+            .expect("Failed to load charts submitted at startup")
+            .unwrap_or(0);
+        let autoscaled_haulers: BTreeMap<SystemSymbol, i64> = db
+            .get_value(&format!("{}/autoscaled_haulers", callsign))
+            .await
+            .expect("Failed to load autoscaled haulers at startup")
             .unwrap_or_default();
         let agent_controller = Self {
             callsign: callsign.to_string(),
             state: Arc::new(Mutex::new(state)),
+            goals: Arc::new(Mutex::new(goals)),
             agent,
             ships,
             api_client: api_client.clone(),
             db: db.clone(),
             universe: universe.clone(),
-            listeners: Arc::new(Mutex::new(Vec::new())),
+            events: Arc::new(EventBus::new(EVENT_CAPACITY)),
             // ship_futs: Arc::new(Mutex::new(VecDeque::new())),
             hdls: Arc::new(JoinHandles::new()),
+            shutdown: CancellationToken::new(),
             ship_config: Arc::new(Mutex::new(vec![])),
             job_assignments: Arc::new(job_assignments),
             job_assignments_rev: Arc::new(job_assignments_rev),
-            ship_state_description: Arc::new(DashMap::new()),
+            ship_states: Arc::new(ship_states),
             probe_jumpgate_reservations: Arc::new(probe_jumpgate_reservations),
             explorer_reservations: Arc::new(explorer_reservations),
+            probe_shipyard_reservations: Arc::new(Mutex::new(probe_shipyard_reservations)),
+            onboarding: Arc::new(Mutex::new(onboarding.clone())),
+            job_filter: Arc::new(Mutex::new(CONFIG.job_filter.clone())),
+            wind_downs: Arc::new(Mutex::new(wind_downs)),
+            running_scripts: Arc::new(DashMap::new()),
+            ship_statuses: Arc::new(DashMap::new()),
+            transfer_requests: Arc::new(DashMap::new()),
             task_manager: Arc::new(task_manager),
             cargo_broker: Arc::new(CargoBroker::new()),
             survey_manager: Arc::new(survey_manager),
             try_buy_ships_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
             probe_reserve_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
             explorer_reserve_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
+            assignment_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
             ledger: Arc::new(ledger),
+            arrival_scheduler: Arc::new(ArrivalScheduler::new()),
+            script_registry: ShipScriptRegistry::new(),
+            chart_queue: Arc::new(ChartQueue::new(CONFIG.chart_budget_per_hour)),
+            hauler_autoscaler: Arc::new(HaulerAutoscaler::new(
+                CONFIG.autoscale_backlog_value,
+                CONFIG.autoscale_cycles,
+                CONFIG.autoscale_max_haulers,
+                autoscaled_haulers,
+            )),
+            charts_submitted: Arc::new(AtomicI64::new(charts_submitted)),
+            cooldowns: Arc::new(cooldowns),
+            ship_updates: Arc::new(ShipUpdateCoalescer::new()),
+            era_reevaluation: Arc::new(tokio::sync::Notify::new()),
         };
         agent_controller
             .task_manager
             .set_agent_controller(&agent_controller);
+        if let Some(progress) = &onboarding {
+            if let Some(since) = progress.completed.get(&OnboardingStep::TaskManager) {
+                agent_controller
+                    .task_manager
+                    .serve_system(&progress.system_symbol, *since);
+            }
+        }
         let credits = agent_controller.ledger.credits();
         let num_ships = agent_controller.num_ships();
         info!(
@@ -334,9 +820,51 @@ impl AgentController {
     pub async fn update_agent(&self, agent_upd: Agent) {
         self.emit_event(&Event::AgentUpdate(agent_upd.clone()))
             .await;
-        let mut agent = self.agent.lock().unwrap();
-        *agent = agent_upd;
-        self.ledger.set_credits(agent.credits);
+        let prev_credits = self.ledger.available_credits();
+        {
+            let mut agent = self.agent.lock().unwrap();
+            *agent = agent_upd;
+            self.ledger.set_credits(agent.credits);
+        }
+        let credit_goals = self
+            .goals()
+            .iter()
+            .filter(|g| !g.is_complete())
+            .filter_map(|g| match g.goal {
+                Goal::ReachCredits(target) => Some(target),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let crossed =
+            crossed_thresholds(prev_credits, self.ledger.available_credits(), &credit_goals);
+        if let Some(threshold) = crossed.first() {
+            self.schedule_era_reevaluation(&format!("credits reached {}", threshold));
+        }
+    }
+
+    // Called when a ship arrives in a new system
+    pub fn ship_entered_system(&self, system_symbol: &SystemSymbol) {
+        let colonizes = self
+            .goals()
+            .iter()
+            .any(|g| !g.is_complete() && g.goal == Goal::ColonizeSystem(system_symbol.clone()));
+        let onboarding = matches!(
+            &*self.onboarding.lock().unwrap(),
+            Some(progress) if progress.system_symbol == *system_symbol && !progress.is_complete()
+        );
+        if colonizes || onboarding {
+            self.schedule_era_reevaluation(&format!("reached system {}", system_symbol));
+        }
+    }
+
+    // Re-evaluate the era and refresh the ship config as soon as possible,
+    // rather than waiting for the next purchase round
+    pub fn schedule_era_reevaluation(&self, reason: &str) {
+        info!(
+            "Agent {} scheduling era re-evaluation: {}",
+            self.callsign, reason
+        );
+        self.era_reevaluation.notify_one();
     }
     fn debug(&self, msg: &str) {
         debug!("[{}] {}", self.callsign, msg);
@@ -352,9 +880,117 @@ impl AgentController {
             state.era = era;
             state.clone()
         };
-        self.db
-            .set_value(&format!("{}/state", self.callsign), &state)
-            .await;
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/state", self.callsign), &state)
+                .await,
+            "save state",
+        );
+    }
+
+    pub fn goals(&self) -> Vec<GoalStatus> {
+        self.goals.lock().unwrap().clone()
+    }
+
+    pub fn is_goal_complete(&self, goal: &Goal) -> bool {
+        self.goals
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|g| g.goal == *goal && g.is_complete())
+    }
+
+    pub async fn add_goal(&self, goal: Goal, priority: i64) {
+        let goals = {
+            let mut goals = self.goals.lock().unwrap();
+            if goals.iter().any(|g| g.goal == goal) {
+                return;
+            }
+            info!("Agent {} adding goal {:?}", self.callsign, goal);
+            goals.push(GoalStatus::new(goal, priority));
+            goals.sort_by_key(|g| g.priority);
+            goals.clone()
+        };
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/goals", self.callsign), &goals)
+                .await,
+            "save goals",
+        );
+    }
+
+    async fn goal_progress(&self, goal: &Goal) -> f64 {
+        match goal {
+            Goal::ReachCredits(target) => {
+                let credits = self.ledger.available_credits();
+                (credits as f64 / *target as f64).clamp(0.0, 1.0)
+            }
+            Goal::FinishJumpGate => {
+                let jump_gate_symbol = self.universe.get_jumpgate(&self.starting_system()).await;
+                let construction = self.universe.get_construction(&jump_gate_symbol).await;
+                match &construction.data {
+                    None => 1.0,
+                    Some(x) if x.is_complete => 1.0,
+                    Some(x) => {
+                        let required: i64 = x.materials.iter().map(|m| m.required).sum();
+                        let fulfilled: i64 = x.materials.iter().map(|m| m.fulfilled).sum();
+                        // only complete once the construction is marked complete
+                        (fulfilled as f64 / required.max(1) as f64).min(0.99)
+                    }
+                }
+            }
+            Goal::ColonizeSystem(system_symbol) => {
+                let has_presence = self
+                    .ships
+                    .iter()
+                    .any(|ship| ship.value().read().unwrap().nav.system_symbol == *system_symbol);
+                if has_presence {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    pub async fn update_goals(&self) {
+        let incomplete = {
+            let goals = self.goals.lock().unwrap();
+            goals
+                .iter()
+                .filter(|g| !g.is_complete())
+                .map(|g| g.goal.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut progress = Vec::new();
+        for goal in incomplete {
+            let p = self.goal_progress(&goal).await;
+            progress.push((goal, p));
+        }
+        let now = chrono::Utc::now();
+        let mut completed = Vec::new();
+        let goals = {
+            let mut goals = self.goals.lock().unwrap();
+            for (goal, p) in progress {
+                let status = goals.iter_mut().find(|g| g.goal == goal).unwrap();
+                status.progress = p;
+                if p >= 1.0 {
+                    status.completed_at = Some(now);
+                    completed.push(goal);
+                }
+            }
+            goals.clone()
+        };
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/goals", self.callsign), &goals)
+                .await,
+            "save goals",
+        );
+        for goal in completed {
+            info!("Agent {} completed goal {:?}", self.callsign, goal);
+            self.emit_event(&Event::GoalCompleted(goal)).await;
+        }
     }
 
     pub async fn check_era_advance(&self) {
@@ -367,32 +1003,13 @@ impl AgentController {
                 );
                 self.update_era(era_override).await;
             }
+            self.onboard_capital().await;
             return;
         }
+        self.update_goals().await;
         loop {
             let current_era = self.state().era;
-            let next_era = match current_era {
-                AgentEra::StartingSystem1 => {
-                    // Conditions for going to mid:
-                    // - 800k credits available
-                    let credits = self.ledger.available_credits();
-                    if credits >= 800_000 {
-                        Some(AgentEra::StartingSystem2)
-                    } else {
-                        None
-                    }
-                }
-                AgentEra::StartingSystem2 => {
-                    let jumpgate_finished = self.is_jumpgate_finished().await;
-                    if jumpgate_finished {
-                        Some(AgentEra::InterSystem1)
-                    } else {
-                        None
-                    }
-                }
-                AgentEra::InterSystem1 => None,
-                AgentEra::InterSystem2 => None,
-            };
+            let next_era = next_era(current_era, &self.goals(), CONFIG.strategy);
             match next_era {
                 None => break,
                 Some(next_era) => {
@@ -402,6 +1019,174 @@ impl AgentController {
                 }
             }
         }
+        self.plan_expansion().await;
+        self.onboard_capital().await;
+    }
+
+    pub fn onboarding(&self) -> Option<OnboardingProgress> {
+        self.onboarding.lock().unwrap().clone()
+    }
+
+    // The capital has nothing when the gate opens: no probes, no market data, nothing for the
+    // task manager. Each step runs once the previous one is done, steps waiting on ships are
+    // retried at the next era re-evaluation
+    async fn onboard_capital(&self) {
+        if self.state().era != AgentEra::InterSystem1 {
+            return;
+        }
+        let capital = self.faction_capital().await;
+        let mut progress = self
+            .onboarding()
+            .filter(|p| p.system_symbol == capital)
+            .unwrap_or_else(|| OnboardingProgress::new(&capital));
+        while let Some(step) = progress.next_step() {
+            let done = match step {
+                OnboardingStep::FirstProbe => self
+                    .ships
+                    .iter()
+                    .any(|ship| ship.value().read().unwrap().nav.system_symbol == capital),
+                OnboardingStep::RefreshShipyards => {
+                    let shipyards = self.universe.get_system_shipyards_remote(&capital).await;
+                    let mut detailed = false;
+                    for shipyard in &shipyards {
+                        if self.universe.get_shipyard(&shipyard.symbol).await.is_some() {
+                            detailed = true;
+                            break;
+                        }
+                    }
+                    detailed
+                }
+                OnboardingStep::SeedMarkets => {
+                    let markets = self.universe.get_system_markets_remote(&capital).await;
+                    debug!("Seeded {} markets in {}", markets.len(), capital);
+                    true
+                }
+                OnboardingStep::TaskManager => {
+                    self.task_manager
+                        .serve_system(&capital, self.universe.now());
+                    true
+                }
+            };
+            if !done {
+                break;
+            }
+            info!(
+                "Agent {} onboarding {}: {:?} done",
+                self.callsign, capital, step
+            );
+            progress.complete(step, self.universe.now());
+            ok_or_warn(
+                self.db
+                    .set_value(&format!("{}/onboarding", self.callsign), &progress)
+                    .await,
+                "save onboarding",
+            );
+            self.emit_event(&Event::Onboarding(progress.clone())).await;
+        }
+        *self.onboarding.lock().unwrap() = Some(progress);
+    }
+
+    // Candidate systems to expand into, best first
+    pub async fn expansion_candidates(&self) -> Vec<ExpansionCandidate> {
+        let start_system = self.starting_system();
+        let graph = self.universe.warp_jump_graph().await;
+        let reachables = dijkstra_all(&start_system, |node| {
+            graph
+                .get(node)
+                .unwrap()
+                .iter()
+                .map(|(s, d)| (s.clone(), d.duration))
+        });
+        let mut reachable = reachables
+            .into_iter()
+            .map(|(system_symbol, (_pre, distance))| (system_symbol, distance))
+            .filter(|(system_symbol, _)| !self.universe.system(system_symbol).waypoints.is_empty())
+            .collect::<Vec<_>>();
+        reachable.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let probe_price = self
+            .ship_listing_prices(&start_system)
+            .await
+            .get("SHIP_PROBE")
+            .cloned();
+        let mut candidates = vec![];
+        for (system_symbol, distance) in reachable.into_iter().take(MAX_CANDIDATES) {
+            let waypoints = self.universe.get_system_waypoints(&system_symbol).await;
+            let markets = self
+                .universe
+                .get_system_markets_remote(&system_symbol)
+                .await;
+            candidates.push(estimate_candidate(
+                &system_symbol,
+                distance,
+                &waypoints,
+                &markets,
+                probe_price,
+            ));
+        }
+        rank_candidates(candidates)
+    }
+
+    pub async fn expansion_override(&self) -> ExpansionOverride {
+        let expansion_override = self
+            .db
+            .get_value(&format!("{}/expansion_override", self.callsign))
+            .await;
+        ok_or_warn(expansion_override, "load expansion override")
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    // Applies to the next expansion, an in-progress ColonizeSystem goal is left alone
+    pub async fn set_expansion_override(&self, expansion_override: &ExpansionOverride) {
+        info!(
+            "Agent {} setting expansion override {:?}",
+            self.callsign, expansion_override.system_symbol
+        );
+        ok_or_warn(
+            self.db
+                .set_value(
+                    &format!("{}/expansion_override", self.callsign),
+                    expansion_override,
+                )
+                .await,
+            "save expansion override",
+        );
+        self.schedule_era_reevaluation("expansion override changed");
+    }
+
+    // Once the gate is open, colonize the best candidate system, one at a time
+    async fn plan_expansion(&self) {
+        if self.state().era != AgentEra::InterSystem1 {
+            return;
+        }
+        let colonized = self
+            .goals()
+            .into_iter()
+            .filter_map(|g| match g.goal {
+                Goal::ColonizeSystem(ref system_symbol) => {
+                    Some((system_symbol.clone(), g.is_complete()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if colonized.len() >= MAX_EXPANSIONS || colonized.iter().any(|(_, complete)| !complete) {
+            return;
+        }
+        let existing = colonized
+            .into_iter()
+            .map(|(system_symbol, _)| system_symbol)
+            .collect::<Vec<_>>();
+        let ranked = self.expansion_candidates().await;
+        let expansion_override = self.expansion_override().await;
+        if let Some(system_symbol) = select_expansion(&ranked, &expansion_override, &existing) {
+            info!(
+                "Agent {} expanding into system {}",
+                self.callsign, system_symbol
+            );
+            self.add_goal(Goal::ColonizeSystem(system_symbol.clone()), 0)
+                .await;
+        }
     }
 
     pub fn probed_waypoints(&self) -> Vec<(String, Vec<WaypointSymbol>)> {
@@ -435,7 +1220,7 @@ impl AgentController {
                     let waypoint_symbol = &waypoints[0];
                     if let Some(assignment) = self.job_assignments.get(&job.id) {
                         let ship = self.ships.get(assignment.value()).unwrap();
-                        let ship = ship.lock().unwrap();
+                        let ship = ship.read().unwrap();
                         if ship.nav.status != InTransit
                             && ship.nav.waypoint_symbol == *waypoint_symbol
                         {
@@ -446,7 +1231,7 @@ impl AgentController {
                     if let Some(assignment) = self.job_assignments.get(&job.id) {
                         // Construction Hauler ship terminates at a shipyard so it can be used to buy ships
                         let ship = self.ships.get(assignment.value()).unwrap();
-                        let ship = ship.lock().unwrap();
+                        let ship = ship.read().unwrap();
                         if ship.nav.status != InTransit && ship.nav.system_symbol != starting_system
                         {
                             return Some((ship.symbol.clone(), ship.nav.waypoint_symbol.clone()));
@@ -458,39 +1243,683 @@ impl AgentController {
             .collect()
     }
 
-    async fn buy_ship(&self, shipyard: &WaypointSymbol, ship_model: &str) -> String {
+    async fn buy_ship(
+        &self,
+        shipyard: &WaypointSymbol,
+        ship_model: &str,
+    ) -> Result<String, ApiError> {
         self.debug(&format!("Buying {} at {}", &ship_model, &shipyard));
         let uri = "/my/ships";
         let body = json!({
             "shipType": ship_model,
             "waypointSymbol": shipyard,
         });
-        let mut response: Value = self.api_client.post(uri, &body).await;
+        let mut response: Value = self.api_client.try_post(uri, &body).await?;
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
         let ship: Ship = serde_json::from_value(response["data"]["ship"].take()).unwrap();
-        // let transaction = response["data"]["transaction"].take();
+        let price = response["data"]["transaction"]["price"].as_i64().unwrap();
+        self.ledger.register_spend(SHIP_SPEND, price);
         let ship_symbol = ship.symbol.clone();
         self.debug(&format!("Successfully bought ship {}", ship_symbol));
         self.update_agent(agent).await;
         self.ships
-            .insert(ship_symbol.clone(), Arc::new(Mutex::new(ship)));
-        ship_symbol
+            .insert(ship_symbol.clone(), Arc::new(RwLock::new(ship)));
+        Ok(ship_symbol)
     }
 
-    pub fn ship_controller(&self, ship_symbol: &str) -> ShipController {
-        let ship = self.ships.get(ship_symbol).unwrap();
-        ShipController::new(&self.api_client, &self.universe, ship.clone(), self)
-    }
-    pub fn ship_assigned(&self, ship_symbol: &str) -> bool {
-        self.job_assignments_rev.contains_key(ship_symbol)
-    }
-    pub fn job_assigned(&self, job_id: &str) -> bool {
-        self.job_assignments.contains_key(job_id)
-    }
+    // Move the ships to `target` as a convoy. Ships that can share a route plan it once, departures
+    // are staggered, and a single ConvoyArrived event is emitted once the last ship arrives
+    pub async fn move_convoy(
+        &self,
+        ship_symbols: &[String],
+        target: &WaypointSymbol,
+    ) -> Result<(), ApiError> {
+        let ships = ship_symbols
+            .iter()
+            .map(|symbol| self.ship_controller(symbol))
+            .collect::<Vec<_>>();
+        join_all(ships.iter().map(|ship| ship.wait_for_transit())).await;
+        let departed = self.universe.now();
+        let convoy_ships = ships
+            .iter()
+            .map(|ship| ConvoyShip {
+                symbol: ship.symbol(),
+                waypoint: ship.waypoint(),
+                speed: ship.engine_speed(),
+                fuel: ship.current_fuel(),
+                fuel_capacity: ship.fuel_capacity(),
+            })
+            .collect::<Vec<_>>();
+
+        // routes within the system are shared, other ships navigate on their own
+        let mut routes = BTreeMap::new();
+        for group in route_groups(&convoy_ships) {
+            let lead = convoy_ships.iter().find(|s| s.symbol == group[0]).unwrap();
+            if lead.waypoint.system() != target.system()
+                || lead.waypoint == *target
+                || lead.fuel_capacity == 0
+            {
+                continue;
+            }
+            let route = self
+                .universe
+                .get_route(
+                    &lead.waypoint,
+                    target,
+                    lead.speed,
+                    lead.fuel,
+                    lead.fuel_capacity,
+                    Urgency::Normal,
+                )
+                .await;
+            for symbol in group {
+                routes.insert(symbol, route.clone());
+            }
+        }
+        debug!(
+            "Convoy of {} ships to {}, {} sharing routes",
+            ships.len(),
+            target,
+            routes.len()
+        );
 
-    async fn try_buy_ships_lock(&self) -> tokio::sync::MutexGuard<()> {
-        match self.try_buy_ships_mutex_guard.try_lock() {
-            Ok(guard) => guard,
+        let results = join_all(ships.iter().enumerate().map(|(idx, ship)| {
+            let route = routes.get(&ship.symbol()).cloned();
+            async move {
+                let stagger = DEPARTURE_STAGGER_MS * idx as u64;
+                tokio::time::sleep(std::time::Duration::from_millis(stagger)).await;
+                match route {
+                    Some(route) => ship.follow_route(target, route).await,
+                    None => ship.goto_waypoint(target).await,
+                }
+            }
+        }))
+        .await;
+        // the group didn't arrive, so there's no arrival to report
+        results.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+        let arrival = ConvoyArrival {
+            ships: ship_symbols.to_vec(),
+            destination: target.clone(),
+            departed,
+            arrived: self.universe.now(),
+        };
+        info!(
+            "Convoy of {} ships arrived at {}",
+            arrival.ships.len(),
+            target
+        );
+        self.emit_event(&Event::ConvoyArrived(arrival)).await;
+        Ok(())
+    }
+
+    // Send ships that aren't running a script to `target` as a convoy, e.g. newly bought drones
+    // and their shuttle headed for a distant work site. The ships are held like a running script
+    // for the trip, so nothing else takes them, and are started again once the convoy is over
+    pub async fn dispatch_convoy(
+        &self,
+        ship_symbols: &[String],
+        target: &WaypointSymbol,
+    ) -> Result<(), String> {
+        if ship_symbols.is_empty() {
+            return Err("Convoy has no ships".to_string());
+        }
+        if let Some(symbol) = ship_symbols.iter().find(|s| !self.ships.contains_key(*s)) {
+            return Err(format!("Unknown ship {}", symbol));
+        }
+        let mut claimed: Vec<String> = vec![];
+        for ship_symbol in ship_symbols {
+            let job_id = self
+                .job_assignments_rev
+                .get(ship_symbol)
+                .map(|x| x.value().clone())
+                .unwrap_or_else(|| CONVOY_JOB_ID.to_string());
+            match self.running_scripts.entry(ship_symbol.clone()) {
+                dashmap::mapref::entry::Entry::Vacant(entry) => {
+                    entry.insert(job_id);
+                    claimed.push(ship_symbol.clone());
+                }
+                dashmap::mapref::entry::Entry::Occupied(_) => {
+                    for symbol in &claimed {
+                        self.running_scripts.remove(symbol);
+                    }
+                    return Err(format!("Ship {} is busy", ship_symbol));
+                }
+            }
+        }
+        info!(
+            "Agent {} dispatching convoy of {} ships to {}",
+            self.callsign,
+            claimed.len(),
+            target
+        );
+        for ship_symbol in &claimed {
+            self.set_ship_status(ship_symbol, ShipStatus::Running("convoy".to_string()))
+                .await;
+        }
+
+        let self_clone = self.clone();
+        let target = target.clone();
+        let join_hdl = tokio::spawn(async move {
+            let result = self_clone.move_convoy(&claimed, &target).await;
+            if let Err(e) = &result {
+                warn!("Convoy to {} failed: {}", target, e);
+            }
+            for ship_symbol in claimed {
+                self_clone.running_scripts.remove(&ship_symbol);
+                if result.is_err() {
+                    self_clone.resync_ship(&ship_symbol).await;
+                }
+                let target = self_clone
+                    .transfer_requests
+                    .get(&ship_symbol)
+                    .map(|x| x.value().clone());
+                match target {
+                    // requested while the ship was held for the convoy, it has no job to go back to
+                    Some(TransferTarget::Job(job_id)) if job_id == CONVOY_JOB_ID => {
+                        self_clone.transfer_requests.remove(&ship_symbol);
+                        self_clone._spawn_run_ship(ship_symbol).await
+                    }
+                    Some(target) => self_clone.complete_transfer(&ship_symbol, target).await,
+                    None => self_clone._spawn_run_ship(ship_symbol).await,
+                }
+            }
+        });
+        self.hdls.push(join_hdl).await;
+        Ok(())
+    }
+
+    pub fn ship_controller(&self, ship_symbol: &str) -> ShipController {
+        let ship = self.ships.get(ship_symbol).unwrap();
+        ShipController::new(&self.api_client, &self.universe, ship.clone(), self)
+    }
+    pub fn ship_assigned(&self, ship_symbol: &str) -> bool {
+        self.job_assignments_rev.contains_key(ship_symbol)
+    }
+    pub fn job_assigned(&self, job_id: &str) -> bool {
+        self.job_assignments.contains_key(job_id)
+    }
+
+    // Hand running ships off stale jobs to a free job (or salvage) at their next safe point
+    fn rebalance_assignments(&self, stale_ships: &[String], ship_config: &[ShipConfig]) {
+        let stale = stale_ships
+            .iter()
+            .filter(|ship_symbol| !self.transfer_requests.contains_key(*ship_symbol))
+            .map(|ship_symbol| {
+                let ship = self.ships.get(ship_symbol).unwrap();
+                let model = self
+                    .universe
+                    .ship_catalog()
+                    .identify(&ship.read().unwrap())
+                    .unwrap();
+                (ship_symbol.clone(), model)
+            })
+            .collect::<Vec<_>>();
+        let pending = self
+            .transfer_requests
+            .iter()
+            .filter_map(|x| match x.value() {
+                TransferTarget::Job(job_id) => Some(job_id.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let plan = plan_rebalance(
+            &stale,
+            ship_config,
+            |job_id| self.job_assigned(job_id) || pending.iter().any(|p| p == job_id),
+            CONFIG.scrap_unassigned,
+        );
+        for (ship_symbol, target) in plan {
+            self.request_transfer(&ship_symbol, target);
+        }
+    }
+
+    fn ship_safe_to_unassign(&self, ship_symbol: &str) -> bool {
+        let ship = self.ships.get(ship_symbol).unwrap();
+        let ship = ship.read().unwrap();
+        safe_to_unassign(
+            &ship,
+            self.transfer_requests.contains_key(ship_symbol),
+            self.universe.now(),
+        )
+    }
+
+    // Idle ships off stale jobs that aren't safe to unassign: sell their cargo through the salvage
+    // behaviour, then hand them over like a running ship reaching a safe point.
+    // Ships mid-transfer are left until the transfer completes.
+    async fn unload_stale_ships(&self, stale_ships: &[String], ship_config: &[ShipConfig]) {
+        let stale_ships = stale_ships
+            .iter()
+            .filter(|ship_symbol| !self.transfer_requests.contains_key(*ship_symbol))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.rebalance_assignments(&stale_ships, ship_config);
+        for ship_symbol in stale_ships {
+            info!("Unloading {} before unassigning it", ship_symbol);
+            let job_id = self
+                .job_assignments_rev
+                .get(&ship_symbol)
+                .map(|x| x.value().clone())
+                .unwrap_or_default();
+            self.running_scripts.insert(ship_symbol.clone(), job_id);
+            let ship_controller = self.ship_controller(&ship_symbol);
+            let self_clone = self.clone();
+            let join_hdl = tokio::spawn(async move {
+                ship_controller.wait_for_transit().await;
+                match ship_scripts::scrap::sell_cargo(&ship_controller).await {
+                    Ok(()) => self_clone.on_script_exit(&ship_symbol).await,
+                    Err(e) => self_clone.on_script_error(&ship_symbol, e).await,
+                }
+            });
+            self.hdls.push(join_hdl).await;
+        }
+    }
+
+    pub fn job_filter(&self) -> JobFilter {
+        self.job_filter.lock().unwrap().clone()
+    }
+
+    // Ships running a job the filter now excludes stop at their next safe point, keeping the job.
+    // Skipped ships are started again, and skipped again if their job is still filtered out
+    pub async fn set_job_filter(&self, job_filter: JobFilter) -> Result<(), String> {
+        job_filter.validate()?;
+        info!(
+            "Agent {} setting job filter {:?}",
+            self.callsign, job_filter
+        );
+        *self.job_filter.lock().unwrap() = job_filter.clone();
+
+        let ship_config = self.get_ship_config();
+        let running = self
+            .running_scripts
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect::<Vec<_>>();
+        for (ship_symbol, job_id) in running {
+            let Some(job) = ship_config.iter().find(|job| job.id == job_id) else {
+                continue;
+            };
+            if job_filter.exclusion(job).is_some() && !self.transfer_requested(&ship_symbol) {
+                self.request_transfer(&ship_symbol, TransferTarget::Job(job_id));
+            }
+        }
+        for ship_symbol in self.skipped_ships() {
+            self.spawn_run_ship(ship_symbol).await;
+        }
+        Ok(())
+    }
+
+    pub fn wind_downs(&self) -> Vec<WindDown> {
+        self.wind_downs.lock().unwrap().clone()
+    }
+
+    pub fn system_wind_down(&self, system_symbol: &SystemSymbol) -> Option<WindDown> {
+        wind_down::find(&self.wind_downs.lock().unwrap(), system_symbol).cloned()
+    }
+
+    // Ships in the system still running a script or flying to the parking waypoint
+    fn wind_down_pending(&self, system_symbol: &SystemSymbol) -> Vec<String> {
+        self.running_scripts
+            .iter()
+            .map(|x| x.key().clone())
+            .filter(|ship_symbol| {
+                self.ship(ship_symbol)
+                    .is_some_and(|ship| ship.nav.system_symbol == *system_symbol)
+            })
+            .collect()
+    }
+
+    pub fn wind_down_statuses(&self) -> Vec<WindDownStatus> {
+        self.wind_downs()
+            .into_iter()
+            .map(|wind_down| WindDownStatus {
+                pending_ships: self.wind_down_pending(&wind_down.system_symbol),
+                wind_down,
+            })
+            .collect()
+    }
+
+    // Called as ships in a system being wound down stop
+    async fn check_wind_down(&self, system_symbol: &SystemSymbol) {
+        let pending = self.wind_down_pending(system_symbol).len();
+        let now = self.universe.now();
+        let changed = {
+            let mut wind_downs = self.wind_downs.lock().unwrap();
+            let Some(wind_down) = wind_downs
+                .iter_mut()
+                .find(|w| w.system_symbol == *system_symbol)
+            else {
+                return;
+            };
+            let was_complete = wind_down.completed_at.is_some();
+            let completed = wind_down.update_completion(pending, now);
+            completed || was_complete != wind_down.completed_at.is_some()
+        };
+        if !changed {
+            return;
+        }
+        match pending {
+            0 => info!(
+                "Agent {} wound down {}, all ships stopped",
+                self.callsign, system_symbol
+            ),
+            _ => info!(
+                "Agent {} winding down {} again, {} ships to stop",
+                self.callsign, system_symbol, pending
+            ),
+        }
+        self.save_wind_downs().await;
+    }
+
+    async fn save_wind_downs(&self) {
+        let wind_downs = self.wind_downs();
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/wind_downs", self.callsign), &wind_downs)
+                .await,
+            "save wind downs",
+        );
+    }
+
+    // Ships running a script in the system stop at their next safe point, then park.
+    // Ships already stopped there are parked straight away
+    pub async fn wind_down_system(&self, wind_down: WindDown) -> Result<(), String> {
+        wind_down.validate()?;
+        info!("Agent {} winding down {:?}", self.callsign, wind_down);
+        let system_symbol = wind_down.system_symbol.clone();
+        wind_down::upsert(&mut self.wind_downs.lock().unwrap(), wind_down);
+        self.save_wind_downs().await;
+
+        let in_system = |ship_symbol: &str| {
+            self.ship(ship_symbol)
+                .is_some_and(|ship| ship.nav.system_symbol == system_symbol)
+        };
+        let running = self
+            .running_scripts
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect::<Vec<_>>();
+        for (ship_symbol, job_id) in running {
+            if in_system(&ship_symbol) && !self.transfer_requested(&ship_symbol) {
+                self.request_transfer(&ship_symbol, TransferTarget::Job(job_id));
+            }
+        }
+        for ship_symbol in self.skipped_ships() {
+            if in_system(&ship_symbol) {
+                self.spawn_run_ship(ship_symbol).await;
+            }
+        }
+        self.check_wind_down(&system_symbol).await;
+        Ok(())
+    }
+
+    // Parked ships go back to their jobs, false if the system wasn't being wound down
+    pub async fn resume_system(&self, system_symbol: &SystemSymbol) -> bool {
+        let resumed = {
+            let mut wind_downs = self.wind_downs.lock().unwrap();
+            let before = wind_downs.len();
+            wind_downs.retain(|w| w.system_symbol != *system_symbol);
+            wind_downs.len() != before
+        };
+        if !resumed {
+            return false;
+        }
+        info!("Agent {} resuming {}", self.callsign, system_symbol);
+        self.save_wind_downs().await;
+        for ship_symbol in self.skipped_ships() {
+            self.spawn_run_ship(ship_symbol).await;
+        }
+        true
+    }
+
+    fn skipped_ships(&self) -> Vec<String> {
+        self.ship_statuses
+            .iter()
+            .filter(|x| matches!(x.value(), ShipStatus::Skipped(_)))
+            .map(|x| x.key().clone())
+            .collect()
+    }
+
+    // Fly to the parking waypoint of the system being wound down. The ship is started again on
+    // arrival, which leaves it skipped unless the wind down was cancelled on the way
+    async fn park_ship(&self, ship_symbol: &str, job_id: &str, park_at: &WaypointSymbol) {
+        info!("Parking {} at {}", ship_symbol, park_at);
+        self.running_scripts
+            .insert(ship_symbol.to_string(), job_id.to_string());
+        self.set_ship_status(ship_symbol, ShipStatus::Running("park".to_string()))
+            .await;
+        let ship_controller = self.ship_controller(ship_symbol);
+        let self_clone = self.clone();
+        let ship_symbol = ship_symbol.to_string();
+        let park_at = park_at.clone();
+        let join_hdl = tokio::spawn(async move {
+            if let Err(e) = ship_controller.goto_waypoint(&park_at).await {
+                self_clone.on_script_error(&ship_symbol, e).await;
+                return;
+            }
+            self_clone.running_scripts.remove(&ship_symbol);
+            let target = self_clone
+                .transfer_requests
+                .get(&ship_symbol)
+                .map(|x| x.value().clone());
+            match target {
+                Some(target) => self_clone.complete_transfer(&ship_symbol, target).await,
+                None => self_clone._spawn_run_ship(ship_symbol).await,
+            }
+        });
+        self.hdls.push(join_hdl).await;
+    }
+
+    pub fn request_transfer(&self, ship_symbol: &str, target: TransferTarget) {
+        info!("Requesting transfer of {} to {:?}", ship_symbol, target);
+        self.transfer_requests
+            .insert(ship_symbol.to_string(), target);
+    }
+
+    // A ship with a script running, including one waiting out a restart delay
+    pub fn script_running(&self, ship_symbol: &str) -> bool {
+        self.running_scripts.contains_key(ship_symbol)
+    }
+
+    // Polled by scripts at safe points (not in transit, cargo empty or accounted for)
+    pub fn transfer_requested(&self, ship_symbol: &str) -> bool {
+        self.transfer_requests.contains_key(ship_symbol)
+    }
+
+    pub fn ship_status(&self, ship_symbol: &str) -> Option<ShipStatus> {
+        self.ship_statuses
+            .get(ship_symbol)
+            .map(|x| x.value().clone())
+    }
+
+    async fn set_ship_status(&self, ship_symbol: &str, status: ShipStatus) {
+        let prev = self
+            .ship_statuses
+            .insert(ship_symbol.to_string(), status.clone());
+        if prev.as_ref() == Some(&status) {
+            return;
+        }
+        match &status {
+            ShipStatus::Skipped(reason) => warn!("Ship {} skipped: {}", ship_symbol, reason),
+            ShipStatus::Crashed(error) => {
+                error!("Ship {} script crashed: {}", ship_symbol, error);
+                ALERTS.notify(
+                    &format!("script_crashed/{}", ship_symbol),
+                    &format!("Ship {} script crashed: {}", ship_symbol, error),
+                );
+            }
+            _ => {}
+        }
+        let update = ShipStatusUpdate {
+            symbol: ship_symbol.to_string(),
+            status,
+        };
+        self.emit_event(&Event::ShipStatus(update)).await;
+    }
+
+    // A crashed script leaves the ship stopped, the rest of the agent keeps running
+    async fn on_script_crash(&self, ship_symbol: &str, error: String) {
+        self.running_scripts.remove(ship_symbol);
+        self.set_ship_status(ship_symbol, ShipStatus::Crashed(error))
+            .await;
+    }
+
+    // An API error ends the script but not the ship: after a pause the ship is resynced and started
+    // again, and the script resumes from its saved state
+    async fn on_script_error(&self, ship_symbol: &str, error: ApiError) {
+        warn!(
+            "Ship {} script failed, restarting in {}s: {}",
+            ship_symbol, SCRIPT_RESTART_SECS, error
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(std::time::Duration::from_secs(SCRIPT_RESTART_SECS)) => {}
+            _ = self.shutdown.cancelled() => return,
+        }
+        self.resync_ship(ship_symbol).await;
+        self.running_scripts.remove(ship_symbol);
+        let target = self
+            .transfer_requests
+            .get(ship_symbol)
+            .map(|x| x.value().clone());
+        match target {
+            Some(target) => self.complete_transfer(ship_symbol, target).await,
+            None => self._spawn_run_ship(ship_symbol.to_string()).await,
+        }
+    }
+
+    // Replace our copy of the ship, which a failed request may have left out of date
+    async fn resync_ship(&self, ship_symbol: &str) {
+        let mut ship = match self.api_client.get_ship(ship_symbol).await {
+            Ok(ship) => ship,
+            Err(e) => {
+                warn!("Failed to resync ship {}: {}", ship_symbol, e);
+                return;
+            }
+        };
+        if let Some(current) = self.ships.get(ship_symbol) {
+            let mut current = current.write().unwrap();
+            if let Some(expiration) = current.cooldown.expiration {
+                ship.cooldown.restore(expiration, self.universe.now());
+            }
+            *current = ship;
+        }
+        self.mark_ship_dirty(ship_symbol);
+    }
+
+    async fn on_script_exit(&self, ship_symbol: &str) {
+        self.running_scripts.remove(ship_symbol);
+        let target = match self.transfer_requests.get(ship_symbol) {
+            Some(target) => target.value().clone(),
+            None => {
+                self.set_ship_status(ship_symbol, ShipStatus::Paused).await;
+                return;
+            }
+        };
+        self.complete_transfer(ship_symbol, target).await;
+    }
+
+    async fn complete_transfer(&self, ship_symbol: &str, target: TransferTarget) {
+        let ship_controller = self.ship_controller(ship_symbol);
+        ship_controller.wait_for_transit().await;
+
+        let old_job = self
+            .job_assignments_rev
+            .get(ship_symbol)
+            .map(|x| x.value().clone());
+        let old_job_exists = match &old_job {
+            Some(job_id) => self.get_ship_config().iter().any(|job| job.id == *job_id),
+            None => false,
+        };
+        let mut target = target;
+        if !ship_controller.cargo_empty() && matches!(target, TransferTarget::Job(_)) {
+            if old_job_exists {
+                warn!(
+                    "Ship {} has unaccounted cargo, resuming job {:?} instead of transfer",
+                    ship_symbol, old_job
+                );
+                self.transfer_requests.remove(ship_symbol);
+                self._spawn_run_ship(ship_symbol.to_string()).await;
+                return;
+            }
+            warn!(
+                "Ship {} has cargo and no job to resume, salvaging",
+                ship_symbol
+            );
+            target = TransferTarget::Salvage;
+        }
+
+        {
+            let _guard = self.assignment_mutex_guard.lock().await;
+            if let Some(old_job) = &old_job {
+                self.job_assignments.remove(old_job);
+            }
+            self.job_assignments_rev.remove(ship_symbol);
+            self.task_manager.release_ship_tasks(ship_symbol).await;
+            match &target {
+                TransferTarget::Job(job_id) => {
+                    ok_or_warn(
+                        self.db
+                            .assign_job(&self.callsign, ship_symbol, job_id)
+                            .await,
+                        "assign job",
+                    );
+                    self.job_assignments
+                        .insert(job_id.clone(), ship_symbol.to_string());
+                    self.job_assignments_rev
+                        .insert(ship_symbol.to_string(), job_id.clone());
+                    let ship_config = self.get_ship_config();
+                    if let Some(job) = ship_config.iter().find(|job| job.id == *job_id) {
+                        self.ledger.reserve_credits(ship_symbol, 0);
+                        self.reserve_credits_for_job(job, ship_symbol);
+                    }
+                }
+                TransferTarget::Idle => {
+                    ok_or_warn(
+                        self.db.unassign_job(&self.callsign, ship_symbol).await,
+                        "unassign job",
+                    );
+                    self.ledger.reserve_credits(ship_symbol, 0);
+                }
+                TransferTarget::Salvage => {
+                    ok_or_warn(
+                        self.db.unassign_job(&self.callsign, ship_symbol).await,
+                        "unassign job",
+                    );
+                }
+            }
+            self.transfer_requests.remove(ship_symbol);
+        }
+        info!(
+            "Transferred {} from {:?} to {:?}",
+            ship_symbol, old_job, target
+        );
+
+        match target {
+            TransferTarget::Job(_) => self._spawn_run_ship(ship_symbol.to_string()).await,
+            TransferTarget::Salvage => {
+                self.set_ship_status(ship_symbol, ShipStatus::Running("salvage".to_string()))
+                    .await;
+                let ship_symbol = ship_symbol.to_string();
+                let join_hdl = tokio::spawn(async move {
+                    // salvage isn't a job the ship can be restarted with, so it's retried here
+                    while let Err(e) = ship_scripts::scrap::run(ship_controller.clone()).await {
+                        warn!(
+                            "Ship {} salvage failed, retrying in {}s: {}",
+                            ship_symbol, SCRIPT_RESTART_SECS, e
+                        );
+                        tokio::time::sleep(std::time::Duration::from_secs(SCRIPT_RESTART_SECS))
+                            .await;
+                    }
+                });
+                self.hdls.push(join_hdl).await;
+            }
+            TransferTarget::Idle => self.set_ship_status(ship_symbol, ShipStatus::Paused).await,
+        }
+    }
+
+    async fn try_buy_ships_lock(&self) -> tokio::sync::MutexGuard<()> {
+        match self.try_buy_ships_mutex_guard.try_lock() {
+            Ok(guard) => guard,
             Err(_e) => {
                 debug!("AgentController::try_buy_ships is already running");
                 let timeout = tokio::time::Duration::from_secs(30);
@@ -514,93 +1943,273 @@ impl AgentController {
             "try_buy_ship ({:?}): {} {} {:?}",
             purchaser, job.id, job.ship_model, purchase_criteria
         );
-        if purchase_criteria.never_purchase {
-            return BuyShipResult::FailedNeverPurchase;
-        }
         let purchase_system = match &purchase_criteria.system_symbol {
             Some(system_symbol) => system_symbol.clone(),
             None => self.starting_system(),
         };
 
-        // if ship docked at shipyard + credits available, buy ship immediately
-        // otherwise, register as a (potential) task
-        let mut shipyards = self
-            .universe
-            .search_shipyards(&purchase_system, &job.ship_model)
-            .await;
-        shipyards.sort_by_key(|x| x.1);
-
-        if shipyards.len() == 0 {
-            return BuyShipResult::FailedNoShipyards;
-        }
-        let job_credit_reservation = match &job.behaviour {
-            ShipBehaviour::Logistics(_) => {
-                SHIP_MODELS[job.ship_model.as_str()].cargo_capacity * 5000
+        // The shipyard is refreshed by the purchaser, at most once, if its data is stale or the
+        // purchase is rejected, then the purchase is planned again
+        let mut refreshed = false;
+        loop {
+            // if ship docked at shipyard + credits available, buy ship immediately
+            // otherwise, register as a (potential) task
+            let mut shipyards = self
+                .universe
+                .search_shipyards(&purchase_system, &job.ship_model)
+                .await;
+            shipyards.sort_by_key(|x| x.1);
+            if let Some(threshold) = self
+                .ship_price_threshold(&purchase_system, &job.ship_model)
+                .await
+            {
+                if shipyards
+                    .first()
+                    .is_some_and(|(_, price)| *price > threshold)
+                {
+                    return BuyShipResult::FailedPriceHigh;
+                }
             }
-            _ => 0,
-        };
-        let current_credits = self.ledger.available_credits();
-        let cheapest_shipard = shipyards[0].0.clone();
-        let can_afford_cheapest = current_credits >= shipyards[0].1 + job_credit_reservation;
-        debug!("try_buy_ship Credits available: {}", current_credits);
-        debug!(
-            "try_buy_ship Extra credits for job reservation: {}",
-            job_credit_reservation
-        );
 
-        let static_probes = self.statically_probed_waypoints();
-        for (shipyard, cost) in &shipyards {
-            if current_credits < cost + job_credit_reservation {
-                break; // no point looking at more expensive shipyards
+            let current_credits = self.ledger.available_credits();
+            let static_probes = self.statically_probed_waypoints();
+            let plan = plan_ship_purchase(
+                job,
+                self.universe.ship_catalog(),
+                &shipyards,
+                current_credits,
+                |shipyard| {
+                    // look for a purchaser
+                    self.ships
+                        .iter()
+                        .find(|ship| {
+                            let ship = ship.value().read().unwrap();
+                            if ship.nav.waypoint_symbol != *shipyard || ship.nav.status == InTransit
+                            {
+                                return false;
+                            }
+                            let is_static_probe =
+                                static_probes.iter().any(|(s, _w)| s == &ship.symbol);
+                            let is_purchaser = match &purchaser {
+                                Some(purchaser) => ship.symbol == *purchaser,
+                                None => false,
+                            };
+                            is_static_probe || is_purchaser
+                        })
+                        .map(|ship| ship.key().clone())
+                },
+            );
+            let (shipyard, ship_symbol) = match plan {
+                Ok(plan) => plan,
+                Err(result) => return result,
+            };
+            let ship_controller = self.ship_controller(&ship_symbol);
+            if !refreshed {
+                let last_refreshed = self
+                    .universe
+                    .get_shipyard(&shipyard)
+                    .await
+                    .map(|shipyard| shipyard.timestamp);
+                if is_shipyard_stale(
+                    last_refreshed,
+                    self.universe.now(),
+                    CONFIG.shipyard_stale_mins,
+                ) {
+                    self.debug(&format!("Shipyard {} is stale, refreshing", shipyard));
+                    if let Err(e) = ship_controller.refresh_shipyard().await {
+                        warn!("Refresh of shipyard {} failed: {}", shipyard, e);
+                        return BuyShipResult::FailedApiError;
+                    }
+                    refreshed = true;
+                    continue;
+                }
             }
-            // look for a purchaser
-            let ship_symbol: Option<String> = self
-                .ships
-                .iter()
-                .find(|ship| {
-                    let ship = ship.value().lock().unwrap();
-                    if ship.nav.waypoint_symbol != *shipyard || ship.nav.status == InTransit {
-                        return false;
+            match self.buy_ship(&shipyard, &job.ship_model).await {
+                Ok(bought_ship_symbol) => {
+                    if let Err(e) = ship_controller.refresh_shipyard().await {
+                        warn!("Refresh of shipyard {} failed: {}", shipyard, e);
                     }
-                    let is_static_probe = static_probes.iter().any(|(s, _w)| s == &ship.symbol);
-                    let is_purchaser = match &purchaser {
-                        Some(purchaser) => ship.symbol == *purchaser,
-                        None => false,
-                    };
-                    is_static_probe || is_purchaser
-                })
-                .map(|ship| ship.key().clone());
-            let ship_controller = match &ship_symbol {
-                Some(ship_symbol) => self.ship_controller(ship_symbol),
-                None => {
-                    // this 'no purchaser' case is the only one where we iterate through the other shipyards
-                    if purchase_criteria.require_cheapest {
-                        break;
-                    } else {
-                        continue;
+                    let assigned = self.try_assign_ship(&bought_ship_symbol).await;
+                    assert!(assigned);
+                    return BuyShipResult::Bought(bought_ship_symbol);
+                }
+                Err(e) if e.is_rejected() => {
+                    warn!(
+                        "Purchase of {} at {} rejected: {}",
+                        job.ship_model,
+                        shipyard,
+                        e.body().unwrap_or_default()
+                    );
+                    if let Err(e) = ship_controller.refresh_shipyard().await {
+                        warn!("Refresh of shipyard {} failed: {}", shipyard, e);
+                        return BuyShipResult::FailedApiError;
+                    }
+                    if refreshed {
+                        return BuyShipResult::FailedRejected;
                     }
+                    refreshed = true;
+                }
+                Err(e) => {
+                    warn!(
+                        "Purchase of {} at {} failed: {}",
+                        job.ship_model, shipyard, e
+                    );
+                    return BuyShipResult::FailedApiError;
                 }
+            }
+        }
+    }
+
+    // Highest price to pay for the model in the system, None to buy at any price
+    async fn ship_price_threshold(&self, system: &SystemSymbol, ship_model: &str) -> Option<i64> {
+        if CONFIG.ship_price_percentile == 0 {
+            return None;
+        }
+        let since =
+            self.universe.now() - Duration::try_hours(CONFIG.ship_price_window_hours).unwrap();
+        let history = self.db.get_ship_listing_history(ship_model, since).await;
+        let prices = ok_or_warn(history, "load ship listing history")?
+            .into_iter()
+            .filter(|sample| WaypointSymbol::new(&sample.shipyard_symbol).system() == *system)
+            .map(|sample| sample.purchase_price as i64)
+            .collect::<Vec<_>>();
+        price_threshold(&prices, CONFIG.ship_price_percentile)
+    }
+
+    // A ship model still to be bought that the shipyard now sells at or below its price threshold
+    async fn wanted_price_drop(&self, shipyard: &WaypointSymbol) -> Option<(String, i64)> {
+        let listings = self.universe.get_shipyard(shipyard).await?;
+        for job in self
+            .get_ship_config()
+            .iter()
+            .filter(|job| !self.job_assigned(&job.id))
+            .filter(|job| !job.purchase_criteria.never_purchase)
+        {
+            let purchase_system = match &job.purchase_criteria.system_symbol {
+                Some(system_symbol) => system_symbol.clone(),
+                None => self.starting_system(),
+            };
+            if purchase_system != shipyard.system() {
+                continue;
+            }
+            let Some(listing) = listings
+                .data
+                .ships
+                .iter()
+                .find(|ship| ship.ship_type == job.ship_model)
+            else {
+                continue;
             };
-            let bought_ship_symbol = self.buy_ship(shipyard, &job.ship_model).await;
-            ship_controller.refresh_shipyard().await;
-            let assigned = self.try_assign_ship(&bought_ship_symbol).await;
-            assert!(assigned);
-            return BuyShipResult::Bought(bought_ship_symbol);
+            let Some(threshold) = self
+                .ship_price_threshold(&purchase_system, &job.ship_model)
+                .await
+            else {
+                continue;
+            };
+            if listing.purchase_price <= threshold {
+                return Some((job.ship_model.clone(), listing.purchase_price));
+            }
         }
-        if !can_afford_cheapest {
-            return BuyShipResult::FailedLowCredits;
+        None
+    }
+
+    // Cheapest known shipyard for the job in its purchase system, and the cost including the job's
+    // credit reservation
+    async fn cheapest_purchase(&self, job: &ShipConfig) -> Option<(WaypointSymbol, i64)> {
+        let purchase_system = match &job.purchase_criteria.system_symbol {
+            Some(system_symbol) => system_symbol.clone(),
+            None => self.starting_system(),
+        };
+        let shipyards = self
+            .universe
+            .search_shipyards(&purchase_system, &job.ship_model)
+            .await;
+        let reservation = job_credit_reservation(job, self.universe.ship_catalog());
+        shipyards
+            .into_iter()
+            .min_by_key(|x| x.1)
+            .map(|(shipyard, price)| (shipyard, price + reservation))
+    }
+
+    // When the next ships to buy are forecast to be affordable
+    pub async fn credit_forecast(&self) -> CreditForecast {
+        let available_credits = self.ledger.available_credits();
+        let income_per_hour = self.ledger.net_income_per_hour();
+        let ship_config = self.get_ship_config();
+        let mut jobs = vec![];
+        for job in ship_config
+            .iter()
+            .filter(|job| !self.job_assigned(&job.id))
+            .filter(|job| !job.purchase_criteria.never_purchase)
+        {
+            let Some((_shipyard, cost)) = self.cheapest_purchase(job).await else {
+                continue;
+            };
+            jobs.push((job, cost));
+            if jobs.len() == FORECAST_PURCHASES {
+                break;
+            }
         }
-        if purchase_criteria.allow_logistic_task {
-            BuyShipResult::FailedNoPurchaser(Some(cheapest_shipard))
-        } else {
-            BuyShipResult::FailedNoPurchaser(None)
+        let costs = jobs.iter().map(|(_job, cost)| *cost).collect::<Vec<_>>();
+        let times = affordable_times(
+            available_credits,
+            income_per_hour,
+            &costs,
+            self.universe.now(),
+        );
+        CreditForecast {
+            available_credits,
+            income_per_hour,
+            purchases: jobs
+                .into_iter()
+                .zip(times)
+                .map(|((job, cost), affordable_at)| PurchaseForecast {
+                    job_id: job.id.clone(),
+                    ship_model: job.ship_model.clone(),
+                    cost,
+                    affordable_at,
+                })
+                .collect(),
+        }
+    }
+
+    // A shipyard to send a purchaser to, and when to be there, if the job can't be afforded yet
+    // but is forecast to be within the purchase lead time
+    async fn preposition_purchase(
+        &self,
+        job: &ShipConfig,
+    ) -> Option<(WaypointSymbol, DateTime<Utc>)> {
+        if !job.purchase_criteria.allow_logistic_task {
+            return None;
         }
+        let (shipyard, cost) = self.cheapest_purchase(job).await?;
+        // a probe stationed there buys it as soon as we can afford it
+        if self
+            .statically_probed_waypoints()
+            .iter()
+            .any(|(_ship, waypoint)| *waypoint == shipyard)
+        {
+            return None;
+        }
+        let now = self.universe.now();
+        let affordable_at = affordable_times(
+            self.ledger.available_credits(),
+            self.ledger.net_income_per_hour(),
+            &[cost],
+            now,
+        )[0]?;
+        if affordable_at > now + Duration::try_minutes(CONFIG.purchase_lead_mins).unwrap() {
+            return None;
+        }
+        Some((shipyard, affordable_at))
     }
 
+    // Ships bought, and a shipyard a logistics ship should visit to buy the next one, no earlier
+    // than the given time
     pub async fn try_buy_ships(
         &self,
         purchaser: Option<String>,
-    ) -> (Vec<String>, Option<WaypointSymbol>) {
+    ) -> (Vec<String>, Option<(WaypointSymbol, Option<DateTime<Utc>>)>) {
         let _guard = self.try_buy_ships_lock().await;
 
         self.check_era_advance().await;
@@ -624,6 +2233,13 @@ impl AgentController {
                     return (purchased_ships, None);
                 }
                 BuyShipResult::FailedLowCredits => {
+                    if let Some((waypoint, affordable_at)) = self.preposition_purchase(job).await {
+                        debug!(
+                            "Not buying ship {}: low credits, affordable at {}. Adding task @ {}",
+                            job.ship_model, affordable_at, waypoint
+                        );
+                        return (purchased_ships, Some((waypoint, Some(affordable_at))));
+                    }
                     debug!("Not buying ship {}: low credits", job.ship_model);
                     return (purchased_ships, None);
                 }
@@ -631,13 +2247,26 @@ impl AgentController {
                     debug!("Not buying ship {}: no shipyards", job.ship_model);
                     return (purchased_ships, None);
                 }
+                BuyShipResult::FailedRejected => {
+                    debug!("Not buying ship {}: purchase rejected", job.ship_model);
+                    return (purchased_ships, None);
+                }
+                BuyShipResult::FailedApiError => {
+                    debug!("Not buying ship {}: request failed", job.ship_model);
+                    return (purchased_ships, None);
+                }
+                BuyShipResult::FailedPriceHigh => {
+                    // later jobs may be at a good price now
+                    debug!("Not buying ship {}: price above threshold", job.ship_model);
+                    continue;
+                }
                 BuyShipResult::FailedNoPurchaser(waypoint) => {
                     if let Some(waypoint) = waypoint {
                         debug!(
                             "Not buying ship {}: no purchaser. Adding task @ {}",
                             job.ship_model, waypoint
                         );
-                        return (purchased_ships, Some(waypoint));
+                        return (purchased_ships, Some((waypoint, None)));
                     }
                     debug!("Not buying ship {}: no purchaser", job.ship_model);
                     return (purchased_ships, None);
@@ -654,12 +2283,47 @@ impl AgentController {
             _ => return,
         }
         let ship = self.ships.get(ship_symbol).unwrap();
-        let ship = ship.lock().unwrap();
+        let ship = ship.read().unwrap();
         self.ledger
             .reserve_credits(ship_symbol, ship.cargo.capacity * 5000);
     }
 
     pub async fn generate_ship_config(&self) -> Vec<ShipConfig> {
+        let mut ships = self._generate_ship_config().await;
+        ships.append(&mut self.goal_ship_config().await);
+        let start_system = self.starting_system();
+        for (system_symbol, count) in self.hauler_autoscaler.extra_haulers() {
+            let mut haulers = autoscaled_haulers(&ships, &system_symbol, &start_system, count);
+            ships.append(&mut haulers);
+        }
+        ships
+    }
+
+    // A probe stationed in each system we have a ColonizeSystem goal for
+    async fn goal_ship_config(&self) -> Vec<ShipConfig> {
+        let mut ships = vec![];
+        for goal in self.goals() {
+            let Goal::ColonizeSystem(system_symbol) = &goal.goal else {
+                continue;
+            };
+            let waypoint = self.universe.first_waypoint(system_symbol).await;
+            ships.push(ShipConfig {
+                id: format!("colonize/{}", system_symbol),
+                ship_model: "SHIP_PROBE".to_string(),
+                behaviour: ShipBehaviour::Probe(ProbeScriptConfig {
+                    waypoints: vec![waypoint],
+                    refresh_market: true,
+                }),
+                purchase_criteria: PurchaseCriteria {
+                    system_symbol: Some(system_symbol.clone()),
+                    ..PurchaseCriteria::default()
+                },
+            });
+        }
+        ships
+    }
+
+    async fn _generate_ship_config(&self) -> Vec<ShipConfig> {
         let era = self.state().era;
 
         if era == AgentEra::InterSystem2 {
@@ -698,6 +2362,7 @@ impl AgentController {
             &shipyards,
             use_nonstatic_probes,
             incl_outer_probes_and_siphons,
+            CONFIG.strategy,
         ));
 
         if era == AgentEra::InterSystem1 {
@@ -706,14 +2371,28 @@ impl AgentController {
                 self.universe.get_system_waypoints(&capital).await;
             let markets = self.universe.get_system_markets_remote(&capital).await;
             let shipyards = self.universe.get_system_shipyards_remote(&capital).await;
-            ships.append(&mut ship_config_capital_system(
+            let mut capital_ships = ship_config_capital_system(
                 &capital,
                 &start_system,
                 &waypoints,
                 &markets,
                 &shipyards,
                 false,
-            ));
+            );
+            let onboarding = self.onboarding().filter(|p| p.system_symbol == capital);
+            let next_step = onboarding.and_then(|p| p.next_step());
+            if next_step.is_some() {
+                // only probes until the capital is onboarded, the first bought at home to fly
+                // through the gate, since nothing can buy ships in the capital before it arrives
+                capital_ships.retain(|job| matches!(job.behaviour, ShipBehaviour::Probe(_)));
+                if next_step == Some(OnboardingStep::FirstProbe) {
+                    capital_ships.truncate(1);
+                    for job in capital_ships.iter_mut() {
+                        job.purchase_criteria.system_symbol = Some(start_system.clone());
+                    }
+                }
+            }
+            ships.append(&mut capital_ships);
         }
         ships
     }
@@ -739,14 +2418,22 @@ impl AgentController {
         self.set_ship_config(ship_config.clone());
 
         // Unassign
+        let guard = self.assignment_mutex_guard.lock().await;
         let mut keys_to_remove = Vec::new();
+        let mut stale_running = Vec::new();
+        let mut stale_unsafe = Vec::new();
         for it in self.job_assignments.iter() {
             let (job_id, ship_symbol) = it.pair();
             let job_exists = ship_config.iter().any(|job| job.id == *job_id);
             let ship_exists = self.ships.contains_key(ship_symbol);
-            if !job_exists {
-                // if the job no longer exists, unassign the ship,
-                // May be risky because we don't know if the ship is in the middle of a task
+            if !job_exists && ship_exists && self.running_scripts.contains_key(ship_symbol) {
+                // the ship is mid-script, so hand it over once it reaches a safe point
+                stale_running.push(ship_symbol.clone());
+            } else if !job_exists && ship_exists && !self.ship_safe_to_unassign(ship_symbol) {
+                // holding cargo, in transit or mid-transfer, so unload it before handing it over
+                stale_unsafe.push(ship_symbol.clone());
+            } else if !job_exists {
+                // if the job no longer exists, unassign the ship
                 warn!(
                     "Unassigning ship {} from non-existant job {}",
                     ship_symbol, job_id
@@ -765,13 +2452,14 @@ impl AgentController {
         for (job_id, ship_symbol) in keys_to_remove {
             self.job_assignments.remove(&job_id);
             self.job_assignments_rev.remove(&ship_symbol);
+            ok_or_warn(
+                self.db.unassign_job(&self.callsign, &ship_symbol).await,
+                "unassign job",
+            );
         }
-        self.db
-            .set_value(
-                &format!("{}/ship_assignments", self.callsign),
-                self.job_assignments.deref(),
-            )
-            .await;
+        drop(guard);
+        self.rebalance_assignments(&stale_running, &ship_config);
+        self.unload_stale_ships(&stale_unsafe, &ship_config).await;
 
         // Assign
         for ship in self.ships.iter() {
@@ -781,35 +2469,125 @@ impl AgentController {
             }
         }
 
-        // load/refresh ledger - important to do this before starting ship scripts or buying more ships
-        self.ledger.reserve_credits("FUEL", 10_000);
-        if self.is_jumpgate_finished().await {
-            self.ledger.reserve_credits("JUMPGATE_COSTS", 500_000);
+        // load/refresh ledger - important to do this before starting ship scripts or buying more ships
+        self.ledger.reserve_credits("FUEL", 10_000);
+        if self.is_jumpgate_finished().await {
+            self.ledger.reserve_credits("JUMPGATE_COSTS", 500_000);
+        }
+        for ship_config in ship_config {
+            if let Some(ship_symbol) = &self.job_assignments.get(&ship_config.id) {
+                let ship_symbol: &String = ship_symbol.value();
+                self.reserve_credits_for_job(&ship_config, ship_symbol);
+            }
+        }
+    }
+
+    pub async fn run_ships(&self) {
+        let self_clone = self.clone();
+        {
+            let join_hdl = tokio::spawn(async move {
+                let broker = self_clone.cargo_broker.clone();
+                broker.run(Box::new(self_clone)).await;
+            });
+            debug!("spawn_broker try push join_hdl");
+            self.hdls.push(join_hdl).await;
+            debug!("spawn_broker pushed join_hdl");
+        }
+        let self_clone = self.clone();
+        {
+            let join_hdl = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(NET_WORTH_SAMPLE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    self_clone.record_net_worth().await;
+                }
+            });
+            self.hdls.push(join_hdl).await;
+        }
+        let self_clone = self.clone();
+        {
+            let join_hdl = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(SHIP_UPDATE_WINDOW);
+                loop {
+                    interval.tick().await;
+                    self_clone.flush_ship_updates().await;
+                }
+            });
+            self.hdls.push(join_hdl).await;
+        }
+
+        let self_clone = self.clone();
+        {
+            let join_hdl = tokio::spawn(async move {
+                loop {
+                    self_clone.era_reevaluation.notified().await;
+                    let (bought, _) = self_clone.try_buy_ships(None).await;
+                    for ship_symbol in bought {
+                        self_clone._spawn_run_ship(ship_symbol).await;
+                    }
+                }
+            });
+            self.hdls.push(join_hdl).await;
         }
-        for ship_config in ship_config {
-            if let Some(ship_symbol) = &self.job_assignments.get(&ship_config.id) {
-                let ship_symbol: &String = ship_symbol.value();
-                self.reserve_credits_for_job(&ship_config, ship_symbol);
-            }
+        let self_clone = self.clone();
+        {
+            let mut completions = self.universe.subscribe_jumpgate_completions();
+            let join_hdl = tokio::spawn(async move {
+                while completions.changed().await.is_ok() {
+                    let jumpgate = completions.borrow_and_update().clone();
+                    if let Some(jumpgate) = jumpgate {
+                        self_clone
+                            .schedule_era_reevaluation(&format!("jumpgate {} completed", jumpgate));
+                    }
+                }
+            });
+            self.hdls.push(join_hdl).await;
         }
-    }
-
-    pub async fn run_ships(&self) {
         let self_clone = self.clone();
         {
+            let mut shipyard_updates = self.universe.subscribe_shipyard_updates();
             let join_hdl = tokio::spawn(async move {
-                let broker = self_clone.cargo_broker.clone();
-                broker.run(Box::new(self_clone)).await;
+                while shipyard_updates.changed().await.is_ok() {
+                    let shipyard = shipyard_updates.borrow_and_update().clone();
+                    let Some(shipyard) = shipyard else {
+                        continue;
+                    };
+                    let Some((ship_model, price)) = self_clone.wanted_price_drop(&shipyard).await
+                    else {
+                        continue;
+                    };
+                    info!(
+                        "{} at {} dropped to {}, trying to buy ships",
+                        ship_model, shipyard, price
+                    );
+                    let (bought, _) = self_clone.try_buy_ships(None).await;
+                    for ship_symbol in bought {
+                        self_clone._spawn_run_ship(ship_symbol).await;
+                    }
+                }
             });
-            debug!("spawn_broker try push join_hdl");
             self.hdls.push(join_hdl).await;
-            debug!("spawn_broker pushed join_hdl");
         }
 
         // Generate ship config, purchase + assign ships
         // purchased ships are assigned, but not yet started
         let (_bought, _tasks) = self.try_buy_ships(None).await;
 
+        // goods bought before the last shutdown, over the fresh reservations. The snapshot is
+        // cleared so a later crash doesn't restore it again
+        let ledger_key = format!("{}/ledger", self.callsign);
+        let snapshot: Option<LedgerSnapshot> =
+            ok_or_warn(self.db.get_value(&ledger_key).await, "load ledger snapshot").flatten();
+        if let Some(snapshot) = snapshot {
+            self.ledger.restore(snapshot);
+            ok_or_warn(
+                self.db
+                    .set_value(&ledger_key, &LedgerSnapshot::default())
+                    .await,
+                "clear ledger snapshot",
+            );
+        }
+
         let self_clone = self.clone();
         let start = tokio::spawn(async move {
             for ship in self_clone.ships.iter() {
@@ -821,13 +2599,61 @@ impl AgentController {
         info!("All ships have completed their tasks");
     }
 
+    // Abort every ship script and background loop started by run_ships, which must no longer be polled
+    pub fn stop_ships(&self) {
+        self.shutdown.cancel();
+        self.hdls.abort_all();
+        info!("Stopped all ship scripts");
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    // Stop the ship scripts and save the state only held in memory, so they resume on restart.
+    // API requests should be drained first, so no script is stopped partway through a call
+    pub async fn shutdown(&self) {
+        self.stop_ships();
+        self.task_manager.flush().await;
+        self.survey_manager.flush().await;
+        ok_or_warn(
+            self.db
+                .set_value(
+                    &format!("{}/ledger", self.callsign),
+                    &self.ledger.snapshot(),
+                )
+                .await,
+            "save ledger",
+        );
+        ok_or_warn(
+            self.db
+                .set_value(&format!("{}/cooldowns", self.callsign), &*self.cooldowns)
+                .await,
+            "save cooldowns",
+        );
+        ok_or_warn(
+            self.db
+                .set_value(
+                    &format!("{}/ship_states", self.callsign),
+                    &*self.ship_states,
+                )
+                .await,
+            "save ship states",
+        );
+        info!("Saved state of agent {}", self.callsign);
+    }
+
     pub async fn try_assign_ship(&self, ship_symbol: &str) -> bool {
+        let _guard = self.assignment_mutex_guard.lock().await;
         assert!(!self.job_assignments_rev.contains_key(ship_symbol));
         let ship = self.ships.get(ship_symbol).unwrap();
-        let ship_model = { ship.lock().unwrap().model().unwrap() };
+        let ship_model = {
+            let ship = ship.read().unwrap();
+            self.universe.ship_catalog().identify(&ship).unwrap()
+        };
         let ship_config = self.get_ship_config();
-        let job_opt = ship_config.iter().find(|job| {
-            !self.job_assignments.contains_key(&job.id) && job.ship_model == ship_model
+        let job_opt = select_job(&ship_model, &ship_config, |job_id| {
+            self.job_assignments.contains_key(job_id)
         });
         match job_opt {
             Some(job) => {
@@ -839,12 +2665,12 @@ impl AgentController {
                     "Assigned {} ({}) to job {}",
                     ship_symbol, ship_model, job.id,
                 );
-                self.db
-                    .set_value(
-                        &format!("{}/ship_assignments", self.callsign),
-                        self.job_assignments.deref(),
-                    )
-                    .await;
+                ok_or_warn(
+                    self.db
+                        .assign_job(&self.callsign, ship_symbol, &job.id)
+                        .await,
+                    "assign job",
+                );
                 self.reserve_credits_for_job(job, ship_symbol);
                 true
             }
@@ -868,114 +2694,155 @@ impl AgentController {
         let job_id_opt = self.job_assignments_rev.get(&ship_symbol);
         let scrap = CONFIG.scrap_all_ships || (job_id_opt.is_none() && CONFIG.scrap_unassigned);
         if scrap {
+            drop(job_id_opt);
+            self.set_ship_status(&ship_symbol, ShipStatus::Running("scrap".to_string()))
+                .await;
             let ship_controller = self.ship_controller(&ship_symbol);
-            let join_hdl = tokio::spawn(async move {
-                ship_scripts::scrap::run(ship_controller).await;
-            });
-            self.hdls.push(join_hdl).await;
+            let script = Box::pin(ship_scripts::scrap::run(ship_controller));
+            self.supervise_script(ship_symbol, script).await;
             return;
         }
 
-        match job_id_opt {
-            Some(job_id) => {
-                let ship_config = self.get_ship_config();
-                let job_spec = ship_config
-                    .iter()
-                    .find(|s| s.id == *job_id)
-                    .unwrap_or_else(|| panic!("No job found for {}", *job_id));
-                if !CONFIG.job_id_filter.is_match(&job_spec.id) {
-                    return;
-                }
-                let ship_controller = self.ship_controller(&ship_symbol);
-                let ship = ship_controller.ship();
-                if ship.engine.condition.unwrap() < 0.0 {
-                    warn!(
-                        "Ship {} has engine condition {}",
-                        ship_symbol,
-                        ship.engine.condition.unwrap()
-                    );
-                    return;
+        let Some(job_id) = job_id_opt.map(|x| x.value().clone()) else {
+            debug!("Warning. No job assigned to ship {}", ship_symbol);
+            self.set_ship_status(&ship_symbol, ShipStatus::Paused).await;
+            return;
+        };
+        let ship_config = self.get_ship_config();
+        let job_spec = ship_config
+            .iter()
+            .find(|s| s.id == job_id)
+            .unwrap_or_else(|| panic!("No job found for {}", job_id));
+        let ship_controller = self.ship_controller(&ship_symbol);
+        let ship = ship_controller.ship();
+        let job_filter = self.job_filter();
+        if let Some(reason) = skip_reason(&ship, job_spec, &job_filter) {
+            self.set_ship_status(&ship_symbol, ShipStatus::Skipped(reason))
+                .await;
+            return;
+        }
+        if let Some(wind_down) = self.system_wind_down(&ship.nav.system_symbol) {
+            match wind_down.parking_target(&ship.nav.waypoint_symbol) {
+                Some(park_at) => self.park_ship(&ship_symbol, &job_spec.id, park_at).await,
+                None => {
+                    self.set_ship_status(&ship_symbol, ShipStatus::Skipped(wind_down.reason()))
+                        .await;
+                    self.check_wind_down(&wind_down.system_symbol).await;
                 }
-                if ship.frame.condition.unwrap() < 0.0 {
-                    warn!(
-                        "Ship {} has frame condition {}",
-                        ship_symbol,
-                        ship.frame.condition.unwrap()
-                    );
-                    return;
+            }
+            return;
+        }
+
+        // run script for assigned job
+        let script: BoxFuture<'static, Result<(), ApiError>> = match &job_spec.behaviour {
+            ShipBehaviour::Probe(config) => {
+                let config = config.clone();
+                Box::pin(async move { ship_scripts::probe::run(ship_controller, &config).await })
+            }
+            ShipBehaviour::Logistics(config) => {
+                let db = self.db.clone();
+                let task_manager = self.task_manager.clone();
+                let config = config.clone();
+                Box::pin(async move {
+                    ship_scripts::logistics::run(ship_controller, db, task_manager, config).await
+                })
+            }
+            ShipBehaviour::SiphonDrone => {
+                Box::pin(async move { ship_scripts::siphon::run_drone(ship_controller).await })
+            }
+            ShipBehaviour::SiphonShuttle => {
+                let db = self.db.clone();
+                Box::pin(
+                    async move { ship_scripts::siphon::run_shuttle(ship_controller, db).await },
+                )
+            }
+            ShipBehaviour::MiningDrone => {
+                let db = self.db.clone();
+                Box::pin(async move {
+                    ship_scripts::mining::run_mining_drone(ship_controller, db).await
+                })
+            }
+            ShipBehaviour::MiningShuttle => {
+                let db = self.db.clone();
+                Box::pin(
+                    async move { ship_scripts::mining::run_shuttle(ship_controller, db).await },
+                )
+            }
+            ShipBehaviour::MiningSurveyor => {
+                let db = self.db.clone();
+                Box::pin(
+                    async move { ship_scripts::mining::run_surveyor(ship_controller, db).await },
+                )
+            }
+            ShipBehaviour::ConstructionHauler => {
+                let db = self.db.clone();
+                Box::pin(async move {
+                    ship_scripts::construction::run_hauler(ship_controller, db).await
+                })
+            }
+            ShipBehaviour::JumpgateProbe => Box::pin(async move {
+                ship_scripts::probe_exploration::run_jumpgate_probe(ship_controller).await
+            }),
+            ShipBehaviour::Explorer => {
+                let db = self.db.clone();
+                Box::pin(async move {
+                    ship_scripts::exploration::run_explorer(ship_controller, db).await
+                })
+            }
+            ShipBehaviour::Custom(name, config) => match self.script_registry.get(name) {
+                Some(script) => {
+                    let config = config.clone();
+                    Box::pin(async move { script.run(ship_controller, config).await })
                 }
-                if ship.reactor.condition.unwrap() < 0.0 {
-                    warn!(
-                        "Ship {} has reactor condition {}",
-                        ship_symbol,
-                        ship.reactor.condition.unwrap()
-                    );
+                None => {
+                    let reason = format!("no ship script registered as {}", name);
+                    self.set_ship_status(&ship_symbol, ShipStatus::Skipped(reason))
+                        .await;
                     return;
                 }
+            },
+        };
 
-                // run script for assigned job
-                let join_hdl = match &job_spec.behaviour {
-                    ShipBehaviour::Probe(config) => {
-                        let config = config.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::probe::run(ship_controller, &config).await;
-                        })
-                    }
-                    ShipBehaviour::Logistics(config) => {
-                        let db = self.db.clone();
-                        let task_manager = self.task_manager.clone();
-                        let config = config.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::logistics::run(ship_controller, db, task_manager, config)
-                                .await;
-                        })
-                    }
-                    ShipBehaviour::SiphonDrone => tokio::spawn(async move {
-                        ship_scripts::siphon::run_drone(ship_controller).await;
-                    }),
-                    ShipBehaviour::SiphonShuttle => {
-                        let db = self.db.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::siphon::run_shuttle(ship_controller, db).await;
-                        })
-                    }
-                    ShipBehaviour::MiningDrone => tokio::spawn(async move {
-                        ship_scripts::mining::run_mining_drone(ship_controller).await;
-                    }),
-                    ShipBehaviour::MiningShuttle => {
-                        let db = self.db.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::mining::run_shuttle(ship_controller, db).await;
-                        })
-                    }
-                    ShipBehaviour::MiningSurveyor => tokio::spawn(async move {
-                        ship_scripts::mining::run_surveyor(ship_controller).await;
-                    }),
-                    ShipBehaviour::ConstructionHauler => {
-                        let db = self.db.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::construction::run_hauler(ship_controller, db).await;
-                        })
-                    }
-                    ShipBehaviour::JumpgateProbe => tokio::spawn(async move {
-                        ship_scripts::probe_exploration::run_jumpgate_probe(ship_controller).await;
-                    }),
-                    ShipBehaviour::Explorer => {
-                        let db = self.db.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::exploration::run_explorer(ship_controller, db).await;
-                        })
+        self.running_scripts
+            .insert(ship_symbol.clone(), job_spec.id.clone());
+        self.set_ship_status(&ship_symbol, ShipStatus::Running(job_spec.id.clone()))
+            .await;
+        self.supervise_script(ship_symbol, script).await;
+    }
+
+    // Runs the script until it ends or the agent shuts down. An API error restarts the ship, a
+    // panic leaves it crashed
+    async fn supervise_script(
+        &self,
+        ship_symbol: String,
+        script: BoxFuture<'static, Result<(), ApiError>>,
+    ) {
+        let self_clone = self.clone();
+        let shutdown = self.shutdown.clone();
+        let join_hdl = tokio::spawn(async move {
+            let script = tokio::spawn(script);
+            let abort = script.abort_handle();
+            tokio::select! {
+                result = script => match result {
+                    Ok(Ok(())) => self_clone.on_script_exit(&ship_symbol).await,
+                    Ok(Err(e)) => self_clone.on_script_error(&ship_symbol, e).await,
+                    Err(e) => {
+                        let error = match e.try_into_panic() {
+                            Ok(panic) => panic_message(panic),
+                            Err(e) => e.to_string(),
+                        };
+                        self_clone.on_script_crash(&ship_symbol, error).await;
                     }
-                };
-                debug!("spawn_run_ship try push join_hdl");
-                self.hdls.push(join_hdl).await;
-                // self.ship_futs.lock().unwrap().push_back(join_hdl);
-                debug!("spawn_run_ship pushed join_hdl");
-            }
-            None => {
-                debug!("Warning. No job assigned to ship {}", ship_symbol);
+                },
+                _ = shutdown.cancelled() => {
+                    abort.abort();
+                    debug!("Cancelled script for {}", ship_symbol);
+                }
             }
-        }
+        });
+        debug!("spawn_run_ship try push join_hdl");
+        self.hdls.push(join_hdl).await;
+        debug!("spawn_run_ship pushed join_hdl");
     }
 
     pub async fn get_probe_jumpgate_reservation(
@@ -1017,12 +2884,15 @@ impl AgentController {
             Some((target, _)) => {
                 self.probe_jumpgate_reservations
                     .insert(ship_symbol.to_string(), target.clone());
-                self.db
-                    .save_probe_jumpgate_reservations(
-                        &self.callsign,
-                        &self.probe_jumpgate_reservations,
-                    )
-                    .await;
+                ok_or_warn(
+                    self.db
+                        .save_probe_jumpgate_reservations(
+                            &self.callsign,
+                            &self.probe_jumpgate_reservations,
+                        )
+                        .await,
+                    "save probe jumpgate reservations",
+                );
                 Some(target.clone())
             }
             None => None,
@@ -1035,9 +2905,76 @@ impl AgentController {
             assert_eq!(self.universe.connections_known(target.value()), true);
         }
         self.probe_jumpgate_reservations.remove(ship_symbol);
-        self.db
-            .save_probe_jumpgate_reservations(&self.callsign, &self.probe_jumpgate_reservations)
-            .await;
+        ok_or_warn(
+            self.db
+                .save_probe_jumpgate_reservations(&self.callsign, &self.probe_jumpgate_reservations)
+                .await,
+            "save probe jumpgate reservations",
+        );
+    }
+
+    // A shipyard with no probe assigned that this roaming probe should refresh in its cycle, until a
+    // replacement probe is bought
+    pub async fn get_probe_shipyard_reservation(
+        &self,
+        ship_symbol: &str,
+    ) -> Option<WaypointSymbol> {
+        let _lock = self.probe_reserve_mutex_guard.lock().await;
+        let mut uncovered = vec![];
+        let mut probes = vec![];
+        for job in self.get_ship_config() {
+            let ShipBehaviour::Probe(config) = &job.behaviour else {
+                continue;
+            };
+            let assigned = self.job_assignments.get(&job.id).map(|x| x.value().clone());
+            match assigned {
+                None if config.waypoints.len() == 1 => {
+                    let waypoint = self.universe.detailed_waypoint(&config.waypoints[0]).await;
+                    if waypoint.is_shipyard() {
+                        uncovered.push((waypoint.symbol.clone(), waypoint.x, waypoint.y));
+                    }
+                }
+                Some(probe_symbol) if config.waypoints.len() > 1 => {
+                    let location = self
+                        .ships
+                        .get(&probe_symbol)
+                        .map(|ship| ship.read().unwrap().nav.waypoint_symbol.clone());
+                    let Some(location) = location else {
+                        continue;
+                    };
+                    let waypoint = self.universe.detailed_waypoint(&location).await;
+                    probes.push(RoamingProbe {
+                        symbol: probe_symbol,
+                        waypoint: location,
+                        x: waypoint.x,
+                        y: waypoint.y,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let assignments = {
+            let mut reserved = self.probe_shipyard_reservations.lock().unwrap();
+            let assignments = cover_assignments(&uncovered, &probes, &reserved);
+            if assignments == *reserved {
+                return assignments.get(ship_symbol).cloned();
+            }
+            for (probe, shipyard) in &assignments {
+                if reserved.get(probe) != Some(shipyard) {
+                    info!("Probe {} covering shipyard {}", probe, shipyard);
+                }
+            }
+            *reserved = assignments.clone();
+            assignments
+        };
+        ok_or_warn(
+            self.db
+                .save_probe_shipyard_reservations(&self.callsign, &assignments)
+                .await,
+            "save probe shipyard reservations",
+        );
+        assignments.get(ship_symbol).cloned()
     }
 
     pub async fn get_explorer_reservation(
@@ -1053,12 +2990,13 @@ impl AgentController {
         // Choose a new system to reserve, closest to the ship's current location that is not already reserved
         let _lock = self.explorer_reserve_mutex_guard.lock().await;
         let graph = self.universe.warp_jump_graph().await;
+        let fuel_price = self.universe.fleet_fuel_price();
         let reachables = dijkstra_all(ship_loc, |node| {
             graph
                 .get(node)
                 .unwrap()
                 .iter()
-                .map(|(s, d)| (s.clone(), d.duration))
+                .map(|(s, d)| (s.clone(), d.weight(CONFIG.burn_time_value, fuel_price)))
         });
         let mut starter_systems = vec![];
         for system in self.universe.systems() {
@@ -1085,32 +3023,95 @@ impl AgentController {
 
         match target {
             Some((target, _)) => {
+                if let Some(route) = self.universe.warp_route(ship_loc, target).await {
+                    info!(
+                        "Reserving {} for {}: {} hops, {}s ({}s drifting), {} fuel",
+                        target,
+                        ship_symbol,
+                        route.segments.len(),
+                        route.duration,
+                        route.drift_duration(),
+                        route.fuel
+                    );
+                }
                 self.explorer_reservations
                     .insert(ship_symbol.to_string(), target.clone());
-                self.db
-                    .save_explorer_reservations(&self.callsign, &self.explorer_reservations)
-                    .await;
+                ok_or_warn(
+                    self.db
+                        .save_explorer_reservations(&self.callsign, &self.explorer_reservations)
+                        .await,
+                    "save explorer reservations",
+                );
                 Some(target.clone())
             }
             None => None,
         }
     }
 
-    pub fn set_state_description(&self, ship_symbol: &str, desc: &str) {
-        self.ship_state_description
-            .insert(ship_symbol.to_string(), desc.to_string());
+    pub async fn set_state_description(&self, ship_symbol: &str, desc: &str) {
+        ALERTS.record_ship_state(ship_symbol, desc);
+        let now = self.universe.now();
+        self.update_ship_state(ship_symbol, |prev| ShipState::with_step(prev, desc, now))
+            .await;
+    }
+
+    // The destination of the ship's current navigation, None once it arrives
+    pub async fn set_ship_target(
+        &self,
+        ship_symbol: &str,
+        target: Option<&WaypointSymbol>,
+        eta: Option<DateTime<Utc>>,
+    ) {
+        let now = self.universe.now();
+        self.update_ship_state(ship_symbol, |prev| {
+            ShipState::with_target(prev, target, eta, now)
+        })
+        .await;
+    }
+
+    // Saved and published when the state changes
+    async fn update_ship_state(
+        &self,
+        ship_symbol: &str,
+        f: impl FnOnce(Option<&ShipState>) -> ShipState,
+    ) {
+        let state = {
+            let prev = self.ship_states.get(ship_symbol);
+            let state = f(prev.as_deref());
+            if prev.as_deref() == Some(&state) {
+                return;
+            }
+            state
+        };
+        self.ship_states
+            .insert(ship_symbol.to_string(), state.clone());
+        ok_or_warn(
+            self.db
+                .set_value(
+                    &format!("{}/ship_states", self.callsign),
+                    &*self.ship_states,
+                )
+                .await,
+            "save ship states",
+        );
+        let update = ShipStateUpdate {
+            symbol: ship_symbol.to_string(),
+            state,
+        };
+        self.emit_event(&Event::ShipState(update)).await;
     }
 }
 
 // ! todo: replace JoinHandles with TaskTracker from tokio-util (or tokio::task::join_set::JoinSet also from tokio-util)
 // https://docs.rs/tokio-util/0.7.10/tokio_util/task/task_tracker/struct.TaskTracker.html
-use tokio::task::JoinHandle;
+use tokio::task::{AbortHandle, JoinHandle};
 
 #[derive(Debug)]
 struct JoinHandles {
     handles: Arc<Mutex<FuturesUnordered<JoinHandle<()>>>>,
     rx: Arc<Mutex<tokio::sync::mpsc::Receiver<JoinHandle<()>>>>,
     tx: tokio::sync::mpsc::Sender<JoinHandle<()>>,
+    aborts: Mutex<Vec<AbortHandle>>,
 }
 impl JoinHandles {
     fn new() -> Self {
@@ -1119,11 +3120,18 @@ impl JoinHandles {
             handles: Arc::new(Mutex::new(FuturesUnordered::new())),
             rx: Arc::new(Mutex::new(rx)),
             tx,
+            aborts: Mutex::new(vec![]),
         }
     }
     async fn push(&self, handle: tokio::task::JoinHandle<()>) {
+        self.aborts.lock().unwrap().push(handle.abort_handle());
         self.tx.send(handle).await.unwrap();
     }
+    fn abort_all(&self) {
+        for abort in self.aborts.lock().unwrap().drain(..) {
+            abort.abort();
+        }
+    }
     async fn wait_all(&self, start: Option<tokio::task::JoinHandle<()>>) {
         use futures::StreamExt as _;
         let mut handles = self.handles.lock().unwrap();
@@ -1147,3 +3155,468 @@ impl JoinHandles {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ship_config::ship_config_starter_system;
+    use crate::test_fixtures::*;
+
+    #[test]
+    fn test_next_era() {
+        let credits_goal = Goal::ReachCredits(STARTING_SYSTEM_CREDITS_GOAL);
+        assert_eq!(
+            next_era(AgentEra::StartingSystem1, &[], Strategy::GateRush),
+            None
+        );
+        let goals = vec![completed_goal(credits_goal.clone())];
+        assert_eq!(
+            next_era(AgentEra::StartingSystem1, &goals, Strategy::GateRush),
+            Some(AgentEra::StartingSystem2)
+        );
+        assert_eq!(
+            next_era(AgentEra::StartingSystem2, &goals, Strategy::GateRush),
+            None
+        );
+        let goals = vec![
+            completed_goal(credits_goal),
+            completed_goal(Goal::FinishJumpGate),
+        ];
+        assert_eq!(
+            next_era(AgentEra::StartingSystem2, &goals, Strategy::GateRush),
+            Some(AgentEra::InterSystem1)
+        );
+        assert_eq!(
+            next_era(AgentEra::InterSystem1, &goals, Strategy::GateRush),
+            None
+        );
+        // pure trading never leaves the starting system
+        assert_eq!(
+            next_era(AgentEra::StartingSystem2, &goals, Strategy::PureTrading),
+            None
+        );
+    }
+
+    #[test]
+    fn test_restore_cooldown() {
+        let now = Utc::now();
+        let expiration = now + chrono::Duration::try_seconds(70).unwrap();
+        let mut cooldown = ShipCooldown {
+            ship_symbol: "AGENT-1".to_string(),
+            total_seconds: 0,
+            remaining_seconds: 0,
+            expiration: None,
+        };
+        cooldown.restore(now - chrono::Duration::try_seconds(1).unwrap(), now);
+        assert_eq!(cooldown.expiration, None);
+        cooldown.restore(expiration, now);
+        assert_eq!(cooldown.expiration, Some(expiration));
+        assert_eq!(cooldown.remaining_seconds, 70);
+
+        // the API's cooldown takes precedence
+        let api_expiration = now + chrono::Duration::try_seconds(10).unwrap();
+        cooldown.expiration = Some(api_expiration);
+        cooldown.restore(expiration, now);
+        assert_eq!(cooldown.expiration, Some(api_expiration));
+    }
+
+    #[test]
+    fn test_select_job() {
+        let waypoints = starter_system_waypoints();
+        let ship_config = ship_config_starter_system(
+            &waypoints,
+            &vec![],
+            &vec![],
+            true,
+            false,
+            Strategy::GateRush,
+        );
+        let ship = probe("TEST-2", &WaypointSymbol::new("X1-TEST-A1"));
+        let ship_model = ShipCatalog::builtin().identify(&ship).unwrap();
+        assert_eq!(ship_model, "SHIP_PROBE");
+
+        // probes with shipyards are first in line
+        let job = select_job(&ship_model, &ship_config, |_| false).unwrap();
+        assert_eq!(job.id, "probe/X1-TEST-A1");
+        let job = select_job(&ship_model, &ship_config, |id| id == "probe/X1-TEST-A1").unwrap();
+        assert_ne!(job.id, "probe/X1-TEST-A1");
+        assert_eq!(job.ship_model, "SHIP_PROBE");
+
+        assert!(select_job(&ship_model, &ship_config, |_| true).is_none());
+        assert!(select_job("SHIP_UNKNOWN", &ship_config, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_plan_rebalance() {
+        let probe_job = |id: &str| {
+            job(
+                id,
+                "SHIP_PROBE",
+                ShipBehaviour::Probe(ProbeScriptConfig {
+                    waypoints: vec![],
+                    refresh_market: true,
+                }),
+            )
+        };
+        let ship_config = vec![probe_job("probe/1"), probe_job("probe/2")];
+        let stale = vec![
+            ("A".to_string(), "SHIP_PROBE".to_string()),
+            ("B".to_string(), "SHIP_PROBE".to_string()),
+            ("C".to_string(), "SHIP_LIGHT_HAULER".to_string()),
+        ];
+        let plan = plan_rebalance(&stale, &ship_config, |id| id == "probe/1", false);
+        assert_eq!(
+            plan,
+            vec![
+                ("A".to_string(), TransferTarget::Job("probe/2".to_string())),
+                ("B".to_string(), TransferTarget::Idle),
+                ("C".to_string(), TransferTarget::Idle),
+            ]
+        );
+        let plan = plan_rebalance(&stale, &ship_config, |_| false, true);
+        assert_eq!(plan[1].1, TransferTarget::Job("probe/2".to_string()));
+        assert_eq!(plan[2].1, TransferTarget::Salvage);
+    }
+
+    #[test]
+    fn test_safe_to_unassign() {
+        let now = Utc::now();
+        let mut ship = probe("A-1", &WaypointSymbol::new("X1-TEST-A1"));
+        ship.nav.route.arrival = now - chrono::Duration::try_minutes(1).unwrap();
+        ship.cargo.units = 0;
+        assert!(safe_to_unassign(&ship, false, now));
+        assert!(!safe_to_unassign(&ship, true, now));
+
+        ship.cargo.units = 10;
+        assert!(!safe_to_unassign(&ship, false, now));
+
+        ship.cargo.units = 0;
+        ship.nav.route.arrival = now + chrono::Duration::try_minutes(1).unwrap();
+        assert!(!safe_to_unassign(&ship, false, now));
+    }
+
+    #[test]
+    fn test_shipyard_staleness() {
+        let now = Utc::now();
+        let mins = |m: i64| now - chrono::Duration::try_minutes(m).unwrap();
+        assert!(is_shipyard_stale(None, now, 30));
+        assert!(!is_shipyard_stale(Some(mins(10)), now, 30));
+        assert!(is_shipyard_stale(Some(mins(30)), now, 30));
+        assert!(is_shipyard_stale(Some(mins(120)), now, 30));
+    }
+
+    #[test]
+    fn test_plan_ship_purchase() {
+        let cheap = WaypointSymbol::new("X1-TEST-A1");
+        let expensive = WaypointSymbol::new("X1-TEST-F7");
+        let shipyards = vec![(cheap.clone(), 20_000), (expensive.clone(), 30_000)];
+        let catalog = ShipCatalog::builtin();
+        let probe_job = job(
+            "probe/1",
+            "SHIP_PROBE",
+            ShipBehaviour::Probe(ProbeScriptConfig {
+                waypoints: vec![],
+                refresh_market: true,
+            }),
+        );
+        let purchaser_at_expensive = |w: &WaypointSymbol| (*w == expensive).then(|| "P".into());
+
+        assert_eq!(
+            plan_ship_purchase(&probe_job, &catalog, &[], 100_000, |_| None),
+            Err(BuyShipResult::FailedNoShipyards)
+        );
+        assert_eq!(
+            plan_ship_purchase(&probe_job, &catalog, &shipyards, 10_000, |_| None),
+            Err(BuyShipResult::FailedLowCredits)
+        );
+        assert_eq!(
+            plan_ship_purchase(&probe_job, &catalog, &shipyards, 100_000, |_| Some(
+                "P".into()
+            )),
+            Ok((cheap.clone(), "P".to_string()))
+        );
+        // require_cheapest: a purchaser at a more expensive shipyard is not used
+        assert_eq!(
+            plan_ship_purchase(
+                &probe_job,
+                &catalog,
+                &shipyards,
+                100_000,
+                purchaser_at_expensive
+            ),
+            Err(BuyShipResult::FailedNoPurchaser(None))
+        );
+
+        let mut flexible_job = probe_job.clone();
+        flexible_job.purchase_criteria.require_cheapest = false;
+        flexible_job.purchase_criteria.allow_logistic_task = true;
+        assert_eq!(
+            plan_ship_purchase(
+                &flexible_job,
+                &catalog,
+                &shipyards,
+                100_000,
+                purchaser_at_expensive
+            ),
+            Ok((expensive.clone(), "P".to_string()))
+        );
+        assert_eq!(
+            plan_ship_purchase(&flexible_job, &catalog, &shipyards, 100_000, |_| None),
+            Err(BuyShipResult::FailedNoPurchaser(Some(cheap.clone())))
+        );
+
+        let mut never_job = probe_job.clone();
+        never_job.purchase_criteria.never_purchase = true;
+        assert_eq!(
+            plan_ship_purchase(&never_job, &catalog, &shipyards, 100_000, |_| None),
+            Err(BuyShipResult::FailedNeverPurchase)
+        );
+
+        // logistics jobs reserve trading credits on top of the ship price
+        let hauler_job = logistics_job("hauler/1", "SHIP_LIGHT_HAULER");
+        assert_eq!(
+            plan_ship_purchase(&hauler_job, &catalog, &shipyards, 100_000, |_| Some(
+                "P".into()
+            )),
+            Err(BuyShipResult::FailedLowCredits)
+        );
+        assert_eq!(
+            plan_ship_purchase(&hauler_job, &catalog, &shipyards, 420_000, |_| Some(
+                "P".into()
+            )),
+            Ok((cheap, "P".to_string()))
+        );
+    }
+
+    // Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_try_assign_ship() {
+        use crate::test_harness::TestAgent;
+        let jobs = vec![
+            logistics_job("cmd", "SHIP_COMMAND_FRIGATE"),
+            job(
+                "probe/A1",
+                "SHIP_PROBE",
+                ShipBehaviour::Probe(ProbeScriptConfig {
+                    waypoints: vec![WaypointSymbol::new("X1-TEST-A1")],
+                    refresh_market: true,
+                }),
+            ),
+        ];
+        let test = TestAgent::builder().ship_config(jobs).build().await;
+        let agent = &test.agent_controller;
+        assert!(agent.try_assign_ship("MOCK-1").await);
+        assert!(agent.try_assign_ship("MOCK-2").await);
+        assert!(agent.job_assigned("cmd"));
+        assert!(agent.job_assigned("probe/A1"));
+        // the command ship's logistics job reserves trading credits
+        let capacity = agent.ship("MOCK-1").unwrap().cargo.capacity;
+        assert_eq!(agent.ledger.effective_reserved_credits(), capacity * 5000);
+
+        // assignments are kept across restarts
+        let restarted =
+            AgentController::new(&test.api_client, &test.db, &test.universe, "MOCK").await;
+        assert!(restarted.ship_assigned("MOCK-1"));
+        assert!(restarted.ship_assigned("MOCK-2"));
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_try_buy_ships() {
+        use crate::test_harness::TestAgent;
+        let test = TestAgent::builder().build().await;
+        let agent = &test.agent_controller;
+        // the generated starter config wants probes, sold at the headquarters' shipyard
+        test.ship_controller("MOCK-1").refresh_shipyard().await;
+        // the mock's gate is complete, its cost reserve leaves nothing for ships
+        let (bought, _) = agent.try_buy_ships(Some("MOCK-1".to_string())).await;
+        assert_eq!(bought, Vec::<String>::new());
+        assert!(agent.ship_assigned("MOCK-1"));
+
+        let credits = agent.agent().credits;
+        agent
+            .update_agent(Agent {
+                credits: credits + 1_000_000,
+                ..agent.agent()
+            })
+            .await;
+        let (bought, _) = agent.try_buy_ships(Some("MOCK-1".to_string())).await;
+        assert!(!bought.is_empty());
+        assert_eq!(agent.num_ships(), 2 + bought.len());
+        assert!(agent.agent().credits < credits);
+        for ship_symbol in &bought {
+            assert!(agent.ship_assigned(ship_symbol));
+        }
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_era_advance() {
+        use crate::test_harness::TestAgent;
+        let test = TestAgent::builder().build().await;
+        let agent = &test.agent_controller;
+        agent.check_era_advance().await;
+        assert_eq!(agent.state().era, AgentEra::StartingSystem1);
+
+        // reaching the credits goal moves on to the next era
+        agent
+            .update_agent(Agent {
+                credits: STARTING_SYSTEM_CREDITS_GOAL,
+                ..agent.agent()
+            })
+            .await;
+        agent.check_era_advance().await;
+        assert!(agent.is_goal_complete(&Goal::ReachCredits(STARTING_SYSTEM_CREDITS_GOAL)));
+        assert_ne!(agent.state().era, AgentEra::StartingSystem1);
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_transfer_surveyor() {
+        use crate::test_harness::TestAgent;
+        let jobs = vec![
+            job(
+                "surveyor/1",
+                "SHIP_COMMAND_FRIGATE",
+                ShipBehaviour::MiningSurveyor,
+            ),
+            job(
+                "surveyor/2",
+                "SHIP_COMMAND_FRIGATE",
+                ShipBehaviour::MiningSurveyor,
+            ),
+        ];
+        let test = TestAgent::builder().ship_config(jobs).build().await;
+        let agent = &test.agent_controller;
+        let asteroid = WaypointSymbol::new("X1-TEST-C4");
+        assert!(agent.try_assign_ship("MOCK-1").await);
+        agent.spawn_run_ship("MOCK-1".to_string()).await;
+        // surveying at the engineered asteroid
+        test.wait_until("the surveyor", std::time::Duration::from_secs(10), || {
+            agent.ship("MOCK-1").unwrap().nav.waypoint_symbol == asteroid
+        })
+        .await;
+
+        // the surveyor stops before its next survey and is handed to the other job
+        agent.request_transfer("MOCK-1", TransferTarget::Job("surveyor/2".to_string()));
+        test.wait_until("the transfer", std::time::Duration::from_secs(10), || {
+            !agent.transfer_requested("MOCK-1")
+        })
+        .await;
+        assert!(agent.job_assigned("surveyor/2"));
+        assert!(!agent.job_assigned("surveyor/1"));
+        assert_eq!(
+            agent.ship_status("MOCK-1"),
+            Some(ShipStatus::Running("surveyor/2".to_string()))
+        );
+        assert!(agent.survey_manager.get_survey(&asteroid).await.is_some());
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    // Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+    async fn test_dispatch_convoy() {
+        use crate::test_harness::TestAgent;
+        let test = TestAgent::builder().build().await;
+        let agent = &test.agent_controller;
+        let ships = vec!["MOCK-1".to_string(), "MOCK-2".to_string()];
+        let target = WaypointSymbol::new("X1-TEST-B3");
+        let mut events = agent.subscribe_events("test");
+
+        // a ship running its script can't be taken for a convoy
+        agent
+            .running_scripts
+            .insert("MOCK-2".to_string(), "probe/A1".to_string());
+        assert!(agent.dispatch_convoy(&ships, &target).await.is_err());
+        assert!(!agent.script_running("MOCK-1"));
+        agent.running_scripts.remove("MOCK-2");
+
+        agent.dispatch_convoy(&ships, &target).await.unwrap();
+        assert!(agent.script_running("MOCK-1") && agent.script_running("MOCK-2"));
+        assert!(agent.dispatch_convoy(&ships, &target).await.is_err());
+        let arrival = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            loop {
+                if let Some(Event::ConvoyArrived(arrival)) = events.recv().await {
+                    return arrival;
+                }
+            }
+        })
+        .await
+        .expect("Timed out waiting for the convoy");
+        assert_eq!(arrival.ships, ships);
+        assert_eq!(arrival.destination, target);
+        for ship in &ships {
+            assert_eq!(agent.ship(ship).unwrap().nav.waypoint_symbol, target);
+        }
+
+        // released on arrival, without jobs to go back to
+        test.wait_until(
+            "the convoy release",
+            std::time::Duration::from_secs(10),
+            || {
+                ships
+                    .iter()
+                    .all(|ship| agent.ship_status(ship) == Some(ShipStatus::Paused))
+            },
+        )
+        .await;
+        assert!(!agent.script_running("MOCK-1") && !agent.script_running("MOCK-2"));
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    // Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+    async fn test_wind_down_completion() {
+        use crate::test_harness::TestAgent;
+        let jobs = vec![job(
+            "surveyor/1",
+            "SHIP_COMMAND_FRIGATE",
+            ShipBehaviour::MiningSurveyor,
+        )];
+        let test = TestAgent::builder().ship_config(jobs).build().await;
+        let agent = &test.agent_controller;
+        let system = SystemSymbol::new("X1-TEST");
+        let park_at = WaypointSymbol::new("X1-TEST-A2");
+        assert!(agent.try_assign_ship("MOCK-1").await);
+        agent.spawn_run_ship("MOCK-1".to_string()).await;
+        test.wait_until("the surveyor", std::time::Duration::from_secs(10), || {
+            agent.ship("MOCK-1").unwrap().nav.waypoint_symbol == WaypointSymbol::new("X1-TEST-C4")
+        })
+        .await;
+
+        // the surveyor is still running, so the wind down is pending
+        let wind_down = WindDown {
+            system_symbol: system.clone(),
+            park_at: Some(park_at.clone()),
+            since: Utc::now(),
+            completed_at: None,
+        };
+        agent.wind_down_system(wind_down).await.unwrap();
+        let statuses = agent.wind_down_statuses();
+        assert_eq!(statuses[0].pending_ships, vec!["MOCK-1".to_string()]);
+        assert_eq!(statuses[0].wind_down.completed_at, None);
+
+        // complete only once the surveyor has stopped and flown to the parking waypoint
+        test.wait_until("the wind down", std::time::Duration::from_secs(10), || {
+            agent
+                .system_wind_down(&system)
+                .unwrap()
+                .completed_at
+                .is_some()
+        })
+        .await;
+        assert_eq!(agent.ship("MOCK-1").unwrap().nav.waypoint_symbol, park_at);
+        assert!(matches!(
+            agent.ship_status("MOCK-1"),
+            Some(ShipStatus::Skipped(_))
+        ));
+        assert!(agent.wind_down_statuses()[0].pending_ships.is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+/// Coalesce ship wakeups (transit arrivals, cooldown expirations) into fixed ticks, and stagger
+/// ships sharing a tick so their follow-up requests don't all land on the rate limiter at once
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+const TICK_MS: i64 = 2000;
+const STAGGER_MS: i64 = 250;
+// Wakeups stay within their tick, ships past the cap share the last slot and the rate limiter
+// spaces out their requests
+const MAX_STAGGER_MS: i64 = TICK_MS - STAGGER_MS;
+
+#[derive(Debug, Default)]
+pub struct ArrivalScheduler {
+    // tick index -> number of wakeups already scheduled in that tick
+    ticks: Mutex<BTreeMap<i64, i64>>,
+}
+
+impl ArrivalScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Wait until at least `ts`, waking on a shared tick. `ts` and `now` must come from the same
+    // clock, which may be skewed from the local one
+    pub async fn wait_until(&self, ts: DateTime<Utc>, now: DateTime<Utc>) {
+        let wake = self.schedule(ts, now);
+        let wait_time = wake - now;
+        if wait_time > Duration::zero() {
+            tokio::time::sleep(wait_time.to_std().unwrap()).await;
+        }
+    }
+
+    fn schedule(&self, ts: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+        // first tick boundary at or after ts
+        let tick = (ts.timestamp_millis() + TICK_MS - 1).div_euclid(TICK_MS);
+        let now_tick = now.timestamp_millis().div_euclid(TICK_MS);
+
+        let mut ticks = self.ticks.lock().unwrap();
+        *ticks = ticks.split_off(&now_tick);
+        let slot = ticks.entry(tick).or_insert(0);
+        let offset = (*slot * STAGGER_MS).min(MAX_STAGGER_MS);
+        *slot += 1;
+        DateTime::from_timestamp_millis(tick * TICK_MS + offset).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_schedule_coalesce_and_stagger() {
+        let scheduler = ArrivalScheduler::new();
+        let now = DateTime::from_timestamp_millis(1_000_000).unwrap();
+        let a = scheduler.schedule(now + Duration::milliseconds(100), now);
+        let b = scheduler.schedule(now + Duration::milliseconds(1500), now);
+        let c = scheduler.schedule(now + Duration::milliseconds(2100), now);
+
+        // a and b share a tick, b is staggered behind a
+        assert_eq!(a.timestamp_millis(), 1_002_000);
+        assert_eq!(b.timestamp_millis(), 1_002_000 + STAGGER_MS);
+        assert_eq!(c.timestamp_millis(), 1_004_000);
+
+        // past ticks are forgotten
+        let later = now + Duration::milliseconds(10_000);
+        let d = scheduler.schedule(later, later);
+        assert_eq!(d.timestamp_millis(), 1_010_000);
+        assert_eq!(scheduler.ticks.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_schedule_stagger_cap() {
+        let scheduler = ArrivalScheduler::new();
+        let now = DateTime::from_timestamp_millis(1_000_000).unwrap();
+        let wakes = (0..100)
+            .map(|_| scheduler.schedule(now + Duration::milliseconds(100), now))
+            .map(|wake| wake.timestamp_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(wakes[1], 1_002_000 + STAGGER_MS);
+        assert!(wakes.windows(2).all(|w| w[0] <= w[1]));
+        // a hundred arrivals in one tick all wake before the next
+        assert_eq!(wakes[99], 1_002_000 + MAX_STAGGER_MS);
+        assert!(wakes[99] < 1_004_000);
+    }
+}
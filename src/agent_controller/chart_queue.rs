@@ -0,0 +1,131 @@
+/// Uncharted waypoints found by sensor scans, waiting for a ship to chart them.
+/// Charting draws from its own budget so it doesn't crowd trading requests off the rate limiter
+use crate::models::{SystemSymbol, WaypointSymbol};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+// Maximum charts submitted back to back
+const BURST: f64 = 5.0;
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    per_hour: f64,
+    last: DateTime<Utc>,
+}
+
+impl TokenBucket {
+    fn try_take(&mut self, now: DateTime<Utc>) -> bool {
+        let elapsed = (now - self.last).num_milliseconds().max(0) as f64 / 3_600_000.0;
+        self.tokens = (self.tokens + elapsed * self.per_hour).min(BURST);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ChartQueue {
+    queue: Mutex<BTreeSet<WaypointSymbol>>,
+    budget: Mutex<TokenBucket>,
+}
+
+impl ChartQueue {
+    pub fn new(per_hour: f64) -> Self {
+        Self {
+            queue: Mutex::new(BTreeSet::new()),
+            budget: Mutex::new(TokenBucket {
+                tokens: BURST,
+                per_hour,
+                last: Utc::now(),
+            }),
+        }
+    }
+
+    pub fn enqueue(&self, waypoints: impl IntoIterator<Item = WaypointSymbol>) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+        waypoints
+            .into_iter()
+            .filter(|w| queue.insert(w.clone()))
+            .count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Take `waypoint` if it's queued and the budget allows
+    pub fn take_at(&self, waypoint: &WaypointSymbol, now: DateTime<Utc>) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.contains(waypoint) || !self.budget.lock().unwrap().try_take(now) {
+            return false;
+        }
+        queue.remove(waypoint)
+    }
+
+    // Take the next waypoint to chart in a system, if the budget allows. Prefers `current`.
+    pub fn take(
+        &self,
+        system: &SystemSymbol,
+        current: &WaypointSymbol,
+        now: DateTime<Utc>,
+    ) -> Option<WaypointSymbol> {
+        let mut queue = self.queue.lock().unwrap();
+        let next = match queue.contains(current) {
+            true => current.clone(),
+            false => queue.iter().find(|w| &w.system() == system)?.clone(),
+        };
+        if !self.budget.lock().unwrap().try_take(now) {
+            return None;
+        }
+        queue.remove(&next);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_chart_queue() {
+        let queue = ChartQueue::new(60.0);
+        let system = SystemSymbol::new("X1-AB12");
+        let waypoints = (1..=7)
+            .map(|i| WaypointSymbol::new(&format!("X1-AB12-A{}", i)))
+            .collect::<Vec<_>>();
+        assert_eq!(queue.enqueue(waypoints.clone()), 7);
+        assert_eq!(queue.enqueue(vec![waypoints[0].clone()]), 0);
+
+        let other = WaypointSymbol::new("X1-CD34-B1");
+        let now = Utc::now();
+        assert_eq!(
+            queue.take(&system, &waypoints[3], now),
+            Some(waypoints[3].clone())
+        );
+        assert_eq!(queue.take(&system, &other, now), Some(waypoints[0].clone()));
+        assert_eq!(queue.take(&other.system(), &other, now), None);
+        assert!(!queue.take_at(&other, now));
+
+        // burst exhausted, then refills at 1 per minute
+        assert!(queue.take_at(&waypoints[1], now));
+        for _ in 0..2 {
+            assert!(queue.take(&system, &other, now).is_some());
+        }
+        assert_eq!(queue.take(&system, &other, now), None);
+        let later = now + Duration::try_seconds(61).unwrap();
+        assert!(queue.take(&system, &other, later).is_some());
+        assert_eq!(queue.take(&system, &other, later), None);
+        assert_eq!(queue.len(), 1);
+    }
+}
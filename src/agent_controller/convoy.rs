@@ -0,0 +1,82 @@
+/// Moving a group of ships to a work site together, e.g. newly bought drones and their shuttle.
+/// Ships at the same waypoint with the same engine and fuel fly the same route, so it's planned once
+/// per group. Departures are staggered so the navigate requests don't hit the rate limiter at once,
+/// and the group's arrival is reported as a single event
+use crate::models::WaypointSymbol;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Delay between the departures of consecutive ships
+pub const DEPARTURE_STAGGER_MS: u64 = 500;
+
+#[derive(Debug, Clone)]
+pub struct ConvoyShip {
+    pub symbol: String,
+    pub waypoint: WaypointSymbol,
+    pub speed: i64,
+    pub fuel: i64,
+    pub fuel_capacity: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvoyArrival {
+    pub ships: Vec<String>,
+    pub destination: WaypointSymbol,
+    pub departed: DateTime<Utc>,
+    pub arrived: DateTime<Utc>,
+}
+
+// Ships that can share a route, in the order given
+pub fn route_groups(ships: &[ConvoyShip]) -> Vec<Vec<String>> {
+    let mut groups: BTreeMap<(WaypointSymbol, i64, i64, i64), Vec<String>> = BTreeMap::new();
+    let mut order = vec![];
+    for ship in ships {
+        let key = (
+            ship.waypoint.clone(),
+            ship.speed,
+            ship.fuel,
+            ship.fuel_capacity,
+        );
+        if !groups.contains_key(&key) {
+            order.push(key.clone());
+        }
+        groups.entry(key).or_default().push(ship.symbol.clone());
+    }
+    order
+        .into_iter()
+        .map(|key| groups.remove(&key).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_route_groups() {
+        let ship = |symbol: &str, waypoint: &str, speed: i64, fuel: i64| ConvoyShip {
+            symbol: symbol.to_string(),
+            waypoint: WaypointSymbol::new(waypoint),
+            speed,
+            fuel,
+            fuel_capacity: 80,
+        };
+        let ships = vec![
+            ship("SHUTTLE-1", "X1-S1-A1", 10, 80),
+            ship("DRONE-1", "X1-S1-A1", 3, 80),
+            ship("DRONE-2", "X1-S1-A1", 3, 80),
+            // drones that refuelled differently fly their own route
+            ship("DRONE-3", "X1-S1-A1", 3, 40),
+            ship("SHUTTLE-2", "X1-S1-A1", 10, 80),
+        ];
+        assert_eq!(
+            route_groups(&ships),
+            vec![
+                vec!["SHUTTLE-1".to_string(), "SHUTTLE-2".to_string()],
+                vec!["DRONE-1".to_string(), "DRONE-2".to_string()],
+                vec!["DRONE-3".to_string()],
+            ]
+        );
+    }
+}
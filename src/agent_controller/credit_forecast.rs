@@ -0,0 +1,66 @@
+/// When upcoming ship purchases become affordable, at the recent net income rate.
+/// Purchases are paid for in order, so each one waits for the credits of those before it. A
+/// purchaser can then be sent to the shipyard ahead of time, arriving as the credits do
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PurchaseForecast {
+    pub job_id: String,
+    pub ship_model: String,
+    // cheapest known price plus the job's credit reservation
+    pub cost: i64,
+    // None if it's never affordable at the current income rate
+    pub affordable_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreditForecast {
+    pub available_credits: i64,
+    pub income_per_hour: i64,
+    pub purchases: Vec<PurchaseForecast>,
+}
+
+// When each of `costs`, bought in order, becomes affordable
+pub fn affordable_times(
+    credits: i64,
+    income_per_hour: i64,
+    costs: &[i64],
+    now: DateTime<Utc>,
+) -> Vec<Option<DateTime<Utc>>> {
+    let mut required = 0;
+    costs
+        .iter()
+        .map(|cost| {
+            required += cost;
+            let missing = required - credits;
+            if missing <= 0 {
+                Some(now)
+            } else if income_per_hour <= 0 {
+                None
+            } else {
+                let seconds = (missing * 3600 + income_per_hour - 1) / income_per_hour;
+                Some(now + Duration::try_seconds(seconds).unwrap())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_affordable_times() {
+        let now = Utc::now();
+        let mins = |m| Some(now + Duration::try_minutes(m).unwrap());
+        assert_eq!(
+            affordable_times(100_000, 600_000, &[80_000, 50_000, 100_000], now),
+            vec![Some(now), mins(3), mins(13)]
+        );
+        assert_eq!(
+            affordable_times(100_000, 0, &[80_000, 50_000], now),
+            vec![Some(now), None]
+        );
+    }
+}
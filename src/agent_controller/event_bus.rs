@@ -0,0 +1,217 @@
+/// Fan-out of agent events to subscribers (the web server's socket), which must never hold up the agent.
+/// Emitting doesn't wait. Low priority events (ship states, ops reports) go through a bounded broadcast,
+/// a subscriber that falls behind loses the oldest of them and counts the loss. Each replaces the last,
+/// so nothing is lost for good. The rest are always delivered, ahead of any low priority events still
+/// queued: ship updates, which are diffs against the previous update, and rare events
+use super::Event;
+use log::*;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+
+// Low priority events queued per subscriber before the oldest are dropped
+pub const EVENT_CAPACITY: usize = 256;
+
+impl Event {
+    pub fn is_low_priority(&self) -> bool {
+        matches!(self, Event::ShipState(_) | Event::OpsReport(_))
+    }
+}
+
+#[derive(Debug, Default)]
+struct SubscriberStats {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    // low priority events sent before the subscriber joined, and received or dropped since
+    low_before: u64,
+    low_seen: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriberMetrics {
+    pub name: String,
+    pub received: u64,
+    pub dropped: u64,
+    // low priority events queued, not yet received
+    pub lag: usize,
+}
+
+struct Subscriber {
+    name: String,
+    high: mpsc::UnboundedSender<Event>,
+    stats: Arc<SubscriberStats>,
+}
+
+pub struct EventBus {
+    low: broadcast::Sender<Event>,
+    low_sent: AtomicU64,
+    capacity: usize,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+pub struct EventSubscriber {
+    low: broadcast::Receiver<Event>,
+    high: mpsc::UnboundedReceiver<Event>,
+    stats: Arc<SubscriberStats>,
+    name: String,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            low: broadcast::Sender::new(capacity),
+            low_sent: AtomicU64::new(0),
+            capacity,
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn subscribe(&self, name: &str) -> EventSubscriber {
+        let (high_tx, high_rx) = mpsc::unbounded_channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let stats = Arc::new(SubscriberStats {
+            low_before: self.low_sent.load(Ordering::Relaxed),
+            ..SubscriberStats::default()
+        });
+        subscribers.push(Subscriber {
+            name: name.to_string(),
+            high: high_tx,
+            stats: stats.clone(),
+        });
+        info!("Added event subscriber {}", name);
+        EventSubscriber {
+            low: self.low.subscribe(),
+            high: high_rx,
+            stats,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.high.is_closed());
+        !subscribers.is_empty()
+    }
+
+    pub fn emit(&self, event: &Event) {
+        if event.is_low_priority() {
+            // no receivers is fine, the event is dropped
+            let _ = self.low.send(event.clone());
+            self.low_sent.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| s.high.send(event.clone()).is_ok());
+    }
+
+    pub fn metrics(&self) -> Vec<SubscriberMetrics> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.high.is_closed());
+        let low_sent = self.low_sent.load(Ordering::Relaxed);
+        subscribers
+            .iter()
+            .map(|s| {
+                let seen = s.stats.low_before + s.stats.low_seen.load(Ordering::Relaxed);
+                SubscriberMetrics {
+                    name: s.name.clone(),
+                    received: s.stats.received.load(Ordering::Relaxed),
+                    dropped: s.stats.dropped.load(Ordering::Relaxed),
+                    lag: (low_sent.saturating_sub(seen) as usize).min(self.capacity),
+                }
+            })
+            .collect()
+    }
+}
+
+impl EventSubscriber {
+    // None once the bus is gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            let event = tokio::select! {
+                biased;
+                event = self.high.recv() => event,
+                event = self.low.recv() => match event {
+                    Ok(event) => {
+                        self.stats.low_seen.fetch_add(1, Ordering::Relaxed);
+                        Some(event)
+                    }
+                    Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                        self.stats.dropped.fetch_add(dropped, Ordering::Relaxed);
+                        self.stats.low_seen.fetch_add(dropped, Ordering::Relaxed);
+                        warn!("Event subscriber {} fell behind, dropped {} events", self.name, dropped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => None,
+                },
+            };
+            if event.is_some() {
+                self.stats.received.fetch_add(1, Ordering::Relaxed);
+            }
+            return event;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent_controller::goals::Goal;
+    use crate::agent_controller::ship_status::{ShipState, ShipStateUpdate};
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_event_bus() {
+        let bus = EventBus::new(4);
+        assert!(!bus.has_subscribers());
+        // nobody listening, nothing blocks
+        bus.emit(&Event::NetWorthMilestone(1_000_000));
+
+        let mut subscriber = bus.subscribe("test");
+        assert!(bus.has_subscribers());
+        for n in 0..10 {
+            let update = ShipStateUpdate {
+                symbol: format!("A-{}", n),
+                state: ShipState::with_step(None, "Trading", Utc::now()),
+            };
+            // low priority, only the newest 4 are kept
+            bus.emit(&Event::ShipState(update));
+        }
+        bus.emit(&Event::GoalCompleted(Goal::FinishJumpGate));
+        assert_eq!(bus.metrics()[0].lag, 4);
+
+        // high priority first, then what's left of the low priority ones
+        assert!(matches!(
+            subscriber.recv().await,
+            Some(Event::GoalCompleted(_))
+        ));
+        let mut received = vec![];
+        for _ in 0..4 {
+            match subscriber.recv().await {
+                Some(Event::ShipState(update)) => received.push(update.symbol),
+                event => panic!("Unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(received, vec!["A-6", "A-7", "A-8", "A-9"]);
+        let metrics = &bus.metrics()[0];
+        assert_eq!((metrics.received, metrics.dropped, metrics.lag), (5, 6, 0));
+
+        // ship updates are diffs, a lagging subscriber still gets them all
+        let ship =
+            crate::test_fixtures::probe("A-1", &crate::models::WaypointSymbol::new("X1-A-A1"));
+        let diff = crate::agent_controller::ship_updates::diff_ship(None, &ship).unwrap();
+        for _ in 0..10 {
+            bus.emit(&Event::ShipUpdate(Arc::new(diff.clone())));
+        }
+        for _ in 0..10 {
+            assert!(matches!(
+                subscriber.recv().await,
+                Some(Event::ShipUpdate(_))
+            ));
+        }
+        assert_eq!(bus.metrics()[0].dropped, 6);
+
+        drop(subscriber);
+        assert!(!bus.has_subscribers());
+    }
+}
@@ -0,0 +1,334 @@
+//!
+//! Ranking of candidate systems to expand into.
+//!
+//! Each reachable system is scored in credits: the hauler profit its market structure suggests over a
+//! payback period, less the probes needed to watch its markets, the cost of flying ships in from
+//! elsewhere when it has no shipyard, and the travel time from the starting system.
+//! The top candidate becomes the next ColonizeSystem goal, unless overridden through the web API.
+//!
+//! The capital system is onboarded when the gate opens: it starts with no probes, no market data and
+//! nothing for the task manager to plan with, so it's brought online one step at a time.
+//!
+use crate::api_client::api_models::WaypointDetailed;
+use crate::models::{MarketRemoteView, SystemSymbol};
+use crate::ship_config::market_waypoints;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Nearest reachable systems considered, each costs a few requests to load on first use
+pub const MAX_CANDIDATES: usize = 20;
+// ColonizeSystem goals the planner adds on its own
+pub const MAX_EXPANSIONS: usize = 3;
+
+// Rough hauler earnings for a good exported at one market and imported at another in the system
+const ROUTE_PROFIT_PER_HOUR: i64 = 15_000;
+// Hours of hauler profit weighed against the setup costs
+const PAYBACK_HOURS: i64 = 24;
+// Used when no probe listing has been seen yet
+const DEFAULT_PROBE_PRICE: i64 = 25_000;
+// Flying haulers in from another system's shipyard instead of buying them locally
+const NO_SHIPYARD_COST: i64 = 200_000;
+// Ship time spent travelling to the system, per second of travel
+const TRAVEL_COST_PER_SECOND: i64 = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpansionCandidate {
+    pub system_symbol: SystemSymbol,
+    // travel time from the starting system, in seconds
+    pub distance: i64,
+    pub probes_needed: i64,
+    // (good, export market count, import market count) for goods both exported and imported
+    pub trade_routes: Vec<(String, i64, i64)>,
+    // credits per hour
+    pub expected_hauler_profit: i64,
+    pub has_shipyard: bool,
+    pub score: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExpansionOverride {
+    // expand into this system next, regardless of rank
+    pub system_symbol: Option<SystemSymbol>,
+}
+
+// Probe locations as the ship config would station them: one per shipyard, one per coordinate otherwise
+pub fn probes_needed(waypoints: &Vec<WaypointDetailed>) -> i64 {
+    let markets = market_waypoints(waypoints, None);
+    let locations = waypoints
+        .iter()
+        .filter(|w| markets.contains(&w.symbol) || w.is_shipyard())
+        .map(|w| match w.is_shipyard() {
+            true => w.symbol.to_string(),
+            false => format!("({},{})", w.x, w.y),
+        })
+        .collect::<BTreeSet<_>>();
+    locations.len() as i64
+}
+
+pub fn trade_routes(markets: &[MarketRemoteView]) -> Vec<(String, i64, i64)> {
+    let mut goods: BTreeMap<&str, (i64, i64)> = BTreeMap::new();
+    for market in markets {
+        for good in &market.exports {
+            goods.entry(&good.symbol).or_default().0 += 1;
+        }
+        for good in &market.imports {
+            goods.entry(&good.symbol).or_default().1 += 1;
+        }
+    }
+    goods
+        .into_iter()
+        .filter(|(_, (exports, imports))| *exports > 0 && *imports > 0)
+        .map(|(good, (exports, imports))| (good.to_string(), exports, imports))
+        .collect()
+}
+
+// Steps to bring a newly reachable system online, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum OnboardingStep {
+    // a probe bought at home flies through the gate, a ship in the system to buy the rest with
+    FirstProbe,
+    // shipyard listings, and the probe's shipyard detailed enough to buy from
+    RefreshShipyards,
+    // imports and exports of every market, for the ship config and the first trade tasks
+    SeedMarkets,
+    // the task manager serves the system, so its probes refresh markets at the full rate
+    TaskManager,
+}
+
+pub const ONBOARDING_STEPS: [OnboardingStep; 4] = [
+    OnboardingStep::FirstProbe,
+    OnboardingStep::RefreshShipyards,
+    OnboardingStep::SeedMarkets,
+    OnboardingStep::TaskManager,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OnboardingProgress {
+    pub system_symbol: SystemSymbol,
+    // when each step was completed
+    pub completed: BTreeMap<OnboardingStep, DateTime<Utc>>,
+}
+
+impl OnboardingProgress {
+    pub fn new(system_symbol: &SystemSymbol) -> Self {
+        OnboardingProgress {
+            system_symbol: system_symbol.clone(),
+            completed: BTreeMap::new(),
+        }
+    }
+
+    pub fn next_step(&self) -> Option<OnboardingStep> {
+        ONBOARDING_STEPS
+            .into_iter()
+            .find(|step| !self.completed.contains_key(step))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_step().is_none()
+    }
+
+    pub fn complete(&mut self, step: OnboardingStep, now: DateTime<Utc>) {
+        assert_eq!(
+            self.next_step(),
+            Some(step),
+            "Onboarding steps are in order"
+        );
+        self.completed.insert(step, now);
+    }
+}
+
+pub fn estimate_candidate(
+    system_symbol: &SystemSymbol,
+    distance: i64,
+    waypoints: &Vec<WaypointDetailed>,
+    markets: &[MarketRemoteView],
+    probe_price: Option<i64>,
+) -> ExpansionCandidate {
+    let probes_needed = probes_needed(waypoints);
+    let trade_routes = trade_routes(markets);
+    let expected_hauler_profit = trade_routes.len() as i64 * ROUTE_PROFIT_PER_HOUR;
+    let has_shipyard = waypoints.iter().any(|w| w.is_shipyard());
+    let score = expected_hauler_profit * PAYBACK_HOURS
+        - probes_needed * probe_price.unwrap_or(DEFAULT_PROBE_PRICE)
+        - if has_shipyard { 0 } else { NO_SHIPYARD_COST }
+        - distance * TRAVEL_COST_PER_SECOND;
+    ExpansionCandidate {
+        system_symbol: system_symbol.clone(),
+        distance,
+        probes_needed,
+        trade_routes,
+        expected_hauler_profit,
+        has_shipyard,
+        score,
+    }
+}
+
+// Best first
+pub fn rank_candidates(mut candidates: Vec<ExpansionCandidate>) -> Vec<ExpansionCandidate> {
+    candidates.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.system_symbol.cmp(&b.system_symbol))
+    });
+    candidates
+}
+
+// The system to expand into next, skipping systems we already have (or had) a goal for
+pub fn select_expansion<'a>(
+    ranked: &'a [ExpansionCandidate],
+    override_: &ExpansionOverride,
+    existing: &[SystemSymbol],
+) -> Option<&'a SystemSymbol> {
+    if let Some(system_symbol) = &override_.system_symbol {
+        if let Some(candidate) = ranked.iter().find(|c| c.system_symbol == *system_symbol) {
+            if !existing.contains(system_symbol) {
+                return Some(&candidate.system_symbol);
+            }
+        }
+    }
+    ranked
+        .iter()
+        .map(|c| &c.system_symbol)
+        .find(|s| !existing.contains(s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{SymbolNameDescr, WaypointSymbol};
+
+    fn waypoint(symbol: &str, x: i64, y: i64, traits: &[&str]) -> WaypointDetailed {
+        let waypoint_symbol = WaypointSymbol::new(symbol);
+        WaypointDetailed {
+            system_symbol: waypoint_symbol.system(),
+            symbol: waypoint_symbol,
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y,
+            traits: traits
+                .iter()
+                .map(|t| SymbolNameDescr {
+                    symbol: t.to_string(),
+                    name: String::new(),
+                    description: String::new(),
+                })
+                .collect(),
+            is_under_construction: false,
+            modifiers: vec![],
+            orbits: None,
+            chart: None,
+        }
+    }
+
+    fn market(symbol: &str, exports: &[&str], imports: &[&str]) -> MarketRemoteView {
+        let goods = |goods: &[&str]| {
+            goods
+                .iter()
+                .map(|g| SymbolNameDescr {
+                    symbol: g.to_string(),
+                    name: String::new(),
+                    description: String::new(),
+                })
+                .collect()
+        };
+        MarketRemoteView {
+            symbol: WaypointSymbol::new(symbol),
+            imports: goods(imports),
+            exports: goods(exports),
+            exchange: vec![],
+        }
+    }
+
+    #[test]
+    fn test_estimate_candidate() {
+        let system = SystemSymbol::new("X1-AB12");
+        let waypoints = vec![
+            waypoint("X1-AB12-A1", 0, 0, &["MARKETPLACE", "SHIPYARD"]),
+            // same orbit, one probe
+            waypoint("X1-AB12-B1", 10, 10, &["MARKETPLACE"]),
+            waypoint("X1-AB12-B2", 10, 10, &["MARKETPLACE"]),
+            waypoint("X1-AB12-C1", 50, 0, &[]),
+        ];
+        let markets = vec![
+            market("X1-AB12-A1", &["IRON", "FUEL"], &["IRON_ORE"]),
+            market("X1-AB12-B1", &["IRON_ORE"], &["IRON"]),
+            market("X1-AB12-B2", &["COPPER"], &["FUEL"]),
+        ];
+        let candidate = estimate_candidate(&system, 1000, &waypoints, &markets, Some(20_000));
+        assert_eq!(candidate.probes_needed, 2);
+        assert_eq!(
+            candidate.trade_routes,
+            vec![
+                ("FUEL".to_string(), 1, 1),
+                ("IRON".to_string(), 1, 1),
+                ("IRON_ORE".to_string(), 1, 1),
+            ]
+        );
+        assert_eq!(candidate.expected_hauler_profit, 45_000);
+        assert!(candidate.has_shipyard);
+        assert_eq!(candidate.score, 45_000 * 24 - 2 * 20_000 - 1000 * 50);
+    }
+
+    #[test]
+    fn test_onboarding_progress() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut progress = OnboardingProgress::new(&SystemSymbol::new("X1-AB12"));
+        assert_eq!(progress.next_step(), Some(OnboardingStep::FirstProbe));
+        for step in ONBOARDING_STEPS {
+            assert!(!progress.is_complete());
+            progress.complete(step, now);
+        }
+        assert!(progress.is_complete());
+        assert_eq!(progress.completed.len(), 4);
+
+        let stored = serde_json::to_value(&progress).unwrap();
+        assert_eq!(
+            serde_json::from_value::<OnboardingProgress>(stored).unwrap(),
+            progress
+        );
+    }
+
+    #[test]
+    fn test_select_expansion() {
+        let candidate = |symbol: &str, score: i64| ExpansionCandidate {
+            system_symbol: SystemSymbol::new(symbol),
+            distance: 0,
+            probes_needed: 0,
+            trade_routes: vec![],
+            expected_hauler_profit: 0,
+            has_shipyard: true,
+            score,
+        };
+        let ranked = rank_candidates(vec![
+            candidate("X1-AA11", 100),
+            candidate("X1-BB22", 300),
+            candidate("X1-CC33", 200),
+        ]);
+        let none = ExpansionOverride::default();
+        assert_eq!(
+            select_expansion(&ranked, &none, &[]),
+            Some(&SystemSymbol::new("X1-BB22"))
+        );
+        let existing = [SystemSymbol::new("X1-BB22")];
+        assert_eq!(
+            select_expansion(&ranked, &none, &existing),
+            Some(&SystemSymbol::new("X1-CC33"))
+        );
+        let override_ = ExpansionOverride {
+            system_symbol: Some(SystemSymbol::new("X1-AA11")),
+        };
+        assert_eq!(
+            select_expansion(&ranked, &override_, &existing),
+            Some(&SystemSymbol::new("X1-AA11"))
+        );
+        // an override that isn't a reachable candidate is ignored
+        let override_ = ExpansionOverride {
+            system_symbol: Some(SystemSymbol::new("X1-ZZ99")),
+        };
+        assert_eq!(
+            select_expansion(&ranked, &override_, &existing),
+            Some(&SystemSymbol::new("X1-CC33"))
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//!
+//! High level agent goals ("missions").
+//!
+//! Goals make the agent's strategy inspectable: the era logic, ship config generator and task manager
+//! consult goal completion rather than hard-coding their own conditions.
+//!
+use crate::models::SystemSymbol;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Goal {
+    FinishJumpGate,
+    ReachCredits(i64),
+    ColonizeSystem(SystemSymbol),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalStatus {
+    pub goal: Goal,
+    // lower value = higher priority
+    pub priority: i64,
+    // 0.0 - 1.0
+    pub progress: f64,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl GoalStatus {
+    pub fn new(goal: Goal, priority: i64) -> Self {
+        Self {
+            goal,
+            priority,
+            progress: 0.0,
+            completed_at: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_at.is_some()
+    }
+}
+
+// Credits required to leave StartingSystem1
+pub const STARTING_SYSTEM_CREDITS_GOAL: i64 = 800_000;
+
+pub fn default_goals() -> Vec<GoalStatus> {
+    vec![
+        GoalStatus::new(Goal::ReachCredits(STARTING_SYSTEM_CREDITS_GOAL), 1),
+        GoalStatus::new(Goal::FinishJumpGate, 2),
+        GoalStatus::new(Goal::ReachCredits(5_000_000), 3),
+    ]
+}
+
+// Add any default goals missing from a loaded goal list
+pub fn merge_default_goals(goals: &mut Vec<GoalStatus>) {
+    for default_goal in default_goals() {
+        if !goals.iter().any(|g| g.goal == default_goal.goal) {
+            goals.push(default_goal);
+        }
+    }
+    goals.sort_by_key(|g| g.priority);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge_default_goals() {
+        let mut goals = vec![
+            GoalStatus::new(Goal::ColonizeSystem(SystemSymbol::new("X1-AB12")), 0),
+            GoalStatus {
+                completed_at: Some(Utc::now()),
+                ..GoalStatus::new(Goal::FinishJumpGate, 2)
+            },
+        ];
+        merge_default_goals(&mut goals);
+        assert_eq!(goals.len(), 4);
+        assert_eq!(
+            goals[0].goal,
+            Goal::ColonizeSystem(SystemSymbol::new("X1-AB12"))
+        );
+        let jump_gate = goals
+            .iter()
+            .find(|g| g.goal == Goal::FinishJumpGate)
+            .unwrap();
+        assert!(jump_gate.is_complete());
+    }
+}
@@ -0,0 +1,144 @@
+/// Extra haulers for systems where the task manager keeps a valuable backlog of trade tasks no ship is
+/// taking. A hauler is added after the backlog stays above the threshold for a number of planning
+/// cycles, and removed again once it has been empty for as long
+use crate::models::SystemSymbol;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Profitable tasks left unassigned after a planning cycle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TaskBacklog {
+    pub tasks: usize,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SystemScale {
+    extra_haulers: i64,
+    high_cycles: i64,
+    dry_cycles: i64,
+}
+
+#[derive(Debug)]
+pub struct HaulerAutoscaler {
+    threshold: i64,
+    cycles: i64,
+    max_haulers: i64,
+    systems: Mutex<BTreeMap<SystemSymbol, SystemScale>>,
+}
+
+impl HaulerAutoscaler {
+    pub fn new(
+        threshold: i64,
+        cycles: i64,
+        max_haulers: i64,
+        extra_haulers: BTreeMap<SystemSymbol, i64>,
+    ) -> Self {
+        let systems = extra_haulers
+            .into_iter()
+            .map(|(system, extra_haulers)| {
+                (
+                    system,
+                    SystemScale {
+                        extra_haulers: extra_haulers.min(max_haulers),
+                        ..SystemScale::default()
+                    },
+                )
+            })
+            .collect();
+        Self {
+            threshold,
+            cycles,
+            max_haulers,
+            systems: Mutex::new(systems),
+        }
+    }
+
+    // Record the backlog after a planning cycle, returns the new number of extra haulers if it changed
+    pub fn observe(&self, system: &SystemSymbol, backlog: TaskBacklog) -> Option<i64> {
+        let mut systems = self.systems.lock().unwrap();
+        let scale = systems.entry(system.clone()).or_default();
+        if backlog.value >= self.threshold {
+            scale.high_cycles += 1;
+            scale.dry_cycles = 0;
+        } else if backlog.tasks == 0 {
+            scale.dry_cycles += 1;
+            scale.high_cycles = 0;
+        } else {
+            scale.high_cycles = 0;
+            scale.dry_cycles = 0;
+        }
+
+        if scale.high_cycles >= self.cycles && scale.extra_haulers < self.max_haulers {
+            scale.extra_haulers += 1;
+            scale.high_cycles = 0;
+            return Some(scale.extra_haulers);
+        }
+        if scale.dry_cycles >= self.cycles && scale.extra_haulers > 0 {
+            scale.extra_haulers -= 1;
+            scale.dry_cycles = 0;
+            return Some(scale.extra_haulers);
+        }
+        None
+    }
+
+    pub fn extra_haulers(&self) -> BTreeMap<SystemSymbol, i64> {
+        self.systems
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, scale)| scale.extra_haulers > 0)
+            .map(|(system, scale)| (system.clone(), scale.extra_haulers))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hauler_autoscaler() {
+        let system = SystemSymbol::new("X1-S1");
+        let autoscaler = HaulerAutoscaler::new(10_000, 3, 2, BTreeMap::new());
+        let high = TaskBacklog {
+            tasks: 4,
+            value: 50_000,
+        };
+        let low = TaskBacklog {
+            tasks: 1,
+            value: 2_000,
+        };
+        let dry = TaskBacklog::default();
+
+        // a dip resets the count
+        assert_eq!(autoscaler.observe(&system, high), None);
+        assert_eq!(autoscaler.observe(&system, high), None);
+        assert_eq!(autoscaler.observe(&system, low), None);
+        assert_eq!(autoscaler.observe(&system, high), None);
+        assert_eq!(autoscaler.observe(&system, high), None);
+        assert_eq!(autoscaler.observe(&system, high), Some(1));
+
+        // capped at max_haulers
+        for _ in 0..3 {
+            autoscaler.observe(&system, high);
+        }
+        for _ in 0..3 {
+            assert_eq!(autoscaler.observe(&system, high), None);
+        }
+        assert_eq!(
+            autoscaler.extra_haulers(),
+            BTreeMap::from([(system.clone(), 2)])
+        );
+
+        // removed one at a time once the backlog dries up
+        assert_eq!(autoscaler.observe(&system, dry), None);
+        assert_eq!(autoscaler.observe(&system, dry), None);
+        assert_eq!(autoscaler.observe(&system, dry), Some(1));
+
+        // restored from the persisted count
+        let restored = HaulerAutoscaler::new(10_000, 3, 2, BTreeMap::from([(system.clone(), 5)]));
+        assert_eq!(restored.extra_haulers(), BTreeMap::from([(system, 2)]));
+    }
+}
@@ -0,0 +1,146 @@
+/// Which jobs have their ship scripts run.
+/// Set at startup from JOB_INCLUDE, JOB_EXCLUDE and DISABLED_BEHAVIOURS, and replaced at runtime through
+/// the command API until the next restart. Ships running a job that becomes excluded stop at their next safe point, skipped
+/// ships whose job is let through again are restarted
+use crate::models::{ShipBehaviour, ShipConfig};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+pub const BEHAVIOUR_KINDS: &[&str] = &[
+    "probe",
+    "logistics",
+    "siphon",
+    "mining",
+    "construction",
+    "jumpgate_probe",
+    "explorer",
+    "custom",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobFilter {
+    // job id patterns, if any are set a job must match one of them
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // kinds of behaviour that are never run, e.g. "mining" for the surveyors, drones and shuttles
+    #[serde(default)]
+    pub disabled_behaviours: BTreeSet<String>,
+}
+
+pub fn behaviour_kind(behaviour: &ShipBehaviour) -> &'static str {
+    match behaviour {
+        ShipBehaviour::Probe(_) => "probe",
+        ShipBehaviour::Logistics(_) => "logistics",
+        ShipBehaviour::SiphonDrone | ShipBehaviour::SiphonShuttle => "siphon",
+        ShipBehaviour::MiningSurveyor
+        | ShipBehaviour::MiningDrone
+        | ShipBehaviour::MiningShuttle => "mining",
+        ShipBehaviour::ConstructionHauler => "construction",
+        ShipBehaviour::JumpgateProbe => "jumpgate_probe",
+        ShipBehaviour::Explorer => "explorer",
+        ShipBehaviour::Custom(_, _) => "custom",
+    }
+}
+
+fn is_match(pattern: &str, job_id: &str) -> bool {
+    Regex::new(pattern)
+        .expect("Invalid job filter pattern")
+        .is_match(job_id)
+}
+
+impl JobFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        for pattern in self.include.iter().chain(&self.exclude) {
+            Regex::new(pattern).map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?;
+        }
+        for kind in &self.disabled_behaviours {
+            if !BEHAVIOUR_KINDS.contains(&kind.as_str()) {
+                return Err(format!(
+                    "Unknown behaviour {}, expected one of {}",
+                    kind,
+                    BEHAVIOUR_KINDS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Why the job's script isn't run, None if it passes the filter
+    pub fn exclusion(&self, job: &ShipConfig) -> Option<String> {
+        let kind = behaviour_kind(&job.behaviour);
+        if self.disabled_behaviours.contains(kind) {
+            return Some(format!("{} jobs disabled", kind));
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|p| is_match(p, &job.id)) {
+            return Some(format!("job {} not included by the job filter", job.id));
+        }
+        if let Some(pattern) = self.exclude.iter().find(|p| is_match(p, &job.id)) {
+            return Some(format!("job {} excluded by {}", job.id, pattern));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_fixtures::{job, logistics_job};
+
+    #[test]
+    fn test_job_filter() {
+        let drone = job(
+            "mining_drone/3",
+            "SHIP_MINING_DRONE",
+            ShipBehaviour::MiningDrone,
+        );
+        let surveyor = job(
+            "mining_surveyor/1",
+            "SHIP_SURVEYOR",
+            ShipBehaviour::MiningSurveyor,
+        );
+        let hauler = logistics_job("logistics/1", "SHIP_LIGHT_HAULER");
+        let filter = JobFilter::default();
+        assert_eq!(filter.exclusion(&drone), None);
+
+        let filter = JobFilter {
+            disabled_behaviours: BTreeSet::from(["mining".to_string()]),
+            ..JobFilter::default()
+        };
+        assert_eq!(
+            filter.exclusion(&surveyor),
+            Some("mining jobs disabled".to_string())
+        );
+        assert_eq!(filter.exclusion(&hauler), None);
+
+        // excludes win over includes
+        let filter = JobFilter {
+            include: vec!["^mining".to_string()],
+            exclude: vec!["/3$".to_string()],
+            ..JobFilter::default()
+        };
+        assert_eq!(filter.exclusion(&surveyor), None);
+        assert_eq!(
+            filter.exclusion(&drone),
+            Some("job mining_drone/3 excluded by /3$".to_string())
+        );
+        assert_eq!(
+            filter.exclusion(&hauler),
+            Some("job logistics/1 not included by the job filter".to_string())
+        );
+        assert!(filter.validate().is_ok());
+
+        let invalid = JobFilter {
+            exclude: vec!["(".to_string()],
+            ..JobFilter::default()
+        };
+        assert!(invalid.validate().is_err());
+        let unknown = JobFilter {
+            disabled_behaviours: BTreeSet::from(["trading".to_string()]),
+            ..JobFilter::default()
+        };
+        assert!(unknown.validate().is_err());
+    }
+}
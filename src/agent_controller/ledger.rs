@@ -1,9 +1,12 @@
 /// Track the allocations of current credits of the agent
+use chrono::{DateTime, Duration, Utc};
 use log::*;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::max;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Mutex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ShipEntry {
     reserved_credits: i64,
     // trade_symbol -> (units, total_value)
@@ -19,10 +22,91 @@ impl Default for ShipEntry {
     }
 }
 
+// Goods each ship bought and what they cost, saved at shutdown. Reservations are recomputed from the
+// job assignments at startup, but the cost of goods already in the holds isn't
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LedgerSnapshot {
+    ships: BTreeMap<String, ShipEntry>,
+}
+
+type CreditLog = VecDeque<(DateTime<Utc>, i64)>;
+
+// Spending category of ship purchases
+pub const SHIP_SPEND: &str = "SHIPS";
+// Spending category of goods bought for construction sites
+pub const CONSTRUCTION_SPEND: &str = "CONSTRUCTION";
+// Income rates over a shorter window than this are too noisy to forecast from
+const MIN_INCOME_WINDOW_SECONDS: i64 = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetWorth {
+    pub credits: i64,
+    // value of all ship inventories (including ships in transit) at best known sell prices
+    pub cargo_value: i64,
+    // ships valued at the cheapest known shipyard listing for their model
+    pub ship_value: i64,
+    pub total: i64,
+}
+
+pub const NET_WORTH_MILESTONES: &[i64] = &[1_000_000, 10_000_000, 100_000_000];
+
+// Milestones crossed by `total` that haven't been reached before
+pub fn new_milestones(reached: &[i64], total: i64) -> Vec<i64> {
+    NET_WORTH_MILESTONES
+        .iter()
+        .filter(|m| total >= **m && !reached.contains(m))
+        .cloned()
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct Ledger {
     total_credits: Mutex<i64>,
     ships: Mutex<BTreeMap<String, ShipEntry>>,
+    // credit increases over the last hour
+    income: Mutex<CreditLog>,
+    // category -> spending over the last hour
+    spending: Mutex<BTreeMap<String, CreditLog>>,
+    // credit balance over the last hour, and the last balance before it
+    balance: Mutex<CreditLog>,
+    // income and spending are only tracked in memory, from startup
+    tracking_since: DateTime<Utc>,
+}
+
+fn sum_last_hour(entries: &mut CreditLog) -> i64 {
+    let cutoff = Utc::now() - Duration::try_hours(1).unwrap();
+    while matches!(entries.front(), Some((ts, _)) if *ts < cutoff) {
+        entries.pop_front();
+    }
+    entries.iter().map(|(_, amount)| amount).sum()
+}
+
+// Net credits earned per hour, from the balance at the start of the window to `credits`.
+// `added_back` is spending that shouldn't count against income, e.g. the ship purchases being forecast
+fn net_income_per_hour(
+    balance: &CreditLog,
+    credits: i64,
+    added_back: i64,
+    now: DateTime<Utc>,
+) -> i64 {
+    let window_start = now - Duration::try_hours(1).unwrap();
+    let Some((ts, start_credits)) = balance.front() else {
+        return 0;
+    };
+    let elapsed = (now - max(*ts, window_start)).num_seconds();
+    if elapsed < MIN_INCOME_WINDOW_SECONDS {
+        return 0;
+    }
+    (credits - start_credits + added_back) * 3600 / elapsed
+}
+
+// Thresholds that `credits` rose to or past in the update from `prev`
+pub fn crossed_thresholds(prev: i64, credits: i64, thresholds: &[i64]) -> Vec<i64> {
+    thresholds
+        .iter()
+        .filter(|t| prev < **t && credits >= **t)
+        .copied()
+        .collect()
 }
 
 impl Ledger {
@@ -30,17 +114,85 @@ impl Ledger {
         Ledger {
             total_credits: Mutex::new(start_credits),
             ships: Mutex::new(BTreeMap::new()),
+            income: Mutex::new(VecDeque::new()),
+            spending: Mutex::new(BTreeMap::new()),
+            balance: Mutex::new(VecDeque::from([(Utc::now(), start_credits)])),
+            tracking_since: Utc::now(),
         }
     }
 
     pub fn set_credits(&self, credits: i64) {
-        *self.total_credits.lock().unwrap() = credits;
+        let mut total_credits = self.total_credits.lock().unwrap();
+        let delta = credits - *total_credits;
+        if delta > 0 {
+            self.income.lock().unwrap().push_back((Utc::now(), delta));
+        }
+        *total_credits = credits;
+
+        // keep the last balance from before the window, it's where the window starts from
+        let mut balance = self.balance.lock().unwrap();
+        balance.push_back((Utc::now(), credits));
+        let cutoff = Utc::now() - Duration::try_hours(1).unwrap();
+        while balance.len() > 1 && balance[1].0 <= cutoff {
+            balance.pop_front();
+        }
+    }
+
+    pub fn income_last_hour(&self) -> i64 {
+        sum_last_hour(&mut self.income.lock().unwrap())
+    }
+
+    // Income over the last hour, None for the first hour after startup when it only covers part of it
+    pub fn full_hour_income(&self) -> Option<i64> {
+        match Utc::now() - self.tracking_since >= Duration::try_hours(1).unwrap() {
+            true => Some(self.income_last_hour()),
+            false => None,
+        }
+    }
+
+    // Credits earned per hour, net of trade purchases and other spending, but not ship purchases
+    pub fn net_income_per_hour(&self) -> i64 {
+        let credits = self.credits();
+        let ship_spend = self.spend_last_hour(SHIP_SPEND);
+        let balance = self.balance.lock().unwrap();
+        net_income_per_hour(&balance, credits, ship_spend, Utc::now())
+    }
+
+    pub fn register_spend(&self, category: &str, amount: i64) {
+        let mut spending = self.spending.lock().unwrap();
+        spending
+            .entry(category.to_string())
+            .or_default()
+            .push_back((Utc::now(), amount));
+    }
+
+    pub fn spend_last_hour(&self, category: &str) -> i64 {
+        let mut spending = self.spending.lock().unwrap();
+        match spending.get_mut(category) {
+            Some(entries) => sum_last_hour(entries),
+            None => 0,
+        }
+    }
+
+    // Tripped when reservations exceed the credits we actually hold
+    pub fn circuit_breaker_tripped(&self) -> bool {
+        self.available_credits() < 0
     }
 
     pub fn credits(&self) -> i64 {
         *self.total_credits.lock().unwrap()
     }
 
+    pub fn net_worth(&self, cargo_value: i64, ship_value: i64) -> NetWorth {
+        let credits = self.credits();
+        NetWorth {
+            credits,
+            cargo_value,
+            ship_value,
+            total: credits + cargo_value + ship_value,
+        }
+    }
+
     pub fn reserve_credits(&self, ship_symbol: &str, amount: i64) {
         debug!("Setting {} credits reserved for {}", amount, ship_symbol);
         let mut ships = self.ships.lock().unwrap();
@@ -72,6 +224,31 @@ impl Ledger {
         }
     }
 
+    // Drop a ship's unspent reservation, keeping the goods it already holds
+    pub fn release_reservation(&self, ship_symbol: &str) {
+        let mut ships = self.ships.lock().unwrap();
+        if let Some(ship_entry) = ships.get_mut(ship_symbol) {
+            ship_entry.reserved_credits = ship_entry.goods.values().map(|(_, v)| v).sum();
+        }
+    }
+
+    pub fn snapshot(&self) -> LedgerSnapshot {
+        LedgerSnapshot {
+            ships: self.ships.lock().unwrap().clone(),
+        }
+    }
+
+    // Restore the goods of a snapshot, keeping the current reservations
+    pub fn restore(&self, snapshot: LedgerSnapshot) {
+        let mut ships = self.ships.lock().unwrap();
+        for (ship_symbol, saved) in snapshot.ships {
+            let ship_entry = ships.entry(ship_symbol).or_default();
+            if ship_entry.goods.is_empty() {
+                ship_entry.goods = saved.goods;
+            }
+        }
+    }
+
     pub fn available_credits(&self) -> i64 {
         self.credits() - self.effective_reserved_credits()
     }
@@ -88,3 +265,92 @@ impl Ledger {
             .sum()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_milestones() {
+        assert_eq!(new_milestones(&[], 500_000), Vec::<i64>::new());
+        assert_eq!(new_milestones(&[], 1_200_000), vec![1_000_000]);
+        assert_eq!(new_milestones(&[1_000_000], 1_200_000), Vec::<i64>::new());
+        assert_eq!(new_milestones(&[1_000_000], 12_000_000), vec![10_000_000]);
+    }
+
+    #[test]
+    fn test_crossed_thresholds() {
+        let thresholds = [800_000, 2_000_000];
+        assert_eq!(
+            crossed_thresholds(700_000, 900_000, &thresholds),
+            vec![800_000]
+        );
+        assert_eq!(
+            crossed_thresholds(900_000, 950_000, &thresholds),
+            Vec::<i64>::new()
+        );
+        assert_eq!(
+            crossed_thresholds(900_000, 700_000, &thresholds),
+            Vec::<i64>::new()
+        );
+        assert_eq!(
+            crossed_thresholds(0, 2_000_000, &thresholds),
+            vec![800_000, 2_000_000]
+        );
+    }
+
+    #[test]
+    fn test_full_hour_income() {
+        let mut ledger = Ledger::new(100_000);
+        ledger.set_credits(150_000);
+        assert_eq!(ledger.income_last_hour(), 50_000);
+        assert_eq!(ledger.full_hour_income(), None);
+        ledger.tracking_since = Utc::now() - Duration::try_minutes(61).unwrap();
+        assert_eq!(ledger.full_hour_income(), Some(50_000));
+    }
+
+    #[test]
+    fn test_net_income_per_hour() {
+        let now = Utc::now();
+        let ago = |mins| now - Duration::try_minutes(mins).unwrap();
+        let balance = CreditLog::from([(ago(30), 100_000), (ago(20), 80_000)]);
+        assert_eq!(net_income_per_hour(&balance, 150_000, 0, now), 100_000);
+        // a ship bought in the window doesn't count against income
+        assert_eq!(net_income_per_hour(&balance, 100_000, 50_000, now), 100_000);
+
+        // the window is capped at an hour, from the balance at its start
+        let balance = CreditLog::from([(ago(600), 100_000), (ago(30), 120_000)]);
+        assert_eq!(net_income_per_hour(&balance, 160_000, 0, now), 60_000);
+
+        // too soon to tell
+        let balance = CreditLog::from([(ago(2), 100_000)]);
+        assert_eq!(net_income_per_hour(&balance, 150_000, 0, now), 0);
+    }
+
+    #[test]
+    fn test_release_reservation() {
+        let ledger = Ledger::new(100_000);
+        ledger.reserve_credits("A-1", 50_000);
+        ledger.register_goods_change("A-1", "FUEL", 100, 70);
+        assert_eq!(ledger.available_credits(), 57_000);
+        ledger.release_reservation("A-1");
+        assert_eq!(ledger.effective_reserved_credits(), 0);
+        assert_eq!(ledger.available_credits(), 100_000);
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let ledger = Ledger::new(100_000);
+        ledger.reserve_credits("A-1", 50_000);
+        ledger.register_goods_change("A-1", "FUEL", 100, 70);
+        let snapshot = serde_json::to_value(ledger.snapshot()).unwrap();
+
+        // after a restart the reservation is made again, the goods come from the snapshot
+        let restarted = Ledger::new(100_000);
+        restarted.reserve_credits("A-1", 60_000);
+        restarted.restore(serde_json::from_value(snapshot).unwrap());
+        assert_eq!(restarted.effective_reserved_credits(), 53_000);
+        restarted.release_reservation("A-1");
+        assert_eq!(restarted.effective_reserved_credits(), 0);
+    }
+}
@@ -1,3 +1,19 @@
 mod agent_controller;
+pub mod arrival_scheduler;
+pub mod chart_queue;
+pub mod convoy;
+pub mod credit_forecast;
+pub mod event_bus;
+pub mod expansion;
+pub mod goals;
+pub mod hauler_autoscaler;
+pub mod job_filter;
 pub mod ledger;
+pub mod ship_price_watch;
+pub mod ship_status;
+pub mod ship_updates;
+pub mod shipyard_cover;
+pub mod strategy;
+pub mod wind_down;
 pub use agent_controller::*;
+pub use strategy::Strategy;
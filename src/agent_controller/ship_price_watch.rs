@@ -0,0 +1,34 @@
+/// Rolling percentile of a ship model's recorded shipyard prices.
+/// With a percentile configured, a ship is only bought while its price is at or below the percentile of
+/// the prices seen over the window, and a shipyard refresh showing a wanted model at such a price
+/// triggers a purchase. Models with too little history are bought at any price
+// Samples needed before prices are judged against the history
+pub const MIN_SAMPLES: usize = 10;
+
+// Nearest-rank percentile of the prices, None if there are too few to go by
+pub fn price_threshold(prices: &[i64], percentile: i64) -> Option<i64> {
+    if prices.len() < MIN_SAMPLES {
+        return None;
+    }
+    let mut prices = prices.to_vec();
+    prices.sort();
+    let rank = (percentile.clamp(1, 100) as usize * prices.len()).div_ceil(100);
+    Some(prices[rank - 1])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_price_threshold() {
+        let prices = [190, 100, 180, 110, 170, 120, 160, 130, 150, 140];
+        assert_eq!(price_threshold(&prices, 50), Some(140));
+        assert_eq!(price_threshold(&prices, 25), Some(120));
+        assert_eq!(price_threshold(&prices, 100), Some(190));
+        assert_eq!(price_threshold(&prices, 1), Some(100));
+        // a price that never moves is always at its percentile
+        assert_eq!(price_threshold(&[250; 12], 10), Some(250));
+        assert_eq!(price_threshold(&prices[..9], 50), None);
+    }
+}
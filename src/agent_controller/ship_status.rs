@@ -0,0 +1,155 @@
+/// Whether each ship is running its script, and why not. Ships skipped at spawn (damaged, filtered
+/// out by the job filter) or whose script panicked would otherwise only show up in the logs.
+/// While a script runs, its ship state records the current step and where the ship is headed
+use super::job_filter::JobFilter;
+use crate::models::{Ship, ShipConfig, WaypointSymbol};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", content = "detail")]
+pub enum ShipStatus {
+    // job id
+    Running(String),
+    Skipped(String),
+    Crashed(String),
+    // no script running, e.g. an idle ship or a script that exited
+    Paused,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipStatusUpdate {
+    pub symbol: String,
+    pub status: ShipStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipState {
+    // set by the script, e.g. "Exploring jumpgate X1-AB12-I55"
+    pub step: String,
+    // destination of the current navigation
+    pub target: Option<WaypointSymbol>,
+    pub eta: Option<DateTime<Utc>>,
+    // when the step started
+    pub since: DateTime<Utc>,
+}
+
+impl ShipState {
+    pub fn with_step(prev: Option<&ShipState>, step: &str, now: DateTime<Utc>) -> ShipState {
+        match prev {
+            Some(prev) if prev.step == step => prev.clone(),
+            Some(prev) => ShipState {
+                step: step.to_string(),
+                since: now,
+                ..prev.clone()
+            },
+            None => ShipState {
+                step: step.to_string(),
+                target: None,
+                eta: None,
+                since: now,
+            },
+        }
+    }
+
+    pub fn with_target(
+        prev: Option<&ShipState>,
+        target: Option<&WaypointSymbol>,
+        eta: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> ShipState {
+        let mut state = match prev {
+            Some(prev) => prev.clone(),
+            None => ShipState::with_step(None, "", now),
+        };
+        state.target = target.cloned();
+        state.eta = eta;
+        state
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipStateUpdate {
+    pub symbol: String,
+    pub state: ShipState,
+}
+
+// Reason the ship's script for the job shouldn't be started, if any
+pub fn skip_reason(ship: &Ship, job: &ShipConfig, job_filter: &JobFilter) -> Option<String> {
+    if let Some(reason) = job_filter.exclusion(job) {
+        return Some(reason);
+    }
+    let components = [
+        ("engine", ship.engine.condition),
+        ("frame", ship.frame.condition),
+        ("reactor", ship.reactor.condition),
+    ];
+    for (component, condition) in components {
+        let condition = condition.unwrap();
+        if condition < 0.0 {
+            return Some(format!("{} condition {}", component, condition));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::ShipBehaviour;
+    use crate::models::WaypointSymbol;
+    use crate::test_fixtures::{job, probe};
+
+    #[test]
+    fn test_ship_state() {
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let t1 = DateTime::from_timestamp(1_700_000_060, 0).unwrap();
+        let eta = DateTime::from_timestamp(1_700_000_300, 0).unwrap();
+        let target = WaypointSymbol::new("X1-TEST-A1");
+
+        let state = ShipState::with_step(None, "Exploring", t0);
+        let state = ShipState::with_target(Some(&state), Some(&target), Some(eta), t1);
+        assert_eq!(state.step, "Exploring");
+        assert_eq!(state.target, Some(target.clone()));
+        assert_eq!(state.since, t0);
+
+        // repeating the step keeps its start time, a new step keeps the navigation
+        assert_eq!(ShipState::with_step(Some(&state), "Exploring", t1), state);
+        let next = ShipState::with_step(Some(&state), "Trading", t1);
+        assert_eq!(next.since, t1);
+        assert_eq!(next.eta, Some(eta));
+
+        let arrived = ShipState::with_target(Some(&next), None, None, t1);
+        assert_eq!(arrived.target, None);
+        assert_eq!(arrived.step, "Trading");
+    }
+
+    #[test]
+    fn test_skip_reason() {
+        let mut ship = probe("A-1", &WaypointSymbol::new("X1-TEST-A1"));
+        let probe_job = job("probe/1", "SHIP_PROBE", ShipBehaviour::JumpgateProbe);
+        let any = JobFilter::default();
+        assert_eq!(skip_reason(&ship, &probe_job, &any), None);
+
+        let only_logistics = JobFilter {
+            include: vec!["^logistics".to_string()],
+            ..JobFilter::default()
+        };
+        assert_eq!(
+            skip_reason(&ship, &probe_job, &only_logistics),
+            Some("job probe/1 not included by the job filter".to_string())
+        );
+
+        ship.frame.condition = Some(-0.5);
+        assert_eq!(
+            skip_reason(&ship, &probe_job, &any),
+            Some("frame condition -0.5".to_string())
+        );
+
+        let status = serde_json::to_value(ShipStatus::Skipped("frame".to_string())).unwrap();
+        assert_eq!(
+            status,
+            serde_json::json!({"status": "Skipped", "detail": "frame"})
+        );
+    }
+}
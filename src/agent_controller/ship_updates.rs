@@ -0,0 +1,153 @@
+/// Coalesce ship updates for event emission.
+/// Ships are marked dirty on every change, and flushed once per window as a diff holding only the
+/// fields that changed since the ship was last emitted. The first emission of a ship is complete, and so
+/// is the next one after a resync, e.g. for a new subscriber that missed the earlier ones.
+use crate::models::Ship;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+pub const SHIP_UPDATE_WINDOW: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipDiff {
+    pub symbol: String,
+    // changed top-level fields, named as in the serialized Ship
+    #[serde(flatten)]
+    pub changes: Map<String, Value>,
+}
+
+fn diff_field<T: Serialize + PartialEq>(
+    changes: &mut Map<String, Value>,
+    name: &str,
+    prev: Option<&T>,
+    next: &T,
+) {
+    if prev != Some(next) {
+        changes.insert(name.to_string(), serde_json::to_value(next).unwrap());
+    }
+}
+
+pub fn diff_ship(prev: Option<&Ship>, ship: &Ship) -> Option<ShipDiff> {
+    let mut changes = Map::new();
+    diff_field(&mut changes, "nav", prev.map(|p| &p.nav), &ship.nav);
+    diff_field(&mut changes, "crew", prev.map(|p| &p.crew), &ship.crew);
+    diff_field(&mut changes, "fuel", prev.map(|p| &p.fuel), &ship.fuel);
+    diff_field(
+        &mut changes,
+        "cooldown",
+        prev.map(|p| &p.cooldown),
+        &ship.cooldown,
+    );
+    diff_field(&mut changes, "frame", prev.map(|p| &p.frame), &ship.frame);
+    diff_field(
+        &mut changes,
+        "reactor",
+        prev.map(|p| &p.reactor),
+        &ship.reactor,
+    );
+    diff_field(
+        &mut changes,
+        "engine",
+        prev.map(|p| &p.engine),
+        &ship.engine,
+    );
+    diff_field(
+        &mut changes,
+        "modules",
+        prev.map(|p| &p.modules),
+        &ship.modules,
+    );
+    diff_field(
+        &mut changes,
+        "mounts",
+        prev.map(|p| &p.mounts),
+        &ship.mounts,
+    );
+    diff_field(
+        &mut changes,
+        "registration",
+        prev.map(|p| &p.registration),
+        &ship.registration,
+    );
+    diff_field(&mut changes, "cargo", prev.map(|p| &p.cargo), &ship.cargo);
+    match changes.is_empty() {
+        true => None,
+        false => Some(ShipDiff {
+            symbol: ship.symbol.clone(),
+            changes,
+        }),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ShipUpdateCoalescer {
+    dirty: Mutex<BTreeSet<String>>,
+    last_emitted: Mutex<BTreeMap<String, Ship>>,
+}
+
+impl ShipUpdateCoalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mark_dirty(&self, ship_symbol: &str) {
+        self.dirty.lock().unwrap().insert(ship_symbol.to_string());
+    }
+
+    pub fn take_dirty(&self) -> BTreeSet<String> {
+        std::mem::take(&mut *self.dirty.lock().unwrap())
+    }
+
+    // Forget what was emitted, so the next update of each ship is complete
+    pub fn resync(&self, ship_symbols: impl IntoIterator<Item = String>) {
+        self.last_emitted.lock().unwrap().clear();
+        self.dirty.lock().unwrap().extend(ship_symbols);
+    }
+
+    // Diff against the last emitted state, and record `ship` as emitted
+    pub fn diff(&self, ship: &Ship) -> Option<ShipDiff> {
+        let mut last_emitted = self.last_emitted.lock().unwrap();
+        let diff = diff_ship(last_emitted.get(&ship.symbol), ship);
+        if diff.is_some() {
+            last_emitted.insert(ship.symbol.clone(), ship.clone());
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::WaypointSymbol;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_ship_diff() {
+        let coalescer = ShipUpdateCoalescer::new();
+        let mut ship = test_fixtures::probe("AGENT-1", &WaypointSymbol::new("X1-AB12-A1"));
+        coalescer.mark_dirty(&ship.symbol);
+        coalescer.mark_dirty(&ship.symbol);
+        assert_eq!(coalescer.take_dirty().len(), 1);
+        assert!(coalescer.take_dirty().is_empty());
+
+        // first emission is complete
+        let first = coalescer.diff(&ship).unwrap();
+        assert_eq!(first.changes.len(), 11);
+        assert!(coalescer.diff(&ship).is_none());
+
+        ship.fuel.current -= 1;
+        ship.cargo.units += 1;
+        let diff = coalescer.diff(&ship).unwrap();
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["symbol"], "AGENT-1");
+        assert!(json.get("fuel").is_some() && json.get("cargo").is_some());
+        assert!(json.get("nav").is_none());
+
+        // complete again after a resync
+        coalescer.resync([ship.symbol.clone()]);
+        assert_eq!(coalescer.take_dirty().len(), 1);
+        assert_eq!(coalescer.diff(&ship).unwrap().changes.len(), 11);
+    }
+}
@@ -0,0 +1,85 @@
+/// Shipyards whose probe is missing (e.g. it was scrapped) are borrowed by the nearest roaming probe
+/// in the system until a replacement is bought, so refreshing them doesn't need a hauler detour
+use crate::models::WaypointSymbol;
+use std::collections::BTreeMap;
+
+// A roaming probe and where it is
+#[derive(Debug, Clone)]
+pub struct RoamingProbe {
+    pub symbol: String,
+    pub waypoint: WaypointSymbol,
+    pub x: i64,
+    pub y: i64,
+}
+
+// Probe -> shipyard it covers. Existing reservations are kept while the shipyard is still uncovered
+// and the probe still roaming, each remaining shipyard goes to the nearest free probe in its system
+pub fn cover_assignments(
+    uncovered: &[(WaypointSymbol, i64, i64)],
+    probes: &[RoamingProbe],
+    reserved: &BTreeMap<String, WaypointSymbol>,
+) -> BTreeMap<String, WaypointSymbol> {
+    let mut assignments = reserved
+        .iter()
+        .filter(|(probe, shipyard)| {
+            uncovered.iter().any(|(s, _, _)| s == *shipyard)
+                && probes.iter().any(|p| p.symbol == **probe)
+        })
+        .map(|(probe, shipyard)| (probe.clone(), shipyard.clone()))
+        .collect::<BTreeMap<_, _>>();
+    for (shipyard, x, y) in uncovered {
+        if assignments.values().any(|s| s == shipyard) {
+            continue;
+        }
+        let nearest = probes
+            .iter()
+            .filter(|p| p.waypoint.system() == shipyard.system())
+            .filter(|p| !assignments.contains_key(&p.symbol))
+            .min_by_key(|p| (p.x - x).pow(2) + (p.y - y).pow(2));
+        if let Some(probe) = nearest {
+            assignments.insert(probe.symbol.clone(), shipyard.clone());
+        }
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cover_assignments() {
+        let probe = |symbol: &str, waypoint: &str, x: i64, y: i64| RoamingProbe {
+            symbol: symbol.to_string(),
+            waypoint: WaypointSymbol::new(waypoint),
+            x,
+            y,
+        };
+        let yard_a = WaypointSymbol::new("X1-S1-A1");
+        let yard_b = WaypointSymbol::new("X1-S1-B2");
+        let probes = vec![
+            probe("P-1", "X1-S1-C3", 0, 0),
+            probe("P-2", "X1-S1-D4", 100, 0),
+            probe("P-3", "X1-S2-A1", 90, 0),
+        ];
+        let uncovered = vec![(yard_a.clone(), 80, 0), (yard_b.clone(), 70, 0)];
+
+        // the nearest probe in the system, each probe covers one shipyard
+        let assignments = cover_assignments(&uncovered, &probes, &BTreeMap::new());
+        assert_eq!(
+            assignments,
+            BTreeMap::from([
+                ("P-2".to_string(), yard_a.clone()),
+                ("P-1".to_string(), yard_b.clone()),
+            ])
+        );
+
+        // reservations stick, even if another probe is now closer
+        let reserved = BTreeMap::from([("P-1".to_string(), yard_a.clone())]);
+        let assignments = cover_assignments(&uncovered[..1], &probes, &reserved);
+        assert_eq!(assignments, reserved);
+
+        // released once the shipyard's probe is replaced
+        assert!(cover_assignments(&[], &probes, &reserved).is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+/// Strategy presets, chosen at startup with STRATEGY. A preset selects the ship config generator
+/// for the starting system and which eras the agent advances through
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, EnumString)]
+pub enum Strategy {
+    // Build the jump gate, then expand into the capital system
+    #[default]
+    GateRush,
+    // Trade in the starting system and never build the gate, same as NO_GATE_MODE
+    PureTrading,
+    // Gate rush with a larger mining operation
+    MiningHeavy,
+    // Probes at every market from the start, so trading sees the whole system early
+    ProbeNetworkFirst,
+}
+
+impl Strategy {
+    pub fn no_gate(self) -> bool {
+        self == Strategy::PureTrading
+    }
+
+    // Multiplier on the starting system's mining drones and shuttles
+    pub fn mining_scale(self) -> i64 {
+        match self {
+            Strategy::MiningHeavy => 2,
+            _ => 1,
+        }
+    }
+
+    // Whether probes for the outer markets are bought in the first era, ahead of the mining operation
+    pub fn early_outer_probes(self) -> bool {
+        self == Strategy::ProbeNetworkFirst
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::ShipBehaviour;
+    use crate::ship_config::ship_config_starter_system;
+    use crate::test_fixtures::starter_system_waypoints;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_strategy_ship_config() {
+        assert_eq!(Strategy::from_str("MiningHeavy"), Ok(Strategy::MiningHeavy));
+        assert!(Strategy::from_str("Turtle").is_err());
+
+        let waypoints = starter_system_waypoints();
+        let config = |strategy| {
+            ship_config_starter_system(&waypoints, &vec![], &vec![], true, false, strategy)
+        };
+        let count = |strategy, f: fn(&ShipBehaviour) -> bool| {
+            config(strategy).iter().filter(|c| f(&c.behaviour)).count()
+        };
+        let is_drone = |b: &ShipBehaviour| matches!(b, ShipBehaviour::MiningDrone);
+        let is_probe = |b: &ShipBehaviour| matches!(b, ShipBehaviour::Probe(_));
+        let is_siphon = |b: &ShipBehaviour| matches!(b, ShipBehaviour::SiphonDrone);
+
+        assert_eq!(
+            count(Strategy::MiningHeavy, is_drone),
+            2 * count(Strategy::GateRush, is_drone)
+        );
+
+        // outer probes come before the mining fleet, siphons still wait for the next era
+        let probe_first = config(Strategy::ProbeNetworkFirst);
+        assert!(count(Strategy::ProbeNetworkFirst, is_probe) > count(Strategy::GateRush, is_probe));
+        assert_eq!(count(Strategy::ProbeNetworkFirst, is_siphon), 0);
+        let last_probe = probe_first.iter().rposition(|c| is_probe(&c.behaviour));
+        let first_drone = probe_first.iter().position(|c| is_drone(&c.behaviour));
+        assert!(last_probe < first_drone);
+    }
+}
@@ -0,0 +1,127 @@
+/// Systems whose operation is being wound down, e.g. an unprofitable expansion being abandoned or a
+/// fleet gathering at the gate before migrating. Set through the command API and kept across restarts.
+/// Ships in the system stop at their next safe point, as for a job the job filter excludes, then fly to
+/// the system's parking waypoint and wait there. No logistics tasks are generated for the system.
+/// The wind down is complete once no ship in the system is still running a script or on its way to park
+use crate::models::{SystemSymbol, WaypointSymbol};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindDown {
+    pub system_symbol: SystemSymbol,
+    // where the system's ships wait, None leaves them where their scripts stop
+    pub park_at: Option<WaypointSymbol>,
+    pub since: DateTime<Utc>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindDownStatus {
+    #[serde(flatten)]
+    pub wind_down: WindDown,
+    // ships in the system yet to stop
+    pub pending_ships: Vec<String>,
+}
+
+impl WindDown {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.park_at {
+            Some(park_at) if park_at.system() != self.system_symbol => Err(format!(
+                "Parking waypoint {} is not in system {}",
+                park_at, self.system_symbol
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn reason(&self) -> String {
+        format!("system {} winding down", self.system_symbol)
+    }
+
+    // Where a ship at this waypoint still has to fly to, None once it's parked
+    pub fn parking_target(&self, waypoint: &WaypointSymbol) -> Option<&WaypointSymbol> {
+        self.park_at.as_ref().filter(|park_at| *park_at != waypoint)
+    }
+
+    // Complete once no ship is pending, reopened if one turns up. True if it just completed
+    pub fn update_completion(&mut self, pending: usize, now: DateTime<Utc>) -> bool {
+        match (pending, self.completed_at) {
+            (0, None) => {
+                self.completed_at = Some(now);
+                true
+            }
+            (0, Some(_)) => false,
+            (_, _) => {
+                self.completed_at = None;
+                false
+            }
+        }
+    }
+}
+
+// A new wind down replaces any other for the same system
+pub fn upsert(wind_downs: &mut Vec<WindDown>, wind_down: WindDown) {
+    wind_downs.retain(|w| w.system_symbol != wind_down.system_symbol);
+    wind_downs.push(wind_down);
+}
+
+pub fn find<'a>(wind_downs: &'a [WindDown], system_symbol: &SystemSymbol) -> Option<&'a WindDown> {
+    wind_downs
+        .iter()
+        .find(|w| w.system_symbol == *system_symbol)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wind_down() {
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let system = SystemSymbol::new("X1-AB12");
+        let gate = WaypointSymbol::new("X1-AB12-I55");
+        let market = WaypointSymbol::new("X1-AB12-A1");
+        let wind_down = WindDown {
+            system_symbol: system.clone(),
+            park_at: Some(gate.clone()),
+            since: t0,
+            completed_at: None,
+        };
+        assert!(wind_down.validate().is_ok());
+        assert_eq!(wind_down.parking_target(&market), Some(&gate));
+        assert_eq!(wind_down.parking_target(&gate), None);
+        assert_eq!(wind_down.reason(), "system X1-AB12 winding down");
+
+        // not complete while a ship is on its way to park
+        let mut completion = wind_down.clone();
+        let t1 = t0 + chrono::Duration::try_minutes(5).unwrap();
+        assert!(!completion.update_completion(2, t1));
+        assert_eq!(completion.completed_at, None);
+        assert!(completion.update_completion(0, t1));
+        assert!(!completion.update_completion(0, t1 + chrono::Duration::try_minutes(1).unwrap()));
+        assert_eq!(completion.completed_at, Some(t1));
+        assert!(!completion.update_completion(1, t1));
+        assert_eq!(completion.completed_at, None);
+
+        let elsewhere = WindDown {
+            park_at: Some(WaypointSymbol::new("X1-CD34-A1")),
+            ..wind_down.clone()
+        };
+        assert!(elsewhere.validate().is_err());
+
+        // ships stay where they stop without a parking waypoint
+        let in_place = WindDown {
+            park_at: None,
+            ..wind_down.clone()
+        };
+        assert_eq!(in_place.parking_target(&market), None);
+
+        let mut wind_downs = vec![wind_down];
+        upsert(&mut wind_downs, in_place.clone());
+        assert_eq!(wind_downs, vec![in_place.clone()]);
+        assert_eq!(find(&wind_downs, &system), Some(&in_place));
+        assert_eq!(find(&wind_downs, &SystemSymbol::new("X1-CD34")), None);
+    }
+}
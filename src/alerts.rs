@@ -0,0 +1,270 @@
+//!
+//! Threshold-based operator alerts.
+//!
+//! Alerts are logged at warn level and, if ALERT_WEBHOOK_URL is set, posted to the webhook as
+//! `{"content": <message>}` (Discord/Slack compatible). Each kind of alert is sent at most once per
+//! ALERT_REPEAT_MINS, so a persistent condition doesn't flood the channel.
+//!
+use crate::config::CONFIG;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use log::*;
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+const ALERT_REPEAT_MINS: i64 = 30;
+// Requests in the latency window, p99 is checked each time the window fills
+const LATENCY_WINDOW: usize = 200;
+const RATE_LIMITED_WINDOW_MINS: i64 = 5;
+const RATE_LIMITED_THRESHOLD: usize = 5;
+// Waiting this long for a database connection means the pool is exhausted
+const DB_POOL_WAIT_THRESHOLD_MS: u128 = 2000;
+// Sent alerts kept for the operations report
+const SENT_HISTORY: usize = 100;
+
+lazy_static! {
+    pub static ref ALERTS: Alerts = Alerts::new(match CONFIG.features.notifications {
+        true => CONFIG.alert_webhook_url.clone(),
+        false => None,
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct Alerts {
+    webhook_url: Option<String>,
+    last_sent: Mutex<BTreeMap<String, DateTime<Utc>>>,
+    latencies: Mutex<Vec<u128>>,
+    rate_limited: Mutex<VecDeque<DateTime<Utc>>>,
+    // ship -> (state description, last action or change to its nav, cargo, fuel or cooldown)
+    ship_activity: Mutex<BTreeMap<String, (String, DateTime<Utc>)>>,
+    sent: Mutex<VecDeque<(DateTime<Utc>, String)>>,
+}
+
+fn p99(latencies: &mut [u128]) -> u128 {
+    latencies.sort_unstable();
+    let idx = (latencies.len() * 99 / 100).min(latencies.len() - 1);
+    latencies[idx]
+}
+
+impl Alerts {
+    pub fn new(webhook_url: Option<String>) -> Self {
+        Self {
+            webhook_url,
+            ..Self::default()
+        }
+    }
+
+    // Returns true if the alert was sent, false if suppressed as a repeat
+    pub fn notify(&self, key: &str, message: &str) -> bool {
+        self.notify_at(key, message, Utc::now())
+    }
+
+    fn notify_at(&self, key: &str, message: &str, now: DateTime<Utc>) -> bool {
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if let Some(last) = last_sent.get(key) {
+                if now - *last < Duration::try_minutes(ALERT_REPEAT_MINS).unwrap() {
+                    return false;
+                }
+            }
+            last_sent.insert(key.to_string(), now);
+        }
+        warn!("ALERT [{}] {}", key, message);
+        let content = format!("[{}] {}", key, message);
+        {
+            let mut sent = self.sent.lock().unwrap();
+            sent.push_back((now, content.clone()));
+            if sent.len() > SENT_HISTORY {
+                sent.pop_front();
+            }
+        }
+        self.post(&content);
+        true
+    }
+
+    // Post a message to the webhook, if set
+    pub fn post(&self, content: &str) {
+        if let Some(url) = &self.webhook_url {
+            let url = url.clone();
+            let body = json!({ "content": content });
+            tokio::spawn(async move {
+                let result = reqwest::Client::new().post(&url).json(&body).send().await;
+                if let Err(e) = result {
+                    warn!("Failed to post alert to webhook: {}", e);
+                }
+            });
+        }
+    }
+
+    // Alerts sent at or after `since`, oldest first
+    pub fn sent_since(&self, since: DateTime<Utc>) -> Vec<String> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter()
+            .filter(|(ts, _)| *ts >= since)
+            .map(|(_, content)| content.clone())
+            .collect()
+    }
+
+    pub fn record_request(&self, method: &str, path: &str, latency_ms: u128, status: u16) {
+        let now = Utc::now();
+        let p99_ms = {
+            let mut latencies = self.latencies.lock().unwrap();
+            latencies.push(latency_ms);
+            match latencies.len() >= LATENCY_WINDOW {
+                true => Some(p99(&mut std::mem::take(&mut *latencies))),
+                false => None,
+            }
+        };
+        if let Some(p99_ms) = p99_ms {
+            if p99_ms > CONFIG.alert_api_p99_ms {
+                self.notify(
+                    "api_latency",
+                    &format!(
+                        "API p99 latency {}ms over the last {} requests",
+                        p99_ms, LATENCY_WINDOW
+                    ),
+                );
+            }
+        }
+        if status == 429 {
+            let count = {
+                let mut rate_limited = self.rate_limited.lock().unwrap();
+                rate_limited.push_back(now);
+                let cutoff = now - Duration::try_minutes(RATE_LIMITED_WINDOW_MINS).unwrap();
+                while matches!(rate_limited.front(), Some(ts) if *ts < cutoff) {
+                    rate_limited.pop_front();
+                }
+                rate_limited.len()
+            };
+            if count >= RATE_LIMITED_THRESHOLD {
+                self.notify(
+                    "api_rate_limited",
+                    &format!(
+                        "{} rate limited (429) responses in {} minutes, latest {} {}",
+                        count, RATE_LIMITED_WINDOW_MINS, method, path
+                    ),
+                );
+            }
+        }
+    }
+
+    pub fn record_planner_run(&self, elapsed: Duration, max_compute_time: Duration) {
+        // the planner checks its time limit between iterations, allow a second of slack
+        if elapsed > max_compute_time + Duration::try_seconds(1).unwrap() {
+            self.notify(
+                "planner_overrun",
+                &format!(
+                    "Planner took {:.1}s, max compute time is {}s",
+                    elapsed.num_milliseconds() as f64 / 1000.0,
+                    max_compute_time.num_seconds()
+                ),
+            );
+        }
+    }
+
+    pub fn record_db_pool_wait(&self, wait_ms: u128) {
+        if wait_ms > DB_POOL_WAIT_THRESHOLD_MS {
+            self.notify(
+                "db_pool",
+                &format!("Waited {}ms for a database connection", wait_ms),
+            );
+        }
+    }
+
+    pub fn record_ship_state(&self, ship_symbol: &str, desc: &str) {
+        self.record_ship_state_at(ship_symbol, desc, Utc::now());
+    }
+
+    // The description is only reported, a script cycling through descriptions can still be stuck
+    fn record_ship_state_at(&self, ship_symbol: &str, desc: &str, now: DateTime<Utc>) {
+        let mut ship_activity = self.ship_activity.lock().unwrap();
+        ship_activity
+            .entry(ship_symbol.to_string())
+            .and_modify(|(prev, _)| *prev = desc.to_string())
+            .or_insert_with(|| (desc.to_string(), now));
+    }
+
+    pub fn record_ship_activity(&self, ship_symbol: &str) {
+        self.record_ship_activity_at(ship_symbol, Utc::now());
+    }
+
+    fn record_ship_activity_at(&self, ship_symbol: &str, now: DateTime<Utc>) {
+        let mut ship_activity = self.ship_activity.lock().unwrap();
+        ship_activity
+            .entry(ship_symbol.to_string())
+            .and_modify(|(_, last)| *last = now)
+            .or_insert_with(|| (String::new(), now));
+    }
+
+    // Ships that haven't acted or changed in `threshold`
+    fn stuck_ships(&self, threshold: Duration, now: DateTime<Utc>) -> Vec<(String, String, i64)> {
+        let ship_activity = self.ship_activity.lock().unwrap();
+        ship_activity
+            .iter()
+            .filter(|(_, (_, last))| now - *last > threshold)
+            .map(|(ship, (desc, last))| (ship.clone(), desc.clone(), (now - *last).num_minutes()))
+            .collect()
+    }
+
+    pub fn check_stuck_ships(&self) {
+        let threshold = Duration::try_minutes(CONFIG.alert_ship_stuck_mins).unwrap();
+        for (ship, desc, minutes) in self.stuck_ships(threshold, Utc::now()) {
+            self.notify(
+                &format!("ship_stuck/{}", ship),
+                &format!(
+                    "Ship {} hasn't acted for {} minutes, in state '{}'",
+                    ship, minutes, desc
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alerts() {
+        let alerts = Alerts::default();
+        let now = Utc::now();
+        assert!(alerts.notify_at("db_pool", "waited", now));
+        assert!(!alerts.notify_at("db_pool", "waited", now + Duration::try_minutes(5).unwrap()));
+        assert!(alerts.notify_at("api_latency", "slow", now));
+        assert!(alerts.notify_at(
+            "db_pool",
+            "waited",
+            now + Duration::try_minutes(31).unwrap()
+        ));
+        assert_eq!(
+            alerts.sent_since(now + Duration::try_minutes(1).unwrap()),
+            vec!["[db_pool] waited".to_string()]
+        );
+
+        let mut latencies = (1..=200).collect::<Vec<u128>>();
+        assert_eq!(p99(&mut latencies), 199);
+
+        let minutes = |m: i64| now + Duration::try_minutes(m).unwrap();
+        alerts.record_ship_state_at("A-1", "Mining", now);
+        alerts.record_ship_state_at("A-2", "Mining", now);
+        alerts.record_ship_state_at("A-3", "Selling", now);
+        // A-1 only changes its description, A-2 extracts, A-3 arrives at a market
+        alerts.record_ship_state_at("A-1", "Awaiting transfer", minutes(30));
+        alerts.record_ship_activity_at("A-2", minutes(50));
+        alerts.record_ship_activity_at("A-3", minutes(20));
+        let stuck = alerts.stuck_ships(Duration::try_minutes(60).unwrap(), minutes(61));
+        assert_eq!(
+            stuck,
+            vec![("A-1".to_string(), "Awaiting transfer".to_string(), 61)]
+        );
+        let stuck = alerts.stuck_ships(Duration::try_minutes(60).unwrap(), minutes(81));
+        assert_eq!(
+            stuck,
+            vec![
+                ("A-1".to_string(), "Awaiting transfer".to_string(), 81),
+                ("A-3".to_string(), "Selling".to_string(), 61)
+            ]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use super::{SystemSymbol, WaypointSymbol};
 use crate::models::SymbolNameDescr;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,11 +37,26 @@ pub struct WaypointDetailed {
     pub traits: Vec<SymbolNameDescr>,
     // pub faction: Option<Symbol>,
     pub is_under_construction: bool,
-    // orbitals
-    // modifiers
-    // chart
+    #[serde(default)]
+    pub modifiers: Vec<SymbolNameDescr>,
+    // the waypoint this one orbits, e.g. a moon or station orbiting a planet
+    #[serde(default)]
+    pub orbits: Option<WaypointSymbol>,
+    // None while uncharted
+    #[serde(default)]
+    pub chart: Option<Chart>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chart {
+    pub submitted_by: String,
+    pub submitted_on: DateTime<Utc>,
+}
+
+// Modifiers that damage ships operating at the waypoint
+pub const HAZARDOUS_MODIFIERS: &[&str] = &["UNSTABLE", "RADIATION_LEAK"];
+
 impl WaypointDetailed {
     pub fn is_uncharted(&self) -> bool {
         self.traits.iter().any(|t| t.symbol == "UNCHARTED")
@@ -60,6 +76,47 @@ impl WaypointDetailed {
     pub fn is_engineered_asteroid(&self) -> bool {
         self.waypoint_type == "ENGINEERED_ASTEROID"
     }
+    pub fn has_modifier(&self, modifier: &str) -> bool {
+        self.modifiers.iter().any(|m| m.symbol == modifier)
+    }
+    pub fn is_hazardous(&self) -> bool {
+        HAZARDOUS_MODIFIERS.iter().any(|m| self.has_modifier(m))
+    }
+    // Waypoints orbiting the same body share a root, and are zero distance apart
+    pub fn orbital_root(&self) -> &WaypointSymbol {
+        self.orbits.as_ref().unwrap_or(&self.symbol)
+    }
+    pub fn is_charted_by(&self, agent: &str) -> bool {
+        self.chart
+            .as_ref()
+            .is_some_and(|chart| chart.submitted_by == agent)
+    }
+    pub fn is_asteroid(&self) -> bool {
+        matches!(
+            self.waypoint_type.as_str(),
+            "ASTEROID" | "ENGINEERED_ASTEROID" | "ASTEROID_BASE"
+        )
+    }
+}
+
+// Waypoint as returned by a sensor scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannedWaypoint {
+    pub system_symbol: SystemSymbol,
+    pub symbol: WaypointSymbol,
+    #[serde(rename = "type")]
+    pub waypoint_type: String,
+    pub x: i64,
+    pub y: i64,
+    #[serde(default)]
+    pub traits: Vec<SymbolNameDescr>,
+}
+
+impl ScannedWaypoint {
+    pub fn is_uncharted(&self) -> bool {
+        self.traits.iter().any(|t| t.symbol == "UNCHARTED")
+    }
 }
 
 #[cfg(test)]
@@ -89,6 +146,43 @@ mod test {
             WaypointSymbol::new("X1-HN18-ZX1B")
         );
         assert_eq!(waypoints.data.len(), 10);
+        assert!(!waypoints.data[1].is_hazardous());
+    }
+
+    #[test]
+    fn test_waypoint_modifiers() {
+        let json = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-DD4X","type":"ASTEROID","x":116,"y":-713,"orbitals":[],"traits":[],"modifiers":[{"symbol":"UNSTABLE","name":"Unstable","description":"Rapidly unstable."}],"isUnderConstruction":false}"#;
+        let waypoint: WaypointDetailed = serde_json::from_str(json).unwrap();
+        assert!(waypoint.has_modifier("UNSTABLE"));
+        assert!(waypoint.is_hazardous());
+    }
+
+    #[test]
+    fn test_waypoint_orbits() {
+        let planet = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-A1","type":"PLANET","x":10,"y":20,"orbitals":[{"symbol":"X1-HN18-A2"}],"traits":[],"isUnderConstruction":false}"#;
+        let moon = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-A2","type":"MOON","x":10,"y":20,"orbitals":[],"orbits":"X1-HN18-A1","traits":[],"isUnderConstruction":false}"#;
+        let asteroid = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-B3","type":"ASTEROID","x":13,"y":24,"orbitals":[],"traits":[],"isUnderConstruction":false}"#;
+        let planet: WaypointDetailed = serde_json::from_str(planet).unwrap();
+        let moon: WaypointDetailed = serde_json::from_str(moon).unwrap();
+        let asteroid: WaypointDetailed = serde_json::from_str(asteroid).unwrap();
+        assert_eq!(moon.orbital_root(), &planet.symbol);
+        assert_eq!(planet.distance(&moon), 0);
+        assert_eq!(moon.distance(&asteroid), 5);
+    }
+
+    #[test]
+    fn test_waypoint_chart() {
+        let charted = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-A1","type":"PLANET","x":10,"y":20,"orbitals":[],"traits":[],"chart":{"waypointSymbol":"X1-HN18-A1","submittedBy":"WHYANDO","submittedOn":"2024-03-24T16:20:00.000Z"},"isUnderConstruction":false}"#;
+        let uncharted = r#"{"systemSymbol":"X1-HN18","symbol":"X1-HN18-B3","type":"ASTEROID","x":13,"y":24,"orbitals":[],"traits":[{"symbol":"UNCHARTED","name":"Uncharted","description":""}],"isUnderConstruction":false}"#;
+        let charted: WaypointDetailed = serde_json::from_str(charted).unwrap();
+        let uncharted: WaypointDetailed = serde_json::from_str(uncharted).unwrap();
+        let chart = charted.chart.as_ref().unwrap();
+        assert_eq!(chart.submitted_by, "WHYANDO");
+        assert_eq!(chart.submitted_on.to_rfc3339(), "2024-03-24T16:20:00+00:00");
+        assert!(charted.is_charted_by("WHYANDO"));
+        assert!(!charted.is_charted_by("OTHER"));
+        assert!(uncharted.chart.is_none());
+        assert!(!uncharted.is_charted_by("WHYANDO"));
     }
 
     #[test]
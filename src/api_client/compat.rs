@@ -0,0 +1,127 @@
+//!
+//! API version compatibility.
+//!
+//! Models ignore unknown fields and default non-essential ones, so additive changes to the API don't
+//! break parsing. Recorded payloads for each supported game version live in /fixtures/api/{version}.
+//!
+use crate::models::Status;
+use log::*;
+
+// Major.minor versions the models have been checked against
+pub const SUPPORTED_API_VERSIONS: &[&str] = &["v2.1", "v2.2"];
+
+pub fn is_supported_version(version: &str) -> bool {
+    SUPPORTED_API_VERSIONS
+        .iter()
+        .any(|supported| version == *supported || version.starts_with(&format!("{}.", supported)))
+}
+
+pub fn check_api_version(status: &Status) {
+    if is_supported_version(&status.version) {
+        info!("API version {}", status.version);
+    } else {
+        warn!(
+            "API version {} is not in the supported versions {:?}. Parsing may fail.",
+            status.version, SUPPORTED_API_VERSIONS
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api_client::api_models::WaypointDetailed;
+    use crate::models::*;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::Value;
+
+    // Paths of fields present in the payload that the model drops
+    fn unknown_fields(raw: &Value, parsed: &Value, path: &str, out: &mut Vec<String>) {
+        match (raw, parsed) {
+            (Value::Object(raw), Value::Object(parsed)) => {
+                for (key, raw_val) in raw {
+                    let key_path = format!("{}.{}", path, key);
+                    match parsed.get(key) {
+                        Some(parsed_val) => unknown_fields(raw_val, parsed_val, &key_path, out),
+                        None => out.push(key_path),
+                    }
+                }
+            }
+            (Value::Array(raw), Value::Array(parsed)) => {
+                for (raw_val, parsed_val) in raw.iter().zip(parsed) {
+                    unknown_fields(raw_val, parsed_val, &format!("{}[]", path), out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn parse<T: DeserializeOwned + Serialize>(json: &str) -> (T, Vec<String>) {
+        let raw: Value = serde_json::from_str(json).unwrap();
+        let parsed: T = serde_json::from_value(raw.clone()).unwrap();
+        let mut unknown = vec![];
+        unknown_fields(
+            &raw,
+            &serde_json::to_value(&parsed).unwrap(),
+            "",
+            &mut unknown,
+        );
+        unknown.sort();
+        unknown.dedup();
+        (parsed, unknown)
+    }
+
+    #[test]
+    fn test_is_supported_version() {
+        assert!(is_supported_version("v2.1.5"));
+        assert!(is_supported_version("v2.2.0"));
+        assert!(is_supported_version("v2.2"));
+        assert!(!is_supported_version("v2.20.0"));
+        assert!(!is_supported_version("v3.0.0"));
+    }
+
+    #[test]
+    fn test_compat_v2_1() {
+        let (status, _) = parse::<Status>(include_str!("../../fixtures/api/v2.1.5/status.json"));
+        assert!(is_supported_version(&status.version));
+        let (ship, unknown) = parse::<Ship>(include_str!("../../fixtures/api/v2.1.5/ship.json"));
+        assert_eq!(ship.symbol, "WHYANDO_TEST_1-1");
+        assert!(unknown.contains(&".nav.route.departure".to_string()));
+    }
+
+    #[test]
+    fn test_compat_v2_2() {
+        let (status, unknown) =
+            parse::<Status>(include_str!("../../fixtures/api/v2.2.0/status.json"));
+        assert!(is_supported_version(&status.version));
+        assert_eq!(status.stats.agents, 590);
+        assert!(unknown.contains(&".health".to_string()));
+        assert!(unknown.contains(&".stats.accounts".to_string()));
+
+        // crew was dropped from the payload
+        let (ship, unknown) = parse::<Ship>(include_str!("../../fixtures/api/v2.2.0/ship.json"));
+        assert_eq!(ship.crew.capacity, 0);
+        assert_eq!(ship.cargo.units, 12);
+        assert!(unknown.contains(&".frame.quality".to_string()));
+
+        // new activity levels parse as Unknown
+        let (market, _) = parse::<Market>(include_str!("../../fixtures/api/v2.2.0/market.json"));
+        assert_eq!(market.trade_goods.len(), 3);
+        assert_eq!(
+            market.trade_goods[1].activity,
+            Some(MarketActivity::Unknown)
+        );
+
+        let (shipyard, unknown) =
+            parse::<Shipyard>(include_str!("../../fixtures/api/v2.2.0/shipyard.json"));
+        assert_eq!(shipyard.ships[0].ship_type, "SHIP_PROBE");
+        assert!(unknown.contains(&".ships[].crew".to_string()));
+
+        let (waypoints, _) = parse::<Vec<WaypointDetailed>>(include_str!(
+            "../../fixtures/api/v2.2.0/waypoints.json"
+        ));
+        assert!(waypoints[0].is_market());
+        assert!(waypoints[1].is_uncharted());
+    }
+}
@@ -0,0 +1,889 @@
+//!
+//! Dry-run mode (DRY_RUN=1).
+//!
+//! Mutating requests are never sent. Instead they're logged and answered from local state, built up from
+//! the responses to read requests (ships, agent, contracts, markets, shipyards, constructions), which
+//! still go to the server, and the universe's waypoint positions.
+//! Transit is instant but burns fuel for the distance flown. Actions that depend on server-side randomness
+//! (survey, extract, siphon) get made up yields, with the usual cooldowns.
+//! State is kept under its own reset id (see `dry_run_reset_id`), apart from the live agent's.
+//!
+use super::api_models::ScannedWaypoint;
+use crate::models::*;
+use chrono::{Duration, Utc};
+use dashmap::{DashMap, DashSet};
+use log::*;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+const SURVEY_COOLDOWN: i64 = 70;
+const EXTRACT_COOLDOWN: i64 = 70;
+const SIPHON_COOLDOWN: i64 = 70;
+const SCAN_COOLDOWN: i64 = 60;
+const EXTRACT_UNITS: i64 = 10;
+const GAS_GOODS: [&str; 3] = ["HYDROCARBON", "LIQUID_HYDROGEN", "LIQUID_NITROGEN"];
+
+#[derive(Debug, Default)]
+pub struct DryRun {
+    ships: DashMap<String, Ship>,
+    agent: Mutex<Option<Agent>>,
+    contracts: DashMap<String, Contract>,
+    markets: DashMap<WaypointSymbol, Market>,
+    shipyards: DashMap<WaypointSymbol, Shipyard>,
+    constructions: DashMap<WaypointSymbol, Construction>,
+    // shared by the agents' dry runs
+    waypoints: Arc<DashMap<WaypointSymbol, ScannedWaypoint>>,
+    systems: Arc<DashMap<SystemSymbol, (i64, i64)>>,
+    charted: DashSet<WaypointSymbol>,
+    ships_bought: AtomicI64,
+}
+
+// Agent state of a dry run is kept apart from the live agent's, with the universe data it gathers
+pub fn dry_run_reset_id(reset_id: &str) -> String {
+    format!("{}-dry-run", reset_id)
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Dry run for another agent, sharing the universe's positions
+    pub fn agent_dry_run(&self) -> Self {
+        Self {
+            waypoints: self.waypoints.clone(),
+            systems: self.systems.clone(),
+            ..Self::default()
+        }
+    }
+
+    // Waypoint and system positions, to work out the fuel for simulated flights
+    pub fn observe_system(&self, system: &System) {
+        self.systems
+            .insert(system.symbol.clone(), (system.x, system.y));
+        for waypoint in &system.waypoints {
+            self.waypoints.insert(
+                waypoint.symbol.clone(),
+                ScannedWaypoint {
+                    system_symbol: system.symbol.clone(),
+                    symbol: waypoint.symbol.clone(),
+                    waypoint_type: waypoint.waypoint_type.clone(),
+                    x: waypoint.x,
+                    y: waypoint.y,
+                    traits: vec![],
+                },
+            );
+        }
+    }
+
+    // Record the response to a read request
+    pub fn observe(&self, path: &str, response: &Value) {
+        let path = path.split('?').next().unwrap();
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let data = &response["data"];
+        match segments.as_slice() {
+            ["my", "agent"] => {
+                if let Ok(agent) = serde_json::from_value(data.clone()) {
+                    *self.agent.lock().unwrap() = Some(agent);
+                }
+            }
+            ["my", "ships"] => {
+                if let Ok(ships) = serde_json::from_value::<Vec<Ship>>(data.clone()) {
+                    for ship in ships {
+                        self.ships.insert(ship.symbol.clone(), ship);
+                    }
+                }
+            }
+            ["my", "ships", _] => {
+                if let Ok(ship) = serde_json::from_value::<Ship>(data.clone()) {
+                    self.ships.insert(ship.symbol.clone(), ship);
+                }
+            }
+            ["systems", _, "waypoints", _, "market"] => {
+                if let Ok(market) = serde_json::from_value::<Market>(data.clone()) {
+                    self.markets.insert(market.symbol.clone(), market);
+                }
+            }
+            ["systems", _, "waypoints", _, "shipyard"] => {
+                if let Ok(shipyard) = serde_json::from_value::<Shipyard>(data.clone()) {
+                    self.shipyards.insert(shipyard.symbol.clone(), shipyard);
+                }
+            }
+            ["systems", _, "waypoints", _, "construction"] => {
+                if let Ok(construction) = serde_json::from_value::<Construction>(data.clone()) {
+                    self.constructions
+                        .insert(construction.symbol.clone(), construction);
+                }
+            }
+            ["my", "contracts"] => {
+                if let Ok(contracts) = serde_json::from_value::<Vec<Contract>>(data.clone()) {
+                    for contract in contracts {
+                        self.contracts.insert(contract.id.clone(), contract);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Answer a mutating request from local state. Err contains an error response body.
+    pub fn simulate(&self, method: &str, path: &str, body: &Value) -> Result<Value, String> {
+        info!("DRY RUN {} {} {}", method, path, body);
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let data = match segments.as_slice() {
+            ["register"] => self.register(body)?,
+            ["my", "ships"] => self.buy_ship(body)?,
+            ["my", "ships", ship, action] => self.ship_action(ship, action, body)?,
+            ["my", "ships", ship, "extract", "survey"] => self.extract_survey(ship, body)?,
+            ["my", "ships", ship, "scan", "waypoints"] => self.scan_waypoints(ship)?,
+            ["my", "contracts", id, action] => self.contract_action(id, action, body)?,
+            ["systems", _, "waypoints", waypoint, "construction", "supply"] => {
+                self.supply_construction(waypoint, body)?
+            }
+            _ => return Err(error_body(&format!("{} {} is not simulated", method, path))),
+        };
+        Ok(json!({ "data": data }))
+    }
+
+    fn ship_action(&self, ship_symbol: &str, action: &str, body: &Value) -> Result<Value, String> {
+        let mut ship = match self.ships.get_mut(ship_symbol) {
+            Some(ship) => ship,
+            None => return Err(error_body(&format!("Ship {} not known", ship_symbol))),
+        };
+        let now = Utc::now();
+        let data = match action {
+            "orbit" => {
+                ship.nav.status = ShipNavStatus::InOrbit;
+                json!({ "nav": ship.nav })
+            }
+            "dock" => {
+                ship.nav.status = ShipNavStatus::Docked;
+                json!({ "nav": ship.nav })
+            }
+            // PATCH flight mode
+            "nav" => {
+                ship.nav.flight_mode = serde_json::from_value(body["flightMode"].clone()).unwrap();
+                json!(ship.nav)
+            }
+            "navigate" | "warp" | "jump" => {
+                let target = WaypointSymbol::new(body["waypointSymbol"].as_str().unwrap());
+                // jumps cost antimatter rather than fuel
+                let fuel = match action {
+                    "jump" => 0,
+                    _ => self.fuel_cost(&ship, &target)?,
+                };
+                if fuel > ship.fuel.current {
+                    return Err(json!({ "error": {
+                        "message": format!("Dry run: {} requires {} more fuel for navigation", ship_symbol, fuel - ship.fuel.current),
+                        "code": 4203,
+                    }})
+                    .to_string());
+                }
+                ship.fuel.current -= fuel;
+                ship.fuel.consumed = ShipFuelConsumed {
+                    amount: fuel,
+                    timestamp: now,
+                };
+                let mut destination = ship.nav.route.destination.clone();
+                destination.symbol = target.clone();
+                destination.system_symbol = target.system();
+                if let Some(waypoint) = self.waypoints.get(&target) {
+                    destination.waypoint_type = waypoint.waypoint_type.clone();
+                    destination.x = waypoint.x;
+                    destination.y = waypoint.y;
+                }
+                ship.nav.route.origin = ship.nav.route.destination.clone();
+                ship.nav.route.destination = destination;
+                ship.nav.route.departure_time = now;
+                ship.nav.route.arrival = now;
+                ship.nav.system_symbol = target.system();
+                ship.nav.waypoint_symbol = target;
+                ship.nav.status = ShipNavStatus::InOrbit;
+                let agent = self.agent()?;
+                json!({
+                    "nav": ship.nav,
+                    "fuel": ship.fuel,
+                    "cooldown": ship.cooldown,
+                    "events": [],
+                    "agent": agent,
+                    "transaction": transaction(&ship, "FUEL", "PURCHASE", 0, 0),
+                })
+            }
+            "purchase" | "sell" => {
+                let good = body["symbol"].as_str().unwrap();
+                let units = body["units"].as_i64().unwrap();
+                let market = self.markets.get(&ship.nav.waypoint_symbol);
+                let trade = market
+                    .as_ref()
+                    .and_then(|m| m.trade_goods.iter().find(|g| g.symbol == good));
+                let Some(trade) = trade else {
+                    return Err(error_body(&format!("No known price for {}", good)));
+                };
+                let (price, delta, _type) = match action {
+                    "purchase" => (trade.purchase_price, units, "PURCHASE"),
+                    _ => (trade.sell_price, -units, "SELL"),
+                };
+                add_cargo(&mut ship.cargo, good, delta)?;
+                let agent = self.add_credits(-delta * price)?;
+                json!({
+                    "cargo": ship.cargo,
+                    "agent": agent,
+                    "transaction": transaction(&ship, good, _type, units, price),
+                })
+            }
+            "jettison" => {
+                let good = body["symbol"].as_str().unwrap();
+                let units = body["units"].as_i64().unwrap();
+                add_cargo(&mut ship.cargo, good, -units)?;
+                json!({ "cargo": ship.cargo })
+            }
+            "refuel" => {
+                let units = body["units"].as_i64().unwrap();
+                let from_cargo = body["fromCargo"].as_bool().unwrap_or(false);
+                let market_units = (units + 99) / 100;
+                let cost = match from_cargo {
+                    true => 0,
+                    false => {
+                        let market = self.markets.get(&ship.nav.waypoint_symbol);
+                        let price = market
+                            .as_ref()
+                            .and_then(|m| m.trade_goods.iter().find(|g| g.symbol == "FUEL"))
+                            .map(|g| g.purchase_price)
+                            .unwrap_or(0);
+                        market_units * price
+                    }
+                };
+                if from_cargo {
+                    add_cargo(&mut ship.cargo, "FUEL", -market_units)?;
+                }
+                ship.fuel.current = (ship.fuel.current + units).min(ship.fuel.capacity);
+                let agent = self.add_credits(-cost)?;
+                json!({ "fuel": ship.fuel, "agent": agent, "cargo": ship.cargo })
+            }
+            "survey" => {
+                let Some(mount) = ship
+                    .mounts
+                    .iter()
+                    .find(|m| m.symbol.starts_with("MOUNT_SURVEYOR"))
+                else {
+                    return Err(error_body(&format!("{} has no surveyor", ship_symbol)));
+                };
+                let deposits = survey_deposits(&mount.symbol);
+                let count = mount.strength.unwrap_or(1).max(1);
+                set_cooldown(&mut ship, SURVEY_COOLDOWN);
+                let surveys = (0..count)
+                    .map(|_| Survey {
+                        signature: format!(
+                            "{}-DRY{:06X}",
+                            ship.nav.waypoint_symbol,
+                            rand::random::<u32>() & 0xFFFFFF
+                        ),
+                        symbol: ship.nav.waypoint_symbol.clone(),
+                        deposits: deposits
+                            .iter()
+                            .map(|good| Symbol {
+                                symbol: good.to_string(),
+                            })
+                            .collect(),
+                        expiration: now + Duration::try_minutes(15).unwrap(),
+                        size: "SMALL".to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "cooldown": ship.cooldown, "surveys": surveys })
+            }
+            "siphon" => {
+                use rand::seq::SliceRandom as _;
+                let good = GAS_GOODS.choose(&mut rand::thread_rng()).unwrap();
+                let units = EXTRACT_UNITS.min(ship.cargo.capacity - ship.cargo.units);
+                if units == 0 {
+                    return Err(cargo_full_body());
+                }
+                add_cargo(&mut ship.cargo, good, units)?;
+                set_cooldown(&mut ship, SIPHON_COOLDOWN);
+                json!({
+                    "siphon": { "shipSymbol": ship_symbol, "yield": { "symbol": good, "units": units } },
+                    "cooldown": ship.cooldown,
+                    "cargo": ship.cargo,
+                    "events": [],
+                })
+            }
+            "chart" => {
+                let waypoint = ship.nav.waypoint_symbol.clone();
+                if !self.charted.insert(waypoint.clone()) {
+                    return Err(json!({ "error": {
+                        "message": format!("Dry run: Waypoint already charted: {}", waypoint),
+                        "code": 4230,
+                    }})
+                    .to_string());
+                }
+                let agent = self.agent()?;
+                json!({
+                    "chart": { "waypointSymbol": waypoint, "submittedBy": agent.symbol, "submittedOn": now },
+                    "agent": agent,
+                })
+            }
+            "scrap" => {
+                let price = self
+                    .shipyards
+                    .get(&ship.nav.waypoint_symbol)
+                    .and_then(|shipyard| {
+                        shipyard
+                            .ships
+                            .iter()
+                            .find(|s| s.frame.symbol == ship.frame.symbol)
+                            .map(|s| s.purchase_price / 2)
+                    })
+                    .unwrap_or(0);
+                let transaction = ScrapTransaction {
+                    waypoint_symbol: ship.nav.waypoint_symbol.clone(),
+                    ship_symbol: ship.symbol.clone(),
+                    total_price: price,
+                    timestamp: now,
+                };
+                drop(ship);
+                self.ships.remove(ship_symbol);
+                let agent = self.add_credits(price)?;
+                json!({ "agent": agent, "transaction": transaction })
+            }
+            "transfer" => {
+                let good = body["tradeSymbol"].as_str().unwrap();
+                let units = body["units"].as_i64().unwrap();
+                let dest_symbol = body["shipSymbol"].as_str().unwrap();
+                add_cargo(&mut ship.cargo, good, -units)?;
+                let cargo = ship.cargo.clone();
+                drop(ship);
+                if let Some(mut dest) = self.ships.get_mut(dest_symbol) {
+                    add_cargo(&mut dest.cargo, good, units)?;
+                }
+                json!({ "cargo": cargo })
+            }
+            _ => {
+                return Err(error_body(&format!(
+                    "Ship action {} is not simulated",
+                    action
+                )))
+            }
+        };
+        Ok(data)
+    }
+
+    fn extract_survey(&self, ship_symbol: &str, survey: &Value) -> Result<Value, String> {
+        let Some(mut ship) = self.ships.get_mut(ship_symbol) else {
+            return Err(error_body(&format!("Ship {} not known", ship_symbol)));
+        };
+        let survey: Survey = serde_json::from_value(survey.clone())
+            .map_err(|e| error_body(&format!("Invalid survey: {}", e)))?;
+        if survey.expiration < Utc::now() {
+            return Err(json!({ "error": {
+                "message": "Dry run: Target signature is no longer in range or valid",
+                "code": 4221,
+            }})
+            .to_string());
+        }
+        use rand::seq::SliceRandom as _;
+        let Some(good) = survey.deposits.choose(&mut rand::thread_rng()) else {
+            return Err(error_body("Survey has no deposits"));
+        };
+        let units = EXTRACT_UNITS.min(ship.cargo.capacity - ship.cargo.units);
+        if units == 0 {
+            return Err(cargo_full_body());
+        }
+        add_cargo(&mut ship.cargo, &good.symbol, units)?;
+        set_cooldown(&mut ship, EXTRACT_COOLDOWN);
+        Ok(json!({
+            "extraction": { "shipSymbol": ship_symbol, "yield": { "symbol": good.symbol, "units": units } },
+            "cooldown": ship.cooldown,
+            "cargo": ship.cargo,
+            "events": [],
+        }))
+    }
+
+    // The known waypoints in the ship's system, without traits
+    fn scan_waypoints(&self, ship_symbol: &str) -> Result<Value, String> {
+        let Some(mut ship) = self.ships.get_mut(ship_symbol) else {
+            return Err(error_body(&format!("Ship {} not known", ship_symbol)));
+        };
+        let waypoints = self
+            .waypoints
+            .iter()
+            .filter(|w| w.system_symbol == ship.nav.system_symbol)
+            .map(|w| w.value().clone())
+            .collect::<Vec<_>>();
+        set_cooldown(&mut ship, SCAN_COOLDOWN);
+        Ok(json!({ "cooldown": ship.cooldown, "waypoints": waypoints }))
+    }
+
+    fn supply_construction(&self, waypoint: &str, body: &Value) -> Result<Value, String> {
+        let waypoint = WaypointSymbol::new(waypoint);
+        let ship_symbol = body["shipSymbol"].as_str().unwrap();
+        let good = body["tradeSymbol"].as_str().unwrap();
+        let units = body["units"].as_i64().unwrap();
+        let Some(mut construction) = self.constructions.get_mut(&waypoint) else {
+            return Err(error_body(&format!(
+                "Construction at {} not known",
+                waypoint
+            )));
+        };
+        let Some(material) = construction
+            .materials
+            .iter_mut()
+            .find(|m| m.trade_symbol == good)
+        else {
+            return Err(error_body(&format!("{} does not need {}", waypoint, good)));
+        };
+        let Some(mut ship) = self.ships.get_mut(ship_symbol) else {
+            return Err(error_body(&format!("Ship {} not known", ship_symbol)));
+        };
+        add_cargo(&mut ship.cargo, good, -units)?;
+        material.fulfilled = (material.fulfilled + units).min(material.required);
+        construction.is_complete = construction
+            .materials
+            .iter()
+            .all(|m| m.fulfilled >= m.required);
+        Ok(json!({ "construction": *construction, "cargo": ship.cargo }))
+    }
+
+    fn contract_action(&self, id: &str, action: &str, body: &Value) -> Result<Value, String> {
+        let Some(mut contract) = self.contracts.get_mut(id) else {
+            return Err(error_body(&format!("Contract {} not known", id)));
+        };
+        let data = match action {
+            "accept" => {
+                contract.accepted = true;
+                let agent = self.add_credits(contract.terms.payment.on_accepted)?;
+                json!({ "contract": *contract, "agent": agent })
+            }
+            "deliver" => {
+                let ship_symbol = body["shipSymbol"].as_str().unwrap();
+                let good = body["tradeSymbol"].as_str().unwrap();
+                let units = body["units"].as_i64().unwrap();
+                let Some(deliver) = contract
+                    .terms
+                    .deliver
+                    .iter_mut()
+                    .find(|d| d.trade_symbol == good)
+                else {
+                    return Err(error_body(&format!(
+                        "Contract {} does not need {}",
+                        id, good
+                    )));
+                };
+                let Some(mut ship) = self.ships.get_mut(ship_symbol) else {
+                    return Err(error_body(&format!("Ship {} not known", ship_symbol)));
+                };
+                add_cargo(&mut ship.cargo, good, -units)?;
+                deliver.units_fulfilled =
+                    (deliver.units_fulfilled + units).min(deliver.units_required);
+                json!({ "contract": *contract, "cargo": ship.cargo })
+            }
+            "fulfill" => {
+                if contract
+                    .terms
+                    .deliver
+                    .iter()
+                    .any(|d| d.units_fulfilled < d.units_required)
+                {
+                    return Err(error_body(&format!("Contract {} is not complete", id)));
+                }
+                contract.fulfilled = true;
+                let agent = self.add_credits(contract.terms.payment.on_fulfilled)?;
+                json!({ "contract": *contract, "agent": agent })
+            }
+            _ => {
+                return Err(error_body(&format!(
+                    "Contract action {} is not simulated",
+                    action
+                )))
+            }
+        };
+        Ok(data)
+    }
+
+    // A made up agent with the starting command ship. The token is not valid on the server
+    fn register(&self, body: &Value) -> Result<Value, String> {
+        let symbol = body["symbol"].as_str().unwrap().to_uppercase();
+        let faction = body["faction"].as_str().unwrap();
+        let mut ship: Ship =
+            serde_json::from_str(include_str!("../../fixtures/api/v2.2.0/ship.json")).unwrap();
+        ship.symbol = format!("{}-1", symbol);
+        ship.registration.name = ship.symbol.clone();
+        ship.registration.faction_symbol = faction.to_string();
+        ship.cooldown.ship_symbol = ship.symbol.clone();
+        ship.cargo.units = 0;
+        ship.cargo.inventory = vec![];
+        let agent = Agent {
+            account_id: None,
+            symbol: symbol.clone(),
+            headquarters: ship.nav.waypoint_symbol.clone(),
+            credits: 175_000,
+            starting_faction: faction.to_string(),
+            ship_count: 1,
+        };
+        let now = Utc::now();
+        let contract = json!({
+            "id": format!("dry-run-{}", symbol.to_lowercase()),
+            "factionSymbol": faction,
+            "type": "PROCUREMENT",
+            "terms": {
+                "deadline": (now + Duration::try_days(7).unwrap()).to_rfc3339(),
+                "payment": { "onAccepted": 0, "onFulfilled": 0 },
+                "deliver": [],
+            },
+            "accepted": false,
+            "fulfilled": false,
+            "expiration": now + Duration::try_days(1).unwrap(),
+            "deadlineToAccept": now + Duration::try_days(1).unwrap(),
+        });
+        let faction = json!({
+            "symbol": faction,
+            "name": faction,
+            "description": "",
+            "headquarters": "",
+            "traits": [],
+            "isRecruiting": true,
+        });
+        *self.agent.lock().unwrap() = Some(agent.clone());
+        self.ships.insert(ship.symbol.clone(), ship.clone());
+        Ok(json!({
+            "token": format!("DRY-RUN-{}", symbol),
+            "agent": agent,
+            "contract": contract,
+            "faction": faction,
+            "ship": ship,
+        }))
+    }
+
+    // Fuel burnt flying to the target, as the server works it out from the distance and flight mode
+    fn fuel_cost(&self, ship: &Ship, target: &WaypointSymbol) -> Result<i64, String> {
+        if ship.fuel.capacity == 0 {
+            return Ok(0);
+        }
+        let distance = match target.system() == ship.nav.system_symbol {
+            true => {
+                let position = |symbol: &WaypointSymbol| {
+                    self.waypoints
+                        .get(symbol)
+                        .map(|w| (w.x, w.y))
+                        .ok_or_else(|| error_body(&format!("Position of {} not known", symbol)))
+                };
+                distance(position(&ship.nav.waypoint_symbol)?, position(target)?)
+            }
+            false => {
+                let position = |symbol: &SystemSymbol| {
+                    self.systems
+                        .get(symbol)
+                        .map(|s| *s)
+                        .ok_or_else(|| error_body(&format!("Position of {} not known", symbol)))
+                };
+                distance(
+                    position(&ship.nav.system_symbol)?,
+                    position(&target.system())?,
+                )
+            }
+        };
+        Ok(match ship.nav.flight_mode {
+            ShipFlightMode::Cruise | ShipFlightMode::Stealth => distance.max(1),
+            ShipFlightMode::Burn => 2 * distance.max(1),
+            ShipFlightMode::Drift => 1,
+        })
+    }
+
+    fn buy_ship(&self, body: &Value) -> Result<Value, String> {
+        let ship_type = body["shipType"].as_str().unwrap();
+        let waypoint = WaypointSymbol::new(body["waypointSymbol"].as_str().unwrap());
+        let listing = self.shipyards.get(&waypoint).and_then(|shipyard| {
+            shipyard
+                .ships
+                .iter()
+                .find(|s| s.ship_type == ship_type)
+                .cloned()
+        });
+        let Some(listing) = listing else {
+            return Err(error_body(&format!(
+                "No known {} listing at {}",
+                ship_type, waypoint
+            )));
+        };
+        let agent = self.add_credits(-listing.purchase_price)?;
+        let n = self.ships_bought.fetch_add(1, Ordering::SeqCst) + 1;
+        let symbol = format!("{}-DRY{:X}", agent.symbol, n);
+
+        let now = Utc::now();
+        let route_waypoint = ShipNavRouteWaypoint {
+            symbol: waypoint.clone(),
+            waypoint_type: "".to_string(),
+            system_symbol: waypoint.system(),
+            x: 0,
+            y: 0,
+        };
+        let cargo_capacity = ShipModel::from_listing(&listing).cargo_capacity;
+        let ship = Ship {
+            symbol: symbol.clone(),
+            nav: ShipNav {
+                system_symbol: waypoint.system(),
+                waypoint_symbol: waypoint.clone(),
+                route: ShipNavRoute {
+                    origin: route_waypoint.clone(),
+                    destination: route_waypoint,
+                    arrival: now,
+                    departure_time: now,
+                },
+                status: ShipNavStatus::Docked,
+                flight_mode: ShipFlightMode::Cruise,
+            },
+            crew: ShipCrew::default(),
+            fuel: ShipFuel {
+                current: listing.frame.fuel_capacity,
+                capacity: listing.frame.fuel_capacity,
+                consumed: ShipFuelConsumed {
+                    amount: 0,
+                    timestamp: now,
+                },
+            },
+            cooldown: ShipCooldown {
+                ship_symbol: symbol.clone(),
+                total_seconds: 0,
+                remaining_seconds: 0,
+                expiration: None,
+            },
+            frame: listing.frame,
+            reactor: listing.reactor,
+            engine: listing.engine,
+            modules: listing.modules,
+            mounts: listing.mounts,
+            registration: ShipRegistration {
+                name: symbol.clone(),
+                faction_symbol: agent.starting_faction.clone(),
+                role: "".to_string(),
+            },
+            cargo: ShipCargo {
+                capacity: cargo_capacity,
+                units: 0,
+                inventory: vec![],
+            },
+        };
+        self.ships.insert(symbol, ship.clone());
+        Ok(json!({ "agent": agent, "ship": ship }))
+    }
+
+    fn agent(&self) -> Result<Agent, String> {
+        self.add_credits(0)
+    }
+
+    fn add_credits(&self, delta: i64) -> Result<Agent, String> {
+        let mut agent = self.agent.lock().unwrap();
+        let Some(agent) = agent.as_mut() else {
+            return Err(error_body("Agent not known"));
+        };
+        if agent.credits + delta < 0 {
+            return Err(
+                json!({ "error": { "message": "Dry run: Insufficient credits", "code": 4600 } })
+                    .to_string(),
+            );
+        }
+        agent.credits += delta;
+        Ok(agent.clone())
+    }
+}
+
+fn add_cargo(cargo: &mut ShipCargo, good: &str, units: i64) -> Result<(), String> {
+    if cargo.units + units > cargo.capacity {
+        return Err(error_body("Insufficient cargo space"));
+    }
+    match cargo.inventory.iter_mut().find(|i| i.symbol == good) {
+        Some(item) if item.units + units >= 0 => item.units += units,
+        None if units >= 0 => cargo.inventory.push(ShipCargoItem {
+            symbol: good.to_string(),
+            name: good.to_string(),
+            description: "".to_string(),
+            units,
+        }),
+        _ => return Err(error_body(&format!("Insufficient {} in cargo", good))),
+    }
+    cargo.inventory.retain(|i| i.units > 0);
+    cargo.units += units;
+    Ok(())
+}
+
+fn transaction(ship: &Ship, good: &str, _type: &str, units: i64, price: i64) -> MarketTransaction {
+    MarketTransaction {
+        waypoint_symbol: ship.nav.waypoint_symbol.clone(),
+        ship_symbol: ship.symbol.clone(),
+        trade_symbol: good.to_string(),
+        _type: _type.to_string(),
+        units,
+        price_per_unit: price,
+        total_price: units * price,
+        timestamp: Utc::now(),
+    }
+}
+
+fn distance(a: (i64, i64), b: (i64, i64)) -> i64 {
+    let (dx, dy) = ((a.0 - b.0) as f64, (a.1 - b.1) as f64);
+    (dx * dx + dy * dy).sqrt().round() as i64
+}
+
+fn set_cooldown(ship: &mut Ship, seconds: i64) {
+    ship.cooldown = ShipCooldown {
+        ship_symbol: ship.symbol.clone(),
+        total_seconds: seconds,
+        remaining_seconds: seconds,
+        expiration: Some(Utc::now() + Duration::try_seconds(seconds).unwrap()),
+    };
+}
+
+// What a surveyor finds, by mount
+fn survey_deposits(mount: &str) -> &'static [&'static str] {
+    match mount {
+        "MOUNT_SURVEYOR_I" => &["QUARTZ_SAND", "SILICON_CRYSTALS", "ICE_WATER", "IRON_ORE"],
+        _ => &[
+            "QUARTZ_SAND",
+            "SILICON_CRYSTALS",
+            "ICE_WATER",
+            "IRON_ORE",
+            "COPPER_ORE",
+            "ALUMINUM_ORE",
+        ],
+    }
+}
+
+fn cargo_full_body() -> String {
+    json!({ "error": { "message": "Dry run: Cargo full", "code": 4228 } }).to_string()
+}
+
+fn error_body(message: &str) -> String {
+    json!({ "error": { "message": format!("Dry run: {}", message), "code": 0 } }).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_dry_run_trade() {
+        let dry_run = DryRun::new();
+        let waypoint = WaypointSymbol::new("X1-AB12-A1");
+        let ship = test_fixtures::probe("AGENT-1", &waypoint);
+        dry_run.observe("/my/ships", &json!({ "data": [ship] }));
+        dry_run.observe(
+            "/my/agent",
+            &json!({ "data": {
+                "symbol": "AGENT", "headquarters": "X1-AB12-A1", "credits": 1000,
+                "startingFaction": "COSMIC", "shipCount": 1
+            }}),
+        );
+
+        let response = dry_run
+            .simulate(
+                "POST",
+                "/my/ships/AGENT-1/navigate",
+                &json!({ "waypointSymbol": "X1-AB12-B2" }),
+            )
+            .unwrap();
+        assert_eq!(response["data"]["nav"]["waypointSymbol"], "X1-AB12-B2");
+
+        // no market seen at the new location
+        let err = dry_run
+            .simulate(
+                "POST",
+                "/my/ships/AGENT-1/sell",
+                &json!({ "symbol": "FUEL", "units": 1 }),
+            )
+            .unwrap_err();
+        assert!(err.contains("No known price"));
+        assert!(dry_run
+            .simulate("POST", "/my/ships/AGENT-1/survey", &json!({}))
+            .is_err());
+    }
+
+    #[test]
+    fn test_dry_run_navigate_fuel() {
+        let dry_run = DryRun::new();
+        let waypoint = WaypointSymbol::new("X1-AB12-A1");
+        let mut ship = test_fixtures::probe("AGENT-1", &waypoint);
+        ship.fuel = ShipFuel {
+            current: 100,
+            capacity: 100,
+            consumed: ShipFuelConsumed {
+                amount: 0,
+                timestamp: Utc::now(),
+            },
+        };
+        dry_run.observe("/my/ships", &json!({ "data": [ship] }));
+        dry_run.observe(
+            "/my/agent",
+            &json!({ "data": {
+                "symbol": "AGENT", "headquarters": "X1-AB12-A1", "credits": 1000,
+                "startingFaction": "COSMIC", "shipCount": 1
+            }}),
+        );
+        let position = |symbol: &str, x, y| Waypoint {
+            id: 0,
+            symbol: WaypointSymbol::new(symbol),
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y,
+            details: None,
+        };
+        dry_run.observe_system(&System {
+            symbol: waypoint.system(),
+            system_type: "RED_STAR".to_string(),
+            x: 0,
+            y: 0,
+            waypoints: vec![position("X1-AB12-A1", 0, 0), position("X1-AB12-B2", 30, 40)],
+        });
+
+        let navigate = |target: &str| {
+            dry_run.simulate(
+                "POST",
+                "/my/ships/AGENT-1/navigate",
+                &json!({ "waypointSymbol": target }),
+            )
+        };
+        let response = navigate("X1-AB12-B2").unwrap();
+        assert_eq!(response["data"]["fuel"]["current"], 50);
+        assert_eq!(response["data"]["fuel"]["consumed"]["amount"], 50);
+        assert_eq!(response["data"]["nav"]["route"]["destination"]["x"], 30);
+        navigate("X1-AB12-A1").unwrap();
+        // out of fuel
+        let err = navigate("X1-AB12-B2").unwrap_err();
+        assert!(err.contains("4203"));
+        // no position known
+        assert!(navigate("X1-AB12-C3").is_err());
+    }
+
+    #[test]
+    fn test_dry_run_mining() {
+        let dry_run = DryRun::new();
+        let waypoint = WaypointSymbol::new("X1-AB12-A1");
+        let mut ship: Ship =
+            serde_json::from_str(include_str!("../../fixtures/api/v2.2.0/ship.json")).unwrap();
+        ship.symbol = "AGENT-1".to_string();
+        ship.nav.waypoint_symbol = waypoint.clone();
+        ship.cargo.units = 0;
+        ship.cargo.inventory = vec![];
+        dry_run.observe("/my/ships", &json!({ "data": [ship] }));
+
+        let response = dry_run
+            .simulate("POST", "/my/ships/AGENT-1/survey", &json!({}))
+            .unwrap();
+        assert!(
+            response["data"]["cooldown"]["totalSeconds"]
+                .as_i64()
+                .unwrap()
+                > 0
+        );
+        let survey = response["data"]["surveys"][0].clone();
+        assert_eq!(survey["symbol"], "X1-AB12-A1");
+
+        let response = dry_run
+            .simulate("POST", "/my/ships/AGENT-1/extract/survey", &survey)
+            .unwrap();
+        let good = response["data"]["extraction"]["yield"]["symbol"].clone();
+        let deposits = survey["deposits"].as_array().unwrap();
+        assert!(deposits.iter().any(|d| d["symbol"] == good));
+        assert_eq!(response["data"]["cargo"]["units"], EXTRACT_UNITS);
+    }
+}
@@ -0,0 +1,132 @@
+/// Failed API requests, returned once any retries have run out
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ApiError {
+    // the server answered with an error status
+    Status {
+        method: Method,
+        path: String,
+        status: StatusCode,
+        body: String,
+    },
+    // no response, e.g. the connection failed or timed out
+    Send {
+        method: Method,
+        path: String,
+        error: String,
+    },
+    // a successful response that isn't what we expected
+    Parse {
+        method: Method,
+        path: String,
+        error: String,
+    },
+}
+
+impl ApiError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ApiError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> Option<&str> {
+        match self {
+            ApiError::Status { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+
+    // Game error code of an error response, e.g. 4600 for insufficient credits
+    pub fn code(&self) -> Option<i64> {
+        let body: Value = serde_json::from_str(self.body()?).ok()?;
+        body["error"]["code"].as_i64()
+    }
+
+    // A rejected request, e.g. a game rule, rather than a server or network failure
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self.status(),
+            Some(StatusCode::BAD_REQUEST | StatusCode::CONFLICT)
+        )
+    }
+
+    // The server is down for maintenance, usually around a reset
+    pub fn is_maintenance(&self) -> bool {
+        self.status() == Some(StatusCode::SERVICE_UNAVAILABLE)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Status {
+                method,
+                path,
+                status,
+                body,
+            } => write!(
+                f,
+                "Request failed: {} {} {}\nbody: {}",
+                status.as_u16(),
+                method,
+                path,
+                body
+            ),
+            ApiError::Send {
+                method,
+                path,
+                error,
+            } => write!(f, "Failed to send request {} {}: {}", method, path, error),
+            ApiError::Parse {
+                method,
+                path,
+                error,
+            } => write!(f, "Failed to parse response {} {}: {}", method, path, error),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_api_error() {
+        let error = ApiError::Status {
+            method: Method::POST,
+            path: "/my/ships/A-1/purchase".to_string(),
+            status: StatusCode::BAD_REQUEST,
+            body: r#"{"error":{"message":"Insufficient funds","code":4600}}"#.to_string(),
+        };
+        assert_eq!(error.code(), Some(4600));
+        assert!(error.is_rejected());
+        assert!(!error.is_maintenance());
+        assert!(error
+            .to_string()
+            .starts_with("Request failed: 400 POST /my/ships/A-1/purchase"));
+
+        let maintenance = ApiError::Status {
+            method: Method::GET,
+            path: "/".to_string(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: "maintenance".to_string(),
+        };
+        assert_eq!(maintenance.code(), None);
+        assert!(maintenance.is_maintenance());
+
+        let timeout = ApiError::Send {
+            method: Method::GET,
+            path: "/my/ships".to_string(),
+            error: "timed out".to_string(),
+        };
+        assert_eq!(timeout.status(), None);
+        assert!(!timeout.is_rejected());
+    }
+}
@@ -0,0 +1,122 @@
+/// Share of failed API requests over a sliding window. While the error rate is over budget the
+/// agent is degraded: low-value activities (probe refreshes, speculative trades) pause and only the
+/// core loops keep using the rate limit, until the error rate falls back under half the budget
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+pub const ERROR_WINDOW_MINS: i64 = 5;
+// too few requests in the window to judge the error rate
+pub const MIN_REQUESTS: usize = 20;
+
+#[derive(Debug)]
+pub struct ErrorBudget {
+    // fraction of requests allowed to fail, 0 never degrades
+    budget: f64,
+    // (time, failed) of each request in the window
+    requests: Mutex<VecDeque<(DateTime<Utc>, bool)>>,
+    degraded: watch::Sender<bool>,
+}
+
+impl ErrorBudget {
+    pub fn new(budget: f64) -> Self {
+        Self {
+            budget,
+            requests: Mutex::new(VecDeque::new()),
+            degraded: watch::Sender::new(false),
+        }
+    }
+
+    pub fn degraded(&self) -> bool {
+        *self.degraded.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.degraded.subscribe()
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        let requests = self.requests.lock().unwrap();
+        match requests.len() {
+            0 => 0.0,
+            n => requests.iter().filter(|(_, failed)| *failed).count() as f64 / n as f64,
+        }
+    }
+
+    // Returns the new state if this request entered or left degradation mode
+    pub fn record(&self, failed: bool, now: DateTime<Utc>) -> Option<bool> {
+        if self.budget <= 0.0 {
+            return None;
+        }
+        let (count, failures) = {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push_back((now, failed));
+            let cutoff = now - Duration::try_minutes(ERROR_WINDOW_MINS).unwrap();
+            while matches!(requests.front(), Some((ts, _)) if *ts < cutoff) {
+                requests.pop_front();
+            }
+            let failures = requests.iter().filter(|(_, failed)| *failed).count();
+            (requests.len(), failures)
+        };
+        if count < MIN_REQUESTS {
+            return None;
+        }
+        let error_rate = failures as f64 / count as f64;
+        let degraded = match self.degraded() {
+            false => error_rate > self.budget,
+            true => error_rate >= self.budget / 2.0,
+        };
+        match self.degraded.send_if_modified(|prev| {
+            let changed = *prev != degraded;
+            *prev = degraded;
+            changed
+        }) {
+            true => Some(degraded),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_budget() {
+        let budget = ErrorBudget::new(0.2);
+        let start = Utc::now();
+        let at = |secs: i64| start + Duration::try_seconds(secs).unwrap();
+
+        // a burst of failures before the window has enough requests doesn't count yet
+        for i in 0..10 {
+            assert_eq!(budget.record(true, at(i)), None);
+        }
+        for i in 10..19 {
+            assert_eq!(budget.record(false, at(i)), None);
+        }
+        assert_eq!(budget.record(false, at(19)), Some(true));
+        assert!(budget.degraded());
+
+        // still over half the budget
+        for i in 20..40 {
+            assert_eq!(budget.record(false, at(i)), None);
+        }
+        assert!(budget.degraded());
+
+        // the failures leave the window
+        let later = ERROR_WINDOW_MINS * 60 + 60;
+        for i in 0..19 {
+            assert_eq!(budget.record(false, at(later + i)), None);
+        }
+        assert_eq!(budget.record(false, at(later + 19)), Some(false));
+        assert!(!budget.degraded());
+        assert_eq!(budget.error_rate(), 0.0);
+
+        let disabled = ErrorBudget::new(0.0);
+        for i in 0..50 {
+            assert_eq!(disabled.record(true, at(i)), None);
+        }
+        assert!(!disabled.degraded());
+    }
+}
@@ -0,0 +1,246 @@
+/// Token bucket rate limiter matching the server's limits: a steady rate, plus a burst pool that is
+/// spent once the steady tokens run out and refills over the burst window.
+/// Urgent requests (ship actions like navigate, dock, extract) take the next token ahead of
+/// background requests (market polling, pagination), which wait until no urgent request is queued
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const BURST_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Urgent,
+    Background,
+}
+
+impl Priority {
+    // Requests that change state are a ship waiting on its next step, reads can wait
+    pub fn of(method: &reqwest::Method) -> Priority {
+        match *method == reqwest::Method::GET {
+            true => Priority::Background,
+            false => Priority::Urgent,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_sec: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            tokens: capacity,
+            per_sec,
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+        self.refilled = now;
+    }
+
+    fn try_take(&mut self) -> bool {
+        match self.tokens >= 1.0 {
+            true => {
+                self.tokens -= 1.0;
+                true
+            }
+            false => false,
+        }
+    }
+
+    // Until the next whole token, after a refill
+    fn next_token(&self) -> Duration {
+        match self.per_sec > 0.0 {
+            true => Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.per_sec),
+            false => Duration::MAX,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    steady: TokenBucket,
+    burst: TokenBucket,
+    urgent_queued: usize,
+    background_queued: usize,
+    max_wait: Duration,
+}
+
+impl LimiterState {
+    fn queued(&mut self, priority: Priority) -> &mut usize {
+        match priority {
+            Priority::Urgent => &mut self.urgent_queued,
+            Priority::Background => &mut self.background_queued,
+        }
+    }
+
+    // Ok once a token is taken, otherwise how long to wait before trying again
+    fn try_acquire(&mut self, priority: Priority, now: Instant) -> Result<(), Duration> {
+        self.steady.refill(now);
+        self.burst.refill(now);
+        let next_token = self.steady.next_token().min(self.burst.next_token());
+        if priority == Priority::Background && self.urgent_queued > 0 {
+            // the next token goes to the urgent request, check again after it
+            return Err(next_token.max(Duration::from_millis(1)));
+        }
+        match self.steady.try_take() || self.burst.try_take() {
+            true => Ok(()),
+            false => Err(next_token),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitMetrics {
+    pub urgent_queued: usize,
+    pub background_queued: usize,
+    // burst tokens left, 0 while saturated
+    pub burst_available: f64,
+    // longest wait for a token since the last metrics read
+    pub max_wait_ms: u128,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+// Keeps the queue depth right when a waiting request is dropped, e.g. at shutdown
+struct Queued<'a> {
+    limiter: &'a RateLimiter,
+    priority: Priority,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        *state.queued(self.priority) -= 1;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: f64, burst: f64, now: Instant) -> Self {
+        let burst_per_sec = burst / BURST_WINDOW.as_secs_f64();
+        Self {
+            state: Mutex::new(LimiterState {
+                // a second's worth of steady requests can go out together
+                steady: TokenBucket::new(per_sec.max(1.0), per_sec, now),
+                burst: TokenBucket::new(burst, burst_per_sec, now),
+                urgent_queued: 0,
+                background_queued: 0,
+                max_wait: Duration::ZERO,
+            }),
+        }
+    }
+
+    // Resolves once the request may be sent, returns how long it waited
+    pub async fn acquire(&self, priority: Priority) -> Duration {
+        let start = Instant::now();
+        *self.state.lock().unwrap().queued(priority) += 1;
+        let _queued = Queued {
+            limiter: self,
+            priority,
+        };
+        loop {
+            let result = self
+                .state
+                .lock()
+                .unwrap()
+                .try_acquire(priority, Instant::now());
+            match result {
+                Ok(()) => break,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+        let waited = start.elapsed();
+        let mut state = self.state.lock().unwrap();
+        state.max_wait = state.max_wait.max(waited);
+        waited
+    }
+
+    pub fn metrics(&self) -> RateLimitMetrics {
+        let mut state = self.state.lock().unwrap();
+        state.burst.refill(Instant::now());
+        let metrics = RateLimitMetrics {
+            urgent_queued: state.urgent_queued,
+            background_queued: state.background_queued,
+            burst_available: state.burst.tokens.floor(),
+            max_wait_ms: state.max_wait.as_millis(),
+        };
+        state.max_wait = Duration::ZERO;
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let t0 = Instant::now();
+        let mut state = RateLimiter::new(2.0, 4.0, t0).state.into_inner().unwrap();
+
+        // the steady tokens go first, then the burst pool
+        for _ in 0..6 {
+            assert_eq!(state.try_acquire(Priority::Urgent, t0), Ok(()));
+        }
+        // both empty: the steady bucket refills first
+        assert_eq!(
+            state.try_acquire(Priority::Urgent, t0),
+            Err(Duration::from_millis(500))
+        );
+        let t1 = t0 + Duration::from_millis(500);
+        assert_eq!(state.try_acquire(Priority::Background, t1), Ok(()));
+        assert!(state.try_acquire(Priority::Background, t1).is_err());
+
+        // queued urgent requests take the next token ahead of background requests
+        let t2 = t1 + Duration::from_secs(1);
+        state.urgent_queued = 1;
+        assert!(state.try_acquire(Priority::Background, t2).is_err());
+        assert_eq!(state.try_acquire(Priority::Urgent, t2), Ok(()));
+        state.urgent_queued = 0;
+        assert_eq!(state.try_acquire(Priority::Background, t2), Ok(()));
+
+        // the burst pool refills over the burst window
+        let t3 = t2 + BURST_WINDOW;
+        state.steady.refill(t3);
+        state.burst.refill(t3);
+        assert_eq!(state.burst.tokens, 4.0);
+        assert_eq!(state.steady.tokens, 2.0);
+
+        assert_eq!(Priority::of(&reqwest::Method::POST), Priority::Urgent);
+        assert_eq!(Priority::of(&reqwest::Method::GET), Priority::Background);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_queue_depth() {
+        let limiter = RateLimiter::new(2.0, 0.0, Instant::now());
+        for _ in 0..2 {
+            assert!(limiter.acquire(Priority::Urgent).await < Duration::from_millis(50));
+        }
+
+        // the third request waits for the steady bucket, and leaves the queue when dropped
+        let waiting = limiter.acquire(Priority::Background);
+        let timeout = tokio::time::timeout(Duration::from_millis(100), waiting).await;
+        assert!(timeout.is_err());
+        let metrics = limiter.metrics();
+        assert_eq!(metrics.urgent_queued, 0);
+        assert_eq!(metrics.background_queued, 0);
+
+        let waited = limiter.acquire(Priority::Background).await;
+        assert!(waited >= Duration::from_millis(300));
+        assert!(limiter.metrics().max_wait_ms >= 300);
+    }
+}
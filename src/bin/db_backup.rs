@@ -0,0 +1,34 @@
+//!
+//! Write a backup archive of the current reset's data.
+//!
+//! Usage: db_backup [dir]   (defaults to BACKUP_DIR, then ./backups)
+//!
+
+use st::api_client::ApiClient;
+use st::config::CONFIG;
+use st::db::DbClient;
+use std::path::PathBuf;
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+    pretty_env_logger::init_timed();
+
+    let dir = std::env::args()
+        .nth(1)
+        .or(CONFIG.backup_dir.clone())
+        .unwrap_or("backups".to_string());
+
+    let api_client = ApiClient::new();
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
+    let db = DbClient::new(&status.reset_date).await;
+
+    let path = db
+        .backup_reset(&PathBuf::from(dir), CONFIG.backup_retention)
+        .await
+        .expect("Failed to back up reset");
+    println!("Backup written to {}", path.display());
+}
@@ -0,0 +1,65 @@
+//!
+//! List the resets in the database and their sizes, or drop the data of old resets.
+//!
+//! Usage: db_prune                    list resets
+//!        db_prune <keep> [--dry-run] drop all but the newest <keep> resets, including the current one
+//!
+
+use st::api_client::ApiClient;
+use st::db::DbClient;
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+    pretty_env_logger::init_timed();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let keep = args.iter().find(|arg| *arg != "--dry-run").map(|arg| {
+        arg.parse::<usize>()
+            .expect("Invalid number of resets to keep")
+    });
+
+    let api_client = ApiClient::new();
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
+    let db = DbClient::new(&status.reset_date).await;
+
+    let Some(keep) = keep else {
+        for reset in db.list_resets().await.expect("Failed to list resets") {
+            let current = match reset.reset_id == status.reset_date {
+                true => " (current)",
+                false => "",
+            };
+            println!(
+                "{}{}: {} rows, {:.1} MB",
+                reset.reset_id,
+                current,
+                reset.rows,
+                reset.bytes as f64 / 1_000_000.0
+            );
+        }
+        return;
+    };
+
+    let dropped = db
+        .prune_resets(keep, dry_run)
+        .await
+        .expect("Failed to prune resets");
+    let verb = match dry_run {
+        true => "Would drop",
+        false => "Dropped",
+    };
+    for reset in &dropped {
+        println!(
+            "{} {}: {} rows, {:.1} MB",
+            verb,
+            reset.reset_id,
+            reset.rows,
+            reset.bytes as f64 / 1_000_000.0
+        );
+    }
+    println!("{} {} resets", verb, dropped.len());
+}
@@ -1,12 +1,22 @@
+use futures::future::join_all;
 use log::*;
 use st::agent_controller::AgentController;
-use st::api_client::ApiClient;
-use st::config::CONFIG;
+use st::alerts::ALERTS;
+use st::api_client::{dry_run::dry_run_reset_id, ApiClient};
+use st::config::{CONFIG, UNAVAILABLE_FEATURES};
 use st::db::DbClient;
-use st::universe::Universe;
-use st::web_api_server::WebApiServer;
+use st::universe::UniverseHandle;
+use st::util::retry::RetryPolicy;
+use st::web_api_server::{WebApiServer, DEFAULT_PORT};
 use std::env;
-use std::sync::Arc;
+use std::time::Duration;
+
+// The status request at startup retries for longer than other requests
+const STARTUP_RETRY: RetryPolicy =
+    RetryPolicy::exponential(Duration::from_secs(2), Duration::from_secs(30), 6);
+
+const JUMPGATE_RECHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+const STUCK_SHIP_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 #[tokio::main]
 async fn main() {
@@ -14,34 +24,251 @@ async fn main() {
     pretty_env_logger::init_timed();
 
     let faction = env::var("AGENT_FACTION").unwrap_or("".to_string());
-    let callsign = env::var("AGENT_CALLSIGN")
-        .expect("AGENT_CALLSIGN env var not set")
-        .to_ascii_uppercase();
+    let callsigns = agent_callsigns();
+
+    // --self-test: print the report and exit, otherwise only start if every check passes
+    let self_test = env::args().any(|arg| arg == "--self-test");
+    let mut passed = true;
+    for callsign in &callsigns {
+        let report = st::doctor::run(callsign).await;
+        for check in &report.checks {
+            info!(
+                "Self-test {} {}: {:?} {}",
+                callsign, check.name, check.status, check.detail
+            );
+        }
+        if self_test {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        }
+        passed &= report.passed();
+    }
+    if self_test {
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    if !passed {
+        error!("Self-test failed, not starting the agent");
+        std::process::exit(1);
+    }
+
+    if let Some(endpoint) = &CONFIG.otlp_endpoint {
+        st::telemetry::init(endpoint, &callsigns.join(","));
+    }
 
-    info!("Starting agent {} for faction {}", callsign, faction);
+    info!(
+        "Starting agents {} for faction {}",
+        callsigns.join(", "),
+        faction
+    );
     info!("Loaded config: {:?}", *CONFIG);
+    for (feature, enabled) in CONFIG.features.list() {
+        match (enabled, UNAVAILABLE_FEATURES.contains(&feature)) {
+            (true, true) => warn!("Feature {}: not available in this build", feature),
+            (true, false) => info!("Feature {}: on", feature),
+            (false, _) => info!("Feature {}: off", feature),
+        }
+    }
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    // the server is down for maintenance around a reset, wait it out rather than exiting
+    let status = loop {
+        match api_client.with_retry_policy(STARTUP_RETRY).status().await {
+            Ok(status) => break status,
+            Err(e) if e.is_maintenance() || e.status().is_none() => {
+                warn!("Server unavailable, checking again in a minute: {}", e);
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            Err(e) => panic!("{}", e),
+        }
+    };
+    st::api_client::compat::check_api_version(&status);
 
     // Use the reset date on the status response as a unique identifier to partition data between resets
-    let db = DbClient::new(&status.reset_date).await;
-    let universe = Arc::new(Universe::new(&api_client, &db));
-    universe.init().await;
-
-    // Startup Phase: register if not already registered, and load agent token
-    let agent_token = match db.get_agent_token(&callsign).await {
-        Some(token) => token,
-        None => {
-            let token = api_client.register(&faction, &callsign).await;
-            db.save_agent_token(&callsign, &token).await;
-            token
+    let live_db = DbClient::new(&status.reset_date).await;
+    // a dry run keeps its made up state apart, it only reads the live agent tokens
+    let db = match CONFIG.dry_run {
+        true => DbClient::new(&dry_run_reset_id(&status.reset_date)).await,
+        false => live_db.clone(),
+    };
+    db.run_migrations().await.expect("Failed to run migrations");
+    if let Some(backup_dir) = &CONFIG.backup_dir {
+        let db = db.clone();
+        let backup_dir = std::path::PathBuf::from(backup_dir);
+        tokio::spawn(async move {
+            let period = Duration::from_secs(CONFIG.backup_interval_hours * 3600);
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = db.backup_reset(&backup_dir, CONFIG.backup_retention).await {
+                    log::warn!("Failed to back up reset {}: {}", db.reset_date(), e);
+                }
+            }
+        });
+    }
+    // agents share the rate limit, the universe fetches with the first agent's token
+    let agent_clients = callsigns
+        .iter()
+        .map(|_| api_client.agent_client())
+        .collect::<Vec<_>>();
+    let universe = UniverseHandle::new(&agent_clients[0], &db);
+    universe.set_record_market_trades(CONFIG.features.market_trades);
+    universe.init().await.expect("Failed to load universe");
+    if CONFIG.cache_sync {
+        universe.start_cache_sync();
+    }
+    {
+        let universe = universe.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(JUMPGATE_RECHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                universe.recheck_jumpgate_construction().await;
+            }
+        });
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STUCK_SHIP_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            ALERTS.check_stuck_ships();
+        }
+    });
+
+    if let Ok(token) = env::var("AGENT_TOKEN") {
+        import_agent_token(&api_client, &db, &callsigns, &token, &status.reset_date).await;
+    }
+
+    let mut agent_controllers = vec![];
+    for (callsign, api_client) in callsigns.iter().zip(&agent_clients) {
+        // Startup Phase: register if not already registered, and load agent token
+        let mut stored_token = db.get_agent_token(callsign).await.expect("DB Query error");
+        if stored_token.is_none() && CONFIG.dry_run {
+            stored_token = live_db
+                .get_agent_token(callsign)
+                .await
+                .expect("DB Query error");
+        }
+        let agent_token = match stored_token {
+            Some(token) => token,
+            None => {
+                let token = api_client
+                    .register(&faction, callsign)
+                    .await
+                    .expect("Failed to register agent");
+                db.save_agent_token(callsign, &token)
+                    .await
+                    .expect("Failed to save agent token");
+                token
+            }
+        };
+        log::info!("Setting token {} for {}", agent_token, callsign);
+        api_client.set_agent_token(&agent_token);
+
+        let agent_controller = AgentController::new(api_client, &db, &universe, callsign).await;
+        tokio::spawn(st::ops_report::run(
+            agent_controller.clone(),
+            universe.reader(),
+            db.clone(),
+        ));
+        agent_controllers.push(agent_controller);
+    }
+    // the status feed has a single path, it follows the first agent
+    tokio::spawn(st::status_feed::run(
+        agent_controllers[0].clone(),
+        universe.reader(),
+    ));
+
+    // each agent's web server listens on the next port
+    let agents = agent_controllers
+        .iter()
+        .enumerate()
+        .map(|(idx, agent_controller)| {
+            let api_server = WebApiServer::new(agent_controller, &db, &universe)
+                .with_port(DEFAULT_PORT + idx as u16);
+            async move {
+                tokio::join!(agent_controller.run_ships(), async {
+                    if CONFIG.features.web_server {
+                        api_server.run().await;
+                    }
+                })
+            }
+        });
+    tokio::select! {
+        _ = join_all(agents) => {}
+        new_reset = st::reset_watch::watch(&agent_clients[0], &status.reset_date) => {
+            for agent_controller in &agent_controllers {
+                agent_controller.stop_ships();
+            }
+            st::reset_watch::cutover(&db, &new_reset).await;
+        }
+        _ = st::shutdown::requested() => {
+            // agent clients share the request gate, draining one drains them all
+            let _drained = agent_clients[0].drain().await;
+            tokio::time::sleep(st::shutdown::SCRIPT_GRACE).await;
+            for agent_controller in &agent_controllers {
+                agent_controller.shutdown().await;
+            }
+            info!("Shutdown complete");
+        }
+    }
+}
+
+// Store a token issued outside the bot, e.g. for an agent registered from the account portal. It
+// replaces any stored token for the agent, so the agent isn't registered again. A token from another
+// reset, for an agent this process doesn't run, or one the server rejects is ignored, and the agent
+// goes on with its stored token or registers
+async fn import_agent_token(
+    api_client: &ApiClient,
+    db: &DbClient,
+    callsigns: &[String],
+    token: &str,
+    reset_date: &str,
+) {
+    match st::api_client::token_reset_date(token) {
+        Some(token_reset) if token_reset != reset_date => {
+            warn!(
+                "AGENT_TOKEN is for reset {}, not the current reset {}. Ignoring it",
+                token_reset, reset_date
+            );
+            return;
+        }
+        _ => {}
+    }
+    let api_client = api_client.agent_client();
+    api_client.set_agent_token(token);
+    let agent = match api_client.get_agent().await {
+        Ok(agent) => agent,
+        Err(e) => {
+            warn!("AGENT_TOKEN was rejected, ignoring it: {}", e);
+            return;
         }
     };
-    log::info!("Setting token {}", agent_token);
-    api_client.set_agent_token(&agent_token);
+    if !callsigns.contains(&agent.symbol) {
+        warn!(
+            "AGENT_TOKEN is for agent {}, not one of {}. Ignoring it",
+            agent.symbol,
+            callsigns.join(", ")
+        );
+        return;
+    }
+    db.save_agent_token(&agent.symbol, token)
+        .await
+        .expect("Failed to save agent token");
+    info!("Imported AGENT_TOKEN for {}", agent.symbol);
+}
 
-    let agent_controller = AgentController::new(&api_client, &db, &universe, &callsign).await;
-    let api_server = WebApiServer::new(&agent_controller, &db, &universe);
-    tokio::join!(agent_controller.run_ships(), api_server.run());
+// AGENT_CALLSIGNS (comma separated) runs several agents in this process, otherwise AGENT_CALLSIGN.
+// The first is the primary agent: the shared universe fetches with its token, the status feed follows
+// it and the reset watch polls with its client. The others run their ships and web servers only
+fn agent_callsigns() -> Vec<String> {
+    let callsigns = match env::var("AGENT_CALLSIGNS") {
+        Ok(val) => val,
+        Err(_) => env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set"),
+    };
+    let callsigns = callsigns
+        .split(',')
+        .map(|callsign| callsign.trim().to_ascii_uppercase())
+        .filter(|callsign| !callsign.is_empty())
+        .collect::<Vec<_>>();
+    assert!(!callsigns.is_empty(), "No agent callsigns set");
+    callsigns
 }
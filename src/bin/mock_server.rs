@@ -0,0 +1,19 @@
+use st::mock_server::MockServer;
+use std::env;
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+    pretty_env_logger::init_timed();
+
+    let port: u16 = env::var("MOCK_SERVER_PORT")
+        .map(|val| val.parse().expect("Invalid MOCK_SERVER_PORT"))
+        .unwrap_or(8081);
+    let time_scale: f64 = env::var("MOCK_TIME_SCALE")
+        .map(|val| val.parse().expect("Invalid MOCK_TIME_SCALE"))
+        .unwrap_or(1.0);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .unwrap();
+    MockServer::new(time_scale).serve(listener).await;
+}
@@ -13,7 +13,7 @@ async fn main() {
     pretty_env_logger::init_timed();
 
     let db = DbClient::new("").await;
-    let mut conn = db.conn().await;
+    let mut conn = db.conn().await.unwrap();
 
     diesel::delete(general_lookup::table)
         .execute(&mut conn)
@@ -31,6 +31,10 @@ async fn main() {
         .execute(&mut conn)
         .await
         .unwrap();
+    diesel::delete(survey_consumption::table)
+        .execute(&mut conn)
+        .await
+        .unwrap();
     diesel::delete(surveys::table)
         .execute(&mut conn)
         .await
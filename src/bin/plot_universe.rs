@@ -6,7 +6,7 @@ use quadtree_rs::{point::Point, Quadtree};
 use st::api_client::ApiClient;
 use st::db::DbClient;
 use st::models::SystemSymbol;
-use st::universe::Universe;
+use st::universe::UniverseHandle;
 use std::cmp::max;
 use std::env;
 
@@ -18,10 +18,13 @@ async fn main() {
     let callsign = env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set");
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
     let db = DbClient::new(&status.reset_date).await;
-    let universe = Universe::new(&api_client, &db);
-    universe.init().await;
+    let universe = UniverseHandle::new(&api_client, &db);
+    universe.init().await.expect("Failed to load universe");
     assert_eq!(status.stats.systems, universe.num_systems() as i64);
     assert_eq!(status.stats.waypoints, universe.num_waypoints() as i64);
 
@@ -55,7 +58,10 @@ async fn main() {
     //         jumpgates.push((jump_gate_system, gate.connections, gate.is_constructed));
     //     }
     // }
-    let agent = api_client.get_agent_public(&callsign).await;
+    let agent = api_client
+        .get_agent_public(&callsign)
+        .await
+        .expect("Failed to get agent");
     let system = universe
         .get_faction(&agent.starting_faction)
         .await
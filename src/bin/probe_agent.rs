@@ -1,4 +1,3 @@
-use reqwest::StatusCode;
 use serde_json::Value;
 use st::api_client::ApiClient;
 use st::db::DbClient;
@@ -15,13 +14,16 @@ async fn main() {
         .to_ascii_uppercase();
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
 
     // Use the reset date on the status response as a unique identifier to partition data between resets
     let db = DbClient::new(&status.reset_date).await;
 
     // Startup Phase: register if not already registered, and load agent token
-    let agent_token = match db.get_agent_token(&callsign).await {
+    let agent_token = match db.get_agent_token(&callsign).await.expect("DB Query error") {
         Some(token) => token,
         None => panic!("Agent not registered"),
     };
@@ -31,10 +33,7 @@ async fn main() {
     for i in 1..=213 {
         // format as hex
         let uri = format!("/my/ships/{}-{:X}", target, i);
-        let (code, resp_body): (StatusCode, Result<Value, String>) = api_client
-            .request(reqwest::Method::GET, &uri, None::<&()>)
-            .await;
-        let resp = resp_body.unwrap_err();
-        println!("{} {:?} {:?}", uri, code, resp);
+        let resp = api_client.try_get::<Value>(&uri).await.unwrap_err();
+        println!("{} {:?} {:?}", uri, resp.status(), resp.body());
     }
 }
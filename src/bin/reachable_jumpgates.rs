@@ -2,10 +2,9 @@ use log::*;
 use pathfinding::prelude::*;
 use st::api_client::ApiClient;
 use st::db::DbClient;
-use st::universe::Universe;
+use st::universe::UniverseHandle;
 use std::env;
 use std::io;
-use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -15,12 +14,18 @@ async fn main() -> io::Result<()> {
     let callsign = env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set");
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
     let db = DbClient::new(&status.reset_date).await;
-    let universe = Arc::new(Universe::new(&api_client, &db));
-    universe.init().await;
+    let universe = UniverseHandle::new(&api_client, &db);
+    universe.init().await.expect("Failed to load universe");
 
-    let agent = api_client.get_agent_public(&callsign).await;
+    let agent = api_client
+        .get_agent_public(&callsign)
+        .await
+        .expect("Failed to get agent");
     let system = universe
         .get_faction(&agent.starting_faction)
         .await
@@ -2,12 +2,11 @@ use pathfinding::prelude::*;
 use st::api_client::ApiClient;
 use st::db::DbClient;
 use st::universe::pathfinding::EdgeType;
-use st::universe::Universe;
+use st::universe::UniverseHandle;
 use std::cmp::min;
 use std::env;
 use std::fs::File;
 use std::io;
-use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -17,12 +16,18 @@ async fn main() -> io::Result<()> {
     let callsign = env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set");
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
     let db = DbClient::new(&status.reset_date).await;
-    let universe = Arc::new(Universe::new(&api_client, &db));
-    universe.init().await;
+    let universe = UniverseHandle::new(&api_client, &db);
+    universe.init().await.expect("Failed to load universe");
 
-    let agent = api_client.get_agent_public(&callsign).await;
+    let agent = api_client
+        .get_agent_public(&callsign)
+        .await
+        .expect("Failed to get agent");
     let start = universe
         .get_faction(&agent.starting_faction)
         .await
@@ -86,10 +91,11 @@ async fn main() -> io::Result<()> {
                     let edge = &graph[s][t];
                     let type_ = match edge.edge_type {
                         EdgeType::Warp => "W",
+                        EdgeType::Drift => "D",
                         EdgeType::Jumpgate => "J",
                     };
                     match edge.edge_type {
-                        EdgeType::Warp => {
+                        EdgeType::Warp | EdgeType::Drift => {
                             // refuel to at least edge.fuel
                             let refuel = {
                                 let missing_fuel = MAX_FUEL - fuel;
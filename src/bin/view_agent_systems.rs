@@ -25,7 +25,10 @@ async fn main() -> std::io::Result<()> {
 
     let api_client = ApiClient::new();
     // {"symbol":"05HD3ITEFVHT","headquarters":"X1-SZ63-A1","credits":175000,"startingFaction":"COSMIC","shipCount":2}
-    let mut agents: Vec<Agent> = api_client.get_all_pages("/agents").await;
+    let mut agents: Vec<Agent> = api_client
+        .get_all_pages("/agents")
+        .await
+        .expect("Failed to get agents");
     let mut factions = std::collections::BTreeMap::new();
     let mut headquarters = std::collections::BTreeMap::new();
 
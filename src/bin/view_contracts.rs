@@ -14,15 +14,25 @@ async fn main() {
     let callsign = env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set");
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
 
     // Use the reset date on the status response as a unique identifier to partition data between resets
     let db = DbClient::new(&status.reset_date).await;
-    let agent_token = db.get_agent_token(&callsign).await.unwrap();
+    let agent_token = db
+        .get_agent_token(&callsign)
+        .await
+        .expect("DB Query error")
+        .unwrap();
     api_client.set_agent_token(&agent_token);
     // let universe = Universe::new(&api_client, &db);
 
     // let agent_controller = AgentController::new(&api_client, &db, &universe, &callsign).await;
-    let contracts: Vec<Contract> = api_client.get_all_pages("/my/contracts").await;
+    let contracts: Vec<Contract> = api_client
+        .get_all_pages("/my/contracts")
+        .await
+        .expect("Failed to get contracts");
     info!("Contracts: {:?}", contracts);
 }
@@ -4,11 +4,10 @@ use st::api_client::api_models::WaypointDetailed;
 use st::api_client::ApiClient;
 use st::db::DbClient;
 
-use st::universe::Universe;
+use st::universe::UniverseHandle;
 use std::env;
 use std::fs::File;
 use std::io;
-use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -18,14 +17,21 @@ async fn main() -> io::Result<()> {
     let callsign = env::var("AGENT_CALLSIGN").expect("AGENT_CALLSIGN env var not set");
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
 
     // Use the reset date on the status response as a unique identifier to partition data between resets
     let db = DbClient::new(&status.reset_date).await;
-    let agent_token = db.get_agent_token(&callsign).await.unwrap();
+    let agent_token = db
+        .get_agent_token(&callsign)
+        .await
+        .expect("DB Query error")
+        .unwrap();
     api_client.set_agent_token(&agent_token);
-    let universe = Arc::new(Universe::new(&api_client, &db));
-    universe.init().await;
+    let universe = UniverseHandle::new(&api_client, &db);
+    universe.init().await.expect("Failed to load universe");
 
     let agent_controller = AgentController::new(&api_client, &db, &universe, &callsign).await;
     let system_symbol = agent_controller.faction_capital().await;
@@ -1,7 +1,8 @@
 use st::{
-    agent_controller::AgentController, api_client::ApiClient, db::DbClient, universe::Universe,
+    agent_controller::AgentController, api_client::ApiClient, db::DbClient,
+    universe::UniverseHandle,
 };
-use std::{env, sync::Arc};
+use std::env;
 
 #[tokio::main]
 async fn main() {
@@ -13,14 +14,17 @@ async fn main() {
         .to_ascii_uppercase();
 
     let api_client = ApiClient::new();
-    let status = api_client.status().await;
+    let status = api_client
+        .status()
+        .await
+        .expect("Failed to get server status");
 
     // Use the reset date on the status response as a unique identifier to partition data between resets
     let db = DbClient::new(&status.reset_date).await;
-    let universe = Arc::new(Universe::new(&api_client, &db));
+    let universe = UniverseHandle::new(&api_client, &db);
 
     // Startup Phase: register if not already registered, and load agent token
-    let agent_token = match db.get_agent_token(&callsign).await {
+    let agent_token = match db.get_agent_token(&callsign).await.expect("DB Query error") {
         Some(token) => token,
         None => panic!("No agent token found for callsign: {}", &callsign),
     };
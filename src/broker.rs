@@ -11,10 +11,12 @@ use crate::models::WaypointSymbol;
 #[derive(Debug)]
 enum Message {
     ReceiveCargo(String, WaypointSymbol, i64, oneshot::Sender<()>),
+    // the bool puts the sender at the front of the queue
     TransferCargo(
         String,
         WaypointSymbol,
         Vec<(String, i64)>,
+        bool,
         oneshot::Sender<()>,
     ),
     Terminate,
@@ -80,6 +82,28 @@ impl CargoBroker {
         ship_symbol: &str,
         waypoint: &WaypointSymbol,
         goods: Vec<(String, i64)>,
+    ) {
+        self.transfer_cargo_with(ship_symbol, waypoint, goods, false)
+            .await
+    }
+
+    // Served before the senders already waiting, for ships that can't continue until they're unloaded
+    pub async fn transfer_cargo_priority(
+        &self,
+        ship_symbol: &str,
+        waypoint: &WaypointSymbol,
+        goods: Vec<(String, i64)>,
+    ) {
+        self.transfer_cargo_with(ship_symbol, waypoint, goods, true)
+            .await
+    }
+
+    async fn transfer_cargo_with(
+        &self,
+        ship_symbol: &str,
+        waypoint: &WaypointSymbol,
+        goods: Vec<(String, i64)>,
+        priority: bool,
     ) {
         let (tx, rx) = oneshot::channel::<()>();
         self.tx
@@ -87,6 +111,7 @@ impl CargoBroker {
                 ship_symbol.to_string(),
                 waypoint.clone(),
                 goods,
+                priority,
                 tx,
             ))
             .await
@@ -114,9 +139,12 @@ impl CargoBrokerInner {
                     e.push_back((ship_symbol, capacity, rx));
                     self.try_transfer(actor, &waypoint).await;
                 }
-                Message::TransferCargo(ship_symbol, waypoint, goods, rx) => {
+                Message::TransferCargo(ship_symbol, waypoint, goods, priority, rx) => {
                     let e = self.senders.entry(waypoint.clone()).or_default();
-                    e.push_back((ship_symbol, goods, rx));
+                    match priority {
+                        true => e.push_front((ship_symbol, goods, rx)),
+                        false => e.push_back((ship_symbol, goods, rx)),
+                    }
                     self.try_transfer(actor, &waypoint).await;
                 }
                 Message::Terminate => {
@@ -262,4 +290,53 @@ mod tests {
         broker.terminate().await;
         broker_handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_cargo_broker_priority() {
+        let mock = MockTransferActor::new();
+        let broker = Arc::new(CargoBroker::new());
+        let waypoint = WaypointSymbol::new("X1-S1-W1");
+        let broker_handle = {
+            let broker = broker.clone();
+            let mock = mock.clone();
+            tokio::task::spawn(async move { broker.run(Box::new(mock)).await })
+        };
+        let sleep = || tokio::time::sleep(tokio::time::Duration::from_millis(20));
+        let waiting = {
+            let broker = broker.clone();
+            let waypoint = waypoint.clone();
+            tokio::task::spawn(async move {
+                broker
+                    .transfer_cargo("waiting", &waypoint, vec![("good1".to_string(), 50)])
+                    .await;
+            })
+        };
+        sleep().await;
+        // a full drone jumps the queue
+        let full = {
+            let broker = broker.clone();
+            let waypoint = waypoint.clone();
+            tokio::task::spawn(async move {
+                broker
+                    .transfer_cargo_priority("full", &waypoint, vec![("good2".to_string(), 50)])
+                    .await;
+            })
+        };
+        sleep().await;
+        broker.receive_cargo("shuttle", &waypoint, 100).await;
+        waiting.await.unwrap();
+        full.await.unwrap();
+
+        let senders = mock
+            .transfers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(src, _, _, _)| src.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(senders, vec!["full", "waiting"]);
+
+        broker.terminate().await;
+        broker_handle.await.unwrap();
+    }
 }
@@ -0,0 +1,170 @@
+//!
+//! Value ship inventories against the best known sell prices in a system.
+//!
+use crate::models::{Market, ShipCargo, SystemSymbol, WaypointSymbol, WithTimestamp};
+use crate::universe::access::UniverseReader;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BestSellPrice {
+    pub market: WaypointSymbol,
+    pub price: i64,
+    pub trade_volume: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CargoItemValue {
+    pub symbol: String,
+    pub units: i64,
+    // None if no known market in the system buys this good
+    pub best_sell: Option<BestSellPrice>,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CargoValuation {
+    pub system: Option<SystemSymbol>,
+    pub items: Vec<CargoItemValue>,
+    pub total: i64,
+}
+
+#[derive(Clone)]
+pub struct CargoValuer {
+    universe: Arc<dyn UniverseReader>,
+}
+
+impl CargoValuer {
+    pub fn new(universe: Arc<dyn UniverseReader>) -> Self {
+        Self { universe }
+    }
+
+    pub async fn best_sell_prices(&self, system: &SystemSymbol) -> BTreeMap<String, BestSellPrice> {
+        let markets = self.universe.known_system_markets(system).await;
+        best_sell_prices(&markets)
+    }
+
+    pub async fn value_cargo(&self, system: &SystemSymbol, cargo: &ShipCargo) -> CargoValuation {
+        let prices = self.best_sell_prices(system).await;
+        let mut valuation = value_cargo(cargo, &prices);
+        valuation.system = Some(system.clone());
+        valuation
+    }
+}
+
+pub fn best_sell_prices(markets: &[Arc<WithTimestamp<Market>>]) -> BTreeMap<String, BestSellPrice> {
+    let mut prices: BTreeMap<String, BestSellPrice> = BTreeMap::new();
+    for market in markets {
+        for good in &market.data.trade_goods {
+            let better = match prices.get(&good.symbol) {
+                Some(best) => good.sell_price > best.price,
+                None => true,
+            };
+            if better {
+                prices.insert(
+                    good.symbol.clone(),
+                    BestSellPrice {
+                        market: market.data.symbol.clone(),
+                        price: good.sell_price,
+                        trade_volume: good.trade_volume,
+                    },
+                );
+            }
+        }
+    }
+    prices
+}
+
+pub fn value_cargo(cargo: &ShipCargo, prices: &BTreeMap<String, BestSellPrice>) -> CargoValuation {
+    let items = cargo
+        .inventory
+        .iter()
+        .map(|item| {
+            let best_sell = prices.get(&item.symbol).cloned();
+            let value = best_sell
+                .as_ref()
+                .map(|p| p.price * item.units)
+                .unwrap_or(0);
+            CargoItemValue {
+                symbol: item.symbol.clone(),
+                units: item.units,
+                best_sell,
+                value,
+            }
+        })
+        .collect::<Vec<_>>();
+    let total = items.iter().map(|item| item.value).sum();
+    CargoValuation {
+        system: None,
+        items,
+        total,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{Market, MarketSupply, MarketTradeGood, MarketType, ShipCargoItem};
+    use chrono::Utc;
+
+    fn market(symbol: &str, goods: &[(&str, i64)]) -> Arc<WithTimestamp<Market>> {
+        let trade_goods = goods
+            .iter()
+            .map(|(good, sell_price)| MarketTradeGood {
+                symbol: good.to_string(),
+                trade_volume: 10,
+                _type: MarketType::Exchange,
+                supply: MarketSupply::Moderate,
+                activity: None,
+                purchase_price: sell_price + 10,
+                sell_price: *sell_price,
+            })
+            .collect();
+        Arc::new(WithTimestamp {
+            timestamp: Utc::now(),
+            data: Market {
+                symbol: WaypointSymbol::new(symbol),
+                transactions: vec![],
+                imports: vec![],
+                exports: vec![],
+                exchange: vec![],
+                trade_goods,
+            },
+        })
+    }
+
+    fn cargo_item(symbol: &str, units: i64) -> ShipCargoItem {
+        ShipCargoItem {
+            symbol: symbol.to_string(),
+            units,
+            name: String::new(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_value_cargo() {
+        let markets = vec![
+            market("X1-S1-A1", &[("FUEL", 70), ("IRON_ORE", 40)]),
+            market("X1-S1-B2", &[("IRON_ORE", 55)]),
+        ];
+        let prices = best_sell_prices(&markets);
+        assert_eq!(prices["IRON_ORE"].market, WaypointSymbol::new("X1-S1-B2"));
+
+        let cargo = ShipCargo {
+            capacity: 40,
+            units: 25,
+            inventory: vec![
+                cargo_item("IRON_ORE", 10),
+                cargo_item("FUEL", 5),
+                cargo_item("QUARTZ_SAND", 10),
+            ],
+        };
+        let valuation = value_cargo(&cargo, &prices);
+        assert_eq!(valuation.items[0].value, 550);
+        assert_eq!(valuation.items[1].value, 350);
+        assert_eq!(valuation.items[2].best_sell, None);
+        assert_eq!(valuation.total, 900);
+    }
+}
@@ -0,0 +1,147 @@
+//!
+//! Source of the current time for time-based logic.
+//!
+//! Logic that depends on the current time (market staleness, transit and cooldown waits, task
+//! exclusivity) reads it from a `Clock` rather than calling `Utc::now()`, so tests can control it.
+//! Arrival and cooldown timestamps come from the API, so the agent runs on a `ServerClock`: the local
+//! clock corrected by the skew measured from API response Date headers.
+//!
+use chrono::{DateTime, Duration, Utc};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Weight of each new skew sample, Date headers only have second resolution so single samples are noisy
+const SKEW_SMOOTHING: f64 = 0.1;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// Running estimate of server clock minus local clock
+#[derive(Debug, Default)]
+pub struct ClockSkew {
+    skew_ms: AtomicI64,
+    samples: AtomicI64,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a sample from a response Date header, truncated to the second, for a request sent at
+    // `sent` and answered at `received` (local clock)
+    pub fn observe(&self, date: DateTime<Utc>, sent: DateTime<Utc>, received: DateTime<Utc>) {
+        let midpoint = sent + (received - sent) / 2;
+        let sample = (date - midpoint).num_milliseconds() + 500;
+        let samples = self.samples.fetch_add(1, Ordering::Relaxed);
+        let skew = match samples {
+            0 => sample,
+            _ => {
+                let prev = self.skew_ms.load(Ordering::Relaxed);
+                prev + ((sample - prev) as f64 * SKEW_SMOOTHING).round() as i64
+            }
+        };
+        self.skew_ms.store(skew, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::milliseconds(self.skew_ms.load(Ordering::Relaxed))
+    }
+}
+
+// The local clock corrected to the API server's clock
+#[derive(Debug, Default)]
+pub struct ServerClock {
+    skew: Arc<ClockSkew>,
+}
+
+impl ServerClock {
+    pub fn new(skew: Arc<ClockSkew>) -> Self {
+        Self { skew }
+    }
+}
+
+impl Clock for ServerClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now() + self.skew.get()
+    }
+}
+
+// A clock that only moves when told to
+#[derive(Debug)]
+pub struct TestClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl TestClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = TestClock::new(t0);
+        assert_eq!(clock.now(), t0);
+        clock.advance(Duration::try_minutes(5).unwrap());
+        assert_eq!(clock.now(), t0 + Duration::try_minutes(5).unwrap());
+        clock.set(t0);
+        assert_eq!(clock.now(), t0);
+
+        let system: SharedClock = Arc::new(SystemClock);
+        assert!(system.now() > t0);
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let ms = |ms: i64| Duration::try_milliseconds(ms).unwrap();
+        let skew = ClockSkew::new();
+        assert_eq!(skew.get(), Duration::zero());
+
+        // server 3.2s ahead, the header is truncated to 3s, the first sample is taken as is
+        skew.observe(t0 + ms(3000), t0 - ms(100), t0 + ms(100));
+        assert_eq!(skew.get(), ms(3500));
+
+        // later samples are smoothed
+        skew.observe(t0 + ms(4000), t0 - ms(100), t0 + ms(100));
+        assert_eq!(skew.get(), ms(3600));
+
+        let clock = ServerClock::new(Arc::new(skew));
+        let diff = clock.now() - Utc::now();
+        assert!(diff > ms(3500) && diff <= ms(3600));
+    }
+}
@@ -1,17 +1,145 @@
 use lazy_static::lazy_static;
-use regex::Regex;
 
-use crate::agent_controller::AgentEra;
+use crate::agent_controller::job_filter::JobFilter;
+use crate::agent_controller::{AgentEra, Strategy};
+use std::collections::BTreeSet;
+
+// Goods the logistics tasks may trade
+#[derive(Debug, Clone, Default)]
+pub struct GoodFilter {
+    // only these goods, if set
+    pub allowlist: Option<BTreeSet<String>>,
+    pub denylist: BTreeSet<String>,
+}
+
+impl GoodFilter {
+    pub fn allows(&self, good: &str) -> bool {
+        if self.denylist.contains(good) {
+            return false;
+        }
+        match &self.allowlist {
+            Some(allowlist) => allowlist.contains(good),
+            None => true,
+        }
+    }
+}
+
+// Comma separated list, e.g. of goods
+fn parse_list(val: &str) -> BTreeSet<String> {
+    val.split(',')
+        .map(|good| good.trim().to_string())
+        .filter(|good| !good.is_empty())
+        .collect()
+}
+
+// Integrations that can be switched off per deployment, set by FEATURE_<NAME>=0/1
+#[derive(Debug, Clone)]
+pub struct FeatureFlags {
+    pub event_log: bool,
+    pub web_server: bool,
+    pub scylla: bool,
+    // record every market snapshot's trade goods in market_trades
+    pub market_trades: bool,
+    // post alerts to ALERT_WEBHOOK_URL
+    pub notifications: bool,
+    pub competitor_tracking: bool,
+}
+
+// Flags with no integration in this build, accepted so deployment configs can be shared
+pub const UNAVAILABLE_FEATURES: &[&str] = &["event_log", "scylla", "competitor_tracking"];
+
+fn feature(name: &str, default: bool) -> bool {
+    let var = format!("FEATURE_{}", name.to_ascii_uppercase());
+    std::env::var(&var).map(|val| val == "1").unwrap_or(default)
+}
+
+impl FeatureFlags {
+    fn from_env() -> Self {
+        Self {
+            event_log: feature("event_log", false),
+            web_server: feature("web_server", true),
+            scylla: feature("scylla", false),
+            market_trades: feature("market_trades", true),
+            notifications: feature("notifications", true),
+            competitor_tracking: feature("competitor_tracking", false),
+        }
+    }
+
+    pub fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("event_log", self.event_log),
+            ("web_server", self.web_server),
+            ("scylla", self.scylla),
+            ("market_trades", self.market_trades),
+            ("notifications", self.notifications),
+            ("competitor_tracking", self.competitor_tracking),
+        ]
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub api_base_url: String,
-    pub job_id_filter: Regex,
+    // ship scripts run at startup, can be replaced through the command API until the next restart
+    pub job_filter: JobFilter,
     pub override_construction_supply_check: bool,
     pub scrap_all_ships: bool,
     pub scrap_unassigned: bool,
+    pub dry_run: bool,
+    // set by NO_GATE_MODE, or implied by the PureTrading strategy
     pub no_gate_mode: bool,
+    pub strategy: Strategy,
     pub era_override: Option<AgentEra>,
+    pub construction_budget_fraction: Option<f64>,
+    pub backup_dir: Option<String>,
+    pub backup_interval_hours: u64,
+    pub backup_retention: usize,
+    pub chart_budget_per_hour: f64,
+    pub alert_webhook_url: Option<String>,
+    pub alert_api_p99_ms: u128,
+    pub alert_ship_stuck_mins: i64,
+    pub docking_cost: i64,
+    // credits per second of ship time, to weigh BURN's extra fuel against the time it saves
+    pub burn_time_value: f64,
+    // time values of low urgency trips (market refreshes) and high urgency trips (valuable tasks)
+    pub burn_time_value_low: f64,
+    pub burn_time_value_high: f64,
+    // task value from which trips to it are high urgency
+    pub urgent_task_value: i64,
+    pub status_feed_path: Option<String>,
+    // PUT target for the status feed, e.g. a presigned S3 URL
+    pub status_feed_url: Option<String>,
+    pub status_feed_interval_secs: u64,
+    // unassigned trade task value that, sustained for autoscale_cycles planning cycles, adds a hauler
+    pub autoscale_backlog_value: i64,
+    pub autoscale_cycles: i64,
+    pub autoscale_max_haulers: i64,
+    // shipyard data older than this is refreshed before buying a ship there
+    pub shipyard_stale_mins: i64,
+    // fraction of API requests allowed to fail before probes and speculative trades pause, 0 never pauses
+    pub api_error_budget: f64,
+    // requests per second, and requests over the minute burst window on top of it, shared by all agents
+    pub api_rate_limit: f64,
+    pub api_rate_burst: f64,
+    // attempts at a database connection or key-value read/write before the error is returned
+    pub db_retry_attempts: u32,
+    // a purchaser is sent to the shipyard when the next ship is forecast to be affordable within this
+    pub purchase_lead_mins: i64,
+    // ships are only bought at or below this percentile of their recent prices, 0 buys at any price
+    pub ship_price_percentile: i64,
+    pub ship_price_window_hours: i64,
+    pub trade_goods: GoodFilter,
+    // buys worth at least price_guard_min_value are abandoned if the price rose more than
+    // price_guard_increase (a fraction) since they were planned
+    pub price_guard_increase: f64,
+    pub price_guard_min_value: i64,
+    // OTLP/HTTP collector that planner, API, DB and ship step spans are exported to
+    pub otlp_endpoint: Option<String>,
+    // share market and shipyard updates with other processes using the same database
+    pub cache_sync: bool,
+    // pool surveys with other agents using the same database
+    pub shared_surveys: bool,
+    pub features: FeatureFlags,
 }
 
 lazy_static! {
@@ -20,15 +148,25 @@ lazy_static! {
             .expect("API_BASE_URL env var not set")
             .parse()
             .expect("Invalid API_BASE_URL");
-        let job_id_filter = match std::env::var("JOB_ID_FILTER") {
-            Ok(val) if val.is_empty() => None,
-            Ok(val) => Some(val),
-            Err(_) => None,
-        };
-        let job_id_filter = match job_id_filter {
-            Some(val) => Regex::new(&val).expect("Invalid JOB_ID_FILTER regex"),
-            None => Regex::new(".*").expect("Invalid default regex"),
+        let mut job_filter = JobFilter {
+            include: std::env::var("JOB_INCLUDE")
+                .map(|val| parse_list(&val).into_iter().collect())
+                .unwrap_or_default(),
+            exclude: std::env::var("JOB_EXCLUDE")
+                .map(|val| parse_list(&val).into_iter().collect())
+                .unwrap_or_default(),
+            disabled_behaviours: std::env::var("DISABLED_BEHAVIOURS")
+                .map(|val| parse_list(&val))
+                .unwrap_or_default(),
         };
+        // single include pattern, from before JOB_INCLUDE
+        match std::env::var("JOB_ID_FILTER") {
+            Ok(val) if !val.is_empty() => job_filter.include.push(val),
+            _ => {}
+        }
+        if let Err(e) = job_filter.validate() {
+            panic!("Invalid job filter: {}", e);
+        }
         let override_construction_supply_check =
             std::env::var("OVERRIDE_CONSTRUCTION_SUPPLY_CHECK")
                 .map(|val| val == "1")
@@ -39,22 +177,186 @@ lazy_static! {
         let scrap_unassigned = std::env::var("SCRAP_UNASSIGNED")
             .map(|val| val == "1")
             .unwrap_or(false);
-        let no_gate_mode = std::env::var("NO_GATE_MODE")
+        let dry_run = std::env::var("DRY_RUN")
             .map(|val| val == "1")
             .unwrap_or(false);
+        let strategy: Strategy = match std::env::var("STRATEGY") {
+            Ok(val) if val.is_empty() => Strategy::default(),
+            Ok(val) => val.parse().expect("Invalid STRATEGY"),
+            Err(_) => Strategy::default(),
+        };
+        let no_gate_mode = std::env::var("NO_GATE_MODE")
+            .map(|val| val == "1")
+            .unwrap_or(false)
+            || strategy.no_gate();
         let era_override = match std::env::var("ERA_OVERRIDE") {
             Ok(val) if val.is_empty() => None,
             Ok(val) => Some(val.parse().expect("Invalid ERA_OVERRIDE")),
             Err(_) => None,
         };
+        let construction_budget_fraction = match std::env::var("CONSTRUCTION_BUDGET_FRACTION") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val.parse().expect("Invalid CONSTRUCTION_BUDGET_FRACTION")),
+            Err(_) => None,
+        };
+        let backup_dir = match std::env::var("BACKUP_DIR") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
+        let backup_interval_hours = std::env::var("BACKUP_INTERVAL_HOURS")
+            .map(|val| val.parse().expect("Invalid BACKUP_INTERVAL_HOURS"))
+            .unwrap_or(6);
+        let backup_retention = std::env::var("BACKUP_RETENTION")
+            .map(|val| val.parse().expect("Invalid BACKUP_RETENTION"))
+            .unwrap_or(10);
+        let chart_budget_per_hour = std::env::var("CHART_BUDGET_PER_HOUR")
+            .map(|val| val.parse().expect("Invalid CHART_BUDGET_PER_HOUR"))
+            .unwrap_or(60.0);
+        let alert_webhook_url = match std::env::var("ALERT_WEBHOOK_URL") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
+        let alert_api_p99_ms = std::env::var("ALERT_API_P99_MS")
+            .map(|val| val.parse().expect("Invalid ALERT_API_P99_MS"))
+            .unwrap_or(5000);
+        let alert_ship_stuck_mins = std::env::var("ALERT_SHIP_STUCK_MINS")
+            .map(|val| val.parse().expect("Invalid ALERT_SHIP_STUCK_MINS"))
+            .unwrap_or(60);
+        let docking_cost = std::env::var("DOCKING_COST")
+            .map(|val| val.parse().expect("Invalid DOCKING_COST"))
+            .unwrap_or(250);
+        let burn_time_value = std::env::var("BURN_TIME_VALUE")
+            .map(|val| val.parse().expect("Invalid BURN_TIME_VALUE"))
+            .unwrap_or(2.0);
+        let burn_time_value_low = std::env::var("BURN_TIME_VALUE_LOW")
+            .map(|val| val.parse().expect("Invalid BURN_TIME_VALUE_LOW"))
+            .unwrap_or(0.0);
+        let burn_time_value_high = std::env::var("BURN_TIME_VALUE_HIGH")
+            .map(|val| val.parse().expect("Invalid BURN_TIME_VALUE_HIGH"))
+            .unwrap_or(20.0);
+        let urgent_task_value = std::env::var("URGENT_TASK_VALUE")
+            .map(|val| val.parse().expect("Invalid URGENT_TASK_VALUE"))
+            .unwrap_or(100_000);
+        let status_feed_path = match std::env::var("STATUS_FEED_PATH") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
+        let status_feed_url = match std::env::var("STATUS_FEED_URL") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
+        let status_feed_interval_secs = std::env::var("STATUS_FEED_INTERVAL_SECS")
+            .map(|val| val.parse().expect("Invalid STATUS_FEED_INTERVAL_SECS"))
+            .unwrap_or(60);
+        let autoscale_backlog_value = std::env::var("AUTOSCALE_BACKLOG_VALUE")
+            .map(|val| val.parse().expect("Invalid AUTOSCALE_BACKLOG_VALUE"))
+            .unwrap_or(200_000);
+        let autoscale_cycles = std::env::var("AUTOSCALE_CYCLES")
+            .map(|val| val.parse().expect("Invalid AUTOSCALE_CYCLES"))
+            .unwrap_or(10);
+        let autoscale_max_haulers = std::env::var("AUTOSCALE_MAX_HAULERS")
+            .map(|val| val.parse().expect("Invalid AUTOSCALE_MAX_HAULERS"))
+            .unwrap_or(3);
+        let shipyard_stale_mins = std::env::var("SHIPYARD_STALE_MINS")
+            .map(|val| val.parse().expect("Invalid SHIPYARD_STALE_MINS"))
+            .unwrap_or(30);
+        let api_error_budget = std::env::var("API_ERROR_BUDGET")
+            .map(|val| val.parse().expect("Invalid API_ERROR_BUDGET"))
+            .unwrap_or(0.25);
+        let api_rate_limit = std::env::var("API_RATE_LIMIT")
+            .map(|val| val.parse().expect("Invalid API_RATE_LIMIT"))
+            .unwrap_or(2.0);
+        let api_rate_burst = std::env::var("API_RATE_BURST")
+            .map(|val| val.parse().expect("Invalid API_RATE_BURST"))
+            .unwrap_or(30.0);
+        let db_retry_attempts = std::env::var("DB_RETRY_ATTEMPTS")
+            .map(|val| val.parse().expect("Invalid DB_RETRY_ATTEMPTS"))
+            .unwrap_or(5);
+        let purchase_lead_mins = std::env::var("PURCHASE_LEAD_MINS")
+            .map(|val| val.parse().expect("Invalid PURCHASE_LEAD_MINS"))
+            .unwrap_or(5);
+        let ship_price_percentile = std::env::var("SHIP_PRICE_PERCENTILE")
+            .map(|val| val.parse().expect("Invalid SHIP_PRICE_PERCENTILE"))
+            .unwrap_or(0);
+        let ship_price_window_hours = std::env::var("SHIP_PRICE_WINDOW_HOURS")
+            .map(|val| val.parse().expect("Invalid SHIP_PRICE_WINDOW_HOURS"))
+            .unwrap_or(24);
+        let trade_goods = GoodFilter {
+            allowlist: match std::env::var("TRADE_GOOD_ALLOWLIST") {
+                Ok(val) if val.is_empty() => None,
+                Ok(val) => Some(parse_list(&val)),
+                Err(_) => None,
+            },
+            denylist: std::env::var("TRADE_GOOD_DENYLIST")
+                .map(|val| parse_list(&val))
+                .unwrap_or_default(),
+        };
+        let price_guard_increase = std::env::var("PRICE_GUARD_INCREASE")
+            .map(|val| val.parse().expect("Invalid PRICE_GUARD_INCREASE"))
+            .unwrap_or(0.2);
+        let price_guard_min_value = std::env::var("PRICE_GUARD_MIN_VALUE")
+            .map(|val| val.parse().expect("Invalid PRICE_GUARD_MIN_VALUE"))
+            .unwrap_or(50_000);
+        let otlp_endpoint = match std::env::var("OTLP_ENDPOINT") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
+        let cache_sync = std::env::var("CACHE_SYNC")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let shared_surveys = std::env::var("SHARED_SURVEYS")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let features = FeatureFlags::from_env();
         Config {
             api_base_url,
-            job_id_filter,
+            job_filter,
             override_construction_supply_check,
             scrap_all_ships,
             scrap_unassigned,
+            dry_run,
             era_override,
             no_gate_mode,
+            strategy,
+            construction_budget_fraction,
+            backup_dir,
+            backup_interval_hours,
+            backup_retention,
+            chart_budget_per_hour,
+            alert_webhook_url,
+            alert_api_p99_ms,
+            alert_ship_stuck_mins,
+            docking_cost,
+            burn_time_value,
+            burn_time_value_low,
+            burn_time_value_high,
+            urgent_task_value,
+            status_feed_path,
+            status_feed_url,
+            status_feed_interval_secs,
+            autoscale_backlog_value,
+            autoscale_cycles,
+            autoscale_max_haulers,
+            shipyard_stale_mins,
+            api_error_budget,
+            api_rate_limit,
+            api_rate_burst,
+            db_retry_attempts,
+            purchase_lead_mins,
+            ship_price_percentile,
+            ship_price_window_hours,
+            trade_goods,
+            price_guard_increase,
+            price_guard_min_value,
+            otlp_endpoint,
+            cache_sync,
+            shared_surveys,
+            features,
         }
     };
 }
@@ -0,0 +1,214 @@
+//!
+//! Backup of the current reset's data to gzipped json-lines archives.
+//!
+//! Each line is `{"table": <table>, "row": <row as json>}`.
+//! Archives are written to `{dir}/{reset_id}/{timestamp}.jsonl.gz`, keeping the newest `retention` archives.
+//! Rows are paged by primary key, and compressed and written on a blocking thread.
+//!
+use super::{DbClient, DbError};
+use diesel::sql_types::{BigInt, Text, Timestamptz};
+use diesel::QueryableByName;
+use diesel_async::RunQueryDsl as _;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::*;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+const BATCH_SIZE: i64 = 50_000;
+// Pages fetched ahead of the writer
+const WRITE_QUEUE_PAGES: usize = 2;
+
+// (table, primary key, filter selecting the current reset's rows)
+// market_trades, market_transactions and shipyard_listings are not partitioned by reset, so select by timestamp instead
+const BACKUP_TABLES: &[(&str, &str, &str)] = &[
+    (
+        "construction_deliveries",
+        "reset_id, ship_symbol, \"timestamp\"",
+        "reset_id = $1",
+    ),
+    ("general_lookup", "reset_id, key", "reset_id = $1"),
+    (
+        "job_assignments",
+        "reset_id, callsign, ship_symbol, assigned_at",
+        "reset_id = $1",
+    ),
+    (
+        "jumpgate_connections",
+        "reset_id, waypoint_symbol",
+        "reset_id = $1",
+    ),
+    (
+        "net_worth_history",
+        "reset_id, callsign, \"timestamp\"",
+        "reset_id = $1",
+    ),
+    ("ship_models", "reset_id, ship_type", "reset_id = $1"),
+    (
+        "survey_consumption",
+        "reset_id, owner, consumer",
+        "reset_id = $1",
+    ),
+    ("surveys", "reset_id, uuid", "reset_id = $1"),
+    ("systems", "id", "reset_id = $1"),
+    ("waypoints", "id", "reset_id = $1"),
+    ("waypoint_details", "id", "reset_id = $1"),
+    ("market_trades", "id, \"timestamp\"", "\"timestamp\" >= $2"),
+    (
+        "market_transactions",
+        "market_symbol, \"timestamp\", ship_symbol",
+        "\"timestamp\" >= $2",
+    ),
+    (
+        "shipyard_listings",
+        "shipyard_symbol, ship_type, \"timestamp\"",
+        "\"timestamp\" >= $2",
+    ),
+];
+
+#[derive(QueryableByName)]
+struct JsonRow {
+    #[diesel(sql_type = Text)]
+    row: String,
+}
+
+enum Page {
+    Rows(&'static str, Vec<JsonRow>),
+    // every table was dumped, the archive can be finalised
+    Done,
+}
+
+impl DbClient {
+    pub async fn backup_reset(&self, dir: &Path, retention: usize) -> Result<PathBuf, DbError> {
+        let backup_dir = dir.join(self.reset_date());
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+        let path = backup_dir.join(format!("{}.jsonl.gz", timestamp));
+
+        let (tx, rx) = mpsc::channel(WRITE_QUEUE_PAGES);
+        let writer = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || write_archive(&backup_dir, &path, retention, rx))
+        };
+        let dumped = self.dump_tables(&tx).await;
+        if dumped.is_ok() {
+            // the writer only stops early on an error, which it returns below
+            let _ = tx.send(Page::Done).await;
+        }
+        drop(tx);
+        let written = writer
+            .await
+            .map_err(|e| DbError::Io(std::io::Error::other(e)))
+            .and_then(|result| result);
+        dumped?;
+        written?;
+        info!("Wrote backup {}", path.display());
+        Ok(path)
+    }
+
+    // Pages through each table in primary key order, resuming after the last row sent
+    async fn dump_tables(&self, tx: &mpsc::Sender<Page>) -> Result<(), DbError> {
+        for (table, key, filter) in BACKUP_TABLES {
+            let mut last: Option<String> = None;
+            let mut count = 0;
+            loop {
+                let rows: Vec<JsonRow> = match &last {
+                    None => {
+                        let query = format!(
+                            "SELECT row_to_json(t)::text AS row FROM {} t WHERE {} ORDER BY {} LIMIT $3",
+                            table, filter, key
+                        );
+                        diesel::sql_query(query)
+                            .bind::<Text, _>(self.reset_date())
+                            .bind::<Timestamptz, _>(self.reset_start())
+                            .bind::<BigInt, _>(BATCH_SIZE)
+                            .load(&mut self.conn().await?)
+                            .await?
+                    }
+                    Some(last) => {
+                        let query = format!(
+                            "SELECT row_to_json(t)::text AS row FROM {table} t WHERE {filter} \
+                             AND ({key}) > (SELECT {key} FROM json_populate_record(NULL::{table}, $4::json)) \
+                             ORDER BY {key} LIMIT $3",
+                        );
+                        diesel::sql_query(query)
+                            .bind::<Text, _>(self.reset_date())
+                            .bind::<Timestamptz, _>(self.reset_start())
+                            .bind::<BigInt, _>(BATCH_SIZE)
+                            .bind::<Text, _>(last)
+                            .load(&mut self.conn().await?)
+                            .await?
+                    }
+                };
+                let num_rows = rows.len() as i64;
+                count += num_rows;
+                last = rows.last().map(|r| r.row.clone());
+                if num_rows > 0 && tx.send(Page::Rows(table, rows)).await.is_err() {
+                    // the writer failed, and reports why
+                    return Ok(());
+                }
+                if num_rows < BATCH_SIZE {
+                    break;
+                }
+            }
+            debug!("Backed up {} rows from {}", count, table);
+        }
+        Ok(())
+    }
+}
+
+// Written to a temporary file, renamed into place once every table has been dumped
+fn write_archive(
+    backup_dir: &Path,
+    path: &Path,
+    retention: usize,
+    mut rx: mpsc::Receiver<Page>,
+) -> Result<(), DbError> {
+    std::fs::create_dir_all(backup_dir)?;
+    let tmp_path = path.with_extension("tmp");
+    let file = File::create(&tmp_path)?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    let written = loop {
+        match rx.blocking_recv() {
+            Some(Page::Rows(table, rows)) => {
+                for row in rows {
+                    let row: Value = serde_json::from_str(&row.row)?;
+                    let line = json!({ "table": table, "row": row });
+                    writeln!(encoder, "{}", line)?;
+                }
+            }
+            Some(Page::Done) => break true,
+            None => break false,
+        }
+    };
+    if !written {
+        // the dump failed part way, its error is reported instead
+        drop(encoder);
+        let _ = std::fs::remove_file(&tmp_path);
+        return Ok(());
+    }
+    encoder.finish()?;
+    std::fs::rename(&tmp_path, path)?;
+
+    if let Err(e) = prune_backups(backup_dir, retention) {
+        warn!("Failed to prune backups in {}: {}", backup_dir.display(), e);
+    }
+    Ok(())
+}
+
+// Delete all but the newest `retention` archives. Archive names sort chronologically.
+fn prune_backups(backup_dir: &Path, retention: usize) -> std::io::Result<()> {
+    let mut archives: Vec<PathBuf> = std::fs::read_dir(backup_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.to_string_lossy().ends_with(".jsonl.gz"))
+        .collect();
+    archives.sort();
+    let num_delete = archives.len().saturating_sub(retention);
+    for path in archives.into_iter().take(num_delete) {
+        info!("Deleting old backup {}", path.display());
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
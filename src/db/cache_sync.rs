@@ -0,0 +1,159 @@
+//!
+//! Cross-process cache invalidation over Postgres LISTEN/NOTIFY.
+//!
+//! Processes sharing a database each keep their own market and shipyard snapshots. With CACHE_SYNC
+//! set, a process that saves a snapshot notifies the others, which drop their copy and reload it
+//! from the database. Notifications are tagged with the sending process and the reset, so a process
+//! ignores its own and those of other resets.
+//!
+use super::{DbClient, DbError};
+use crate::models::WaypointSymbol;
+use diesel::sql_types::Text;
+use diesel_async::RunQueryDsl as _;
+use futures::StreamExt as _;
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+use uuid::Uuid;
+
+const CHANNEL: &str = "st_cache";
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+lazy_static! {
+    static ref PROCESS_ID: String = Uuid::new_v4().to_string();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheInvalidation {
+    Market(WaypointSymbol),
+    Shipyard(WaypointSymbol),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheNotification {
+    origin: String,
+    reset_id: String,
+    invalidation: CacheInvalidation,
+}
+
+// The invalidation in a notification, None if it's our own or from another reset
+fn parse_notification(payload: &str, origin: &str, reset_id: &str) -> Option<CacheInvalidation> {
+    let notification: CacheNotification = match serde_json::from_str(payload) {
+        Ok(notification) => notification,
+        Err(e) => {
+            warn!("Invalid cache notification {}: {}", payload, e);
+            return None;
+        }
+    };
+    if notification.origin == *origin || notification.reset_id != reset_id {
+        return None;
+    }
+    Some(notification.invalidation)
+}
+
+impl DbClient {
+    pub async fn notify_cache_invalidation(
+        &self,
+        invalidation: &CacheInvalidation,
+    ) -> Result<(), DbError> {
+        let payload = serde_json::to_string(&CacheNotification {
+            origin: PROCESS_ID.clone(),
+            reset_id: self.reset_date().to_string(),
+            invalidation: invalidation.clone(),
+        })
+        .unwrap();
+        diesel::sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(CHANNEL)
+            .bind::<Text, _>(payload)
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    // Invalidations sent by other processes, reconnecting if the connection drops
+    pub fn listen_cache_invalidations(&self) -> mpsc::UnboundedReceiver<CacheInvalidation> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let reset_id = self.reset_date().to_string();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = listen(&reset_id, &tx).await {
+                    warn!("Cache sync connection lost: {}", e);
+                }
+                if tx.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+        rx
+    }
+}
+
+async fn listen(
+    reset_id: &str,
+    tx: &mpsc::UnboundedSender<CacheInvalidation>,
+) -> Result<(), tokio_postgres::Error> {
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let (client, mut connection) = tokio_postgres::connect(&database_url, NoTls).await?;
+
+    // the connection only makes progress while its messages are polled
+    let forward = {
+        let tx = tx.clone();
+        let reset_id = reset_id.to_string();
+        tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                if let AsyncMessage::Notification(notification) = message? {
+                    if let Some(invalidation) =
+                        parse_notification(notification.payload(), &PROCESS_ID, &reset_id)
+                    {
+                        debug!("Cache invalidation: {:?}", invalidation);
+                        if tx.send(invalidation).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    };
+    client.batch_execute(&format!("LISTEN {}", CHANNEL)).await?;
+    info!("Listening for cache invalidations");
+    forward.await.unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_notification() {
+        let origin = Uuid::new_v4().to_string();
+        let other = Uuid::new_v4().to_string();
+        let market = CacheInvalidation::Market(WaypointSymbol::new("X1-S1-A1"));
+        let payload = |origin: &str, reset_id: &str| {
+            serde_json::to_string(&CacheNotification {
+                origin: origin.to_string(),
+                reset_id: reset_id.to_string(),
+                invalidation: market.clone(),
+            })
+            .unwrap()
+        };
+        assert_eq!(
+            parse_notification(&payload(&other, "2024-03-24"), &origin, "2024-03-24"),
+            Some(market.clone())
+        );
+        // our own, and another reset's
+        assert_eq!(
+            parse_notification(&payload(&origin, "2024-03-24"), &origin, "2024-03-24"),
+            None
+        );
+        assert_eq!(
+            parse_notification(&payload(&other, "2024-03-10"), &origin, "2024-03-24"),
+            None
+        );
+        assert_eq!(parse_notification("{}", &origin, "2024-03-24"), None);
+    }
+}
@@ -2,6 +2,7 @@ use chrono::{DateTime, Utc};
 use diesel::{
     associations::Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable,
 };
+use serde::Serialize;
 
 #[derive(Debug, Clone, Insertable)]
 #[diesel(table_name = crate::schema::systems)]
@@ -36,6 +37,10 @@ pub struct NewWaypointDetails<'a> {
     pub is_shipyard: bool,
     pub is_uncharted: bool,
     pub is_under_construction: bool,
+    pub modifiers: Vec<&'a str>,
+    pub orbits: Option<&'a str>,
+    pub chart_submitted_by: Option<&'a str>,
+    pub chart_submitted_on: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Insertable)]
@@ -86,6 +91,10 @@ pub struct WaypointDetails {
     pub is_shipyard: bool,
     pub is_uncharted: bool,
     pub is_under_construction: bool,
+    pub modifiers: Vec<String>,
+    pub orbits: Option<String>,
+    pub chart_submitted_by: Option<String>,
+    pub chart_submitted_on: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Queryable, QueryableByName, Selectable)]
@@ -96,3 +105,75 @@ pub struct JumpGateConnections {
     pub is_under_construction: bool,
     pub edges: Vec<String>,
 }
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::survey_consumption)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SurveyConsumption {
+    // agent that took the surveys
+    pub owner: String,
+    // agent that extracted with them
+    pub consumer: String,
+    pub extractions: i64,
+    pub units: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::net_worth_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NetWorthSample {
+    pub timestamp: DateTime<Utc>,
+    pub credits: i64,
+    pub cargo_value: i64,
+    pub ship_value: i64,
+    pub total: i64,
+}
+
+// Units of a construction material a ship delivered, summed over its deliveries
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ConstructionContribution {
+    pub waypoint_symbol: String,
+    pub ship_symbol: String,
+    pub trade_symbol: String,
+    pub units: i64,
+    pub deliveries: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::job_assignments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct JobAssignment {
+    pub ship_symbol: String,
+    pub job_id: String,
+    pub assigned_at: DateTime<Utc>,
+    // None while the assignment is current
+    pub unassigned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::ship_models)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ShipModelRecord {
+    pub ship_type: String,
+    pub frame: String,
+    pub reactor: String,
+    pub engine: String,
+    pub modules: Vec<String>,
+    pub mounts: Vec<String>,
+    pub cargo_capacity: i32,
+    pub fuel_capacity: i32,
+    pub speed: i32,
+    pub min_price: i32,
+    pub max_price: i32,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::shipyard_listings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ShipListingSample {
+    pub timestamp: DateTime<Utc>,
+    pub shipyard_symbol: String,
+    pub supply: String,
+    pub activity: Option<String>,
+    pub purchase_price: i32,
+}
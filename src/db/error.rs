@@ -0,0 +1,122 @@
+/// Failed database operations, returned once any retries have run out
+use log::*;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+const LOAD_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum DbError {
+    // no connection from the pool, e.g. the database is restarting
+    Connection(String),
+    Query(diesel::result::Error),
+    // a stored value that doesn't have the shape we expected
+    Json(serde_json::Error),
+    // writing a backup archive
+    Io(std::io::Error),
+}
+
+impl DbError {
+    // Connection drops and serialization conflicts, worth retrying the query
+    pub fn is_transient(&self) -> bool {
+        use diesel::result::{DatabaseErrorKind, Error};
+        matches!(
+            self,
+            DbError::Query(Error::DatabaseError(
+                DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::SerializationFailure,
+                _
+            ))
+        )
+    }
+}
+
+impl From<diesel::result::Error> for DbError {
+    fn from(e: diesel::result::Error) -> Self {
+        DbError::Query(e)
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(e: serde_json::Error) -> Self {
+        DbError::Json(e)
+    }
+}
+
+impl From<std::io::Error> for DbError {
+    fn from(e: std::io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Connection(e) => write!(f, "No database connection: {}", e),
+            DbError::Query(e) => write!(f, "DB Query error: {}", e),
+            DbError::Json(e) => write!(f, "Invalid stored value: {}", e),
+            DbError::Io(e) => write!(f, "Backup file error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+// For operations the caller can carry on without, e.g. a snapshot the next save rewrites anyway
+pub fn ok_or_warn<T>(result: Result<T, DbError>, action: &str) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Failed to {}: {}", action, e);
+            None
+        }
+    }
+}
+
+// For loads a ship script can't start without, e.g. its saved state. Ship scripts wait for the
+// database instead of panicking, the ship sits idle until the load succeeds
+pub async fn load_until_ok<T, F, Fut>(action: &str, mut op: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DbError>>,
+{
+    loop {
+        match op().await {
+            Ok(value) => return value,
+            Err(e) => {
+                warn!(
+                    "Failed to {}, retrying in {:?}: {}",
+                    action, LOAD_RETRY_DELAY, e
+                );
+                tokio::time::sleep(LOAD_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    #[test]
+    fn test_db_error() {
+        let closed = DbError::from(Error::DatabaseError(
+            DatabaseErrorKind::ClosedConnection,
+            Box::new("server closed the connection".to_string()),
+        ));
+        assert!(closed.is_transient());
+        assert!(closed.to_string().starts_with("DB Query error"));
+
+        let unique = DbError::from(Error::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("duplicate key".to_string()),
+        ));
+        assert!(!unique.is_transient());
+        assert!(!DbError::from(Error::NotFound).is_transient());
+        assert!(!DbError::Connection("timed out".to_string()).is_transient());
+
+        let json = serde_json::from_str::<i64>("{").unwrap_err();
+        assert!(!DbError::from(json).is_transient());
+    }
+}
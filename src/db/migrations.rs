@@ -0,0 +1,328 @@
+//!
+//! Schema changes applied at startup.
+//!
+//! spacetraders_schema.sql is a dump of the current schema, so databases created from an older dump
+//! are brought up to date here. Every statement is idempotent and runs on each startup.
+//!
+//! market_trades and market_transactions grow by millions of rows per reset. They're indexed for the
+//! per-market, per-good lookups of the analytics queries, and if TimescaleDB is installed
+//! market_transactions is partitioned by time like market_trades.
+//!
+use super::{DbClient, DbError};
+use diesel::sql_types::{BigInt, Text};
+use diesel::QueryableByName;
+use diesel_async::RunQueryDsl as _;
+use log::*;
+
+// (name, statement)
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "market_trades_market_symbol_idx",
+        "CREATE INDEX IF NOT EXISTS market_trades_market_symbol_idx ON public.market_trades USING btree (market_symbol, symbol, \"timestamp\")",
+    ),
+    (
+        "market_transactions_market_symbol_idx",
+        "CREATE INDEX IF NOT EXISTS market_transactions_market_symbol_idx ON public.market_transactions USING btree (market_symbol, symbol, \"timestamp\")",
+    ),
+    (
+        "waypoint_details_chart",
+        "ALTER TABLE public.waypoint_details ADD COLUMN IF NOT EXISTS chart_submitted_by text, ADD COLUMN IF NOT EXISTS chart_submitted_on timestamp with time zone",
+    ),
+    (
+        "surveys_owner",
+        "ALTER TABLE public.surveys ADD COLUMN IF NOT EXISTS owner text DEFAULT '' NOT NULL",
+    ),
+    (
+        "survey_consumption",
+        "CREATE TABLE IF NOT EXISTS public.survey_consumption (reset_id text NOT NULL, owner text NOT NULL, consumer text NOT NULL, extractions bigint NOT NULL, units bigint NOT NULL, PRIMARY KEY (reset_id, owner, consumer))",
+    ),
+    (
+        "waypoint_details_modifiers",
+        "ALTER TABLE public.waypoint_details ADD COLUMN IF NOT EXISTS modifiers text[] DEFAULT '{}'::text[] NOT NULL",
+    ),
+    (
+        "waypoint_details_orbits",
+        "ALTER TABLE public.waypoint_details ADD COLUMN IF NOT EXISTS orbits text",
+    ),
+    (
+        "net_worth_history",
+        "CREATE TABLE IF NOT EXISTS public.net_worth_history (reset_id text NOT NULL, callsign text NOT NULL, \"timestamp\" timestamp with time zone NOT NULL, credits bigint NOT NULL, cargo_value bigint NOT NULL, ship_value bigint NOT NULL, total bigint NOT NULL, PRIMARY KEY (reset_id, callsign, \"timestamp\"))",
+    ),
+    (
+        "shipyard_listings",
+        "CREATE TABLE IF NOT EXISTS public.shipyard_listings (\"timestamp\" timestamp with time zone NOT NULL, shipyard_symbol text NOT NULL, ship_type text NOT NULL, supply text NOT NULL, activity text, purchase_price integer NOT NULL, frame text NOT NULL, reactor text NOT NULL, engine text NOT NULL, modules text[] NOT NULL, mounts text[] NOT NULL, PRIMARY KEY (shipyard_symbol, ship_type, \"timestamp\"))",
+    ),
+    (
+        "shipyard_listings_ship_type_idx",
+        "CREATE INDEX IF NOT EXISTS shipyard_listings_ship_type_idx ON public.shipyard_listings USING btree (ship_type, \"timestamp\" DESC)",
+    ),
+    (
+        "job_assignments",
+        "CREATE TABLE IF NOT EXISTS public.job_assignments (reset_id text NOT NULL, callsign text NOT NULL, ship_symbol text NOT NULL, job_id text NOT NULL, assigned_at timestamp with time zone NOT NULL, unassigned_at timestamp with time zone, PRIMARY KEY (reset_id, callsign, ship_symbol, assigned_at))",
+    ),
+    (
+        "job_assignments_open_idx",
+        "CREATE UNIQUE INDEX IF NOT EXISTS job_assignments_open_idx ON public.job_assignments USING btree (reset_id, callsign, ship_symbol) WHERE (unassigned_at IS NULL)",
+    ),
+    (
+        "ship_models",
+        "CREATE TABLE IF NOT EXISTS public.ship_models (reset_id text NOT NULL, ship_type text NOT NULL, frame text NOT NULL, reactor text NOT NULL, engine text NOT NULL, modules text[] NOT NULL, mounts text[] NOT NULL, cargo_capacity integer NOT NULL, fuel_capacity integer NOT NULL, speed integer NOT NULL, min_price integer NOT NULL, max_price integer NOT NULL, updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL, PRIMARY KEY (reset_id, ship_type))",
+    ),
+    (
+        "construction_deliveries",
+        "CREATE TABLE IF NOT EXISTS public.construction_deliveries (reset_id text NOT NULL, \"timestamp\" timestamp with time zone NOT NULL, waypoint_symbol text NOT NULL, ship_symbol text NOT NULL, trade_symbol text NOT NULL, units integer NOT NULL, PRIMARY KEY (reset_id, ship_symbol, \"timestamp\"))",
+    ),
+    // Several ships can trade at a market in the same instant, the old key only had (market_symbol, timestamp)
+    (
+        "market_transactions_pkey",
+        "DO $$ BEGIN IF NOT EXISTS (SELECT 1 FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid WHERE c.relname = 'market_transactions_pkey' AND i.indnatts = 3) THEN ALTER TABLE public.market_transactions DROP CONSTRAINT IF EXISTS market_transactions_pkey, ADD CONSTRAINT market_transactions_pkey PRIMARY KEY (market_symbol, \"timestamp\", ship_symbol); END IF; END $$",
+    ),
+];
+
+// Only applied if the timescaledb extension is installed
+const TIMESCALE_MIGRATIONS: &[(&str, &str)] = &[(
+    "market_transactions_hypertable",
+    "SELECT 1 FROM create_hypertable('public.market_transactions', 'timestamp', chunk_time_interval => INTERVAL '1 day', if_not_exists => TRUE, migrate_data => TRUE)",
+)];
+
+// Tables of spacetraders_schema.sql the client reads or writes
+const TABLES: &[&str] = &[
+    "construction_deliveries",
+    "general_lookup",
+    "job_assignments",
+    "jumpgate_connections",
+    "market_trades",
+    "market_transactions",
+    "net_worth_history",
+    "ship_models",
+    "shipyard_listings",
+    "survey_consumption",
+    "surveys",
+    "systems",
+    "waypoint_details",
+    "waypoints",
+];
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct Name {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+impl DbClient {
+    pub async fn run_migrations(&self) -> Result<(), DbError> {
+        let start = std::time::Instant::now();
+        let mut conn = self.conn().await?;
+        let timescale: Vec<Count> = diesel::sql_query(
+            "SELECT count(*) AS count FROM pg_extension WHERE extname = 'timescaledb'",
+        )
+        .load(&mut conn)
+        .await?;
+        let timescale = timescale[0].count > 0;
+        let migrations = match timescale {
+            true => [MIGRATIONS, TIMESCALE_MIGRATIONS].concat(),
+            false => MIGRATIONS.to_vec(),
+        };
+        for (name, sql) in &migrations {
+            debug!("Applying migration {}", name);
+            diesel::sql_query(*sql).execute(&mut conn).await?;
+        }
+        if !timescale {
+            info!("TimescaleDB not installed, market_transactions is not partitioned");
+        }
+        let duration = start.elapsed().as_millis() as f64 / 1000.0;
+        info!(
+            "Applied {} migrations in {:.3}s",
+            migrations.len(),
+            duration
+        );
+        Ok(())
+    }
+
+    // Tables and migration indexes missing from the database
+    pub async fn missing_schema(&self) -> Result<Vec<String>, DbError> {
+        let mut conn = self.conn().await?;
+        let tables: Vec<Name> = diesel::sql_query(
+            "SELECT table_name AS name FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .load(&mut conn)
+        .await?;
+        let indexes: Vec<Name> = diesel::sql_query(
+            "SELECT indexname AS name FROM pg_indexes WHERE schemaname = 'public'",
+        )
+        .load(&mut conn)
+        .await?;
+        let tables = tables.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        let indexes = indexes.into_iter().map(|i| i.name).collect::<Vec<_>>();
+        let missing_tables = TABLES
+            .iter()
+            .filter(|table| !tables.iter().any(|t| t == *table))
+            .map(|table| format!("table {}", table));
+        let missing_indexes = MIGRATIONS
+            .iter()
+            .filter(|(_, sql)| sql.starts_with("CREATE INDEX"))
+            .filter(|(name, _)| !indexes.iter().any(|i| i == name))
+            .map(|(name, _)| format!("index {}", name));
+        Ok(missing_tables.chain(missing_indexes).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{MarketTransaction, WaypointSymbol};
+    use diesel_async::{AsyncConnection as _, AsyncPgConnection, SimpleAsyncConnection as _};
+
+    #[test]
+    fn test_migrations_idempotent() {
+        let all = [MIGRATIONS, TIMESCALE_MIGRATIONS].concat();
+        let mut names = all.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), all.len());
+        for (name, sql) in &all {
+            assert!(
+                sql.contains("IF NOT EXISTS") || sql.contains("if_not_exists => TRUE"),
+                "migration {} is not idempotent",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_tables_in_schema() {
+        let schema = include_str!("../schema.rs");
+        for table in TABLES {
+            assert!(
+                schema.contains(&format!("    {} (", table)),
+                "table {} is not in schema.rs",
+                table
+            );
+        }
+    }
+
+    #[derive(QueryableByName)]
+    struct PlanLine {
+        #[diesel(sql_type = Text, column_name = "QUERY PLAN")]
+        line: String,
+    }
+
+    // Needs a database: DATABASE_URL=... cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn test_query_plans() {
+        let db = DbClient::new("test").await;
+        db.run_migrations().await.unwrap();
+        let mut conn = db.conn().await.unwrap();
+        conn.begin_test_transaction().await.unwrap();
+        for sql in [
+            "INSERT INTO market_trades (timestamp, market_symbol, symbol, trade_volume, type, supply, activity, purchase_price, sell_price) VALUES (now(), 'X1-AB12-A1', 'IRON', 60, 'EXPORT', 'HIGH', NULL, 100, 90)",
+            "INSERT INTO market_transactions (timestamp, market_symbol, symbol, ship_symbol, type, units, price_per_unit, total_price) VALUES (now(), 'X1-AB12-A1', 'IRON', 'A-1', 'PURCHASE', 10, 100, 1000)",
+            "SET LOCAL enable_seqscan = off",
+        ] {
+            diesel::sql_query(sql).execute(&mut conn).await.unwrap();
+        }
+        let plans = [
+            (
+                "market_trades_market_symbol_idx",
+                "EXPLAIN SELECT DISTINCT ON (market_symbol, symbol) market_symbol, symbol, trade_volume FROM market_trades WHERE market_symbol = ANY(ARRAY['X1-AB12-A1']) AND timestamp >= now() - INTERVAL '1 day' ORDER BY market_symbol, symbol, timestamp",
+            ),
+            (
+                "market_trades_market_symbol_idx",
+                "EXPLAIN SELECT date_trunc('hour', timestamp) AS hour, market_symbol, symbol, max(trade_volume) AS trade_volume FROM market_trades WHERE market_symbol = ANY(ARRAY['X1-AB12-A1']) AND timestamp >= now() - INTERVAL '3 days' GROUP BY 1, 2, 3 ORDER BY 1",
+            ),
+            (
+                "market_transactions_market_symbol_idx",
+                "EXPLAIN SELECT * FROM market_transactions WHERE market_symbol = 'X1-AB12-A1' AND symbol = 'IRON' ORDER BY timestamp DESC LIMIT 100",
+            ),
+        ];
+        for (index, sql) in plans {
+            let plan: Vec<PlanLine> = diesel::sql_query(sql).load(&mut conn).await.unwrap();
+            let plan = plan
+                .into_iter()
+                .map(|l| l.line)
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert!(plan.contains(index), "{} not used:\n{}", index, plan);
+        }
+    }
+
+    // Needs a database: DATABASE_URL=... cargo test -- --ignored
+    // Creates a scratch database next to it, loaded from the schema dump the repo started with
+    #[tokio::test]
+    #[ignore]
+    async fn test_migrate_baseline_schema() {
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let (server, _) = database_url.rsplit_once('/').unwrap();
+        let scratch_url = format!("{}/st_migrations_test", server);
+        let mut conn = AsyncPgConnection::establish(&database_url).await.unwrap();
+        for sql in [
+            "DROP DATABASE IF EXISTS st_migrations_test",
+            "CREATE DATABASE st_migrations_test",
+        ] {
+            diesel::sql_query(sql).execute(&mut conn).await.unwrap();
+        }
+
+        // without timescaledb, and public already exists in a new database
+        let baseline = include_str!("../../fixtures/baseline_schema.sql")
+            .lines()
+            .filter(|line| !line.contains("_timescaledb") && !line.contains("SCHEMA public"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut scratch = AsyncPgConnection::establish(&scratch_url).await.unwrap();
+        scratch.batch_execute(&baseline).await.unwrap();
+        drop(scratch);
+
+        let db = DbClient::connect(&scratch_url, "test").await;
+        assert!(!db.missing_schema().await.unwrap().is_empty());
+        db.run_migrations().await.unwrap();
+        db.run_migrations().await.unwrap();
+        assert_eq!(db.missing_schema().await.unwrap(), Vec::<String>::new());
+
+        // two ships trading at a market in the same instant
+        let market = WaypointSymbol::new("X1-AB12-A1");
+        let timestamp = chrono::Utc::now();
+        let transactions = ["A-1", "A-2"]
+            .into_iter()
+            .map(|ship_symbol| MarketTransaction {
+                waypoint_symbol: market.clone(),
+                ship_symbol: ship_symbol.to_string(),
+                trade_symbol: "IRON".to_string(),
+                _type: "PURCHASE".to_string(),
+                units: 10,
+                price_per_unit: 100,
+                total_price: 1000,
+                timestamp,
+            })
+            .collect::<Vec<_>>();
+        db.upsert_transactions(&market, &transactions)
+            .await
+            .unwrap();
+        db.upsert_transactions(&market, &transactions)
+            .await
+            .unwrap();
+
+        // tables added since the baseline
+        db.migrate_legacy_job_assignments("A").await.unwrap();
+        assert!(db.get_job_assignments("A").await.unwrap().is_empty());
+        let columns: Vec<Name> = diesel::sql_query(
+            "SELECT column_name AS name FROM information_schema.columns WHERE table_name = 'waypoint_details'",
+        )
+        .load(&mut db.conn().await.unwrap())
+        .await
+        .unwrap();
+        for column in ["modifiers", "orbits", "chart_submitted_by"] {
+            assert!(
+                columns.iter().any(|c| c.name == column),
+                "{} missing",
+                column
+            );
+        }
+    }
+}
@@ -1,46 +1,95 @@
+pub mod backup;
+pub mod cache_sync;
 pub mod db_models;
+pub mod error;
+pub mod migrations;
+pub mod retention;
+pub mod versioned;
 
+use crate::agent_controller::ledger::NetWorth;
+use crate::alerts::ALERTS;
+use crate::config::CONFIG;
+use crate::logistics_planner::market_impact::TransactionHistory;
 use crate::logistics_planner::Task;
+use crate::market_health::MarketHealthReport;
 use crate::models::Construction;
 use crate::models::KeyedSurvey;
+use crate::ops_report::OpsReport;
 use crate::schema::*;
+use crate::telemetry;
+use crate::trade_volume::TradeVolumeHistory;
+use crate::universe::transaction_costs::TradeSide;
+use crate::util::retry::{retry, RetryPolicy};
 use crate::{
-    logistics_planner::ShipSchedule,
+    logistics_planner::{ScheduleProgress, ShipSchedule},
     models::{
-        Market, MarketRemoteView, Shipyard, ShipyardRemoteView, SystemSymbol, WaypointSymbol,
-        WithTimestamp,
+        Market, MarketRemoteView, MarketTransaction, ShipModel, Shipyard, ShipyardRemoteView,
+        SystemSymbol, WaypointSymbol, WithTimestamp,
     },
 };
 use chrono::DateTime;
 use chrono::Utc;
 use dashmap::DashMap;
-use diesel::sql_types::Integer;
+use diesel::sql_types::{Array, Integer, Text, Timestamptz};
 use diesel::ExpressionMethods as _;
 use diesel::OptionalExtension as _;
 use diesel::QueryDsl as _;
 use diesel::QueryableByName;
 use diesel::SelectableHelper as _;
+use diesel::TextExpressionMethods as _;
 use diesel_async::pooled_connection::deadpool::Object;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_async::pooled_connection::AsyncDieselConnectionManager;
 use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl as _;
+pub use error::{load_until_ok, ok_or_warn, DbError};
 use log::*;
+use opentelemetry::KeyValue;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use uuid::Uuid;
+use versioned::Versioned;
+
+impl Versioned for DashMap<String, (Task, String, DateTime<Utc>)> {
+    const TYPE_NAME: &'static str = "TaskManagerState";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for DashMap<String, WaypointSymbol> {
+    const TYPE_NAME: &'static str = "ProbeJumpgateReservations";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for DashMap<String, SystemSymbol> {
+    const TYPE_NAME: &'static str = "ExplorerReservations";
+    const VERSION: u32 = 1;
+}
+
+// Connection drops and serialization conflicts, e.g. while the database restarts
+pub const DB_RETRY: RetryPolicy = RetryPolicy::exponential(
+    std::time::Duration::from_millis(100),
+    std::time::Duration::from_secs(5),
+    5,
+);
 
 #[derive(Clone)]
 pub struct DbClient {
     db: Pool<AsyncPgConnection>,
     reset_id: Arc<String>,
+    // for getting a connection and for key-value reads and writes
+    retry_policy: RetryPolicy,
 }
 
 impl DbClient {
     pub async fn new(reset_identifier: &str) -> DbClient {
         let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        DbClient::connect(&database_url, reset_identifier).await
+    }
+
+    pub async fn connect(database_url: &str, reset_identifier: &str) -> DbClient {
         let db = {
             let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(database_url);
             Pool::builder(manager).max_size(5).build().unwrap()
@@ -64,61 +113,103 @@ impl DbClient {
         DbClient {
             db,
             reset_id: Arc::new(reset_identifier.to_string()),
+            retry_policy: RetryPolicy {
+                max_attempts: CONFIG.db_retry_attempts,
+                ..DB_RETRY
+            },
         }
     }
 
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
     pub fn reset_date(&self) -> &str {
         self.reset_id.as_str()
     }
 
-    pub async fn conn(&self) -> Object<AsyncPgConnection> {
-        self.db
-            .get()
-            .await
-            .expect("Timed out waiting for a database connection")
+    pub async fn conn(&self) -> Result<Object<AsyncPgConnection>, DbError> {
+        let start = std::time::Instant::now();
+        let conn = retry(
+            &self.retry_policy,
+            "db connection",
+            |_| true,
+            || self.db.get(),
+        )
+        .await
+        .map_err(|e| DbError::Connection(e.to_string()))?;
+        ALERTS.record_db_pool_wait(start.elapsed().as_millis());
+        Ok(conn)
     }
 
-    pub async fn get_value<T>(&self, key: &str) -> Option<T>
+    pub async fn get_value<T>(&self, key: &str) -> Result<Option<T>, DbError>
     where
         T: Sized + DeserializeOwned,
     {
         debug!("db get: {}", key);
-        let value_opt: Option<Value> = general_lookup::table
-            .select(general_lookup::value)
-            .filter(general_lookup::reset_id.eq(self.reset_date()))
-            .filter(general_lookup::key.eq(key))
-            .first(&mut self.conn().await)
-            .await
-            .optional()
-            .expect("DB Query error");
-        value_opt.map(|data| serde_json::from_value(data).unwrap())
+        let query = retry(
+            &self.retry_policy,
+            "db get",
+            DbError::is_transient,
+            || async {
+                let value = general_lookup::table
+                    .select(general_lookup::value)
+                    .filter(general_lookup::reset_id.eq(self.reset_date()))
+                    .filter(general_lookup::key.eq(key))
+                    .first(&mut self.conn().await?)
+                    .await
+                    .optional()?;
+                Ok(value)
+            },
+        );
+        let value_opt: Option<Value> = telemetry::in_span(
+            "db_get",
+            vec![KeyValue::new("db.key", key.to_string())],
+            query,
+        )
+        .await?;
+        Ok(value_opt.map(serde_json::from_value).transpose()?)
     }
 
-    pub async fn set_value<T>(&self, key: &str, value: &T)
+    pub async fn set_value<T>(&self, key: &str, value: &T) -> Result<(), DbError>
     where
         T: Serialize + ?Sized,
     {
         debug!("db set: {}", key);
-        let value: Value = serde_json::to_value(value).unwrap();
-        diesel::insert_into(general_lookup::table)
-            .values((
-                general_lookup::reset_id.eq(self.reset_date()),
-                general_lookup::key.eq(key),
-                general_lookup::value.eq(&value),
-            ))
-            .on_conflict((general_lookup::reset_id, general_lookup::key))
-            .do_update()
-            .set(general_lookup::value.eq(&value))
-            .execute(&mut self.conn().await)
-            .await
-            .expect("DB Query error");
+        let value: Value = serde_json::to_value(value)?;
+        let query = retry(
+            &self.retry_policy,
+            "db set",
+            DbError::is_transient,
+            || async {
+                diesel::insert_into(general_lookup::table)
+                    .values((
+                        general_lookup::reset_id.eq(self.reset_date()),
+                        general_lookup::key.eq(key),
+                        general_lookup::value.eq(&value),
+                    ))
+                    .on_conflict((general_lookup::reset_id, general_lookup::key))
+                    .do_update()
+                    .set(general_lookup::value.eq(&value))
+                    .execute(&mut self.conn().await?)
+                    .await?;
+                Ok(())
+            },
+        );
+        telemetry::in_span(
+            "db_set",
+            vec![KeyValue::new("db.key", key.to_string())],
+            query,
+        )
+        .await
     }
 
-    pub async fn get_agent_token(&self, callsign: &str) -> Option<String> {
+    pub async fn get_agent_token(&self, callsign: &str) -> Result<Option<String>, DbError> {
         self.get_value(&format!("registrations/{}", callsign)).await
     }
 
-    pub async fn save_agent_token(&self, callsign: &str, token: &str) {
+    pub async fn save_agent_token(&self, callsign: &str, token: &str) -> Result<(), DbError> {
         self.set_value(&format!("registrations/{}", callsign), token)
             .await
     }
@@ -141,16 +232,26 @@ impl DbClient {
     //     self.set_value(&key, waypoints).await
     // }
 
-    pub async fn get_market_remote(&self, symbol: &WaypointSymbol) -> Option<MarketRemoteView> {
+    pub async fn get_market_remote(
+        &self,
+        symbol: &WaypointSymbol,
+    ) -> Result<Option<MarketRemoteView>, DbError> {
         self.get_value(&format!("markets_remote/{}", symbol)).await
     }
 
-    pub async fn save_market_remote(&self, symbol: &WaypointSymbol, market: &MarketRemoteView) {
+    pub async fn save_market_remote(
+        &self,
+        symbol: &WaypointSymbol,
+        market: &MarketRemoteView,
+    ) -> Result<(), DbError> {
         let key = format!("markets_remote/{}", symbol);
         self.set_value(&key, market).await
     }
 
-    pub async fn get_shipyard_remote(&self, symbol: &WaypointSymbol) -> Option<ShipyardRemoteView> {
+    pub async fn get_shipyard_remote(
+        &self,
+        symbol: &WaypointSymbol,
+    ) -> Result<Option<ShipyardRemoteView>, DbError> {
         let key = format!("shipyards_remote/{}", symbol);
         self.get_value(&key).await
     }
@@ -159,23 +260,33 @@ impl DbClient {
         &self,
         symbol: &WaypointSymbol,
         shipyard: &ShipyardRemoteView,
-    ) {
+    ) -> Result<(), DbError> {
         let key = format!("shipyards_remote/{}", symbol);
         self.set_value(&key, shipyard).await
     }
 
-    pub async fn get_market(&self, symbol: &WaypointSymbol) -> Option<WithTimestamp<Market>> {
+    pub async fn get_market(
+        &self,
+        symbol: &WaypointSymbol,
+    ) -> Result<Option<WithTimestamp<Market>>, DbError> {
         let key = format!("markets/{}", symbol);
         self.get_value(&key).await
     }
 
-    pub async fn save_market(&self, symbol: &WaypointSymbol, market: &WithTimestamp<Market>) {
+    pub async fn save_market(
+        &self,
+        symbol: &WaypointSymbol,
+        market: &WithTimestamp<Market>,
+    ) -> Result<(), DbError> {
         // save to snapshot market view
         let key = format!("markets/{}", symbol);
-        self.set_value(&key, &market).await;
+        self.set_value(&key, &market).await
     }
 
-    pub async fn insert_market_trades(&self, market: &WithTimestamp<Market>) {
+    pub async fn insert_market_trades(
+        &self,
+        market: &WithTimestamp<Market>,
+    ) -> Result<(), DbError> {
         let inserts = market
             .data
             .trade_goods
@@ -197,20 +308,161 @@ impl DbClient {
             .collect::<Vec<_>>();
         diesel::insert_into(market_trades::table)
             .values(&inserts)
-            .execute(&mut self.conn().await)
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    // First seen trade volume this reset for each (market, good)
+    pub async fn initial_trade_volumes(
+        &self,
+        markets: &[WaypointSymbol],
+    ) -> Result<BTreeMap<(WaypointSymbol, String), i64>, DbError> {
+        let first_seen: Vec<(String, String, i32)> = market_trades::table
+            .filter(market_trades::market_symbol.eq_any(markets.iter().map(|m| m.to_string())))
+            .filter(market_trades::timestamp.ge(self.reset_start()))
+            .distinct_on((market_trades::market_symbol, market_trades::symbol))
+            .order_by((
+                market_trades::market_symbol,
+                market_trades::symbol,
+                market_trades::timestamp,
+            ))
+            .select((
+                market_trades::market_symbol,
+                market_trades::symbol,
+                market_trades::trade_volume,
+            ))
+            .load(&mut self.conn().await?)
+            .await?;
+        Ok(first_seen
+            .into_iter()
+            .map(|(market_symbol, symbol, trade_volume)| {
+                (
+                    (WaypointSymbol::new(&market_symbol), symbol),
+                    trade_volume as i64,
+                )
+            })
+            .collect())
+    }
+
+    // Hourly trade volume observations since `since` (and the reset), in chronological order
+    // Aggregated in the database, every market snapshot is a row in market_trades
+    pub async fn trade_volume_history(
+        &self,
+        markets: &[WaypointSymbol],
+        since: DateTime<Utc>,
+    ) -> Result<TradeVolumeHistory, DbError> {
+        #[derive(QueryableByName)]
+        struct HourlyVolume {
+            #[diesel(sql_type = Timestamptz)]
+            hour: DateTime<Utc>,
+            #[diesel(sql_type = Text)]
+            market_symbol: String,
+            #[diesel(sql_type = Text)]
+            symbol: String,
+            #[diesel(sql_type = Integer)]
+            trade_volume: i32,
+        }
+        let rows: Vec<HourlyVolume> = diesel::sql_query(
+            "SELECT date_trunc('hour', timestamp) AS hour, market_symbol, symbol, max(trade_volume) AS trade_volume \
+             FROM market_trades WHERE market_symbol = ANY($1) AND timestamp >= $2 \
+             GROUP BY 1, 2, 3 ORDER BY 1",
+        )
+        .bind::<Array<Text>, _>(markets.iter().map(|m| m.to_string()).collect::<Vec<_>>())
+        .bind::<Timestamptz, _>(since.max(self.reset_start()))
+        .load(&mut self.conn().await?)
+        .await?;
+        let mut history = TradeVolumeHistory::new();
+        for row in rows {
+            history
+                .entry((WaypointSymbol::new(&row.market_symbol), row.symbol))
+                .or_default()
+                .push((row.hour, row.trade_volume as i64));
+        }
+        Ok(history)
+    }
+
+    pub async fn transaction_history(
+        &self,
+        markets: &[WaypointSymbol],
+    ) -> Result<TransactionHistory, DbError> {
+        let rows: Vec<(DateTime<Utc>, String, String, String, i32, i32)> =
+            market_transactions::table
+                .filter(
+                    market_transactions::market_symbol
+                        .eq_any(markets.iter().map(|m| m.to_string())),
+                )
+                .filter(market_transactions::timestamp.ge(self.reset_start()))
+                .order_by(market_transactions::timestamp)
+                .select((
+                    market_transactions::timestamp,
+                    market_transactions::market_symbol,
+                    market_transactions::symbol,
+                    market_transactions::type_,
+                    market_transactions::units,
+                    market_transactions::price_per_unit,
+                ))
+                .load(&mut self.conn().await?)
+                .await?;
+        let mut history = TransactionHistory::new();
+        for (timestamp, market_symbol, symbol, _type, units, price) in rows {
+            let Some(side) = TradeSide::from_transaction_type(&_type) else {
+                continue;
+            };
+            history
+                .entry((WaypointSymbol::new(&market_symbol), symbol, side))
+                .or_default()
+                .push((timestamp, units as i64, price as i64));
+        }
+        Ok(history)
+    }
+
+    // From the date the reset id starts with, e.g. "2024-01-28" or the dry run's "2024-01-28-dry-run".
+    // Ids without one (tests) don't bound the history
+    fn reset_start(&self) -> DateTime<Utc> {
+        self.reset_date()
+            .get(..10)
+            .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+            .unwrap_or(DateTime::UNIX_EPOCH)
+    }
+
+    pub async fn get_market_health(
+        &self,
+        system: &SystemSymbol,
+    ) -> Result<Option<MarketHealthReport>, DbError> {
+        let key = format!("market_health/{}", system);
+        self.get_value(&key).await
+    }
+
+    pub async fn save_market_health(&self, report: &MarketHealthReport) -> Result<(), DbError> {
+        let key = format!("market_health/{}", report.system);
+        self.set_value(&key, report).await
+    }
+
+    pub async fn upsert_market_transactions(
+        &self,
+        market: &WithTimestamp<Market>,
+    ) -> Result<(), DbError> {
+        self.upsert_transactions(&market.data.symbol, &market.data.transactions)
             .await
-            .expect("DB Query error");
     }
 
-    pub async fn upsert_market_transactions(&self, market: &WithTimestamp<Market>) {
-        let inserts = market
-            .data
-            .transactions
+    // Transactions are deduplicated on (market, timestamp, ship)
+    pub async fn upsert_transactions(
+        &self,
+        market_symbol: &WaypointSymbol,
+        transactions: &[MarketTransaction],
+    ) -> Result<(), DbError> {
+        if transactions.is_empty() {
+            return Ok(());
+        }
+        let inserts = transactions
             .iter()
             .map(|transaction| {
                 (
                     market_transactions::timestamp.eq(transaction.timestamp),
-                    market_transactions::market_symbol.eq(market.data.symbol.to_string()),
+                    market_transactions::market_symbol.eq(market_symbol.to_string()),
                     market_transactions::symbol.eq(&transaction.trade_symbol),
                     market_transactions::ship_symbol.eq(&transaction.ship_symbol),
                     market_transactions::type_.eq(&transaction._type),
@@ -225,64 +477,523 @@ impl DbClient {
             .on_conflict((
                 market_transactions::market_symbol,
                 market_transactions::timestamp,
+                market_transactions::ship_symbol,
             ))
             .do_nothing()
-            .execute(&mut self.conn().await)
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    // Re-ingest the transactions on every stored market snapshot, in case a previous run
+    // saved a market without recording its transactions
+    pub async fn backfill_market_transactions(&self) -> Result<(), DbError> {
+        const BATCH_SIZE: i64 = 100;
+        let query_start = std::time::Instant::now();
+        let mut offset = 0;
+        loop {
+            let values: Vec<Value> = general_lookup::table
+                .select(general_lookup::value)
+                .filter(general_lookup::reset_id.eq(self.reset_date()))
+                .filter(general_lookup::key.like("markets/%"))
+                .order((general_lookup::inserted_at, general_lookup::key))
+                .limit(BATCH_SIZE)
+                .offset(offset)
+                .load(&mut self.conn().await?)
+                .await?;
+            for value in &values {
+                let market: WithTimestamp<Market> = serde_json::from_value(value.clone())?;
+                self.upsert_market_transactions(&market).await?;
+            }
+            offset += values.len() as i64;
+            if (values.len() as i64) < BATCH_SIZE {
+                break;
+            }
+        }
+        let duration = query_start.elapsed().as_millis() as f64 / 1000.0;
+        info!(
+            "Backfilled transactions from {} markets in {:.3}s",
+            offset, duration
+        );
+        Ok(())
+    }
+
+    pub async fn insert_shipyard_listings(
+        &self,
+        shipyard: &WithTimestamp<Shipyard>,
+    ) -> Result<(), DbError> {
+        if shipyard.data.ships.is_empty() {
+            return Ok(());
+        }
+        let inserts = shipyard
+            .data
+            .ships
+            .iter()
+            .map(|ship| {
+                (
+                    shipyard_listings::timestamp.eq(shipyard.timestamp),
+                    shipyard_listings::shipyard_symbol.eq(shipyard.data.symbol.to_string()),
+                    shipyard_listings::ship_type.eq(&ship.ship_type),
+                    shipyard_listings::supply.eq(&ship.supply),
+                    shipyard_listings::activity.eq(&ship.activity),
+                    shipyard_listings::purchase_price.eq(ship.purchase_price as i32),
+                    shipyard_listings::frame.eq(&ship.frame.symbol),
+                    shipyard_listings::reactor.eq(&ship.reactor.symbol),
+                    shipyard_listings::engine.eq(&ship.engine.symbol),
+                    shipyard_listings::modules.eq(ship
+                        .modules
+                        .iter()
+                        .map(|m| &m.symbol)
+                        .collect::<Vec<_>>()),
+                    shipyard_listings::mounts.eq(ship
+                        .mounts
+                        .iter()
+                        .map(|m| &m.symbol)
+                        .collect::<Vec<_>>()),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(shipyard_listings::table)
+            .values(&inserts)
+            .on_conflict_do_nothing()
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_ship_listing_history(
+        &self,
+        ship_type: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<db_models::ShipListingSample>, DbError> {
+        Ok(shipyard_listings::table
+            .filter(shipyard_listings::ship_type.eq(ship_type))
+            .filter(shipyard_listings::timestamp.ge(since))
+            .order(shipyard_listings::timestamp.asc())
+            .select(db_models::ShipListingSample::as_select())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    pub async fn get_ship_models(&self) -> Result<Vec<ShipModel>, DbError> {
+        Ok(ship_models::table
+            .filter(ship_models::reset_id.eq(self.reset_date()))
+            .select(db_models::ShipModelRecord::as_select())
+            .load(&mut self.conn().await?)
+            .await?
+            .into_iter()
+            .map(|record| ShipModel {
+                ship_type: record.ship_type,
+                frame: record.frame,
+                reactor: record.reactor,
+                engine: record.engine,
+                modules: record.modules,
+                mounts: record.mounts,
+                cargo_capacity: record.cargo_capacity as i64,
+                fuel_capacity: Some(record.fuel_capacity as i64),
+                speed: Some(record.speed as i64),
+                min_price: Some(record.min_price as i64),
+                max_price: Some(record.max_price as i64),
+            })
+            .collect())
+    }
+
+    // Only models seen in a shipyard are saved, so all stats are known
+    pub async fn save_ship_model(&self, model: &ShipModel) -> Result<(), DbError> {
+        let values = (
+            ship_models::frame.eq(&model.frame),
+            ship_models::reactor.eq(&model.reactor),
+            ship_models::engine.eq(&model.engine),
+            ship_models::modules.eq(&model.modules),
+            ship_models::mounts.eq(&model.mounts),
+            ship_models::cargo_capacity.eq(model.cargo_capacity as i32),
+            ship_models::fuel_capacity.eq(model.fuel_capacity.unwrap() as i32),
+            ship_models::speed.eq(model.speed.unwrap() as i32),
+            ship_models::min_price.eq(model.min_price.unwrap() as i32),
+            ship_models::max_price.eq(model.max_price.unwrap() as i32),
+            ship_models::updated_at.eq(Utc::now()),
+        );
+        diesel::insert_into(ship_models::table)
+            .values((
+                ship_models::reset_id.eq(self.reset_date()),
+                ship_models::ship_type.eq(&model.ship_type),
+                values,
+            ))
+            .on_conflict((ship_models::reset_id, ship_models::ship_type))
+            .do_update()
+            .set(values)
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_net_worth(
+        &self,
+        callsign: &str,
+        net_worth: &NetWorth,
+    ) -> Result<(), DbError> {
+        diesel::insert_into(net_worth_history::table)
+            .values((
+                net_worth_history::reset_id.eq(self.reset_date()),
+                net_worth_history::callsign.eq(callsign),
+                net_worth_history::timestamp.eq(Utc::now()),
+                net_worth_history::credits.eq(net_worth.credits),
+                net_worth_history::cargo_value.eq(net_worth.cargo_value),
+                net_worth_history::ship_value.eq(net_worth.ship_value),
+                net_worth_history::total.eq(net_worth.total),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_ops_report(&self, callsign: &str, report: &OpsReport) -> Result<(), DbError> {
+        let key = format!(
+            "ops_reports/{}/{}",
+            callsign,
+            report.timestamp.format("%Y-%m-%dT%H:%M")
+        );
+        self.set_value(&key, report).await?;
+        self.set_value(&format!("ops_reports/{}/latest", callsign), report)
+            .await
+    }
+
+    pub async fn load_latest_ops_report(
+        &self,
+        callsign: &str,
+    ) -> Result<Option<OpsReport>, DbError> {
+        self.get_value(&format!("ops_reports/{}/latest", callsign))
             .await
-            .expect("DB Query error");
     }
 
-    pub async fn get_shipyard(&self, symbol: &WaypointSymbol) -> Option<WithTimestamp<Shipyard>> {
+    pub async fn get_net_worth_history(
+        &self,
+        callsign: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<db_models::NetWorthSample>, DbError> {
+        Ok(net_worth_history::table
+            .filter(net_worth_history::reset_id.eq(self.reset_date()))
+            .filter(net_worth_history::callsign.eq(callsign))
+            .filter(net_worth_history::timestamp.ge(since))
+            .order(net_worth_history::timestamp.asc())
+            .select(db_models::NetWorthSample::as_select())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    pub async fn insert_construction_delivery(
+        &self,
+        waypoint_symbol: &WaypointSymbol,
+        ship_symbol: &str,
+        trade_symbol: &str,
+        units: i64,
+    ) -> Result<(), DbError> {
+        diesel::insert_into(construction_deliveries::table)
+            .values((
+                construction_deliveries::reset_id.eq(self.reset_date()),
+                construction_deliveries::timestamp.eq(Utc::now()),
+                construction_deliveries::waypoint_symbol.eq(waypoint_symbol.to_string()),
+                construction_deliveries::ship_symbol.eq(ship_symbol),
+                construction_deliveries::trade_symbol.eq(trade_symbol),
+                construction_deliveries::units.eq(units as i32),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    // Per-ship totals of each material delivered, largest first
+    pub async fn get_construction_contributions(
+        &self,
+        waypoint_symbol: Option<&WaypointSymbol>,
+    ) -> Result<Vec<db_models::ConstructionContribution>, DbError> {
+        let rows: Vec<(String, String, String, Option<i64>, i64)> = construction_deliveries::table
+            .filter(construction_deliveries::reset_id.eq(self.reset_date()))
+            .group_by((
+                construction_deliveries::waypoint_symbol,
+                construction_deliveries::ship_symbol,
+                construction_deliveries::trade_symbol,
+            ))
+            .select((
+                construction_deliveries::waypoint_symbol,
+                construction_deliveries::ship_symbol,
+                construction_deliveries::trade_symbol,
+                diesel::dsl::sum(construction_deliveries::units),
+                diesel::dsl::count_star(),
+            ))
+            .load(&mut self.conn().await?)
+            .await?;
+        let mut contributions = rows
+            .into_iter()
+            .filter(|row| match waypoint_symbol {
+                Some(waypoint_symbol) => row.0 == waypoint_symbol.to_string(),
+                None => true,
+            })
+            .map(
+                |(waypoint_symbol, ship_symbol, trade_symbol, units, deliveries)| {
+                    db_models::ConstructionContribution {
+                        waypoint_symbol,
+                        ship_symbol,
+                        trade_symbol,
+                        units: units.unwrap_or(0),
+                        deliveries,
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        contributions.sort_by_key(|c| -c.units);
+        Ok(contributions)
+    }
+
+    // Current assignments, job_id -> ship_symbol
+    pub async fn get_job_assignments(
+        &self,
+        callsign: &str,
+    ) -> Result<DashMap<String, String>, DbError> {
+        Ok(job_assignments::table
+            .filter(job_assignments::reset_id.eq(self.reset_date()))
+            .filter(job_assignments::callsign.eq(callsign))
+            .filter(job_assignments::unassigned_at.is_null())
+            .select((job_assignments::job_id, job_assignments::ship_symbol))
+            .load::<(String, String)>(&mut self.conn().await?)
+            .await?
+            .into_iter()
+            .collect())
+    }
+
+    pub async fn get_job_assignment_history(
+        &self,
+        callsign: &str,
+        ship_symbol: Option<&str>,
+    ) -> Result<Vec<db_models::JobAssignment>, DbError> {
+        let mut query = job_assignments::table
+            .filter(job_assignments::reset_id.eq(self.reset_date()))
+            .filter(job_assignments::callsign.eq(callsign))
+            .into_boxed();
+        if let Some(ship_symbol) = ship_symbol {
+            query = query.filter(job_assignments::ship_symbol.eq(ship_symbol));
+        }
+        Ok(query
+            .order(job_assignments::assigned_at.asc())
+            .select(db_models::JobAssignment::as_select())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    // Ends the ship's current assignment, if any, and starts a new one
+    pub async fn assign_job(
+        &self,
+        callsign: &str,
+        ship_symbol: &str,
+        job_id: &str,
+    ) -> Result<(), DbError> {
+        self.unassign_job(callsign, ship_symbol).await?;
+        diesel::insert_into(job_assignments::table)
+            .values((
+                job_assignments::reset_id.eq(self.reset_date()),
+                job_assignments::callsign.eq(callsign),
+                job_assignments::ship_symbol.eq(ship_symbol),
+                job_assignments::job_id.eq(job_id),
+                job_assignments::assigned_at.eq(Utc::now()),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unassign_job(&self, callsign: &str, ship_symbol: &str) -> Result<(), DbError> {
+        diesel::update(job_assignments::table)
+            .filter(job_assignments::reset_id.eq(self.reset_date()))
+            .filter(job_assignments::callsign.eq(callsign))
+            .filter(job_assignments::ship_symbol.eq(ship_symbol))
+            .filter(job_assignments::unassigned_at.is_null())
+            .set(job_assignments::unassigned_at.eq(Utc::now()))
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    // One-off import of assignments stored as a single general_lookup value by earlier releases
+    pub async fn migrate_legacy_job_assignments(&self, callsign: &str) -> Result<(), DbError> {
+        let key = format!("{}/ship_assignments", callsign);
+        let Some(legacy) = self.get_value::<BTreeMap<String, String>>(&key).await? else {
+            return Ok(());
+        };
+        if !self
+            .get_job_assignment_history(callsign, None)
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+        info!("Migrating {} legacy job assignments", legacy.len());
+        for (job_id, ship_symbol) in legacy {
+            self.assign_job(callsign, &ship_symbol, &job_id).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_shipyard(
+        &self,
+        symbol: &WaypointSymbol,
+    ) -> Result<Option<WithTimestamp<Shipyard>>, DbError> {
         let key = format!("shipyards/{}", symbol);
         self.get_value(&key).await
     }
 
-    pub async fn save_shipyard(&self, symbol: &WaypointSymbol, shipyard: &WithTimestamp<Shipyard>) {
+    pub async fn save_shipyard(
+        &self,
+        symbol: &WaypointSymbol,
+        shipyard: &WithTimestamp<Shipyard>,
+    ) -> Result<(), DbError> {
         let key = format!("shipyards/{}", symbol);
-        self.set_value(&key, &shipyard).await;
+        self.set_value(&key, &shipyard).await
     }
 
-    pub async fn load_schedule(&self, ship_symbol: &str) -> Option<ShipSchedule> {
-        let key = format!("schedules/{}", ship_symbol);
-        self.get_value(&key).await
+    pub async fn load_schedule(&self, ship_symbol: &str) -> Result<Option<ShipSchedule>, DbError> {
+        self.namespace::<ShipSchedule>("schedules")
+            .get(ship_symbol)
+            .await
     }
-    pub async fn load_schedule_progress(&self, ship_symbol: &str) -> Option<usize> {
+    pub async fn load_schedule_progress(
+        &self,
+        ship_symbol: &str,
+    ) -> Result<Option<ScheduleProgress>, DbError> {
         let key = format!("schedule_progress/{}", ship_symbol);
-        self.get_value(&key).await
+        self.get_versioned(&key).await
     }
-    pub async fn save_schedule(&self, ship_symbol: &str, schedule: &ShipSchedule) {
-        let key = format!("schedules/{}", ship_symbol);
-        self.set_value(&key, schedule).await
+    pub async fn save_schedule(
+        &self,
+        ship_symbol: &str,
+        schedule: &ShipSchedule,
+    ) -> Result<(), DbError> {
+        self.namespace::<ShipSchedule>("schedules")
+            .set(ship_symbol, schedule)
+            .await
     }
-    pub async fn save_schedule_progress(&self, ship_symbol: &str, progress: usize) {
+    pub async fn save_schedule_progress(
+        &self,
+        ship_symbol: &str,
+        progress: &ScheduleProgress,
+    ) -> Result<(), DbError> {
         let key = format!("schedule_progress/{}", ship_symbol);
-        self.set_value(&key, &progress).await
+        self.set_versioned(&key, progress).await
     }
-    pub async fn update_schedule_progress(&self, ship_symbol: &str, progress: usize) {
-        self.save_schedule_progress(ship_symbol, progress).await;
+
+    // The system a logistics ship plans in, while its gate trades take it elsewhere
+    pub async fn load_logistics_home(
+        &self,
+        ship_symbol: &str,
+    ) -> Result<Option<SystemSymbol>, DbError> {
+        let key = format!("logistics_home/{}", ship_symbol);
+        Ok(self
+            .get_value::<Option<SystemSymbol>>(&key)
+            .await?
+            .flatten())
+    }
+    pub async fn save_logistics_home(
+        &self,
+        ship_symbol: &str,
+        system: Option<&SystemSymbol>,
+    ) -> Result<(), DbError> {
+        let key = format!("logistics_home/{}", ship_symbol);
+        self.set_value(&key, &system).await
     }
 
     // type TaskManagerStatus = DashMap<String, (Task, String, DateTime<Utc>)>
     pub async fn save_task_manager_state(
         &self,
+        callsign: &str,
         system_symbol: &SystemSymbol,
         status: &DashMap<String, (Task, String, DateTime<Utc>)>,
-    ) {
-        let key = format!("task_manager/{}", system_symbol);
-        self.set_value(&key, status).await
+    ) -> Result<(), DbError> {
+        let key = format!("{}/task_manager/{}", callsign, system_symbol);
+        self.set_versioned(&key, status).await
     }
     pub async fn load_task_manager_state(
         &self,
+        callsign: &str,
         system_symbol: &SystemSymbol,
-    ) -> Option<DashMap<String, (Task, String, DateTime<Utc>)>> {
-        let key = format!("task_manager/{}", system_symbol);
+    ) -> Result<Option<DashMap<String, (Task, String, DateTime<Utc>)>>, DbError> {
+        let key = format!("{}/task_manager/{}", callsign, system_symbol);
+        self.get_versioned(&key).await
+    }
+    pub async fn save_manual_tasks(
+        &self,
+        callsign: &str,
+        system_symbol: &SystemSymbol,
+        tasks: &DashMap<String, Task>,
+    ) -> Result<(), DbError> {
+        let key = format!("{}/manual_tasks/{}", callsign, system_symbol);
+        self.set_value(&key, tasks).await
+    }
+    pub async fn load_manual_tasks(
+        &self,
+        callsign: &str,
+        system_symbol: &SystemSymbol,
+    ) -> Result<Option<DashMap<String, Task>>, DbError> {
+        let key = format!("{}/manual_tasks/{}", callsign, system_symbol);
         self.get_value(&key).await
     }
 
+    // One-off import of task manager state stored without the callsign by earlier releases. Reservations
+    // go to the agent whose ship holds them. Manual tasks go to the first agent to load, the legacy key is
+    // emptied so agents sharing the system don't run them twice
+    pub async fn migrate_legacy_task_manager_state(
+        &self,
+        callsign: &str,
+        system_symbol: &SystemSymbol,
+    ) -> Result<(), DbError> {
+        let legacy_key = format!("task_manager/{}", system_symbol);
+        if self
+            .load_task_manager_state(callsign, system_symbol)
+            .await?
+            .is_none()
+        {
+            let legacy = self
+                .get_versioned::<DashMap<String, (Task, String, DateTime<Utc>)>>(&legacy_key)
+                .await?;
+            if let Some(legacy) = legacy {
+                let ship_prefix = format!("{}-", callsign);
+                legacy.retain(|_, (_, ship_symbol, _)| ship_symbol.starts_with(&ship_prefix));
+                info!(
+                    "Migrating {} legacy task reservations in {}",
+                    legacy.len(),
+                    system_symbol
+                );
+                self.save_task_manager_state(callsign, system_symbol, &legacy)
+                    .await?;
+            }
+        }
+
+        let legacy_key = format!("manual_tasks/{}", system_symbol);
+        let legacy: Option<DashMap<String, Task>> = self.get_value(&legacy_key).await?;
+        match legacy {
+            Some(legacy) if !legacy.is_empty() => {
+                if self
+                    .load_manual_tasks(callsign, system_symbol)
+                    .await?
+                    .is_none()
+                {
+                    info!(
+                        "Migrating {} legacy manual tasks in {}",
+                        legacy.len(),
+                        system_symbol
+                    );
+                    self.save_manual_tasks(callsign, system_symbol, &legacy)
+                        .await?;
+                }
+                self.set_value(&legacy_key, &DashMap::<String, Task>::new())
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn get_construction(
         &self,
         symbol: &WaypointSymbol,
-    ) -> Option<WithTimestamp<Option<Construction>>> {
+    ) -> Result<Option<WithTimestamp<Option<Construction>>>, DbError> {
         let key = format!("construction/{}", symbol);
         self.get_value(&key).await
     }
@@ -290,7 +1001,7 @@ impl DbClient {
         &self,
         symbol: &WaypointSymbol,
         construction: &WithTimestamp<Option<Construction>>,
-    ) {
+    ) -> Result<(), DbError> {
         let key = format!("construction/{}", symbol);
         self.set_value(&key, construction).await
     }
@@ -298,35 +1009,55 @@ impl DbClient {
     pub async fn get_probe_jumpgate_reservations(
         &self,
         callsign: &str,
-    ) -> DashMap<String, WaypointSymbol> {
+    ) -> Result<DashMap<String, WaypointSymbol>, DbError> {
         let key = format!("probe_jumpgate_reservations/{}", callsign);
-        self.get_value(&key).await.unwrap_or_default()
+        Ok(self.get_versioned(&key).await?.unwrap_or_default())
     }
 
     pub async fn save_probe_jumpgate_reservations(
         &self,
         callsign: &str,
         reservations: &DashMap<String, WaypointSymbol>,
-    ) {
+    ) -> Result<(), DbError> {
         let key = format!("probe_jumpgate_reservations/{}", callsign);
-        self.set_value(&key, &reservations).await
+        self.set_versioned(&key, reservations).await
+    }
+
+    pub async fn get_probe_shipyard_reservations(
+        &self,
+        callsign: &str,
+    ) -> Result<BTreeMap<String, WaypointSymbol>, DbError> {
+        let key = format!("probe_shipyard_reservations/{}", callsign);
+        Ok(self.get_value(&key).await?.unwrap_or_default())
     }
 
-    pub async fn get_explorer_reservations(&self, callsign: &str) -> DashMap<String, SystemSymbol> {
+    pub async fn save_probe_shipyard_reservations(
+        &self,
+        callsign: &str,
+        reservations: &BTreeMap<String, WaypointSymbol>,
+    ) -> Result<(), DbError> {
+        let key = format!("probe_shipyard_reservations/{}", callsign);
+        self.set_value(&key, reservations).await
+    }
+
+    pub async fn get_explorer_reservations(
+        &self,
+        callsign: &str,
+    ) -> Result<DashMap<String, SystemSymbol>, DbError> {
         let key = format!("explorer_reservations/{}", callsign);
-        self.get_value(&key).await.unwrap_or_default()
+        Ok(self.get_versioned(&key).await?.unwrap_or_default())
     }
 
     pub async fn save_explorer_reservations(
         &self,
         callsign: &str,
         reservations: &DashMap<String, SystemSymbol>,
-    ) {
+    ) -> Result<(), DbError> {
         let key = format!("explorer_reservations/{}", callsign);
-        self.set_value(&key, &reservations).await
+        self.set_versioned(&key, reservations).await
     }
 
-    pub async fn insert_surveys(&self, surveys: &Vec<KeyedSurvey>) {
+    pub async fn insert_surveys(&self, surveys: &Vec<KeyedSurvey>) -> Result<(), DbError> {
         let now = Utc::now();
         let inserts = surveys
             .iter()
@@ -338,57 +1069,129 @@ impl DbClient {
                     surveys::asteroid_symbol.eq(survey.survey.symbol.to_string()),
                     surveys::inserted_at.eq(now),
                     surveys::expires_at.eq(survey.survey.expiration),
+                    surveys::owner.eq(&survey.owner),
                 )
             })
             .collect::<Vec<_>>();
         diesel::insert_into(surveys::table)
             .values(&inserts)
-            .execute(&mut self.conn().await)
-            .await
-            .expect("DB Query error");
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
     }
 
-    pub async fn get_surveys(&self) -> Vec<KeyedSurvey> {
-        let surveys: Vec<(Uuid, Value)> = surveys::table
+    // Surveys taken by `owner`, or by every agent if None
+    pub async fn get_surveys(&self, owner: Option<&str>) -> Result<Vec<KeyedSurvey>, DbError> {
+        let mut query = surveys::table
             .filter(surveys::reset_id.eq(self.reset_date()))
-            .select((surveys::uuid, surveys::survey))
-            .load(&mut self.conn().await)
-            .await
-            .expect("DB Query error");
+            .select((surveys::uuid, surveys::survey, surveys::owner))
+            .into_boxed();
+        if let Some(owner) = owner {
+            query = query.filter(surveys::owner.eq(owner));
+        }
+        let surveys: Vec<(Uuid, Value, String)> = query.load(&mut self.conn().await?).await?;
+        surveys
+            .into_iter()
+            .map(|(uuid, survey, owner)| {
+                Ok(KeyedSurvey {
+                    uuid,
+                    survey: serde_json::from_value(survey)?,
+                    owner,
+                })
+            })
+            .collect()
+    }
+
+    // Surveys of an asteroid taken by any agent
+    pub async fn get_asteroid_surveys(
+        &self,
+        asteroid: &WaypointSymbol,
+    ) -> Result<Vec<KeyedSurvey>, DbError> {
+        let surveys: Vec<(Uuid, Value, String)> = surveys::table
+            .filter(surveys::reset_id.eq(self.reset_date()))
+            .filter(surveys::asteroid_symbol.eq(asteroid.as_str()))
+            .select((surveys::uuid, surveys::survey, surveys::owner))
+            .load(&mut self.conn().await?)
+            .await?;
         surveys
             .into_iter()
-            .map(|(uuid, survey)| KeyedSurvey {
-                uuid,
-                survey: serde_json::from_value(survey).unwrap(),
+            .map(|(uuid, survey, owner)| {
+                Ok(KeyedSurvey {
+                    uuid,
+                    survey: serde_json::from_value(survey)?,
+                    owner,
+                })
             })
             .collect()
     }
 
-    pub async fn remove_survey(&self, uuid: &Uuid) {
+    pub async fn record_survey_consumption(
+        &self,
+        owner: &str,
+        consumer: &str,
+        units: i64,
+    ) -> Result<(), DbError> {
+        diesel::insert_into(survey_consumption::table)
+            .values((
+                survey_consumption::reset_id.eq(self.reset_date()),
+                survey_consumption::owner.eq(owner),
+                survey_consumption::consumer.eq(consumer),
+                survey_consumption::extractions.eq(1),
+                survey_consumption::units.eq(units),
+            ))
+            .on_conflict((
+                survey_consumption::reset_id,
+                survey_consumption::owner,
+                survey_consumption::consumer,
+            ))
+            .do_update()
+            .set((
+                survey_consumption::extractions.eq(survey_consumption::extractions + 1),
+                survey_consumption::units.eq(survey_consumption::units + units),
+            ))
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_survey_consumption(
+        &self,
+    ) -> Result<Vec<db_models::SurveyConsumption>, DbError> {
+        Ok(survey_consumption::table
+            .filter(survey_consumption::reset_id.eq(self.reset_date()))
+            .order((survey_consumption::owner, survey_consumption::consumer))
+            .select(db_models::SurveyConsumption::as_select())
+            .load(&mut self.conn().await?)
+            .await?)
+    }
+
+    pub async fn remove_survey(&self, uuid: &Uuid) -> Result<(), DbError> {
         diesel::delete(
             surveys::table
                 .filter(surveys::reset_id.eq(self.reset_date()))
                 .filter(surveys::uuid.eq(uuid)),
         )
-        .execute(&mut self.conn().await)
-        .await
-        .expect("DB Query error");
+        .execute(&mut self.conn().await?)
+        .await?;
+        Ok(())
     }
 
-    pub async fn get_systems(&self) -> Vec<db_models::System> {
-        systems::table
+    pub async fn get_systems(&self) -> Result<Vec<db_models::System>, DbError> {
+        Ok(systems::table
             .filter(systems::reset_id.eq(self.reset_date()))
             .select(db_models::System::as_select())
-            .load(&mut self.conn().await)
-            .await
-            .expect("DB Query error")
+            .load(&mut self.conn().await?)
+            .await?)
     }
 
-    pub async fn insert_systems(&self, systems: &Vec<db_models::NewSystem<'_>>) {
+    pub async fn insert_systems(
+        &self,
+        systems: &Vec<db_models::NewSystem<'_>>,
+    ) -> Result<(), DbError> {
         diesel::insert_into(systems::table)
             .values(systems)
-            .execute(&mut self.conn().await)
-            .await
-            .expect("DB Query error");
+            .execute(&mut self.conn().await?)
+            .await?;
+        Ok(())
     }
 }
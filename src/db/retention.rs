@@ -0,0 +1,206 @@
+//!
+//! Listing and dropping the data of old resets.
+//!
+//! Most tables are partitioned by reset_id. The market data tables aren't, their rows belong to the
+//! reset that was running at the time: from its date until the next reset's. Rows from before the
+//! oldest reset count towards it. There's no other store, market data is kept in postgres too.
+//! Must be updated when new tables are added.
+//!
+use super::{DbClient, DbError};
+use diesel::sql_types::{BigInt, Text};
+use diesel::QueryableByName;
+use diesel_async::RunQueryDsl as _;
+use log::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Tables with a reset_id column
+const RESET_TABLES: &[&str] = &[
+    "construction_deliveries",
+    "general_lookup",
+    "job_assignments",
+    "jumpgate_connections",
+    "net_worth_history",
+    "ship_models",
+    "survey_consumption",
+    "surveys",
+    "systems",
+    "waypoint_details",
+    "waypoints",
+];
+
+// Tables only partitioned by timestamp
+const TIMESTAMP_TABLES: &[&str] = &["market_trades", "market_transactions", "shipyard_listings"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ResetSize {
+    pub reset_id: String,
+    pub rows: i64,
+    // on-disk size of the rows, excluding indexes
+    pub bytes: i64,
+}
+
+#[derive(QueryableByName)]
+struct ResetRows {
+    #[diesel(sql_type = Text)]
+    reset_id: String,
+    #[diesel(sql_type = BigInt)]
+    rows: i64,
+    #[diesel(sql_type = BigInt)]
+    bytes: i64,
+}
+
+#[derive(QueryableByName)]
+struct Rows {
+    #[diesel(sql_type = BigInt)]
+    rows: i64,
+    #[diesel(sql_type = BigInt)]
+    bytes: i64,
+}
+
+// Resets to drop to keep the newest `keep`, oldest first. Reset ids are dates, so they sort
+// chronologically. The current reset is always kept, as is anything after it
+pub fn resets_to_drop(resets: &[String], keep: usize, current: &str) -> Vec<String> {
+    let mut resets = resets
+        .iter()
+        .filter(|r| r.as_str() < current)
+        .cloned()
+        .collect::<Vec<_>>();
+    resets.sort();
+    resets.dedup();
+    let num_drop = (resets.len() + 1).saturating_sub(keep);
+    resets.into_iter().take(num_drop).collect()
+}
+
+impl DbClient {
+    // Every reset with data in the database, oldest first
+    pub async fn list_resets(&self) -> Result<Vec<ResetSize>, DbError> {
+        let mut conn = self.conn().await?;
+        let mut sizes: BTreeMap<String, ResetSize> = BTreeMap::new();
+        for table in RESET_TABLES {
+            let query = format!(
+                "SELECT reset_id, count(*) AS rows, coalesce(sum(pg_column_size(t.*)), 0)::bigint AS bytes FROM {} t GROUP BY reset_id",
+                table
+            );
+            let rows: Vec<ResetRows> = diesel::sql_query(query).load(&mut conn).await?;
+            for row in rows {
+                let size = sizes.entry(row.reset_id.clone()).or_default();
+                size.reset_id = row.reset_id;
+                size.rows += row.rows;
+                size.bytes += row.bytes;
+            }
+        }
+
+        // each reset's market data runs until the next reset
+        let reset_ids = sizes.keys().cloned().collect::<Vec<_>>();
+        for (idx, reset_id) in reset_ids.iter().enumerate() {
+            let from = match idx {
+                0 => "-infinity",
+                _ => reset_id.as_str(),
+            };
+            let until = reset_ids
+                .get(idx + 1)
+                .map(|r| r.as_str())
+                .unwrap_or("infinity");
+            for table in TIMESTAMP_TABLES {
+                let query = format!(
+                    "SELECT count(*) AS rows, coalesce(sum(pg_column_size(t.*)), 0)::bigint AS bytes FROM {} t WHERE timestamp >= $1::date AND timestamp < $2::date",
+                    table
+                );
+                let rows: Vec<Rows> = diesel::sql_query(query)
+                    .bind::<Text, _>(from)
+                    .bind::<Text, _>(until)
+                    .load(&mut conn)
+                    .await?;
+                let size = sizes.get_mut(reset_id).unwrap();
+                size.rows += rows[0].rows;
+                size.bytes += rows[0].bytes;
+            }
+        }
+        Ok(sizes.into_values().collect())
+    }
+
+    // Drop all data of the resets older than the newest `keep`, returns the resets dropped.
+    // With `dry_run` nothing is deleted, and the resets that would be dropped are returned
+    pub async fn prune_resets(
+        &self,
+        keep: usize,
+        dry_run: bool,
+    ) -> Result<Vec<ResetSize>, DbError> {
+        assert!(keep >= 1, "Must keep the current reset");
+        let resets = self.list_resets().await?;
+        let reset_ids = resets
+            .iter()
+            .map(|r| r.reset_id.clone())
+            .collect::<Vec<_>>();
+        let drop = resets_to_drop(&reset_ids, keep, self.reset_date());
+        let dropped = resets
+            .into_iter()
+            .filter(|r| drop.contains(&r.reset_id))
+            .collect::<Vec<_>>();
+        if dry_run || dropped.is_empty() {
+            return Ok(dropped);
+        }
+
+        let mut conn = self.conn().await?;
+        for reset_id in &drop {
+            for table in RESET_TABLES {
+                let query = format!("DELETE FROM {} WHERE reset_id = $1", table);
+                let deleted = diesel::sql_query(query)
+                    .bind::<Text, _>(reset_id)
+                    .execute(&mut conn)
+                    .await?;
+                debug!(
+                    "Deleted {} rows of reset {} from {}",
+                    deleted, reset_id, table
+                );
+            }
+        }
+        // market data from before the oldest reset kept
+        let oldest_kept = reset_ids
+            .iter()
+            .find(|r| !drop.contains(r))
+            .map(|r| r.as_str())
+            .unwrap_or(self.reset_date());
+        for table in TIMESTAMP_TABLES {
+            let query = format!("DELETE FROM {} WHERE timestamp < $1::date", table);
+            let deleted = diesel::sql_query(query)
+                .bind::<Text, _>(oldest_kept)
+                .execute(&mut conn)
+                .await?;
+            debug!(
+                "Deleted {} rows before {} from {}",
+                deleted, oldest_kept, table
+            );
+        }
+        info!("Dropped {} resets: {}", drop.len(), drop.join(", "));
+        Ok(dropped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resets_to_drop() {
+        let resets = ["2024-03-10", "2024-01-14", "2024-02-11", "2024-03-24"]
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resets_to_drop(&resets, 2, "2024-03-24"),
+            vec!["2024-01-14", "2024-02-11"]
+        );
+        assert!(resets_to_drop(&resets, 4, "2024-03-24").is_empty());
+        assert_eq!(resets_to_drop(&resets, 1, "2024-03-24").len(), 3);
+
+        // the current reset counts towards `keep` before it has any data of its own
+        assert_eq!(
+            resets_to_drop(&resets, 2, "2024-04-07"),
+            vec!["2024-01-14", "2024-02-11", "2024-03-10"]
+        );
+        // resets after the current one (e.g. a stale reset date) are never dropped
+        assert_eq!(resets_to_drop(&resets, 1, "2024-02-11"), vec!["2024-01-14"]);
+    }
+}
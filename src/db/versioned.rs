@@ -0,0 +1,202 @@
+//!
+//! Versioned values for the general_lookup key-value store.
+//!
+//! Values are stored in an envelope `{"_type": <type name>, "version": <version>, "payload": <value>}`.
+//! When a type's serialized shape changes, bump its VERSION and handle the old layout in `upgrade`,
+//! so values written by a previous release are migrated on load instead of failing to parse.
+//! Values written before envelopes existed are read as version 1.
+//!
+use super::{DbClient, DbError};
+use log::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::marker::PhantomData;
+
+pub trait Versioned: Serialize + DeserializeOwned {
+    const TYPE_NAME: &'static str;
+    const VERSION: u32;
+
+    // Convert a payload at `from_version` to the layout of `from_version + 1`.
+    // None if there is no upgrade path, in which case the stored value is discarded.
+    fn upgrade(from_version: u32, payload: Value) -> Option<Value> {
+        let _ = (from_version, payload);
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    #[serde(rename = "_type")]
+    type_name: String,
+    version: u32,
+    payload: Value,
+}
+
+pub fn encode<T: Versioned>(value: &T) -> Value {
+    let envelope = Envelope {
+        type_name: T::TYPE_NAME.to_string(),
+        version: T::VERSION,
+        payload: serde_json::to_value(value).unwrap(),
+    };
+    serde_json::to_value(envelope).unwrap()
+}
+
+// Decode a stored value, upgrading it to the current version. None (with a warning) if it can't be read.
+pub fn decode<T: Versioned>(key: &str, stored: Value) -> Option<T> {
+    let is_envelope = stored.get("_type").is_some() && stored.get("payload").is_some();
+    let (mut version, mut payload) = match is_envelope {
+        true => {
+            let envelope: Envelope = match serde_json::from_value(stored) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    warn!("Discarding {}: invalid envelope: {}", key, e);
+                    return None;
+                }
+            };
+            if envelope.type_name != T::TYPE_NAME {
+                warn!(
+                    "Discarding {}: stored type {} but expected {}",
+                    key,
+                    envelope.type_name,
+                    T::TYPE_NAME
+                );
+                return None;
+            }
+            (envelope.version, envelope.payload)
+        }
+        false => (1, stored),
+    };
+    if version > T::VERSION {
+        warn!(
+            "Discarding {}: {} version {} is newer than this release's version {}",
+            key,
+            T::TYPE_NAME,
+            version,
+            T::VERSION
+        );
+        return None;
+    }
+    while version < T::VERSION {
+        payload = match T::upgrade(version, payload) {
+            Some(payload) => payload,
+            None => {
+                warn!(
+                    "Discarding {}: no upgrade for {} from version {}",
+                    key,
+                    T::TYPE_NAME,
+                    version
+                );
+                return None;
+            }
+        };
+        version += 1;
+        info!("Upgraded {} to {} version {}", key, T::TYPE_NAME, version);
+    }
+    match serde_json::from_value(payload) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!(
+                "Discarding {}: failed to parse {} version {}: {}",
+                key,
+                T::TYPE_NAME,
+                version,
+                e
+            );
+            None
+        }
+    }
+}
+
+// Typed view of the keys under `{prefix}/`
+pub struct KvNamespace<T> {
+    db: DbClient,
+    prefix: String,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: Versioned> KvNamespace<T> {
+    pub fn key(&self, id: &str) -> String {
+        format!("{}/{}", self.prefix, id)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<T>, DbError> {
+        self.db.get_versioned(&self.key(id)).await
+    }
+
+    pub async fn set(&self, id: &str, value: &T) -> Result<(), DbError> {
+        self.db.set_versioned(&self.key(id), value).await
+    }
+}
+
+impl DbClient {
+    pub fn namespace<T: Versioned>(&self, prefix: &str) -> KvNamespace<T> {
+        KvNamespace {
+            db: self.clone(),
+            prefix: prefix.to_string(),
+            _type: PhantomData,
+        }
+    }
+
+    pub async fn get_versioned<T: Versioned>(&self, key: &str) -> Result<Option<T>, DbError> {
+        let stored: Option<Value> = self.get_value(key).await?;
+        Ok(stored.and_then(|stored| decode(key, stored)))
+    }
+
+    pub async fn set_versioned<T: Versioned>(&self, key: &str, value: &T) -> Result<(), DbError> {
+        self.set_value(key, &encode(value)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reservation {
+        ship: String,
+        waypoint: String,
+        priority: i64,
+    }
+
+    impl Versioned for Reservation {
+        const TYPE_NAME: &'static str = "Reservation";
+        const VERSION: u32 = 2;
+
+        fn upgrade(from_version: u32, mut payload: Value) -> Option<Value> {
+            match from_version {
+                // v2 added priority
+                1 => {
+                    payload["priority"] = json!(0);
+                    Some(payload)
+                }
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_versioned() {
+        let value = Reservation {
+            ship: "A-1".to_string(),
+            waypoint: "X1-AB12-A1".to_string(),
+            priority: 3,
+        };
+        let stored = encode(&value);
+        assert_eq!(stored["version"], 2);
+        assert_eq!(decode::<Reservation>("k", stored), Some(value));
+
+        // written before envelopes, upgraded from version 1
+        let legacy = json!({ "ship": "A-1", "waypoint": "X1-AB12-A1" });
+        let upgraded = decode::<Reservation>("k", legacy).unwrap();
+        assert_eq!(upgraded.priority, 0);
+
+        let newer = json!({ "_type": "Reservation", "version": 3, "payload": {} });
+        assert_eq!(decode::<Reservation>("k", newer), None);
+        let other_type = json!({ "_type": "Survey", "version": 2, "payload": {} });
+        assert_eq!(decode::<Reservation>("k", other_type), None);
+        let bad_payload = json!({ "_type": "Reservation", "version": 2, "payload": { "ship": 1 } });
+        assert_eq!(decode::<Reservation>("k", bad_payload), None);
+    }
+}
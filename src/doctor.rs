@@ -0,0 +1,243 @@
+//!
+//! Startup self-test (`main --self-test`).
+//!
+//! Checks config, database, schema, API connectivity and version, the agent token and clock skew
+//! against the API, and reports the result of each. The agent doesn't start while any check fails.
+//! Most clients panic on failure, so each check runs in its own task and a panic is reported as a
+//! failure of that check.
+//!
+use crate::api_client::{compat, ApiClient};
+use crate::config::CONFIG;
+use crate::db::DbClient;
+use crate::util::panic_message;
+use chrono::Duration;
+use serde::Serialize;
+use std::future::Future;
+
+// Clock skew past which transit and cooldown waits go noticeably wrong
+const SKEW_WARN_SECS: i64 = 2;
+const SKEW_FAIL_SECS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    fn push(&mut self, name: &str, status: CheckStatus, detail: String) {
+        self.checks.push(CheckResult {
+            name: name.to_string(),
+            status,
+            detail,
+        });
+    }
+}
+
+pub fn skew_status(skew: Duration) -> CheckStatus {
+    match skew.num_seconds().abs() {
+        s if s >= SKEW_FAIL_SECS => CheckStatus::Fail,
+        s if s >= SKEW_WARN_SECS => CheckStatus::Warn,
+        _ => CheckStatus::Pass,
+    }
+}
+
+// Run a check that may panic, Err holds the panic message
+async fn guarded<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    tokio::spawn(f).await.map_err(|e| match e.try_into_panic() {
+        Ok(panic) => panic_message(panic),
+        Err(e) => e.to_string(),
+    })
+}
+
+pub async fn run(callsign: &str) -> DoctorReport {
+    use CheckStatus::*;
+    let mut report = DoctorReport { checks: vec![] };
+
+    if let Err(e) = std::panic::catch_unwind(|| lazy_static::initialize(&CONFIG)) {
+        let msg = e
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or("invalid config".to_string());
+        report.push("config", Fail, msg);
+        return report;
+    }
+    match std::env::var("DATABASE_URL") {
+        Ok(_) => report.push("config", Pass, format!("API {}", CONFIG.api_base_url)),
+        Err(_) => {
+            report.push("config", Fail, "DATABASE_URL not set".to_string());
+            return report;
+        }
+    }
+
+    let api_client = ApiClient::new();
+    let status = {
+        let api_client = api_client.clone();
+        guarded(async move { api_client.status().await })
+            .await
+            .and_then(|status| status.map_err(|e| e.to_string()))
+    };
+    let status = match status {
+        Ok(status) => {
+            let version_status = match compat::is_supported_version(&status.version) {
+                true => Pass,
+                false => Warn,
+            };
+            report.push(
+                "api",
+                Pass,
+                format!("{} (reset {})", status.status, status.reset_date),
+            );
+            report.push("api_version", version_status, status.version.clone());
+            status
+        }
+        Err(e) => {
+            report.push("api", Fail, e);
+            return report;
+        }
+    };
+
+    match api_client.clock_skew().await {
+        Some(skew) => report.push(
+            "clock_skew",
+            skew_status(skew),
+            format!(
+                "server clock is {:.1}s ahead",
+                skew.num_milliseconds() as f64 / 1000.0
+            ),
+        ),
+        None => report.push("clock_skew", Warn, "no Date header".to_string()),
+    }
+
+    let db = guarded(async move { DbClient::new(&status.reset_date).await }).await;
+    let db = match db {
+        Ok(db) => {
+            report.push("database", Pass, "connected".to_string());
+            db
+        }
+        Err(e) => {
+            report.push("database", Fail, e);
+            return report;
+        }
+    };
+    let missing = {
+        let db = db.clone();
+        guarded(async move { db.missing_schema().await })
+            .await
+            .and_then(|missing| missing.map_err(|e| e.to_string()))
+    };
+    match missing {
+        Ok(missing) if missing.is_empty() => report.push("schema", Pass, "up to date".to_string()),
+        // indexes are created by the migrations at startup, missing tables are fatal
+        Ok(missing) => match missing.iter().any(|m| m.starts_with("table")) {
+            true => report.push("schema", Fail, format!("missing {}", missing.join(", "))),
+            false => report.push("schema", Warn, format!("missing {}", missing.join(", "))),
+        },
+        Err(e) => report.push("schema", Fail, e),
+    }
+    report.push(
+        "scylla",
+        Skipped,
+        "not used, market data is stored in postgres".to_string(),
+    );
+
+    // a token in AGENT_TOKEN replaces the stored one of its agent at startup
+    let provided = match std::env::var("AGENT_TOKEN") {
+        Ok(token) => {
+            let api_client = api_client.agent_client();
+            api_client.set_agent_token(&token);
+            match api_client.get_agent().await {
+                Ok(agent) if agent.symbol == callsign => Some(Ok(agent)),
+                Ok(_) => None,
+                Err(e) => Some(Err(format!("AGENT_TOKEN was rejected: {}", e))),
+            }
+        }
+        Err(_) => None,
+    };
+    let agent = match provided {
+        Some(agent) => Some(agent),
+        None => match db.get_agent_token(callsign).await {
+            Ok(Some(token)) => {
+                let api_client = api_client.clone();
+                let agent = guarded(async move {
+                    api_client.set_agent_token(&token);
+                    api_client.get_agent().await
+                })
+                .await
+                .and_then(|agent| agent.map_err(|e| e.to_string()));
+                Some(agent)
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(e.to_string())),
+        },
+    };
+    match agent {
+        Some(Ok(agent)) => report.push(
+            "token",
+            Pass,
+            format!("{} with {} credits", agent.symbol, agent.credits),
+        ),
+        Some(Err(e)) => report.push("token", Fail, e),
+        None => report.push(
+            "token",
+            Warn,
+            format!("no token for {}, the agent will register", callsign),
+        ),
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_skew_status() {
+        assert_eq!(
+            skew_status(Duration::try_milliseconds(800).unwrap()),
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            skew_status(Duration::try_seconds(-5).unwrap()),
+            CheckStatus::Warn
+        );
+        assert_eq!(
+            skew_status(Duration::try_seconds(60).unwrap()),
+            CheckStatus::Fail
+        );
+    }
+
+    #[tokio::test]
+    async fn test_guarded() {
+        assert_eq!(guarded(async { 1 }).await, Ok(1));
+        let err = guarded(async {
+            if true {
+                panic!("Request failed: {}", 401);
+            }
+        })
+        .await;
+        assert_eq!(err, Err("Request failed: 401".to_string()));
+    }
+}
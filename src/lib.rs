@@ -3,16 +3,35 @@ pub mod schema;
 
 pub mod api_client;
 pub mod db;
+pub mod doctor;
 pub mod universe;
 
 pub mod agent_controller;
+pub mod alerts;
 pub mod broker;
+pub mod cargo_valuer;
+pub mod clock;
 pub mod config;
 pub mod logistics_planner;
+pub mod market_health;
+#[cfg(feature = "mock_server")]
+pub mod mock_server;
+pub mod ops_report;
 pub mod pathfinding;
+pub mod purchase_sizing;
+pub mod reset_watch;
 pub mod ship_config;
 pub mod ship_controller;
 pub mod ship_scripts;
+pub mod shutdown;
+pub mod status_feed;
 pub mod survey_manager;
 pub mod tasks;
+pub mod telemetry;
+#[cfg(test)]
+pub mod test_fixtures;
+#[cfg(all(test, feature = "mock_server"))]
+pub mod test_harness;
+pub mod trade_volume;
+pub mod util;
 pub mod web_api_server;
@@ -0,0 +1,264 @@
+/// What-if simulation of our own trades' impact on market prices. Each unit we buy moves the
+/// market's prices up, and each unit we sell moves them down, by a fraction of the listed price
+/// that is linear in the units traded. The fraction per unit is calibrated from consecutive
+/// transactions in the market's history, or assumed from the supply and trade volume without any.
+/// Evaluating a plan replays its trades in order, so a trade is priced after the trades before it
+use crate::models::{MarketTradeGood, WaypointSymbol};
+use crate::purchase_sizing::price_impact;
+use crate::universe::transaction_costs::TradeSide;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+// Transactions further apart than this, the market may have restocked in between
+const CALIBRATION_WINDOW_MINS: i64 = 5;
+// Prices don't fall below this fraction of the listed price, however much is sold
+const MIN_PRICE_FRACTION: f64 = 0.1;
+
+// Chronological (timestamp, units, price per unit) of the transactions per (market, good, side)
+pub type TransactionHistory =
+    BTreeMap<(WaypointSymbol, String, TradeSide), Vec<(DateTime<Utc>, i64, i64)>>;
+
+// Current listings per (market, good)
+pub type Listings = BTreeMap<(WaypointSymbol, String), MarketTradeGood>;
+
+#[derive(Debug, Clone, Default)]
+pub struct ImpactModel {
+    // (market, good, side) -> fraction the price moves per unit traded
+    per_unit: BTreeMap<(WaypointSymbol, String, TradeSide), f64>,
+}
+
+impl ImpactModel {
+    pub fn fit(history: &TransactionHistory) -> Self {
+        let window = Duration::try_minutes(CALIBRATION_WINDOW_MINS).unwrap();
+        let mut per_unit = BTreeMap::new();
+        for (key, transactions) in history {
+            let samples = transactions
+                .windows(2)
+                .filter(|pair| pair[1].0 - pair[0].0 <= window)
+                .filter(|pair| pair[0].1 > 0 && pair[0].2 > 0)
+                .map(|pair| {
+                    let (_, units, price) = pair[0];
+                    let (_, _, next_price) = pair[1];
+                    // purchases push the price up, sales push it down
+                    let moved = match key.2 {
+                        TradeSide::Purchase => next_price - price,
+                        TradeSide::Sell => price - next_price,
+                    };
+                    (moved as f64 / price as f64 / units as f64).max(0.0)
+                })
+                .collect::<Vec<_>>();
+            if !samples.is_empty() {
+                let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+                per_unit.insert(key.clone(), mean);
+            }
+        }
+        Self { per_unit }
+    }
+
+    // Fraction the price moves per unit traded on this side of the market
+    pub fn per_unit(
+        &self,
+        market: &WaypointSymbol,
+        good: &str,
+        side: TradeSide,
+        listing: &MarketTradeGood,
+    ) -> f64 {
+        let key = (market.clone(), good.to_string(), side);
+        match self.per_unit.get(&key) {
+            Some(per_unit) => *per_unit,
+            None => price_impact(&listing.supply) / listing.trade_volume.max(1) as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedTrade {
+    pub market: WaypointSymbol,
+    pub good: String,
+    pub side: TradeSide,
+    pub units: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedTrade {
+    pub trade: PlannedTrade,
+    pub listed_price: i64,
+    // mean price per unit, after the plan's earlier trades and over the units of this one
+    pub price: i64,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectedOutcome {
+    // trades at markets without a listing of the good are left out
+    pub trades: Vec<ProjectedTrade>,
+    pub purchases: i64,
+    pub sales: i64,
+    // (market, good) -> (purchase price, sell price) once the plan has run
+    pub final_prices: BTreeMap<(WaypointSymbol, String), (i64, i64)>,
+}
+
+impl ProjectedOutcome {
+    pub fn net(&self) -> i64 {
+        self.sales - self.purchases
+    }
+}
+
+// Price after `net_units` (bought minus sold) have been traded since the listing
+fn price_after(listed: i64, per_unit: f64, net_units: f64) -> f64 {
+    (listed as f64 * (1.0 + per_unit * net_units)).max(listed as f64 * MIN_PRICE_FRACTION)
+}
+
+pub fn evaluate_plan(
+    model: &ImpactModel,
+    listings: &Listings,
+    plan: &[PlannedTrade],
+) -> ProjectedOutcome {
+    let mut outcome = ProjectedOutcome::default();
+    // (market, good) -> units bought minus units sold so far
+    let mut net_units: BTreeMap<(WaypointSymbol, String), i64> = BTreeMap::new();
+    for trade in plan {
+        let key = (trade.market.clone(), trade.good.clone());
+        let Some(listing) = listings.get(&key) else {
+            continue;
+        };
+        let listed_price = match trade.side {
+            TradeSide::Purchase => listing.purchase_price,
+            TradeSide::Sell => listing.sell_price,
+        };
+        let per_unit = model.per_unit(&trade.market, &trade.good, trade.side, listing);
+        let net = net_units.entry(key).or_insert(0);
+        let after = match trade.side {
+            TradeSide::Purchase => *net + trade.units,
+            TradeSide::Sell => *net - trade.units,
+        };
+        // linear, so the mean over the trade is the price halfway through it
+        let midpoint = (*net + after) as f64 / 2.0;
+        let price = price_after(listed_price, per_unit, midpoint).round() as i64;
+        *net = after;
+        let total = price * trade.units;
+        match trade.side {
+            TradeSide::Purchase => outcome.purchases += total,
+            TradeSide::Sell => outcome.sales += total,
+        }
+        outcome.trades.push(ProjectedTrade {
+            trade: trade.clone(),
+            listed_price,
+            price,
+            total,
+        });
+    }
+    for ((market, good), net) in net_units {
+        let listing = &listings[&(market.clone(), good.clone())];
+        let prices = [TradeSide::Purchase, TradeSide::Sell].map(|side| {
+            let listed = match side {
+                TradeSide::Purchase => listing.purchase_price,
+                TradeSide::Sell => listing.sell_price,
+            };
+            let per_unit = model.per_unit(&market, &good, side, listing);
+            price_after(listed, per_unit, net as f64).round() as i64
+        });
+        outcome
+            .final_prices
+            .insert((market, good), (prices[0], prices[1]));
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{MarketSupply, MarketType};
+
+    fn listing(
+        good: &str,
+        purchase_price: i64,
+        sell_price: i64,
+        trade_volume: i64,
+    ) -> MarketTradeGood {
+        MarketTradeGood {
+            symbol: good.to_string(),
+            trade_volume,
+            _type: MarketType::Exchange,
+            supply: MarketSupply::Moderate,
+            activity: None,
+            purchase_price,
+            sell_price,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_plan() {
+        let a = WaypointSymbol::new("X1-S1-A1");
+        let b = WaypointSymbol::new("X1-S1-B2");
+        let listings = Listings::from([
+            (
+                (a.clone(), "IRON".to_string()),
+                listing("IRON", 1000, 900, 20),
+            ),
+            (
+                (b.clone(), "IRON".to_string()),
+                listing("IRON", 2100, 2000, 20),
+            ),
+        ]);
+        let trade = |market: &WaypointSymbol, side, units| PlannedTrade {
+            market: market.clone(),
+            good: "IRON".to_string(),
+            side,
+            units,
+        };
+        let buy = trade(&a, TradeSide::Purchase, 20);
+        let sell = trade(&b, TradeSide::Sell, 20);
+
+        // without history, a trade volume moves a moderate market by 5%
+        let model = ImpactModel::default();
+        let one = evaluate_plan(&model, &listings, &[buy.clone(), sell.clone()]);
+        assert_eq!(one.trades[0].price, 1025);
+        assert_eq!(one.trades[1].price, 1950);
+        assert_eq!(one.net(), 1950 * 20 - 1025 * 20);
+        assert_eq!(
+            one.final_prices[&(a.clone(), "IRON".to_string())],
+            (1050, 945)
+        );
+
+        // a second hauler into the same markets gets the prices the first one left
+        let two = evaluate_plan(&model, &listings, &[buy.clone(), sell.clone(), buy, sell]);
+        assert_eq!(two.trades[2].price, 1075);
+        assert_eq!(two.trades[3].price, 1850);
+        assert!(two.net() - one.net() < one.net());
+
+        // markets without a listing of the good are left out
+        let c = WaypointSymbol::new("X1-S1-C3");
+        let unlisted = evaluate_plan(&model, &listings, &[trade(&c, TradeSide::Sell, 5)]);
+        assert_eq!(unlisted, ProjectedOutcome::default());
+        assert_eq!(
+            model.per_unit(&c, "IRON", TradeSide::Sell, &listing("IRON", 1, 1, 10)),
+            price_impact(&MarketSupply::Moderate) / 10.0
+        );
+    }
+
+    #[test]
+    fn test_impact_model_fit() {
+        let a = WaypointSymbol::new("X1-S1-A1");
+        let now = Utc::now();
+        let at = |mins| now + Duration::try_minutes(mins).unwrap();
+        let history = TransactionHistory::from([
+            (
+                (a.clone(), "IRON".to_string(), TradeSide::Purchase),
+                // 10 units move the price 2%, then the market restocks
+                vec![(at(0), 10, 100), (at(1), 10, 102), (at(60), 10, 100)],
+            ),
+            (
+                (a.clone(), "IRON".to_string(), TradeSide::Sell),
+                vec![(at(0), 10, 100)],
+            ),
+        ]);
+        let model = ImpactModel::fit(&history);
+        let listing = listing("IRON", 100, 90, 20);
+        let per_unit = model.per_unit(&a, "IRON", TradeSide::Purchase, &listing);
+        assert!((per_unit - 0.002).abs() < 1e-9);
+        // a single transaction can't be calibrated from
+        let per_unit = model.per_unit(&a, "IRON", TradeSide::Sell, &listing);
+        assert_eq!(per_unit, price_impact(&MarketSupply::Moderate) / 20.0);
+    }
+}
@@ -1,6 +1,15 @@
+pub mod market_impact;
 pub mod plan;
+use crate::db::versioned::Versioned;
 use crate::models::WaypointSymbol;
+use crate::pathfinding::Urgency;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Dock, act and return to orbit, at the API rate limit
+pub const DOCKING_DURATION_SECONDS: f64 = 3.0;
 
 // An action that can be taken at a waypoint
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -31,6 +40,28 @@ impl Action {
             Action::GetContract => None,
         }
     }
+
+    // Whether the ship has to dock to perform the action
+    pub fn requires_docking(&self) -> bool {
+        match self {
+            Action::BuyGoods(_, _) => true,
+            Action::SellGoods(_, _) => true,
+            Action::DeliverContract(_, _) => true,
+            Action::DeliverConstruction(_, _) => true,
+            Action::GetContract => true,
+            Action::RefreshMarket => false,
+            Action::RefreshShipyard => false,
+            Action::TryBuyShips => false,
+        }
+    }
+
+    // Seconds the action adds to a schedule, for the dock/orbit round trip
+    pub fn duration(&self) -> f64 {
+        match self.requires_docking() {
+            true => DOCKING_DURATION_SECONDS,
+            false => 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -38,6 +69,23 @@ pub struct Task {
     pub id: String,
     pub actions: TaskActions,
     pub value: i64,
+    // The task can't start before this time, e.g. a repeat of a trade route once its markets restock
+    #[serde(default)]
+    pub not_before: Option<DateTime<Utc>>,
+}
+
+impl Task {
+    // Tasks worth at least `urgent_value` are high urgency
+    pub fn urgency(&self, urgent_value: i64) -> Urgency {
+        match &self.actions {
+            _ if self.value >= urgent_value => Urgency::High,
+            TaskActions::VisitLocation {
+                action: Action::RefreshMarket | Action::RefreshShipyard,
+                ..
+            } => Urgency::Low,
+            _ => Urgency::Normal,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -64,8 +112,11 @@ pub struct LogisticShip {
 
 #[derive(Debug, Clone)]
 pub struct PlannerConstraints {
+    pub start: DateTime<Utc>,
     pub plan_length: chrono::Duration,
     pub max_compute_time: chrono::Duration,
+    // task_id -> hard deadline for the task's delivery, e.g. an accepted contract's
+    pub deadlines: BTreeMap<String, DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +125,30 @@ pub struct ScheduledAction {
     pub action: Action,
     pub timestamp: i64,
     pub task_completed: Option<Task>,
+    #[serde(default)]
+    pub not_before: Option<DateTime<Utc>>,
+    // Listed price when the schedule was planned: the purchase price for buys, the sell price for sells
+    #[serde(default)]
+    pub planned_price: Option<i64>,
+}
+
+impl ScheduledAction {
+    // Buys planned to cost at least `min_value` are checked for a price spike before buying
+    pub fn price_guarded(&self, min_value: i64) -> bool {
+        match (&self.action, self.planned_price) {
+            (Action::BuyGoods(_, units), Some(planned)) => units * planned >= min_value,
+            _ => false,
+        }
+    }
+
+    // The price rose more than `max_increase` (a fraction) since the buy was planned, e.g. another
+    // agent just bought out the market
+    pub fn price_spiked(&self, price: i64, max_increase: f64) -> bool {
+        match self.planned_price {
+            Some(planned) => price as f64 > planned as f64 * (1.0 + max_increase),
+            None => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,3 +156,277 @@ pub struct ShipSchedule {
     pub ship: LogisticShip,
     pub actions: Vec<ScheduledAction>,
 }
+
+impl ShipSchedule {
+    // Urgency of the trip to action `idx`, from the task the action is part of
+    pub fn urgency(&self, idx: usize, urgent_value: i64) -> Urgency {
+        let task = self.actions[idx].task_completed.as_ref().or_else(|| {
+            // a pickup's task is completed by its delivery
+            self.delivery_of(idx)
+                .and_then(|delivery| self.actions[delivery].task_completed.as_ref())
+        });
+        task.map(|task| task.urgency(urgent_value))
+            .unwrap_or(Urgency::Normal)
+    }
+
+    // Index of the delivery that completes the pickup at `idx`
+    pub fn delivery_of(&self, idx: usize) -> Option<usize> {
+        let pickup = &self.actions[idx];
+        (idx + 1..self.actions.len()).find(|&i| match &self.actions[i].task_completed {
+            Some(Task {
+                actions:
+                    TaskActions::TransportCargo {
+                        src, src_action, ..
+                    },
+                ..
+            }) => *src == pickup.waypoint && *src_action == pickup.action,
+            _ => false,
+        })
+    }
+}
+
+impl Versioned for ShipSchedule {
+    const TYPE_NAME: &'static str = "ShipSchedule";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionState {
+    Pending,
+    // started but not recorded as finished, it may or may not have taken effect
+    Executing,
+    Done,
+    // dropped from the schedule without being executed
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionStatus {
+    pub state: ActionState,
+    pub started: Option<DateTime<Utc>>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+impl ActionStatus {
+    fn pending() -> Self {
+        ActionStatus {
+            state: ActionState::Pending,
+            started: None,
+            finished: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, ActionState::Done | ActionState::Skipped)
+    }
+}
+
+// Status of each action of a ship's schedule, saved as each action starts and finishes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleProgress {
+    pub actions: Vec<ActionStatus>,
+}
+
+impl ScheduleProgress {
+    pub fn new(len: usize) -> Self {
+        ScheduleProgress {
+            actions: vec![ActionStatus::pending(); len],
+        }
+    }
+
+    // Match the length of the schedule, actions without a status are pending
+    pub fn resize(&mut self, len: usize) {
+        self.actions.resize(len, ActionStatus::pending());
+    }
+
+    // Index of the first action that hasn't finished
+    pub fn next(&self) -> Option<usize> {
+        self.actions.iter().position(|a| !a.is_finished())
+    }
+
+    pub fn finished_count(&self) -> usize {
+        self.next().unwrap_or(self.actions.len())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next().is_none()
+    }
+
+    pub fn state(&self, idx: usize) -> ActionState {
+        self.actions[idx].state
+    }
+
+    pub fn start(&mut self, idx: usize, now: DateTime<Utc>) {
+        let status = &mut self.actions[idx];
+        status.state = ActionState::Executing;
+        status.started = Some(now);
+    }
+
+    pub fn finish(&mut self, idx: usize, now: DateTime<Utc>) {
+        let status = &mut self.actions[idx];
+        status.state = ActionState::Done;
+        status.finished = Some(now);
+    }
+
+    pub fn skip(&mut self, idx: usize, now: DateTime<Utc>) {
+        let status = &mut self.actions[idx];
+        status.state = ActionState::Skipped;
+        status.finished = Some(now);
+    }
+
+    // Drop the actions from `idx` on
+    pub fn skip_from(&mut self, idx: usize, now: DateTime<Utc>) {
+        for status in self.actions.iter_mut().skip(idx) {
+            if !status.is_finished() {
+                status.state = ActionState::Skipped;
+                status.finished = Some(now);
+            }
+        }
+    }
+}
+
+impl Versioned for ScheduleProgress {
+    const TYPE_NAME: &'static str = "ScheduleProgress";
+    const VERSION: u32 = 2;
+
+    fn upgrade(from_version: u32, payload: Value) -> Option<Value> {
+        match from_version {
+            // v1 was the number of completed actions
+            1 => {
+                let done = ActionStatus {
+                    state: ActionState::Done,
+                    started: None,
+                    finished: None,
+                };
+                let progress = ScheduleProgress {
+                    actions: vec![done; payload.as_u64()? as usize],
+                };
+                Some(serde_json::to_value(progress).unwrap())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::versioned::{decode, encode};
+    use serde_json::json;
+
+    #[test]
+    fn test_schedule_progress() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut progress = ScheduleProgress::new(3);
+        assert_eq!(progress.next(), Some(0));
+
+        progress.start(0, now);
+        assert_eq!(progress.state(0), ActionState::Executing);
+        assert_eq!(progress.next(), Some(0));
+        progress.finish(0, now);
+        assert_eq!(progress.next(), Some(1));
+        assert_eq!(progress.finished_count(), 1);
+
+        progress.skip_from(1, now);
+        assert!(progress.is_complete());
+        assert_eq!(progress.state(0), ActionState::Done);
+        assert_eq!(progress.state(2), ActionState::Skipped);
+
+        let stored = encode(&progress);
+        assert_eq!(decode::<ScheduleProgress>("k", stored), Some(progress));
+
+        // the legacy completed action count
+        let mut legacy = decode::<ScheduleProgress>("k", json!(2)).unwrap();
+        legacy.resize(3);
+        assert_eq!(legacy.next(), Some(2));
+        assert_eq!(legacy.state(1), ActionState::Done);
+    }
+
+    #[test]
+    fn test_schedule_urgency() {
+        let a = WaypointSymbol::new("X1-AB12-A1");
+        let b = WaypointSymbol::new("X1-AB12-B2");
+        let trade = |value: i64| Task {
+            id: format!("trade_{}", value),
+            actions: TaskActions::TransportCargo {
+                src: a.clone(),
+                dest: b.clone(),
+                src_action: Action::BuyGoods("IRON".to_string(), 40),
+                dest_action: Action::SellGoods("IRON".to_string(), 40),
+            },
+            value,
+            not_before: None,
+        };
+        let refresh = Task {
+            id: "refresh".to_string(),
+            actions: TaskActions::VisitLocation {
+                waypoint: b.clone(),
+                action: Action::RefreshMarket,
+            },
+            value: 5000,
+            not_before: None,
+        };
+        let urgent = trade(100_000);
+        let scheduled =
+            |waypoint: &WaypointSymbol, action: Action, task: Option<&Task>| ScheduledAction {
+                waypoint: waypoint.clone(),
+                action,
+                timestamp: 0,
+                task_completed: task.cloned(),
+                not_before: None,
+                planned_price: None,
+            };
+        let schedule = ShipSchedule {
+            ship: LogisticShip {
+                symbol: "A-1".to_string(),
+                capacity: 40,
+                speed: 30,
+                start_waypoint: a.clone(),
+            },
+            actions: vec![
+                scheduled(&a, Action::BuyGoods("IRON".to_string(), 40), None),
+                scheduled(&b, Action::RefreshMarket, Some(&refresh)),
+                scheduled(&b, Action::SellGoods("IRON".to_string(), 40), Some(&urgent)),
+                scheduled(&a, Action::BuyGoods("COPPER".to_string(), 40), None),
+            ],
+        };
+        // the pickup takes the urgency of its delivery
+        assert_eq!(schedule.urgency(0, 100_000), Urgency::High);
+        assert_eq!(schedule.urgency(1, 100_000), Urgency::Low);
+        assert_eq!(schedule.urgency(2, 100_000), Urgency::High);
+        assert_eq!(schedule.urgency(3, 100_000), Urgency::Normal);
+        assert_eq!(trade(1000).urgency(100_000), Urgency::Normal);
+        assert_eq!(schedule.delivery_of(0), Some(2));
+        assert_eq!(schedule.delivery_of(3), None);
+    }
+
+    #[test]
+    fn test_price_spiked() {
+        let buy = ScheduledAction {
+            waypoint: WaypointSymbol::new("X1-AB12-A1"),
+            action: Action::BuyGoods("IRON".to_string(), 40),
+            timestamp: 0,
+            task_completed: None,
+            not_before: None,
+            planned_price: Some(1000),
+        };
+        assert!(buy.price_guarded(40_000));
+        assert!(!buy.price_guarded(40_001));
+        assert!(!buy.price_spiked(1200, 0.2));
+        assert!(buy.price_spiked(1201, 0.2));
+        assert!(!buy.price_spiked(500, 0.2));
+
+        // sells, and schedules planned before prices were recorded
+        let sell = ScheduledAction {
+            action: Action::SellGoods("IRON".to_string(), 40),
+            ..buy.clone()
+        };
+        assert!(!sell.price_guarded(0));
+        let unplanned = ScheduledAction {
+            planned_price: None,
+            ..buy.clone()
+        };
+        assert!(!unplanned.price_guarded(0));
+        assert!(!unplanned.price_spiked(2000, 0.2));
+    }
+}
@@ -48,6 +48,30 @@ pub fn run_planner(
         get_timestamp(constraints.plan_length.num_seconds()),
     ]];
 
+    // a task with a deadline must be done by then, or not at all
+    let end_offset = |task: &Task| match constraints.deadlines.get(&task.id) {
+        Some(deadline) => (*deadline - constraints.start)
+            .num_seconds()
+            .clamp(0, constraints.plan_length.num_seconds()),
+        None => constraints.plan_length.num_seconds(),
+    };
+    let end_window = |task: &Task| match constraints.deadlines.contains_key(&task.id) {
+        true => vec![vec![get_timestamp(0), get_timestamp(end_offset(task))]],
+        false => time_window.clone(),
+    };
+
+    // the task's first action waits for its not_before
+    let start_window = |task: &Task| match task.not_before {
+        Some(not_before) => {
+            let end = end_offset(task);
+            vec![vec![
+                get_timestamp((not_before - constraints.start).num_seconds().clamp(0, end)),
+                get_timestamp(end),
+            ]]
+        }
+        None => end_window(task),
+    };
+
     let jobs: Vec<Job> = tasks
         .iter()
         .map(|task| {
@@ -66,8 +90,8 @@ pub fn run_planner(
                                 location: Location::Reference {
                                     index: location_index(&mut locations, waypoint),
                                 },
-                                duration: 0.0,
-                                times: Some(time_window.clone()),
+                                duration: action.duration(),
+                                times: Some(start_window(task)),
                                 tag: Some(tag),
                             }],
                             demand: None, // service: no demand
@@ -97,7 +121,7 @@ pub fn run_planner(
                     };
                     assert_eq!(good, dest_good);
                     assert_eq!(units, dest_units);
-                    let id = format!("Transport-{}", good);
+                    let id = format!("Transport-{}", task.id);
                     task_job_id_map.insert(id.clone(), task);
                     Job {
                         id,
@@ -106,8 +130,8 @@ pub fn run_planner(
                                 location: Location::Reference {
                                     index: location_index(&mut locations, src),
                                 },
-                                duration: 0.0,
-                                times: Some(time_window.clone()),
+                                duration: src_action.duration(),
+                                times: Some(start_window(task)),
                                 tag: Some(format!("[{}] {:?} {} {}", src, src_action, units, good)),
                             }],
                             demand: Some(vec![*units as i32]),
@@ -118,8 +142,8 @@ pub fn run_planner(
                                 location: Location::Reference {
                                     index: location_index(&mut locations, dest),
                                 },
-                                duration: 0.0,
-                                times: Some(time_window.clone()),
+                                duration: dest_action.duration(),
+                                times: Some(end_window(task)),
                                 tag: Some(format!(
                                     "[{}] {:?} {} {}",
                                     dest, dest_action, units, good
@@ -316,16 +340,18 @@ pub fn task_to_scheduled_action(
     activity_type: &str,
     arrival: Option<i64>,
 ) -> ScheduledAction {
-    let (waypoint, action, task_completed) = match &task.actions {
-        TaskActions::VisitLocation { waypoint, action } => (waypoint, action, Some(task.clone())),
+    let (waypoint, action, task_completed, not_before) = match &task.actions {
+        TaskActions::VisitLocation { waypoint, action } => {
+            (waypoint, action, Some(task.clone()), task.not_before)
+        }
         TaskActions::TransportCargo {
             src,
             dest,
             src_action,
             dest_action,
         } => match activity_type {
-            "pickup" => (src, src_action, None),
-            "delivery" => (dest, dest_action, Some(task.clone())),
+            "pickup" => (src, src_action, None, task.not_before),
+            "delivery" => (dest, dest_action, Some(task.clone()), None),
             _ => panic!("unexpected activity type"),
         },
     };
@@ -334,6 +360,8 @@ pub fn task_to_scheduled_action(
         action: action.clone(),
         timestamp: arrival.unwrap_or_default(),
         task_completed,
+        not_before,
+        planned_price: None,
     }
 }
 
@@ -375,7 +403,7 @@ mod test {
                 start_waypoint: WaypointSymbol::new("X1-S1-W1"),
             },
         ];
-        let tasks = vec![
+        let mut tasks = vec![
             Task {
                 id: "TASK1".to_string(),
                 actions: TaskActions::VisitLocation {
@@ -383,6 +411,7 @@ mod test {
                     action: Action::RefreshMarket,
                 },
                 value: 1000,
+                not_before: None,
             },
             Task {
                 id: "TASK2".to_string(),
@@ -391,6 +420,7 @@ mod test {
                     action: Action::RefreshShipyard,
                 },
                 value: 1000,
+                not_before: None,
             },
             Task {
                 id: "TASK3".to_string(),
@@ -401,11 +431,19 @@ mod test {
                     dest_action: Action::SellGoods("FOOD".to_string(), 10),
                 },
                 value: 5000,
+                not_before: None,
             },
         ];
+        let start = Utc::now();
+        let mut repeat = tasks[2].clone();
+        repeat.id = "TASK3#2".to_string();
+        repeat.not_before = Some(start + Duration::try_seconds(1000).unwrap());
+        tasks.push(repeat);
         let constraints = PlannerConstraints {
+            start,
             plan_length: Duration::try_hours(24).unwrap(),
             max_compute_time: Duration::try_seconds(1).unwrap(),
+            deadlines: BTreeMap::new(),
         };
         let matrix = {
             let mut duration_matrix: BTreeMap<WaypointSymbol, BTreeMap<WaypointSymbol, i64>> =
@@ -426,6 +464,65 @@ mod test {
         };
         let (assignments, schedule) = run_planner(&ships, &tasks, &matrix, &constraints);
         assert_eq!(schedule.len(), 2);
-        assert_eq!(assignments.len(), 3);
+        assert_eq!(assignments.len(), 4);
+        // the repeat trip's pickup carries its window to the ship
+        let repeat_pickup = schedule
+            .iter()
+            .flat_map(|s| s.actions.iter())
+            .find(|a| a.not_before.is_some())
+            .unwrap();
+        assert_eq!(repeat_pickup.not_before, tasks[3].not_before);
+        assert!(matches!(repeat_pickup.action, Action::BuyGoods(_, _)));
+    }
+
+    #[test]
+    fn test_run_planner_deadline() {
+        let w1 = WaypointSymbol::new("X1-S1-W1");
+        let w2 = WaypointSymbol::new("X1-S1-W2");
+        let ships = vec![LogisticShip {
+            symbol: "SHIP1".to_string(),
+            capacity: 100,
+            speed: 10,
+            start_waypoint: w1.clone(),
+        }];
+        let tasks = vec![Task {
+            id: "CONTRACT1".to_string(),
+            actions: TaskActions::TransportCargo {
+                src: w1.clone(),
+                dest: w2.clone(),
+                src_action: Action::BuyGoods("IRON".to_string(), 10),
+                dest_action: Action::DeliverContract("IRON".to_string(), 10),
+            },
+            value: 5000,
+            not_before: None,
+        }];
+        let matrix = BTreeMap::from([
+            (
+                w1.clone(),
+                BTreeMap::from([(w1.clone(), 0), (w2.clone(), 100)]),
+            ),
+            (
+                w2.clone(),
+                BTreeMap::from([(w1.clone(), 100), (w2.clone(), 0)]),
+            ),
+        ]);
+        let start = Utc::now();
+        let constraints = |deadline_secs| PlannerConstraints {
+            start,
+            plan_length: Duration::try_hours(24).unwrap(),
+            max_compute_time: Duration::try_seconds(1).unwrap(),
+            deadlines: BTreeMap::from([(
+                "CONTRACT1".to_string(),
+                start + Duration::try_seconds(deadline_secs).unwrap(),
+            )]),
+        };
+
+        let (assignments, _) = run_planner(&ships, &tasks, &matrix, &constraints(3600));
+        assert_eq!(assignments[&tasks[0]], Some("SHIP1".to_string()));
+
+        // the delivery can't arrive in time
+        let (assignments, schedule) = run_planner(&ships, &tasks, &matrix, &constraints(50));
+        assert_eq!(assignments[&tasks[0]], None);
+        assert!(schedule[0].actions.is_empty());
     }
 }
@@ -0,0 +1,234 @@
+//!
+//! Per-system market health reports.
+//!
+//! Flags goods whose supply chains are broken, imports that are starving, imports that have evolved
+//! well past their starting trade volume, and exports nobody is buying. Reports are stored per system
+//! and consulted by the task manager when deciding how hard to push imports.
+//!
+use crate::db::{ok_or_warn, DbClient};
+use crate::models::MarketType::*;
+use crate::models::*;
+use crate::universe::access::UniverseReader;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+// Trade volume, as a multiple of the first seen trade volume, past which an import is over-evolved
+const OVER_EVOLVED_FACTOR: i64 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum HealthIssue {
+    // Market exports goods, but some of its imports aren't sold anywhere in the system
+    BrokenSupplyChain {
+        market: WaypointSymbol,
+        exports: Vec<String>,
+        missing_inputs: Vec<String>,
+    },
+    StarvingImport {
+        market: WaypointSymbol,
+        good: String,
+        supply: MarketSupply,
+    },
+    OverEvolved {
+        market: WaypointSymbol,
+        good: String,
+        trade_volume: i64,
+        initial_trade_volume: i64,
+    },
+    IdleExport {
+        market: WaypointSymbol,
+        good: String,
+        supply: MarketSupply,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketHealthReport {
+    pub system: SystemSymbol,
+    pub timestamp: DateTime<Utc>,
+    pub issues: Vec<HealthIssue>,
+}
+
+impl MarketHealthReport {
+    pub fn is_over_evolved(&self, market: &WaypointSymbol, good: &str) -> bool {
+        self.issues.iter().any(|issue| match issue {
+            HealthIssue::OverEvolved {
+                market: m, good: g, ..
+            } => m == market && g == good,
+            _ => false,
+        })
+    }
+}
+
+pub fn analyze(
+    system: &SystemSymbol,
+    markets: &[Arc<WithTimestamp<Market>>],
+    initial_trade_volumes: &BTreeMap<(WaypointSymbol, String), i64>,
+) -> MarketHealthReport {
+    // goods that can be bought somewhere in the system
+    let sources: BTreeSet<&str> = markets
+        .iter()
+        .flat_map(|m| m.data.exports.iter().chain(m.data.exchange.iter()))
+        .map(|g| g.symbol.as_str())
+        .collect();
+
+    let mut issues = vec![];
+    for market in markets {
+        let market_symbol = &market.data.symbol;
+        if !market.data.exports.is_empty() {
+            let missing_inputs = market
+                .data
+                .imports
+                .iter()
+                .filter(|g| !sources.contains(g.symbol.as_str()))
+                .map(|g| g.symbol.clone())
+                .collect::<Vec<_>>();
+            if !missing_inputs.is_empty() {
+                issues.push(HealthIssue::BrokenSupplyChain {
+                    market: market_symbol.clone(),
+                    exports: market
+                        .data
+                        .exports
+                        .iter()
+                        .map(|g| g.symbol.clone())
+                        .collect(),
+                    missing_inputs,
+                });
+            }
+        }
+        for trade in &market.data.trade_goods {
+            match trade._type {
+                Import => {
+                    if trade.supply == MarketSupply::Scarce {
+                        issues.push(HealthIssue::StarvingImport {
+                            market: market_symbol.clone(),
+                            good: trade.symbol.clone(),
+                            supply: trade.supply.clone(),
+                        });
+                    }
+                    let key = (market_symbol.clone(), trade.symbol.clone());
+                    if let Some(initial) = initial_trade_volumes.get(&key) {
+                        if trade.trade_volume >= initial * OVER_EVOLVED_FACTOR {
+                            issues.push(HealthIssue::OverEvolved {
+                                market: market_symbol.clone(),
+                                good: trade.symbol.clone(),
+                                trade_volume: trade.trade_volume,
+                                initial_trade_volume: *initial,
+                            });
+                        }
+                    }
+                }
+                Export => {
+                    if trade.supply >= MarketSupply::High
+                        && trade.activity == Some(MarketActivity::Weak)
+                    {
+                        issues.push(HealthIssue::IdleExport {
+                            market: market_symbol.clone(),
+                            good: trade.symbol.clone(),
+                            supply: trade.supply.clone(),
+                        });
+                    }
+                }
+                Exchange => {}
+            }
+        }
+    }
+    MarketHealthReport {
+        system: system.clone(),
+        timestamp: Utc::now(),
+        issues,
+    }
+}
+
+// Analyze the system's current markets and store the report
+pub async fn generate_report(
+    universe: &dyn UniverseReader,
+    db: &DbClient,
+    system: &SystemSymbol,
+) -> MarketHealthReport {
+    let markets = universe.known_system_markets(system).await;
+    let market_symbols = markets
+        .iter()
+        .map(|m| m.data.symbol.clone())
+        .collect::<Vec<_>>();
+    let initial_trade_volumes = ok_or_warn(
+        db.initial_trade_volumes(&market_symbols).await,
+        "load initial trade volumes",
+    )
+    .unwrap_or_default();
+    let report = analyze(system, &markets, &initial_trade_volumes);
+    ok_or_warn(db.save_market_health(&report).await, "save market health");
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trade(
+        symbol: &str,
+        _type: MarketType,
+        supply: MarketSupply,
+        volume: i64,
+    ) -> MarketTradeGood {
+        MarketTradeGood {
+            symbol: symbol.to_string(),
+            trade_volume: volume,
+            _type,
+            supply,
+            activity: Some(MarketActivity::Weak),
+            purchase_price: 100,
+            sell_price: 90,
+        }
+    }
+
+    fn symbols(goods: &[&str]) -> Vec<SymbolNameDescr> {
+        goods
+            .iter()
+            .map(|g| SymbolNameDescr {
+                symbol: g.to_string(),
+                name: String::new(),
+                description: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze() {
+        let smeltery = WaypointSymbol::new("X1-AB12-A1");
+        let market = Arc::new(WithTimestamp {
+            timestamp: Utc::now(),
+            data: Market {
+                symbol: smeltery.clone(),
+                transactions: vec![],
+                imports: symbols(&["IRON_ORE", "FUEL"]),
+                exports: symbols(&["IRON"]),
+                exchange: symbols(&["FUEL"]),
+                trade_goods: vec![
+                    trade("IRON_ORE", Import, MarketSupply::Scarce, 180),
+                    trade("IRON", Export, MarketSupply::Abundant, 60),
+                ],
+            },
+        });
+        let initial = BTreeMap::from([((smeltery.clone(), "IRON_ORE".to_string()), 60)]);
+        let report = analyze(&smeltery.system(), &[market], &initial);
+        assert_eq!(
+            report.issues[0],
+            HealthIssue::BrokenSupplyChain {
+                market: smeltery.clone(),
+                exports: vec!["IRON".to_string()],
+                missing_inputs: vec!["IRON_ORE".to_string()],
+            }
+        );
+        assert!(matches!(
+            report.issues[1],
+            HealthIssue::StarvingImport { .. }
+        ));
+        assert!(report.is_over_evolved(&smeltery, "IRON_ORE"));
+        assert!(!report.is_over_evolved(&smeltery, "IRON"));
+        assert!(matches!(report.issues[3], HealthIssue::IdleExport { .. }));
+        assert_eq!(report.issues.len(), 4);
+    }
+}
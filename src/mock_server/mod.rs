@@ -0,0 +1,74 @@
+//!
+//! Local mock SpaceTraders server (feature `mock_server`).
+//!
+//! Serves enough of the v2 API over HTTP (status, systems, waypoints, markets, shipyards, registration,
+//! navigation, trading and surveying) for the real ApiClient to run against it end-to-end without the
+//! internet.
+//! Point API_BASE_URL at it, e.g. http://localhost:8080.
+//!
+pub mod world;
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use log::*;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use world::MockWorld;
+
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    world: Arc<Mutex<MockWorld>>,
+}
+
+impl MockServer {
+    // time_scale multiplies transit times, 0.0 for instant arrival
+    pub fn new(time_scale: f64) -> Self {
+        Self {
+            world: Arc::new(Mutex::new(MockWorld::new(time_scale))),
+        }
+    }
+
+    pub fn router(&self) -> axum::Router {
+        let world = self.world.clone();
+        axum::Router::new().fallback(
+            move |method: Method, uri: Uri, headers: HeaderMap, body: Bytes| async move {
+                handle(&world, method, uri, headers, body)
+            },
+        )
+    }
+
+    pub async fn serve(self, listener: tokio::net::TcpListener) {
+        info!(
+            "Mock SpaceTraders server listening on {}",
+            listener.local_addr().unwrap()
+        );
+        axum::serve(listener, self.router()).await.unwrap();
+    }
+}
+
+fn handle(
+    world: &Mutex<MockWorld>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let token = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let result = world.lock().unwrap().handle(
+        method.as_str(),
+        uri.path(),
+        uri.query(),
+        token,
+        &body,
+        chrono::Utc::now(),
+    );
+    let (status, body) = result.unwrap_or_else(|err| err);
+    debug!("{} {} {}", status, method, uri);
+    (StatusCode::from_u16(status).unwrap(), Json(body)).into_response()
+}
@@ -0,0 +1,896 @@
+//!
+//! State and rules of the mock server's universe.
+//!
+//! A single system, the starter system fixture, with a fixed set of markets. Each trade moves the
+//! price against the trader in proportion to its size relative to the trade volume, and prices recover
+//! towards their base over time. Transit takes the game's travel time multiplied by `time_scale`.
+//!
+use crate::api_client::api_models::{self, WaypointDetailed};
+use crate::models::*;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+pub const SYSTEM_SYMBOL: &str = "X1-TEST";
+pub const HEADQUARTERS: &str = "X1-TEST-A1";
+const FACTION: &str = "COSMIC";
+const START_CREDITS: i64 = 175_000;
+
+const TRADE_VOLUME: i64 = 60;
+// Half the gap between purchase and sell price, as a fraction of the mid price
+const SPREAD: f64 = 0.05;
+// Price move caused by trading one full trade volume, as a fraction of the mid price
+const PRICE_IMPACT: f64 = 0.1;
+// Seconds for a price to recover half way back to its base
+const RECOVERY_HALF_LIFE: f64 = 600.0;
+// Cooldown after a survey, before time scaling
+const SURVEY_COOLDOWN: f64 = 70.0;
+const SURVEY_DEPOSITS: &[&str] = &["IRON_ORE", "QUARTZ_SAND", "SILICON_CRYSTALS", "ICE_WATER"];
+
+// (waypoint, good, type, base price)
+const MARKET_GOODS: &[(&str, &str, MarketType, i64)] = &[
+    ("X1-TEST-A1", "FUEL", MarketType::Exchange, 72),
+    ("X1-TEST-A1", "IRON", MarketType::Import, 140),
+    ("X1-TEST-A1", "ALUMINUM", MarketType::Export, 110),
+    ("X1-TEST-A2", "FUEL", MarketType::Exchange, 74),
+    ("X1-TEST-A2", "IRON", MarketType::Export, 100),
+    ("X1-TEST-B3", "FUEL", MarketType::Exchange, 70),
+    ("X1-TEST-B3", "HYDROCARBON", MarketType::Export, 40),
+    ("X1-TEST-C4", "FUEL", MarketType::Exchange, 76),
+    ("X1-TEST-C4", "IRON_ORE", MarketType::Export, 20),
+    ("X1-TEST-E6", "FUEL", MarketType::Exchange, 60),
+    ("X1-TEST-F7", "FUEL", MarketType::Exchange, 80),
+    ("X1-TEST-F7", "ALUMINUM", MarketType::Import, 160),
+    ("X1-TEST-F7", "HYDROCARBON", MarketType::Import, 80),
+    ("X1-TEST-F7", "IRON_ORE", MarketType::Import, 50),
+];
+
+// Error status and body, in the shape the real server uses
+pub type MockError = (u16, Value);
+
+fn error(status: u16, code: i64, message: &str) -> MockError {
+    (
+        status,
+        json!({ "error": { "message": message, "code": code } }),
+    )
+}
+
+#[derive(Debug, Clone)]
+struct MockGood {
+    symbol: String,
+    _type: MarketType,
+    base_price: f64,
+    price: f64,
+    updated: DateTime<Utc>,
+}
+
+impl MockGood {
+    fn recover(&mut self, now: DateTime<Utc>) {
+        let seconds = (now - self.updated).num_milliseconds().max(0) as f64 / 1000.0;
+        let remaining = 0.5f64.powf(seconds / RECOVERY_HALF_LIFE);
+        self.price = self.base_price + (self.price - self.base_price) * remaining;
+        self.updated = now;
+    }
+
+    // units > 0 for our purchases (price rises), units < 0 for our sales (price falls)
+    fn trade(&mut self, units: i64, now: DateTime<Utc>) {
+        self.recover(now);
+        let impact = PRICE_IMPACT * units as f64 / TRADE_VOLUME as f64;
+        self.price = (self.price * (1.0 + impact)).max(1.0);
+    }
+
+    fn purchase_price(&self) -> i64 {
+        (self.price * (1.0 + SPREAD)).round() as i64
+    }
+
+    fn sell_price(&self) -> i64 {
+        (self.price * (1.0 - SPREAD)).round() as i64
+    }
+
+    fn supply(&self) -> MarketSupply {
+        match self.price / self.base_price {
+            r if r >= 1.3 => MarketSupply::Scarce,
+            r if r >= 1.1 => MarketSupply::Limited,
+            r if r > 0.9 => MarketSupply::Moderate,
+            r if r > 0.7 => MarketSupply::High,
+            _ => MarketSupply::Abundant,
+        }
+    }
+
+    fn trade_good(&self) -> MarketTradeGood {
+        MarketTradeGood {
+            symbol: self.symbol.clone(),
+            trade_volume: TRADE_VOLUME,
+            _type: self._type.clone(),
+            supply: self.supply(),
+            activity: Some(MarketActivity::Growing),
+            purchase_price: self.purchase_price(),
+            sell_price: self.sell_price(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockAgent {
+    agent: Agent,
+    ships: BTreeMap<String, Ship>,
+}
+
+#[derive(Debug)]
+pub struct MockWorld {
+    time_scale: f64,
+    reset_date: String,
+    waypoints: Vec<WaypointDetailed>,
+    markets: BTreeMap<WaypointSymbol, Vec<MockGood>>,
+    transactions: BTreeMap<WaypointSymbol, Vec<MarketTransaction>>,
+    probe_listing: ShipyardShip,
+    // token -> agent
+    agents: BTreeMap<String, MockAgent>,
+}
+
+impl MockWorld {
+    pub fn new(time_scale: f64) -> Self {
+        let now = Utc::now();
+        let waypoints: Vec<WaypointDetailed> =
+            serde_json::from_str(include_str!("../../fixtures/starter_system_waypoints.json"))
+                .unwrap();
+        let mut markets: BTreeMap<WaypointSymbol, Vec<MockGood>> = BTreeMap::new();
+        for (waypoint, good, _type, price) in MARKET_GOODS {
+            markets
+                .entry(WaypointSymbol::new(waypoint))
+                .or_default()
+                .push(MockGood {
+                    symbol: good.to_string(),
+                    _type: _type.clone(),
+                    base_price: *price as f64,
+                    price: *price as f64,
+                    updated: now,
+                });
+        }
+        let shipyard: Shipyard =
+            serde_json::from_str(include_str!("../../fixtures/api/v2.2.0/shipyard.json")).unwrap();
+        let probe_listing = shipyard
+            .ships
+            .into_iter()
+            .find(|s| s.ship_type == "SHIP_PROBE")
+            .unwrap();
+        MockWorld {
+            time_scale,
+            reset_date: now.format("%Y-%m-%d").to_string(),
+            waypoints,
+            markets,
+            transactions: BTreeMap::new(),
+            probe_listing,
+            agents: BTreeMap::new(),
+        }
+    }
+
+    // Answer a request. `body` is Null for requests without one.
+    pub fn handle(
+        &mut self,
+        method: &str,
+        path: &str,
+        query: Option<&str>,
+        token: Option<&str>,
+        body: &Value,
+        now: DateTime<Utc>,
+    ) -> Result<(u16, Value), MockError> {
+        for agent in self.agents.values_mut() {
+            for ship in agent.ships.values_mut() {
+                settle(ship, now);
+            }
+        }
+        let page = Page::parse(query);
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let data = match (method, segments.as_slice()) {
+            ("GET", [""]) => return Ok((200, self.status())),
+            ("GET", ["systems.json"]) => return Ok((200, json!([self.system()]))),
+            ("GET", ["factions"]) => return Ok((200, page.list(&[faction()]))),
+            ("GET", ["systems", system]) => {
+                self.check_system(system)?;
+                json!(self.system())
+            }
+            ("GET", ["systems", system, "waypoints"]) => {
+                self.check_system(system)?;
+                return Ok((200, page.list(&self.waypoints)));
+            }
+            ("GET", ["systems", _, "waypoints", waypoint, resource]) => {
+                let waypoint = self.waypoint(waypoint)?.symbol.clone();
+                let present = token
+                    .and_then(|token| self.agents.get(token))
+                    .map(|agent| agent.ships.values().any(|s| is_present(s, &waypoint)))
+                    .unwrap_or(false);
+                match *resource {
+                    "market" => self.market(&waypoint, present, now)?,
+                    "shipyard" => self.shipyard(&waypoint, present)?,
+                    "jump-gate" => json!({ "symbol": waypoint, "connections": [] }),
+                    _ => return Err(error(404, 404, "Not found")),
+                }
+            }
+            ("POST", ["register"]) => return Ok((201, self.register(body, now)?)),
+            (_, ["my", rest @ ..]) => {
+                let Some(agent) = token.and_then(|token| self.agents.get(token)) else {
+                    return Err(error(401, 4100, "Missing or invalid bearer token"));
+                };
+                let agent_symbol = agent.agent.symbol.clone();
+                let token = token.unwrap().to_string();
+                match (method, rest) {
+                    ("GET", ["agent"]) => json!(agent.agent),
+                    ("GET", ["ships"]) => {
+                        let ships = agent.ships.values().cloned().collect::<Vec<_>>();
+                        return Ok((200, page.list(&ships)));
+                    }
+                    ("POST", ["ships"]) => return Ok((201, self.buy_ship(&token, body, now)?)),
+                    ("GET", ["ships", ship]) => match agent.ships.get(*ship) {
+                        Some(ship) => json!(ship),
+                        None => return Err(ship_not_found(&agent_symbol, ship)),
+                    },
+                    ("PATCH", ["ships", ship, "nav"]) => {
+                        self.set_flight_mode(&token, ship, body)?
+                    }
+                    ("POST", ["ships", ship, action]) => {
+                        self.ship_action(&token, ship, action, body, now)?
+                    }
+                    _ => return Err(error(404, 404, "Not found")),
+                }
+            }
+            _ => return Err(error(404, 404, "Not found")),
+        };
+        Ok((200, json!({ "data": data })))
+    }
+
+    fn status(&self) -> Value {
+        let ships = self.agents.values().map(|a| a.ships.len()).sum::<usize>();
+        json!({
+            "status": "SpaceTraders mock server",
+            "version": "v2.2.0",
+            "resetDate": self.reset_date,
+            "stats": {
+                "agents": self.agents.len(),
+                "ships": ships,
+                "systems": 1,
+                "waypoints": self.waypoints.len(),
+            },
+        })
+    }
+
+    fn system(&self) -> api_models::System {
+        api_models::System {
+            symbol: SystemSymbol::new(SYSTEM_SYMBOL),
+            system_type: "ORANGE_STAR".to_string(),
+            x: 0,
+            y: 0,
+            waypoints: self
+                .waypoints
+                .iter()
+                .map(|w| api_models::WaypointSimplified {
+                    symbol: w.symbol.clone(),
+                    waypoint_type: w.waypoint_type.clone(),
+                    x: w.x,
+                    y: w.y,
+                })
+                .collect(),
+        }
+    }
+
+    fn check_system(&self, system: &str) -> Result<(), MockError> {
+        match system == SYSTEM_SYMBOL {
+            true => Ok(()),
+            false => Err(error(404, 404, &format!("System {} not found", system))),
+        }
+    }
+
+    fn waypoint(&self, symbol: &str) -> Result<&WaypointDetailed, MockError> {
+        self.waypoints
+            .iter()
+            .find(|w| w.symbol.as_str() == symbol)
+            .ok_or_else(|| error(404, 404, &format!("Waypoint {} not found", symbol)))
+    }
+
+    fn market(
+        &mut self,
+        waypoint: &WaypointSymbol,
+        present: bool,
+        now: DateTime<Utc>,
+    ) -> Result<Value, MockError> {
+        let Some(goods) = self.markets.get_mut(waypoint) else {
+            return Err(error(404, 404, &format!("No market at {}", waypoint)));
+        };
+        for good in goods.iter_mut() {
+            good.recover(now);
+        }
+        let of_type = |_type: MarketType| {
+            goods
+                .iter()
+                .filter(|g| g._type == _type)
+                .map(|g| SymbolNameDescr {
+                    symbol: g.symbol.clone(),
+                    name: g.symbol.clone(),
+                    description: String::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let remote = MarketRemoteView {
+            symbol: waypoint.clone(),
+            imports: of_type(MarketType::Import),
+            exports: of_type(MarketType::Export),
+            exchange: of_type(MarketType::Exchange),
+        };
+        if !present {
+            return Ok(json!(remote));
+        }
+        Ok(json!(Market {
+            symbol: remote.symbol,
+            transactions: self.transactions.get(waypoint).cloned().unwrap_or_default(),
+            imports: remote.imports,
+            exports: remote.exports,
+            exchange: remote.exchange,
+            trade_goods: goods.iter().map(|g| g.trade_good()).collect(),
+        }))
+    }
+
+    fn shipyard(&self, waypoint: &WaypointSymbol, present: bool) -> Result<Value, MockError> {
+        if !self.waypoint(waypoint.as_str())?.is_shipyard() {
+            return Err(error(404, 404, &format!("No shipyard at {}", waypoint)));
+        }
+        let mut shipyard = json!({
+            "symbol": waypoint,
+            "shipTypes": [{ "type": self.probe_listing.ship_type }],
+            "modificationsFee": 100,
+        });
+        if present {
+            shipyard["transactions"] = json!([]);
+            shipyard["ships"] = json!([self.probe_listing]);
+        }
+        Ok(shipyard)
+    }
+
+    fn register(&mut self, body: &Value, now: DateTime<Utc>) -> Result<Value, MockError> {
+        let Some(symbol) = body["symbol"].as_str() else {
+            return Err(error(422, 422, "symbol is required"));
+        };
+        let symbol = symbol.to_uppercase();
+        if self.agents.values().any(|a| a.agent.symbol == symbol) {
+            return Err(error(
+                409,
+                4111,
+                &format!("Agent {} already exists", symbol),
+            ));
+        }
+        let token = uuid::Uuid::new_v4().to_string();
+        let hq = WaypointSymbol::new(HEADQUARTERS);
+        let mut command: Ship =
+            serde_json::from_str(include_str!("../../fixtures/api/v2.2.0/ship.json")).unwrap();
+        let probe: Ship =
+            serde_json::from_str(include_str!("../../fixtures/ship_probe.json")).unwrap();
+        command.cargo.units = 0;
+        command.cargo.inventory = vec![];
+        let command = self.new_ship(command, &format!("{}-1", symbol), &hq, "COMMAND", now);
+        let probe = self.new_ship(probe, &format!("{}-2", symbol), &hq, "SATELLITE", now);
+        let agent = Agent {
+            account_id: Some(token.clone()),
+            symbol: symbol.clone(),
+            headquarters: hq.clone(),
+            credits: START_CREDITS,
+            starting_faction: FACTION.to_string(),
+            ship_count: 2,
+        };
+        let contract = json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "factionSymbol": FACTION,
+            "type": "PROCUREMENT",
+            "terms": {
+                "deadline": (now + Duration::try_days(7).unwrap()).to_rfc3339(),
+                "payment": { "onAccepted": 10_000, "onFulfilled": 50_000 },
+                "deliver": [{
+                    "tradeSymbol": "IRON",
+                    "destinationSymbol": hq,
+                    "unitsRequired": 60,
+                    "unitsFulfilled": 0,
+                }],
+            },
+            "accepted": false,
+            "fulfilled": false,
+            "expiration": now + Duration::try_days(1).unwrap(),
+            "deadlineToAccept": now + Duration::try_days(1).unwrap(),
+        });
+        let data = json!({
+            "token": token,
+            "agent": agent,
+            "contract": contract,
+            "faction": faction(),
+            "ship": command,
+        });
+        let ships = BTreeMap::from([
+            (command.symbol.clone(), command),
+            (probe.symbol.clone(), probe),
+        ]);
+        self.agents.insert(token, MockAgent { agent, ships });
+        Ok(json!({ "data": data }))
+    }
+
+    // A ship built from a template, docked at the waypoint with full fuel and empty cargo
+    fn new_ship(
+        &self,
+        mut ship: Ship,
+        symbol: &str,
+        waypoint: &WaypointSymbol,
+        role: &str,
+        now: DateTime<Utc>,
+    ) -> Ship {
+        let w = self.waypoint(waypoint.as_str()).unwrap();
+        let route_waypoint = route_waypoint(w);
+        // arrived well before now, clients correct their clock by the second-resolution Date header
+        let arrived = now - Duration::try_minutes(1).unwrap();
+        ship.symbol = symbol.to_string();
+        ship.nav = ShipNav {
+            system_symbol: waypoint.system(),
+            waypoint_symbol: waypoint.clone(),
+            route: ShipNavRoute {
+                origin: route_waypoint.clone(),
+                destination: route_waypoint,
+                arrival: arrived,
+                departure_time: arrived,
+            },
+            status: ShipNavStatus::Docked,
+            flight_mode: ShipFlightMode::Cruise,
+        };
+        ship.fuel.current = ship.fuel.capacity;
+        ship.fuel.consumed = ShipFuelConsumed {
+            amount: 0,
+            timestamp: now,
+        };
+        ship.cooldown = ShipCooldown {
+            ship_symbol: symbol.to_string(),
+            total_seconds: 0,
+            remaining_seconds: 0,
+            expiration: None,
+        };
+        ship.registration = ShipRegistration {
+            name: symbol.to_string(),
+            faction_symbol: FACTION.to_string(),
+            role: role.to_string(),
+        };
+        ship
+    }
+
+    fn buy_ship(
+        &mut self,
+        token: &str,
+        body: &Value,
+        now: DateTime<Utc>,
+    ) -> Result<Value, MockError> {
+        let ship_type = body["shipType"].as_str().unwrap_or_default();
+        let waypoint = self
+            .waypoint(body["waypointSymbol"].as_str().unwrap_or_default())?
+            .symbol
+            .clone();
+        let agent = &self.agents[token];
+        if !agent.ships.values().any(|s| is_present(s, &waypoint)) {
+            return Err(error(
+                400,
+                4604,
+                &format!("No ship present at {} to purchase from", waypoint),
+            ));
+        }
+        if ship_type != self.probe_listing.ship_type
+            || !self.waypoint(waypoint.as_str())?.is_shipyard()
+        {
+            return Err(error(
+                400,
+                4605,
+                &format!("{} is not sold at {}", ship_type, waypoint),
+            ));
+        }
+        let price = self.probe_listing.purchase_price;
+        if agent.agent.credits < price {
+            return Err(insufficient_credits(price, agent.agent.credits));
+        }
+        let symbol = format!("{}-{:X}", agent.agent.symbol, agent.ships.len() + 1);
+        let template: Ship =
+            serde_json::from_str(include_str!("../../fixtures/ship_probe.json")).unwrap();
+        let ship = self.new_ship(template, &symbol, &waypoint, "SATELLITE", now);
+        let agent = self.agents.get_mut(token).unwrap();
+        agent.agent.credits -= price;
+        agent.agent.ship_count += 1;
+        agent.ships.insert(symbol, ship.clone());
+        let transaction = json!({
+            "waypointSymbol": waypoint,
+            "shipSymbol": ship.symbol,
+            "shipType": ship_type,
+            "price": price,
+            "agentSymbol": agent.agent.symbol,
+            "timestamp": now,
+        });
+        Ok(json!({ "data": { "agent": agent.agent, "ship": ship, "transaction": transaction } }))
+    }
+
+    fn set_flight_mode(
+        &mut self,
+        token: &str,
+        ship: &str,
+        body: &Value,
+    ) -> Result<Value, MockError> {
+        let agent = self.agents.get_mut(token).unwrap();
+        let Some(ship) = agent.ships.get_mut(ship) else {
+            return Err(ship_not_found(&agent.agent.symbol, ship));
+        };
+        let Ok(flight_mode) = serde_json::from_value(body["flightMode"].clone()) else {
+            return Err(error(422, 422, "Invalid flightMode"));
+        };
+        ship.nav.flight_mode = flight_mode;
+        Ok(json!(ship.nav))
+    }
+
+    fn ship_action(
+        &mut self,
+        token: &str,
+        ship_symbol: &str,
+        action: &str,
+        body: &Value,
+        now: DateTime<Utc>,
+    ) -> Result<Value, MockError> {
+        let agent = self.agents.get_mut(token).unwrap();
+        let Some(ship) = agent.ships.get_mut(ship_symbol) else {
+            return Err(ship_not_found(&agent.agent.symbol, ship_symbol));
+        };
+        if ship.nav.status == ShipNavStatus::InTransit {
+            return Err(error(
+                400,
+                4214,
+                &format!("Ship {} is currently in transit", ship_symbol),
+            ));
+        }
+        let data = match action {
+            "orbit" => {
+                ship.nav.status = ShipNavStatus::InOrbit;
+                json!({ "nav": ship.nav })
+            }
+            "dock" => {
+                ship.nav.status = ShipNavStatus::Docked;
+                json!({ "nav": ship.nav })
+            }
+            "navigate" => {
+                let target = body["waypointSymbol"].as_str().unwrap_or_default();
+                let Some(dest) = self.waypoints.iter().find(|w| w.symbol.as_str() == target) else {
+                    return Err(error(404, 404, &format!("Waypoint {} not found", target)));
+                };
+                if ship.nav.status != ShipNavStatus::InOrbit {
+                    return Err(error(
+                        400,
+                        4236,
+                        &format!("Ship {} is not currently in orbit", ship_symbol),
+                    ));
+                }
+                if dest.symbol == ship.nav.waypoint_symbol {
+                    return Err(error(
+                        400,
+                        4204,
+                        &format!("Ship {} is already at {}", ship_symbol, target),
+                    ));
+                }
+                let src = self
+                    .waypoints
+                    .iter()
+                    .find(|w| w.symbol == ship.nav.waypoint_symbol)
+                    .unwrap();
+                let distance = src.distance(dest);
+                let (fuel, modifier) = match ship.nav.flight_mode {
+                    ShipFlightMode::Cruise => (distance, 25.0),
+                    ShipFlightMode::Burn => (2 * distance, 12.5),
+                    ShipFlightMode::Drift => (1, 250.0),
+                    ShipFlightMode::Stealth => (distance, 30.0),
+                };
+                let fuel = if ship.fuel.capacity == 0 { 0 } else { fuel };
+                if fuel > ship.fuel.current {
+                    return Err(error(
+                        400,
+                        4203,
+                        &format!(
+                            "Navigate request failed. Ship {} requires {} more fuel for navigation.",
+                            ship_symbol,
+                            fuel - ship.fuel.current
+                        ),
+                    ));
+                }
+                let speed = ship.engine.speed.max(1) as f64;
+                let seconds = (15.0 + modifier / speed * distance as f64).round() * self.time_scale;
+                ship.fuel.current -= fuel;
+                ship.fuel.consumed = ShipFuelConsumed {
+                    amount: fuel,
+                    timestamp: now,
+                };
+                ship.nav.route = ShipNavRoute {
+                    origin: route_waypoint(src),
+                    destination: route_waypoint(dest),
+                    arrival: now + Duration::try_milliseconds((seconds * 1000.0) as i64).unwrap(),
+                    departure_time: now,
+                };
+                ship.nav.waypoint_symbol = dest.symbol.clone();
+                ship.nav.status = ShipNavStatus::InTransit;
+                settle(ship, now);
+                json!({ "nav": ship.nav, "fuel": ship.fuel, "events": [] })
+            }
+            "survey" => {
+                let waypoint = self
+                    .waypoints
+                    .iter()
+                    .find(|w| w.symbol == ship.nav.waypoint_symbol)
+                    .unwrap();
+                if !waypoint.is_asteroid() {
+                    return Err(error(
+                        400,
+                        4223,
+                        &format!("Waypoint {} is not an asteroid", waypoint.symbol),
+                    ));
+                }
+                if !ship
+                    .mounts
+                    .iter()
+                    .any(|m| m.symbol.starts_with("MOUNT_SURVEYOR"))
+                {
+                    return Err(error(
+                        400,
+                        4225,
+                        &format!("Ship {} has no surveyor mount", ship_symbol),
+                    ));
+                }
+                let seconds = (SURVEY_COOLDOWN * self.time_scale).round() as i64;
+                ship.cooldown = ShipCooldown {
+                    ship_symbol: ship_symbol.to_string(),
+                    total_seconds: seconds,
+                    remaining_seconds: seconds,
+                    expiration: Some(now + Duration::try_seconds(seconds).unwrap()),
+                };
+                let survey = Survey {
+                    signature: format!("{}-{:08X}", waypoint.symbol, rand::random::<u32>()),
+                    symbol: waypoint.symbol.clone(),
+                    deposits: SURVEY_DEPOSITS
+                        .iter()
+                        .map(|good| Symbol {
+                            symbol: good.to_string(),
+                        })
+                        .collect(),
+                    expiration: now + Duration::try_minutes(15).unwrap(),
+                    size: "SMALL".to_string(),
+                };
+                json!({ "cooldown": ship.cooldown, "surveys": [survey] })
+            }
+            "refuel" | "purchase" | "sell" => {
+                if ship.nav.status != ShipNavStatus::Docked {
+                    return Err(error(
+                        400,
+                        4244,
+                        &format!("Ship {} is not docked", ship_symbol),
+                    ));
+                }
+                let waypoint = ship.nav.waypoint_symbol.clone();
+                let good = match action {
+                    "refuel" => "FUEL",
+                    _ => body["symbol"].as_str().unwrap_or_default(),
+                };
+                let Some(market_good) = self
+                    .markets
+                    .get_mut(&waypoint)
+                    .and_then(|goods| goods.iter_mut().find(|g| g.symbol == good))
+                else {
+                    return Err(error(
+                        400,
+                        4602,
+                        &format!("Market at {} does not trade {}", waypoint, good),
+                    ));
+                };
+                market_good.recover(now);
+                let (units, market_units) = match action {
+                    "refuel" => {
+                        let missing = ship.fuel.capacity - ship.fuel.current;
+                        let units = body["units"].as_i64().unwrap_or(missing).min(missing);
+                        (units, (units + 99) / 100)
+                    }
+                    _ => {
+                        let units = body["units"].as_i64().unwrap_or_default();
+                        (units, units)
+                    }
+                };
+                if market_units <= 0 || market_units > TRADE_VOLUME {
+                    return Err(error(
+                        400,
+                        4604,
+                        &format!(
+                            "Trade of {} units is outside the trade volume of {}",
+                            market_units, TRADE_VOLUME
+                        ),
+                    ));
+                }
+                let (price, _type) = match action {
+                    "sell" => (market_good.sell_price(), "SELL"),
+                    _ => (market_good.purchase_price(), "PURCHASE"),
+                };
+                let total_price = market_units * price;
+                if _type == "PURCHASE" && agent.agent.credits < total_price {
+                    return Err(insufficient_credits(total_price, agent.agent.credits));
+                }
+                match action {
+                    "refuel" => ship.fuel.current += units,
+                    "purchase" => add_cargo(&mut ship.cargo, good, units)?,
+                    _ => add_cargo(&mut ship.cargo, good, -units)?,
+                }
+                match _type {
+                    "SELL" => {
+                        agent.agent.credits += total_price;
+                        market_good.trade(-market_units, now);
+                    }
+                    _ => {
+                        agent.agent.credits -= total_price;
+                        market_good.trade(market_units, now);
+                    }
+                }
+                let transaction = MarketTransaction {
+                    waypoint_symbol: waypoint.clone(),
+                    ship_symbol: ship_symbol.to_string(),
+                    trade_symbol: good.to_string(),
+                    _type: _type.to_string(),
+                    units: market_units,
+                    price_per_unit: price,
+                    total_price,
+                    timestamp: now,
+                };
+                self.transactions
+                    .entry(waypoint)
+                    .or_default()
+                    .push(transaction.clone());
+                match action {
+                    "refuel" => json!({
+                        "agent": agent.agent,
+                        "fuel": ship.fuel,
+                        "transaction": transaction,
+                    }),
+                    _ => json!({
+                        "agent": agent.agent,
+                        "cargo": ship.cargo,
+                        "transaction": transaction,
+                    }),
+                }
+            }
+            _ => {
+                return Err(error(
+                    400,
+                    0,
+                    &format!("Ship action {} is not supported by the mock server", action),
+                ))
+            }
+        };
+        Ok(data)
+    }
+}
+
+// Query parameters of a paginated list
+struct Page {
+    page: usize,
+    limit: usize,
+}
+
+impl Page {
+    fn parse(query: Option<&str>) -> Self {
+        let mut page = Page { page: 1, limit: 10 };
+        for pair in query.unwrap_or_default().split('&') {
+            match pair.split_once('=') {
+                Some(("page", value)) => page.page = value.parse().unwrap_or(1).max(1),
+                Some(("limit", value)) => page.limit = value.parse().unwrap_or(10).clamp(1, 20),
+                _ => {}
+            }
+        }
+        page
+    }
+
+    fn list<T: serde::Serialize>(&self, items: &[T]) -> Value {
+        let data = items
+            .iter()
+            .skip((self.page - 1) * self.limit)
+            .take(self.limit)
+            .collect::<Vec<_>>();
+        json!({
+            "data": data,
+            "meta": { "page": self.page, "limit": self.limit, "total": items.len() },
+        })
+    }
+}
+
+fn faction() -> Value {
+    json!({
+        "symbol": FACTION,
+        "name": "Cosmic Engineers",
+        "description": "",
+        "headquarters": SYSTEM_SYMBOL,
+        "traits": [],
+        "isRecruiting": true,
+    })
+}
+
+fn route_waypoint(waypoint: &WaypointDetailed) -> ShipNavRouteWaypoint {
+    ShipNavRouteWaypoint {
+        symbol: waypoint.symbol.clone(),
+        waypoint_type: waypoint.waypoint_type.clone(),
+        system_symbol: waypoint.system_symbol.clone(),
+        x: waypoint.x,
+        y: waypoint.y,
+    }
+}
+
+// Complete a transit that has reached its arrival time
+fn settle(ship: &mut Ship, now: DateTime<Utc>) {
+    if ship.nav.status == ShipNavStatus::InTransit && ship.nav.route.arrival <= now {
+        ship.nav.status = ShipNavStatus::InOrbit;
+    }
+}
+
+fn is_present(ship: &Ship, waypoint: &WaypointSymbol) -> bool {
+    ship.nav.waypoint_symbol == *waypoint && ship.nav.status != ShipNavStatus::InTransit
+}
+
+fn ship_not_found(agent_symbol: &str, ship_symbol: &str) -> MockError {
+    error(
+        404,
+        404,
+        &format!("Ship {} not found for agent {}", ship_symbol, agent_symbol),
+    )
+}
+
+fn insufficient_credits(required: i64, credits: i64) -> MockError {
+    error(
+        400,
+        4600,
+        &format!(
+            "Agent has insufficient funds. Available: {}, required: {}",
+            credits, required
+        ),
+    )
+}
+
+fn add_cargo(cargo: &mut ShipCargo, good: &str, units: i64) -> Result<(), MockError> {
+    if cargo.units + units > cargo.capacity {
+        return Err(error(400, 4228, "Insufficient cargo space"));
+    }
+    match cargo.inventory.iter_mut().find(|i| i.symbol == good) {
+        Some(item) if item.units + units >= 0 => item.units += units,
+        None if units >= 0 => cargo.inventory.push(ShipCargoItem {
+            symbol: good.to_string(),
+            name: good.to_string(),
+            description: String::new(),
+            units,
+        }),
+        _ => return Err(error(400, 4219, &format!("Insufficient {} in cargo", good))),
+    }
+    cargo.inventory.retain(|i| i.units > 0);
+    cargo.units += units;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_price_dynamics() {
+        let now = Utc::now();
+        let mut good = MockGood {
+            symbol: "IRON".to_string(),
+            _type: MarketType::Export,
+            base_price: 100.0,
+            price: 100.0,
+            updated: now,
+        };
+        assert_eq!(good.purchase_price(), 105);
+        assert_eq!(good.sell_price(), 95);
+
+        // buying a full trade volume raises the price by 10%
+        good.trade(TRADE_VOLUME, now);
+        assert_eq!(good.purchase_price(), 116);
+        good.trade(2 * TRADE_VOLUME, now);
+        assert_eq!(good.supply(), MarketSupply::Scarce);
+
+        // half way back to base after one half life
+        good.recover(now + Duration::try_seconds(600).unwrap());
+        assert!((good.price - 100.0 - 0.5 * (132.0 - 100.0)).abs() < 1e-9);
+    }
+}
@@ -15,6 +15,15 @@ pub struct Contract {
     pub deadline_to_accept: DateTime<Utc>,
 }
 
+impl Contract {
+    // Deliveries must be fulfilled by then
+    pub fn deadline(&self) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&self.terms.deadline)
+            .expect("Invalid contract deadline")
+            .with_timezone(&Utc)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Terms {
     pub deadline: String,
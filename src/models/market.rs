@@ -27,7 +27,7 @@ pub struct MarketRemoteView {
     pub exchange: Vec<SymbolNameDescr>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MarketTradeGood {
     pub symbol: String,
@@ -85,6 +85,9 @@ pub enum MarketActivity {
     Strong,
     #[serde(rename = "RESTRICTED")]
     Restricted,
+    // Any activity level added after this was written
+    #[serde(other)]
+    Unknown,
 }
 
 impl Display for MarketActivity {
@@ -108,9 +111,13 @@ pub struct Shipyard {
 pub struct ShipyardShip {
     #[serde(rename = "type")]
     pub ship_type: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub supply: String,
+    pub activity: Option<String>,
     pub purchase_price: i64,
     pub frame: ShipFrame,
     pub reactor: ShipReactor,
@@ -22,11 +22,12 @@ pub struct Status {
     pub status: String,
     pub version: String,
     pub reset_date: String,
+    #[serde(default)]
     pub stats: Stats,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 pub struct Stats {
     pub agents: i64,
     pub ships: i64,
@@ -72,7 +73,9 @@ pub struct Symbol {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SymbolNameDescr {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
 }
 
@@ -88,6 +91,8 @@ pub struct LogisticsScriptConfig {
     pub allow_shipbuying: bool,
     pub allow_construction: bool,
     pub allow_market_refresh: bool,
+    // trades selling in a system one jump away
+    pub allow_gate_trades: bool,
     pub waypoint_allowlist: Option<Vec<WaypointSymbol>>,
     pub min_profit: i64,
 }
@@ -110,6 +115,8 @@ pub enum ShipBehaviour {
     ConstructionHauler,
     JumpgateProbe,
     Explorer,
+    // script registered under this name in the ShipScriptRegistry, with its config
+    Custom(String, serde_json::Value),
 }
 
 #[derive(Debug, Clone)]
@@ -176,6 +183,8 @@ pub struct Survey {
 pub struct KeyedSurvey {
     pub uuid: Uuid,
     pub survey: Survey,
+    // callsign of the agent that took the survey
+    pub owner: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -219,6 +228,12 @@ mod test {
         let contract: contract::Contract =
             serde_json::from_value(val["data"]["contract"].clone()).unwrap();
         assert_eq!(contract.id, "cls7fi0q2rns0s60cgvarxu6v");
+        assert_eq!(
+            contract
+                .deadline()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "2024-02-11T11:37:29.626Z"
+        );
 
         let faction: faction::Faction =
             serde_json::from_value(val["data"]["faction"].clone()).unwrap();
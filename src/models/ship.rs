@@ -1,14 +1,13 @@
-use crate::models::{SystemSymbol, WaypointSymbol};
+use crate::models::{ShipyardShip, SystemSymbol, WaypointSymbol};
 use chrono::{DateTime, Utc};
-use maplit::hashmap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Ship {
     pub symbol: String,
     pub nav: ShipNav,
+    #[serde(default)]
     pub crew: ShipCrew,
     pub fuel: ShipFuel,
     pub cooldown: ShipCooldown,
@@ -21,7 +20,7 @@ pub struct Ship {
     pub cargo: ShipCargo,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipNav {
     pub system_symbol: SystemSymbol,
@@ -54,7 +53,7 @@ pub enum ShipNavStatus {
     InOrbit,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipNavRoute {
     // pub departure: ShipNavRouteWaypoint // deprecated
@@ -64,7 +63,7 @@ pub struct ShipNavRoute {
     pub departure_time: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipNavRouteWaypoint {
     pub symbol: WaypointSymbol,
@@ -75,7 +74,7 @@ pub struct ShipNavRouteWaypoint {
     pub y: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipCrew {
     pub current: i64,
@@ -86,7 +85,7 @@ pub struct ShipCrew {
     pub wages: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipFuel {
     pub current: i64,
@@ -94,14 +93,14 @@ pub struct ShipFuel {
     pub consumed: ShipFuelConsumed,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipFuelConsumed {
     pub amount: i64,
     pub timestamp: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipCooldown {
     pub ship_symbol: String,
@@ -110,21 +109,24 @@ pub struct ShipCooldown {
     pub expiration: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipFrame {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub module_slots: i64,
     pub mounting_points: i64,
     pub fuel_capacity: i64,
     pub condition: Option<f64>,
     pub integrity: Option<f64>,
+    #[serde(default)]
     pub requirements: ShipRequirements,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipRequirements {
     #[serde(default)]
@@ -135,51 +137,63 @@ pub struct ShipRequirements {
     pub slots: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipReactor {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub condition: Option<f64>,
     pub integrity: Option<f64>,
     pub power_output: i64,
+    #[serde(default)]
     pub requirements: ShipRequirements,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipEngine {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub condition: Option<f64>,
     pub integrity: Option<f64>,
     pub speed: i64,
+    #[serde(default)]
     pub requirements: ShipRequirements,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipModule {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub capacity: Option<i64>,
+    #[serde(default)]
     pub requirements: ShipRequirements,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipMount {
     pub symbol: String,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub strength: Option<i64>,
+    #[serde(default)]
     pub requirements: ShipRequirements,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipRegistration {
     pub name: String,
@@ -187,7 +201,7 @@ pub struct ShipRegistration {
     pub role: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipCargo {
     pub capacity: i64,
@@ -195,147 +209,87 @@ pub struct ShipCargo {
     pub inventory: Vec<ShipCargoItem>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipCargoItem {
     pub symbol: String,
     pub units: i64,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub description: String,
 }
 
-#[derive(Debug, Clone)]
+// Stats of a ship model, as listed in shipyards
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShipModel {
+    pub ship_type: String,
     pub frame: String,
     pub reactor: String,
     pub engine: String,
-    pub req_modules: Vec<String>,
-    pub req_mounts: Vec<String>,
+    // a ship of this model has at least these modules and mounts
+    pub modules: Vec<String>,
+    pub mounts: Vec<String>,
     pub cargo_capacity: i64,
+    // unknown until the model has been seen in a shipyard
+    pub fuel_capacity: Option<i64>,
+    pub speed: Option<i64>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
 }
 
-// ship models
-lazy_static::lazy_static! {
-    pub static ref SHIP_MODELS: HashMap<&'static str, ShipModel> = hashmap!{
-        "SHIP_COMMAND_FRIGATE" => ShipModel {
-            frame: "FRAME_FRIGATE".to_string(),
-            reactor: "REACTOR_FISSION_I".to_string(),
-            engine: "ENGINE_ION_DRIVE_II".to_string(),
-            req_modules: vec![],
-            req_mounts: vec![],
-            cargo_capacity: 40,
-        },
-        "SHIP_PROBE" => ShipModel {
-            frame: "FRAME_PROBE".to_string(),
-            reactor: "REACTOR_SOLAR_I".to_string(),
-            engine: "ENGINE_IMPULSE_DRIVE_I".to_string(),
-            req_modules: vec![],
-            req_mounts: vec![],
-            cargo_capacity: 0,
-        },
-        "SHIP_LIGHT_SHUTTLE" => ShipModel {
-            frame: "FRAME_SHUTTLE".to_string(),
-            reactor: "REACTOR_CHEMICAL_I".to_string(),
-            engine: "ENGINE_IMPULSE_DRIVE_I".to_string(),
-            req_modules: vec![],
-            req_mounts: vec![],
-            cargo_capacity: 40,
-        },
-        "SHIP_LIGHT_HAULER" => ShipModel {
-            frame: "FRAME_LIGHT_FREIGHTER".to_string(),
-            reactor: "REACTOR_CHEMICAL_I".to_string(),
-            engine: "ENGINE_ION_DRIVE_I".to_string(),
-            req_modules: vec![],
-            req_mounts: vec![],
-            cargo_capacity: 80,
-        },
-        "SHIP_MINING_DRONE" => ShipModel {
-            frame: "FRAME_DRONE".to_string(),
-            reactor: "REACTOR_CHEMICAL_I".to_string(),
-            engine: "ENGINE_IMPULSE_DRIVE_I".to_string(),
-            req_modules: vec!["MODULE_MINERAL_PROCESSOR_I".to_string()],
-            req_mounts: vec!["MOUNT_MINING_LASER_I".to_string()],
-            cargo_capacity: 15,
-        },
-        "SHIP_SURVEYOR" => ShipModel {
-            frame: "FRAME_DRONE".to_string(),
-            reactor: "REACTOR_CHEMICAL_I".to_string(),
-            engine: "ENGINE_IMPULSE_DRIVE_I".to_string(),
-            req_modules: vec![],
-            req_mounts: vec!["MOUNT_SURVEYOR_I".to_string()],
-            cargo_capacity: 0,
-        },
-        "SHIP_SIPHON_DRONE" => ShipModel {
-            frame: "FRAME_DRONE".to_string(),
-            reactor: "REACTOR_CHEMICAL_I".to_string(),
-            engine: "ENGINE_IMPULSE_DRIVE_I".to_string(),
-            req_modules: vec!["MODULE_GAS_PROCESSOR_I".to_string()],
-            req_mounts: vec!["MOUNT_GAS_SIPHON_I".to_string()],
-            cargo_capacity: 15,
-        },
-        "SHIP_REFINING_FREIGHTER" => ShipModel {
-            frame: "FRAME_HEAVY_FREIGHTER".to_string(),
-            reactor: "REACTOR_FUSION_I".to_string(),
-            engine: "ENGINE_ION_DRIVE_II".to_string(),
-            req_modules: vec!["MODULE_CARGO_HOLD_III".to_string(), "MODULE_ORE_REFINERY_I".to_string()],
-            req_mounts: vec!["MOUNT_MISSILE_LAUNCHER_I".to_string()],
-            cargo_capacity: 150,
-        },
-        "SHIP_ORE_HOUND" => ShipModel {
-            frame: "FRAME_MINER".to_string(),
-            reactor: "REACTOR_FISSION_I".to_string(),
-            engine: "ENGINE_ION_DRIVE_I".to_string(),
-            req_modules: vec!["MODULE_MINERAL_PROCESSOR_I".to_string()],
-            req_mounts: vec!["MOUNT_MINING_LASER_II".to_string(), "MOUNT_SURVEYOR_I".to_string()],
-            cargo_capacity: 40,
-        },
-        "SHIP_EXPLORER" => ShipModel {
-            frame: "FRAME_EXPLORER".to_string(),
-            reactor: "REACTOR_FUSION_I".to_string(),
-            engine: "ENGINE_ION_DRIVE_II".to_string(),
-            req_modules: vec!["MODULE_WARP_DRIVE_I".to_string()],
-            req_mounts: vec!["MOUNT_SENSOR_ARRAY_II".to_string(), "MOUNT_GAS_SIPHON_II".to_string()],
-            cargo_capacity: 40,
-        },
-    };
+impl ShipModel {
+    pub fn from_listing(listing: &ShipyardShip) -> Self {
+        Self {
+            ship_type: listing.ship_type.clone(),
+            frame: listing.frame.symbol.clone(),
+            reactor: listing.reactor.symbol.clone(),
+            engine: listing.engine.symbol.clone(),
+            modules: listing.modules.iter().map(|m| m.symbol.clone()).collect(),
+            mounts: listing.mounts.iter().map(|m| m.symbol.clone()).collect(),
+            cargo_capacity: listing
+                .modules
+                .iter()
+                .filter(|m| m.symbol.starts_with("MODULE_CARGO_HOLD"))
+                .filter_map(|m| m.capacity)
+                .sum(),
+            fuel_capacity: Some(listing.frame.fuel_capacity),
+            speed: Some(listing.engine.speed),
+            min_price: Some(listing.purchase_price),
+            max_price: Some(listing.purchase_price),
+        }
+    }
+
+    pub fn matches(&self, ship: &Ship) -> bool {
+        ship.frame.symbol == self.frame
+            && ship.reactor.symbol == self.reactor
+            && ship.engine.symbol == self.engine
+            && ship.cargo.capacity == self.cargo_capacity
+            && self
+                .modules
+                .iter()
+                .all(|module| ship.modules.iter().any(|m| m.symbol == *module))
+            && self
+                .mounts
+                .iter()
+                .all(|mount| ship.mounts.iter().any(|m| m.symbol == *mount))
+    }
 }
 
-impl Ship {
-    pub fn model(&self) -> Result<String, String> {
-        // find the model in SHIP_MODELS with matching frame, reactor, and engine
-        let matching_models = SHIP_MODELS
-            .iter()
-            .filter(|(_, ship_model)| self.frame.symbol == ship_model.frame)
-            .filter(|(_, ship_model)| self.reactor.symbol == ship_model.reactor)
-            .filter(|(_, ship_model)| self.engine.symbol == ship_model.engine)
-            .filter(|(_, ship_model)| self.cargo.capacity == ship_model.cargo_capacity)
-            .filter(|(_, ship_model)| {
-                for module in ship_model.req_modules.iter() {
-                    if !self.modules.iter().any(|m| m.symbol == *module) {
-                        return false;
-                    }
-                }
-                for mount in ship_model.req_mounts.iter() {
-                    if !self.mounts.iter().any(|m| m.symbol == *mount) {
-                        return false;
-                    }
-                }
-                true
-            })
-            .collect::<Vec<(&&str, &ShipModel)>>();
-        if matching_models.len() == 1 {
-            return Ok(matching_models[0].0.to_string());
+impl ShipCooldown {
+    // Restore a cooldown persisted before a restart. The API's cooldown wins if it has one.
+    pub fn restore(&mut self, last_known_expiration: DateTime<Utc>, now: DateTime<Utc>) {
+        if self.expiration.is_some() || last_known_expiration <= now {
+            return;
         }
-        Err(format!(
-            "{} matching models for ship {} with frame: {}, reactor: {}, engine: {}",
-            matching_models.len(),
-            self.symbol,
-            self.frame.symbol,
-            self.reactor.symbol,
-            self.engine.symbol
-        ))
+        let remaining = (last_known_expiration - now).num_seconds();
+        self.expiration = Some(last_known_expiration);
+        self.remaining_seconds = remaining;
+        self.total_seconds = self.total_seconds.max(remaining);
     }
+}
 
+impl Ship {
     pub fn symbol(&self) -> String {
         self.symbol.clone()
     }
@@ -1,4 +1,5 @@
 use crate::models::{SystemSymbol, WaypointSymbol};
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone)]
 pub struct Waypoint {
@@ -16,6 +17,10 @@ pub struct WaypointDetails {
     pub is_shipyard: bool,
     pub is_uncharted: bool,
     pub is_under_construction: bool,
+    pub modifiers: Vec<String>,
+    pub orbits: Option<WaypointSymbol>,
+    // (submitted by, submitted on)
+    pub chart: Option<(String, DateTime<Utc>)>,
 }
 
 #[derive(Debug, Clone)]
@@ -48,4 +53,20 @@ impl Waypoint {
         }
         false
     }
+
+    // false if details haven't been loaded
+    pub fn is_charted_by(&self, agent: &str) -> bool {
+        self.details
+            .as_ref()
+            .and_then(|d| d.chart.as_ref())
+            .is_some_and(|(submitted_by, _)| submitted_by == agent)
+    }
+
+    // false if details haven't been loaded
+    pub fn is_uncharted(&self) -> bool {
+        self.details
+            .as_ref()
+            .map(|d| d.is_uncharted)
+            .unwrap_or(false)
+    }
 }
@@ -0,0 +1,200 @@
+//!
+//! Hourly operations report.
+//!
+//! A digest of the last hour: credits delta, trades completed, the most profitable routes, idle ships,
+//! jump gate construction progress and the alerts sent. Each report is stored in the database, sent
+//! to the event listeners and posted to the alert webhook, for daily check-ins without reading logs.
+//!
+use crate::agent_controller::{AgentController, Event};
+use crate::alerts::ALERTS;
+use crate::db::{ok_or_warn, DbClient};
+use crate::logistics_planner::{Action, TaskActions};
+use crate::status_feed::{gate_progress, top_routes, GateProgress, RouteSummary};
+use crate::universe::access::UniverseReader;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+const TOP_ROUTES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpsReport {
+    pub callsign: String,
+    pub period_start: DateTime<Utc>,
+    pub timestamp: DateTime<Utc>,
+    pub credits: i64,
+    pub credits_delta: i64,
+    pub trades_completed: usize,
+    pub top_routes: Vec<RouteSummary>,
+    pub idle_ships: Vec<String>,
+    pub gate: Option<GateProgress>,
+    pub alerts: Vec<String>,
+}
+
+impl OpsReport {
+    // Plain text for the webhook
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "Operations report for {}, {} to {}",
+                self.callsign,
+                self.period_start.format("%H:%M"),
+                self.timestamp.format("%H:%M UTC")
+            ),
+            format!("Credits: {} ({:+})", self.credits, self.credits_delta),
+            format!("Trades completed: {}", self.trades_completed),
+        ];
+        for route in &self.top_routes {
+            lines.push(format!(
+                "  {} {} -> {}: {}",
+                route.good, route.src, route.dest, route.expected_profit
+            ));
+        }
+        match self.idle_ships.is_empty() {
+            true => lines.push("Idle ships: none".to_string()),
+            false => lines.push(format!("Idle ships: {}", self.idle_ships.join(", "))),
+        }
+        if let Some(gate) = &self.gate {
+            lines.push(format!(
+                "Jump gate {}: {}/{}{}",
+                gate.waypoint,
+                gate.fulfilled,
+                gate.required,
+                if gate.is_complete { " (complete)" } else { "" }
+            ));
+        }
+        lines.push(format!("Alerts: {}", self.alerts.len()));
+        for alert in &self.alerts {
+            lines.push(format!("  {}", alert));
+        }
+        lines.join("\n")
+    }
+}
+
+pub async fn build(
+    agent_controller: &AgentController,
+    universe: &dyn UniverseReader,
+    prev: Option<&OpsReport>,
+) -> OpsReport {
+    let agent = agent_controller.agent();
+    let now = universe.now();
+    let period_start = match prev {
+        Some(prev) => prev.timestamp,
+        None => now - Duration::try_hours(1).unwrap(),
+    };
+    let gate = match universe.known_jumpgate(&agent.headquarters.system()) {
+        Some(jump_gate) => universe
+            .known_construction(&jump_gate)
+            .await
+            .and_then(|construction| construction.data.as_ref().map(gate_progress)),
+        None => None,
+    };
+    let completed = agent_controller
+        .task_manager
+        .completed_tasks_since(period_start);
+    let trades_completed = completed
+        .iter()
+        .filter(|task| {
+            matches!(
+                task.actions,
+                TaskActions::TransportCargo {
+                    src_action: Action::BuyGoods(..),
+                    ..
+                }
+            )
+        })
+        .count();
+    // unassigned ships, and logistics ships without tasks
+    let mut idle_ships = agent_controller
+        .ships()
+        .into_iter()
+        .filter(|(_, _, job_id, _, _)| job_id.is_empty())
+        .map(|(ship_symbol, _, _, _, _)| ship_symbol)
+        .collect::<Vec<_>>();
+    idle_ships.extend(agent_controller.task_manager.idle_logistics_ships());
+    idle_ships.sort();
+    idle_ships.dedup();
+    OpsReport {
+        callsign: agent.symbol.clone(),
+        period_start,
+        timestamp: now,
+        credits: agent.credits,
+        credits_delta: prev.map(|prev| agent.credits - prev.credits).unwrap_or(0),
+        trades_completed,
+        top_routes: top_routes(completed.iter(), TOP_ROUTES),
+        idle_ships,
+        gate,
+        alerts: ALERTS.sent_since(period_start),
+    }
+}
+
+pub async fn run(
+    agent_controller: AgentController,
+    universe: Arc<dyn UniverseReader>,
+    db: DbClient,
+) {
+    let callsign = agent_controller.agent().symbol;
+    let mut prev = ok_or_warn(
+        db.load_latest_ops_report(&callsign).await,
+        "load latest ops report",
+    )
+    .flatten();
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+    // the first tick is immediate, the first report covers the hour after startup
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let report = build(&agent_controller, universe.as_ref(), prev.as_ref()).await;
+        ok_or_warn(
+            db.save_ops_report(&callsign, &report).await,
+            "save ops report",
+        );
+        agent_controller
+            .emit_event(&Event::OpsReport(Arc::new(report.clone())))
+            .await;
+        ALERTS.post(&report.summary());
+        prev = Some(report);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::WaypointSymbol;
+
+    #[test]
+    fn test_ops_report_summary() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let report = OpsReport {
+            callsign: "AGENT".to_string(),
+            period_start: start,
+            timestamp: start + Duration::try_hours(1).unwrap(),
+            credits: 1_250_000,
+            credits_delta: -40_000,
+            trades_completed: 12,
+            top_routes: vec![RouteSummary {
+                good: "IRON".to_string(),
+                src: WaypointSymbol::new("X1-AB12-A1"),
+                dest: WaypointSymbol::new("X1-AB12-B2"),
+                expected_profit: 8000,
+            }],
+            idle_ships: vec![],
+            gate: None,
+            alerts: vec!["[db_pool] Waited 2500ms for a database connection".to_string()],
+        };
+        let summary = report.summary();
+        assert_eq!(
+            summary.lines().collect::<Vec<_>>(),
+            vec![
+                "Operations report for AGENT, 22:13 to 23:13 UTC",
+                "Credits: 1250000 (-40000)",
+                "Trades completed: 12",
+                "  IRON X1-AB12-A1 -> X1-AB12-B2: 8000",
+                "Idle ships: none",
+                "Alerts: 1",
+                "  [db_pool] Waited 2500ms for a database connection",
+            ]
+        );
+    }
+}
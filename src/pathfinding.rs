@@ -1,7 +1,11 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
 use crate::{
     api_client::api_models::WaypointDetailed,
+    config::CONFIG,
     models::{ShipFlightMode, System, WaypointSymbol},
 };
 use std::cmp::max;
@@ -10,12 +14,104 @@ use std::cmp::max;
 const CRUISE_NAV_MODIFIER: f64 = 25.0;
 const BURN_NAV_MODIFIER: f64 = 12.5;
 
+// How much a trip's time is worth, which decides whether it's flown in BURN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    // e.g. market refreshes, CRUISE unless fuel is cheap enough
+    Low,
+    // BURN unless the fuel ledger has shown it doesn't pay on the route
+    Normal,
+    // valuable tasks, BURN unless fuel is expensive enough
+    High,
+}
+
+impl Urgency {
+    // credits per second of ship time
+    pub fn time_value(&self) -> f64 {
+        match self {
+            Urgency::Low => CONFIG.burn_time_value_low,
+            Urgency::Normal => CONFIG.burn_time_value,
+            Urgency::High => CONFIG.burn_time_value_high,
+        }
+    }
+}
+
+// Whether the time BURN saves, valued at `time_value` credits per second, pays for its extra fuel.
+// Per unit of distance it saves BURN_NAV_MODIFIER / speed seconds for 1 extra fuel, whatever the hop.
+pub fn burn_pays(speed: i64, time_value: f64, fuel_price: f64) -> bool {
+    BURN_NAV_MODIFIER / (speed as f64) * time_value >= fuel_price
+}
+
+pub type DurationMatrix = BTreeMap<WaypointSymbol, BTreeMap<WaypointSymbol, i64>>;
+
+// Join the duration matrices of gate-connected systems, given as (jump gate, matrix). Trips between
+// systems fly to the gate, take the quickest sequence of `jumps` (cooldown between directly connected
+// gates), then fly on from the far gate. Systems that can't be reached from each other aren't joined.
+pub fn join_gate_matrices(
+    systems: &[(WaypointSymbol, DurationMatrix)],
+    jumps: &BTreeMap<(WaypointSymbol, WaypointSymbol), i64>,
+) -> DurationMatrix {
+    // quickest jumps between each pair of gates
+    let gates = systems.iter().map(|(gate, _)| gate).collect::<Vec<_>>();
+    let mut gate_durations = BTreeMap::new();
+    for a in &gates {
+        gate_durations.insert(((*a).clone(), (*a).clone()), 0);
+        for b in &gates {
+            if let Some(duration) = jumps.get(&((*a).clone(), (*b).clone())) {
+                gate_durations.insert(((*a).clone(), (*b).clone()), *duration);
+            }
+        }
+    }
+    for k in &gates {
+        for a in &gates {
+            for b in &gates {
+                let (Some(ak), Some(kb)) = (
+                    gate_durations.get(&((*a).clone(), (*k).clone())),
+                    gate_durations.get(&((*k).clone(), (*b).clone())),
+                ) else {
+                    continue;
+                };
+                let via_k = ak + kb;
+                let entry = gate_durations
+                    .entry(((*a).clone(), (*b).clone()))
+                    .or_insert(via_k);
+                *entry = (*entry).min(via_k);
+            }
+        }
+    }
+
+    let mut joined = DurationMatrix::new();
+    for (a_gate, a_matrix) in systems {
+        for (b_gate, b_matrix) in systems {
+            let Some(jump) = gate_durations.get(&(a_gate.clone(), b_gate.clone())) else {
+                continue;
+            };
+            for (src, src_durations) in a_matrix {
+                let entry = joined.entry(src.clone()).or_default();
+                if a_gate == b_gate {
+                    entry.extend(src_durations.iter().map(|(k, v)| (k.clone(), *v)));
+                    continue;
+                }
+                let to_gate = src_durations[a_gate];
+                for (dest, from_gate) in &b_matrix[b_gate] {
+                    entry.insert(dest.clone(), to_gate + jump + from_gate);
+                }
+            }
+        }
+    }
+    joined
+}
+
 #[derive(Debug)]
 pub struct Pathfinding {
     waypoints: Arc<BTreeMap<WaypointSymbol, WaypointDetailed>>,
     closest_market: BTreeMap<WaypointSymbol, Option<(WaypointSymbol, i64)>>,
+    // (src, dest) hops flown in CRUISE even when there's fuel to BURN
+    cruise_only: BTreeSet<(WaypointSymbol, WaypointSymbol)>,
+    allow_burn: bool,
 }
 
+#[derive(Clone)]
 pub struct Route {
     pub hops: Vec<(WaypointSymbol, Edge, bool, bool)>,
     pub min_travel_duration: i64,
@@ -45,9 +141,39 @@ impl Pathfinding {
         Pathfinding {
             waypoints: Arc::new(waypoint_map),
             closest_market,
+            cruise_only: BTreeSet::new(),
+            allow_burn: true,
         }
     }
 
+    pub fn with_cruise_only(
+        mut self,
+        cruise_only: BTreeSet<(WaypointSymbol, WaypointSymbol)>,
+    ) -> Self {
+        self.cruise_only = cruise_only;
+        self
+    }
+
+    pub fn with_allow_burn(mut self, allow_burn: bool) -> Self {
+        self.allow_burn = allow_burn;
+        self
+    }
+
+    fn edge(
+        &self,
+        a: &WaypointDetailed,
+        b: &WaypointDetailed,
+        speed: i64,
+        fuel_max: i64,
+    ) -> Option<Edge> {
+        let allow_burn = self.allow_burn
+            && (self.cruise_only.is_empty()
+                || !self
+                    .cruise_only
+                    .contains(&(a.symbol.clone(), b.symbol.clone())));
+        mode_edge(a, b, speed, fuel_max, allow_burn)
+    }
+
     pub fn estimate_duration_matrix(
         &self,
         speed: i64,
@@ -124,7 +250,7 @@ impl Pathfinding {
                             if x_symbol == y_symbol {
                                 return None;
                             }
-                            if let Some(e) = edge(x, y, speed, fuel_capacity) {
+                            if let Some(e) = self.edge(x, y, speed, fuel_capacity) {
                                 Some((y_symbol.clone(), e.travel_duration))
                             } else {
                                 None
@@ -141,7 +267,7 @@ impl Pathfinding {
                         .iter()
                         .filter(|(_y_symbol, y)| y.is_market())
                         .filter_map(|(y_symbol, y)| {
-                            if let Some(e) = edge(x, y, speed, start_fuel) {
+                            if let Some(e) = self.edge(x, y, speed, start_fuel) {
                                 Some((y_symbol.clone(), e.travel_duration))
                             } else {
                                 None
@@ -151,14 +277,14 @@ impl Pathfinding {
                     edges.extend(edges1);
                 }
                 // add market -> non-market edge ( fuel_cost <= max_fuel - req_escape_fuel )
-                if !dest_is_market && x_symbol != dest_symbol {
-                    if let Some(e) = edge(x, dst, speed, fuel_capacity - req_escape_fuel) {
+                if !dest_is_market && x.is_market() && x_symbol != dest_symbol {
+                    if let Some(e) = self.edge(x, dst, speed, fuel_capacity - req_escape_fuel) {
                         edges.push((dest_symbol.clone(), e.travel_duration));
                     }
                 }
                 // finally add non-market -> non-market edge ( fuel_cost <= start_fuel - req_escape_fuel )
                 if !src_is_market && !dest_is_market && x_symbol == src_symbol {
-                    if let Some(e) = edge(src, dst, speed, start_fuel - req_escape_fuel) {
+                    if let Some(e) = self.edge(src, dst, speed, start_fuel - req_escape_fuel) {
                         edges.push((dest_symbol.clone(), e.travel_duration));
                     }
                 }
@@ -181,7 +307,7 @@ impl Pathfinding {
                     (false, true) => start_fuel,
                     (false, false) => start_fuel - req_escape_fuel,
                 };
-                let e = edge(a, b, speed, fuel_max).unwrap();
+                let e = self.edge(a, b, speed, fuel_max).unwrap();
                 (b_symbol.clone(), e, a.is_market(), b.is_market())
             })
             .collect();
@@ -195,7 +321,7 @@ impl Pathfinding {
 
 impl WaypointDetailed {
     pub fn distance(&self, other: &WaypointDetailed) -> i64 {
-        if self.symbol == other.symbol {
+        if self.orbital_root() == other.orbital_root() {
             return 0;
         }
         let distance2 = (self.x - other.x).pow(2) + (self.y - other.y).pow(2);
@@ -213,6 +339,7 @@ impl System {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Edge {
     pub distance: i64,
     pub travel_duration: i64,
@@ -221,10 +348,20 @@ pub struct Edge {
 }
 
 pub fn edge(a: &WaypointDetailed, b: &WaypointDetailed, speed: i64, fuel_max: i64) -> Option<Edge> {
+    mode_edge(a, b, speed, fuel_max, true)
+}
+
+fn mode_edge(
+    a: &WaypointDetailed,
+    b: &WaypointDetailed,
+    speed: i64,
+    fuel_max: i64,
+    allow_burn: bool,
+) -> Option<Edge> {
     let distance = a.distance(b);
 
     // burn
-    if 2 * distance <= fuel_max {
+    if allow_burn && 2 * distance <= fuel_max {
         let travel_duration =
             (15.0 + BURN_NAV_MODIFIER / (speed as f64) * (distance as f64)).round() as i64;
         return Some(Edge {
@@ -248,3 +385,246 @@ pub fn edge(a: &WaypointDetailed, b: &WaypointDetailed, speed: i64, fuel_max: i6
     }
     None
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::SymbolNameDescr;
+    use crate::test_fixtures::starter_system_waypoints;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct GoldenRoute {
+        src: WaypointSymbol,
+        dest: WaypointSymbol,
+        speed: i64,
+        start_fuel: i64,
+        fuel_capacity: i64,
+        allow_burn: bool,
+        hops: Vec<(WaypointSymbol, ShipFlightMode)>,
+        duration: i64,
+    }
+
+    fn waypoint(symbol: &str, x: i64, y: i64, is_market: bool) -> WaypointDetailed {
+        let symbol = WaypointSymbol::new(symbol);
+        let traits = match is_market {
+            true => vec![SymbolNameDescr {
+                symbol: "MARKETPLACE".to_string(),
+                name: String::new(),
+                description: String::new(),
+            }],
+            false => vec![],
+        };
+        WaypointDetailed {
+            system_symbol: symbol.system(),
+            symbol,
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y,
+            traits,
+            is_under_construction: false,
+            modifiers: vec![],
+            orbits: None,
+            chart: None,
+        }
+    }
+
+    // Properties every route must have: it ends at the destination, no hop needs more fuel than
+    // the ship has (refuelling to capacity at markets), and enough fuel is left to reach a market
+    fn check_route(
+        waypoints: &[WaypointDetailed],
+        route: &Route,
+        src: &WaypointSymbol,
+        dest: &WaypointSymbol,
+        start_fuel: i64,
+        fuel_capacity: i64,
+    ) {
+        let get = |symbol: &WaypointSymbol| waypoints.iter().find(|w| w.symbol == *symbol).unwrap();
+        match route.hops.last() {
+            Some((last, _, _, _)) => assert_eq!(last, dest),
+            None => assert_eq!(src, dest),
+        }
+        let mut at = get(src);
+        let mut fuel = start_fuel;
+        let mut duration = 0;
+        for (next, edge, a_market, b_market) in &route.hops {
+            let next = get(next);
+            assert_ne!(at.symbol, next.symbol, "hop to the same waypoint");
+            assert_eq!(*a_market, at.is_market());
+            assert_eq!(*b_market, next.is_market());
+            assert_eq!(edge.distance, at.distance(next));
+            assert!(edge.fuel_cost <= fuel_capacity, "hop exceeds fuel capacity");
+            if at.is_market() {
+                fuel = fuel_capacity;
+            }
+            fuel -= edge.fuel_cost;
+            assert!(fuel >= 0, "ran out of fuel before {}", next.symbol);
+            duration += edge.travel_duration;
+            at = next;
+        }
+        // a ship already at its destination has nothing to check
+        if !route.hops.is_empty() && !get(dest).is_market() {
+            assert!(fuel >= route.req_terminal_fuel, "stranded at {}", dest);
+        }
+        assert_eq!(duration, route.min_travel_duration);
+    }
+
+    #[test]
+    fn test_golden_routes() {
+        let waypoints = starter_system_waypoints();
+        let cases: Vec<GoldenRoute> =
+            serde_json::from_str(include_str!("../fixtures/golden_routes.json")).unwrap();
+        for case in cases {
+            let pathfinding = Pathfinding::new(waypoints.clone()).with_allow_burn(case.allow_burn);
+            let route = pathfinding.get_route(
+                &case.src,
+                &case.dest,
+                case.speed,
+                case.start_fuel,
+                case.fuel_capacity,
+            );
+            check_route(
+                &waypoints,
+                &route,
+                &case.src,
+                &case.dest,
+                case.start_fuel,
+                case.fuel_capacity,
+            );
+            let hops = route
+                .hops
+                .iter()
+                .map(|(symbol, edge, _, _)| (symbol.clone(), edge.flight_mode.clone()))
+                .collect::<Vec<_>>();
+            assert_eq!(hops, case.hops, "{} -> {}", case.src, case.dest);
+            assert_eq!(route.min_travel_duration, case.duration);
+        }
+    }
+
+    // Whether a route exists, flying CRUISE between markets and keeping the escape fuel at the end
+    fn reachable(
+        waypoints: &[WaypointDetailed],
+        src: &WaypointDetailed,
+        dest: &WaypointDetailed,
+        start_fuel: i64,
+        fuel_capacity: i64,
+    ) -> bool {
+        if src.symbol == dest.symbol {
+            return true;
+        }
+        let escape = match dest.is_market() {
+            true => 0,
+            false => waypoints
+                .iter()
+                .filter(|w| w.is_market())
+                .map(|w| dest.distance(w))
+                .min()
+                .unwrap(),
+        };
+        let fuel_from = |w: &WaypointDetailed| match w.is_market() {
+            true => fuel_capacity,
+            false => start_fuel,
+        };
+        let mut visited = BTreeSet::from([src.symbol.clone()]);
+        let mut queue = vec![src];
+        while let Some(at) = queue.pop() {
+            if !dest.is_market() && at.distance(dest) <= fuel_from(at) - escape {
+                return true;
+            }
+            for next in waypoints.iter().filter(|w| w.is_market()) {
+                if at.distance(next) <= fuel_from(at) && visited.insert(next.symbol.clone()) {
+                    if next.symbol == dest.symbol {
+                        return true;
+                    }
+                    queue.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_route_fuzz() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut routes = 0;
+        for _ in 0..300 {
+            let n = rng.gen_range(2..12);
+            let mut waypoints = (0..n)
+                .map(|i| {
+                    waypoint(
+                        &format!("X1-FUZZ-W{}", i),
+                        rng.gen_range(-300..=300),
+                        rng.gen_range(-300..=300),
+                        rng.gen_bool(0.6),
+                    )
+                })
+                .collect::<Vec<_>>();
+            waypoints[0].traits = waypoint("X1-FUZZ-W0", 0, 0, true).traits;
+            let src = waypoints[rng.gen_range(0..n)].clone();
+            let dest = waypoints[rng.gen_range(0..n)].clone();
+            let speed = rng.gen_range(1..=50);
+            let fuel_capacity = rng.gen_range(50..=800);
+            let start_fuel = rng.gen_range(0..=fuel_capacity);
+            if !reachable(&waypoints, &src, &dest, start_fuel, fuel_capacity) {
+                continue;
+            }
+            let pathfinding =
+                Pathfinding::new(waypoints.clone()).with_allow_burn(rng.gen_bool(0.5));
+            let route =
+                pathfinding.get_route(&src.symbol, &dest.symbol, speed, start_fuel, fuel_capacity);
+            check_route(
+                &waypoints,
+                &route,
+                &src.symbol,
+                &dest.symbol,
+                start_fuel,
+                fuel_capacity,
+            );
+            routes += 1;
+        }
+        // the generated layouts aren't mostly unreachable
+        assert!(routes > 100, "only {} reachable routes", routes);
+    }
+
+    #[test]
+    fn test_join_gate_matrices() {
+        let wp = WaypointSymbol::new;
+        let matrix = |durations: &[(&str, &str, i64)]| {
+            let mut matrix = DurationMatrix::new();
+            for (a, b, d) in durations {
+                matrix.entry(wp(a)).or_default().insert(wp(b), *d);
+            }
+            matrix
+        };
+        let system_a = matrix(&[
+            ("X1-A-GATE", "X1-A-GATE", 0),
+            ("X1-A-GATE", "X1-A-M1", 50),
+            ("X1-A-M1", "X1-A-GATE", 50),
+            ("X1-A-M1", "X1-A-M1", 0),
+        ]);
+        let system_b = matrix(&[
+            ("X1-B-GATE", "X1-B-GATE", 0),
+            ("X1-B-GATE", "X1-B-M2", 30),
+            ("X1-B-M2", "X1-B-GATE", 30),
+            ("X1-B-M2", "X1-B-M2", 0),
+        ]);
+        let system_c = matrix(&[("X1-C-GATE", "X1-C-GATE", 0)]);
+        let systems = vec![
+            (wp("X1-A-GATE"), system_a),
+            (wp("X1-B-GATE"), system_b),
+            (wp("X1-C-GATE"), system_c),
+        ];
+        // C is not connected
+        let jumps = BTreeMap::from([
+            ((wp("X1-A-GATE"), wp("X1-B-GATE")), 100),
+            ((wp("X1-B-GATE"), wp("X1-A-GATE")), 100),
+        ]);
+        let joined = join_gate_matrices(&systems, &jumps);
+        assert_eq!(joined[&wp("X1-A-M1")][&wp("X1-A-M1")], 0);
+        assert_eq!(joined[&wp("X1-A-M1")][&wp("X1-B-M2")], 50 + 100 + 30);
+        assert_eq!(joined[&wp("X1-B-M2")][&wp("X1-A-GATE")], 30 + 100);
+        assert!(!joined[&wp("X1-A-M1")].contains_key(&wp("X1-C-GATE")));
+        assert_eq!(joined[&wp("X1-C-GATE")].len(), 1);
+    }
+}
@@ -0,0 +1,115 @@
+//!
+//! Sizing the purchases of a trade.
+//!
+//! Buying a good in trade volume sized chunks raises its price and lowers its supply, and the units
+//! are then sold in chunks that each fetch less than the last. Before each chunk the next purchase is
+//! sized against the current purchase price and the predicted sell price of the units it adds, and
+//! buying stops once the marginal units would no longer turn a profit.
+//!
+//! Price impact per chunk traded is assumed from the supply level: markets short on a good move the
+//! most.
+//!
+use crate::models::{MarketSupply, MarketTradeGood};
+use std::cmp::min;
+
+// A market's listing of the good, from one side of the trade
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub price: i64,
+    pub trade_volume: i64,
+    pub supply: MarketSupply,
+}
+
+impl Quote {
+    pub fn purchase(trade: &MarketTradeGood) -> Self {
+        Self {
+            price: trade.purchase_price,
+            trade_volume: trade.trade_volume,
+            supply: trade.supply.clone(),
+        }
+    }
+
+    pub fn sell(trade: &MarketTradeGood) -> Self {
+        Self {
+            price: trade.sell_price,
+            trade_volume: trade.trade_volume,
+            supply: trade.supply.clone(),
+        }
+    }
+}
+
+// Fraction the price moves for each trade volume of units traded
+pub fn price_impact(supply: &MarketSupply) -> f64 {
+    match supply {
+        MarketSupply::Scarce => 0.12,
+        MarketSupply::Limited => 0.08,
+        MarketSupply::Moderate => 0.05,
+        MarketSupply::High => 0.03,
+        MarketSupply::Abundant => 0.02,
+    }
+}
+
+// Predicted sell price of a unit after `units_sold` have been sold before it
+pub fn predicted_sell_price(sell: &Quote, units_sold: i64) -> f64 {
+    let chunks = units_sold / sell.trade_volume.max(1);
+    sell.price as f64 * (1.0 - price_impact(&sell.supply)).powi(chunks as i32)
+}
+
+// Units of the next purchase towards `remaining`, 0 to stop buying. The `held` units are sold first.
+// Without a sell quote, e.g. for deliveries, buys a full chunk
+pub fn next_purchase(
+    buy: &Quote,
+    sell: Option<&Quote>,
+    held: i64,
+    remaining: i64,
+    min_margin: i64,
+) -> i64 {
+    let chunk = min(buy.trade_volume, remaining).max(0);
+    let Some(sell) = sell else {
+        return chunk;
+    };
+    let sell_volume = sell.trade_volume.max(1);
+    let mut units = 0;
+    while units < chunk {
+        let margin = predicted_sell_price(sell, held + units) - buy.price as f64;
+        if margin <= min_margin as f64 {
+            break;
+        }
+        // units up to the end of the sell chunk fetch the same price
+        let chunk_end = ((held + units) / sell_volume + 1) * sell_volume;
+        units = min(chunk, chunk_end - held);
+    }
+    units
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_purchase() {
+        let quote = |price, trade_volume, supply| Quote {
+            price,
+            trade_volume,
+            supply,
+        };
+        let buy = quote(1000, 20, MarketSupply::Moderate);
+        let sell = quote(1150, 20, MarketSupply::Moderate);
+        // sells at 1150, 1092, 1038, 986
+        assert_eq!(next_purchase(&buy, None, 0, 80, 0), 20);
+        assert_eq!(next_purchase(&buy, Some(&sell), 0, 80, 0), 20);
+        assert_eq!(next_purchase(&buy, Some(&sell), 40, 40, 0), 20);
+        assert_eq!(next_purchase(&buy, Some(&sell), 60, 20, 0), 0);
+        // a transaction cost margin stops earlier
+        assert_eq!(next_purchase(&buy, Some(&sell), 40, 40, 50), 0);
+        // partial sell chunks: only the units that still sell at a profit
+        let sell = quote(1150, 10, MarketSupply::Moderate);
+        assert_eq!(next_purchase(&buy, Some(&sell), 20, 20, 0), 10);
+        // scarce imports drop faster
+        let sell = quote(1150, 20, MarketSupply::Scarce);
+        assert_eq!(next_purchase(&buy, Some(&sell), 20, 60, 0), 20);
+        assert_eq!(next_purchase(&buy, Some(&sell), 40, 40, 0), 0);
+        // never more than requested
+        assert_eq!(next_purchase(&buy, Some(&sell), 0, 5, 0), 5);
+    }
+}
@@ -0,0 +1,81 @@
+//!
+//! Detecting a server reset mid-run, and cutting over to the new reset.
+//!
+//! After a reset the agent token is rejected and the status reports a new reset date. The status is
+//! checked periodically, and straight away when a request is rejected for its token. Once the server
+//! is back up under a new reset, the ships are stopped, the old reset's data is archived (if backups
+//! are configured) and the process restarts itself. Startup then applies migrations, registers the
+//! agent under the new reset and starts from scratch, so an unattended agent survives reset night.
+//!
+use crate::api_client::ApiClient;
+use crate::config::CONFIG;
+use crate::db::DbClient;
+use log::*;
+use std::os::unix::process::CommandExt as _;
+use std::path::Path;
+use std::time::Duration;
+
+const RESET_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// Reset dates are YYYY-MM-DD, so a newer reset sorts after the current one. An older date, e.g. from
+// a stale response, is never a reset
+pub fn is_new_reset(current: &str, observed: &str) -> bool {
+    observed > current
+}
+
+// Resolves with the new reset date once the server has reset
+pub async fn watch(api_client: &ApiClient, reset_date: &str) -> String {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(RESET_CHECK_INTERVAL) => {}
+            _ = api_client.token_rejected() => {
+                debug!("Agent token rejected, checking for a server reset");
+            }
+        }
+        let Some(observed) = api_client.server_reset_date().await else {
+            continue;
+        };
+        if is_new_reset(reset_date, &observed) {
+            warn!("Server reset detected: {} -> {}", reset_date, observed);
+            return observed;
+        }
+    }
+}
+
+// Archive the old reset, then restart the process to start the new one. Ships must be stopped first
+pub async fn cutover(db: &DbClient, new_reset: &str) -> ! {
+    match &CONFIG.backup_dir {
+        Some(backup_dir) => {
+            let backup = db
+                .backup_reset(Path::new(backup_dir), CONFIG.backup_retention)
+                .await;
+            match backup {
+                Ok(path) => info!("Archived reset {} to {}", db.reset_date(), path.display()),
+                // the new reset shouldn't wait on the old one's archive
+                Err(e) => warn!("Failed to archive reset {}: {}", db.reset_date(), e),
+            }
+        }
+        None => warn!(
+            "BACKUP_DIR not set, not archiving reset {}",
+            db.reset_date()
+        ),
+    }
+    info!("Restarting for reset {}", new_reset);
+    let exe = std::env::current_exe().expect("Failed to locate executable");
+    let err = std::process::Command::new(exe)
+        .args(std::env::args().skip(1))
+        .exec();
+    panic!("Failed to restart: {}", err);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_new_reset() {
+        assert!(is_new_reset("2024-03-10", "2024-03-24"));
+        assert!(!is_new_reset("2024-03-24", "2024-03-24"));
+        assert!(!is_new_reset("2024-03-24", "2024-03-10"));
+    }
+}
@@ -1,5 +1,16 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    construction_deliveries (reset_id, ship_symbol, timestamp) {
+        reset_id -> Text,
+        timestamp -> Timestamptz,
+        waypoint_symbol -> Text,
+        ship_symbol -> Text,
+        trade_symbol -> Text,
+        units -> Int4,
+    }
+}
+
 diesel::table! {
     general_lookup (reset_id, key) {
         reset_id -> Text,
@@ -9,6 +20,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    job_assignments (reset_id, callsign, ship_symbol, assigned_at) {
+        reset_id -> Text,
+        callsign -> Text,
+        ship_symbol -> Text,
+        job_id -> Text,
+        assigned_at -> Timestamptz,
+        unassigned_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     jumpgate_connections (reset_id, waypoint_symbol) {
         reset_id -> Text,
@@ -37,7 +59,7 @@ diesel::table! {
 }
 
 diesel::table! {
-    market_transactions (market_symbol, timestamp) {
+    market_transactions (market_symbol, timestamp, ship_symbol) {
         timestamp -> Timestamptz,
         market_symbol -> Text,
         symbol -> Text,
@@ -50,6 +72,62 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    net_worth_history (reset_id, callsign, timestamp) {
+        reset_id -> Text,
+        callsign -> Text,
+        timestamp -> Timestamptz,
+        credits -> Int8,
+        cargo_value -> Int8,
+        ship_value -> Int8,
+        total -> Int8,
+    }
+}
+
+diesel::table! {
+    ship_models (reset_id, ship_type) {
+        reset_id -> Text,
+        ship_type -> Text,
+        frame -> Text,
+        reactor -> Text,
+        engine -> Text,
+        modules -> Array<Text>,
+        mounts -> Array<Text>,
+        cargo_capacity -> Int4,
+        fuel_capacity -> Int4,
+        speed -> Int4,
+        min_price -> Int4,
+        max_price -> Int4,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    shipyard_listings (shipyard_symbol, ship_type, timestamp) {
+        timestamp -> Timestamptz,
+        shipyard_symbol -> Text,
+        ship_type -> Text,
+        supply -> Text,
+        activity -> Nullable<Text>,
+        purchase_price -> Int4,
+        frame -> Text,
+        reactor -> Text,
+        engine -> Text,
+        modules -> Array<Text>,
+        mounts -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    survey_consumption (reset_id, owner, consumer) {
+        reset_id -> Text,
+        owner -> Text,
+        consumer -> Text,
+        extractions -> Int8,
+        units -> Int8,
+    }
+}
+
 diesel::table! {
     surveys (reset_id, uuid) {
         reset_id -> Text,
@@ -58,6 +136,7 @@ diesel::table! {
         asteroid_symbol -> Text,
         inserted_at -> Timestamptz,
         expires_at -> Timestamptz,
+        owner -> Text,
     }
 }
 
@@ -86,6 +165,10 @@ diesel::table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         is_under_construction -> Bool,
+        modifiers -> Array<Text>,
+        orbits -> Nullable<Text>,
+        chart_submitted_by -> Nullable<Text>,
+        chart_submitted_on -> Nullable<Timestamptz>,
     }
 }
 
@@ -104,10 +187,16 @@ diesel::table! {
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    construction_deliveries,
     general_lookup,
+    job_assignments,
     jumpgate_connections,
     market_trades,
     market_transactions,
+    net_worth_history,
+    ship_models,
+    shipyard_listings,
+    survey_consumption,
     surveys,
     systems,
     waypoint_details,
@@ -1,3 +1,4 @@
+use crate::agent_controller::Strategy;
 use crate::{api_client::api_models::WaypointDetailed, models::*};
 use std::collections::BTreeMap;
 
@@ -30,6 +31,7 @@ pub fn ship_config_starter_system(
     _shipyards: &Vec<ShipyardRemoteView>,
     use_nonstatic_probes: bool,
     incl_outer_and_siphons: bool,
+    strategy: Strategy,
 ) -> Vec<ShipConfig> {
     let mut ships = vec![];
 
@@ -52,6 +54,7 @@ pub fn ship_config_starter_system(
                 allow_shipbuying: true,
                 allow_market_refresh: true,
                 allow_construction: false,
+                allow_gate_trades: false,
                 min_profit: 1,
             }),
         },
@@ -100,10 +103,40 @@ pub fn ship_config_starter_system(
         ));
     }
 
+    // Probes for the remaining markets, ahead of the mining operation if the strategy wants them early
+    let outer_probes_order = match strategy.early_outer_probes() {
+        true => 2.5,
+        false => 5.0,
+    };
+    if incl_outer_and_siphons || strategy.early_outer_probes() {
+        // should we convert the old ones to static probes everywhere??
+        for w in waypoints
+            .iter()
+            .filter(|w| all_market_waypoints.contains(&w.symbol))
+            .filter(|w| !inner_market_waypoints.contains(&w.symbol))
+        {
+            let config = ProbeScriptConfig {
+                waypoints: vec![w.symbol.clone()],
+                refresh_market: true,
+            };
+            ships.push((
+                (outer_probes_order, 0.0),
+                ShipConfig {
+                    id: format!("probe/{}", w.symbol),
+                    ship_model: "SHIP_PROBE".to_string(),
+                    behaviour: ShipBehaviour::Probe(config),
+                    purchase_criteria: PurchaseCriteria::default(),
+                },
+            ));
+        }
+    }
+
     // Mining operation
     const NUM_SURVEYORS: i64 = 1;
     const NUM_MINING_DRONES: i64 = 8;
     const NUM_MINING_SHUTTLES: i64 = 2;
+    let num_mining_drones = NUM_MINING_DRONES * strategy.mining_scale();
+    let num_mining_shuttles = NUM_MINING_SHUTTLES * strategy.mining_scale();
     for i in 0..NUM_SURVEYORS {
         ships.push((
             (3.0, (i as f64) / (NUM_SURVEYORS as f64)),
@@ -115,9 +148,9 @@ pub fn ship_config_starter_system(
             },
         ));
     }
-    for i in 0..NUM_MINING_DRONES {
+    for i in 0..num_mining_drones {
         ships.push((
-            (3.0, (i as f64) / (NUM_MINING_DRONES as f64)),
+            (3.0, (i as f64) / (num_mining_drones as f64)),
             ShipConfig {
                 id: format!("mining_drone/{}", i),
                 ship_model: "SHIP_MINING_DRONE".to_string(),
@@ -126,9 +159,9 @@ pub fn ship_config_starter_system(
             },
         ));
     }
-    for i in 0..NUM_MINING_SHUTTLES {
+    for i in 0..num_mining_shuttles {
         ships.push((
-            (3.0, (i as f64) / (NUM_MINING_SHUTTLES as f64)),
+            (3.0, (i as f64) / (num_mining_shuttles as f64)),
             ShipConfig {
                 id: format!("mining_shuttle/{}", i),
                 ship_model: "SHIP_LIGHT_HAULER".to_string(),
@@ -150,27 +183,6 @@ pub fn ship_config_starter_system(
     ));
 
     if incl_outer_and_siphons {
-        // Add probes for the remaining markets - should we convert the old ones to static probes everywhere??
-        for w in waypoints
-            .iter()
-            .filter(|w| all_market_waypoints.contains(&w.symbol))
-            .filter(|w| !inner_market_waypoints.contains(&w.symbol))
-        {
-            let config = ProbeScriptConfig {
-                waypoints: vec![w.symbol.clone()],
-                refresh_market: true,
-            };
-            ships.push((
-                (5.0, 0.0),
-                ShipConfig {
-                    id: format!("probe/{}", w.symbol),
-                    ship_model: "SHIP_PROBE".to_string(),
-                    behaviour: ShipBehaviour::Probe(config),
-                    purchase_criteria: PurchaseCriteria::default(),
-                },
-            ));
-        }
-
         // Add 2 logistics haulers - not using planner
         const NUM_LHAULERS: i64 = 2;
         for i in 0..NUM_LHAULERS {
@@ -186,6 +198,7 @@ pub fn ship_config_starter_system(
                         allow_shipbuying: false,
                         allow_market_refresh: false,
                         allow_construction: false,
+                        allow_gate_trades: false,
                         min_profit: 1,
                     }),
                 },
@@ -301,6 +314,7 @@ pub fn ship_config_capital_system(
                 allow_shipbuying: false,
                 allow_market_refresh: false,
                 allow_construction: false,
+                allow_gate_trades: false,
                 min_profit: 1,
             }),
         },
@@ -322,6 +336,7 @@ pub fn ship_config_capital_system(
                     allow_shipbuying: false,
                     allow_market_refresh: false,
                     allow_construction: false,
+                    allow_gate_trades: true,
                     min_profit: 1,
                 }),
             },
@@ -537,6 +552,7 @@ pub fn ship_config_no_gate(
                 allow_shipbuying: true,
                 allow_market_refresh: true,
                 allow_construction: false,
+                allow_gate_trades: false,
                 min_profit: 1,
             }),
         },
@@ -622,6 +638,7 @@ pub fn ship_config_no_gate(
                         allow_shipbuying: false,
                         allow_market_refresh: false,
                         allow_construction: false,
+                        allow_gate_trades: false,
                         min_profit: 1,
                     }),
                 },
@@ -632,3 +649,36 @@ pub fn ship_config_no_gate(
     ships.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
     ships.into_iter().map(|(_, c)| c).collect()
 }
+
+// Copies of a system's unrestricted, unplanned hauler for the autoscaler, none if the system has none
+pub fn autoscaled_haulers(
+    ship_config: &[ShipConfig],
+    system_symbol: &SystemSymbol,
+    start_system: &SystemSymbol,
+    count: i64,
+) -> Vec<ShipConfig> {
+    let template = ship_config.iter().find(|job| {
+        let purchase_system = job
+            .purchase_criteria
+            .system_symbol
+            .as_ref()
+            .unwrap_or(start_system);
+        match &job.behaviour {
+            ShipBehaviour::Logistics(config) => {
+                purchase_system == system_symbol
+                    && !config.use_planner
+                    && config.waypoint_allowlist.is_none()
+            }
+            _ => false,
+        }
+    });
+    let Some(template) = template else {
+        return vec![];
+    };
+    (1..=count)
+        .map(|i| ShipConfig {
+            id: format!("logistics_autoscale/{}/{}", system_symbol, i),
+            ..template.clone()
+        })
+        .collect()
+}
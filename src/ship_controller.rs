@@ -1,34 +1,58 @@
-use crate::agent_controller::Event;
+use crate::api_client::api_models::{Chart, ScannedWaypoint};
+use crate::cargo_valuer::{CargoValuation, CargoValuer};
 use crate::models::{ShipCargoItem, ShipCooldown, Survey};
+use crate::purchase_sizing::{next_purchase, Quote};
 use crate::ship_controller::ShipNavStatus::*;
 use crate::{
-    agent_controller::AgentController, api_client::ApiClient, logistics_planner::Action, models::*,
-    universe::Universe,
+    agent_controller::AgentController,
+    alerts::ALERTS,
+    api_client::{ApiClient, ApiError},
+    logistics_planner::Action,
+    models::*,
+    pathfinding::{Route, Urgency},
+    telemetry,
+    universe::UniverseHandle,
 };
 use log::*;
-use reqwest::{Method, StatusCode};
+use opentelemetry::KeyValue;
 use serde_json::{json, Value};
 use std::cmp::min;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
+
+// Error codes for purchases the agent can't afford
+const INSUFFICIENT_CREDITS_CODES: &[i64] = &[4216, 4600];
+const PURCHASE_RETRIES: u32 = 3;
+const PURCHASE_BACKOFF_SECONDS: u64 = 30;
+// Error code for an extraction or siphon with no cargo space left
+const CARGO_FULL_CODE: i64 = 4228;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractResult {
+    Extracted,
+    // exhausted or expired, nothing extracted
+    SurveyInvalid,
+    // the server's cargo was fuller than ours, the cargo has been refreshed
+    CargoFull,
+}
 
 #[derive(Clone)]
 pub struct ShipController {
     pub ship_symbol: String,
-    ship: Arc<Mutex<Ship>>,
+    ship: Arc<RwLock<Ship>>,
 
     api_client: ApiClient,
-    pub universe: Arc<Universe>,
+    pub universe: UniverseHandle,
     pub agent_controller: AgentController,
 }
 
 impl ShipController {
     pub fn new(
         api_client: &ApiClient,
-        universe: &Arc<Universe>,
-        ship: Arc<Mutex<Ship>>,
+        universe: &UniverseHandle,
+        ship: Arc<RwLock<Ship>>,
         agent_controller: &AgentController,
     ) -> ShipController {
-        let symbol = ship.lock().unwrap().symbol.clone();
+        let symbol = ship.read().unwrap().symbol.clone();
         ShipController {
             api_client: api_client.clone(),
             universe: universe.clone(),
@@ -38,98 +62,115 @@ impl ShipController {
         }
     }
     pub fn ship(&self) -> Ship {
-        self.ship.lock().unwrap().clone()
+        self.ship.read().unwrap().clone()
     }
     pub fn symbol(&self) -> String {
         self.ship_symbol.clone()
     }
     pub fn flight_mode(&self) -> ShipFlightMode {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.nav.flight_mode.clone()
     }
     pub fn nav_status(&self) -> ShipNavStatus {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.nav.status.clone()
     }
     pub fn engine_speed(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.engine.speed
     }
     pub fn fuel_capacity(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.fuel.capacity
     }
     pub fn current_fuel(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.fuel.current
     }
     pub fn cargo_capacity(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo.capacity
     }
     pub fn cargo_units(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo.units
     }
     pub fn waypoint(&self) -> WaypointSymbol {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.nav.waypoint_symbol.clone()
     }
     pub fn system(&self) -> SystemSymbol {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.nav.system_symbol.clone()
     }
     pub fn cargo_empty(&self) -> bool {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo.units == 0
     }
     pub async fn emit_ship(&self) {
-        let ship = self.ship();
-        self.agent_controller
-            .emit_event(&Event::ShipUpdate(ship))
-            .await;
+        self.agent_controller.mark_ship_dirty(&self.ship_symbol);
     }
     pub async fn set_orbit_status(&self) {
         {
-            let mut ship = self.ship.lock().unwrap();
+            let mut ship = self.ship.write().unwrap();
             ship.nav.status = InOrbit;
         }
         self.emit_ship().await;
     }
     pub async fn update_nav(&self, nav: ShipNav) {
-        {
-            let mut ship = self.ship.lock().unwrap();
+        let entered_system = {
+            let mut ship = self.ship.write().unwrap();
+            if ship.nav == nav {
+                return;
+            }
+            let entered_system = ship.nav.system_symbol != nav.system_symbol;
             ship.nav = nav;
+            entered_system.then(|| ship.nav.system_symbol.clone())
+        };
+        if let Some(system_symbol) = entered_system {
+            self.agent_controller.ship_entered_system(&system_symbol);
         }
         self.emit_ship().await;
     }
     pub async fn update_fuel(&self, fuel: ShipFuel) {
         {
-            let mut ship = self.ship.lock().unwrap();
+            let mut ship = self.ship.write().unwrap();
+            if ship.fuel == fuel {
+                return;
+            }
             ship.fuel = fuel;
         }
         self.emit_ship().await;
     }
     pub async fn update_cargo(&self, cargo: ShipCargo) {
         {
-            let mut ship = self.ship.lock().unwrap();
+            let mut ship = self.ship.write().unwrap();
+            if ship.cargo == cargo {
+                return;
+            }
             ship.cargo = cargo;
         }
         self.emit_ship().await;
     }
     pub async fn update_cooldown(&self, cooldown: ShipCooldown) {
         {
-            let mut ship = self.ship.lock().unwrap();
-            ship.cooldown = cooldown;
+            let mut ship = self.ship.write().unwrap();
+            if ship.cooldown == cooldown {
+                return;
+            }
+            ship.cooldown = cooldown.clone();
         }
+        self.agent_controller
+            .record_cooldown(&self.ship_symbol, cooldown.expiration)
+            .await;
         self.emit_ship().await;
     }
     pub fn cargo_first_item(&self) -> Option<ShipCargoItem> {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo.inventory.first().cloned()
     }
     pub fn cargo_good_count(&self, good: &str) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo
             .inventory
             .iter()
@@ -138,11 +179,23 @@ impl ShipController {
             .unwrap_or(0)
     }
     pub fn cargo_space_available(&self) -> i64 {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo.capacity - ship.cargo.units
     }
+    pub fn transfer_requested(&self) -> bool {
+        self.agent_controller.transfer_requested(&self.ship_symbol)
+    }
+    pub fn api_degraded(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.api_client.subscribe_degraded()
+    }
+    pub async fn cargo_value(&self) -> CargoValuation {
+        let cargo = self.ship.read().unwrap().cargo.clone();
+        CargoValuer::new(self.universe.reader())
+            .value_cargo(&self.system(), &cargo)
+            .await
+    }
     pub fn cargo_map(&self) -> std::collections::BTreeMap<String, i64> {
-        let ship = self.ship.lock().unwrap();
+        let ship = self.ship.read().unwrap();
         ship.cargo
             .inventory
             .iter()
@@ -154,98 +207,164 @@ impl ShipController {
         debug!("[{}] {}", self.ship_symbol, msg);
     }
 
-    pub async fn orbit(&self) {
+    pub async fn orbit(&self) -> Result<(), ApiError> {
         if self.nav_status() == InOrbit {
-            return;
+            return Ok(());
         }
         let uri = format!("/my/ships/{}/orbit", self.ship_symbol);
-        let mut response: Value = self.api_client.post(&uri, &json!({})).await;
+        let mut response: Value = self.api_client.try_post(&uri, &json!({})).await?;
         let nav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
         self.update_nav(nav).await;
+        Ok(())
     }
 
-    pub async fn dock(&self) {
+    pub async fn dock(&self) -> Result<(), ApiError> {
         if self.nav_status() == Docked {
-            return;
+            return Ok(());
         }
         let uri = format!("/my/ships/{}/dock", self.ship_symbol);
-        let mut response: Value = self.api_client.post(&uri, &json!({})).await;
+        let mut response: Value = self.api_client.try_post(&uri, &json!({})).await?;
         let nav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
         self.update_nav(nav).await;
+        Ok(())
     }
 
-    pub async fn set_flight_mode(&self, mode: ShipFlightMode) {
+    pub async fn set_flight_mode(&self, mode: ShipFlightMode) -> Result<(), ApiError> {
         if self.flight_mode() == mode {
-            return;
+            return Ok(());
         }
         self.debug(&format!("Setting flight mode to {:?}", mode));
         let uri = format!("/my/ships/{}/nav", self.ship_symbol);
         let mut response: Value = self
             .api_client
-            .patch(&uri, &json!({ "flightMode": mode }))
-            .await;
+            .try_patch(&uri, &json!({ "flightMode": mode }))
+            .await?;
         let nav = serde_json::from_value(response["data"].take()).unwrap();
         self.update_nav(nav).await;
+        Ok(())
     }
 
     pub fn is_in_transit(&self) -> bool {
-        let arrival_time = self.ship.lock().unwrap().nav.route.arrival;
-        let now = chrono::Utc::now();
+        let arrival_time = self.ship.read().unwrap().nav.route.arrival;
+        let now = self.universe.now();
         arrival_time >= now
     }
 
     pub fn set_nav_status(&self, status: ShipNavStatus) {
-        let mut ship = self.ship.lock().unwrap();
+        let mut ship = self.ship.write().unwrap();
         ship.nav.status = status;
     }
 
     pub async fn wait_for_transit(&self) {
-        let arrival_time = { self.ship.lock().unwrap().nav.route.arrival };
-        let now = chrono::Utc::now();
+        let arrival_time = { self.ship.read().unwrap().nav.route.arrival };
+        let now = self.universe.now();
         let wait_time = arrival_time - now + chrono::Duration::try_seconds(1).unwrap();
         if wait_time > chrono::Duration::try_seconds(0).unwrap() {
             self.debug(&format!(
                 "Waiting for transit: {} seconds",
                 wait_time.num_seconds()
             ));
-            tokio::time::sleep(wait_time.to_std().unwrap()).await;
+            let wait = self
+                .agent_controller
+                .arrival_scheduler
+                .wait_until(now + wait_time, now);
+            telemetry::in_span(
+                "wait_transit",
+                vec![KeyValue::new("ship", self.symbol())],
+                wait,
+            )
+            .await;
         }
     }
     pub async fn wait_for_cooldown(&self) {
-        let cooldown = { self.ship.lock().unwrap().cooldown.clone() };
+        let cooldown = { self.ship.read().unwrap().cooldown.clone() };
         if let Some(expiration) = cooldown.expiration {
-            let now = chrono::Utc::now();
+            let now = self.universe.now();
             let wait_time = expiration - now + chrono::Duration::try_seconds(1).unwrap();
             if wait_time > chrono::Duration::try_seconds(0).unwrap() {
                 self.debug(&format!(
                     "Waiting for cooldown: {} seconds",
                     wait_time.num_seconds()
                 ));
-                tokio::time::sleep(wait_time.to_std().unwrap()).await;
+                let wait = self
+                    .agent_controller
+                    .arrival_scheduler
+                    .wait_until(now + wait_time, now);
+                telemetry::in_span(
+                    "wait_cooldown",
+                    vec![KeyValue::new("ship", self.symbol())],
+                    wait,
+                )
+                .await;
             }
         }
     }
 
-    pub async fn buy_goods(&self, good: &str, units: i64, adjust_reserved_credits: bool) {
+    // Returns false if the purchase was abandoned because the agent couldn't afford it
+    pub async fn buy_goods(
+        &self,
+        good: &str,
+        units: i64,
+        adjust_reserved_credits: bool,
+    ) -> Result<bool, ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
         assert!(
             units <= self.cargo_capacity(),
             "Ship can't hold that much cargo"
         );
-        self.dock().await;
+        self.dock().await?;
         self.debug(&format!("Buying {} units of {}", units, good));
         let uri = format!("/my/ships/{}/purchase", self.ship_symbol);
         let body = json!({
             "symbol": good,
             "units": units,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut attempt = 0;
+        let mut response = loop {
+            let error = match self.api_client.try_post::<Value, _>(&uri, &body).await {
+                Ok(response) => break response,
+                Err(e) if e.is_rejected() => e,
+                Err(e) => return Err(e),
+            };
+            let error_code = error.code().unwrap_or(0);
+            if !INSUFFICIENT_CREDITS_CODES.contains(&error_code) {
+                return Err(error);
+            }
+            // Our view of credits was stale: resync, and stop holding credits we can't spend
+            warn!(
+                "{} purchase of {} {} failed with insufficient credits: {}",
+                self.ship_symbol,
+                units,
+                good,
+                error.body().unwrap_or_default()
+            );
+            let agent = self.api_client.get_agent().await?;
+            self.agent_controller.update_agent(agent).await;
+            if adjust_reserved_credits {
+                self.agent_controller
+                    .ledger
+                    .release_reservation(&self.ship_symbol);
+            }
+            attempt += 1;
+            if attempt > PURCHASE_RETRIES {
+                warn!(
+                    "{} abandoning purchase of {} {} after {} attempts",
+                    self.ship_symbol, units, good, attempt
+                );
+                return Ok(false);
+            }
+            let backoff = PURCHASE_BACKOFF_SECONDS << (attempt - 1);
+            tokio::time::sleep(tokio::time::Duration::from_secs(backoff)).await;
+        };
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
         let transaction: MarketTransaction =
             serde_json::from_value(response["data"]["transaction"].take()).unwrap();
         self.update_cargo(cargo).await;
         self.agent_controller.update_agent(agent).await;
+        self.universe
+            .record_transaction(&self.waypoint(), &transaction)
+            .await;
         if adjust_reserved_credits {
             self.agent_controller.ledger.register_goods_change(
                 &self.ship_symbol,
@@ -262,24 +381,33 @@ impl ShipController {
             transaction.price_per_unit,
             transaction.total_price
         ));
+        Ok(true)
     }
 
-    pub async fn sell_goods(&self, good: &str, units: i64, adjust_reserved_credits: bool) {
+    pub async fn sell_goods(
+        &self,
+        good: &str,
+        units: i64,
+        adjust_reserved_credits: bool,
+    ) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        self.dock().await;
+        self.dock().await?;
         self.debug(&format!("Selling {} units of {}", units, good));
         let uri = format!("/my/ships/{}/sell", self.ship_symbol);
         let body = json!({
             "symbol": good,
             "units": units,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
         let transaction: MarketTransaction =
             serde_json::from_value(response["data"]["transaction"].take()).unwrap();
         self.update_cargo(cargo).await;
         self.agent_controller.update_agent(agent).await;
+        self.universe
+            .record_transaction(&self.waypoint(), &transaction)
+            .await;
         if adjust_reserved_credits {
             self.agent_controller.ledger.register_goods_change(
                 &self.ship_symbol,
@@ -295,9 +423,10 @@ impl ShipController {
             transaction.price_per_unit,
             transaction.total_price
         ));
+        Ok(())
     }
-    pub async fn sell_all_cargo(&self) {
-        self.refresh_market().await;
+    pub async fn sell_all_cargo(&self) -> Result<(), ApiError> {
+        self.refresh_market().await?;
         let market = self.universe.get_market(&self.waypoint()).await.unwrap();
         while let Some(cargo_item) = self.cargo_first_item() {
             let market_good = market
@@ -308,14 +437,14 @@ impl ShipController {
                 .unwrap();
             let units = min(market_good.trade_volume, cargo_item.units);
             assert!(units > 0);
-            self.sell_goods(&cargo_item.symbol, units, false).await;
+            self.sell_goods(&cargo_item.symbol, units, false).await?;
             let new_units = self.cargo_good_count(&cargo_item.symbol);
             assert!(new_units == cargo_item.units - units);
         }
-        self.refresh_market().await;
+        self.refresh_market().await
     }
 
-    pub async fn jettison_cargo(&self, good: &str, units: i64) {
+    pub async fn jettison_cargo(&self, good: &str, units: i64) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
         self.debug(format!("Jettisoning {} {}", units, good).as_str());
         let uri = format!("/my/ships/{}/jettison", self.ship_symbol);
@@ -323,9 +452,10 @@ impl ShipController {
             "symbol": good,
             "units": units,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
         self.update_cargo(cargo).await;
+        Ok(())
     }
 
     // Fuel is bought in multiples of 100, so refuel as the highest multiple of 100
@@ -333,14 +463,14 @@ impl ShipController {
     //
     // If from_cargo is true, refuel from cargo, and we must check after the refuel whether the refuel suceeded
     // Whereas if buying from market, we can safely assume we can obtain the required amount
-    pub async fn refuel(&self, required_fuel: i64, from_cargo: bool) {
+    pub async fn refuel(&self, required_fuel: i64, from_cargo: bool) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
         assert!(
             required_fuel <= self.fuel_capacity(),
             "Ship can't hold that much fuel"
         );
         if self.current_fuel() >= required_fuel {
-            return;
+            return Ok(());
         }
 
         let current = self.current_fuel();
@@ -351,7 +481,7 @@ impl ShipController {
         };
         if max_refuel_units == 0 {
             self.debug("No fuel in cargo to refuel");
-            return;
+            return Ok(());
         }
         let mut units = {
             let missing_fuel = capacity - current;
@@ -364,7 +494,7 @@ impl ShipController {
             }
         };
         units = min(units, max_refuel_units);
-        self.dock().await;
+        self.dock().await?;
         self.debug(&format!(
             "Refueling {} to {}/{}",
             units,
@@ -376,14 +506,22 @@ impl ShipController {
             "units": units,
             "fromCargo": from_cargo,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
-        let fuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
+        let fuel: ShipFuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
-        // let transaction: Transaction = serde_json::from_value(response["data"]["transaction"].take()).unwrap();
+        let transaction: Option<MarketTransaction> =
+            serde_json::from_value(response["data"]["transaction"].take()).unwrap();
+        if let (false, Some(transaction)) = (from_cargo, transaction) {
+            self.universe.record_fuel_purchase(
+                &self.ship_symbol,
+                fuel.current - current,
+                transaction.total_price,
+            );
+        }
         self.update_fuel(fuel).await;
         if from_cargo {
             let cargo_units = (units + 99) / 100;
-            let mut ship = self.ship.lock().unwrap();
+            let mut ship = self.ship.write().unwrap();
             let fuel_item = ship
                 .cargo
                 .inventory
@@ -394,58 +532,82 @@ impl ShipController {
             fuel_item.units -= cargo_units;
         }
         self.agent_controller.update_agent(agent).await;
+        Ok(())
     }
 
-    pub async fn full_load_cargo(&self, good: &str) {
+    pub async fn full_load_cargo(&self, good: &str) -> Result<(), ApiError> {
         let cargo_units = self.cargo_good_count(good);
         assert_eq!(cargo_units, self.cargo_units());
 
         let buy_units = self.cargo_capacity() - cargo_units;
         if buy_units > 0 {
             // Makes assumptions about the TV of the good
-            self.buy_goods(good, buy_units, false).await;
-            self.refresh_market().await;
+            if self.buy_goods(good, buy_units, false).await? {
+                self.refresh_market().await?;
+            }
         }
+        Ok(())
     }
 
-    async fn navigate(&self, flight_mode: ShipFlightMode, waypoint: &WaypointSymbol) {
+    async fn navigate(
+        &self,
+        flight_mode: ShipFlightMode,
+        waypoint: &WaypointSymbol,
+    ) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is already in transit");
         if self.waypoint() == *waypoint {
-            return;
+            return Ok(());
         }
         assert_eq!(self.waypoint().system(), waypoint.system());
-        self.set_flight_mode(flight_mode).await;
-        self.orbit().await;
+        self.set_flight_mode(flight_mode.clone()).await?;
+        self.orbit().await?;
         self.debug(&format!("Navigating to waypoint: {}", waypoint));
+        let src = self.waypoint();
         let uri = format!("/my/ships/{}/navigate", self.ship_symbol);
         let mut response: Value = self
             .api_client
-            .post(&uri, &json!({ "waypointSymbol": waypoint }))
-            .await;
-        let nav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
-        let fuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
+            .try_post(&uri, &json!({ "waypointSymbol": waypoint }))
+            .await?;
+        let nav: ShipNav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
+        let fuel: ShipFuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
         let events = serde_json::from_value(response["data"]["events"].take()).unwrap();
         self.handle_ship_condition_events(&events);
+        if fuel.capacity > 0 {
+            let seconds = (nav.route.arrival - nav.route.departure_time).num_seconds();
+            self.universe.record_fuel_trip(
+                &self.ship_symbol,
+                &src,
+                waypoint,
+                &flight_mode,
+                fuel.consumed.amount,
+                seconds,
+            );
+        }
         self.update_nav(nav).await;
         self.update_fuel(fuel).await;
         self.wait_for_transit().await;
         self.set_orbit_status().await;
+        Ok(())
     }
 
-    pub async fn warp(&self, flight_mode: ShipFlightMode, waypoint: &WaypointSymbol) {
+    pub async fn warp(
+        &self,
+        flight_mode: ShipFlightMode,
+        waypoint: &WaypointSymbol,
+    ) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is already in transit");
         if self.waypoint() == *waypoint {
-            return;
+            return Ok(());
         }
         assert_ne!(self.waypoint().system(), waypoint.system());
-        self.set_flight_mode(flight_mode).await;
-        self.orbit().await;
+        self.set_flight_mode(flight_mode).await?;
+        self.orbit().await?;
         self.debug(&format!("Warp to waypoint: {}", waypoint));
         let uri = format!("/my/ships/{}/warp", self.ship_symbol);
         let mut response: Value = self
             .api_client
-            .post(&uri, &json!({ "waypointSymbol": waypoint }))
-            .await;
+            .try_post(&uri, &json!({ "waypointSymbol": waypoint }))
+            .await?;
         let nav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
         let fuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
         // let events = serde_json::from_value(response["data"]["events"].take()).unwrap();
@@ -454,38 +616,80 @@ impl ShipController {
         self.update_fuel(fuel).await;
         self.wait_for_transit().await;
         self.set_orbit_status().await;
+        Ok(())
     }
 
-    pub async fn jump(&self, waypoint: &WaypointSymbol) {
+    pub async fn jump(&self, waypoint: &WaypointSymbol) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
         self.wait_for_cooldown().await;
-        self.orbit().await;
+        self.orbit().await?;
         self.debug(&format!("Jumping to waypoint: {}", waypoint));
         let uri = format!("/my/ships/{}/jump", self.ship_symbol);
         let body = json!({ "waypointSymbol": waypoint });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
 
         let nav = serde_json::from_value(response["data"]["nav"].take()).unwrap();
         let cooldown: ShipCooldown =
             serde_json::from_value(response["data"]["cooldown"].take()).unwrap();
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
-        let _transaction: MarketTransaction =
+        let transaction: MarketTransaction =
             serde_json::from_value(response["data"]["transaction"].take()).unwrap();
+        self.universe.record_jump(
+            &self.system(),
+            &waypoint.system(),
+            transaction.total_price,
+            cooldown.total_seconds,
+        );
         self.update_nav(nav).await;
         self.agent_controller.update_agent(agent).await;
         self.update_cooldown(cooldown).await;
+        Ok(())
     }
 
     // Navigation between two waypoints
-    pub async fn goto_waypoint(&self, target: &WaypointSymbol) {
+    pub async fn goto_waypoint(&self, target: &WaypointSymbol) -> Result<(), ApiError> {
+        self.goto_waypoint_with(target, Urgency::Normal).await
+    }
+
+    // Targets in another system are reached through the jump gates, which must be directly connected
+    pub async fn goto_waypoint_with(
+        &self,
+        target: &WaypointSymbol,
+        urgency: Urgency,
+    ) -> Result<(), ApiError> {
+        if self.waypoint() == *target {
+            return Ok(());
+        }
+        let attributes = vec![
+            KeyValue::new("ship", self.symbol()),
+            KeyValue::new("from", self.waypoint().to_string()),
+            KeyValue::new("to", target.to_string()),
+        ];
+        telemetry::in_span("navigate", attributes, async {
+            if target.system() != self.system() {
+                let src_gate = self.universe.get_jumpgate(&self.system()).await;
+                let dest_gate = self.universe.get_jumpgate(&target.system()).await;
+                self.goto_system_waypoint(&src_gate, urgency).await?;
+                self.jump(&dest_gate).await?;
+            }
+            self.goto_system_waypoint(target, urgency).await
+        })
+        .await
+    }
+
+    async fn goto_system_waypoint(
+        &self,
+        target: &WaypointSymbol,
+        urgency: Urgency,
+    ) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is already in transit");
         if self.fuel_capacity() == 0 {
-            self.navigate(ShipFlightMode::Cruise, target).await;
+            self.navigate(ShipFlightMode::Cruise, target).await?;
             self.debug(&format!("Arrived at waypoint: {}", target));
-            return;
+            return Ok(());
         }
         if self.waypoint() == *target {
-            return;
+            return Ok(());
         }
         let route = self
             .universe
@@ -495,8 +699,24 @@ impl ShipController {
                 self.engine_speed(),
                 self.current_fuel(),
                 self.fuel_capacity(),
+                urgency,
             )
             .await;
+        self.follow_route(target, route).await
+    }
+
+    // Fly a route to a waypoint in the system, planned for the ship's current waypoint, speed and fuel
+    pub async fn follow_route(
+        &self,
+        target: &WaypointSymbol,
+        route: Route,
+    ) -> Result<(), ApiError> {
+        assert!(!self.is_in_transit(), "Ship is already in transit");
+        let eta =
+            self.universe.now() + chrono::Duration::try_seconds(route.min_travel_duration).unwrap();
+        self.agent_controller
+            .set_ship_target(&self.ship_symbol, Some(target), Some(eta))
+            .await;
         for (waypoint, edge, a_market, b_market) in route.hops {
             // calculate fuel required before leaving
             let required_fuel = if b_market {
@@ -507,16 +727,20 @@ impl ShipController {
             };
             if self.current_fuel() < required_fuel {
                 assert!(a_market);
-                self.refuel(required_fuel, false).await;
+                self.refuel(required_fuel, false).await?;
             }
-            self.navigate(edge.flight_mode, &waypoint).await;
+            self.navigate(edge.flight_mode, &waypoint).await?;
             self.debug(&format!("Arrived at waypoint: {}", waypoint));
         }
+        self.agent_controller
+            .set_ship_target(&self.ship_symbol, None, None)
+            .await;
+        Ok(())
     }
 
-    pub async fn supply_construction(&self, good: &str, units: i64) {
+    pub async fn supply_construction(&self, good: &str, units: i64) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        self.dock().await;
+        self.dock().await?;
         self.debug(&format!("Constructing {} units of {}", units, good));
         let uri = format!(
             "/systems/{}/waypoints/{}/construction/supply",
@@ -528,50 +752,57 @@ impl ShipController {
             "tradeSymbol": good,
             "units": units,
         });
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response: Value = self.api_client.try_post(&uri, &body).await?;
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
         let construction: Construction =
             serde_json::from_value(response["data"]["construction"].take()).unwrap();
         self.update_cargo(cargo).await;
+        self.universe
+            .record_construction_delivery(&construction.symbol, &self.ship_symbol, good, units)
+            .await;
         self.universe.update_construction(&construction).await;
+        Ok(())
     }
 
-    pub async fn refresh_market(&self) {
+    pub async fn refresh_market(&self) -> Result<(), ApiError> {
         assert!(!self.is_in_transit());
         let waypoint = self.waypoint();
         let system = self.system();
         self.debug(&format!("Refreshing market at waypoint {}", &waypoint));
+        // a probe stationed at a market acts without changing
+        ALERTS.record_ship_activity(&self.ship_symbol);
         let uri = format!("/systems/{}/waypoints/{}/market", &system, &waypoint);
-        let mut response: Value = self.api_client.get(&uri).await;
-        let market: Market = serde_json::from_value(response["data"].take()).unwrap();
-        let market = WithTimestamp::<Market> {
-            timestamp: chrono::Utc::now(),
-            data: market,
-        };
-        self.universe.save_market(&waypoint, market).await;
+        self.universe
+            .refresh_market(&waypoint, || async {
+                let mut response: Value = self.api_client.try_get(&uri).await?;
+                Ok(serde_json::from_value(response["data"].take()).unwrap())
+            })
+            .await
     }
 
-    pub async fn refresh_shipyard(&self) {
+    pub async fn refresh_shipyard(&self) -> Result<(), ApiError> {
         assert!(!self.is_in_transit());
         let waypoint = self.waypoint();
         let system = self.system();
         self.debug(&format!("Refreshing shipyard at waypoint {}", &waypoint));
+        ALERTS.record_ship_activity(&self.ship_symbol);
         let uri = format!("/systems/{}/waypoints/{}/shipyard", &system, &waypoint);
-        let mut response: Value = self.api_client.get(&uri).await;
+        let mut response: Value = self.api_client.try_get(&uri).await?;
         let shipyard: Shipyard = serde_json::from_value(response["data"].take()).unwrap();
         let shipyard = WithTimestamp::<Shipyard> {
-            timestamp: chrono::Utc::now(),
+            timestamp: self.universe.now(),
             data: shipyard,
         };
         self.universe.save_shipyard(&waypoint, shipyard).await;
+        Ok(())
     }
 
-    pub async fn survey(&self) {
+    pub async fn survey(&self) -> Result<(), ApiError> {
         assert!(!self.is_in_transit());
         self.wait_for_cooldown().await;
         self.debug(&format!("Surveying {}", self.waypoint()));
         let uri = format!("/my/ships/{}/survey", self.ship_symbol);
-        let mut response: Value = self.api_client.post(&uri, &json!({})).await;
+        let mut response: Value = self.api_client.try_post(&uri, &json!({})).await?;
         let cooldown: ShipCooldown =
             serde_json::from_value(response["data"]["cooldown"].take()).unwrap();
         let surveys: Vec<Survey> =
@@ -590,37 +821,141 @@ impl ShipController {
             .survey_manager
             .insert_surveys(surveys)
             .await;
+        Ok(())
+    }
+
+    pub fn has_sensor_array(&self) -> bool {
+        let ship = self.ship.read().unwrap();
+        ship.mounts
+            .iter()
+            .any(|m| m.symbol.starts_with("MOUNT_SENSOR_ARRAY"))
+    }
+
+    pub async fn scan_waypoints(&self) -> Result<Vec<ScannedWaypoint>, ApiError> {
+        assert!(!self.is_in_transit());
+        self.wait_for_cooldown().await;
+        self.debug(&format!("Scanning waypoints from {}", self.waypoint()));
+        let uri = format!("/my/ships/{}/scan/waypoints", self.ship_symbol);
+        let mut response: Value = self.api_client.try_post(&uri, &json!({})).await?;
+        let cooldown: ShipCooldown =
+            serde_json::from_value(response["data"]["cooldown"].take()).unwrap();
+        let waypoints: Vec<ScannedWaypoint> =
+            serde_json::from_value(response["data"]["waypoints"].take()).unwrap();
+        self.update_cooldown(cooldown).await;
+        Ok(waypoints)
     }
 
-    pub async fn execute_action(&self, action: &Action) {
+    // Chart the current waypoint. Returns false if it was already charted.
+    pub async fn chart(&self) -> Result<bool, ApiError> {
+        assert!(!self.is_in_transit());
+        let waypoint = self.waypoint();
+        self.debug(&format!("Charting {}", waypoint));
+        let uri = format!("/my/ships/{}/chart", self.ship_symbol);
+        match self.api_client.try_post::<Value, _>(&uri, &json!({})).await {
+            Ok(mut response) => {
+                if let Ok(agent) = serde_json::from_value::<Agent>(response["data"]["agent"].take())
+                {
+                    self.agent_controller.update_agent(agent).await;
+                }
+                if let Ok(chart) = serde_json::from_value::<Chart>(response["data"]["chart"].take())
+                {
+                    self.universe.save_chart(&waypoint, &chart).await;
+                }
+                self.agent_controller.record_chart(&waypoint).await;
+                Ok(true)
+            }
+            Err(e) if e.is_rejected() => {
+                // Request failed: 400 {"error":{"message":"Waypoint already charted: X1-...","code":4230}}
+                self.debug(&format!("Chart failed: {:?}", e.body()));
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Buy until `units` of the good are held. With the quote the goods will be sold at, stops early once
+    // the marginal units would sell for less than their price plus `min_margin`
+    pub async fn buy_to_target(
+        &self,
+        good: &str,
+        units: i64,
+        sell: Option<&Quote>,
+        min_margin: i64,
+    ) -> Result<(), ApiError> {
+        let mut remaining_to_buy = units - self.cargo_good_count(good);
+        self.refresh_market().await?;
+        while remaining_to_buy > 0 {
+            let market = self.universe.get_market(&self.waypoint()).await.unwrap();
+            let trade = market
+                .data
+                .trade_goods
+                .iter()
+                .find(|g| g.symbol == *good)
+                .unwrap();
+            let buy_units = next_purchase(
+                &Quote::purchase(trade),
+                sell,
+                self.cargo_good_count(good),
+                remaining_to_buy,
+                min_margin,
+            );
+            if buy_units == 0 {
+                self.debug(&format!(
+                    "Stopping purchase of {} at {} with {} units left, no longer profitable",
+                    good, trade.purchase_price, remaining_to_buy
+                ));
+                break;
+            }
+            if !self.buy_goods(good, buy_units, true).await? {
+                // deliver what we managed to buy
+                break;
+            }
+            self.refresh_market().await?;
+            remaining_to_buy -= buy_units;
+        }
+        Ok(())
+    }
+
+    // `sell` is where the goods of a buy will be sold and the margin they need, to size the purchases
+    pub async fn execute_action(
+        &self,
+        action: &Action,
+        sell: Option<&(Quote, i64)>,
+    ) -> Result<(), ApiError> {
+        let attributes = vec![
+            KeyValue::new("ship", self.symbol()),
+            KeyValue::new("waypoint", self.waypoint().to_string()),
+            KeyValue::new("action", format!("{:?}", action)),
+        ];
+        telemetry::in_span(
+            "ship_action",
+            attributes,
+            self.execute_action_inner(action, sell),
+        )
+        .await
+    }
+
+    async fn execute_action_inner(
+        &self,
+        action: &Action,
+        sell: Option<&(Quote, i64)>,
+    ) -> Result<(), ApiError> {
         match action {
-            Action::RefreshMarket => self.refresh_market().await,
-            Action::RefreshShipyard => self.refresh_shipyard().await,
-            // Interpret this action as units is the target
+            Action::RefreshMarket => self.refresh_market().await?,
+            Action::RefreshShipyard => self.refresh_shipyard().await?,
             Action::BuyGoods(good, units) => {
-                let good_count = self.cargo_good_count(good);
-                let mut remaining_to_buy = units - good_count;
-                self.refresh_market().await;
-                while remaining_to_buy > 0 {
-                    let market = self.universe.get_market(&self.waypoint()).await.unwrap();
-                    let trade = market
-                        .data
-                        .trade_goods
-                        .iter()
-                        .find(|g| g.symbol == *good)
-                        .unwrap();
-                    let buy_units = min(trade.trade_volume, remaining_to_buy);
-                    self.buy_goods(good, buy_units, true).await;
-                    self.refresh_market().await;
-                    remaining_to_buy -= buy_units;
-                }
+                let (quote, min_margin) = match sell {
+                    Some((quote, min_margin)) => (Some(quote), *min_margin),
+                    None => (None, 0),
+                };
+                self.buy_to_target(good, *units, quote, min_margin).await?
             }
             // Always sell to 0
             Action::SellGoods(good, _units) => {
                 // We need to handle falling trade volume
                 let good_count = self.cargo_good_count(good);
                 let mut remaining_to_sell = good_count; // min(*units, good_count);
-                self.refresh_market().await;
+                self.refresh_market().await?;
                 while remaining_to_sell > 0 {
                     let market = self.universe.get_market(&self.waypoint()).await.unwrap();
                     let trade = market
@@ -630,15 +965,15 @@ impl ShipController {
                         .find(|g| g.symbol == *good)
                         .unwrap();
                     let sell_units = min(trade.trade_volume, remaining_to_sell);
-                    self.sell_goods(good, sell_units, true).await;
-                    self.refresh_market().await;
+                    self.sell_goods(good, sell_units, true).await?;
+                    self.refresh_market().await?;
                     remaining_to_sell -= sell_units;
                 }
             }
             Action::TryBuyShips => {
                 assert!(!self.is_in_transit());
                 info!("Starting buy task for ship {}", self.ship_symbol);
-                self.dock().await; // don't need to dock, but do so anyway to clear 'InTransit' status
+                self.dock().await?; // don't need to dock, but do so anyway to clear 'InTransit' status
                 let (bought, _shipyard_waypoints) = self
                     .agent_controller
                     .try_buy_ships(Some(self.ship_symbol.clone()))
@@ -651,49 +986,83 @@ impl ShipController {
             }
             Action::DeliverConstruction(good, units) => {
                 // todo, handle case where construction materials no longer needed
-                self.supply_construction(good, *units).await;
+                self.supply_construction(good, *units).await?;
             }
             _ => {
                 panic!("Action not implemented: {:?}", action);
             }
         }
+        Ok(())
     }
 
-    pub async fn transfer_cargo(&self) {
+    pub async fn transfer_cargo(&self) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        self.orbit().await;
-        let cargo = {
-            let ship = self.ship.lock().unwrap();
-            ship.cargo
-                .inventory
-                .iter()
-                .map(|g| (g.symbol.clone(), g.units))
-                .collect()
-        };
+        self.orbit().await?;
+        let cargo = self.cargo_goods();
         self.agent_controller
             .cargo_broker
             .transfer_cargo(&self.ship_symbol, &self.waypoint(), cargo)
             .await;
+        Ok(())
+    }
+
+    // Unload ahead of the other ships waiting for a shuttle, returns once the cargo is gone
+    pub async fn transfer_cargo_priority(&self) -> Result<(), ApiError> {
+        assert!(!self.is_in_transit(), "Ship is in transit");
+        self.orbit().await?;
+        let cargo = self.cargo_goods();
+        self.agent_controller
+            .cargo_broker
+            .transfer_cargo_priority(&self.ship_symbol, &self.waypoint(), cargo)
+            .await;
+        Ok(())
+    }
+
+    fn cargo_goods(&self) -> Vec<(String, i64)> {
+        let ship = self.ship.read().unwrap();
+        ship.cargo
+            .inventory
+            .iter()
+            .map(|g| (g.symbol.clone(), g.units))
+            .collect()
     }
 
-    pub async fn receive_cargo(&self) {
-        self.orbit().await;
+    pub async fn refresh_cargo(&self) -> Result<(), ApiError> {
+        let uri = format!("/my/ships/{}/cargo", self.ship_symbol);
+        let mut response: Value = self.api_client.try_get(&uri).await?;
+        let cargo: ShipCargo = serde_json::from_value(response["data"].take()).unwrap();
+        self.update_cargo(cargo).await;
+        Ok(())
+    }
+
+    // Whether the error body is the cargo full error
+    pub async fn receive_cargo(&self) -> Result<(), ApiError> {
+        self.orbit().await?;
         assert!(!self.is_in_transit(), "Ship is in transit");
         let space = self.cargo_space_available();
         self.agent_controller
             .cargo_broker
             .receive_cargo(&self.ship_symbol, &self.waypoint(), space)
             .await;
+        Ok(())
     }
 
-    pub async fn siphon(&self) {
+    pub async fn siphon(&self) -> Result<ExtractResult, ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        self.orbit().await;
+        self.orbit().await?;
         self.wait_for_cooldown().await;
         self.debug("Siphoning");
         let uri = format!("/my/ships/{}/siphon", self.ship_symbol);
         let body = json!({});
-        let mut response: Value = self.api_client.post(&uri, &body).await;
+        let mut response = match self.api_client.try_post::<Value, _>(&uri, &body).await {
+            Ok(response) => response,
+            Err(e) if e.is_rejected() && e.code() == Some(CARGO_FULL_CODE) => {
+                self.debug("Siphon failed: cargo full");
+                self.refresh_cargo().await?;
+                return Ok(ExtractResult::CargoFull);
+            }
+            Err(e) => return Err(e),
+        };
         let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
         let cooldown: ShipCooldown =
             serde_json::from_value(response["data"]["cooldown"].take()).unwrap();
@@ -705,24 +1074,20 @@ impl ShipController {
         self.debug(&format!("Siphoned {} units of {}", units, good));
         self.update_cooldown(cooldown).await;
         self.update_cargo(cargo).await;
+        Ok(ExtractResult::Extracted)
     }
 
-    pub async fn extract_survey(&self, survey: &KeyedSurvey) {
+    pub async fn extract_survey(&self, survey: &KeyedSurvey) -> Result<ExtractResult, ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        // self.orbit().await;
+        // self.orbit().await?;
         self.wait_for_cooldown().await;
         self.debug(&format!("Extracting survey {}", survey.uuid));
         let uri = format!("/my/ships/{}/extract/survey", self.ship_symbol);
         let req_body = &survey.survey;
         // let mut response: Value = self.api_client.post(&uri, body).await;
 
-        let (code, resp_body): (StatusCode, Result<Value, String>) = self
-            .api_client
-            .request(Method::POST, &uri, Some(req_body))
-            .await;
-        match code {
-            StatusCode::CREATED => {
-                let mut response = resp_body.unwrap();
+        match self.api_client.try_post::<Value, _>(&uri, req_body).await {
+            Ok(mut response) => {
                 let cargo: ShipCargo =
                     serde_json::from_value(response["data"]["cargo"].take()).unwrap();
                 let cooldown: ShipCooldown =
@@ -734,14 +1099,26 @@ impl ShipController {
                 let good = extraction["yield"]["symbol"].as_str().unwrap();
                 let units = extraction["yield"]["units"].as_i64().unwrap();
                 self.debug(&format!("Extracted {} units of {}", units, good));
+                self.agent_controller
+                    .survey_manager
+                    .record_extraction(&self.waypoint(), good, units, cooldown.total_seconds)
+                    .await;
+                self.agent_controller
+                    .survey_manager
+                    .record_survey_use(survey, units)
+                    .await;
                 self.update_cooldown(cooldown).await;
                 self.update_cargo(cargo).await;
+                Ok(ExtractResult::Extracted)
             }
-            StatusCode::BAD_REQUEST | StatusCode::CONFLICT => {
-                let response: Value = serde_json::from_str(&resp_body.unwrap_err()).unwrap();
-                // variety of responses we might get here: exhausted, expired, asteroid overmined
-                let code = response["error"]["code"].as_i64().unwrap();
-                if code == 4221 {
+            Err(e) if e.is_rejected() => {
+                // variety of responses we might get here: exhausted, expired, asteroid overmined, cargo full
+                let code = e.code().unwrap_or(0);
+                if code == CARGO_FULL_CODE {
+                    self.debug("Extraction failed: cargo full");
+                    self.refresh_cargo().await?;
+                    Ok(ExtractResult::CargoFull)
+                } else if code == 4221 {
                     // Request failed: 400 {"error":{"message":"Ship survey failed. Target signature is no longer in range or valid.","code":4221}}
                     self.debug(
                         "Extraction failed: Target signature is no longer in range or valid",
@@ -750,6 +1127,7 @@ impl ShipController {
                         .survey_manager
                         .remove_survey(&survey)
                         .await;
+                    Ok(ExtractResult::SurveyInvalid)
                 } else if code == 4224 {
                     // Request failed: 409 Err("{\"error\":{\"message\":\"Ship extract failed. Survey X1-FM95-CD5Z-BEC3E1 has been exhausted.\",\"code\":4224}}")
                     self.debug("Extraction failed: Survey has been exhausted");
@@ -757,32 +1135,21 @@ impl ShipController {
                         .survey_manager
                         .remove_survey(&survey)
                         .await;
+                    Ok(ExtractResult::SurveyInvalid)
                 } else {
-                    panic!(
-                        "Request failed: {} {} {}\nbody: {:?}",
-                        code,
-                        Method::POST,
-                        uri,
-                        response
-                    );
+                    Err(e)
                 }
             }
-            _ => panic!(
-                "Request failed: {} {} {}\nbody: {:?}",
-                code.as_u16(),
-                Method::POST,
-                uri,
-                resp_body
-            ),
-        };
+            Err(e) => Err(e),
+        }
     }
 
-    pub async fn scrap(&self) {
+    pub async fn scrap(&self) -> Result<(), ApiError> {
         assert!(!self.is_in_transit(), "Ship is in transit");
-        self.dock().await;
+        self.dock().await?;
         self.debug("Scrapping Ship");
         let uri = format!("/my/ships/{}/scrap", self.ship_symbol);
-        let mut response: Value = self.api_client.post(&uri, &json!({})).await;
+        let mut response: Value = self.api_client.try_post(&uri, &json!({})).await?;
         let agent: Agent = serde_json::from_value(response["data"]["agent"].take()).unwrap();
         let transaction: ScrapTransaction =
             serde_json::from_value(response["data"]["transaction"].take()).unwrap();
@@ -791,6 +1158,7 @@ impl ShipController {
             self.ship_symbol, transaction.total_price
         );
         self.agent_controller.update_agent(agent).await;
+        Ok(())
     }
 
     pub fn handle_ship_condition_events(&self, events: &Vec<ShipConditionEvent>) {
@@ -799,8 +1167,9 @@ impl ShipController {
         }
     }
 
-    pub fn set_state_description(&self, desc: &str) {
+    pub async fn set_state_description(&self, desc: &str) {
         self.agent_controller
             .set_state_description(&self.ship_symbol, desc)
+            .await
     }
 }
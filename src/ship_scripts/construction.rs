@@ -4,12 +4,14 @@
 //! This script does NOT coordinate with the logistic task manager. Which means the logistics task manager
 //! needs to be configured not to create construction tasks, or any task involving the construction goods.
 //!
+use crate::agent_controller::ledger::CONSTRUCTION_SPEND;
+use crate::api_client::ApiError;
 use crate::config::CONFIG;
 use crate::models::MarketActivity::*;
 use crate::models::MarketSupply::*;
 use crate::models::MarketType::*;
 use crate::{
-    db::DbClient,
+    db::{load_until_ok, ok_or_warn, DbClient},
     models::{Construction, WaypointSymbol},
     ship_controller::ShipController,
     universe::WaypointFilter,
@@ -48,6 +50,35 @@ pub async fn get_probe_shipyard(ship: &ShipController) -> WaypointSymbol {
     filtered[0].symbol.clone()
 }
 
+// Construction spending is paused while the ledger circuit breaker is tripped, and optionally
+// limited to a fraction of the last hour's income. Income is only tracked from startup, so the
+// limit applies once there's an hour of it
+fn construction_budget_allows(ship: &ShipController, expected_cost: i64) -> bool {
+    let ledger = &ship.agent_controller.ledger;
+    if ledger.circuit_breaker_tripped() {
+        debug!("Ledger circuit breaker tripped. Pausing construction spending");
+        return false;
+    }
+    match (
+        CONFIG.construction_budget_fraction,
+        ledger.full_hour_income(),
+    ) {
+        (Some(fraction), Some(income)) => {
+            let budget = (income as f64 * fraction) as i64;
+            let spent = ledger.spend_last_hour(CONSTRUCTION_SPEND);
+            if spent + expected_cost > budget {
+                debug!(
+                    "Construction budget exceeded. spent {} + {} > budget {}",
+                    spent, expected_cost, budget
+                );
+                return false;
+            }
+            true
+        }
+        _ => true,
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 enum ConstructionHaulerState {
     Buying,
@@ -56,7 +87,7 @@ enum ConstructionHaulerState {
     TerminalState,
 }
 
-pub async fn run_hauler(ship: ShipController, db: DbClient) {
+pub async fn run_hauler(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script construction_hauler for {}", ship.symbol());
     ship.wait_for_transit().await;
 
@@ -65,13 +96,21 @@ pub async fn run_hauler(ship: ShipController, db: DbClient) {
     let adv_circuit_market = get_export_market(&ship, "ADVANCED_CIRCUITRY").await;
 
     let key = format!("construction_state/{}", ship.symbol());
-    let mut state: ConstructionHaulerState = db.get_value(&key).await.unwrap_or(Buying);
+    let mut state: ConstructionHaulerState =
+        load_until_ok("load ship state", || db.get_value(&key))
+            .await
+            .unwrap_or(Buying);
 
     if state == TerminalState {
-        ship.refresh_shipyard().await;
+        ship.refresh_shipyard().await?;
     }
 
     while state != TerminalState {
+        // after a delivery, before buying the next load
+        if state == Buying && ship.cargo_empty() && ship.transfer_requested() {
+            info!("Construction hauler {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
         let next_state = tick(
             &ship,
             state,
@@ -79,12 +118,13 @@ pub async fn run_hauler(ship: ShipController, db: DbClient) {
             &fab_mat_market,
             &adv_circuit_market,
         )
-        .await;
+        .await?;
         if let Some(next_state) = next_state {
             state = next_state;
-            db.set_value(&key, &state).await;
+            ok_or_warn(db.set_value(&key, &state).await, "save script state");
         }
     }
+    Ok(())
 }
 
 async fn tick(
@@ -93,17 +133,17 @@ async fn tick(
     jump_gate_symbol: &WaypointSymbol,
     fab_mat_market: &WaypointSymbol,
     adv_circuit_market: &WaypointSymbol,
-) -> Option<ConstructionHaulerState> {
+) -> Result<Option<ConstructionHaulerState>, ApiError> {
     match state {
         Buying => {
             let construction = ship.universe.get_construction(&jump_gate_symbol).await;
             let construction: &Construction = match &construction.data {
-                None => return Some(Completed),
-                Some(x) if x.is_complete => return Some(Completed),
+                None => return Ok(Some(Completed)),
+                Some(x) if x.is_complete => return Ok(Some(Completed)),
                 Some(x) => x,
             };
             if ship.cargo_space_available() == 0 {
-                return Some(Delivering);
+                return Ok(Some(Delivering));
             }
 
             // load up on construction goods
@@ -145,7 +185,7 @@ async fn tick(
                             good.trade_volume,
                             min(ship.cargo_space_available(), required_units),
                         );
-                        ship.goto_waypoint(&market_symbol).await;
+                        ship.goto_waypoint(&market_symbol).await?;
 
                         let expected_cost = good.purchase_price * units;
                         let credits = ship.agent_controller.ledger.available_credits();
@@ -155,40 +195,49 @@ async fn tick(
                                 units, good.symbol, credits, expected_cost, credit_buffer
                             );
                             tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                            return None;
+                            return Ok(None);
+                        }
+                        if !construction_budget_allows(ship, expected_cost) {
+                            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+                            return Ok(None);
+                        }
+                        if !ship.buy_goods(&good.symbol, units, false).await? {
+                            return Ok(None);
                         }
-                        ship.buy_goods(&good.symbol, units, false).await;
-                        ship.refresh_market().await;
-                        return None;
+                        ship.agent_controller
+                            .ledger
+                            .register_spend(CONSTRUCTION_SPEND, expected_cost);
+                        ship.refresh_market().await?;
+                        return Ok(None);
                     }
                 }
             }
             // cargo not full and nothing to buy: retry in 60 seconds
             if incomplete_materials == 0 || ship.cargo_units() != 0 {
-                return Some(Delivering);
+                return Ok(Some(Delivering));
             }
 
             // Nothing to buy right now: reposition ship
             if ship.waypoint() != *fab_mat_market && ship.waypoint() != *adv_circuit_market {
                 ship.debug("Repositioning to FAB_MAT market");
-                ship.goto_waypoint(&fab_mat_market).await;
-                return None;
+                ship.goto_waypoint(&fab_mat_market).await?;
+                return Ok(None);
             }
 
             tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-            return None;
+            return Ok(None);
         }
         Delivering => {
             if ship.cargo_empty() {
-                return Some(Buying);
+                return Ok(Some(Buying));
             }
             // todo - handle case where materials are no longer needed
-            ship.goto_waypoint(&jump_gate_symbol).await;
+            ship.goto_waypoint(&jump_gate_symbol).await?;
             while let Some(cargo_item) = ship.cargo_first_item() {
                 ship.supply_construction(&cargo_item.symbol, cargo_item.units)
-                    .await;
+                    .await?;
             }
-            None
+            Ok(None)
         }
         Completed => {
             // After completing the gate, navigate through the gate to the capital system
@@ -202,16 +251,16 @@ async fn tick(
                 // nav to jumpgate
                 let jumpgate_src = ship.universe.get_jumpgate(&ship.system()).await;
                 let jumpgate_dest = ship.universe.get_jumpgate(&shipyard.system()).await;
-                ship.goto_waypoint(&jumpgate_src).await;
+                ship.goto_waypoint(&jumpgate_src).await?;
                 // jump to correct system
-                ship.jump(&jumpgate_dest).await;
+                ship.jump(&jumpgate_dest).await?;
             }
-            ship.goto_waypoint(&shipyard).await;
-            ship.refresh_shipyard().await;
+            ship.goto_waypoint(&shipyard).await?;
+            ship.refresh_shipyard().await?;
             ship.debug(
                 "Jumpgate is completed + navigating to shipyard complete. Entering terminal state.",
             );
-            return Some(TerminalState);
+            return Ok(Some(TerminalState));
         }
         TerminalState => {
             panic!("Invalid state");
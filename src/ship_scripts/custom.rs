@@ -0,0 +1,78 @@
+//!
+//! User-provided ship scripts.
+//!
+//! Scripts are registered by name on the agent controller's `script_registry` before `run_ships`,
+//! and run for jobs with `ShipBehaviour::Custom(name, config)`. A script that returns an error is
+//! restarted, like the built-in scripts.
+//!
+use crate::api_client::ApiError;
+use crate::ship_controller::ShipController;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type ScriptFuture = Pin<Box<dyn Future<Output = Result<(), ApiError>> + Send>>;
+
+pub trait ShipScript: Send + Sync {
+    fn run(&self, ship: ShipController, config: Value) -> ScriptFuture;
+}
+
+// Allow plain async functions/closures as scripts
+impl<F, Fut> ShipScript for F
+where
+    F: Fn(ShipController, Value) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<(), ApiError>> + Send + 'static,
+{
+    fn run(&self, ship: ShipController, config: Value) -> ScriptFuture {
+        Box::pin(self(ship, config))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ShipScriptRegistry {
+    scripts: Arc<DashMap<String, Arc<dyn ShipScript>>>,
+}
+
+impl ShipScriptRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, name: &str, script: impl ShipScript + 'static) {
+        let prev = self.scripts.insert(name.to_string(), Arc::new(script));
+        assert!(prev.is_none(), "Ship script {} already registered", name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ShipScript>> {
+        self.scripts.get(name).map(|s| s.value().clone())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.scripts.iter().map(|s| s.key().clone()).collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn idle(_ship: ShipController, _config: Value) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = ShipScriptRegistry::new();
+        registry.register("idle", idle);
+        registry.register("noop", |_ship: ShipController, _config: Value| async {
+            Ok(())
+        });
+        assert!(registry.get("idle").is_some());
+        assert!(registry.get("missing").is_none());
+        assert_eq!(registry.names(), vec!["idle", "noop"]);
+    }
+}
@@ -1,12 +1,13 @@
 use crate::{
+    api_client::ApiError,
     db::DbClient,
-    models::{LogisticsScriptConfig, ShipFlightMode, SystemSymbol},
+    models::{LogisticsScriptConfig, SystemSymbol},
     // ship_config::market_waypoints,
     ship_controller::ShipController,
     universe::pathfinding::EdgeType,
 };
+use chrono::Utc;
 use log::*;
-use pathfinding::directed::dijkstra::dijkstra;
 use serde::{Deserialize, Serialize};
 use ExplorerState::*;
 
@@ -18,14 +19,14 @@ enum ExplorerState {
     Exit,
 }
 
-pub async fn run_explorer(ship: ShipController, db: DbClient) {
+pub async fn run_explorer(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script explorer for {}", ship.symbol());
     ship.wait_for_transit().await;
 
     let mut state = Init;
 
     while state != Exit {
-        let next_state = tick(&ship, &state).await;
+        let next_state = tick(&ship, &state).await?;
         if let Some(next_state) = next_state {
             state = next_state;
         }
@@ -36,8 +37,10 @@ pub async fn run_explorer(ship: ShipController, db: DbClient) {
 
     if let Trading(system) = state {
         assert_eq!(ship.system(), system);
+        scan_and_chart(&ship, true).await?;
         info!("Explorer trading in target system {}", system);
-        ship.set_state_description(&format!("Trading in {}", system));
+        ship.set_state_description(&format!("Trading in {}", system))
+            .await;
 
         let task_manager = ship.agent_controller.task_manager.clone();
         // let waypoints = ship.universe.get_system_waypoints(&system).await;
@@ -49,13 +52,61 @@ pub async fn run_explorer(ship: ShipController, db: DbClient) {
             allow_shipbuying: false,
             allow_market_refresh: true,
             allow_construction: false,
+            allow_gate_trades: false,
             min_profit: 5000,
         };
-        crate::ship_scripts::logistics::run(ship.clone(), db, task_manager, config).await;
+        crate::ship_scripts::logistics::run(ship.clone(), db, task_manager, config).await?;
     }
+    Ok(())
 }
 
-async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerState> {
+// Queue uncharted waypoints picked up by the sensor array, then chart what the budget allows.
+// Only leaves the current waypoint when `detour` is set, mid-route fuel is kept for the next warp.
+async fn scan_and_chart(ship: &ShipController, detour: bool) -> Result<(), ApiError> {
+    let queue = ship.agent_controller.chart_queue.clone();
+    if ship.has_sensor_array() {
+        let callsign = ship.agent_controller.agent().symbol;
+        let scanned = ship.scan_waypoints().await?;
+        // scans can lag our own charts, don't go back to those
+        let uncharted = scanned
+            .into_iter()
+            .filter(|w| w.is_uncharted())
+            .filter(|w| !ship.universe.waypoint(&w.symbol).is_charted_by(&callsign))
+            .map(|w| w.symbol);
+        let queued = queue.enqueue(uncharted);
+        debug!("Scan queued {} uncharted waypoints", queued);
+    }
+    if ship.universe.waypoint(&ship.waypoint()).is_uncharted() {
+        queue.enqueue(vec![ship.waypoint()]);
+    }
+
+    let system = ship.system();
+    let mut charted = 0;
+    loop {
+        let next = match detour {
+            true => queue.take(&system, &ship.waypoint(), Utc::now()),
+            false => queue
+                .take_at(&ship.waypoint(), Utc::now())
+                .then(|| ship.waypoint()),
+        };
+        let Some(next) = next else {
+            break;
+        };
+        ship.goto_waypoint(&next).await?;
+        if ship.chart().await? {
+            charted += 1;
+        }
+    }
+    if charted != 0 {
+        ship.universe.refresh_system_waypoints(&system).await;
+    }
+    Ok(())
+}
+
+async fn tick(
+    ship: &ShipController,
+    state: &ExplorerState,
+) -> Result<Option<ExplorerState>, ApiError> {
     match state {
         Init => {
             // Could be existing reservation, or a new one
@@ -67,80 +118,71 @@ async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerSt
                 Some(target) => format!("Navigating to {}", target),
                 None => "No target".to_string(),
             };
-            ship.set_state_description(&desc);
+            ship.set_state_description(&desc).await;
             match target {
-                Some(target) => Some(Navigating(target)),
-                None => Some(Exit),
+                Some(target) => Ok(Some(Navigating(target))),
+                None => Ok(Some(Exit)),
             }
         }
         Navigating(target) => {
             if &ship.system() == target {
                 // might need to empty cargo before starting trading state
-                return Some(Trading(target.clone()));
+                return Ok(Some(Trading(target.clone())));
             }
 
             // Plan route
-            let graph = ship.universe.warp_jump_graph().await;
-            let start = ship.system();
-            let (path, duration) = dijkstra(
-                &start,
-                |node| {
-                    graph
-                        .get(node)
-                        .unwrap()
-                        .iter()
-                        .map(|(s, d)| (s.clone(), d.duration))
-                },
-                |node| node == target,
-            )
-            .expect("No path to target");
-
-            let path_str = path
-                .windows(2)
-                .map(|pair| {
-                    let s = &pair[0];
-                    let t = &pair[1];
-                    let edge = &graph[s][t];
-                    let type_ = match edge.edge_type {
+            let route = ship
+                .universe
+                .warp_route(&ship.system(), target)
+                .await
+                .expect("No path to target");
+            let path_str = route
+                .segments
+                .iter()
+                .map(|segment| {
+                    let type_ = match segment.edge_type {
                         EdgeType::Jumpgate => "JUMP",
                         EdgeType::Warp => "WARP",
+                        EdgeType::Drift => "DRIFT",
                     };
-                    format!("{} {} -> {}", type_, s, t)
+                    format!("{} {} -> {}", type_, segment.src, segment.dest)
                 })
                 .collect::<Vec<_>>()
                 .join(", ");
             let desc = format!(
-                "Navigating to {} in {}s via path {}",
-                target, duration, path_str
+                "Navigating to {} in {}s ({}s drifting) via path {}",
+                target,
+                route.duration,
+                route.drift_duration(),
+                path_str
             );
             debug!("{}", desc);
-            ship.set_state_description(&desc);
+            ship.set_state_description(&desc).await;
 
             // Execute route
-            for pair in path.windows(2) {
-                let s = &pair[0];
-                let t = &pair[1];
-                let edge = &graph[s][t];
-                match edge.edge_type {
+            for segment in &route.segments {
+                let s = &segment.src;
+                let t = &segment.dest;
+                match segment.edge_type {
                     EdgeType::Jumpgate => {
                         let src_gate = ship.universe.get_jumpgate(&s).await;
                         let dst_gate = ship.universe.get_jumpgate(&t).await;
-                        ship.goto_waypoint(&src_gate).await;
-                        ship.jump(&dst_gate).await;
+                        ship.goto_waypoint(&src_gate).await?;
+                        ship.jump(&dst_gate).await?;
                     }
-                    EdgeType::Warp => {
+                    EdgeType::Warp | EdgeType::Drift => {
                         let waypoint = ship.universe.waypoint(&ship.waypoint());
                         if waypoint.is_market() {
-                            ship.refuel(ship.fuel_capacity(), false).await;
-                            ship.full_load_cargo("FUEL").await;
+                            ship.refuel(ship.fuel_capacity(), false).await?;
+                            ship.full_load_cargo("FUEL").await?;
                         } else {
-                            let required_fuel = edge.fuel;
-                            ship.refuel(required_fuel, true).await;
+                            let required_fuel = segment.fuel;
+                            ship.refuel(required_fuel, true).await?;
                         }
 
-                        if ship.current_fuel() < edge.fuel {
+                        if ship.current_fuel() < segment.fuel {
                             info!("Not enough fuel to warp to {}", t);
-                            return Some(Exit);
+                            return Ok(Some(Exit));
                         }
 
                         // target waypoint:
@@ -150,13 +192,20 @@ async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerSt
                             Some(jumpgate) => jumpgate,
                             None => ship.universe.first_waypoint(&t).await,
                         };
-                        ship.warp(ShipFlightMode::Cruise, &warp_target).await;
+                        let flight_mode = segment.flight_mode.clone().unwrap();
+                        ship.warp(flight_mode, &warp_target).await?;
                     }
                 }
+                scan_and_chart(ship, false).await?;
+                // the ship keeps its reservation, and resumes the route if it explores again
+                if ship.transfer_requested() {
+                    info!("Explorer {} exiting for transfer", ship.symbol());
+                    return Ok(Some(Exit));
+                }
             }
 
             // might need to empty cargo before starting trading state
-            Some(Trading(target.clone()))
+            Ok(Some(Trading(target.clone())))
         }
         Trading(_system) => {
             panic!("Invalid state");
@@ -1,48 +1,229 @@
 use std::{collections::BTreeMap, sync::Arc};
 
 use crate::{
-    db::DbClient, models::LogisticsScriptConfig, ship_controller::ShipController,
+    agent_controller::ledger::CONSTRUCTION_SPEND,
+    api_client::ApiError,
+    config::CONFIG,
+    db::{load_until_ok, ok_or_warn, DbClient},
+    logistics_planner::{Action, ActionState, ScheduleProgress, ShipSchedule},
+    models::{LogisticsScriptConfig, MarketTradeGood, WaypointSymbol},
+    purchase_sizing::Quote,
+    ship_controller::ShipController,
     tasks::LogisticTaskManager,
+    telemetry,
+    universe::transaction_costs::TradeSide,
 };
 use chrono::Duration;
 use log::*;
+use opentelemetry::KeyValue;
+
+async fn finish_action(
+    db: &DbClient,
+    ship_controller: &ShipController,
+    taskmanager: &LogisticTaskManager,
+    progress: &mut ScheduleProgress,
+    schedule: &ShipSchedule,
+    action_idx: usize,
+) {
+    progress.finish(action_idx, ship_controller.universe.now());
+    ok_or_warn(
+        db.save_schedule_progress(&ship_controller.symbol(), progress)
+            .await,
+        "save schedule progress",
+    );
+    if let Some(task) = &schedule.actions[action_idx].task_completed {
+        taskmanager.set_task_completed(task).await;
+    }
+}
+
+// Drop the actions from `idx` on, so the ship plans again
+async fn abandon_schedule(
+    db: &DbClient,
+    ship_controller: &ShipController,
+    taskmanager: &LogisticTaskManager,
+    progress: &mut ScheduleProgress,
+    schedule: &ShipSchedule,
+    idx: usize,
+) {
+    let released = schedule.actions[idx..]
+        .iter()
+        .filter_map(|a| a.task_completed.clone())
+        .collect::<Vec<_>>();
+    taskmanager.release_tasks(&released).await;
+    progress.skip_from(idx, ship_controller.universe.now());
+    ok_or_warn(
+        db.save_schedule_progress(&ship_controller.symbol(), progress)
+            .await,
+        "save schedule progress",
+    );
+}
+
+async fn purchase_price(
+    ship_controller: &ShipController,
+    waypoint: &WaypointSymbol,
+    good: &str,
+) -> Option<i64> {
+    let market = ship_controller.universe.get_market(waypoint).await?;
+    let trade = market.data.trade_goods.iter().find(|g| g.symbol == good)?;
+    Some(trade.purchase_price)
+}
+
+async fn market_trade(
+    ship_controller: &ShipController,
+    waypoint: &WaypointSymbol,
+    good: &str,
+) -> Option<MarketTradeGood> {
+    let market = ship_controller.universe.get_market(waypoint).await?;
+    market
+        .data
+        .trade_goods
+        .iter()
+        .find(|g| g.symbol == good)
+        .cloned()
+}
+
+// The good of a buy that's delivered to a construction site. Its cost counts against the construction
+// budget, like the construction hauler's purchases
+fn construction_good(schedule: &ShipSchedule, idx: usize) -> Option<&str> {
+    let Action::BuyGoods(good, _) = &schedule.actions[idx].action else {
+        return None;
+    };
+    let delivery = schedule.delivery_of(idx)?;
+    match &schedule.actions[delivery].action {
+        Action::DeliverConstruction(_, _) => Some(good),
+        _ => None,
+    }
+}
+
+// Prices the trades were planned at. Buys are checked again before buying, and sized against the sell
+async fn record_planned_prices(ship_controller: &ShipController, schedule: &mut ShipSchedule) {
+    for scheduled in schedule.actions.iter_mut() {
+        match &scheduled.action {
+            Action::BuyGoods(good, _) => {
+                scheduled.planned_price =
+                    purchase_price(ship_controller, &scheduled.waypoint, good).await;
+            }
+            Action::SellGoods(good, _) => {
+                scheduled.planned_price = market_trade(ship_controller, &scheduled.waypoint, good)
+                    .await
+                    .map(|trade| trade.sell_price);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Where the goods of a buy will be sold, at the planned sell price, and the transaction costs of the
+// two trades. None if the goods are delivered instead
+async fn sell_quote(
+    ship_controller: &ShipController,
+    schedule: &ShipSchedule,
+    idx: usize,
+) -> Option<(Quote, i64)> {
+    let buy = &schedule.actions[idx];
+    let sell = &schedule.actions[schedule.delivery_of(idx)?];
+    let Action::SellGoods(good, _) = &sell.action else {
+        return None;
+    };
+    let trade = market_trade(ship_controller, &sell.waypoint, good).await?;
+    let mut quote = Quote::sell(&trade);
+    if let Some(planned) = sell.planned_price {
+        quote.price = planned;
+    }
+    let universe = &ship_controller.universe;
+    let transaction_cost = universe.transaction_cost(&buy.waypoint, good, TradeSide::Purchase)
+        + universe.transaction_cost(&sell.waypoint, good, TradeSide::Sell);
+    Some((quote, transaction_cost))
+}
 
 pub async fn run(
     ship_controller: ShipController,
     db: DbClient,
     taskmanager: Arc<LogisticTaskManager>,
     config: LogisticsScriptConfig,
-) {
+) -> Result<(), ApiError> {
     info!("Starting script logistics for {}", ship_controller.symbol());
     ship_controller.wait_for_transit().await;
 
     let ship_symbol = ship_controller.symbol();
-    let system_symbol = ship_controller.system();
+    // gate trades end in another system, the ship returns home before planning again
+    let system_symbol = match load_until_ok("load logistics home", || {
+        db.load_logistics_home(&ship_symbol)
+    })
+    .await
+    {
+        Some(home) => home,
+        None => ship_controller.system(),
+    };
+    ok_or_warn(
+        db.save_logistics_home(&ship_symbol, Some(&system_symbol))
+            .await,
+        "save logistics home",
+    );
 
     loop {
         // Generate or resume schedule
         // !! it would be better if script was not implementing persistence, and instead relied on the task manager for it's persistent state
-        let schedule_opt = db.load_schedule(&ship_symbol).await;
-        let progress_opt = db.load_schedule_progress(&ship_symbol).await;
-        assert_eq!(schedule_opt.is_some(), progress_opt.is_some());
-        let resume_saved = match (&schedule_opt, progress_opt) {
-            (Some(schedule), Some(progress)) => progress < schedule.actions.len(),
-            _ => false,
+        let Some(schedule_opt) = ok_or_warn(db.load_schedule(&ship_symbol).await, "load schedule")
+        else {
+            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+            continue;
+        };
+        let Some(progress_opt) = ok_or_warn(
+            db.load_schedule_progress(&ship_symbol).await,
+            "load schedule progress",
+        ) else {
+            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+            continue;
+        };
+        let saved = match (schedule_opt, progress_opt) {
+            (Some(schedule), progress) => {
+                // stopped between saving a new schedule and its progress, nothing has started
+                let mut progress =
+                    progress.unwrap_or_else(|| ScheduleProgress::new(schedule.actions.len()));
+                progress.resize(schedule.actions.len());
+                match progress.is_complete() {
+                    true => None,
+                    false => Some((schedule, progress)),
+                }
+            }
+            _ => None,
         };
 
-        let (schedule, progress) = if resume_saved {
-            (schedule_opt.unwrap(), progress_opt.unwrap())
+        let (schedule, mut progress) = if let Some(saved) = saved {
+            saved
         } else {
             // sell fuel if we have fuel in cargo, after warps
             let fuel_units = ship_controller.cargo_good_count("FUEL");
             if fuel_units > 0 {
-                ship_controller.sell_goods("FUEL", fuel_units, false).await;
+                ship_controller
+                    .sell_goods("FUEL", fuel_units, false)
+                    .await?;
             }
             assert!(ship_controller.cargo_empty());
 
+            if ship_controller.system() != system_symbol {
+                let home_gate = ship_controller.universe.get_jumpgate(&system_symbol).await;
+                info!(
+                    "Ship {} returning to home system {}",
+                    ship_symbol, system_symbol
+                );
+                ship_controller.goto_waypoint(&home_gate).await?;
+            }
+
+            // Safe point to hand the ship over to another script
+            if ship_controller.transfer_requested() {
+                info!("Ship {} exiting logistics for transfer", ship_symbol);
+                ok_or_warn(
+                    db.save_logistics_home(&ship_symbol, None).await,
+                    "save logistics home",
+                );
+                return Ok(());
+            }
+
             // Generate new schedule
             let plan_length = Duration::try_minutes(15).unwrap();
-            let schedule = taskmanager
+            let mut schedule = taskmanager
                 .take_tasks(
                     &ship_symbol,
                     &system_symbol,
@@ -54,25 +235,55 @@ pub async fn run(
                     plan_length,
                 )
                 .await;
-            db.save_schedule(&ship_symbol, &schedule).await;
-            db.save_schedule_progress(&ship_symbol, 0).await;
-            (schedule, 0)
+            record_planned_prices(&ship_controller, &mut schedule).await;
+            let progress = ScheduleProgress::new(schedule.actions.len());
+            // a stored progress without its schedule would mark the previous schedule's actions done
+            if ok_or_warn(
+                db.save_schedule(&ship_symbol, &schedule).await,
+                "save schedule",
+            )
+            .is_some()
+            {
+                ok_or_warn(
+                    db.save_schedule_progress(&ship_symbol, &progress).await,
+                    "save schedule progress",
+                );
+            }
+            (schedule, progress)
         };
 
         let schedule_len = schedule.actions.len();
         if schedule_len == 0 {
             info!(
-                "Ship {} was scheduled no tasks to perform. Sleeping 5-10 minutes.",
+                "Ship {} was scheduled no tasks to perform. Waiting for market or task updates (max 5-10 minutes).",
                 ship_controller.symbol()
             );
-            let rand_seconds = rand::random::<u64>() % 300;
-            tokio::time::sleep(tokio::time::Duration::from_secs(300 + rand_seconds)).await;
+            let idle = async {
+                // subscribed first, so an update during the minimum wait still wakes the ship
+                let mut updates = taskmanager.subscribe_updates(&system_symbol);
+                tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+                let rand_seconds = rand::random::<u64>() % 300;
+                tokio::select! {
+                    _ = updates.changed() => {}
+                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(240 + rand_seconds)) => {}
+                }
+            };
+            telemetry::in_span(
+                "idle_no_tasks",
+                vec![KeyValue::new("ship", ship_symbol.clone())],
+                idle,
+            )
+            .await;
             continue;
         }
 
-        // sanity check before we start (up to index 'progress')
+        // sanity check before we start (the actions recorded as done)
+        let next_idx = progress.next().unwrap();
         let mut expected_cargo = BTreeMap::new();
-        for action in schedule.actions.iter().take(progress) {
+        for (action_idx, action) in schedule.actions.iter().enumerate() {
+            if progress.state(action_idx) != ActionState::Done {
+                continue;
+            }
             let net_cargo = action.action.net_cargo();
             if let Some((good, amount)) = net_cargo {
                 *expected_cargo.entry(good).or_insert(0) += amount;
@@ -88,35 +299,44 @@ pub async fn run(
         };
         let cargo_correct_except_fuel = expected_cargo == cargo_without_fuel;
 
-        let next_action = schedule.actions.get(progress).unwrap();
+        let next_action = schedule.actions.get(next_idx).unwrap();
         if let Some((good, amount)) = next_action.action.net_cargo() {
             *expected_cargo.entry(good).or_insert(0) += amount;
         }
         expected_cargo.retain(|_, &mut v| v != 0);
         let cargo_correct1 = expected_cargo == ship_controller.cargo_map();
 
-        let mut actions_to_skip = 0usize;
-        if !cargo_correct {
+        // an interrupted action may have taken effect before its finish was saved
+        if cargo_correct1 && !cargo_correct {
+            info!(
+                "Ship {} action {:?} ({:?}) already took effect. Marking it done.",
+                ship_symbol,
+                next_action,
+                progress.state(next_idx)
+            );
+            finish_action(
+                &db,
+                &ship_controller,
+                &taskmanager,
+                &mut progress,
+                &schedule,
+                next_idx,
+            )
+            .await;
+        } else if !cargo_correct {
             warn!(
                 "Ship {} cargo is incorrect. Expected: {:?}, Actual: {:?}",
                 ship_controller.symbol(),
                 expected_cargo,
                 ship_controller.cargo_map()
             );
-            if cargo_correct1 {
-                info!(
-                    "Ship {} cargo would be correct after performing 1 action {:?}. Skipping action.",
-                    ship_controller.symbol(),
-                    next_action
-                );
-                actions_to_skip = 1;
-            } else if cargo_correct_except_fuel {
+            if cargo_correct_except_fuel {
                 info!(
                     "Ship {} cargo would be correct after dropping excess fuel.",
                     ship_controller.symbol(),
                 );
                 let units = ship_controller.cargo_good_count("FUEL");
-                ship_controller.sell_goods("FUEL", units, false).await;
+                ship_controller.sell_goods("FUEL", units, false).await?;
             } else {
                 // ship_controller.sell_goods("FABRICS", 4).await; // manual fix
                 panic!("Couldn't recover cargo state");
@@ -124,24 +344,140 @@ pub async fn run(
         }
 
         // execute
-        for (action_idx, scheduled_action) in schedule.actions.iter().enumerate().skip(progress) {
+        let start_idx = progress.next().unwrap_or(schedule_len);
+        for (action_idx, scheduled_action) in schedule.actions.iter().enumerate().skip(start_idx) {
+            // the delivery of an abandoned buy
+            if progress.state(action_idx) == ActionState::Skipped {
+                continue;
+            }
             ship_controller
-                .goto_waypoint(&scheduled_action.waypoint)
-                .await;
-            // perform action
-            if actions_to_skip == 0 {
-                ship_controller
-                    .execute_action(&scheduled_action.action)
+                .goto_waypoint_with(
+                    &scheduled_action.waypoint,
+                    schedule.urgency(action_idx, CONFIG.urgent_task_value),
+                )
+                .await?;
+            // repeat trips wait for the market to restock
+            if let Some(not_before) = scheduled_action.not_before {
+                let wait_time = not_before - ship_controller.universe.now();
+                if wait_time > Duration::zero() {
+                    debug!(
+                        "Ship {} waiting {}s for restock before {:?}",
+                        ship_symbol,
+                        wait_time.num_seconds(),
+                        scheduled_action.action
+                    );
+                    let wait = tokio::time::sleep(wait_time.to_std().unwrap());
+                    telemetry::in_span(
+                        "wait_restock",
+                        vec![KeyValue::new("ship", ship_symbol.clone())],
+                        wait,
+                    )
                     .await;
-            } else {
-                actions_to_skip -= 1;
+                }
+            }
+            // A large buy whose price spiked since planning is dropped with its delivery, and the
+            // ship plans again with the fresh prices as soon as nothing else is in its hold
+            if scheduled_action.price_guarded(CONFIG.price_guard_min_value) {
+                ship_controller.refresh_market().await?;
+                let Action::BuyGoods(good, _) = &scheduled_action.action else {
+                    unreachable!()
+                };
+                let price = purchase_price(&ship_controller, &scheduled_action.waypoint, good)
+                    .await
+                    .unwrap_or(i64::MAX);
+                if scheduled_action.price_spiked(price, CONFIG.price_guard_increase) {
+                    warn!(
+                        "Ship {} abandoning purchase of {}: price {} was {} when planned",
+                        ship_symbol,
+                        good,
+                        price,
+                        scheduled_action.planned_price.unwrap()
+                    );
+                    let now = ship_controller.universe.now();
+                    progress.skip(action_idx, now);
+                    if let Some(delivery) = schedule.delivery_of(action_idx) {
+                        progress.skip(delivery, now);
+                        let task = schedule.actions[delivery].task_completed.clone().unwrap();
+                        taskmanager.release_tasks(&[task]).await;
+                    }
+                    ok_or_warn(
+                        db.save_schedule_progress(&ship_symbol, &progress).await,
+                        "save schedule progress",
+                    );
+                    if ship_controller.cargo_empty() {
+                        abandon_schedule(
+                            &db,
+                            &ship_controller,
+                            &taskmanager,
+                            &mut progress,
+                            &schedule,
+                            action_idx + 1,
+                        )
+                        .await;
+                        break;
+                    }
+                    continue;
+                }
             }
+            // log the action starting and finishing, so we can resume from this point if we crash
+            progress.start(action_idx, ship_controller.universe.now());
+            ok_or_warn(
+                db.save_schedule_progress(&ship_symbol, &progress).await,
+                "save schedule progress",
+            );
+            let sell = match &scheduled_action.action {
+                Action::BuyGoods(_, _) => sell_quote(&ship_controller, &schedule, action_idx).await,
+                _ => None,
+            };
+            let construction_buy = match construction_good(&schedule, action_idx) {
+                Some(good) => Some((
+                    good,
+                    ship_controller.cargo_good_count(good),
+                    purchase_price(&ship_controller, &scheduled_action.waypoint, good).await,
+                )),
+                None => None,
+            };
+            ship_controller
+                .execute_action(&scheduled_action.action, sell.as_ref())
+                .await?;
+            if let Some((good, held, Some(price))) = construction_buy {
+                let bought = ship_controller.cargo_good_count(good) - held;
+                ship_controller
+                    .agent_controller
+                    .ledger
+                    .register_spend(CONSTRUCTION_SPEND, bought * price);
+            }
+            finish_action(
+                &db,
+                &ship_controller,
+                &taskmanager,
+                &mut progress,
+                &schedule,
+                action_idx,
+            )
+            .await;
 
-            // log action completion, so we can resume from this point if we crash
-            db.update_schedule_progress(&ship_symbol, action_idx + 1)
+            // Safe point to drop the rest of the schedule for an urgent task
+            let remaining = &schedule.actions[action_idx + 1..];
+            if !remaining.is_empty()
+                && ship_controller.cargo_empty()
+                && taskmanager.preemption_requested(&ship_symbol)
+            {
+                info!(
+                    "Ship {} preempted, abandoning {} queued actions",
+                    ship_symbol,
+                    remaining.len()
+                );
+                abandon_schedule(
+                    &db,
+                    &ship_controller,
+                    &taskmanager,
+                    &mut progress,
+                    &schedule,
+                    action_idx + 1,
+                )
                 .await;
-            if let Some(task) = &scheduled_action.task_completed {
-                taskmanager.set_task_completed(task).await;
+                break;
             }
         }
         info!(
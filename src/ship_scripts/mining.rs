@@ -1,10 +1,16 @@
 use std::cmp::min;
 
 use crate::api_client::api_models::WaypointDetailed;
+use crate::api_client::ApiError;
 use crate::models::MarketType::*;
-use crate::ship_controller::ShipController;
+use crate::ship_controller::{ExtractResult, ShipController};
 use crate::universe::WaypointFilter;
-use crate::{db::DbClient, models::*};
+use crate::{
+    db::{load_until_ok, ok_or_warn, DbClient},
+    models::*,
+};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
 use lazy_static::lazy_static;
 use log::*;
 use serde::{Deserialize, Serialize};
@@ -55,28 +61,180 @@ async fn engineered_asteroid_location(ship: &ShipController) -> WaypointSymbol {
     waypoints[0].symbol.clone()
 }
 
-pub async fn run_surveyor(ship: ShipController) {
+// Minimum number of extractions before we trust an asteroid's yield
+const MIN_YIELD_SAMPLES: i64 = 20;
+const TARGET_REEVALUATE_INTERVAL_MINS: i64 = 60;
+// One in this many targets samples an asteroid without enough yield data, so better ones can be found
+const EXPLORE_EVERY_TARGETS: i64 = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MiningTarget {
+    waypoint: WaypointSymbol,
+    timestamp: DateTime<Utc>,
+    // targets chosen since the last one that sampled an asteroid
+    #[serde(default)]
+    since_exploration: i64,
+}
+
+lazy_static! {
+    // (reset, system) -> the mining_target/{system} value, so the loops don't read it every iteration
+    static ref MINING_TARGETS: DashMap<(String, SystemSymbol), MiningTarget> = DashMap::new();
+}
+
+// Realized sell value per hour of drone time, based on extraction history
+async fn asteroid_value_per_hour(ship: &ShipController, asteroid: &WaypointSymbol) -> Option<f64> {
+    let asteroid_yield = ship
+        .agent_controller
+        .survey_manager
+        .get_yield(asteroid)
+        .await;
+    if asteroid_yield.extractions < MIN_YIELD_SAMPLES || asteroid_yield.cooldown_seconds == 0 {
+        return None;
+    }
+    let mut value = 0;
+    for (good, units) in &asteroid_yield.goods {
+        if !SELL_GOODS.contains(&good.as_str()) {
+            continue;
+        }
+        let Some(market_symbol) = sell_location(ship, good).await else {
+            continue;
+        };
+        let Some(market) = ship.universe.get_market(&market_symbol).await else {
+            continue;
+        };
+        let trade = market.data.trade_goods.iter().find(|g| g.symbol == *good);
+        value += trade.map(|t| t.sell_price * units).unwrap_or(0);
+    }
+    Some(value as f64 * 3600.0 / asteroid_yield.cooldown_seconds as f64)
+}
+
+// The asteroid the mining operation in this system should work.
+// Shared by surveyors, drones and shuttles so they stay at the same location
+async fn mining_location(ship: &ShipController, db: &DbClient) -> WaypointSymbol {
+    let key = format!("mining_target/{}", ship.system());
+    let cache_key = (db.reset_date().to_string(), ship.system());
+    let now = Utc::now();
+    let current: Option<MiningTarget> = match MINING_TARGETS.get(&cache_key) {
+        Some(target) => Some(target.clone()),
+        None => ok_or_warn(db.get_value(&key).await, "load mining target").flatten(),
+    };
+    if let Some(current) = &current {
+        if now - current.timestamp < Duration::try_minutes(TARGET_REEVALUATE_INTERVAL_MINS).unwrap()
+        {
+            return current.waypoint.clone();
+        }
+    }
+
+    // modifiers change over time, so pick up the current state before choosing
+    ship.universe.refresh_system_waypoints(&ship.system()).await;
+    let engineered = engineered_asteroid_location(ship).await;
+    let asteroids = ship
+        .universe
+        .search_waypoints(
+            &ship.system(),
+            &[WaypointFilter::Asteroid, WaypointFilter::NotHazardous],
+        )
+        .await;
+    let engineered_safe = asteroids.iter().any(|a| a.symbol == engineered);
+    if !engineered_safe {
+        warn!("Engineered asteroid {} is hazardous", engineered);
+    }
+    let mut best = match engineered_safe {
+        true => (
+            engineered.clone(),
+            asteroid_value_per_hour(ship, &engineered).await,
+        ),
+        false => (engineered.clone(), None),
+    };
+    let mut best_safe = engineered_safe;
+    let mut unsampled = Vec::new();
+    if engineered_safe && best.1.is_none() {
+        unsampled.push(engineered.clone());
+    }
+    for asteroid in asteroids {
+        if asteroid.symbol == engineered {
+            continue;
+        }
+        let value = asteroid_value_per_hour(ship, &asteroid.symbol).await;
+        if value.is_none() {
+            unsampled.push(asteroid.symbol.clone());
+        }
+        let better = match (value, best.1) {
+            (Some(value), Some(best_value)) => value > best_value,
+            (Some(_), None) => true,
+            // no yield data yet, only worth it to get off a hazardous asteroid
+            (None, _) => !best_safe,
+        };
+        if better {
+            best = (asteroid.symbol.clone(), value);
+            best_safe = true;
+        }
+    }
+    let (mut waypoint, mut value) = best;
+    let mut since_exploration = current
+        .as_ref()
+        .map(|c| c.since_exploration + 1)
+        .unwrap_or(0);
+    // Unsampled asteroids are never chosen over a known yield, so give them a turn now and then
+    if value.is_some() && since_exploration >= EXPLORE_EVERY_TARGETS {
+        if let Some(asteroid) = unsampled.into_iter().find(|a| *a != waypoint) {
+            waypoint = asteroid;
+            value = None;
+            since_exploration = 0;
+        }
+    }
+    if current
+        .as_ref()
+        .map(|c| c.waypoint != waypoint)
+        .unwrap_or(true)
+    {
+        info!(
+            "Mining target for {} is {} ({:?} per hour)",
+            ship.system(),
+            waypoint,
+            value
+        );
+    }
+    let target = MiningTarget {
+        waypoint: waypoint.clone(),
+        timestamp: now,
+        since_exploration,
+    };
+    ok_or_warn(db.set_value(&key, &target).await, "save mining target");
+    MINING_TARGETS.insert(cache_key, target);
+    waypoint
+}
+
+pub async fn run_surveyor(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script surveyor for {}", ship.symbol());
     ship.wait_for_transit().await;
 
-    let asteroid_location = engineered_asteroid_location(&ship).await;
-    ship.goto_waypoint(&asteroid_location).await;
-
     loop {
+        if ship.transfer_requested() {
+            info!("Surveyor {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
+        let asteroid_location = mining_location(&ship, &db).await;
+        ship.goto_waypoint(&asteroid_location).await?;
         // Automatically pushes to the survey manager
-        ship.survey().await;
+        ship.survey().await?;
     }
 }
 
-pub async fn run_mining_drone(ship: ShipController) {
+pub async fn run_mining_drone(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script extraction_drone for {}", ship.symbol());
     ship.wait_for_transit().await;
 
-    let asteroid_location = engineered_asteroid_location(&ship).await;
-    ship.goto_waypoint(&asteroid_location).await;
-
     loop {
-        let should_extract = ship.cargo_space_available() >= 4;
+        if ship.transfer_requested() && ship.cargo_empty() {
+            info!("Mining drone {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
+        let asteroid_location = mining_location(&ship, &db).await;
+        ship.goto_waypoint(&asteroid_location).await?;
+
+        // unload to a shuttle before a transfer
+        let should_extract = ship.cargo_space_available() >= 4 && !ship.transfer_requested();
         if should_extract {
             // wait for cooldown before taking survey, helps to get a non-exhausted one
             ship.wait_for_cooldown().await;
@@ -93,23 +251,35 @@ pub async fn run_mining_drone(ship: ShipController) {
                     continue;
                 }
             };
-            ship.extract_survey(&survey).await;
+            if ship.extract_survey(&survey).await? == ExtractResult::CargoFull {
+                await_transfer(&ship).await?;
+                continue;
+            }
 
-            // jettison
+            // jettison anything we don't sell, other asteroids yield goods outside JETTISON_GOODS
             for (cargo, units) in ship.cargo_map() {
-                if JETTISON_GOODS.contains(&cargo.as_str()) {
-                    ship.jettison_cargo(&cargo, units).await;
+                if !SELL_GOODS.contains(&cargo.as_str()) {
+                    ship.jettison_cargo(&cargo, units).await?;
                 }
             }
         } else {
             // transfer goods to shuttle, and wait till completed
             debug!("Mining drone transfer initiated");
-            ship.transfer_cargo().await;
+            ship.transfer_cargo().await?;
             debug!("Mining drone transfer completed");
         }
     }
 }
 
+// Unload a drone that filled up before its next extraction, ahead of the drones waiting normally
+pub async fn await_transfer(ship: &ShipController) -> Result<(), ApiError> {
+    ship.set_state_description("Awaiting transfer").await;
+    debug!("{} cargo full, awaiting transfer", ship.symbol());
+    ship.transfer_cargo_priority().await?;
+    ship.set_state_description("Extracting").await;
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 enum MiningShuttleState {
     Loading,
@@ -122,31 +292,37 @@ lazy_static! {
     static ref JETTISON_GOODS: Vec<&'static str> = vec!["ICE_WATER", "ALUMINUM_ORE",];
 }
 
-pub async fn run_shuttle(ship: ShipController, db: DbClient) {
+pub async fn run_shuttle(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script extraction shuttle for {}", ship.symbol());
     ship.wait_for_transit().await;
 
-    let asteroid_location = engineered_asteroid_location(&ship).await;
-
     let key = format!("extract_shuttle_state/{}", ship.symbol());
-    let mut state: MiningShuttleState = db.get_value(&key).await.unwrap_or(Loading);
+    let mut state: MiningShuttleState = load_until_ok("load ship state", || db.get_value(&key))
+        .await
+        .unwrap_or(Loading);
 
     loop {
         match state {
             Loading => {
+                // between sales and the next load
+                if ship.cargo_empty() && ship.transfer_requested() {
+                    info!("Mining shuttle {} exiting for transfer", ship.symbol());
+                    return Ok(());
+                }
                 if ship.cargo_space_available() == 0 {
                     state = Selling;
-                    db.set_value(&key, &state).await;
+                    ok_or_warn(db.set_value(&key, &state).await, "save script state");
                     continue;
                 }
-                ship.goto_waypoint(&asteroid_location).await;
-                ship.orbit().await;
-                ship.receive_cargo().await;
+                let asteroid_location = mining_location(&ship, &db).await;
+                ship.goto_waypoint(&asteroid_location).await?;
+                ship.orbit().await?;
+                ship.receive_cargo().await?;
             }
             Selling => {
                 if ship.cargo_empty() {
                     state = Loading;
-                    db.set_value(&key, &state).await;
+                    ok_or_warn(db.set_value(&key, &state).await, "save script state");
                     continue;
                 }
                 // !! a smarter selling order would be good here:
@@ -156,24 +332,34 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient) {
                         let sell_location = sell_location(&ship, &cargo.symbol).await;
                         match sell_location {
                             Some(sell_location) => {
-                                ship.goto_waypoint(&sell_location).await;
-                                ship.refresh_market().await;
+                                ship.goto_waypoint(&sell_location).await?;
+                                ship.refresh_market().await?;
                                 while ship.cargo_good_count(&cargo.symbol) != 0 {
                                     let holding = ship.cargo_good_count(&cargo.symbol);
-                                    let market =
-                                        ship.universe.get_market(&sell_location).await.unwrap();
-                                    let market_good = market
-                                        .data
-                                        .trade_goods
-                                        .iter()
-                                        .find(|g| g.symbol == cargo.symbol)
-                                        .unwrap();
+                                    let market = ship.universe.get_market(&sell_location).await;
+                                    let market_good = market.as_ref().and_then(|market| {
+                                        market
+                                            .data
+                                            .trade_goods
+                                            .iter()
+                                            .find(|g| g.symbol == cargo.symbol)
+                                    });
+                                    // the refresh failed or the good is no longer listed, pick a market again
+                                    let Some(market_good) = market_good else {
+                                        warn!(
+                                            "{} not listed at {}. Retry in 60 seconds.",
+                                            cargo.symbol, sell_location
+                                        );
+                                        tokio::time::sleep(tokio::time::Duration::from_secs(60))
+                                            .await;
+                                        break;
+                                    };
                                     let units = min(market_good.trade_volume, holding);
                                     assert!(units > 0);
-                                    ship.sell_goods(&cargo.symbol, units, false).await;
+                                    ship.sell_goods(&cargo.symbol, units, false).await?;
                                     let new_units = ship.cargo_good_count(&cargo.symbol);
                                     assert!(new_units == holding - units);
-                                    ship.refresh_market().await;
+                                    ship.refresh_market().await?;
                                 }
                             }
                             None => {
@@ -186,7 +372,7 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient) {
                             }
                         }
                     } else if JETTISON_GOODS.contains(&cargo.symbol.as_str()) {
-                        ship.jettison_cargo(&cargo.symbol, cargo.units).await;
+                        ship.jettison_cargo(&cargo.symbol, cargo.units).await?;
                     } else {
                         panic!("Unexpected cargo: {}", cargo.symbol);
                     }
@@ -1,4 +1,5 @@
 pub mod construction;
+pub mod custom;
 pub mod exploration;
 pub mod logistics;
 pub mod mining;
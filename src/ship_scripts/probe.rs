@@ -1,27 +1,108 @@
-use crate::{models::ProbeScriptConfig, ship_controller::ShipController};
+use crate::api_client::api_models::WaypointDetailed;
+use crate::api_client::ApiError;
+use crate::models::{ProbeScriptConfig, WaypointSymbol};
+use crate::ship_controller::ShipController;
 use chrono::{DateTime, Duration, Utc};
 use lazy_static::lazy_static;
 use log::*;
+use std::cmp::max;
 use std::ops::Add as _;
 
 lazy_static! {
     static ref MARKET_REFRESH_INTERVAL: Duration = Duration::try_minutes(6).unwrap();
+    // markets no hauler is using are still refreshed occasionally, so new trades can be found
+    static ref IDLE_MARKET_REFRESH_INTERVAL: Duration = Duration::try_minutes(30).unwrap();
     static ref SHIPYARD_REFRESH_INTERVAL: Duration = Duration::try_minutes(60).unwrap();
+    static ref ROAMING_CYCLE_INTERVAL: Duration = Duration::try_minutes(15).unwrap();
 }
 
-pub async fn run(ship_controller: ShipController, config: &ProbeScriptConfig) {
+// Refreshes are low value, probes wait while the API error rate is over budget. Returns true if
+// the probe should check back, e.g. for a transfer, before carrying on
+async fn paused_for_degradation(ship: &ShipController) -> bool {
+    let mut degraded = ship.api_degraded();
+    if !*degraded.borrow() {
+        return false;
+    }
+    debug!("Probe {} paused while the API is degraded", ship.symbol());
+    let _ = tokio::time::timeout(
+        std::time::Duration::from_secs(60),
+        degraded.wait_for(|degraded| !*degraded),
+    )
+    .await;
+    true
+}
+
+fn market_refresh_interval(ship: &ShipController, waypoint_symbol: &WaypointSymbol) -> Duration {
+    let task_manager = &ship.agent_controller.task_manager;
+    match task_manager.market_has_consumers(waypoint_symbol) {
+        true => *MARKET_REFRESH_INTERVAL,
+        false => *IDLE_MARKET_REFRESH_INTERVAL,
+    }
+}
+
+async fn next_market_refresh(
+    ship: &ShipController,
+    waypoint_symbol: &WaypointSymbol,
+) -> Option<DateTime<Utc>> {
+    let market = ship.universe.get_market(waypoint_symbol).await;
+    market.map(|market| market.timestamp + market_refresh_interval(ship, waypoint_symbol))
+}
+
+// Tell the task manager when this cycle (or a later one) will refresh each market,
+// so haulers aren't paid to refresh them first
+async fn publish_cycle_etas(
+    ship: &ShipController,
+    waypoints: &[WaypointDetailed],
+    cycle_start: DateTime<Utc>,
+) {
+    let matrix = ship
+        .universe
+        .estimate_duration_matrix(&ship.system(), ship.engine_speed(), ship.fuel_capacity())
+        .await;
+    let mut eta = cycle_start;
+    let mut position = ship.waypoint();
+    for waypoint in waypoints {
+        let next_refresh = next_market_refresh(ship, &waypoint.symbol).await;
+        let waypoint_eta = match next_refresh {
+            Some(next_refresh) if next_refresh > cycle_start && !waypoint.is_shipyard() => {
+                max(next_refresh, cycle_start + *ROAMING_CYCLE_INTERVAL)
+            }
+            _ => {
+                let travel = matrix
+                    .get(&position)
+                    .and_then(|dests| dests.get(&waypoint.symbol))
+                    .copied()
+                    .unwrap_or(0);
+                eta += Duration::try_seconds(travel).unwrap();
+                position = waypoint.symbol.clone();
+                eta
+            }
+        };
+        ship.agent_controller
+            .task_manager
+            .set_probe_eta(&waypoint.symbol, waypoint_eta);
+    }
+}
+
+pub async fn run(
+    ship_controller: ShipController,
+    config: &ProbeScriptConfig,
+) -> Result<(), ApiError> {
     if config.waypoints.len() == 1 {
-        probe_single_location(ship_controller, config).await;
+        probe_single_location(ship_controller, config).await
     } else {
-        probe_multiple_locations(ship_controller, config).await;
+        probe_multiple_locations(ship_controller, config).await
     }
 }
 
 // Roaming refresh logic is less rate limit efficient
-// - doesn't take into account whether the market has been refreshed recently
+// - only skips markets that have been refreshed recently if no hauler is using them
 // - uses extra api requests to move between waypoints
 // Additionally, cannot be used to buy ships
-pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScriptConfig) {
+pub async fn probe_multiple_locations(
+    ship: ShipController,
+    config: &ProbeScriptConfig,
+) -> Result<(), ApiError> {
     assert_eq!(config.refresh_market, true);
 
     let waypoint_symbols = config
@@ -48,21 +129,49 @@ pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScript
     tokio::time::sleep(tokio::time::Duration::from_secs(rand_start_sleep)).await;
     let mut last_cycle_start: Option<DateTime<Utc>> = None;
     loop {
+        if ship.transfer_requested() {
+            info!("Probe {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
+        if paused_for_degradation(&ship).await {
+            continue;
+        }
         if let Some(last_cycle_start) = last_cycle_start {
-            let sleep_duration =
-                last_cycle_start + Duration::try_minutes(15).unwrap() - chrono::Utc::now();
+            let sleep_duration = last_cycle_start + *ROAMING_CYCLE_INTERVAL - ship.universe.now();
             if sleep_duration > Duration::zero() {
                 debug!("Sleeping for {:.3}s", sleep_duration.num_seconds() as f64);
                 tokio::time::sleep(sleep_duration.to_std().unwrap()).await;
             }
         }
-        last_cycle_start = Some(chrono::Utc::now());
-        for waypoint in &waypoints {
-            ship.goto_waypoint(&waypoint.symbol).await;
-            ship.refresh_market().await;
+        let cycle_start = ship.universe.now();
+        last_cycle_start = Some(cycle_start);
+        // borrowed by a shipyard whose probe is missing
+        let mut cycle = waypoints.clone();
+        let cover = ship
+            .agent_controller
+            .get_probe_shipyard_reservation(&ship.ship_symbol)
+            .await;
+        if let Some(shipyard) = cover {
+            if !cycle.iter().any(|w| w.symbol == shipyard) {
+                debug!("Probe {} covering shipyard {}", ship.symbol(), shipyard);
+                cycle.push(ship.universe.detailed_waypoint(&shipyard).await);
+            }
+        }
+        publish_cycle_etas(&ship, &cycle, cycle_start).await;
+        for waypoint in &cycle {
+            // skip markets that are not due a refresh, markets no hauler uses are due less often
+            let next_refresh = next_market_refresh(&ship, &waypoint.symbol).await;
+            if next_refresh.is_some_and(|next_refresh| next_refresh > ship.universe.now())
+                && !waypoint.is_shipyard()
+            {
+                debug!("Skipping refresh of market {}", waypoint.symbol);
+                continue;
+            }
+            ship.goto_waypoint(&waypoint.symbol).await?;
+            ship.refresh_market().await?;
 
             if waypoint.is_shipyard() {
-                ship.refresh_shipyard().await;
+                ship.refresh_shipyard().await?;
 
                 // // Try to buy ships (DISABLED)
                 // info!("Starting routine buy task for probe {}", ship.ship_symbol);
@@ -83,7 +192,10 @@ pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScript
 
 // Sit at a single location, refreshing market and shipyards (when needed)
 // capable of being used to buy ships
-pub async fn probe_single_location(ship_controller: ShipController, config: &ProbeScriptConfig) {
+pub async fn probe_single_location(
+    ship_controller: ShipController,
+    config: &ProbeScriptConfig,
+) -> Result<(), ApiError> {
     assert_eq!(config.waypoints.len(), 1);
     let waypoint_symbol = &config.waypoints[0];
     info!(
@@ -108,16 +220,16 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
             .universe
             .get_jumpgate(&waypoint.system_symbol)
             .await;
-        ship_controller.goto_waypoint(&jumpgate_src).await;
+        ship_controller.goto_waypoint(&jumpgate_src).await?;
         // jump to correct system
-        ship_controller.jump(&jumpgate_dest).await;
+        ship_controller.jump(&jumpgate_dest).await?;
     }
 
-    ship_controller.goto_waypoint(waypoint_symbol).await;
-    ship_controller.dock().await; // don't need to dock, but do so anyway to clear 'InTransit' status
+    ship_controller.goto_waypoint(waypoint_symbol).await?;
+    ship_controller.dock().await?; // don't need to dock, but do so anyway to clear 'InTransit' status
 
     if !config.refresh_market {
-        return;
+        return Ok(());
     }
 
     // Random sleep for a gentler startup
@@ -125,17 +237,30 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
     tokio::time::sleep(tokio::time::Duration::from_secs(rand_start_sleep)).await;
 
     loop {
-        let now = chrono::Utc::now();
+        if ship_controller.transfer_requested() {
+            info!("Probe {} exiting for transfer", ship_controller.symbol());
+            return Ok(());
+        }
+        if paused_for_degradation(&ship_controller).await {
+            continue;
+        }
+        let now = ship_controller.universe.now();
         let mut next: DateTime<Utc> = now + Duration::try_minutes(15).unwrap();
         if waypoint.is_market() {
             let market = ship_controller.universe.get_market(waypoint_symbol).await;
             let next_refresh = match market {
-                Some(market) => market.timestamp.add(*MARKET_REFRESH_INTERVAL),
+                Some(market) => market
+                    .timestamp
+                    .add(market_refresh_interval(&ship_controller, waypoint_symbol)),
                 None => now,
             };
+            ship_controller
+                .agent_controller
+                .task_manager
+                .set_probe_eta(waypoint_symbol, next_refresh);
             if next_refresh <= now {
                 debug!("Refreshing market {}", waypoint_symbol);
-                ship_controller.refresh_market().await;
+                ship_controller.refresh_market().await?;
             }
             next = std::cmp::min(next, next_refresh);
         }
@@ -148,7 +273,7 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
             };
             if next_refresh <= now {
                 debug!("Refreshing shipyard {}", waypoint_symbol);
-                ship_controller.refresh_shipyard().await;
+                ship_controller.refresh_shipyard().await?;
             }
             next = std::cmp::min(next, next_refresh);
         }
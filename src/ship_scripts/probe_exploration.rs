@@ -1,4 +1,4 @@
-use crate::{models::WaypointSymbol, ship_controller::ShipController};
+use crate::{api_client::ApiError, models::WaypointSymbol, ship_controller::ShipController};
 use log::*;
 use pathfinding::directed::dijkstra::dijkstra;
 use serde::{Deserialize, Serialize};
@@ -11,21 +11,30 @@ enum ExplorerState {
     Exit,
 }
 
-pub async fn run_jumpgate_probe(ship: ShipController) {
+pub async fn run_jumpgate_probe(ship: ShipController) -> Result<(), ApiError> {
     info!("Starting script jumpgate probe for {}", ship.symbol());
     ship.wait_for_transit().await;
 
     let mut state = Init;
 
     while state != Exit {
-        let next_state = tick(&ship, &state).await;
+        // between jumpgates
+        if ship.transfer_requested() {
+            info!("Jumpgate probe {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
+        let next_state = tick(&ship, &state).await?;
         if let Some(next_state) = next_state {
             state = next_state;
         }
     }
+    Ok(())
 }
 
-async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerState> {
+async fn tick(
+    ship: &ShipController,
+    state: &ExplorerState,
+) -> Result<Option<ExplorerState>, ApiError> {
     match state {
         Init => {
             // Could be existing reservation, or a new one
@@ -37,10 +46,10 @@ async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerSt
                 Some(target) => format!("Exploring jumpgate {}", target),
                 None => "No target".to_string(),
             };
-            ship.set_state_description(&desc);
+            ship.set_state_description(&desc).await;
             match target {
-                Some(target) => Some(Exploring(target)),
-                None => Some(Exit),
+                Some(target) => Ok(Some(Exploring(target))),
+                None => Ok(Some(Exit)),
             }
         }
         Exploring(target_jumpgate) => {
@@ -64,12 +73,12 @@ async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerSt
                 target_jumpgate, duration, path_str
             );
             debug!("{}", desc);
-            ship.set_state_description(&desc);
+            ship.set_state_description(&desc).await;
 
             // Execute route
-            ship.goto_waypoint(&start_jumpgate).await;
+            ship.goto_waypoint(&start_jumpgate).await?;
             for gate in path.iter().skip(1) {
-                ship.jump(&gate).await;
+                ship.jump(&gate).await?;
             }
             // Get connections
             assert_eq!(ship.waypoint(), *target_jumpgate);
@@ -81,7 +90,7 @@ async fn tick(ship: &ShipController, state: &ExplorerState) -> Option<ExplorerSt
             ship.agent_controller
                 .clear_probe_jumpgate_reservation(&ship.symbol())
                 .await;
-            Some(Init)
+            Ok(Some(Init))
         }
         Exit => {
             panic!("Invalid state");
@@ -1,16 +1,21 @@
 //!
 //! Scrap script for ships
 //!
-//! Navigate to closest shipyard and scrap the ship
+//! Sell any cargo with a known buyer, then navigate to closest shipyard and scrap the ship
+//! Ships leaving a stale job also sell their cargo here before being handed over
 //!
 
+use crate::api_client::ApiError;
 use crate::ship_controller::ShipController;
 use log::*;
+use std::cmp::min;
 
-pub async fn run(ship: ShipController) {
+pub async fn run(ship: ShipController) -> Result<(), ApiError> {
     info!("Starting script scrap for {}", ship.symbol());
     ship.wait_for_transit().await;
 
+    sell_cargo(&ship).await?;
+
     let system_symbol = ship.system();
     let waypoints = ship.universe.get_system_waypoints(&system_symbol).await;
     let shipyards = ship
@@ -30,10 +35,40 @@ pub async fn run(ship: ShipController) {
         Some(s) => s,
         None => {
             info!("No shipyard in system. Failed to scrap {}", ship.symbol());
-            return;
+            return Ok(());
         }
     };
 
-    ship.goto_waypoint(&shipyard.symbol).await;
-    ship.scrap().await;
+    ship.goto_waypoint(&shipyard.symbol).await?;
+    ship.scrap().await
+}
+
+// Salvage the cargo by selling each good at the best known market in the system
+pub async fn sell_cargo(ship: &ShipController) -> Result<(), ApiError> {
+    let valuation = ship.cargo_value().await;
+    if valuation.total == 0 {
+        return Ok(());
+    }
+    info!(
+        "{} salvaging cargo worth ${} before scrapping",
+        ship.symbol(),
+        valuation.total
+    );
+    for item in valuation.items {
+        let best_sell = match item.best_sell {
+            Some(best_sell) => best_sell,
+            None => {
+                info!("{} no known buyer for {}", ship.symbol(), item.symbol);
+                continue;
+            }
+        };
+        ship.goto_waypoint(&best_sell.market).await?;
+        let mut units = ship.cargo_good_count(&item.symbol);
+        while units > 0 {
+            let sell_units = min(units, best_sell.trade_volume);
+            ship.sell_goods(&item.symbol, sell_units, false).await?;
+            units -= sell_units;
+        }
+    }
+    ship.refresh_market().await
 }
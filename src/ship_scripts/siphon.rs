@@ -1,20 +1,21 @@
+use crate::api_client::ApiError;
+use crate::market_health::MarketHealthReport;
+use crate::models::MarketSupply::*;
+use crate::models::MarketType::*;
+use crate::models::{MarketTradeGood, WaypointSymbol};
+use crate::ship_controller::ExtractResult;
+use crate::ship_scripts::mining::await_transfer;
 use crate::{
-    db::DbClient, models::WaypointSymbol, ship_controller::ShipController, universe::WaypointFilter,
+    db::{load_until_ok, ok_or_warn, DbClient},
+    ship_controller::ShipController,
+    universe::WaypointFilter,
 };
-use lazy_static::lazy_static;
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet};
 use SiphonShuttleState::*;
 
-lazy_static! {
-    // The goods that can be siphoned from a gas giant
-    static ref SIPHON_YIELDS: Vec<String> = vec![
-        "LIQUID_NITROGEN".to_string(),
-        "LIQUID_HYDROGEN".to_string(),
-        "HYDROCARBON".to_string(),
-    ];
-}
-
 async fn siphon_location(ship: &ShipController) -> WaypointSymbol {
     let waypoints = ship
         .universe
@@ -24,34 +25,138 @@ async fn siphon_location(ship: &ShipController) -> WaypointSymbol {
     waypoints[0].symbol.clone()
 }
 
-async fn sell_location(ship: &ShipController) -> WaypointSymbol {
-    let filters = SIPHON_YIELDS
-        .iter()
-        .map(|good| WaypointFilter::Exchanges(good.to_string()))
-        .collect::<Vec<WaypointFilter>>();
-    let waypoints = ship
+// Sales stop at a market once its sell price has fallen this far below the price on arrival,
+// the rest of the cargo goes to the next best market
+const MAX_PRICE_DROP: f64 = 0.2;
+
+#[derive(Debug, Clone)]
+struct SellMarket {
+    symbol: WaypointSymbol,
+    trade_goods: Vec<MarketTradeGood>,
+    // seconds from the siphon location to the market and back
+    round_trip: i64,
+}
+
+async fn sell_markets(ship: &ShipController, siphon_location: &WaypointSymbol) -> Vec<SellMarket> {
+    let durations = ship
         .universe
-        .search_waypoints(&ship.system(), &filters)
+        .estimate_duration_matrix(&ship.system(), ship.engine_speed(), ship.fuel_capacity())
         .await;
-    assert!(waypoints.len() == 1);
-    waypoints[0].symbol.clone()
+    ship.universe
+        .get_system_markets(&ship.system())
+        .await
+        .into_iter()
+        .filter_map(|(_, market)| market)
+        .map(|market| {
+            let symbol = market.data.symbol.clone();
+            let round_trip =
+                durations[siphon_location][&symbol] + durations[&symbol][siphon_location];
+            SellMarket {
+                symbol,
+                trade_goods: market.data.trade_goods.clone(),
+                round_trip,
+            }
+        })
+        .collect()
+}
+
+// Credits per second of round trip for selling a trade volume of each cargo good at the market.
+// Unless dumping, imports that are already flooded, or that the health report flags as over-evolved,
+// are left alone so siphon output doesn't push a single market past what the task manager wants.
+fn market_value(
+    market: &SellMarket,
+    cargo: &BTreeMap<String, i64>,
+    health: Option<&MarketHealthReport>,
+    dump: bool,
+) -> f64 {
+    let credits = market
+        .trade_goods
+        .iter()
+        .filter(|trade| trade._type != Export)
+        .filter(|trade| {
+            dump || trade._type != Import
+                || (trade.supply <= Moderate
+                    && !health.is_some_and(|h| h.is_over_evolved(&market.symbol, &trade.symbol)))
+        })
+        .filter_map(|trade| {
+            let units = cargo.get(&trade.symbol)?;
+            Some(trade.sell_price * min(*units, trade.trade_volume))
+        })
+        .sum::<i64>();
+    credits as f64 / max(market.round_trip, 1) as f64
+}
+
+// The next market to sell at, skipping markets already visited this trip unless dumping
+fn select_market<'a>(
+    markets: &'a [SellMarket],
+    cargo: &BTreeMap<String, i64>,
+    health: Option<&MarketHealthReport>,
+    visited: &BTreeSet<WaypointSymbol>,
+    dump: bool,
+) -> Option<&'a WaypointSymbol> {
+    markets
+        .iter()
+        .filter(|market| dump || !visited.contains(&market.symbol))
+        .map(|market| (market, market_value(market, cargo, health, dump)))
+        .filter(|(_, value)| *value > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(market, _)| &market.symbol)
+}
+
+// Sell cargo at the market a trade volume at a time, stopping each good once its price has dropped
+// too far, unless dumping
+async fn sell_at_market(
+    ship: &ShipController,
+    market_symbol: &WaypointSymbol,
+    dump: bool,
+) -> Result<(), ApiError> {
+    ship.goto_waypoint(market_symbol).await?;
+    ship.refresh_market().await?;
+    for good in ship.cargo_map().into_keys() {
+        let mut arrival_price = None;
+        while ship.cargo_good_count(&good) != 0 {
+            let market = ship.universe.get_market(market_symbol).await.unwrap();
+            let Some(trade) = market.data.trade_goods.iter().find(|g| g.symbol == good) else {
+                break;
+            };
+            let arrival_price = *arrival_price.get_or_insert(trade.sell_price);
+            if !dump && (trade.sell_price as f64) < arrival_price as f64 * (1.0 - MAX_PRICE_DROP) {
+                debug!(
+                    "{} price at {} fell from {} to {}, selling the rest elsewhere",
+                    good, market_symbol, arrival_price, trade.sell_price
+                );
+                break;
+            }
+            let units = min(trade.trade_volume, ship.cargo_good_count(&good));
+            ship.sell_goods(&good, units, false).await?;
+            ship.refresh_market().await?;
+        }
+    }
+    Ok(())
 }
 
-pub async fn run_drone(ship: ShipController) {
+pub async fn run_drone(ship: ShipController) -> Result<(), ApiError> {
     info!("Starting script siphon_drone for {}", ship.symbol());
     ship.wait_for_transit().await;
 
     let siphon_location = siphon_location(&ship).await;
-    ship.goto_waypoint(&siphon_location).await;
+    ship.goto_waypoint(&siphon_location).await?;
 
     loop {
-        let should_siphon = ship.cargo_space_available() > 0;
+        if ship.transfer_requested() && ship.cargo_empty() {
+            info!("Siphon drone {} exiting for transfer", ship.symbol());
+            return Ok(());
+        }
+        // unload to a shuttle before a transfer
+        let should_siphon = ship.cargo_space_available() > 0 && !ship.transfer_requested();
         if should_siphon {
-            ship.siphon().await;
+            if ship.siphon().await? == ExtractResult::CargoFull {
+                await_transfer(&ship).await?;
+            }
         } else {
             // transfer goods to shuttle, and wait till completed
             debug!("Siphon drone transfer initiated");
-            ship.transfer_cargo().await;
+            ship.transfer_cargo().await?;
             debug!("Siphon drone transfer completed");
         }
     }
@@ -64,38 +169,145 @@ enum SiphonShuttleState {
     Selling,
 }
 
-pub async fn run_shuttle(ship: ShipController, db: DbClient) {
+pub async fn run_shuttle(ship: ShipController, db: DbClient) -> Result<(), ApiError> {
     info!("Starting script siphon_shuttle for {}", ship.symbol());
     ship.wait_for_transit().await;
 
     let siphon_location = siphon_location(&ship).await;
-    let sell_location = sell_location(&ship).await;
 
     let key = format!("siphon_shuttle_state/{}", ship.symbol());
-    let mut state: SiphonShuttleState = db.get_value(&key).await.unwrap_or(Loading);
+    let mut state: SiphonShuttleState = load_until_ok("load ship state", || db.get_value(&key))
+        .await
+        .unwrap_or(Loading);
+    // markets sold at since the last load
+    let mut visited = BTreeSet::new();
 
     loop {
         match state {
             Loading => {
+                // between sales and the next load
+                if ship.cargo_empty() && ship.transfer_requested() {
+                    info!("Siphon shuttle {} exiting for transfer", ship.symbol());
+                    return Ok(());
+                }
                 if ship.cargo_space_available() == 0 {
                     state = Selling;
-                    db.set_value(&key, &state).await;
+                    ok_or_warn(db.set_value(&key, &state).await, "save script state");
                     continue;
                 }
-                ship.goto_waypoint(&siphon_location).await;
-                ship.orbit().await;
-                ship.receive_cargo().await;
+                ship.goto_waypoint(&siphon_location).await?;
+                ship.orbit().await?;
+                ship.receive_cargo().await?;
             }
             Selling => {
                 if ship.cargo_empty() {
+                    visited.clear();
                     state = Loading;
-                    db.set_value(&key, &state).await;
+                    ok_or_warn(db.set_value(&key, &state).await, "save script state");
+                    continue;
+                }
+                let health = db.get_market_health(&ship.system()).await;
+                let health = ok_or_warn(health, "load market health").flatten();
+                let markets = sell_markets(&ship, &siphon_location).await;
+                let cargo = ship.cargo_map();
+                if let Some(market) =
+                    select_market(&markets, &cargo, health.as_ref(), &visited, false)
+                {
+                    visited.insert(market.clone());
+                    sell_at_market(&ship, market, false).await?;
                     continue;
                 }
-                ship.goto_waypoint(&sell_location).await;
-                ship.sell_all_cargo().await;
+                // every good market is saturated: dump the rest at the best price available
+                match select_market(&markets, &cargo, health.as_ref(), &visited, true) {
+                    Some(market) => sell_at_market(&ship, market, true).await?,
+                    None => {
+                        warn!(
+                            "No market buys the cargo of {}. Retry in 60 seconds.",
+                            ship.symbol()
+                        );
+                        tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+                    }
+                }
             }
         }
     }
     // info!("Finished script for {}", ship.symbol());
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{MarketActivity, MarketSupply, MarketType};
+
+    fn market(
+        symbol: &str,
+        round_trip: i64,
+        trades: &[(&str, MarketType, MarketSupply, i64)],
+    ) -> SellMarket {
+        SellMarket {
+            symbol: WaypointSymbol::new(symbol),
+            trade_goods: trades
+                .iter()
+                .map(|(good, _type, supply, price)| MarketTradeGood {
+                    symbol: good.to_string(),
+                    trade_volume: 20,
+                    _type: _type.clone(),
+                    supply: supply.clone(),
+                    activity: Some(MarketActivity::Weak),
+                    purchase_price: price + 10,
+                    sell_price: *price,
+                })
+                .collect(),
+            round_trip,
+        }
+    }
+
+    #[test]
+    fn test_select_market() {
+        let cargo = BTreeMap::from([
+            ("HYDROCARBON".to_string(), 40),
+            ("LIQUID_NITROGEN".to_string(), 20),
+        ]);
+        let near = market("X1-AB12-A1", 100, &[("HYDROCARBON", Import, Moderate, 50)]);
+        // better prices for both goods, but twice as far
+        let far = market(
+            "X1-AB12-B2",
+            200,
+            &[
+                ("HYDROCARBON", Import, Limited, 60),
+                ("LIQUID_NITROGEN", Exchange, Moderate, 60),
+            ],
+        );
+        let flooded = market("X1-AB12-C3", 10, &[("HYDROCARBON", Import, High, 40)]);
+        let markets = vec![near, far, flooded];
+        let none = BTreeSet::new();
+
+        // 20 * 120 / 200 beats 20 * 50 / 100, the flooded import is skipped
+        let selected = select_market(&markets, &cargo, None, &none, false);
+        assert_eq!(selected, Some(&WaypointSymbol::new("X1-AB12-B2")));
+        let visited = BTreeSet::from([WaypointSymbol::new("X1-AB12-B2")]);
+        let selected = select_market(&markets, &cargo, None, &visited, false);
+        assert_eq!(selected, Some(&WaypointSymbol::new("X1-AB12-A1")));
+
+        // the health report holds back an over-evolved import
+        let health = MarketHealthReport {
+            system: WaypointSymbol::new("X1-AB12-A1").system(),
+            timestamp: chrono::Utc::now(),
+            issues: vec![crate::market_health::HealthIssue::OverEvolved {
+                market: WaypointSymbol::new("X1-AB12-A1"),
+                good: "HYDROCARBON".to_string(),
+                trade_volume: 180,
+                initial_trade_volume: 60,
+            }],
+        };
+        assert_eq!(
+            select_market(&markets, &cargo, Some(&health), &visited, false),
+            None
+        );
+        // dumping takes whatever pays best, the flooded market is close by
+        assert_eq!(
+            select_market(&markets, &cargo, Some(&health), &visited, true),
+            Some(&WaypointSymbol::new("X1-AB12-C3"))
+        );
+    }
+}
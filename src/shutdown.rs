@@ -0,0 +1,24 @@
+//!
+//! Graceful shutdown on SIGINT/SIGTERM.
+//!
+//! Killing the process mid-step can leave a schedule saved without its progress, or an API call
+//! made without its result recorded. On a signal, in-flight API calls finish and new ones are held,
+//! ship scripts get a moment to save the result of their last call, then every agent cancels its
+//! scripts and writes its in-memory state (tasks, ledger goods, survey yields, cooldowns) to the
+//! database before the process exits. Scripts resume from the saved state on restart.
+//!
+use log::*;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+// Time for scripts to save the result of the API call they were in, before they're cancelled
+pub const SCRIPT_GRACE: Duration = Duration::from_secs(2);
+
+// Resolves on the first SIGINT (ctrl-c) or SIGTERM
+pub async fn requested() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("SIGINT received, shutting down"),
+        _ = sigterm.recv() => info!("SIGTERM received, shutting down"),
+    }
+}
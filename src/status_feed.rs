@@ -0,0 +1,186 @@
+//!
+//! Public status feed.
+//!
+//! A compact JSON summary of the agent (credits, ships, era, jump gate progress, best trade routes in
+//! progress), written periodically to STATUS_FEED_PATH and/or PUT to STATUS_FEED_URL, e.g. a presigned
+//! S3 URL. A static status page can read it without the web API server being exposed.
+//!
+use crate::agent_controller::{AgentController, AgentEra};
+use crate::config::CONFIG;
+use crate::logistics_planner::{Action, Task, TaskActions};
+use crate::models::{Construction, WaypointSymbol};
+use crate::universe::access::UniverseReader;
+use chrono::{DateTime, Utc};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+const TOP_ROUTES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GateProgress {
+    pub waypoint: WaypointSymbol,
+    pub fulfilled: i64,
+    pub required: i64,
+    pub is_complete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteSummary {
+    pub good: String,
+    pub src: WaypointSymbol,
+    pub dest: WaypointSymbol,
+    pub expected_profit: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusFeed {
+    pub callsign: String,
+    pub timestamp: DateTime<Utc>,
+    pub credits: i64,
+    pub ships: usize,
+    pub era: AgentEra,
+    pub gate: Option<GateProgress>,
+    pub top_routes: Vec<RouteSummary>,
+}
+
+pub fn gate_progress(construction: &Construction) -> GateProgress {
+    GateProgress {
+        waypoint: construction.symbol.clone(),
+        fulfilled: construction.materials.iter().map(|m| m.fulfilled).sum(),
+        required: construction.materials.iter().map(|m| m.required).sum(),
+        is_complete: construction.is_complete,
+    }
+}
+
+// Trade tasks by expected profit, best first
+pub fn top_routes<'a>(tasks: impl Iterator<Item = &'a Task>, n: usize) -> Vec<RouteSummary> {
+    let mut routes = tasks
+        .filter_map(|task| match &task.actions {
+            TaskActions::TransportCargo {
+                src,
+                dest,
+                src_action: Action::BuyGoods(good, _),
+                ..
+            } => Some(RouteSummary {
+                good: good.clone(),
+                src: src.clone(),
+                dest: dest.clone(),
+                expected_profit: task.value,
+            }),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    routes.sort_by_key(|r| -r.expected_profit);
+    routes.truncate(n);
+    routes
+}
+
+pub async fn build(
+    agent_controller: &AgentController,
+    universe: &dyn UniverseReader,
+) -> StatusFeed {
+    let agent = agent_controller.agent();
+    let gate = match universe.known_jumpgate(&agent.headquarters.system()) {
+        Some(jump_gate) => universe
+            .known_construction(&jump_gate)
+            .await
+            .and_then(|construction| construction.data.as_ref().map(gate_progress)),
+        None => None,
+    };
+    let in_progress = agent_controller.task_manager.in_progress_tasks();
+    let tasks = in_progress
+        .iter()
+        .map(|entry| entry.value().0.clone())
+        .collect::<Vec<_>>();
+    StatusFeed {
+        callsign: agent.symbol.clone(),
+        timestamp: Utc::now(),
+        credits: agent.credits,
+        ships: agent_controller.num_ships(),
+        era: agent_controller.state().era,
+        gate,
+        top_routes: top_routes(tasks.iter(), TOP_ROUTES),
+    }
+}
+
+async fn publish(feed: &StatusFeed) {
+    let body = serde_json::to_vec(feed).unwrap();
+    if let Some(path) = &CONFIG.status_feed_path {
+        // write then rename, so readers never see a partial file
+        let tmp_path = format!("{}.tmp", path);
+        let result = match tokio::fs::write(&tmp_path, &body).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to write status feed to {}: {}", path, e);
+        }
+    }
+    if let Some(url) = &CONFIG.status_feed_url {
+        let response = reqwest::Client::new()
+            .put(url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => warn!("Status feed upload failed: {}", response.status()),
+            Err(e) => warn!("Status feed upload failed: {}", e),
+        }
+    }
+}
+
+pub async fn run(agent_controller: AgentController, universe: Arc<dyn UniverseReader>) {
+    if CONFIG.status_feed_path.is_none() && CONFIG.status_feed_url.is_none() {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(CONFIG.status_feed_interval_secs));
+    loop {
+        interval.tick().await;
+        let feed = build(&agent_controller, universe.as_ref()).await;
+        publish(&feed).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_top_routes() {
+        let trade = |good: &str, value: i64| Task {
+            id: format!("trade_{}", good),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new("X1-AB12-A1"),
+                dest: WaypointSymbol::new("X1-AB12-B2"),
+                src_action: Action::BuyGoods(good.to_string(), 40),
+                dest_action: Action::SellGoods(good.to_string(), 40),
+            },
+            value,
+            not_before: None,
+        };
+        let tasks = [
+            trade("IRON", 5000),
+            Task {
+                id: "refresh".to_string(),
+                actions: TaskActions::VisitLocation {
+                    waypoint: WaypointSymbol::new("X1-AB12-A1"),
+                    action: Action::RefreshMarket,
+                },
+                value: 9000,
+                not_before: None,
+            },
+            trade("COPPER", 8000),
+            trade("FUEL", 100),
+        ];
+        let routes = top_routes(tasks.iter(), 2);
+        assert_eq!(
+            routes.iter().map(|r| r.good.as_str()).collect::<Vec<_>>(),
+            vec!["COPPER", "IRON"]
+        );
+        assert_eq!(routes[0].expected_profit, 8000);
+    }
+}
@@ -1,21 +1,67 @@
-use crate::db::DbClient;
+use crate::config::CONFIG;
+use crate::db::db_models::SurveyConsumption;
+use crate::db::{ok_or_warn, DbClient, DbError};
 use crate::models::{KeyedSurvey, Survey, WaypointSymbol};
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::sync::Mutex;
 
+// With shared surveys, how often an asteroid's surveys are reloaded to pick up other agents'
+const SHARED_RELOAD_SECONDS: i64 = 30;
+
+// Realized extraction yield at an asteroid
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AsteroidYield {
+    pub extractions: i64,
+    // total cooldown incurred by extractions, i.e. drone time spent
+    pub cooldown_seconds: i64,
+    pub goods: BTreeMap<String, i64>,
+}
+
+// Extractions with surveys of other agents, in both directions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SurveyBalance {
+    // extractions by other agents with our surveys
+    pub provided: i64,
+    // our extractions with other agents' surveys
+    pub received: i64,
+}
+
+pub fn survey_balance(consumption: &[SurveyConsumption], callsign: &str) -> SurveyBalance {
+    let mut balance = SurveyBalance::default();
+    for c in consumption.iter().filter(|c| c.owner != c.consumer) {
+        if c.owner == callsign {
+            balance.provided += c.extractions;
+        }
+        if c.consumer == callsign {
+            balance.received += c.extractions;
+        }
+    }
+    balance
+}
+
 pub struct SurveyManager {
     db: DbClient,
+    callsign: String,
+    // use the surveys of every agent sharing the database, not just our own
+    shared: bool,
     inner: Mutex<SurveyManagerInner>,
 }
 
 struct SurveyManagerInner {
     surveys: BTreeMap<WaypointSymbol, Vec<KeyedSurvey>>,
+    yields: BTreeMap<WaypointSymbol, AsteroidYield>,
+    reloaded: BTreeMap<WaypointSymbol, DateTime<Utc>>,
 }
 
 impl SurveyManager {
-    pub async fn new(db: &DbClient) -> Self {
-        let surveys = db.get_surveys().await;
+    pub async fn new(db: &DbClient, callsign: &str) -> Result<Self, DbError> {
+        let shared = CONFIG.shared_surveys;
+        let surveys = match shared {
+            true => db.get_surveys(None).await?,
+            false => db.get_surveys(Some(callsign)).await?,
+        };
         let surveys = surveys
             .into_iter()
             .fold(BTreeMap::new(), |mut map, survey| {
@@ -24,10 +70,37 @@ impl SurveyManager {
                     .push(survey);
                 map
             });
-        Self {
+        Ok(Self {
             db: db.clone(),
-            inner: Mutex::new(SurveyManagerInner { surveys }),
+            callsign: callsign.to_string(),
+            shared,
+            inner: Mutex::new(SurveyManagerInner {
+                surveys,
+                yields: BTreeMap::new(),
+                reloaded: BTreeMap::new(),
+            }),
+        })
+    }
+
+    // Pick up surveys other agents took at the asteroid, and drop those they used up
+    async fn reload_shared(&self, waypoint: &WaypointSymbol, now: DateTime<Utc>) {
+        let due = match self.inner.lock().unwrap().reloaded.get(waypoint) {
+            Some(reloaded) => {
+                *reloaded + Duration::try_seconds(SHARED_RELOAD_SECONDS).unwrap() < now
+            }
+            None => true,
+        };
+        if !due {
+            return;
         }
+        // keep the surveys we have, and try again next time
+        let stored = self.db.get_asteroid_surveys(waypoint).await;
+        let Some(surveys) = ok_or_warn(stored, &format!("reload surveys of {}", waypoint)) else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.surveys.insert(waypoint.clone(), surveys);
+        inner.reloaded.insert(waypoint.clone(), now);
     }
 
     pub async fn insert_surveys(&self, surveys: Vec<Survey>) {
@@ -36,9 +109,10 @@ impl SurveyManager {
             .map(|survey| KeyedSurvey {
                 uuid: uuid::Uuid::new_v4(),
                 survey,
+                owner: self.callsign.clone(),
             })
             .collect();
-        self.db.insert_surveys(&surveys).await;
+        ok_or_warn(self.db.insert_surveys(&surveys).await, "save surveys");
         let mut inner = self.inner.lock().unwrap();
         for survey in surveys {
             inner
@@ -62,7 +136,8 @@ impl SurveyManager {
                 // USELESS
                 "ALUMINUM_ORE" => 0.0,
                 "ICE_WATER" => 0.0,
-                _ => panic!("Unexpected deposit symbol: {}", deposit.symbol),
+                // other asteroids have deposits we don't use
+                _ => 0.0,
             };
         }
         score / survey.deposits.len() as f64
@@ -70,6 +145,9 @@ impl SurveyManager {
 
     pub async fn get_survey(&self, waypoint: &WaypointSymbol) -> Option<KeyedSurvey> {
         let now = chrono::Utc::now();
+        if self.shared {
+            self.reload_shared(waypoint, now).await;
+        }
         loop {
             // grab front
             let best = {
@@ -97,7 +175,10 @@ impl SurveyManager {
 
     pub async fn remove_survey(&self, survey: &KeyedSurvey) {
         log::debug!("Deleting survey {}", survey.uuid);
-        self.db.remove_survey(&survey.uuid).await;
+        ok_or_warn(
+            self.db.remove_survey(&survey.uuid).await,
+            &format!("delete survey {}", survey.uuid),
+        );
 
         let mut inner = self.inner.lock().unwrap();
         inner
@@ -107,4 +188,114 @@ impl SurveyManager {
                 v.retain(|s| s.uuid != survey.uuid);
             });
     }
+
+    // Account an extraction with the survey to the agent that took it
+    pub async fn record_survey_use(&self, survey: &KeyedSurvey, units: i64) {
+        ok_or_warn(
+            self.db
+                .record_survey_consumption(&survey.owner, &self.callsign, units)
+                .await,
+            "record survey use",
+        );
+    }
+
+    pub async fn get_yield(&self, waypoint: &WaypointSymbol) -> AsteroidYield {
+        self.load_yield(waypoint).await.unwrap_or_default()
+    }
+
+    // None if the stored yield couldn't be read. Not cached, so the next call reads it again
+    async fn load_yield(&self, waypoint: &WaypointSymbol) -> Option<AsteroidYield> {
+        if let Some(asteroid_yield) = self.inner.lock().unwrap().yields.get(waypoint) {
+            return Some(asteroid_yield.clone());
+        }
+        let stored = self
+            .db
+            .get_value(&format!("asteroid_yield/{}", waypoint))
+            .await;
+        let asteroid_yield: AsteroidYield =
+            ok_or_warn(stored, &format!("load yield of {}", waypoint))?.unwrap_or_default();
+        self.inner
+            .lock()
+            .unwrap()
+            .yields
+            .insert(waypoint.clone(), asteroid_yield.clone());
+        Some(asteroid_yield)
+    }
+
+    // Save the cached asteroid yields, at shutdown. Surveys are saved as they're taken and used
+    pub async fn flush(&self) {
+        let yields = self.inner.lock().unwrap().yields.clone();
+        for (waypoint, asteroid_yield) in yields {
+            ok_or_warn(
+                self.db
+                    .set_value(&format!("asteroid_yield/{}", waypoint), &asteroid_yield)
+                    .await,
+                &format!("save yield of {}", waypoint),
+            );
+        }
+    }
+
+    pub async fn record_extraction(
+        &self,
+        waypoint: &WaypointSymbol,
+        good: &str,
+        units: i64,
+        cooldown_seconds: i64,
+    ) {
+        // don't overwrite the stored samples with this one extraction
+        let Some(mut asteroid_yield) = self.load_yield(waypoint).await else {
+            return;
+        };
+        asteroid_yield.extractions += 1;
+        asteroid_yield.cooldown_seconds += cooldown_seconds;
+        *asteroid_yield.goods.entry(good.to_string()).or_insert(0) += units;
+        self.inner
+            .lock()
+            .unwrap()
+            .yields
+            .insert(waypoint.clone(), asteroid_yield.clone());
+        ok_or_warn(
+            self.db
+                .set_value(&format!("asteroid_yield/{}", waypoint), &asteroid_yield)
+                .await,
+            &format!("save yield of {}", waypoint),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_survey_balance() {
+        let consumption = |owner: &str, consumer: &str, extractions| SurveyConsumption {
+            owner: owner.to_string(),
+            consumer: consumer.to_string(),
+            extractions,
+            units: extractions * 10,
+        };
+        let consumption = vec![
+            consumption("A", "A", 50),
+            consumption("A", "B", 20),
+            consumption("A", "C", 5),
+            consumption("B", "A", 3),
+            consumption("B", "C", 7),
+        ];
+        // our own surveys don't count either way
+        assert_eq!(
+            survey_balance(&consumption, "A"),
+            SurveyBalance {
+                provided: 25,
+                received: 3
+            }
+        );
+        assert_eq!(
+            survey_balance(&consumption, "C"),
+            SurveyBalance {
+                provided: 0,
+                received: 12
+            }
+        );
+    }
 }
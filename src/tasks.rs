@@ -1,22 +1,34 @@
+use crate::agent_controller::goals::Goal;
+use crate::agent_controller::hauler_autoscaler::TaskBacklog;
 use crate::agent_controller::AgentController;
+use crate::alerts::ALERTS;
 use crate::api_client::api_models::WaypointDetailed;
-use crate::config::CONFIG;
-use crate::db::DbClient;
+use crate::clock::SharedClock;
+use crate::config::{GoodFilter, CONFIG};
+use crate::db::{ok_or_warn, DbClient, DbError};
+use crate::logistics_planner::market_impact::{self, ImpactModel, Listings, PlannedTrade};
 use crate::logistics_planner::plan::task_to_scheduled_action;
 use crate::logistics_planner::{
-    self, Action, LogisticShip, PlannerConstraints, ShipSchedule, Task, TaskActions,
+    self, Action, LogisticShip, PlannerConstraints, ScheduledAction, ShipSchedule, Task,
+    TaskActions,
 };
+use crate::market_health::{self, MarketHealthReport};
 use crate::models::MarketSupply::*;
 use crate::models::MarketType::*;
 use crate::models::*;
 use crate::models::{LogisticsScriptConfig, MarketActivity::*};
-use crate::universe::{Universe, WaypointFilter};
+use crate::telemetry;
+use crate::trade_volume::TradeVolumeModel;
+use crate::universe::transaction_costs::TradeSide;
+use crate::universe::{UniverseHandle, WaypointFilter};
 use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use log::*;
-use std::cmp::min;
-use std::collections::{BTreeMap, BTreeSet};
+use opentelemetry::KeyValue;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
 
 fn is_task_allowed(task: &Task, config: &LogisticsScriptConfig) -> bool {
     if let Some(waypoint_allowlist) = &config.waypoint_allowlist {
@@ -40,6 +52,9 @@ fn is_task_allowed(task: &Task, config: &LogisticsScriptConfig) -> bool {
             Action::TryBuyShips => config.allow_shipbuying,
             _ => true,
         },
+        TaskActions::TransportCargo { src, dest, .. } if src.system() != dest.system() => {
+            config.allow_gate_trades
+        }
         TaskActions::TransportCargo { dest_action, .. } => match dest_action {
             Action::DeliverConstruction(_, _) => config.allow_construction,
             _ => true,
@@ -47,36 +62,501 @@ fn is_task_allowed(task: &Task, config: &LogisticsScriptConfig) -> bool {
     }
 }
 
+// Trade tasks (buy then sell) are limited to the goods the filter allows
+fn is_trade_allowed(task: &Task, filter: &GoodFilter) -> bool {
+    match &task.actions {
+        TaskActions::TransportCargo {
+            src_action: Action::BuyGoods(good, _),
+            dest_action: Action::SellGoods(_, _),
+            ..
+        } => filter.allows(good),
+        _ => true,
+    }
+}
+
+// Trades for profit (buy then sell), dropped while the API is degraded. Deliveries, e.g. for
+// construction, are core work and carry on
+fn is_speculative(task: &Task) -> bool {
+    matches!(
+        &task.actions,
+        TaskActions::TransportCargo {
+            src_action: Action::BuyGoods(_, _),
+            dest_action: Action::SellGoods(_, _),
+            ..
+        }
+    )
+}
+
+// Manual tasks must move the same cargo they pick up, and visits can't touch cargo
+fn validate_manual_task(task: &Task) -> Result<(), String> {
+    if task.id.is_empty() {
+        return Err("Task id is empty".to_string());
+    }
+    match &task.actions {
+        TaskActions::VisitLocation { action, .. } => match action.net_cargo() {
+            Some(_) => Err(format!("{:?} needs a pickup and a delivery", action)),
+            None => Ok(()),
+        },
+        TaskActions::TransportCargo {
+            src_action,
+            dest_action,
+            ..
+        } => match (src_action.net_cargo(), dest_action.net_cargo()) {
+            (Some((good, units)), Some((dest_good, dest_units)))
+                if units > 0 && good == dest_good && units == -dest_units =>
+            {
+                Ok(())
+            }
+            _ => Err(format!(
+                "{:?} doesn't load the cargo {:?} unloads",
+                src_action, dest_action
+            )),
+        },
+    }
+}
+
+// The system a task starts in
+fn task_system(task: &Task) -> SystemSymbol {
+    match &task.actions {
+        TaskActions::VisitLocation { waypoint, .. } => waypoint.system(),
+        TaskActions::TransportCargo { src, .. } => src.system(),
+    }
+}
+
+const MAX_TRADE_ROUTES_PER_GOOD: usize = 3;
+const TASK_EXCLUSIVITY_WINDOW_MINS: i64 = 60;
+// Markets not refreshed for this long get a refresh task
+const MARKET_STALE_HOURS: i64 = 3;
+const REFRESH_MARKET_VALUE: i64 = 20000;
+// Markets a probe will refresh within this long aren't worth a hauler's visit, the reward scales up to
+// the full value for probe visits an hour out
+const PROBE_ETA_SOON_MINS: i64 = 10;
+const PROBE_ETA_HORIZON_MINS: i64 = 60;
+// A probe this late to a market has been reassigned or delayed, so its ETA is ignored
+const PROBE_ETA_SLACK_MINS: i64 = 5;
+// How far ahead trade volume forecasts look when deciding flow and import caps
+const TRADE_VOLUME_FORECAST_HOURS: i64 = 2;
+// The trade volume model is fit to this much history, and refit this often
+const TRADE_VOLUME_HISTORY_HOURS: i64 = 72;
+const TRADE_VOLUME_REFIT_MINS: i64 = 15;
+// Market health only shifts as markets evolve, the report is regenerated this often
+const MARKET_HEALTH_REFRESH_MINS: i64 = 10;
+// Trips a route capped by trade volume is offered for, one per restock of its markets
+const MAX_ROUTE_CYCLES: i64 = 3;
+// Value lost per later trip, prices drift while waiting for the restock
+const ROUTE_CYCLE_DISCOUNT: f64 = 0.1;
+// Completed tasks kept for the operations report
+const COMPLETED_TASK_RETENTION_HOURS: i64 = 2;
+// Contract deliveries due within this long interrupt a running ship, otherwise they're planned as usual
+const CONTRACT_URGENT_HOURS: i64 = 6;
+const MAX_GATE_TRADES: usize = 5;
+
+type CompletedTasks = VecDeque<(DateTime<Utc>, Task)>;
+// (computed at, value) for per-system analyses refreshed on a timer
+type Cached<T> = (DateTime<Utc>, Arc<T>);
+
+// Predicted time for a market to restock a trade volume's worth of a good, busier markets recover faster
+fn restock_time(activity: Option<MarketActivity>) -> Duration {
+    let minutes = match activity {
+        Some(Strong) => 10,
+        Some(Growing) => 15,
+        Some(Weak) => 30,
+        Some(Restricted) => 60,
+        Some(Unknown) | None => 20,
+    };
+    Duration::try_minutes(minutes).unwrap()
+}
+
+// Repeats of a trade route capped by trade volume, each once the slower market has restocked
+fn route_cycles(task: Task, restock: Duration, now: DateTime<Utc>) -> Vec<Task> {
+    (0..MAX_ROUTE_CYCLES)
+        .map(|cycle| match cycle {
+            0 => task.clone(),
+            _ => Task {
+                id: format!("{}#{}", task.id, cycle + 1),
+                actions: task.actions.clone(),
+                value: (task.value as f64 * (1.0 - ROUTE_CYCLE_DISCOUNT * cycle as f64)) as i64,
+                not_before: Some(now + restock * cycle as i32),
+            },
+        })
+        .collect()
+}
+
+// Two tasks conflict if they are the same task, or trade the same good through a shared market
+fn tasks_conflict(a: &Task, b: &Task) -> bool {
+    if a.id == b.id {
+        return true;
+    }
+    match (&a.actions, &b.actions) {
+        (
+            TaskActions::TransportCargo {
+                src: src_a,
+                dest: dest_a,
+                src_action: action_a,
+                ..
+            },
+            TaskActions::TransportCargo {
+                src: src_b,
+                dest: dest_b,
+                src_action: action_b,
+                ..
+            },
+        ) => {
+            let good_a = action_a.net_cargo().map(|(good, _)| good);
+            let good_b = action_b.net_cargo().map(|(good, _)| good);
+            good_a == good_b && (src_a == src_b || dest_a == dest_b)
+        }
+        _ => false,
+    }
+}
+
+// Tasks worth interrupting a running logistics ship for. `deadline` is the task's contract deadline
+fn is_urgent(task: &Task, deadline: Option<&DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    match &task.actions {
+        TaskActions::VisitLocation { action, .. } => matches!(action, Action::TryBuyShips),
+        TaskActions::TransportCargo { dest_action, .. } => {
+            matches!(dest_action, Action::DeliverContract(_, _))
+                && deadline.is_some_and(|deadline| {
+                    *deadline - now < Duration::try_hours(CONTRACT_URGENT_HOURS).unwrap()
+                })
+        }
+    }
+}
+
+// Pick a running logistics ship in the system that is allowed to do the task
+fn select_preemption_target(
+    task: &Task,
+    system_symbol: &SystemSymbol,
+    candidates: &[(String, (SystemSymbol, LogisticsScriptConfig))],
+    is_excluded: impl Fn(&str) -> bool,
+) -> Option<String> {
+    candidates
+        .iter()
+        .filter(|(ship_symbol, _)| !is_excluded(ship_symbol))
+        .find(|(_, (system, config))| system == system_symbol && is_task_allowed(task, config))
+        .map(|(ship_symbol, _)| ship_symbol.clone())
+}
+
+// Markets whose data the task was valued from
+fn task_markets(task: &Task) -> Vec<WaypointSymbol> {
+    match &task.actions {
+        TaskActions::VisitLocation { .. } => vec![],
+        TaskActions::TransportCargo {
+            src,
+            dest,
+            src_action,
+            dest_action,
+        } => {
+            let mut markets = vec![];
+            if matches!(src_action, Action::BuyGoods(_, _)) {
+                markets.push(src.clone());
+            }
+            if matches!(dest_action, Action::SellGoods(_, _)) {
+                markets.push(dest.clone());
+            }
+            markets
+        }
+    }
+}
+
+// The purchase and sale of a trade task, for simulating its impact on the markets
+fn planned_trades(task: &Task) -> Vec<PlannedTrade> {
+    match &task.actions {
+        TaskActions::TransportCargo {
+            src,
+            dest,
+            src_action: Action::BuyGoods(good, units),
+            dest_action: Action::SellGoods(_, _),
+        } => vec![
+            PlannedTrade {
+                market: src.clone(),
+                good: good.clone(),
+                side: TradeSide::Purchase,
+                units: *units,
+            },
+            PlannedTrade {
+                market: dest.clone(),
+                good: good.clone(),
+                side: TradeSide::Sell,
+                units: *units,
+            },
+        ],
+        _ => vec![],
+    }
+}
+
+// task_id -> deadline of the contract each contract delivery task is for
+fn task_deadlines(
+    tasks: &[Task],
+    contract_deadlines: &DashMap<(WaypointSymbol, String), DateTime<Utc>>,
+) -> BTreeMap<String, DateTime<Utc>> {
+    tasks
+        .iter()
+        .filter_map(|task| match &task.actions {
+            TaskActions::TransportCargo {
+                dest,
+                dest_action: Action::DeliverContract(good, _),
+                ..
+            } => contract_deadlines
+                .get(&(dest.clone(), good.clone()))
+                .map(|deadline| (task.id.clone(), *deadline)),
+            _ => None,
+        })
+        .collect()
+}
+
+// Trades available now that no ship is on, and that don't clash with a route a ship is on
+fn task_backlog<'a>(
+    tasks: &[Task],
+    in_progress: impl Iterator<Item = &'a Task> + Clone,
+) -> TaskBacklog {
+    let backlog = tasks
+        .iter()
+        .filter(|task| task.not_before.is_none())
+        .filter(|task| {
+            matches!(
+                task.actions,
+                TaskActions::TransportCargo {
+                    src_action: Action::BuyGoods(_, _),
+                    ..
+                }
+            )
+        })
+        .filter(|task| !in_progress.clone().any(|other| tasks_conflict(task, other)))
+        .collect::<Vec<_>>();
+    TaskBacklog {
+        tasks: backlog.len(),
+        value: backlog.iter().map(|task| task.value).sum(),
+    }
+}
+
+// Reward for a hauler refreshing a stale market, None if a probe will get there first
+fn refresh_market_value(
+    probe_eta: Option<DateTime<Utc>>,
+    is_probed: bool,
+    now: DateTime<Utc>,
+) -> Option<i64> {
+    let probe_eta = match probe_eta {
+        Some(eta) if eta < now - Duration::try_minutes(PROBE_ETA_SLACK_MINS).unwrap() => {
+            return Some(REFRESH_MARKET_VALUE)
+        }
+        Some(eta) => eta,
+        // probes that haven't published an ETA yet are assumed to be on their way
+        None if is_probed => return None,
+        None => return Some(REFRESH_MARKET_VALUE),
+    };
+    let wait_mins = (probe_eta - now).num_minutes();
+    if wait_mins < PROBE_ETA_SOON_MINS {
+        return None;
+    }
+    Some(REFRESH_MARKET_VALUE * min(wait_mins, PROBE_ETA_HORIZON_MINS) / PROBE_ETA_HORIZON_MINS)
+}
+
+// Whether a hauler currently depends on the market's data.
+// Until a logistics ship has planned, every market is assumed to be wanted.
+fn has_market_consumers(
+    waypoint: &WaypointSymbol,
+    served_systems: &BTreeSet<SystemSymbol>,
+    consumers: Option<&BTreeSet<WaypointSymbol>>,
+) -> bool {
+    if served_systems.is_empty() {
+        return true;
+    }
+    if !served_systems.contains(&waypoint.system()) {
+        return false;
+    }
+    match consumers {
+        Some(consumers) => consumers.contains(waypoint),
+        None => true,
+    }
+}
+
+fn is_market_stale(last_refreshed: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    match last_refreshed {
+        Some(timestamp) => now - timestamp >= Duration::try_hours(MARKET_STALE_HOURS).unwrap(),
+        None => true,
+    }
+}
+
+// Tasks of a ship that stopped running its script expire after a window, so a ship that died
+// mid-task doesn't block them forever. A live ship keeps its tasks however long its schedule runs
+fn expire_in_progress_tasks(
+    in_progress_tasks: &DashMap<String, (Task, String, DateTime<Utc>)>,
+    now: DateTime<Utc>,
+    ship_live: impl Fn(&str) -> bool,
+) {
+    in_progress_tasks.retain(|task_id, v| {
+        let expired = now - v.2 > Duration::try_minutes(TASK_EXCLUSIVITY_WINDOW_MINS).unwrap()
+            && !ship_live(&v.1);
+        if expired {
+            warn!("Task {} assigned to {} expired", task_id, v.1);
+        }
+        !expired
+    });
+}
+
+fn task_actions(task: &Task) -> Vec<ScheduledAction> {
+    match &task.actions {
+        TaskActions::VisitLocation { .. } => vec![task_to_scheduled_action(task, "", None)],
+        TaskActions::TransportCargo { .. } => vec![
+            task_to_scheduled_action(task, "pickup", None),
+            task_to_scheduled_action(task, "delivery", None),
+        ],
+    }
+}
+
 #[derive(Clone)]
 pub struct LogisticTaskManager {
+    callsign: String,
     start_system: SystemSymbol,
     agent_controller: Arc<RwLock<Option<AgentController>>>,
-    universe: Arc<Universe>,
+    universe: UniverseHandle,
     db_client: DbClient,
+    clock: SharedClock,
 
     // task_id -> (task, ship_symbol, timestamp)
     in_progress_tasks: Arc<DashMap<String, (Task, String, DateTime<Utc>)>>,
+    // ship_symbol -> (system, config) of ships running logistics
+    logistics_ships: Arc<DashMap<String, (SystemSymbol, LogisticsScriptConfig)>>,
+    // ship_symbol -> urgent task it should abandon its schedule for, at its next safe point
+    preemptions: Arc<DashMap<String, Task>>,
+    // system -> markets used by the latest candidate and in-progress tasks
+    market_consumers: Arc<DashMap<SystemSymbol, BTreeSet<WaypointSymbol>>>,
+    // waypoint -> when a probe expects to next refresh its market
+    probe_etas: Arc<DashMap<WaypointSymbol, DateTime<Utc>>>,
+    // (completed at, task), oldest first
+    completed_tasks: Arc<std::sync::Mutex<CompletedTasks>>,
+    // task_id -> one-off task submitted by an operator, offered to ships until completed
+    manual_tasks: Arc<DashMap<String, Task>>,
+    // system -> when it was onboarded, served before its first logistics ship arrives
+    onboarded_systems: Arc<DashMap<SystemSymbol, DateTime<Utc>>>,
+    // (destination, good) -> deadline of an accepted contract's delivery
+    contract_deadlines: Arc<DashMap<(WaypointSymbol, String), DateTime<Utc>>>,
+    // system -> (fitted at, trade volume model)
+    trade_volume_models: Arc<DashMap<SystemSymbol, Cached<TradeVolumeModel>>>,
+    // system -> (generated at, market health report)
+    market_health: Arc<DashMap<SystemSymbol, Cached<MarketHealthReport>>>,
+    // bumped when tasks are submitted or released, or new deadlines change what's urgent
+    task_updates: Arc<watch::Sender<()>>,
     take_tasks_mutex_guard: Arc<tokio::sync::Mutex<()>>,
 }
 
+// Wakes a ship with nothing to do when new work may be available: a market in its system was saved,
+// or the task manager's tasks changed
+pub struct TaskUpdates {
+    markets: watch::Receiver<Option<WaypointSymbol>>,
+    tasks: watch::Receiver<()>,
+}
+
+impl TaskUpdates {
+    pub async fn changed(&mut self) {
+        tokio::select! {
+            _ = self.markets.changed() => {}
+            _ = self.tasks.changed() => {}
+        }
+    }
+}
+
 impl LogisticTaskManager {
     pub async fn new(
-        universe: &Arc<Universe>,
+        universe: &UniverseHandle,
         db_client: &DbClient,
+        callsign: &str,
         start_system: &SystemSymbol,
-    ) -> Self {
+    ) -> Result<Self, DbError> {
+        db_client
+            .migrate_legacy_task_manager_state(callsign, start_system)
+            .await?;
         let in_progress_tasks = db_client
-            .load_task_manager_state(start_system)
-            .await
+            .load_task_manager_state(callsign, start_system)
+            .await?
+            .unwrap_or_default();
+        let manual_tasks = db_client
+            .load_manual_tasks(callsign, start_system)
+            .await?
             .unwrap_or_default();
-        Self {
+        Ok(Self {
+            callsign: callsign.to_string(),
             start_system: start_system.clone(),
             universe: universe.clone(),
             db_client: db_client.clone(),
+            clock: universe.clock(),
             agent_controller: Arc::new(RwLock::new(None)),
             in_progress_tasks: Arc::new(in_progress_tasks),
+            logistics_ships: Arc::new(DashMap::new()),
+            preemptions: Arc::new(DashMap::new()),
+            market_consumers: Arc::new(DashMap::new()),
+            probe_etas: Arc::new(DashMap::new()),
+            completed_tasks: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            manual_tasks: Arc::new(manual_tasks),
+            onboarded_systems: Arc::new(DashMap::new()),
+            contract_deadlines: Arc::new(DashMap::new()),
+            trade_volume_models: Arc::new(DashMap::new()),
+            market_health: Arc::new(DashMap::new()),
+            task_updates: Arc::new(watch::Sender::new(())),
             take_tasks_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    // Trade volumes evolve over hours, so the model is refit on a timer rather than every planning pass
+    async fn trade_volume_model(
+        &self,
+        system_symbol: &SystemSymbol,
+        market_symbols: &[WaypointSymbol],
+    ) -> Arc<TradeVolumeModel> {
+        let now = self.clock.now();
+        if let Some(cached) = self.trade_volume_models.get(system_symbol) {
+            let (fitted_at, model) = cached.value();
+            if now - *fitted_at < Duration::try_minutes(TRADE_VOLUME_REFIT_MINS).unwrap() {
+                return model.clone();
+            }
         }
+        let since = now - Duration::try_hours(TRADE_VOLUME_HISTORY_HOURS).unwrap();
+        let history = self
+            .db_client
+            .trade_volume_history(market_symbols, since)
+            .await;
+        // A failed load is retried on the next pass rather than cached
+        let Some(history) = ok_or_warn(history, "load trade volume history") else {
+            return Arc::new(TradeVolumeModel::default());
+        };
+        let model = Arc::new(TradeVolumeModel::fit(&history, now));
+        self.trade_volume_models
+            .insert(system_symbol.clone(), (now, model.clone()));
+        model
+    }
+
+    async fn market_health(&self, system_symbol: &SystemSymbol) -> Arc<MarketHealthReport> {
+        let now = self.clock.now();
+        if let Some(cached) = self.market_health.get(system_symbol) {
+            let (generated_at, report) = cached.value();
+            if now - *generated_at < Duration::try_minutes(MARKET_HEALTH_REFRESH_MINS).unwrap() {
+                return report.clone();
+            }
+        }
+        let report =
+            market_health::generate_report(&*self.universe, &self.db_client, system_symbol).await;
+        let report = Arc::new(report);
+        self.market_health
+            .insert(system_symbol.clone(), (now, report.clone()));
+        report
+    }
+
+    // Reservations stay in memory if a save fails, and the next save catches up
+    async fn save_state(&self) {
+        let saved = self
+            .db_client
+            .save_task_manager_state(&self.callsign, &self.start_system, &self.in_progress_tasks)
+            .await;
+        ok_or_warn(saved, "save task manager state");
+    }
+
+    async fn save_manual_tasks(&self) {
+        let saved = self
+            .db_client
+            .save_manual_tasks(&self.callsign, &self.start_system, &self.manual_tasks)
+            .await;
+        ok_or_warn(saved, "save manual tasks");
     }
 
     pub fn in_progress_tasks(&self) -> Arc<DashMap<String, (Task, String, DateTime<Utc>)>> {
@@ -87,6 +567,73 @@ impl LogisticTaskManager {
         self.in_progress_tasks.get(task_id).map(|v| v.clone())
     }
 
+    // Queue a one-off task, planned and reserved like the generated ones. Ids get a "manual_" prefix
+    pub async fn submit_manual_task(&self, mut task: Task) -> Result<Task, String> {
+        validate_manual_task(&task)?;
+        if !task.id.starts_with("manual_") {
+            task.id = format!("manual_{}", task.id);
+        }
+        if self.manual_tasks.contains_key(&task.id) {
+            return Err(format!("Task {} is already queued", task.id));
+        }
+        info!("Manual task {} submitted: {:?}", task.id, task.actions);
+        self.manual_tasks.insert(task.id.clone(), task.clone());
+        self.save_manual_tasks().await;
+        self.notify_task_update();
+        Ok(task)
+    }
+
+    pub fn manual_tasks(&self) -> Vec<Task> {
+        self.manual_tasks
+            .iter()
+            .map(|x| x.value().clone())
+            .collect()
+    }
+
+    // Subscribed before waiting, so changes made in the meantime are still seen
+    pub fn subscribe_updates(&self, system_symbol: &SystemSymbol) -> TaskUpdates {
+        TaskUpdates {
+            markets: self.universe.subscribe_system_markets(system_symbol),
+            tasks: self.task_updates.subscribe(),
+        }
+    }
+
+    fn notify_task_update(&self) {
+        self.task_updates.send_replace(());
+    }
+
+    // Treat a newly onboarded system as served, so its markets are refreshed while it has no haulers
+    pub fn serve_system(&self, system_symbol: &SystemSymbol, since: DateTime<Utc>) {
+        self.onboarded_systems.insert(system_symbol.clone(), since);
+        self.notify_task_update();
+    }
+
+    // Deliveries for an accepted contract are planned to arrive before its deadline
+    pub fn contract_accepted(&self, contract: &Contract) {
+        let deadline = contract.deadline();
+        for deliver in &contract.terms.deliver {
+            if deliver.units_fulfilled >= deliver.units_required {
+                continue;
+            }
+            info!(
+                "Contract {}: deliver {} {} to {} by {}",
+                contract.id,
+                deliver.units_required - deliver.units_fulfilled,
+                deliver.trade_symbol,
+                deliver.destination_symbol,
+                deadline
+            );
+            self.contract_deadlines.insert(
+                (
+                    WaypointSymbol::new(&deliver.destination_symbol),
+                    deliver.trade_symbol.clone(),
+                ),
+                deadline,
+            );
+        }
+        self.notify_task_update();
+    }
+
     pub fn set_agent_controller(&self, ac: &AgentController) {
         let mut agent_controller = self.agent_controller.write().unwrap();
         assert!(agent_controller.is_none());
@@ -118,7 +665,13 @@ impl LogisticTaskManager {
         buy_ships: bool,
         min_profit: i64,
     ) -> Vec<Task> {
-        let now = chrono::Utc::now();
+        if let Some(wind_down) = self.agent_controller().system_wind_down(system_symbol) {
+            debug!("No tasks generated: {}", wind_down.reason());
+            return Vec::new();
+        }
+        let now = self.clock.now();
+        // only core work is planned while the API error rate is over budget
+        let degraded = self.universe.api_degraded();
         let waypoints: Vec<WaypointDetailed> =
             self.universe.get_system_waypoints(system_symbol).await;
 
@@ -148,7 +701,7 @@ impl LogisticTaskManager {
             debug!("Task controller bought ship {}", ship_symbol);
             self.agent_controller()._spawn_run_ship(ship_symbol).await;
         }
-        if let Some(waypoint) = shipyard_task_waypoint {
+        if let Some((waypoint, not_before)) = shipyard_task_waypoint {
             if &waypoint.system() == system_symbol {
                 tasks.push(Task {
                     id: format!("{}buyships_{}", system_prefix, waypoint),
@@ -157,6 +710,7 @@ impl LogisticTaskManager {
                         action: Action::TryBuyShips,
                     },
                     value: 200000,
+                    not_before: not_before.filter(|t| *t > self.clock.now()),
                 });
             }
         }
@@ -164,6 +718,43 @@ impl LogisticTaskManager {
         // load markets
         let markets = self.universe.get_system_markets(system_symbol).await;
         let shipyards = self.universe.get_system_shipyards(system_symbol).await;
+        let health = self.market_health(system_symbol).await;
+        let trade_volume_model = {
+            let market_symbols = markets
+                .iter()
+                .map(|(remote, _)| remote.symbol.clone())
+                .collect::<Vec<_>>();
+            self.trade_volume_model(system_symbol, &market_symbols)
+                .await
+        };
+        let impact_model = {
+            let market_symbols = markets
+                .iter()
+                .map(|(remote, _)| remote.symbol.clone())
+                .collect::<Vec<_>>();
+            let history = self.db_client.transaction_history(&market_symbols).await;
+            let history = ok_or_warn(history, "load transaction history").unwrap_or_default();
+            ImpactModel::fit(&history)
+        };
+        let listings = markets
+            .iter()
+            .filter_map(|(_, market_opt)| market_opt.as_ref())
+            .flat_map(|market| {
+                market.data.trade_goods.iter().map(move |trade| {
+                    (
+                        (market.data.symbol.clone(), trade.symbol.clone()),
+                        trade.clone(),
+                    )
+                })
+            })
+            .collect::<Listings>();
+        // Trades ships are already on, new routes are valued at the prices they'll leave behind
+        let mut planned = self
+            .in_progress_tasks
+            .iter()
+            .flat_map(|x| planned_trades(&x.value().0))
+            .collect::<Vec<_>>();
+        let forecast_horizon = Duration::try_hours(TRADE_VOLUME_FORECAST_HOURS).unwrap();
 
         // unique list of goods
         let mut goods = BTreeSet::new();
@@ -198,6 +789,13 @@ impl LogisticTaskManager {
         if CONFIG.no_gate_mode {
             construction = None;
         }
+        if system_symbol == &self.start_system
+            && self
+                .agent_controller()
+                .is_goal_complete(&Goal::FinishJumpGate)
+        {
+            construction = None;
+        }
 
         if let Some(construction) = &construction {
             let fab_mat_market = self
@@ -401,24 +999,40 @@ impl LogisticTaskManager {
 
         let probe_locations = self.probe_locations();
         for (market_remote, market_opt) in &markets {
-            let requires_visit = match market_opt {
-                Some(market) => {
-                    now.signed_duration_since(market.timestamp) >= Duration::try_hours(3).unwrap()
-                }
-                None => true,
-            };
+            let requires_visit = is_market_stale(market_opt.as_ref().map(|m| m.timestamp), now);
             let is_probed = probe_locations.contains(&market_remote.symbol);
+            let probe_eta = self
+                .probe_etas
+                .get(&market_remote.symbol)
+                .map(|eta| *eta.value());
             // Some fuel stop markets only trade fuel, so not worth visiting
             let is_pure_exchange =
                 market_remote.exports.is_empty() && market_remote.imports.is_empty();
-            if requires_visit && !is_pure_exchange && !is_probed {
+            // busy markets in a gap of our refreshes are caught up on before they're stale,
+            // unless the API is degraded
+            let catch_up_value = match degraded {
+                true => None,
+                false => self
+                    .universe
+                    .market_coverage(&market_remote.symbol)
+                    .and_then(|coverage| coverage.catch_up_value(now, REFRESH_MARKET_VALUE)),
+            };
+            if (!requires_visit && catch_up_value.is_none()) || is_pure_exchange {
+                continue;
+            }
+            if let Some(value) = refresh_market_value(probe_eta, is_probed, now) {
+                let value = match (requires_visit, catch_up_value) {
+                    (false, Some(catch_up_value)) => value * catch_up_value / REFRESH_MARKET_VALUE,
+                    _ => value,
+                };
                 tasks.push(Task {
                     id: format!("{}refreshmarket_{}", system_prefix, market_remote.symbol),
                     actions: TaskActions::VisitLocation {
                         waypoint: market_remote.symbol.clone(),
                         action: Action::RefreshMarket,
                     },
-                    value: 20000,
+                    value,
+                    not_before: None,
                 });
             }
         }
@@ -439,6 +1053,7 @@ impl LogisticTaskManager {
                         action: Action::RefreshShipyard,
                     },
                     value: 5000,
+                    not_before: None,
                 });
             }
         }
@@ -456,13 +1071,17 @@ impl LogisticTaskManager {
                     None => None,
                 })
                 .collect::<Vec<_>>();
-            let buy_trade_good = trades
+            let buy_trade_goods = trades
                 .iter()
-                .filter(|(_, trade)| match trade._type {
+                .filter(|(market_symbol, trade)| match trade._type {
                     Import => false,
                     Export => {
+                        // Exports predicted to double soon are mid-evolution, keep their flow going
+                        let evolving = trade_volume_model
+                            .time_to_double(market_symbol, &good)
+                            .is_some_and(|t| t <= forecast_horizon);
                         // Strong markets are where we'll make the most consistent profit
-                        if !req_constant_flow && trade.activity == Some(Strong) {
+                        if !req_constant_flow && !evolving && trade.activity == Some(Strong) {
                             trade.supply >= High
                         } else {
                             trade.supply >= Moderate
@@ -470,8 +1089,8 @@ impl LogisticTaskManager {
                     }
                     Exchange => true,
                 })
-                .min_by_key(|(_, trade)| trade.purchase_price);
-            let sell_trade_good = trades
+                .collect::<Vec<_>>();
+            let sell_trade_goods = trades
                 .iter()
                 .filter(|(market_symbol, trade)| {
                     let key = (market_symbol.clone(), good.clone());
@@ -482,14 +1101,26 @@ impl LogisticTaskManager {
                                 trade._type, Import,
                                 "Only import trades should have an import evolution cap"
                             );
-                            if trade.trade_volume >= *evo_cap {
-                                // If we reached the evolution cap, then add an extra requirement to only IMPORT at LIMITED supply
+                            let forecast = trade_volume_model.forecast(
+                                market_symbol,
+                                &good,
+                                trade.trade_volume,
+                                forecast_horizon,
+                            );
+                            if forecast >= *evo_cap {
+                                // If we reached (or are forecast to reach) the evolution cap, then add an extra requirement to only IMPORT at LIMITED supply
                                 // keep the import above scarce, and push limited into low moderate
                                 trade.supply <= Limited
                             } else {
                                 true
                             }
                         }
+                        // Same treatment for imports the health report flags as over-evolved
+                        None if trade._type == Import
+                            && health.is_over_evolved(market_symbol, &good) =>
+                        {
+                            trade.supply <= Limited
+                        }
                         None => true,
                     }
                 })
@@ -502,22 +1133,93 @@ impl LogisticTaskManager {
                     Some(allowlist) => allowlist.contains(market),
                     None => true,
                 })
-                .max_by_key(|(_, trade)| trade.sell_price);
-            let (buy_trade_good, sell_trade_good) = match (buy_trade_good, sell_trade_good) {
-                (Some(buy), Some(sell)) => (buy, sell),
-                _ => continue,
-            };
-            let units = min(
-                min(
-                    buy_trade_good.1.trade_volume,
-                    sell_trade_good.1.trade_volume,
-                ),
-                capacity_cap,
-            );
-            let profit =
-                (sell_trade_good.1.sell_price - buy_trade_good.1.purchase_price) * (units as i64);
-            let can_afford = true; // logistic ships reserve their credits beforehand
-            if profit >= min_profit && can_afford {
+                .collect::<Vec<_>>();
+
+            // Candidate routes, best first. Each market is used by at most one route per good,
+            // so parallel haulers can work different routes of the same good
+            let mut routes = Vec::new();
+            for buy_trade_good in &buy_trade_goods {
+                for sell_trade_good in &sell_trade_goods {
+                    if buy_trade_good.0 == sell_trade_good.0 {
+                        continue;
+                    }
+                    let units = min(
+                        min(
+                            buy_trade_good.1.trade_volume,
+                            sell_trade_good.1.trade_volume,
+                        ),
+                        capacity_cap,
+                    );
+                    // costs observed in our previous trades at these markets, and the two docking stops
+                    let transaction_cost = self.universe.transaction_cost(
+                        &buy_trade_good.0,
+                        &good,
+                        TradeSide::Purchase,
+                    ) + self.universe.transaction_cost(
+                        &sell_trade_good.0,
+                        &good,
+                        TradeSide::Sell,
+                    );
+                    let profit = (sell_trade_good.1.sell_price
+                        - buy_trade_good.1.purchase_price
+                        - transaction_cost)
+                        * (units as i64)
+                        - 2 * CONFIG.docking_cost;
+                    routes.push((
+                        profit,
+                        units,
+                        transaction_cost,
+                        buy_trade_good,
+                        sell_trade_good,
+                    ));
+                }
+            }
+            routes.sort_by_key(|(profit, _, _, _, _)| -profit);
+            let mut used_markets = BTreeSet::new();
+            let mut num_routes = 0;
+            for (profit, units, transaction_cost, buy_trade_good, sell_trade_good) in routes {
+                if num_routes >= MAX_TRADE_ROUTES_PER_GOOD {
+                    break;
+                }
+                if used_markets.contains(&buy_trade_good.0)
+                    || used_markets.contains(&sell_trade_good.0)
+                {
+                    continue;
+                }
+                let can_afford = true; // logistic ships reserve their credits beforehand
+                if profit < min_profit || !can_afford {
+                    break;
+                }
+                let route_trades = [
+                    PlannedTrade {
+                        market: buy_trade_good.0.clone(),
+                        good: good.clone(),
+                        side: TradeSide::Purchase,
+                        units,
+                    },
+                    PlannedTrade {
+                        market: sell_trade_good.0.clone(),
+                        good: good.clone(),
+                        side: TradeSide::Sell,
+                        units,
+                    },
+                ];
+                let plan = planned
+                    .iter()
+                    .chain(route_trades.iter())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let outcome = market_impact::evaluate_plan(&impact_model, &listings, &plan);
+                let projected = &outcome.trades[outcome.trades.len() - 2..];
+                // at the prices left by the trades planned before it
+                let profit = projected[1].total
+                    - projected[0].total
+                    - transaction_cost * units
+                    - 2 * CONFIG.docking_cost;
+                if profit < min_profit {
+                    continue;
+                }
+                planned.extend(route_trades);
                 debug!(
                     "{}: buy {} @ {} for ${}, sell @ {} for ${}, profit: ${}",
                     good,
@@ -528,9 +1230,14 @@ impl LogisticTaskManager {
                     sell_trade_good.1.sell_price,
                     profit
                 );
-                tasks.push(Task {
-                    // exclusion seems a bit broad right now, but it's a start
-                    id: format!("{}trade_{}", system_prefix, good),
+                used_markets.insert(buy_trade_good.0.clone());
+                used_markets.insert(sell_trade_good.0.clone());
+                num_routes += 1;
+                let task = Task {
+                    id: format!(
+                        "{}trade_{}_{}_{}",
+                        system_prefix, good, buy_trade_good.0, sell_trade_good.0
+                    ),
                     actions: TaskActions::TransportCargo {
                         src: buy_trade_good.0.clone(),
                         dest: sell_trade_good.0.clone(),
@@ -538,9 +1245,150 @@ impl LogisticTaskManager {
                         dest_action: Action::SellGoods(good.clone(), units),
                     },
                     value: profit,
-                });
+                    not_before: None,
+                };
+                if units < capacity_cap {
+                    let restock = max(
+                        restock_time(buy_trade_good.1.activity.clone()),
+                        restock_time(sell_trade_good.1.activity.clone()),
+                    );
+                    tasks.extend(route_cycles(task, restock, now));
+                } else {
+                    tasks.push(task);
+                }
             }
         }
+        if !CONFIG.no_gate_mode {
+            let gate_trades = self
+                .gate_trade_tasks(
+                    system_symbol,
+                    &markets,
+                    &good_import_permits,
+                    &system_prefix,
+                    capacity_cap,
+                    min_profit,
+                )
+                .await;
+            tasks.extend(gate_trades);
+        }
+        tasks.retain(|task| is_trade_allowed(task, &CONFIG.trade_goods));
+        if degraded {
+            tasks.retain(|task| !is_speculative(task));
+        }
+        // operators' tasks skip the trade filters
+        tasks.extend(
+            self.manual_tasks
+                .iter()
+                .filter(|x| task_system(x.value()) == *system_symbol)
+                .map(|x| x.value().clone()),
+        );
+        tasks
+    }
+
+    // Trades buying here and selling in a system one jump away, the best route per good.
+    // Goods reserved for construction stay in the system
+    async fn gate_trade_tasks(
+        &self,
+        system_symbol: &SystemSymbol,
+        markets: &[(MarketRemoteView, Option<Arc<WithTimestamp<Market>>>)],
+        good_import_permits: &BTreeMap<String, Vec<WaypointSymbol>>,
+        system_prefix: &str,
+        capacity_cap: i64,
+        min_profit: i64,
+    ) -> Vec<Task> {
+        let buy_trade_goods = markets
+            .iter()
+            .filter_map(|(_, market_opt)| market_opt.as_ref())
+            .flat_map(|market| {
+                market
+                    .data
+                    .trade_goods
+                    .iter()
+                    .map(move |trade| (&market.data.symbol, trade))
+            })
+            .filter(|(_, trade)| !good_import_permits.contains_key(&trade.symbol))
+            .filter(|(_, trade)| match trade._type {
+                Import => false,
+                Export => trade.supply >= Moderate,
+                Exchange => true,
+            })
+            .collect::<Vec<_>>();
+        if buy_trade_goods.is_empty() {
+            return vec![];
+        }
+
+        let mut best_routes = BTreeMap::<String, Task>::new();
+        for (dest_system, _) in self.universe.gate_connections(system_symbol).await {
+            // antimatter for the jump out and the jump home
+            let round_trip_cost = self.universe.jump_cost(system_symbol, &dest_system)
+                + self.universe.jump_cost(&dest_system, system_symbol);
+            let dest_markets = self.universe.get_system_markets(&dest_system).await;
+            for (_, market_opt) in &dest_markets {
+                let Some(market) = market_opt else {
+                    continue;
+                };
+                for sell_trade_good in &market.data.trade_goods {
+                    let can_sell = match sell_trade_good._type {
+                        Import => sell_trade_good.supply <= Moderate,
+                        Export => false,
+                        Exchange => true,
+                    };
+                    if !can_sell {
+                        continue;
+                    }
+                    let good = &sell_trade_good.symbol;
+                    for (src, buy_trade_good) in &buy_trade_goods {
+                        if &buy_trade_good.symbol != good {
+                            continue;
+                        }
+                        let units = min(
+                            min(buy_trade_good.trade_volume, sell_trade_good.trade_volume),
+                            capacity_cap,
+                        );
+                        let transaction_cost =
+                            self.universe
+                                .transaction_cost(src, good, TradeSide::Purchase)
+                                + self.universe.transaction_cost(
+                                    &market.data.symbol,
+                                    good,
+                                    TradeSide::Sell,
+                                );
+                        let profit = (sell_trade_good.sell_price
+                            - buy_trade_good.purchase_price
+                            - transaction_cost)
+                            * units
+                            - 2 * CONFIG.docking_cost
+                            - round_trip_cost;
+                        if profit < min_profit
+                            || best_routes.get(good).is_some_and(|t| t.value >= profit)
+                        {
+                            continue;
+                        }
+                        let task = Task {
+                            id: format!(
+                                "{}gatetrade_{}_{}_{}",
+                                system_prefix, good, src, market.data.symbol
+                            ),
+                            actions: TaskActions::TransportCargo {
+                                src: (*src).clone(),
+                                dest: market.data.symbol.clone(),
+                                src_action: Action::BuyGoods(good.clone(), units),
+                                dest_action: Action::SellGoods(good.clone(), units),
+                            },
+                            value: profit,
+                            not_before: None,
+                        };
+                        best_routes.insert(good.clone(), task);
+                    }
+                }
+            }
+        }
+        let mut tasks = best_routes.into_values().collect::<Vec<_>>();
+        tasks.sort_by_key(|task| -task.value);
+        tasks.truncate(MAX_GATE_TRADES);
+        for task in &tasks {
+            debug!("Gate trade {}, profit: ${}", task.id, task.value);
+        }
         tasks
     }
 
@@ -577,6 +1425,29 @@ impl LogisticTaskManager {
     ) -> ShipSchedule {
         let _guard = self.take_tasks_lock().await;
         assert_eq!(&start_waypoint.system(), system_symbol);
+        self.logistics_ships.insert(
+            ship_symbol.to_string(),
+            (system_symbol.clone(), config.clone()),
+        );
+
+        // The ship was interrupted for an urgent task, which is already reserved for it
+        if let Some((_, task)) = self.preemptions.remove(ship_symbol) {
+            info!("Ship {} taking urgent task {}", ship_symbol, task.id);
+            self.in_progress_tasks.insert(
+                task.id.clone(),
+                (task.clone(), ship_symbol.to_string(), self.clock.now()),
+            );
+            self.save_state().await;
+            return ShipSchedule {
+                ship: LogisticShip {
+                    symbol: ship_symbol.to_string(),
+                    capacity: cargo_capacity,
+                    speed: engine_speed,
+                    start_waypoint: start_waypoint.clone(),
+                },
+                actions: task_actions(&task),
+            };
+        }
 
         // Cleanup in_progress_tasks for this ship
         self.in_progress_tasks.retain(|_k, v| v.1 != ship_symbol);
@@ -586,19 +1457,82 @@ impl LogisticTaskManager {
         self.agent_controller()
             .ledger
             .reserve_credits(ship_symbol, 5000 * cargo_capacity);
+        let in_progress = self
+            .in_progress_tasks
+            .iter()
+            .map(|x| x.value().0.clone())
+            .collect::<Vec<_>>();
+        let consumers = all_tasks
+            .iter()
+            .chain(in_progress.iter())
+            .flat_map(task_markets)
+            .filter(|market| market.system() == *system_symbol)
+            .collect::<BTreeSet<_>>();
+        self.market_consumers
+            .insert(system_symbol.clone(), consumers);
+
+        let agent_controller = self.agent_controller();
+        expire_in_progress_tasks(&self.in_progress_tasks, self.clock.now(), |ship| {
+            agent_controller.script_running(ship)
+        });
+        let candidate_tasks = all_tasks.clone();
 
-        // Filter out tasks that are already in progress
-        // Also filter tasks outlawed by the config for this ship
+        // Filter out tasks that conflict with tasks already in progress
+        // Also filter tasks outlawed by the config for this ship, or that can't start within the plan
+        let plan_start = self.clock.now();
         let available_tasks = all_tasks
             .into_iter()
-            .filter(|task| !self.in_progress_tasks.contains_key(&task.id))
+            .filter(|task| match task.not_before {
+                Some(not_before) => not_before < plan_start + plan_length,
+                None => true,
+            })
+            .filter(|task| {
+                !self
+                    .in_progress_tasks
+                    .iter()
+                    .any(|in_progress| tasks_conflict(task, &in_progress.value().0))
+            })
             .filter(|task| is_task_allowed(&task, config))
             .collect::<Vec<_>>();
+        let deadlines = task_deadlines(&available_tasks, &self.contract_deadlines);
+        let (available_tasks, missed): (Vec<_>, Vec<_>) =
+            available_tasks
+                .into_iter()
+                .partition(|task| match deadlines.get(&task.id) {
+                    Some(deadline) => *deadline > plan_start,
+                    None => true,
+                });
+        for task in &missed {
+            ALERTS.notify(
+                &format!("contract_deadline_{}", task.id),
+                &format!(
+                    "Contract delivery {} missed its deadline {}",
+                    task.id, deadlines[&task.id]
+                ),
+            );
+        }
 
-        let matrix = self
-            .universe
-            .estimate_duration_matrix(&system_symbol, engine_speed, fuel_capacity)
-            .await;
+        // gate trades need the systems they sell in joined to this one
+        let task_systems = available_tasks
+            .iter()
+            .flat_map(|task| match &task.actions {
+                TaskActions::VisitLocation { waypoint, .. } => vec![waypoint.system()],
+                TaskActions::TransportCargo { src, dest, .. } => vec![src.system(), dest.system()],
+            })
+            .chain([system_symbol.clone()])
+            .collect::<BTreeSet<_>>();
+        let matrix = match task_systems.len() {
+            1 => {
+                self.universe
+                    .estimate_duration_matrix(system_symbol, engine_speed, fuel_capacity)
+                    .await
+            }
+            _ => {
+                self.universe
+                    .estimate_gate_duration_matrix(&task_systems, engine_speed, fuel_capacity)
+                    .await
+            }
+        };
         let logistics_ship = LogisticShip {
             symbol: ship_symbol.to_string(),
             capacity: cargo_capacity,
@@ -607,21 +1541,40 @@ impl LogisticTaskManager {
             // available_from: Duration::seconds(0), // if we need to account for in-progress task(s)
         };
         let contraints = PlannerConstraints {
+            start: plan_start,
             plan_length,
             max_compute_time: Duration::try_seconds(5).unwrap(),
+            deadlines: deadlines.clone(),
         };
+        let urgent_tasks = available_tasks
+            .iter()
+            .filter(|task| is_urgent(task, deadlines.get(&task.id), plan_start))
+            .cloned()
+            .collect::<Vec<_>>();
         let available_tasks_clone = available_tasks.clone();
         let (mut task_assignments, schedules) = if config.use_planner {
-            tokio::task::spawn_blocking(move || {
+            let max_compute_time = contraints.max_compute_time;
+            let start = Utc::now();
+            let attributes = vec![
+                KeyValue::new("ship", ship_symbol.to_string()),
+                KeyValue::new("planner.tasks", available_tasks_clone.len() as i64),
+            ];
+            let planner = tokio::task::spawn_blocking(move || {
                 logistics_planner::plan::run_planner(
                     &[logistics_ship],
                     &available_tasks_clone,
                     &matrix,
                     &contraints,
                 )
+            });
+            let result = telemetry::in_span("planner_run", attributes, async {
+                let result = planner.await.unwrap();
+                telemetry::record(KeyValue::new("planner.assigned", result.0.len() as i64));
+                result
             })
-            .await
-            .unwrap()
+            .await;
+            ALERTS.record_planner_run(Utc::now() - start, max_compute_time);
+            result
         } else {
             let ship_schedule = ShipSchedule {
                 ship: logistics_ship,
@@ -631,12 +1584,27 @@ impl LogisticTaskManager {
         };
         assert_eq!(schedules.len(), 1);
         let mut schedule = schedules.into_iter().next().unwrap();
+        for (task, ship) in &task_assignments {
+            if let (Some(deadline), None) = (deadlines.get(&task.id), ship) {
+                ALERTS.notify(
+                    &format!("contract_deadline_{}", task.id),
+                    &format!(
+                        "No feasible schedule for contract delivery {} by {} from {}",
+                        task.id, deadline, ship_symbol
+                    ),
+                );
+            }
+        }
 
         // If 0 tasks were assigned, instead force assign the highest value task
         if schedule.actions.len() == 0 {
             let mut highest_value_task = None;
             let mut highest_value = 0;
             for task in available_tasks {
+                // forced tasks are executed immediately
+                if task.not_before.is_some() {
+                    continue;
+                }
                 if task.value > highest_value {
                     highest_value = task.value;
                     highest_value_task = Some(task);
@@ -647,52 +1615,152 @@ impl LogisticTaskManager {
                     "Forcing assignment of task {} value: {}",
                     task.id, task.value
                 );
-                // add actions for the task
-                match &task.actions {
-                    TaskActions::VisitLocation { .. } => {
-                        schedule
-                            .actions
-                            .push(task_to_scheduled_action(&task, "", None));
-                    }
-                    TaskActions::TransportCargo { .. } => {
-                        schedule
-                            .actions
-                            .push(task_to_scheduled_action(&task, "pickup", None));
-                        schedule
-                            .actions
-                            .push(task_to_scheduled_action(&task, "delivery", None));
-                    }
-                };
+                schedule.actions.extend(task_actions(&task));
                 task_assignments.insert(task, Some(ship_symbol.to_string()));
             }
         }
 
-        for (task, ship) in task_assignments {
-            if let Some(ship) = &ship {
+        for (task, ship) in &task_assignments {
+            if let Some(ship) = ship {
                 debug!("Assigned task {} to ship {}", task.id, ship);
-                self.in_progress_tasks
-                    .insert(task.id.clone(), (task.clone(), ship.clone(), Utc::now()));
+                self.in_progress_tasks.insert(
+                    task.id.clone(),
+                    (task.clone(), ship.clone(), self.clock.now()),
+                );
             }
         }
-        self.db_client
-            .save_task_manager_state(&self.start_system, &self.in_progress_tasks)
+        let assigned = self
+            .in_progress_tasks
+            .iter()
+            .map(|x| x.value().0.clone())
+            .collect::<Vec<_>>();
+        let backlog = task_backlog(&candidate_tasks, assigned.iter());
+        debug!(
+            "Task backlog in {}: {} tasks worth ${}",
+            system_symbol, backlog.tasks, backlog.value
+        );
+        self.agent_controller()
+            .record_task_backlog(system_symbol, backlog)
             .await;
 
+        // Urgent tasks this ship didn't take interrupt another logistics ship
+        let mut logistics_ships = self
+            .logistics_ships
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect::<Vec<_>>();
+        logistics_ships.sort_by(|a, b| a.0.cmp(&b.0));
+        for task in &urgent_tasks {
+            if self.in_progress_tasks.contains_key(&task.id) {
+                continue;
+            }
+            let target = select_preemption_target(task, system_symbol, &logistics_ships, |ship| {
+                ship == ship_symbol || self.preemptions.contains_key(ship)
+            });
+            if let Some(target) = target {
+                info!("Preempting ship {} for urgent task {}", target, task.id);
+                self.in_progress_tasks.insert(
+                    task.id.clone(),
+                    (task.clone(), target.clone(), self.clock.now()),
+                );
+                self.preemptions.insert(target, task.clone());
+            }
+        }
+        self.save_state().await;
+
         schedule
     }
 
+    // Probes publish when they expect to next refresh each of their markets
+    pub fn set_probe_eta(&self, waypoint: &WaypointSymbol, eta: DateTime<Utc>) {
+        self.probe_etas.insert(waypoint.clone(), eta);
+    }
+
+    // Probes slow down refreshes of markets no hauler is using
+    pub fn market_has_consumers(&self, waypoint: &WaypointSymbol) -> bool {
+        let served_systems = self
+            .logistics_ships
+            .iter()
+            .map(|x| x.value().0.clone())
+            .chain(self.onboarded_systems.iter().map(|x| x.key().clone()))
+            .collect::<BTreeSet<_>>();
+        let consumers = self.market_consumers.get(&waypoint.system());
+        has_market_consumers(waypoint, &served_systems, consumers.as_deref())
+    }
+
+    // Polled by the logistics script at safe points (cargo empty, between actions)
+    pub fn preemption_requested(&self, ship_symbol: &str) -> bool {
+        self.preemptions.contains_key(ship_symbol)
+    }
+
+    // Release tasks a ship won't get to, so they can be rescheduled
+    pub async fn release_tasks(&self, tasks: &[Task]) {
+        for task in tasks {
+            self.in_progress_tasks.remove(&task.id);
+        }
+        self.save_state().await;
+    }
+
+    // Drop all tasks held by a ship, e.g. when it's handed over to another job. The ship is no longer a
+    // logistics ship until it next takes tasks
+    pub async fn release_ship_tasks(&self, ship_symbol: &str) {
+        self.in_progress_tasks.retain(|_, v| v.1 != ship_symbol);
+        self.logistics_ships.remove(ship_symbol);
+        self.preemptions.remove(ship_symbol);
+        self.save_state().await;
+        // the ship's tasks are free for the others
+        self.notify_task_update();
+    }
+
     pub async fn set_task_completed(&self, task: &Task) {
         self.in_progress_tasks.remove(&task.id);
-        self.db_client
-            .save_task_manager_state(&self.start_system, &self.in_progress_tasks)
-            .await;
+        self.save_state().await;
+        if self.manual_tasks.remove(&task.id).is_some() {
+            info!("Manual task {} completed", task.id);
+            self.save_manual_tasks().await;
+        }
         debug!("Marking task {} as completed", task.id);
+        let now = self.clock.now();
+        let mut completed_tasks = self.completed_tasks.lock().unwrap();
+        completed_tasks.push_back((now, task.clone()));
+        let cutoff = now - Duration::try_hours(COMPLETED_TASK_RETENTION_HOURS).unwrap();
+        while matches!(completed_tasks.front(), Some((ts, _)) if *ts < cutoff) {
+            completed_tasks.pop_front();
+        }
+    }
+
+    // Save the in-progress and manual tasks, at shutdown
+    pub async fn flush(&self) {
+        self.save_state().await;
+        self.save_manual_tasks().await;
+    }
+
+    pub fn completed_tasks_since(&self, since: DateTime<Utc>) -> Vec<Task> {
+        let completed_tasks = self.completed_tasks.lock().unwrap();
+        completed_tasks
+            .iter()
+            .filter(|(ts, _)| *ts >= since)
+            .map(|(_, task)| task.clone())
+            .collect()
+    }
+
+    // Ships running logistics without any assigned tasks
+    pub fn idle_logistics_ships(&self) -> Vec<String> {
+        let mut idle = self
+            .logistics_ships
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|ship| !self.in_progress_tasks.iter().any(|t| &t.value().1 == ship))
+            .collect::<Vec<_>>();
+        idle.sort();
+        idle
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::clock::{Clock as _, TestClock};
 
     #[tokio::test]
     async fn test_logistic_task_manager_state() {
@@ -704,6 +1772,7 @@ mod test {
                 action: Action::RefreshMarket,
             },
             value: 20000,
+            not_before: None,
         };
         in_progress_tasks.insert(
             "test".to_string(),
@@ -711,4 +1780,457 @@ mod test {
         );
         let _json = serde_json::to_string(&in_progress_tasks).unwrap();
     }
+
+    fn trade_task(good: &str, src: &str, dest: &str) -> Task {
+        Task {
+            id: format!("trade_{}_{}_{}", good, src, dest),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new(src),
+                dest: WaypointSymbol::new(dest),
+                src_action: Action::BuyGoods(good.to_string(), 10),
+                dest_action: Action::SellGoods(good.to_string(), 10),
+            },
+            value: 1000,
+            not_before: None,
+        }
+    }
+
+    #[test]
+    fn test_select_preemption_target() {
+        let system = SystemSymbol::new("X1-S1");
+        let buy_ships = Task {
+            id: "buyships_X1-S1-A1".to_string(),
+            actions: TaskActions::VisitLocation {
+                waypoint: WaypointSymbol::new("X1-S1-A1"),
+                action: Action::TryBuyShips,
+            },
+            value: 200000,
+            not_before: None,
+        };
+        let now = Utc::now();
+        assert!(is_urgent(&buy_ships, None, now));
+        assert!(!is_urgent(
+            &trade_task("FUEL", "X1-S1-A1", "X1-S1-B2"),
+            None,
+            now
+        ));
+        // contract deliveries only once their deadline is near
+        let mut delivery = trade_task("IRON", "X1-S1-A1", "X1-S1-B2");
+        if let TaskActions::TransportCargo { dest_action, .. } = &mut delivery.actions {
+            *dest_action = Action::DeliverContract("IRON".to_string(), 10);
+        }
+        let deadline = |hours| now + Duration::try_hours(hours).unwrap();
+        assert!(!is_urgent(&delivery, Some(&deadline(48)), now));
+        assert!(is_urgent(&delivery, Some(&deadline(2)), now));
+        assert!(!is_urgent(&delivery, None, now));
+
+        let config = |allow_shipbuying| LogisticsScriptConfig {
+            use_planner: true,
+            allow_shipbuying,
+            allow_construction: false,
+            allow_gate_trades: false,
+            allow_market_refresh: true,
+            waypoint_allowlist: None,
+            min_profit: 0,
+        };
+        let ships = vec![
+            ("A-1".to_string(), (system.clone(), config(false))),
+            (
+                "A-2".to_string(),
+                (SystemSymbol::new("X1-S2"), config(true)),
+            ),
+            ("A-3".to_string(), (system.clone(), config(true))),
+            ("A-4".to_string(), (system.clone(), config(true))),
+        ];
+        let target = select_preemption_target(&buy_ships, &system, &ships, |_| false);
+        assert_eq!(target, Some("A-3".to_string()));
+        let target = select_preemption_target(&buy_ships, &system, &ships, |s| s != "A-1");
+        assert_eq!(target, None);
+    }
+
+    #[test]
+    fn test_validate_manual_task() {
+        let deliver_fuel = trade_task("FUEL", "X1-S1-A1", "X1-S2-B2");
+        assert_eq!(validate_manual_task(&deliver_fuel), Ok(()));
+        assert_eq!(task_system(&deliver_fuel), SystemSymbol::new("X1-S1"));
+
+        let mut mismatched = deliver_fuel.clone();
+        mismatched.actions = TaskActions::TransportCargo {
+            src: WaypointSymbol::new("X1-S1-A1"),
+            dest: WaypointSymbol::new("X1-S1-B2"),
+            src_action: Action::BuyGoods("FUEL".to_string(), 40),
+            dest_action: Action::DeliverConstruction("FUEL".to_string(), 20),
+        };
+        assert!(validate_manual_task(&mismatched).is_err());
+
+        let mut visit = deliver_fuel.clone();
+        visit.actions = TaskActions::VisitLocation {
+            waypoint: WaypointSymbol::new("X1-S1-A1"),
+            action: Action::SellGoods("FUEL".to_string(), 40),
+        };
+        assert!(validate_manual_task(&visit).is_err());
+        visit.actions = TaskActions::VisitLocation {
+            waypoint: WaypointSymbol::new("X1-S1-A1"),
+            action: Action::RefreshMarket,
+        };
+        assert_eq!(validate_manual_task(&visit), Ok(()));
+        visit.id = String::new();
+        assert!(validate_manual_task(&visit).is_err());
+    }
+
+    #[test]
+    fn test_market_consumers() {
+        let task = trade_task("FUEL", "X1-S1-A1", "X1-S1-B2");
+        let consumers = task_markets(&task).into_iter().collect::<BTreeSet<_>>();
+        assert_eq!(consumers.len(), 2);
+
+        let used = WaypointSymbol::new("X1-S1-A1");
+        let unused = WaypointSymbol::new("X1-S1-C3");
+        let elsewhere = WaypointSymbol::new("X1-S2-A1");
+        assert!(has_market_consumers(&unused, &BTreeSet::new(), None));
+        let served = BTreeSet::from([SystemSymbol::new("X1-S1")]);
+        assert!(has_market_consumers(&unused, &served, None));
+        assert!(has_market_consumers(&used, &served, Some(&consumers)));
+        assert!(!has_market_consumers(&unused, &served, Some(&consumers)));
+        assert!(!has_market_consumers(&elsewhere, &served, None));
+    }
+
+    #[test]
+    fn test_trade_good_filter() {
+        let iron = trade_task("IRON", "X1-S1-A1", "X1-S1-B2");
+        let fab_mats = trade_task("FAB_MATS", "X1-S1-A1", "X1-S1-B2");
+        let refresh = Task {
+            id: "refresh".to_string(),
+            actions: TaskActions::VisitLocation {
+                waypoint: WaypointSymbol::new("X1-S1-A1"),
+                action: Action::RefreshMarket,
+            },
+            value: 1000,
+            not_before: None,
+        };
+        let goods = |goods: &[&str]| goods.iter().map(|g| g.to_string()).collect();
+
+        let unfiltered = GoodFilter::default();
+        assert!(is_trade_allowed(&iron, &unfiltered));
+        assert!(is_trade_allowed(&fab_mats, &unfiltered));
+
+        let denied = GoodFilter {
+            allowlist: None,
+            denylist: goods(&["FAB_MATS"]),
+        };
+        assert!(is_trade_allowed(&iron, &denied));
+        assert!(!is_trade_allowed(&fab_mats, &denied));
+
+        // the denylist wins over the allowlist, other tasks are unaffected
+        let allowed = GoodFilter {
+            allowlist: Some(goods(&["IRON", "FAB_MATS"])),
+            denylist: goods(&["FAB_MATS"]),
+        };
+        assert!(is_trade_allowed(&iron, &allowed));
+        assert!(!is_trade_allowed(&fab_mats, &allowed));
+        let only_copper = GoodFilter {
+            allowlist: Some(goods(&["COPPER"])),
+            denylist: goods(&[]),
+        };
+        assert!(!is_trade_allowed(&iron, &only_copper));
+        assert!(is_trade_allowed(&refresh, &only_copper));
+
+        assert!(is_speculative(&iron));
+        assert!(!is_speculative(&refresh));
+
+        let trades = planned_trades(&iron);
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].market, WaypointSymbol::new("X1-S1-A1"));
+        assert_eq!(trades[0].side, TradeSide::Purchase);
+        assert_eq!(trades[1].market, WaypointSymbol::new("X1-S1-B2"));
+        assert_eq!((trades[1].side, trades[1].units), (TradeSide::Sell, 10));
+        assert!(planned_trades(&refresh).is_empty());
+    }
+
+    #[test]
+    fn test_task_deadlines() {
+        let deadline = Utc::now();
+        let contract_deadlines = DashMap::new();
+        contract_deadlines.insert(
+            (WaypointSymbol::new("X1-S1-B2"), "IRON".to_string()),
+            deadline,
+        );
+        let deliver = |good: &str| Task {
+            id: format!("contract_{}", good),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new("X1-S1-A1"),
+                dest: WaypointSymbol::new("X1-S1-B2"),
+                src_action: Action::BuyGoods(good.to_string(), 10),
+                dest_action: Action::DeliverContract(good.to_string(), 10),
+            },
+            value: 1000,
+            not_before: None,
+        };
+        // trades of the same good to the destination aren't for the contract
+        let tasks = [
+            deliver("IRON"),
+            deliver("COPPER"),
+            trade_task("IRON", "X1-S1-A1", "X1-S1-B2"),
+        ];
+        let deadlines = task_deadlines(&tasks, &contract_deadlines);
+        assert_eq!(
+            deadlines,
+            BTreeMap::from([("contract_IRON".to_string(), deadline)])
+        );
+    }
+
+    #[test]
+    fn test_tasks_conflict() {
+        let a = trade_task("FUEL", "X1-S1-A1", "X1-S1-B2");
+        assert!(tasks_conflict(&a, &a));
+        // same good, shared market
+        assert!(tasks_conflict(
+            &a,
+            &trade_task("FUEL", "X1-S1-A1", "X1-S1-C3")
+        ));
+        assert!(tasks_conflict(
+            &a,
+            &trade_task("FUEL", "X1-S1-D4", "X1-S1-B2")
+        ));
+        // same good, different route
+        assert!(!tasks_conflict(
+            &a,
+            &trade_task("FUEL", "X1-S1-D4", "X1-S1-C3")
+        ));
+        // different good, same route
+        assert!(!tasks_conflict(
+            &a,
+            &trade_task("FOOD", "X1-S1-A1", "X1-S1-B2")
+        ));
+    }
+
+    #[test]
+    fn test_market_staleness() {
+        let clock = TestClock::new(Utc::now());
+        let refreshed = clock.now();
+        assert!(is_market_stale(None, clock.now()));
+        assert!(!is_market_stale(Some(refreshed), clock.now()));
+        clock.advance(Duration::try_minutes(179).unwrap());
+        assert!(!is_market_stale(Some(refreshed), clock.now()));
+        clock.advance(Duration::try_minutes(1).unwrap());
+        assert!(is_market_stale(Some(refreshed), clock.now()));
+    }
+
+    #[test]
+    fn test_task_exclusivity_expiry() {
+        let clock = TestClock::new(Utc::now());
+        let in_progress_tasks = DashMap::new();
+        let task = trade_task("IRON", "X1-S1-A1", "X1-S1-B2");
+        in_progress_tasks.insert(
+            task.id.clone(),
+            (task.clone(), "A-1".to_string(), clock.now()),
+        );
+        // A-3 is still running its script, on a schedule longer than the window
+        let long_haul = trade_task("FUEL", "X1-S1-A1", "X1-S1-C3");
+        in_progress_tasks.insert(
+            long_haul.id.clone(),
+            (long_haul.clone(), "A-3".to_string(), clock.now()),
+        );
+        let live = |ship: &str| ship == "A-3";
+        clock.advance(Duration::try_minutes(30).unwrap());
+        let later = trade_task("FOOD", "X1-S1-A1", "X1-S1-B2");
+        in_progress_tasks.insert(
+            later.id.clone(),
+            (later.clone(), "A-2".to_string(), clock.now()),
+        );
+
+        clock.advance(Duration::try_minutes(30).unwrap());
+        expire_in_progress_tasks(&in_progress_tasks, clock.now(), live);
+        assert_eq!(in_progress_tasks.len(), 3);
+        clock.advance(Duration::try_minutes(1).unwrap());
+        expire_in_progress_tasks(&in_progress_tasks, clock.now(), live);
+        assert!(!in_progress_tasks.contains_key(&task.id));
+        assert!(in_progress_tasks.contains_key(&later.id));
+        assert!(in_progress_tasks.contains_key(&long_haul.id));
+        clock.advance(Duration::try_minutes(30).unwrap());
+        expire_in_progress_tasks(&in_progress_tasks, clock.now(), live);
+        assert_eq!(in_progress_tasks.len(), 1);
+        assert!(in_progress_tasks.contains_key(&long_haul.id));
+
+        // once its script stops, the ship's task expires too
+        expire_in_progress_tasks(&in_progress_tasks, clock.now(), |_| false);
+        assert!(in_progress_tasks.is_empty());
+    }
+
+    #[test]
+    fn test_route_cycles() {
+        let now = Utc::now();
+        let restock = max(restock_time(Some(Strong)), restock_time(Some(Weak)));
+        assert_eq!(restock, Duration::try_minutes(30).unwrap());
+
+        let mut task = trade_task("IRON", "X1-S1-A1", "X1-S1-B2");
+        task.value = 10000;
+        let cycles = route_cycles(task.clone(), restock, now);
+        assert_eq!(cycles.len(), MAX_ROUTE_CYCLES as usize);
+        assert_eq!(cycles[0], task);
+        assert_eq!(cycles[1].id, format!("{}#2", task.id));
+        assert_eq!(cycles[1].value, 9000);
+        assert_eq!(
+            cycles[2].not_before,
+            Some(now + Duration::try_hours(1).unwrap())
+        );
+        // the repeats are for the same route, so no other ship takes them while one is on it
+        assert!(tasks_conflict(&cycles[0], &cycles[2]));
+    }
+
+    #[test]
+    fn test_task_backlog() {
+        let now = Utc::now();
+        let mut iron = trade_task("IRON", "X1-S1-A1", "X1-S1-B2");
+        iron.value = 10000;
+        let mut food = trade_task("FOOD", "X1-S1-A1", "X1-S1-B2");
+        food.value = 4000;
+        let mut tasks = route_cycles(iron.clone(), Duration::try_minutes(10).unwrap(), now);
+        tasks.push(food.clone());
+        tasks.push(Task {
+            id: "refresh".to_string(),
+            actions: TaskActions::VisitLocation {
+                waypoint: WaypointSymbol::new("X1-S1-A1"),
+                action: Action::RefreshMarket,
+            },
+            value: 5000,
+            not_before: None,
+        });
+
+        // repeat trips and market refreshes aren't backlog
+        let backlog = task_backlog(&tasks, [].iter());
+        assert_eq!(
+            backlog,
+            TaskBacklog {
+                tasks: 2,
+                value: 14000
+            }
+        );
+        let in_progress = [iron];
+        let backlog = task_backlog(&tasks, in_progress.iter());
+        assert_eq!(backlog.value, 4000);
+    }
+
+    #[test]
+    fn test_refresh_market_value() {
+        let now = Utc::now();
+        let mins = |m: i64| now + Duration::try_minutes(m).unwrap();
+        assert_eq!(refresh_market_value(None, false, now), Some(20000));
+        assert_eq!(refresh_market_value(None, true, now), None);
+        // a probe two minutes out will refresh it anyway
+        assert_eq!(refresh_market_value(Some(mins(2)), true, now), None);
+        assert_eq!(refresh_market_value(Some(mins(30)), true, now), Some(10000));
+        assert_eq!(
+            refresh_market_value(Some(mins(240)), true, now),
+            Some(20000)
+        );
+        // the probe never arrived
+        assert_eq!(
+            refresh_market_value(Some(mins(-30)), true, now),
+            Some(20000)
+        );
+    }
+
+    // Needs a database: DATABASE_URL=... cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn test_migrate_legacy_task_manager_state() {
+        let db = DbClient::new(&format!("test-{}", uuid::Uuid::new_v4())).await;
+        db.run_migrations().await.unwrap();
+        let system = SystemSymbol::new("X1-S1");
+        let legacy = DashMap::<String, (Task, String, DateTime<Utc>)>::new();
+        for (task_id, ship) in [("trade_a", "A-1"), ("trade_b", "B-1"), ("trade_ab", "AB-1")] {
+            legacy.insert(
+                task_id.to_string(),
+                (
+                    trade_task(task_id, "X1-S1-A1", "X1-S1-B2"),
+                    ship.to_string(),
+                    Utc::now(),
+                ),
+            );
+        }
+        db.set_versioned(&format!("task_manager/{}", system), &legacy)
+            .await
+            .unwrap();
+        let manual = DashMap::<String, Task>::new();
+        manual.insert(
+            "manual_1".to_string(),
+            trade_task("FUEL", "X1-S1-A1", "X1-S1-B2"),
+        );
+        db.set_value(&format!("manual_tasks/{}", system), &manual)
+            .await
+            .unwrap();
+
+        // reservations go to the agent whose ship holds them, manual tasks to the first agent
+        for callsign in ["A", "B", "A"] {
+            db.migrate_legacy_task_manager_state(callsign, &system)
+                .await
+                .unwrap();
+        }
+        let state = |callsign: &'static str| {
+            let db = db.clone();
+            let system = system.clone();
+            async move {
+                let state = db
+                    .load_task_manager_state(callsign, &system)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                let mut tasks = state.iter().map(|x| x.key().clone()).collect::<Vec<_>>();
+                tasks.sort();
+                tasks
+            }
+        };
+        assert_eq!(state("A").await, vec!["trade_a".to_string()]);
+        assert_eq!(state("B").await, vec!["trade_b".to_string()]);
+        let manual_tasks = |callsign| db.load_manual_tasks(callsign, &system);
+        assert_eq!(manual_tasks("A").await.unwrap().unwrap().len(), 1);
+        assert!(manual_tasks("B").await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    // Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+    async fn test_release_ship_tasks() {
+        use crate::test_harness::TestAgent;
+        let test = TestAgent::builder().build().await;
+        let task_manager = &test.agent_controller.task_manager;
+        let ship = test.agent_controller.ship("MOCK-1").unwrap();
+        let config = LogisticsScriptConfig {
+            use_planner: false,
+            allow_shipbuying: false,
+            allow_construction: false,
+            allow_gate_trades: false,
+            allow_market_refresh: false,
+            waypoint_allowlist: None,
+            min_profit: i64::MAX,
+        };
+        let schedule = task_manager
+            .take_tasks(
+                "MOCK-1",
+                &ship.nav.system_symbol,
+                &config,
+                ship.cargo.capacity,
+                ship.engine.speed,
+                ship.fuel.capacity,
+                &ship.nav.waypoint_symbol,
+                Duration::try_minutes(15).unwrap(),
+            )
+            .await;
+        assert!(schedule.actions.is_empty());
+        assert_eq!(task_manager.idle_logistics_ships(), vec!["MOCK-1"]);
+        // only markets in the systems logistics ships serve have consumers
+        let elsewhere = WaypointSymbol::new("X1-ELSE-A1");
+        assert!(!task_manager.market_has_consumers(&elsewhere));
+        let mut updates = task_manager.subscribe_updates(&ship.nav.system_symbol);
+
+        // handed over to another job, the ship no longer hauls in the system
+        task_manager.release_ship_tasks("MOCK-1").await;
+        assert!(task_manager.idle_logistics_ships().is_empty());
+        assert!(task_manager.market_has_consumers(&elsewhere));
+        // idle ships are woken to pick up what it held
+        let woken = tokio::time::timeout(std::time::Duration::from_secs(1), updates.changed());
+        assert!(woken.await.is_ok());
+    }
 }
@@ -0,0 +1,61 @@
+//!
+//! OpenTelemetry tracing.
+//!
+//! Spans for planner runs, API calls, DB queries and ship script steps, exported to an OTLP/HTTP
+//! collector when OTLP_ENDPOINT is set. Spans started inside a span's future are its children, so a
+//! ship step shows the navigation, API calls and waits it spent its time on. Without an endpoint the
+//! global tracer is a no-op.
+//!
+use log::*;
+use opentelemetry::trace::{FutureExt, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use std::future::Future;
+
+const TRACER_NAME: &str = "st";
+
+// Install the OTLP exporter as the global tracer provider
+pub fn init(endpoint: &str, callsign: &str) {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .expect("Failed to build OTLP span exporter");
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", "st"),
+            KeyValue::new("agent.callsign", callsign.to_string()),
+        ]))
+        .build();
+    global::set_tracer_provider(provider);
+    info!("Exporting traces to {}", endpoint);
+}
+
+// Run the future inside a new span, a child of the current span if there is one
+pub async fn in_span<F: Future>(
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+    future: F,
+) -> F::Output {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(name)
+        .with_attributes(attributes)
+        .start(&tracer);
+    let cx = Context::current_with_span(span);
+    let output = future.with_context(cx.clone()).await;
+    cx.span().end();
+    output
+}
+
+// Add an attribute to the current span, e.g. a result only known once the work is done
+pub fn record(attribute: KeyValue) {
+    Context::current().span().set_attribute(attribute);
+}
+
+// Mark a point in time on the current span
+pub fn event(name: String, attributes: Vec<KeyValue>) {
+    Context::current().span().add_event(name, attributes);
+}
@@ -0,0 +1,51 @@
+/// Fixtures and builders for unit tests. The JSON under /fixtures is hand-written in the shape of
+/// API responses, not recorded from the live server
+use crate::agent_controller::goals::{Goal, GoalStatus};
+use crate::api_client::api_models::WaypointDetailed;
+use crate::models::*;
+use chrono::Utc;
+
+pub fn starter_system_waypoints() -> Vec<WaypointDetailed> {
+    serde_json::from_str(include_str!("../fixtures/starter_system_waypoints.json")).unwrap()
+}
+
+pub fn probe(symbol: &str, waypoint: &WaypointSymbol) -> Ship {
+    let mut ship: Ship = serde_json::from_str(include_str!("../fixtures/ship_probe.json")).unwrap();
+    ship.symbol = symbol.to_string();
+    ship.nav.system_symbol = waypoint.system();
+    ship.nav.waypoint_symbol = waypoint.clone();
+    ship
+}
+
+pub fn job(id: &str, ship_model: &str, behaviour: ShipBehaviour) -> ShipConfig {
+    ShipConfig {
+        id: id.to_string(),
+        ship_model: ship_model.to_string(),
+        purchase_criteria: PurchaseCriteria::default(),
+        behaviour,
+    }
+}
+
+pub fn logistics_job(id: &str, ship_model: &str) -> ShipConfig {
+    job(
+        id,
+        ship_model,
+        ShipBehaviour::Logistics(LogisticsScriptConfig {
+            use_planner: true,
+            waypoint_allowlist: None,
+            allow_shipbuying: false,
+            allow_market_refresh: false,
+            allow_construction: false,
+            allow_gate_trades: false,
+            min_profit: 1,
+        }),
+    )
+}
+
+pub fn completed_goal(goal: Goal) -> GoalStatus {
+    GoalStatus {
+        progress: 1.0,
+        completed_at: Some(Utc::now()),
+        ..GoalStatus::new(goal, 0)
+    }
+}
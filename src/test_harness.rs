@@ -0,0 +1,106 @@
+//!
+//! End-to-end harness for agent controller tests (feature `mock_server`).
+//!
+//! Runs the real AgentController, ShipControllers and Universe against an in-process mock server, whose
+//! universe is the starter system fixture. State goes to the database at DATABASE_URL under a fresh
+//! reset id, so every harness starts from an empty agent and tests can run side by side.
+//!
+//! Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+//!
+use crate::agent_controller::AgentController;
+use crate::api_client::ApiClient;
+use crate::db::DbClient;
+use crate::mock_server::MockServer;
+use crate::models::ShipConfig;
+use crate::ship_controller::ShipController;
+use crate::universe::UniverseHandle;
+use std::time::Duration;
+
+pub struct TestAgent {
+    pub agent_controller: AgentController,
+    pub api_client: ApiClient,
+    pub universe: UniverseHandle,
+    pub db: DbClient,
+}
+
+pub struct TestAgentBuilder {
+    callsign: String,
+    time_scale: f64,
+    ship_config: Vec<ShipConfig>,
+}
+
+impl TestAgent {
+    pub fn builder() -> TestAgentBuilder {
+        TestAgentBuilder {
+            callsign: "MOCK".to_string(),
+            time_scale: 0.0,
+            ship_config: vec![],
+        }
+    }
+
+    pub fn ship_controller(&self, ship_symbol: &str) -> ShipController {
+        self.agent_controller.ship_controller(ship_symbol)
+    }
+
+    // Polls until the condition holds, panics after the timeout
+    pub async fn wait_until(&self, what: &str, timeout: Duration, condition: impl Fn() -> bool) {
+        let start = std::time::Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < timeout, "Timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+impl TestAgentBuilder {
+    pub fn callsign(mut self, callsign: &str) -> Self {
+        self.callsign = callsign.to_string();
+        self
+    }
+
+    // Multiplies the mock server's transit and cooldown times, 0.0 (the default) for instant
+    pub fn time_scale(mut self, time_scale: f64) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+
+    // Jobs to start with, instead of waiting for the generated config
+    pub fn ship_config(mut self, ship_config: Vec<ShipConfig>) -> Self {
+        self.ship_config = ship_config;
+        self
+    }
+
+    pub async fn build(self) -> TestAgent {
+        // RUST_LOG=st=debug to follow a failing test
+        let _ = pretty_env_logger::try_init();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        // CONFIG needs an API_BASE_URL, requests go to the client's own base url
+        if std::env::var("API_BASE_URL").is_err() {
+            std::env::set_var("API_BASE_URL", &base_url);
+        }
+        tokio::spawn(MockServer::new(self.time_scale).serve(listener));
+
+        let api_client = ApiClient::with_base_url(&base_url);
+        let reset_id = format!("test-{}", uuid::Uuid::new_v4());
+        let db = DbClient::new(&reset_id).await;
+        db.run_migrations().await.unwrap();
+        let universe = UniverseHandle::new(&api_client, &db);
+        universe.init().await.unwrap();
+
+        let token = api_client
+            .register("COSMIC", &self.callsign)
+            .await
+            .expect("Failed to register test agent");
+        api_client.set_agent_token(&token);
+        let agent_controller =
+            AgentController::new(&api_client, &db, &universe, &self.callsign).await;
+        agent_controller.set_ship_config(self.ship_config);
+        TestAgent {
+            agent_controller,
+            api_client,
+            universe,
+            db,
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//!
+//! Trade volume evolution model.
+//!
+//! Learns from the market_trades history how long each market takes to double or halve the trade volume
+//! of a good, and forecasts trade volumes from that. Markets without history are forecast not to change.
+//! A trend expires once the volume hasn't moved for twice its usual period.
+//!
+use crate::models::WaypointSymbol;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+// Cap on forecast doublings/halvings, so a fast-evolving market doesn't produce absurd volumes
+const MAX_FORECAST_STEPS: i64 = 3;
+// A trend is stale once this many of its periods pass without the volume moving again
+const TREND_EXPIRY_PERIODS: i32 = 2;
+
+// Chronological trade volume observations per (market, good)
+pub type TradeVolumeHistory = BTreeMap<(WaypointSymbol, String), Vec<(DateTime<Utc>, i64)>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Growing,
+    Shrinking,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Periods {
+    doubling: Vec<Duration>,
+    halving: Vec<Duration>,
+    last_trend: Option<Trend>,
+    last_change: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evolution {
+    // Mean time for the trade volume to double/halve, if it has been observed
+    pub doubling: Option<Duration>,
+    pub halving: Option<Duration>,
+    pub trend: Option<Trend>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TradeVolumeModel {
+    markets: BTreeMap<(WaypointSymbol, String), Evolution>,
+}
+
+fn mean(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    let total: Duration = durations.iter().sum();
+    Some(total / durations.len() as i32)
+}
+
+impl TradeVolumeModel {
+    pub fn fit(history: &TradeVolumeHistory, now: DateTime<Utc>) -> Self {
+        let mut markets = BTreeMap::new();
+        for (key, series) in history {
+            let mut periods = Periods::default();
+            let mut anchor = match series.first() {
+                Some(first) => *first,
+                None => continue,
+            };
+            for &(timestamp, trade_volume) in &series[1..] {
+                let (anchor_timestamp, anchor_volume) = anchor;
+                if trade_volume >= anchor_volume * 2 {
+                    periods.doubling.push(timestamp - anchor_timestamp);
+                    periods.last_trend = Some(Trend::Growing);
+                    periods.last_change = Some(timestamp);
+                    anchor = (timestamp, trade_volume);
+                } else if trade_volume * 2 <= anchor_volume {
+                    periods.halving.push(timestamp - anchor_timestamp);
+                    periods.last_trend = Some(Trend::Shrinking);
+                    periods.last_change = Some(timestamp);
+                    anchor = (timestamp, trade_volume);
+                }
+            }
+            let doubling = mean(&periods.doubling);
+            let halving = mean(&periods.halving);
+            let period = match periods.last_trend {
+                Some(Trend::Growing) => doubling,
+                Some(Trend::Shrinking) => halving,
+                None => None,
+            };
+            let stale = match (period, periods.last_change) {
+                (Some(period), Some(last_change)) => {
+                    now - last_change > period * TREND_EXPIRY_PERIODS
+                }
+                _ => false,
+            };
+            markets.insert(
+                key.clone(),
+                Evolution {
+                    doubling,
+                    halving,
+                    trend: periods.last_trend.filter(|_| !stale),
+                },
+            );
+        }
+        Self { markets }
+    }
+
+    pub fn evolution(&self, market: &WaypointSymbol, good: &str) -> Option<&Evolution> {
+        self.markets.get(&(market.clone(), good.to_string()))
+    }
+
+    // Predicted time until the trade volume next doubles
+    pub fn time_to_double(&self, market: &WaypointSymbol, good: &str) -> Option<Duration> {
+        let evolution = self.evolution(market, good)?;
+        match evolution.trend {
+            Some(Trend::Growing) => evolution.doubling,
+            _ => None,
+        }
+    }
+
+    // Forecast the trade volume `horizon` from now, following the market's most recent trend
+    pub fn forecast(
+        &self,
+        market: &WaypointSymbol,
+        good: &str,
+        trade_volume: i64,
+        horizon: Duration,
+    ) -> i64 {
+        let Some(evolution) = self.evolution(market, good) else {
+            return trade_volume;
+        };
+        let steps = |period: Option<Duration>| match period {
+            Some(period) if period > Duration::zero() => {
+                (horizon.num_seconds() / period.num_seconds().max(1)).min(MAX_FORECAST_STEPS)
+            }
+            _ => 0,
+        };
+        match evolution.trend {
+            Some(Trend::Growing) => trade_volume << steps(evolution.doubling),
+            Some(Trend::Shrinking) => (trade_volume >> steps(evolution.halving)).max(1),
+            None => trade_volume,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trade_volume_model() {
+        let t0 = Utc::now();
+        let hours = |h: i64| t0 + Duration::try_hours(h).unwrap();
+        let smeltery = WaypointSymbol::new("X1-AB12-A1");
+        let refinery = WaypointSymbol::new("X1-AB12-B2");
+        let history = BTreeMap::from([
+            (
+                (smeltery.clone(), "IRON".to_string()),
+                vec![(t0, 60), (hours(1), 100), (hours(2), 120), (hours(6), 240)],
+            ),
+            (
+                (refinery.clone(), "FUEL".to_string()),
+                vec![(t0, 180), (hours(3), 90)],
+            ),
+        ]);
+        let model = TradeVolumeModel::fit(&history, hours(7));
+
+        let iron = model.evolution(&smeltery, "IRON").unwrap();
+        assert_eq!(iron.doubling, Some(Duration::try_hours(3).unwrap()));
+        assert_eq!(iron.trend, Some(Trend::Growing));
+        let horizon = Duration::try_hours(7).unwrap();
+        assert_eq!(model.forecast(&smeltery, "IRON", 240, horizon), 960);
+        assert_eq!(model.forecast(&refinery, "FUEL", 90, horizon), 22);
+
+        assert_eq!(
+            model.time_to_double(&smeltery, "IRON"),
+            Some(Duration::try_hours(3).unwrap())
+        );
+        assert_eq!(model.time_to_double(&refinery, "FUEL"), None);
+        let unseen = WaypointSymbol::new("X1-AB12-C3");
+        assert_eq!(model.time_to_double(&unseen, "IRON"), None);
+        assert_eq!(model.forecast(&unseen, "IRON", 60, horizon), 60);
+
+        // the iron market stops growing: two doubling periods later the trend has expired
+        let model = TradeVolumeModel::fit(&history, hours(13));
+        let iron = model.evolution(&smeltery, "IRON").unwrap();
+        assert_eq!(iron.doubling, Some(Duration::try_hours(3).unwrap()));
+        assert_eq!(iron.trend, None);
+        assert_eq!(model.time_to_double(&smeltery, "IRON"), None);
+        assert_eq!(model.forecast(&smeltery, "IRON", 240, horizon), 240);
+    }
+}
@@ -0,0 +1,241 @@
+/// Read-only and mutating views of the universe.
+/// Most Universe methods fall back to the API on a cache miss and write what they fetch. The web server
+/// and analytics only get a UniverseReader, which answers from memory and the database and returns
+/// None for what isn't known yet. The agent's own code, which keeps the universe up to date, holds the
+/// UniverseHandle and so has both
+use super::fuel_ledger::FuelReport;
+use super::market_deltas::MarketDeltas;
+use super::pathfinding::WarpRoute;
+use super::Universe;
+use crate::api_client::api_models::WaypointDetailed;
+use crate::db::ok_or_warn;
+use crate::models::{
+    Construction, Market, MarketRemoteView, System, SystemSymbol, WaypointSymbol, WithTimestamp,
+};
+use crate::pathfinding::{Route, Urgency};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+// Each market waypoint of a system, with its snapshot if it has one
+pub type SystemMarkets = Vec<(MarketRemoteView, Option<Arc<WithTimestamp<Market>>>)>;
+
+pub trait UniverseReader: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+    fn systems(&self) -> Vec<Arc<System>>;
+    // Waypoint details of the system, None if they haven't been fetched yet
+    fn known_system_waypoints(&self, symbol: &SystemSymbol) -> Option<Vec<WaypointDetailed>>;
+    fn known_waypoint(&self, symbol: &WaypointSymbol) -> Option<WaypointDetailed>;
+    fn known_jumpgate(&self, symbol: &SystemSymbol) -> Option<WaypointSymbol>;
+    fn market_deltas_since(&self, symbol: &SystemSymbol, since: DateTime<Utc>) -> MarketDeltas;
+    fn fuel_report(&self) -> FuelReport;
+    fn charted_by(&self, agent: &str) -> Vec<WaypointSymbol>;
+
+    fn get_market<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Option<Arc<WithTimestamp<Market>>>>;
+    // Snapshots of the system's markets that have been refreshed at least once
+    fn known_system_markets<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<Arc<WithTimestamp<Market>>>>;
+    fn known_construction<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Option<Arc<WithTimestamp<Option<Construction>>>>>;
+    fn known_route<'a>(
+        &'a self,
+        src: &'a WaypointSymbol,
+        dest: &'a WaypointSymbol,
+        speed: i64,
+        start_fuel: i64,
+        fuel_capacity: i64,
+        urgency: Urgency,
+    ) -> BoxFuture<'a, Option<Route>>;
+    // Route over the warp graph, if the explorers have built it
+    fn known_warp_route<'a>(
+        &'a self,
+        src: &'a SystemSymbol,
+        dest: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Option<WarpRoute>>;
+}
+
+pub trait UniverseUpdater: UniverseReader {
+    fn get_system_waypoints<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<WaypointDetailed>>;
+    fn refresh_system_waypoints<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<WaypointDetailed>>;
+    fn get_system_markets<'a>(&'a self, symbol: &'a SystemSymbol) -> BoxFuture<'a, SystemMarkets>;
+    fn get_construction<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Arc<WithTimestamp<Option<Construction>>>>;
+    fn save_market<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+        market: WithTimestamp<Market>,
+    ) -> BoxFuture<'a, ()>;
+    fn warp_route<'a>(
+        &'a self,
+        src: &'a SystemSymbol,
+        dest: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Option<WarpRoute>>;
+}
+
+// Inherent methods take precedence, so `self.x()` below calls Universe::x rather than recursing
+impl UniverseReader for Universe {
+    fn now(&self) -> DateTime<Utc> {
+        self.now()
+    }
+
+    fn systems(&self) -> Vec<Arc<System>> {
+        self.systems()
+    }
+
+    fn known_system_waypoints(&self, symbol: &SystemSymbol) -> Option<Vec<WaypointDetailed>> {
+        self.known_system_waypoints(symbol)
+    }
+
+    fn known_waypoint(&self, symbol: &WaypointSymbol) -> Option<WaypointDetailed> {
+        self.known_system_waypoints(&symbol.system())?
+            .into_iter()
+            .find(|waypoint| &waypoint.symbol == symbol)
+    }
+
+    fn known_jumpgate(&self, symbol: &SystemSymbol) -> Option<WaypointSymbol> {
+        self.system(symbol)
+            .waypoints
+            .iter()
+            .find(|waypoint| waypoint.waypoint_type == "JUMP_GATE")
+            .map(|waypoint| waypoint.symbol.clone())
+    }
+
+    fn market_deltas_since(&self, symbol: &SystemSymbol, since: DateTime<Utc>) -> MarketDeltas {
+        self.market_deltas_since(symbol, since)
+    }
+
+    fn fuel_report(&self) -> FuelReport {
+        self.fuel_report()
+    }
+
+    fn charted_by(&self, agent: &str) -> Vec<WaypointSymbol> {
+        self.charted_by(agent)
+    }
+
+    fn get_market<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Option<Arc<WithTimestamp<Market>>>> {
+        Box::pin(self.get_market(symbol))
+    }
+
+    fn known_system_markets<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<Arc<WithTimestamp<Market>>>> {
+        Box::pin(async move {
+            let mut markets = vec![];
+            for waypoint in self.known_system_waypoints(symbol).unwrap_or_default() {
+                if !waypoint.is_market() {
+                    continue;
+                }
+                if let Some(market) = self.get_market(&waypoint.symbol).await {
+                    markets.push(market);
+                }
+            }
+            markets
+        })
+    }
+
+    fn known_construction<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Option<Arc<WithTimestamp<Option<Construction>>>>> {
+        Box::pin(async move {
+            if let Some(construction) = self.constructions.get(symbol) {
+                return Some(construction.clone());
+            }
+            let stored = self.db.get_construction(symbol).await;
+            let construction = ok_or_warn(stored, &format!("load construction {}", symbol));
+            let construction = Arc::new(construction.flatten()?);
+            self.constructions
+                .insert(symbol.clone(), construction.clone());
+            Some(construction)
+        })
+    }
+
+    fn known_route<'a>(
+        &'a self,
+        src: &'a WaypointSymbol,
+        dest: &'a WaypointSymbol,
+        speed: i64,
+        start_fuel: i64,
+        fuel_capacity: i64,
+        urgency: Urgency,
+    ) -> BoxFuture<'a, Option<Route>> {
+        Box::pin(async move {
+            // with the waypoints known, routing doesn't fetch anything
+            self.known_system_waypoints(&src.system())?;
+            let route = self
+                .get_route(src, dest, speed, start_fuel, fuel_capacity, urgency)
+                .await;
+            Some(route)
+        })
+    }
+
+    fn known_warp_route<'a>(
+        &'a self,
+        src: &'a SystemSymbol,
+        dest: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Option<WarpRoute>> {
+        Box::pin(self.known_warp_route(src, dest))
+    }
+}
+
+impl UniverseUpdater for Universe {
+    fn get_system_waypoints<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<WaypointDetailed>> {
+        Box::pin(self.get_system_waypoints(symbol))
+    }
+
+    fn refresh_system_waypoints<'a>(
+        &'a self,
+        symbol: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Vec<WaypointDetailed>> {
+        Box::pin(self.refresh_system_waypoints(symbol))
+    }
+
+    fn get_system_markets<'a>(&'a self, symbol: &'a SystemSymbol) -> BoxFuture<'a, SystemMarkets> {
+        Box::pin(self.get_system_markets(symbol))
+    }
+
+    fn get_construction<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+    ) -> BoxFuture<'a, Arc<WithTimestamp<Option<Construction>>>> {
+        Box::pin(self.get_construction(symbol))
+    }
+
+    fn save_market<'a>(
+        &'a self,
+        symbol: &'a WaypointSymbol,
+        market: WithTimestamp<Market>,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(self.save_market(symbol, market))
+    }
+
+    fn warp_route<'a>(
+        &'a self,
+        src: &'a SystemSymbol,
+        dest: &'a SystemSymbol,
+    ) -> BoxFuture<'a, Option<WarpRoute>> {
+        Box::pin(self.warp_route(src, dest))
+    }
+}
@@ -0,0 +1,249 @@
+/// Fuel bought and burned by our ships.
+/// Purchases are recorded from refuel transactions, consumption from the fuel reported after each
+/// navigation, against the route and flight mode it was flown in. Comparing BURN and CRUISE trips on a
+/// route shows whether the extra fuel of BURN is worth the time it saves. Routes where it isn't are
+/// flown in CRUISE by the pathfinder.
+use crate::models::{ShipFlightMode, SystemSymbol, WaypointSymbol};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// BURN trips flown on a route before it's judged
+const MIN_BURN_TRIPS: i64 = 3;
+// Part of every navigation's duration that doesn't depend on flight mode or distance
+const NAV_BASE_SECONDS: f64 = 15.0;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShipFuelTotals {
+    pub purchased_units: i64,
+    pub purchased_credits: i64,
+    pub consumed_units: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TripTotals {
+    pub trips: i64,
+    pub fuel: i64,
+    pub seconds: i64,
+}
+
+impl TripTotals {
+    fn avg_fuel(&self) -> f64 {
+        self.fuel as f64 / self.trips as f64
+    }
+
+    fn avg_seconds(&self) -> f64 {
+        self.seconds as f64 / self.trips as f64
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RouteFuel {
+    pub burn: TripTotals,
+    pub cruise: TripTotals,
+}
+
+impl RouteFuel {
+    // (extra fuel, seconds saved) per trip by flying BURN instead of CRUISE
+    pub fn burn_premium(&self) -> Option<(f64, f64)> {
+        if self.burn.trips < MIN_BURN_TRIPS {
+            return None;
+        }
+        let burn_fuel = self.burn.avg_fuel();
+        let burn_seconds = self.burn.avg_seconds();
+        let (cruise_fuel, cruise_seconds) = match self.cruise.trips {
+            // CRUISE uses half the fuel of BURN and travels at half the speed
+            0 => (
+                burn_fuel / 2.0,
+                NAV_BASE_SECONDS + 2.0 * (burn_seconds - NAV_BASE_SECONDS),
+            ),
+            _ => (self.cruise.avg_fuel(), self.cruise.avg_seconds()),
+        };
+        Some((burn_fuel - cruise_fuel, cruise_seconds - burn_seconds))
+    }
+
+    // Whether the time BURN saves, valued at `time_value` credits per second, pays for its extra fuel
+    pub fn burn_worthwhile(&self, fuel_price: f64, time_value: f64) -> Option<bool> {
+        let (extra_fuel, seconds_saved) = self.burn_premium()?;
+        Some(seconds_saved * time_value >= extra_fuel * fuel_price)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteFuelReport {
+    pub src: WaypointSymbol,
+    pub dest: WaypointSymbol,
+    pub burn: TripTotals,
+    pub cruise: TripTotals,
+    pub burn_extra_fuel: Option<f64>,
+    pub burn_seconds_saved: Option<f64>,
+    pub burn_worthwhile: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FuelReport {
+    // credits per unit of ship fuel
+    pub fuel_price: Option<f64>,
+    pub ships: BTreeMap<String, ShipFuelTotals>,
+    pub routes: Vec<RouteFuelReport>,
+}
+
+#[derive(Debug, Default)]
+pub struct FuelLedger {
+    ships: DashMap<String, ShipFuelTotals>,
+    // (src, dest) -> trips flown
+    routes: DashMap<(WaypointSymbol, WaypointSymbol), RouteFuel>,
+}
+
+impl FuelLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_purchase(&self, ship_symbol: &str, units: i64, total_price: i64) {
+        if units <= 0 {
+            return;
+        }
+        let mut ship = self.ships.entry(ship_symbol.to_string()).or_default();
+        ship.purchased_units += units;
+        ship.purchased_credits += total_price;
+    }
+
+    pub fn record_trip(
+        &self,
+        ship_symbol: &str,
+        src: &WaypointSymbol,
+        dest: &WaypointSymbol,
+        flight_mode: &ShipFlightMode,
+        fuel: i64,
+        seconds: i64,
+    ) {
+        self.ships
+            .entry(ship_symbol.to_string())
+            .or_default()
+            .consumed_units += fuel;
+        let mut route = self.routes.entry((src.clone(), dest.clone())).or_default();
+        let totals = match flight_mode {
+            ShipFlightMode::Burn => &mut route.burn,
+            ShipFlightMode::Cruise => &mut route.cruise,
+            ShipFlightMode::Drift | ShipFlightMode::Stealth => return,
+        };
+        totals.trips += 1;
+        totals.fuel += fuel;
+        totals.seconds += seconds;
+    }
+
+    // Average price paid per unit of ship fuel, across all ships
+    pub fn fuel_price(&self) -> Option<f64> {
+        let (units, credits) = self.ships.iter().fold((0, 0), |(units, credits), ship| {
+            (
+                units + ship.purchased_units,
+                credits + ship.purchased_credits,
+            )
+        });
+        match units {
+            0 => None,
+            _ => Some(credits as f64 / units as f64),
+        }
+    }
+
+    // Routes in the system where BURN costs more in fuel than the time it saves is worth
+    pub fn cruise_only_routes(
+        &self,
+        system_symbol: &SystemSymbol,
+        time_value: f64,
+    ) -> BTreeSet<(WaypointSymbol, WaypointSymbol)> {
+        let Some(fuel_price) = self.fuel_price() else {
+            return BTreeSet::new();
+        };
+        self.routes
+            .iter()
+            .filter(|route| route.key().0.system() == *system_symbol)
+            .filter(|route| route.burn_worthwhile(fuel_price, time_value) == Some(false))
+            .map(|route| route.key().clone())
+            .collect()
+    }
+
+    pub fn report(&self, time_value: f64) -> FuelReport {
+        let fuel_price = self.fuel_price();
+        let ships = self
+            .ships
+            .iter()
+            .map(|ship| (ship.key().clone(), ship.value().clone()))
+            .collect();
+        let mut routes = self
+            .routes
+            .iter()
+            .map(|route| {
+                let (src, dest) = route.key().clone();
+                let premium = route.burn_premium();
+                RouteFuelReport {
+                    src,
+                    dest,
+                    burn: route.burn.clone(),
+                    cruise: route.cruise.clone(),
+                    burn_extra_fuel: premium.map(|(fuel, _)| fuel),
+                    burn_seconds_saved: premium.map(|(_, seconds)| seconds),
+                    burn_worthwhile: fuel_price
+                        .and_then(|price| route.burn_worthwhile(price, time_value)),
+                }
+            })
+            .collect::<Vec<_>>();
+        routes.sort_by(|a, b| (&a.src, &a.dest).cmp(&(&b.src, &b.dest)));
+        FuelReport {
+            fuel_price,
+            ships,
+            routes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fuel_ledger() {
+        let ledger = FuelLedger::new();
+        let system = SystemSymbol::new("X1-AB12");
+        let a = WaypointSymbol::new("X1-AB12-A1");
+        let b = WaypointSymbol::new("X1-AB12-B2");
+        let c = WaypointSymbol::new("X1-AB12-C3");
+        assert_eq!(ledger.fuel_price(), None);
+
+        // 2 credits per unit of fuel
+        ledger.record_purchase("A-1", 400, 800);
+        ledger.record_purchase("A-2", 100, 200);
+        assert_eq!(ledger.fuel_price(), Some(2.0));
+
+        // A -> B: BURN uses 100 more fuel to save 30 seconds
+        for _ in 0..3 {
+            ledger.record_trip("A-1", &a, &b, &ShipFlightMode::Burn, 200, 45);
+        }
+        // B -> C: BURN uses 10 more fuel to save 60 seconds, inferred without any CRUISE trips
+        for _ in 0..3 {
+            ledger.record_trip("A-2", &b, &c, &ShipFlightMode::Burn, 20, 75);
+        }
+        // C -> A: not enough trips to judge
+        ledger.record_trip("A-2", &c, &a, &ShipFlightMode::Burn, 500, 100);
+        ledger.record_trip("A-1", &a, &b, &ShipFlightMode::Cruise, 100, 75);
+
+        let report = ledger.report(1.0);
+        assert_eq!(report.ships["A-1"].consumed_units, 700);
+        assert_eq!(report.ships["A-2"].consumed_units, 560);
+        assert_eq!(report.routes[0].burn_extra_fuel, Some(100.0));
+        assert_eq!(report.routes[0].burn_seconds_saved, Some(30.0));
+        assert_eq!(report.routes[1].burn_extra_fuel, Some(10.0));
+        assert_eq!(report.routes[1].burn_seconds_saved, Some(60.0));
+        assert_eq!(report.routes[2].burn_worthwhile, None);
+
+        // at 1 credit per second, only A -> B isn't worth burning
+        let cruise_only = ledger.cruise_only_routes(&system, 1.0);
+        assert_eq!(cruise_only, BTreeSet::from([(a.clone(), b.clone())]));
+        // time valued highly enough to pay for the fuel
+        assert!(ledger.cruise_only_routes(&system, 10.0).is_empty());
+        assert!(ledger
+            .cruise_only_routes(&SystemSymbol::new("X1-CD34"), 1.0)
+            .is_empty());
+    }
+}
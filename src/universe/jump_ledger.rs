@@ -0,0 +1,92 @@
+/// Antimatter paid and cooldowns incurred by our jumps.
+/// Each jump is recorded against the pair of systems it connects, from its transaction and the cooldown
+/// it left the ship with. The router weighs jump edges by these, so routes trade jumping off against
+/// warping, and gate trades against staying home. Routes never jumped are estimated: the average cost
+/// over all our jumps, and the distance model for the cooldown
+use crate::models::SystemSymbol;
+use dashmap::DashMap;
+use serde::Serialize;
+
+// Antimatter for a jump, before any have been recorded
+pub const DEFAULT_JUMP_COST: i64 = 10_000;
+// Cooldown of a jump not yet flown, on top of a second per unit of distance
+const BASE_COOLDOWN_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JumpTotals {
+    pub jumps: i64,
+    pub credits: i64,
+    pub cooldown_seconds: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct JumpLedger {
+    // (src, dest) -> jumps made
+    routes: DashMap<(SystemSymbol, SystemSymbol), JumpTotals>,
+}
+
+impl JumpLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_jump(
+        &self,
+        src: &SystemSymbol,
+        dest: &SystemSymbol,
+        credits: i64,
+        cooldown_seconds: i64,
+    ) {
+        let mut route = self.routes.entry((src.clone(), dest.clone())).or_default();
+        route.jumps += 1;
+        route.credits += credits;
+        route.cooldown_seconds += cooldown_seconds;
+    }
+
+    // Credits of antimatter for a jump from `src` to `dest`
+    pub fn jump_cost(&self, src: &SystemSymbol, dest: &SystemSymbol) -> i64 {
+        if let Some(route) = self.routes.get(&(src.clone(), dest.clone())) {
+            return route.credits / route.jumps;
+        }
+        let (jumps, credits) = self.routes.iter().fold((0, 0), |(jumps, credits), route| {
+            (jumps + route.jumps, credits + route.credits)
+        });
+        match jumps {
+            0 => DEFAULT_JUMP_COST,
+            _ => credits / jumps,
+        }
+    }
+
+    // Seconds of cooldown after a jump from `src` to `dest`, `distance` apart
+    pub fn cooldown(&self, src: &SystemSymbol, dest: &SystemSymbol, distance: i64) -> i64 {
+        match self.routes.get(&(src.clone(), dest.clone())) {
+            Some(route) => route.cooldown_seconds / route.jumps,
+            None => BASE_COOLDOWN_SECONDS + distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_jump_ledger() {
+        let a = SystemSymbol::new("X1-A");
+        let b = SystemSymbol::new("X1-B");
+        let c = SystemSymbol::new("X1-C");
+        let ledger = JumpLedger::new();
+        assert_eq!(ledger.jump_cost(&a, &b), DEFAULT_JUMP_COST);
+        assert_eq!(ledger.cooldown(&a, &b, 400), 460);
+
+        ledger.record_jump(&a, &b, 3000, 500);
+        ledger.record_jump(&a, &b, 5000, 520);
+        assert_eq!(ledger.jump_cost(&a, &b), 4000);
+        assert_eq!(ledger.cooldown(&a, &b, 400), 510);
+
+        // routes not flown yet take the average cost, and the estimated cooldown
+        ledger.record_jump(&b, &c, 10_000, 100);
+        assert_eq!(ledger.jump_cost(&c, &a), 6000);
+        assert_eq!(ledger.cooldown(&c, &a, 400), 460);
+    }
+}
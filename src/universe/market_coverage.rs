@@ -0,0 +1,162 @@
+/// Gaps in the price history of each market, found from the transactions markets report.
+/// A refresh interval longer than GAP_MINS that saw trades, judged by the timestamps of the
+/// transactions listed in the next snapshot, is a gap. The transactions per hour over all intervals
+/// are the market's trade activity, and busy markets that have gone a while without a refresh get a
+/// catch-up refresh task before they're stale
+use crate::models::{Market, WaypointSymbol, WithTimestamp};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+
+// Refresh intervals longer than this are gaps in the price history
+const GAP_MINS: i64 = 60;
+// Catch-up refresh reward per transaction an hour at the market
+const VALUE_PER_HOURLY_TRANSACTION: i64 = 2000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketCoverage {
+    pub first_refresh: DateTime<Utc>,
+    pub last_refresh: DateTime<Utc>,
+    // transactions first seen in each refresh, after the one before
+    pub transactions: i64,
+    pub gaps: i64,
+}
+
+impl MarketCoverage {
+    pub fn activity_per_hour(&self) -> f64 {
+        let hours = (self.last_refresh - self.first_refresh).num_seconds() as f64 / 3600.0;
+        match hours > 0.0 {
+            true => self.transactions as f64 / hours,
+            false => 0.0,
+        }
+    }
+
+    // Reward for refreshing the market now, None if it isn't in a gap or sees no trades
+    pub fn catch_up_value(&self, now: DateTime<Utc>, max_value: i64) -> Option<i64> {
+        if now - self.last_refresh < Duration::try_minutes(GAP_MINS).unwrap() {
+            return None;
+        }
+        let value = (self.activity_per_hour() * VALUE_PER_HOURLY_TRANSACTION as f64) as i64;
+        match value {
+            0 => None,
+            _ => Some(value.min(max_value)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MarketCoverageLog {
+    markets: DashMap<WaypointSymbol, MarketCoverage>,
+}
+
+impl MarketCoverageLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record a refresh, `prev_refresh` is the time of the snapshot it replaces
+    pub fn record(&self, prev_refresh: Option<DateTime<Utc>>, market: &WithTimestamp<Market>) {
+        let refresh = market.timestamp;
+        let Some(prev_refresh) = prev_refresh else {
+            self.markets.insert(
+                market.data.symbol.clone(),
+                MarketCoverage {
+                    first_refresh: refresh,
+                    last_refresh: refresh,
+                    transactions: 0,
+                    gaps: 0,
+                },
+            );
+            return;
+        };
+        let mut coverage = self
+            .markets
+            .entry(market.data.symbol.clone())
+            .or_insert_with(|| MarketCoverage {
+                first_refresh: prev_refresh,
+                last_refresh: prev_refresh,
+                transactions: 0,
+                gaps: 0,
+            });
+        if refresh <= coverage.last_refresh {
+            return;
+        }
+        let new_transactions = market
+            .data
+            .transactions
+            .iter()
+            .filter(|t| t.timestamp > prev_refresh)
+            .count() as i64;
+        if new_transactions > 0
+            && refresh - prev_refresh >= Duration::try_minutes(GAP_MINS).unwrap()
+        {
+            coverage.gaps += 1;
+        }
+        coverage.transactions += new_transactions;
+        coverage.last_refresh = refresh;
+    }
+
+    pub fn get(&self, waypoint: &WaypointSymbol) -> Option<MarketCoverage> {
+        self.markets.get(waypoint).map(|x| x.value().clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::MarketTransaction;
+
+    #[test]
+    fn test_market_coverage() {
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mins = |m| t0 + Duration::try_minutes(m).unwrap();
+        let symbol = WaypointSymbol::new("X1-S1-A1");
+        let market = |refresh, transactions: &[i64]| WithTimestamp {
+            timestamp: refresh,
+            data: Market {
+                symbol: symbol.clone(),
+                transactions: transactions
+                    .iter()
+                    .map(|m| MarketTransaction {
+                        waypoint_symbol: symbol.clone(),
+                        ship_symbol: "OTHER-1".to_string(),
+                        trade_symbol: "IRON".to_string(),
+                        _type: "PURCHASE".to_string(),
+                        units: 10,
+                        price_per_unit: 100,
+                        total_price: 1000,
+                        timestamp: mins(*m),
+                    })
+                    .collect(),
+                imports: vec![],
+                exports: vec![],
+                exchange: vec![],
+                trade_goods: vec![],
+            },
+        };
+        let log = MarketCoverageLog::new();
+        log.record(None, &market(t0, &[-5]));
+        // a short interval, then a long one with trades in it
+        log.record(Some(t0), &market(mins(30), &[-5, 10, 20]));
+        log.record(Some(mins(30)), &market(mins(120), &[10, 20, 40, 60, 100]));
+        let coverage = log.get(&symbol).unwrap();
+        assert_eq!(coverage.transactions, 5);
+        assert_eq!(coverage.gaps, 1);
+        assert_eq!(coverage.activity_per_hour(), 2.5);
+
+        assert_eq!(coverage.catch_up_value(mins(150), 20000), None);
+        assert_eq!(coverage.catch_up_value(mins(180), 20000), Some(5000));
+        assert_eq!(coverage.catch_up_value(mins(180), 4000), Some(4000));
+
+        // a quiet market is never worth a catch-up trip
+        let quiet = WaypointSymbol::new("X1-S1-B2");
+        let mut snapshot = market(mins(120), &[]);
+        snapshot.data.symbol = quiet.clone();
+        log.record(Some(t0), &snapshot);
+        assert_eq!(log.get(&quiet).unwrap().gaps, 0);
+        assert_eq!(
+            log.get(&quiet).unwrap().catch_up_value(mins(300), 20000),
+            None
+        );
+    }
+}
@@ -0,0 +1,173 @@
+/// Recent changes to trade goods, per system, detected when markets are saved.
+/// Lets polling clients fetch only what changed since their last poll.
+use crate::models::{Market, MarketTradeGood, SystemSymbol, WaypointSymbol};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+// Deltas kept per system
+const MAX_DELTAS_PER_SYSTEM: usize = 5000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketDelta {
+    pub market: WaypointSymbol,
+    pub timestamp: DateTime<Utc>,
+    pub trade: MarketTradeGood,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketDeltas {
+    // false if deltas older than `since` have been dropped (or were before startup),
+    // in which case the client should reload the full markets
+    pub complete: bool,
+    pub deltas: Vec<MarketDelta>,
+}
+
+#[derive(Debug)]
+struct SystemLog {
+    // deltas are complete from this time onwards
+    complete_since: DateTime<Utc>,
+    deltas: VecDeque<MarketDelta>,
+}
+
+#[derive(Debug)]
+pub struct MarketDeltaLog {
+    started: DateTime<Utc>,
+    systems: DashMap<SystemSymbol, SystemLog>,
+}
+
+// Trade goods that are new or differ from the previous snapshot
+pub fn diff(prev: Option<&Market>, market: &Market) -> Vec<MarketTradeGood> {
+    market
+        .trade_goods
+        .iter()
+        .filter(|trade| {
+            let prev_trade =
+                prev.and_then(|p| p.trade_goods.iter().find(|g| g.symbol == trade.symbol));
+            prev_trade != Some(*trade)
+        })
+        .cloned()
+        .collect()
+}
+
+impl MarketDeltaLog {
+    pub fn new(started: DateTime<Utc>) -> Self {
+        Self {
+            started,
+            systems: DashMap::new(),
+        }
+    }
+
+    pub fn record(
+        &self,
+        market: &WaypointSymbol,
+        timestamp: DateTime<Utc>,
+        trades: Vec<MarketTradeGood>,
+    ) {
+        if trades.is_empty() {
+            return;
+        }
+        let mut log = self
+            .systems
+            .entry(market.system())
+            .or_insert_with(|| SystemLog {
+                complete_since: self.started,
+                deltas: VecDeque::new(),
+            });
+        for trade in trades {
+            log.deltas.push_back(MarketDelta {
+                market: market.clone(),
+                timestamp,
+                trade,
+            });
+        }
+        while log.deltas.len() > MAX_DELTAS_PER_SYSTEM {
+            let dropped = log.deltas.pop_front().unwrap();
+            log.complete_since = dropped.timestamp;
+        }
+    }
+
+    pub fn since(&self, system: &SystemSymbol, since: DateTime<Utc>) -> MarketDeltas {
+        match self.systems.get(system) {
+            Some(log) => MarketDeltas {
+                complete: since >= log.complete_since,
+                deltas: log
+                    .deltas
+                    .iter()
+                    .filter(|d| d.timestamp > since)
+                    .cloned()
+                    .collect(),
+            },
+            None => MarketDeltas {
+                complete: since >= self.started,
+                deltas: vec![],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{MarketActivity, MarketSupply, MarketType};
+    use chrono::Duration;
+
+    fn trade(symbol: &str, supply: MarketSupply) -> MarketTradeGood {
+        MarketTradeGood {
+            symbol: symbol.to_string(),
+            trade_volume: 60,
+            _type: MarketType::Export,
+            supply,
+            activity: Some(MarketActivity::Strong),
+            purchase_price: 100,
+            sell_price: 90,
+        }
+    }
+
+    fn market(symbol: &WaypointSymbol, trade_goods: Vec<MarketTradeGood>) -> Market {
+        Market {
+            symbol: symbol.clone(),
+            exports: vec![],
+            imports: vec![],
+            exchange: vec![],
+            transactions: vec![],
+            trade_goods,
+        }
+    }
+
+    #[test]
+    fn test_market_deltas() {
+        let waypoint = WaypointSymbol::new("X1-AB12-A1");
+        let prev = market(
+            &waypoint,
+            vec![
+                trade("IRON", MarketSupply::High),
+                trade("FUEL", MarketSupply::High),
+            ],
+        );
+        let new = market(
+            &waypoint,
+            vec![
+                trade("IRON", MarketSupply::High),
+                trade("FUEL", MarketSupply::Moderate),
+            ],
+        );
+        assert_eq!(diff(None, &prev).len(), 2);
+        let changed = diff(Some(&prev), &new);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].symbol, "FUEL");
+
+        let t0 = Utc::now();
+        let log = MarketDeltaLog::new(t0);
+        let t1 = t0 + Duration::try_minutes(1).unwrap();
+        log.record(&waypoint, t1, changed);
+        let deltas = log.since(&waypoint.system(), t0);
+        assert!(deltas.complete);
+        assert_eq!(deltas.deltas.len(), 1);
+        assert!(log.since(&waypoint.system(), t1).deltas.is_empty());
+        // before startup, changes may have been missed
+        let before = t0 - Duration::try_minutes(1).unwrap();
+        assert!(!log.since(&waypoint.system(), before).complete);
+    }
+}
@@ -0,0 +1,86 @@
+/// Coordinates market refreshes between ships at the same waypoint.
+/// Refreshes of a market are serialized, and a ship that finds the market was refreshed within the
+/// last minute, with none of our trades there since, reuses that result instead of fetching it again.
+use crate::models::WaypointSymbol;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+// How long a refresh can be reused by other ships at the market
+const REUSE_SECONDS: i64 = 60;
+
+#[derive(Debug, Default)]
+pub struct MarketRefreshCoordinator {
+    locks: DashMap<WaypointSymbol, Arc<Mutex<()>>>,
+    // time of our latest trade at each market, which makes any earlier refresh stale
+    last_trade: DashMap<WaypointSymbol, DateTime<Utc>>,
+}
+
+impl MarketRefreshCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Held while refreshing the market
+    pub async fn lock(&self, waypoint: &WaypointSymbol) -> OwnedMutexGuard<()> {
+        let lock = self.locks.entry(waypoint.clone()).or_default().clone();
+        lock.lock_owned().await
+    }
+
+    pub fn record_trade(&self, waypoint: &WaypointSymbol, timestamp: DateTime<Utc>) {
+        self.last_trade.insert(waypoint.clone(), timestamp);
+    }
+
+    // Whether a refresh at `refreshed` still reflects the market
+    pub fn is_reusable(
+        &self,
+        waypoint: &WaypointSymbol,
+        refreshed: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let Some(refreshed) = refreshed else {
+            return false;
+        };
+        let traded_since = match self.last_trade.get(waypoint) {
+            Some(last_trade) => *last_trade >= refreshed,
+            None => false,
+        };
+        now - refreshed < Duration::try_seconds(REUSE_SECONDS).unwrap() && !traded_since
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_market_refresh_reuse() {
+        let coordinator = MarketRefreshCoordinator::new();
+        let waypoint = WaypointSymbol::new("X1-S1-A1");
+        let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let secs = |s: i64| t0 + Duration::try_seconds(s).unwrap();
+
+        assert!(!coordinator.is_reusable(&waypoint, None, t0));
+        assert!(coordinator.is_reusable(&waypoint, Some(t0), secs(30)));
+        assert!(!coordinator.is_reusable(&waypoint, Some(t0), secs(60)));
+
+        // our trade changes the prices, so the refresh before it is stale
+        coordinator.record_trade(&waypoint, secs(10));
+        assert!(!coordinator.is_reusable(&waypoint, Some(t0), secs(20)));
+        assert!(coordinator.is_reusable(&waypoint, Some(secs(15)), secs(20)));
+
+        // refreshes of one market wait for each other, other markets don't
+        let guard = coordinator.lock(&waypoint).await;
+        let other = coordinator.lock(&WaypointSymbol::new("X1-S1-B2")).await;
+        assert!(coordinator
+            .locks
+            .get(&waypoint)
+            .unwrap()
+            .try_lock()
+            .is_err());
+        drop(guard);
+        drop(other);
+        assert!(coordinator.locks.get(&waypoint).unwrap().try_lock().is_ok());
+    }
+}
@@ -1,18 +1,33 @@
+pub mod access;
+pub mod fuel_ledger;
+pub mod jump_ledger;
+pub mod market_coverage;
+pub mod market_deltas;
+pub mod market_refresh;
 pub mod pathfinding;
+pub mod ship_catalog;
+pub mod transaction_costs;
 
 use crate::api_client::api_models;
 use crate::api_client::api_models::WaypointDetailed;
-use crate::api_client::ApiClient;
+use crate::api_client::{ApiClient, ApiError};
+use crate::clock::SharedClock;
+use crate::config::CONFIG;
+use crate::db::cache_sync::CacheInvalidation;
 use crate::db::db_models;
 use crate::db::db_models::NewWaypointDetails;
-use crate::db::DbClient;
+use crate::db::{ok_or_warn, DbClient, DbError};
 use crate::models::{
-    Construction, Faction, Market, MarketRemoteView, Shipyard, ShipyardRemoteView, System,
-    SystemSymbol, Waypoint, WaypointSymbol, WithTimestamp,
+    Construction, Faction, Market, MarketRemoteView, MarketTransaction, ShipFlightMode, Shipyard,
+    ShipyardRemoteView, System, SystemSymbol, Waypoint, WaypointSymbol, WithTimestamp,
 };
 use crate::models::{SymbolNameDescr, WaypointDetails};
-use crate::pathfinding::{Pathfinding, Route};
+use crate::pathfinding::{
+    burn_pays, join_gate_matrices, DurationMatrix, Pathfinding, Route, Urgency,
+};
 use crate::schema::*;
+use crate::util::single_flight_cache::SingleFlightCache;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use diesel::upsert::excluded;
 use diesel::BelongingToDsl as _;
@@ -23,10 +38,45 @@ use diesel::SelectableHelper as _;
 use diesel_async::RunQueryDsl as _;
 use log::*;
 use moka::future::Cache;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use tokio::sync::watch;
 
+use self::access::UniverseReader;
+use self::fuel_ledger::{FuelLedger, FuelReport};
+use self::jump_ledger::JumpLedger;
+use self::market_coverage::{MarketCoverage, MarketCoverageLog};
+use self::market_deltas::{MarketDeltaLog, MarketDeltas};
+use self::market_refresh::MarketRefreshCoordinator;
 use self::pathfinding::WarpEdge;
+use self::ship_catalog::ShipCatalog;
+use self::transaction_costs::{TradeSide, TransactionCosts};
+
+const FETCH_RETRY_BASE: std::time::Duration = std::time::Duration::from_secs(10);
+const FETCH_RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(600);
+
+// Universe data has no fallback, so once the api client's own retries run out keep retrying at a
+// slower pace. A fetch that never succeeds leaves its caller waiting, which the stuck ship alert reports.
+async fn fetch_until_ok<T, F, Fut>(label: &str, mut fetch: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let mut delay = FETCH_RETRY_BASE;
+    loop {
+        match fetch().await {
+            Ok(value) => return value,
+            Err(e) => {
+                warn!("Failed to fetch {}, retrying in {:?}: {}", label, delay, e);
+                tokio::time::sleep(delay).await;
+                delay = std::cmp::min(delay * 2, FETCH_RETRY_MAX);
+            }
+        }
+    }
+}
 
 pub enum WaypointFilter {
     Imports(String),
@@ -37,8 +87,12 @@ pub enum WaypointFilter {
     Shipyard,
     // waypoint types
     GasGiant,
+    Asteroid,
     EngineeredAsteroid,
     JumpGate,
+    // waypoint modifiers
+    Modifier(String),
+    NotHazardous,
 }
 
 #[derive(Debug, Clone)]
@@ -50,69 +104,185 @@ pub struct JumpGateInfo {
 pub struct Universe {
     api_client: ApiClient,
     db: DbClient,
+    clock: SharedClock,
 
-    systems: DashMap<SystemSymbol, System>,
-    constructions: DashMap<WaypointSymbol, Arc<WithTimestamp<Option<Construction>>>>,
-    remote_markets: DashMap<WaypointSymbol, MarketRemoteView>,
-    markets: DashMap<WaypointSymbol, Option<Arc<WithTimestamp<Market>>>>,
-    remote_shipyards: DashMap<WaypointSymbol, ShipyardRemoteView>,
-    shipyards: DashMap<WaypointSymbol, Option<Arc<WithTimestamp<Shipyard>>>>,
+    // systems are shared rather than copied on read, they're only modified when waypoint details change
+    systems: DashMap<SystemSymbol, Arc<System>>,
+    // concurrent misses share one fetch, so callers racing on a new waypoint don't repeat API calls
+    constructions: SingleFlightCache<WaypointSymbol, Arc<WithTimestamp<Option<Construction>>>>,
+    remote_markets: SingleFlightCache<WaypointSymbol, MarketRemoteView>,
+    // snapshots are bounded, evicted entries are reloaded from the db on demand
+    markets: Cache<WaypointSymbol, Option<Arc<WithTimestamp<Market>>>>,
+    remote_shipyards: SingleFlightCache<WaypointSymbol, ShipyardRemoteView>,
+    shipyards: Cache<WaypointSymbol, Option<Arc<WithTimestamp<Shipyard>>>>,
     factions: DashMap<String, Faction>,
-    jumpgates: DashMap<WaypointSymbol, JumpGateInfo>,
+    jumpgates: SingleFlightCache<WaypointSymbol, JumpGateInfo>,
+
+    // notifies subscribers with the most recently updated market in each system
+    market_updates: DashMap<SystemSymbol, watch::Sender<Option<WaypointSymbol>>>,
+    market_deltas: MarketDeltaLog,
+    market_coverage: MarketCoverageLog,
+    market_refresh: MarketRefreshCoordinator,
+    // notifies subscribers with the most recently completed jumpgate
+    jumpgate_completions: watch::Sender<Option<WaypointSymbol>>,
+    // notifies subscribers with the most recently saved shipyard
+    shipyard_updates: watch::Sender<Option<WaypointSymbol>>,
+    transaction_costs: TransactionCosts,
+    fuel_ledger: FuelLedger,
+    // off with the market_trades feature
+    record_market_trades: AtomicBool,
+    jump_ledger: JumpLedger,
+    ship_catalog: ShipCatalog,
 
     // cache
     warp_jump_graph: Cache<(), BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>>,
 }
 
-impl Universe {
+// Market and shipyard snapshots kept in memory. Late game agents see far more waypoints than they trade at
+const SNAPSHOT_CACHE_CAPACITY: u64 = 2000;
+const SNAPSHOT_CACHE_IDLE_HOURS: u64 = 6;
+// Credits per unit of ship fuel before any fuel has been bought
+const DEFAULT_FUEL_PRICE: f64 = 1.0;
+
+fn snapshot_cache<V: Clone + Send + Sync + 'static>() -> Cache<WaypointSymbol, V> {
+    Cache::builder()
+        .max_capacity(SNAPSHOT_CACHE_CAPACITY)
+        .time_to_idle(std::time::Duration::from_secs(
+            SNAPSHOT_CACHE_IDLE_HOURS * 3600,
+        ))
+        .build()
+}
+
+// Cheap-to-clone handle to the shared universe
+#[derive(Clone)]
+pub struct UniverseHandle(Arc<Universe>);
+
+impl UniverseHandle {
     pub fn new(api_client: &ApiClient, db: &DbClient) -> Self {
+        Self::with_clock(api_client, db, api_client.server_clock())
+    }
+
+    pub fn with_clock(api_client: &ApiClient, db: &DbClient, clock: SharedClock) -> Self {
+        Self(Arc::new(Universe::new(api_client, db, clock)))
+    }
+
+    // The read-only view, for the web server and analytics
+    pub fn reader(&self) -> Arc<dyn UniverseReader> {
+        self.0.clone()
+    }
+
+    // Apply market and shipyard updates saved by other processes sharing the database
+    pub fn start_cache_sync(&self) {
+        let mut invalidations = self.db.listen_cache_invalidations();
+        let universe = self.clone();
+        tokio::spawn(async move {
+            while let Some(invalidation) = invalidations.recv().await {
+                universe.apply_cache_invalidation(invalidation).await;
+            }
+        });
+    }
+}
+
+impl Deref for UniverseHandle {
+    type Target = Universe;
+
+    fn deref(&self) -> &Universe {
+        &self.0
+    }
+}
+
+impl Universe {
+    fn new(api_client: &ApiClient, db: &DbClient, clock: SharedClock) -> Self {
         Self {
             api_client: api_client.clone(),
             db: db.clone(),
+            market_deltas: MarketDeltaLog::new(clock.now()),
+            market_coverage: MarketCoverageLog::new(),
+            market_refresh: MarketRefreshCoordinator::new(),
+            clock,
             systems: DashMap::new(),
-            constructions: DashMap::new(),
-            remote_markets: DashMap::new(),
-            markets: DashMap::new(),
-            remote_shipyards: DashMap::new(),
-            shipyards: DashMap::new(),
+            constructions: SingleFlightCache::new(),
+            remote_markets: SingleFlightCache::new(),
+            markets: snapshot_cache(),
+            remote_shipyards: SingleFlightCache::new(),
+            shipyards: snapshot_cache(),
             factions: DashMap::new(),
-            jumpgates: DashMap::new(),
+            jumpgates: SingleFlightCache::new(),
+            market_updates: DashMap::new(),
+            jumpgate_completions: watch::Sender::new(None),
+            shipyard_updates: watch::Sender::new(None),
+            transaction_costs: TransactionCosts::new(),
+            fuel_ledger: FuelLedger::new(),
+            record_market_trades: AtomicBool::new(true),
+            jump_ledger: JumpLedger::new(),
+            ship_catalog: ShipCatalog::builtin(),
             warp_jump_graph: Cache::new(1),
         }
     }
 
-    pub async fn init(&self) {
-        self.init_systems().await;
-        self.init_jumpgates().await;
+    pub fn set_record_market_trades(&self, enabled: bool) {
+        self.record_market_trades.store(enabled, Ordering::Relaxed);
     }
 
-    async fn init_systems(&self) {
-        let status = self.api_client.status().await;
+    pub async fn init(&self) -> Result<(), DbError> {
+        self.init_systems().await?;
+        for system in self.systems.iter() {
+            self.api_client.observe_system(system.value());
+        }
+        self.init_jumpgates().await?;
+        self.init_ship_catalog().await?;
+        self.db.backfill_market_transactions().await
+    }
+
+    async fn init_ship_catalog(&self) -> Result<(), DbError> {
+        let models = self.db.get_ship_models().await?;
+        info!("Loaded {} ship models", models.len());
+        for model in models {
+            self.ship_catalog.insert(model);
+        }
+        Ok(())
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn clock(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    pub fn api_degraded(&self) -> bool {
+        self.api_client.degraded()
+    }
+
+    pub fn ship_catalog(&self) -> &ShipCatalog {
+        &self.ship_catalog
+    }
+
+    async fn init_systems(&self) -> Result<(), DbError> {
+        let status = fetch_until_ok("server status", || self.api_client.status()).await;
         let query_start = std::time::Instant::now();
         let systems: Vec<db_models::System> = systems::table
             .filter(systems::reset_id.eq(self.db.reset_date()))
             .select(db_models::System::as_select())
-            .load(&mut self.db.conn().await)
-            .await
-            .expect("DB Query error");
+            .load(&mut self.db.conn().await?)
+            .await?;
         let duration = query_start.elapsed().as_millis() as f64 / 1000.0;
         info!("Loaded {} systems in {:.3}s", systems.len(), duration);
 
         let query_start = std::time::Instant::now();
         let waypoints = db_models::Waypoint::belonging_to(&systems)
             .select(db_models::Waypoint::as_select())
-            .load(&mut self.db.conn().await)
-            .await
-            .expect("DB Query error");
+            .load(&mut self.db.conn().await?)
+            .await?;
         let duration = query_start.elapsed().as_millis() as f64 / 1000.0;
         info!("Loaded {} waypoints in {:.3}s", waypoints.len(), duration);
 
         let query_start = std::time::Instant::now();
         let waypoint_details = db_models::WaypointDetails::belonging_to(&waypoints)
             .select(db_models::WaypointDetails::as_select())
-            .load(&mut self.db.conn().await)
-            .await
-            .expect("DB Query error");
+            .load(&mut self.db.conn().await?)
+            .await?;
         let duration = query_start.elapsed().as_millis() as f64 / 1000.0;
         info!(
             "Loaded {} waypoint details in {:.3}s",
@@ -142,6 +312,11 @@ impl Universe {
                                     is_market: details.is_market,
                                     is_shipyard: details.is_shipyard,
                                     is_uncharted: details.is_uncharted,
+                                    modifiers: details.modifiers,
+                                    orbits: details.orbits.as_deref().map(WaypointSymbol::new),
+                                    chart: details
+                                        .chart_submitted_by
+                                        .zip(details.chart_submitted_on),
                                 })
                             }
                             _ => panic!("Multiple details for waypoint"),
@@ -158,17 +333,18 @@ impl Universe {
                     .collect();
                 self.systems.insert(
                     SystemSymbol::new(&system.symbol),
-                    System {
+                    Arc::new(System {
                         symbol: SystemSymbol::new(&system.symbol),
                         system_type: system.type_,
                         x: system.x as i64,
                         y: system.y as i64,
                         waypoints,
-                    },
+                    }),
                 );
             }
         } else {
-            let systems: Vec<api_models::System> = self.api_client.get("/systems.json").await;
+            let systems: Vec<api_models::System> =
+                fetch_until_ok("systems", || self.api_client.try_get("/systems.json")).await;
             let system_inserts = systems
                 .iter()
                 .map(|system| db_models::NewSystem {
@@ -192,9 +368,8 @@ impl Universe {
                         // yes it's a hack, and empty updates have consequences, but it's okay here
                         systems::symbol.eq(excluded(systems::symbol)),
                     ))
-                    .get_results(&mut self.db.conn().await)
-                    .await
-                    .expect("DB Insert error");
+                    .get_results(&mut self.db.conn().await?)
+                    .await?;
                 assert_eq!(chunk.len(), ids.len());
                 system_ids.extend(ids);
             }
@@ -228,9 +403,8 @@ impl Universe {
                         waypoints::symbol.eq(excluded(waypoints::symbol)),
                     ))
                     .returning(waypoints::id)
-                    .get_results(&mut self.db.conn().await)
-                    .await
-                    .expect("DB Insert error");
+                    .get_results(&mut self.db.conn().await?)
+                    .await?;
                 assert_eq!(chunk.len(), ids.len());
                 waypoint_ids.extend(ids);
             }
@@ -259,19 +433,19 @@ impl Universe {
                         })
                         .collect(),
                 };
-                self.systems.insert(system.symbol.clone(), system);
+                self.systems.insert(system.symbol.clone(), Arc::new(system));
             }
         }
+        Ok(())
     }
 
-    async fn init_jumpgates(&self) {
+    async fn init_jumpgates(&self) -> Result<(), DbError> {
         let query_start = std::time::Instant::now();
         let jumpgates: Vec<db_models::JumpGateConnections> = jumpgate_connections::table
             .filter(jumpgate_connections::reset_id.eq(self.db.reset_date()))
             .select(db_models::JumpGateConnections::as_select())
-            .load(&mut self.db.conn().await)
-            .await
-            .expect("DB Query error");
+            .load(&mut self.db.conn().await?)
+            .await?;
         let duration = query_start.elapsed().as_millis() as f64 / 1000.0;
         info!("Loaded {} jumpgates in {:.3}s", jumpgates.len(), duration);
 
@@ -288,13 +462,14 @@ impl Universe {
                 },
             );
         }
+        Ok(())
     }
 
     pub fn connections_known(&self, waypoint: &WaypointSymbol) -> bool {
         self.jumpgates.contains_key(waypoint)
     }
 
-    pub fn systems(&self) -> Vec<System> {
+    pub fn systems(&self) -> Vec<Arc<System>> {
         self.systems.iter().map(|x| x.value().clone()).collect()
     }
     pub fn num_systems(&self) -> usize {
@@ -303,7 +478,7 @@ impl Universe {
     pub fn num_waypoints(&self) -> usize {
         self.systems.iter().map(|s| s.value().waypoints.len()).sum()
     }
-    pub fn system(&self, symbol: &SystemSymbol) -> System {
+    pub fn system(&self, symbol: &SystemSymbol) -> Arc<System> {
         self.systems
             .get(symbol)
             .expect("System not found")
@@ -326,16 +501,18 @@ impl Universe {
         &self,
         waypoint_symbol: &WaypointSymbol,
     ) -> Option<Arc<WithTimestamp<Market>>> {
-        match self.markets.get(waypoint_symbol) {
-            Some(market) => market.clone(),
-            None => {
-                let market = self
-                    .db
-                    .get_market(waypoint_symbol)
-                    .await
-                    .map(|market| Arc::new(market));
-                self.markets.insert(waypoint_symbol.clone(), market.clone());
-                market
+        // a failed load isn't cached, so the next call tries the database again
+        let market = self
+            .markets
+            .try_get_with_by_ref(waypoint_symbol, async {
+                Ok::<_, DbError>(self.db.get_market(waypoint_symbol).await?.map(Arc::new))
+            })
+            .await;
+        match market {
+            Ok(market) => market,
+            Err(e) => {
+                warn!("Failed to load market {}: {}", waypoint_symbol, e);
+                None
             }
         }
     }
@@ -345,28 +522,265 @@ impl Universe {
         waypoint_symbol: &WaypointSymbol,
         market: WithTimestamp<Market>,
     ) {
+        let changed = {
+            let prev = self.get_market(waypoint_symbol).await;
+            self.market_coverage
+                .record(prev.as_ref().map(|m| m.timestamp), &market);
+            market_deltas::diff(prev.as_ref().map(|m| &m.data), &market.data)
+        };
+        self.market_deltas
+            .record(waypoint_symbol, market.timestamp, changed);
         self.markets
-            .insert(waypoint_symbol.clone(), Some(Arc::new(market.clone())));
-        self.db.save_market(waypoint_symbol, &market).await;
-        self.db.insert_market_trades(&market).await;
-        self.db.upsert_market_transactions(&market).await;
+            .insert(waypoint_symbol.clone(), Some(Arc::new(market.clone())))
+            .await;
+        ok_or_warn(
+            self.db.save_market(waypoint_symbol, &market).await,
+            &format!("save market {}", waypoint_symbol),
+        );
+        if AtomicBool::load(&self.record_market_trades, Ordering::Relaxed) {
+            ok_or_warn(
+                self.db.insert_market_trades(&market).await,
+                &format!("record market trades at {}", waypoint_symbol),
+            );
+        }
+        ok_or_warn(
+            self.db.upsert_market_transactions(&market).await,
+            &format!("record transactions at {}", waypoint_symbol),
+        );
+        self.notify_market_update(waypoint_symbol);
+        self.publish_cache_invalidation(CacheInvalidation::Market(waypoint_symbol.clone()))
+            .await;
+    }
+
+    async fn publish_cache_invalidation(&self, invalidation: CacheInvalidation) {
+        if CONFIG.cache_sync {
+            ok_or_warn(
+                self.db.notify_cache_invalidation(&invalidation).await,
+                "publish cache invalidation",
+            );
+        }
+    }
+
+    // Reload a snapshot another process saved. Deltas are only recorded against a snapshot we had
+    async fn apply_cache_invalidation(&self, invalidation: CacheInvalidation) {
+        match invalidation {
+            CacheInvalidation::Market(waypoint_symbol) => {
+                let prev = self.markets.get(&waypoint_symbol).await.flatten();
+                self.markets.invalidate(&waypoint_symbol).await;
+                let Some(market) = self.get_market(&waypoint_symbol).await else {
+                    return;
+                };
+                if let Some(prev) = prev {
+                    let changed = market_deltas::diff(Some(&prev.data), &market.data);
+                    self.market_deltas
+                        .record(&waypoint_symbol, market.timestamp, changed);
+                }
+                self.notify_market_update(&waypoint_symbol);
+            }
+            CacheInvalidation::Shipyard(waypoint_symbol) => {
+                self.shipyards.invalidate(&waypoint_symbol).await;
+            }
+        }
+    }
+
+    // Refresh a market from the API with `fetch`. Ships arriving together refresh one at a time,
+    // and reuse a refresh from the last minute if none of our trades happened there since
+    pub async fn refresh_market<F, Fut, E>(
+        &self,
+        waypoint_symbol: &WaypointSymbol,
+        fetch: F,
+    ) -> Result<(), E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Market, E>>,
+    {
+        let _guard = self.market_refresh.lock(waypoint_symbol).await;
+        let refreshed = self
+            .get_market(waypoint_symbol)
+            .await
+            .map(|market| market.timestamp);
+        if self
+            .market_refresh
+            .is_reusable(waypoint_symbol, refreshed, self.now())
+        {
+            debug!("Reusing recent refresh of market {}", waypoint_symbol);
+            return Ok(());
+        }
+        let market = fetch().await?;
+        let market = WithTimestamp::<Market> {
+            timestamp: self.now(),
+            data: market,
+        };
+        self.save_market(waypoint_symbol, market).await;
+        Ok(())
+    }
+
+    // Record our own trade immediately, the market may not be refreshed after it
+    pub async fn record_transaction(
+        &self,
+        market_symbol: &WaypointSymbol,
+        transaction: &MarketTransaction,
+    ) {
+        self.market_refresh.record_trade(market_symbol, self.now());
+        // compare against the listing the trade was made from, before any refresh
+        let market = self.get_market(market_symbol).await;
+        let listed_price = market.and_then(|market| {
+            let trade = market
+                .data
+                .trade_goods
+                .iter()
+                .find(|g| g.symbol == transaction.trade_symbol)?;
+            match TradeSide::from_transaction_type(&transaction._type)? {
+                TradeSide::Purchase => Some((TradeSide::Purchase, trade.purchase_price)),
+                TradeSide::Sell => Some((TradeSide::Sell, trade.sell_price)),
+            }
+        });
+        if let Some((side, listed_price)) = listed_price {
+            self.transaction_costs.record(
+                market_symbol,
+                &transaction.trade_symbol,
+                side,
+                listed_price,
+                transaction.units,
+                transaction.total_price,
+            );
+        }
+        ok_or_warn(
+            self.db
+                .upsert_transactions(market_symbol, std::slice::from_ref(transaction))
+                .await,
+            &format!("record transaction at {}", market_symbol),
+        );
+    }
+
+    // Expected cost per unit beyond the listed price, from our previous trades at the market
+    pub fn transaction_cost(
+        &self,
+        market_symbol: &WaypointSymbol,
+        good: &str,
+        side: TradeSide,
+    ) -> i64 {
+        self.transaction_costs.per_unit(market_symbol, good, side)
+    }
+
+    // Price per unit of ship fuel at the waypoint's market, or the fleet's average
+    pub async fn local_fuel_price(&self, waypoint: &WaypointSymbol) -> Option<f64> {
+        let is_market = self.detailed_waypoint(waypoint).await.is_market();
+        let local = match is_market {
+            true => self.get_market(waypoint).await.and_then(|market| {
+                market
+                    .data
+                    .trade_goods
+                    .iter()
+                    .find(|g| g.symbol == "FUEL")
+                    .map(|g| g.purchase_price as f64 / 100.0)
+            }),
+            false => None,
+        };
+        local.or_else(|| self.fuel_ledger.fuel_price())
+    }
+
+    pub fn record_fuel_purchase(&self, ship_symbol: &str, units: i64, total_price: i64) {
+        self.fuel_ledger
+            .record_purchase(ship_symbol, units, total_price);
+    }
+
+    pub fn record_fuel_trip(
+        &self,
+        ship_symbol: &str,
+        src: &WaypointSymbol,
+        dest: &WaypointSymbol,
+        flight_mode: &ShipFlightMode,
+        fuel: i64,
+        seconds: i64,
+    ) {
+        self.fuel_ledger
+            .record_trip(ship_symbol, src, dest, flight_mode, fuel, seconds);
+    }
+
+    // Average price per unit of ship fuel across the fleet, for weighing warps
+    pub fn fleet_fuel_price(&self) -> f64 {
+        self.fuel_ledger.fuel_price().unwrap_or(DEFAULT_FUEL_PRICE)
+    }
+
+    // Jump costs feed into the warp/jump graph's edges, which are rebuilt with them
+    pub fn record_jump(
+        &self,
+        src: &SystemSymbol,
+        dest: &SystemSymbol,
+        credits: i64,
+        cooldown_seconds: i64,
+    ) {
+        self.jump_ledger
+            .record_jump(src, dest, credits, cooldown_seconds);
+        self.warp_jump_graph.invalidate_all();
+    }
+
+    pub fn jump_cost(&self, src: &SystemSymbol, dest: &SystemSymbol) -> i64 {
+        self.jump_ledger.jump_cost(src, dest)
+    }
+
+    pub fn fuel_report(&self) -> FuelReport {
+        self.fuel_ledger.report(CONFIG.burn_time_value)
+    }
+
+    fn notify_market_update(&self, waypoint_symbol: &WaypointSymbol) {
+        let system_symbol = waypoint_symbol.system();
+        if let Some(tx) = self.market_updates.get(&system_symbol) {
+            tx.send_replace(Some(waypoint_symbol.clone()));
+        }
+    }
+
+    // Refresh history of the market, None if it hasn't been refreshed since startup
+    pub fn market_coverage(&self, waypoint_symbol: &WaypointSymbol) -> Option<MarketCoverage> {
+        self.market_coverage.get(waypoint_symbol)
+    }
+
+    // Trade goods changed in the system after `since`
+    pub fn market_deltas_since(
+        &self,
+        system_symbol: &SystemSymbol,
+        since: DateTime<Utc>,
+    ) -> MarketDeltas {
+        self.market_deltas.since(system_symbol, since)
+    }
+
+    // Receiver is notified whenever any market in the system is saved
+    pub fn subscribe_system_markets(
+        &self,
+        system_symbol: &SystemSymbol,
+    ) -> watch::Receiver<Option<WaypointSymbol>> {
+        self.market_updates
+            .entry(system_symbol.clone())
+            .or_insert_with(|| watch::Sender::new(None))
+            .subscribe()
+    }
+
+    // Receiver is notified whenever a jumpgate finishes construction
+    pub fn subscribe_jumpgate_completions(&self) -> watch::Receiver<Option<WaypointSymbol>> {
+        self.jumpgate_completions.subscribe()
+    }
+
+    // Receiver is notified whenever a shipyard is saved
+    pub fn subscribe_shipyard_updates(&self) -> watch::Receiver<Option<WaypointSymbol>> {
+        self.shipyard_updates.subscribe()
     }
 
     pub async fn get_shipyard(
         &self,
         waypoint_symbol: &WaypointSymbol,
     ) -> Option<Arc<WithTimestamp<Shipyard>>> {
-        match self.shipyards.get(waypoint_symbol) {
-            Some(shipyard) => shipyard.clone(),
-            None => {
-                let shipyard = self
-                    .db
-                    .get_shipyard(waypoint_symbol)
-                    .await
-                    .map(|x| Arc::new(x));
-                self.shipyards
-                    .insert(waypoint_symbol.clone(), shipyard.clone());
-                shipyard
+        let shipyard = self
+            .shipyards
+            .try_get_with_by_ref(waypoint_symbol, async {
+                Ok::<_, DbError>(self.db.get_shipyard(waypoint_symbol).await?.map(Arc::new))
+            })
+            .await;
+        match shipyard {
+            Ok(shipyard) => shipyard,
+            Err(e) => {
+                warn!("Failed to load shipyard {}: {}", waypoint_symbol, e);
+                None
             }
         }
     }
@@ -377,8 +791,28 @@ impl Universe {
         shipyard: WithTimestamp<Shipyard>,
     ) {
         self.shipyards
-            .insert(waypoint_symbol.clone(), Some(Arc::new(shipyard.clone())));
-        self.db.save_shipyard(waypoint_symbol, &shipyard).await;
+            .insert(waypoint_symbol.clone(), Some(Arc::new(shipyard.clone())))
+            .await;
+        ok_or_warn(
+            self.db.save_shipyard(waypoint_symbol, &shipyard).await,
+            &format!("save shipyard {}", waypoint_symbol),
+        );
+        ok_or_warn(
+            self.db.insert_shipyard_listings(&shipyard).await,
+            &format!("record shipyard listings at {}", waypoint_symbol),
+        );
+        for listing in &shipyard.data.ships {
+            if let Some(model) = self.ship_catalog.observe(listing) {
+                ok_or_warn(
+                    self.db.save_ship_model(&model).await,
+                    &format!("save ship model {}", model.ship_type),
+                );
+            }
+        }
+        self.shipyard_updates
+            .send_replace(Some(waypoint_symbol.clone()));
+        self.publish_cache_invalidation(CacheInvalidation::Shipyard(waypoint_symbol.clone()))
+            .await;
     }
 
     // load Optional<Construction> from db, or fetch from api
@@ -387,11 +821,18 @@ impl Universe {
         &self,
         symbol: &WaypointSymbol,
     ) -> WithTimestamp<Option<Construction>> {
-        match self.db.get_construction(symbol).await {
+        let stored = self.db.get_construction(symbol).await;
+        match ok_or_warn(stored, &format!("load construction {}", symbol)).flatten() {
             Some(site) => site,
             None => {
-                let site = self.api_client.get_construction(symbol).await;
-                self.db.save_construction(symbol, &site).await;
+                let site = fetch_until_ok(&format!("construction {}", symbol), || {
+                    self.api_client.get_construction(symbol)
+                })
+                .await;
+                ok_or_warn(
+                    self.db.save_construction(symbol, &site).await,
+                    &format!("save construction {}", symbol),
+                );
                 site
             }
         }
@@ -401,30 +842,139 @@ impl Universe {
         &self,
         symbol: &WaypointSymbol,
     ) -> Arc<WithTimestamp<Option<Construction>>> {
-        match self.constructions.get(symbol) {
-            Some(construction) => construction.clone(),
-            None => {
-                let construction = self.load_construction(symbol).await;
-                let construction = Arc::new(construction);
-                self.constructions
-                    .insert(symbol.clone(), construction.clone());
-                construction
-            }
-        }
+        self.constructions
+            .get_or_load(symbol, || async {
+                Arc::new(self.load_construction(symbol).await)
+            })
+            .await
     }
 
     pub async fn update_construction(&self, construction: &Construction) {
         let symbol = &construction.symbol;
         let construction = WithTimestamp {
             data: Some(construction.clone()),
-            timestamp: chrono::Utc::now(),
+            timestamp: self.now(),
         };
         self.constructions
             .insert(symbol.clone(), Arc::new(construction.clone()));
-        self.db.save_construction(symbol, &construction).await;
+        ok_or_warn(
+            self.db.save_construction(symbol, &construction).await,
+            &format!("save construction {}", symbol),
+        );
+        let is_complete = construction.data.as_ref().unwrap().is_complete;
+        if is_complete && self.is_jumpgate_under_construction(symbol) {
+            self.mark_jumpgate_constructed(symbol).await;
+        }
+    }
+
+    pub async fn record_construction_delivery(
+        &self,
+        symbol: &WaypointSymbol,
+        ship_symbol: &str,
+        good: &str,
+        units: i64,
+    ) {
+        ok_or_warn(
+            self.db
+                .insert_construction_delivery(symbol, ship_symbol, good, units)
+                .await,
+            &format!("record construction delivery to {}", symbol),
+        );
+    }
+
+    fn is_jumpgate_under_construction(&self, symbol: &WaypointSymbol) -> bool {
+        if let Some(info) = self.jumpgates.get(symbol) {
+            if !info.is_constructed {
+                return true;
+            }
+        }
+        let system = self.systems.get(&symbol.system());
+        let waypoint = system
+            .as_ref()
+            .and_then(|s| s.waypoints.iter().find(|w| &w.symbol == symbol).cloned());
+        match waypoint {
+            Some(w) => {
+                w.waypoint_type == "JUMP_GATE"
+                    && w.details.map(|d| d.is_under_construction).unwrap_or(false)
+            }
+            None => false,
+        }
     }
 
-    pub async fn get_system(&self, symbol: &SystemSymbol) -> System {
+    // Update the cached waypoint details and jumpgate connections for a gate that just finished construction
+    async fn mark_jumpgate_constructed(&self, symbol: &WaypointSymbol) {
+        info!("Jumpgate {} construction complete", symbol);
+        self.jumpgate_completions.send_replace(Some(symbol.clone()));
+        if let Some(mut info) = self.jumpgates.get_mut(symbol) {
+            info.is_constructed = true;
+        }
+        let updated = async {
+            diesel::update(jumpgate_connections::table)
+                .filter(jumpgate_connections::reset_id.eq(self.db.reset_date()))
+                .filter(jumpgate_connections::waypoint_symbol.eq(symbol.as_str()))
+                .set(jumpgate_connections::is_under_construction.eq(false))
+                .execute(&mut self.db.conn().await?)
+                .await?;
+            Ok(())
+        };
+        ok_or_warn(updated.await, &format!("save jumpgate {}", symbol));
+
+        let waypoint_id = match self.systems.get_mut(&symbol.system()) {
+            Some(mut system) => Arc::make_mut(&mut system)
+                .waypoints
+                .iter_mut()
+                .find(|w| &w.symbol == symbol)
+                .map(|w| {
+                    if let Some(details) = &mut w.details {
+                        details.is_under_construction = false;
+                    }
+                    w.id
+                }),
+            None => None,
+        };
+        if let Some(waypoint_id) = waypoint_id {
+            let updated = async {
+                diesel::update(waypoint_details::table)
+                    .filter(waypoint_details::waypoint_id.eq(waypoint_id))
+                    .set((
+                        waypoint_details::is_under_construction.eq(false),
+                        waypoint_details::updated_at.eq(diesel::dsl::now),
+                    ))
+                    .execute(&mut self.db.conn().await?)
+                    .await?;
+                Ok(())
+            };
+            ok_or_warn(updated.await, &format!("save waypoint details {}", symbol));
+        }
+        self.warp_jump_graph.invalidate_all();
+    }
+
+    // Re-fetch construction sites of gates last seen under construction,
+    // catches gates completed by other agents
+    pub async fn recheck_jumpgate_construction(&self) {
+        let under_construction: Vec<WaypointSymbol> = self
+            .jumpgates
+            .iter()
+            .filter(|kv| !kv.value().is_constructed)
+            .map(|kv| kv.key().clone())
+            .collect();
+        debug!(
+            "Rechecking {} jumpgates under construction",
+            under_construction.len()
+        );
+        for symbol in under_construction {
+            let construction = fetch_until_ok(&format!("construction {}", symbol), || {
+                self.api_client.get_construction(&symbol)
+            })
+            .await;
+            match &construction.data {
+                Some(site) => self.update_construction(site).await,
+                None => self.mark_jumpgate_constructed(&symbol).await,
+            }
+        }
+    }
+
+    pub async fn get_system(&self, symbol: &SystemSymbol) -> Arc<System> {
         self.systems
             .get(symbol)
             .expect("System not found")
@@ -433,7 +983,15 @@ impl Universe {
     }
 
     pub async fn get_system_waypoints(&self, symbol: &SystemSymbol) -> Vec<WaypointDetailed> {
-        let system = self.get_system(symbol).await;
+        match self.known_system_waypoints(symbol) {
+            Some(waypoints) => waypoints,
+            None => self.refresh_system_waypoints(symbol).await,
+        }
+    }
+
+    // Waypoint details of the system, None if they haven't been fetched yet
+    pub fn known_system_waypoints(&self, symbol: &SystemSymbol) -> Option<Vec<WaypointDetailed>> {
+        let system = self.system(symbol);
         // Collect Vec<Option<_>> to Option<Vec<_>>
         let waypoints: Option<Vec<WaypointDetailed>> = system
             .waypoints
@@ -450,14 +1008,13 @@ impl Universe {
                     if details.is_uncharted {
                         traits.push("UNCHARTED".to_string());
                     }
-                    let traits = traits
-                        .into_iter()
-                        .map(|symbol| SymbolNameDescr {
-                            symbol,
-                            name: String::new(),
-                            description: String::new(),
-                        })
-                        .collect();
+                    let to_symbol = |symbol: String| SymbolNameDescr {
+                        symbol,
+                        name: String::new(),
+                        description: String::new(),
+                    };
+                    let traits = traits.into_iter().map(to_symbol).collect();
+                    let modifiers = details.modifiers.iter().cloned().map(to_symbol).collect();
                     Some(WaypointDetailed {
                         system_symbol: symbol.clone(),
                         symbol: w.symbol.clone(),
@@ -467,61 +1024,160 @@ impl Universe {
                         traits: traits,
                         // faction: None,
                         is_under_construction: details.is_under_construction,
+                        modifiers,
+                        orbits: details.orbits.clone(),
+                        chart: details.chart.as_ref().map(|(submitted_by, submitted_on)| {
+                            api_models::Chart {
+                                submitted_by: submitted_by.clone(),
+                                submitted_on: *submitted_on,
+                            }
+                        }),
                     })
                 }
                 None => None,
             })
             .collect();
-        match waypoints {
-            Some(waypoints) => waypoints,
-            None => {
-                let waypoints: Vec<WaypointDetailed> =
-                    self.api_client.get_system_waypoints(symbol).await;
-                assert_eq!(waypoints.len(), system.waypoints.len());
-                let inserts: Vec<_> = waypoints
+        waypoints
+    }
+
+    // Fetch waypoint details for the system from the api, updating traits and modifiers
+    pub async fn refresh_system_waypoints(&self, symbol: &SystemSymbol) -> Vec<WaypointDetailed> {
+        let system = self.get_system(symbol).await;
+        let waypoints: Vec<WaypointDetailed> =
+            fetch_until_ok(&format!("waypoints of {}", symbol), || {
+                self.api_client.get_system_waypoints(symbol)
+            })
+            .await;
+        assert_eq!(waypoints.len(), system.waypoints.len());
+        let inserts: Vec<_> = waypoints
+            .iter()
+            .map(|waypoint| {
+                let db_waypoint = system
+                    .waypoints
                     .iter()
-                    .map(|waypoint| {
-                        let db_waypoint = system
-                            .waypoints
-                            .iter()
-                            .find(|w| &w.symbol == &waypoint.symbol)
-                            .expect("Waypoint not found");
-                        NewWaypointDetails {
-                            waypoint_id: db_waypoint.id,
-                            reset_id: self.db.reset_date(),
-                            is_market: waypoint.is_market(),
-                            is_shipyard: waypoint.is_shipyard(),
-                            is_uncharted: waypoint.is_uncharted(),
-                            is_under_construction: waypoint.is_under_construction,
-                        }
-                    })
-                    .collect();
-                diesel::insert_into(waypoint_details::table)
-                    .values(inserts)
-                    .on_conflict(waypoint_details::waypoint_id)
-                    .do_nothing()
-                    .execute(&mut self.db.conn().await)
-                    .await
-                    .expect("DB Insert error");
-                // load to memory (self.systems)
-                let mut s = self.systems.get_mut(symbol).unwrap();
-                let s = s.value_mut();
-                assert_eq!(s.waypoints.len(), waypoints.len());
-                for w in s.waypoints.iter_mut() {
-                    let waypoint = waypoints
+                    .find(|w| &w.symbol == &waypoint.symbol)
+                    .expect("Waypoint not found");
+                NewWaypointDetails {
+                    waypoint_id: db_waypoint.id,
+                    reset_id: self.db.reset_date(),
+                    is_market: waypoint.is_market(),
+                    is_shipyard: waypoint.is_shipyard(),
+                    is_uncharted: waypoint.is_uncharted(),
+                    is_under_construction: waypoint.is_under_construction,
+                    modifiers: waypoint
+                        .modifiers
                         .iter()
-                        .find(|w2| &w2.symbol == &w.symbol)
-                        .expect("Waypoint not found");
-                    w.details = Some(WaypointDetails {
-                        is_market: waypoint.is_market(),
-                        is_shipyard: waypoint.is_shipyard(),
-                        is_uncharted: waypoint.is_uncharted(),
-                        is_under_construction: waypoint.is_under_construction,
-                    });
+                        .map(|m| m.symbol.as_str())
+                        .collect(),
+                    orbits: waypoint.orbits.as_ref().map(|o| o.as_str()),
+                    chart_submitted_by: waypoint.chart.as_ref().map(|c| c.submitted_by.as_str()),
+                    chart_submitted_on: waypoint.chart.as_ref().map(|c| c.submitted_on),
+                }
+            })
+            .collect();
+        let inserted = async {
+            diesel::insert_into(waypoint_details::table)
+                .values(inserts)
+                .on_conflict(waypoint_details::waypoint_id)
+                .do_update()
+                .set((
+                    waypoint_details::is_market.eq(excluded(waypoint_details::is_market)),
+                    waypoint_details::is_shipyard.eq(excluded(waypoint_details::is_shipyard)),
+                    waypoint_details::is_uncharted.eq(excluded(waypoint_details::is_uncharted)),
+                    waypoint_details::is_under_construction
+                        .eq(excluded(waypoint_details::is_under_construction)),
+                    waypoint_details::modifiers.eq(excluded(waypoint_details::modifiers)),
+                    waypoint_details::orbits.eq(excluded(waypoint_details::orbits)),
+                    waypoint_details::chart_submitted_by
+                        .eq(excluded(waypoint_details::chart_submitted_by)),
+                    waypoint_details::chart_submitted_on
+                        .eq(excluded(waypoint_details::chart_submitted_on)),
+                    waypoint_details::updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(&mut self.db.conn().await?)
+                .await?;
+            Ok(())
+        };
+        ok_or_warn(
+            inserted.await,
+            &format!("save waypoint details of {}", symbol),
+        );
+        // load to memory (self.systems)
+        let mut s = self.systems.get_mut(symbol).unwrap();
+        let s = Arc::make_mut(s.value_mut());
+        assert_eq!(s.waypoints.len(), waypoints.len());
+        for w in s.waypoints.iter_mut() {
+            let waypoint = waypoints
+                .iter()
+                .find(|w2| &w2.symbol == &w.symbol)
+                .expect("Waypoint not found");
+            w.details = Some(WaypointDetails {
+                is_market: waypoint.is_market(),
+                is_shipyard: waypoint.is_shipyard(),
+                is_uncharted: waypoint.is_uncharted(),
+                is_under_construction: waypoint.is_under_construction,
+                modifiers: waypoint
+                    .modifiers
+                    .iter()
+                    .map(|m| m.symbol.clone())
+                    .collect(),
+                orbits: waypoint.orbits.clone(),
+                chart: waypoint
+                    .chart
+                    .as_ref()
+                    .map(|c| (c.submitted_by.clone(), c.submitted_on)),
+            });
+        }
+        waypoints
+    }
+
+    // Record the chart we just submitted for a waypoint whose details are loaded
+    pub async fn save_chart(&self, symbol: &WaypointSymbol, chart: &api_models::Chart) {
+        let waypoint_id = match self.systems.get_mut(&symbol.system()) {
+            Some(mut system) => Arc::make_mut(&mut system)
+                .waypoints
+                .iter_mut()
+                .find(|w| &w.symbol == symbol)
+                .and_then(|w| {
+                    let details = w.details.as_mut()?;
+                    details.is_uncharted = false;
+                    details.chart = Some((chart.submitted_by.clone(), chart.submitted_on));
+                    Some(w.id)
+                }),
+            None => None,
+        };
+        let Some(waypoint_id) = waypoint_id else {
+            return;
+        };
+        let updated = async {
+            diesel::update(waypoint_details::table)
+                .filter(waypoint_details::waypoint_id.eq(waypoint_id))
+                .set((
+                    waypoint_details::is_uncharted.eq(false),
+                    waypoint_details::chart_submitted_by.eq(&chart.submitted_by),
+                    waypoint_details::chart_submitted_on.eq(chart.submitted_on),
+                    waypoint_details::updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(&mut self.db.conn().await?)
+                .await?;
+            Ok(())
+        };
+        ok_or_warn(updated.await, &format!("save chart of {}", symbol));
+    }
+
+    // Waypoints with loaded details that `agent` charted, e.g. our own charts
+    pub fn charted_by(&self, agent: &str) -> Vec<WaypointSymbol> {
+        let mut charted = vec![];
+        for system in self.systems.iter() {
+            for waypoint in &system.waypoints {
+                let chart = waypoint.details.as_ref().and_then(|d| d.chart.as_ref());
+                if chart.is_some_and(|(submitted_by, _)| submitted_by == agent) {
+                    charted.push(waypoint.symbol.clone());
                 }
-                waypoints
             }
         }
+        charted.sort();
+        charted
     }
 
     pub async fn get_system_markets(
@@ -593,38 +1249,48 @@ impl Universe {
 
     pub async fn get_market_remote(&self, symbol: &WaypointSymbol) -> MarketRemoteView {
         // Layer 1 - check cache
-        if let Some(market) = &self.remote_markets.get(symbol) {
-            return market.value().clone();
-        }
-        // Layer 2 - check db
-        if let Some(market) = self.db.get_market_remote(symbol).await {
-            self.remote_markets.insert(symbol.clone(), market.clone());
-            return market;
-        }
-        // Layer 3 - fetch from api
-        let market = self.api_client.get_market_remote(symbol).await;
-        self.db.save_market_remote(symbol, &market).await;
-        self.remote_markets.insert(symbol.clone(), market.clone());
-        market
+        self.remote_markets
+            .get_or_load(symbol, || async {
+                // Layer 2 - check db
+                let stored = self.db.get_market_remote(symbol).await;
+                if let Some(market) = ok_or_warn(stored, "load remote market").flatten() {
+                    return market;
+                }
+                // Layer 3 - fetch from api
+                let market = fetch_until_ok(&format!("remote market {}", symbol), || {
+                    self.api_client.get_market_remote(symbol)
+                })
+                .await;
+                ok_or_warn(
+                    self.db.save_market_remote(symbol, &market).await,
+                    "save remote market",
+                );
+                market
+            })
+            .await
     }
 
     pub async fn get_shipyard_remote(&self, symbol: &WaypointSymbol) -> ShipyardRemoteView {
         // Layer 1 - check cache
-        if let Some(shipyard) = &self.remote_shipyards.get(symbol) {
-            return shipyard.value().clone();
-        }
-        // Layer 2 - check db
-        if let Some(shipyard) = self.db.get_shipyard_remote(symbol).await {
-            self.remote_shipyards
-                .insert(symbol.clone(), shipyard.clone());
-            return shipyard;
-        }
-        // Layer 3 - fetch from api
-        let shipyard = self.api_client.get_shipyard_remote(symbol).await;
-        self.db.save_shipyard_remote(symbol, &shipyard).await;
         self.remote_shipyards
-            .insert(symbol.clone(), shipyard.clone());
-        shipyard
+            .get_or_load(symbol, || async {
+                // Layer 2 - check db
+                let stored = self.db.get_shipyard_remote(symbol).await;
+                if let Some(shipyard) = ok_or_warn(stored, "load remote shipyard").flatten() {
+                    return shipyard;
+                }
+                // Layer 3 - fetch from api
+                let shipyard = fetch_until_ok(&format!("remote shipyard {}", symbol), || {
+                    self.api_client.get_shipyard_remote(symbol)
+                })
+                .await;
+                ok_or_warn(
+                    self.db.save_shipyard_remote(symbol, &shipyard).await,
+                    "save remote shipyard",
+                );
+                shipyard
+            })
+            .await
     }
 
     pub async fn search_shipyards(
@@ -681,8 +1347,11 @@ impl Universe {
             WaypointFilter::Market => waypoint.is_market(),
             WaypointFilter::Shipyard => waypoint.is_shipyard(),
             WaypointFilter::GasGiant => waypoint.is_gas_giant(),
+            WaypointFilter::Asteroid => waypoint.is_asteroid(),
             WaypointFilter::EngineeredAsteroid => waypoint.is_engineered_asteroid(),
             WaypointFilter::JumpGate => waypoint.is_jump_gate(),
+            WaypointFilter::Modifier(modifier) => waypoint.has_modifier(modifier),
+            WaypointFilter::NotHazardous => !waypoint.is_hazardous(),
         }
     }
 
@@ -720,6 +1389,60 @@ impl Universe {
         pathfinding.estimate_duration_matrix(speed, fuel_capacity)
     }
 
+    // Systems one jump away through constructed gates, with the jump cooldown
+    pub async fn gate_connections(&self, system_symbol: &SystemSymbol) -> Vec<(SystemSymbol, i64)> {
+        let Some(gate) = self.get_jumpgate_opt(system_symbol).await else {
+            return vec![];
+        };
+        if self.detailed_waypoint(&gate).await.is_under_construction {
+            return vec![];
+        }
+        let system = self.get_system(system_symbol).await;
+        let mut connections = vec![];
+        for dest_gate in self.get_jumpgate_connections(&gate).await.connections {
+            if self
+                .detailed_waypoint(&dest_gate)
+                .await
+                .is_under_construction
+            {
+                continue;
+            }
+            let dest_system = self.get_system(&dest_gate.system()).await;
+            let cooldown = self.jump_ledger.cooldown(
+                system_symbol,
+                &dest_gate.system(),
+                system.distance(&dest_system),
+            );
+            connections.push((dest_gate.system(), cooldown));
+        }
+        connections
+    }
+
+    // Duration matrix across gate-connected systems, see join_gate_matrices
+    pub async fn estimate_gate_duration_matrix(
+        &self,
+        systems: &BTreeSet<SystemSymbol>,
+        speed: i64,
+        fuel_capacity: i64,
+    ) -> DurationMatrix {
+        let mut matrices = vec![];
+        let mut jumps = BTreeMap::new();
+        for system_symbol in systems {
+            let gate = self.get_jumpgate(system_symbol).await;
+            for (dest_system, cooldown) in self.gate_connections(system_symbol).await {
+                if systems.contains(&dest_system) {
+                    let dest_gate = self.get_jumpgate(&dest_system).await;
+                    jumps.insert((gate.clone(), dest_gate), cooldown);
+                }
+            }
+            let matrix = self
+                .estimate_duration_matrix(system_symbol, speed, fuel_capacity)
+                .await;
+            matrices.push((gate, matrix));
+        }
+        join_gate_matrices(&matrices, &jumps)
+    }
+
     pub async fn get_route(
         &self,
         src: &WaypointSymbol,
@@ -727,11 +1450,22 @@ impl Universe {
         speed: i64,
         start_fuel: i64,
         fuel_capacity: i64,
+        urgency: Urgency,
     ) -> Route {
         let system_symbol = src.system();
         assert_eq!(system_symbol, dest.system());
         let waypoints = self.get_system_waypoints(&system_symbol).await;
-        let pathfinding = Pathfinding::new(waypoints);
+        let time_value = urgency.time_value();
+        let cruise_only = self
+            .fuel_ledger
+            .cruise_only_routes(&system_symbol, time_value);
+        let allow_burn = match (urgency, self.local_fuel_price(src).await) {
+            (Urgency::Normal, _) | (_, None) => true,
+            (_, Some(fuel_price)) => burn_pays(speed, time_value, fuel_price),
+        };
+        let pathfinding = Pathfinding::new(waypoints)
+            .with_cruise_only(cruise_only)
+            .with_allow_burn(allow_burn);
         pathfinding.get_route(src, dest, speed, start_fuel, fuel_capacity)
     }
 
@@ -743,7 +1477,8 @@ impl Universe {
         }
 
         // Layer - check db
-        let factions: Option<Vec<Faction>> = self.db.get_value(db_faction_key).await;
+        let factions: Option<Vec<Faction>> =
+            ok_or_warn(self.db.get_value(db_faction_key).await, "load factions").flatten();
         if let Some(factions) = factions {
             for faction in factions {
                 self.factions
@@ -751,8 +1486,12 @@ impl Universe {
             }
         }
         // Layer - fetch from api
-        let factions: Vec<Faction> = self.api_client.get_all_pages("/factions").await;
-        self.db.set_value(db_faction_key, &factions).await;
+        let factions: Vec<Faction> =
+            fetch_until_ok("factions", || self.api_client.get_all_pages("/factions")).await;
+        ok_or_warn(
+            self.db.set_value(db_faction_key, &factions).await,
+            "save factions",
+        );
         for faction in factions {
             self.factions
                 .insert(faction.symbol.clone(), faction.clone());
@@ -784,13 +1523,28 @@ impl Universe {
 
     // Get jumpgate connections for a charted system
     pub async fn get_jumpgate_connections(&self, symbol: &WaypointSymbol) -> JumpGateInfo {
-        if let Some(jumpgate_info) = &self.jumpgates.get(symbol) {
-            return jumpgate_info.value().clone();
+        let fetched = AtomicBool::new(false);
+        let info = self
+            .jumpgates
+            .get_or_load(symbol, || self.fetch_jumpgate_connections(symbol, &fetched))
+            .await;
+        if AtomicBool::load(&fetched, Ordering::Relaxed) {
+            // rebuilt on next use, from the stored warp edges plus the new connections
+            self.warp_jump_graph.invalidate_all();
         }
+        info
+    }
 
-        // Otherwise fetch from API
+    async fn fetch_jumpgate_connections(
+        &self,
+        symbol: &WaypointSymbol,
+        fetched: &AtomicBool,
+    ) -> JumpGateInfo {
         let waypoint = self.detailed_waypoint(symbol).await;
-        let connections = self.api_client.get_jumpgate_conns(symbol).await;
+        let connections = fetch_until_ok(&format!("jump gate {}", symbol), || {
+            self.api_client.get_jumpgate_conns(symbol)
+        })
+        .await;
         let info = JumpGateInfo {
             is_constructed: !waypoint.is_under_construction,
             connections,
@@ -805,21 +1559,27 @@ impl Universe {
                 .map(|x| x.as_str())
                 .collect::<Vec<_>>(),
         };
-        diesel::insert_into(jumpgate_connections::table)
-            .values(&insert)
-            .on_conflict((
-                jumpgate_connections::reset_id,
-                jumpgate_connections::waypoint_symbol,
-            ))
-            .do_update()
-            .set((
-                jumpgate_connections::is_under_construction.eq(&insert.is_under_construction),
-                jumpgate_connections::edges.eq(&insert.edges),
-            ))
-            .execute(&mut self.db.conn().await)
-            .await
-            .expect("DB Insert error");
-        self.jumpgates.insert(symbol.clone(), info.clone());
+        let inserted = async {
+            diesel::insert_into(jumpgate_connections::table)
+                .values(&insert)
+                .on_conflict((
+                    jumpgate_connections::reset_id,
+                    jumpgate_connections::waypoint_symbol,
+                ))
+                .do_update()
+                .set((
+                    jumpgate_connections::is_under_construction.eq(&insert.is_under_construction),
+                    jumpgate_connections::edges.eq(&insert.edges),
+                ))
+                .execute(&mut self.db.conn().await?)
+                .await?;
+            Ok(())
+        };
+        ok_or_warn(
+            inserted.await,
+            &format!("save jumpgate connections of {}", symbol),
+        );
+        fetched.store(true, Ordering::Relaxed);
         info
     }
 }
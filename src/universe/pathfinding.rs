@@ -1,8 +1,13 @@
 use super::Universe;
-use crate::models::{SystemSymbol, WaypointSymbol};
+use crate::config::CONFIG;
+use crate::db::ok_or_warn;
+use crate::db::versioned::Versioned;
+use crate::models::{ShipFlightMode, SystemSymbol, WaypointSymbol};
 use log::*;
+use pathfinding::directed::dijkstra::dijkstra;
 use quadtree_rs::area::AreaBuilder;
 use quadtree_rs::{point::Point, Quadtree};
+use serde::{Deserialize, Serialize};
 use std::cmp::max;
 use std::collections::BTreeMap;
 
@@ -12,17 +17,217 @@ pub struct JumpGate {
     pub all_connections_known: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EdgeType {
     Warp,
+    // warp in DRIFT mode, for systems beyond cruise range
+    Drift,
     Jumpgate,
 }
 
-#[derive(Debug, Clone)]
+impl EdgeType {
+    // Flight mode to warp in, None for jumps
+    pub fn flight_mode(&self) -> Option<ShipFlightMode> {
+        match self {
+            EdgeType::Warp => Some(ShipFlightMode::Cruise),
+            EdgeType::Drift => Some(ShipFlightMode::Drift),
+            EdgeType::Jumpgate => None,
+        }
+    }
+}
+
+// Systems up to this many times the cruise range away are linked by drift edges
+const DRIFT_RANGE_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WarpEdge {
     pub duration: i64,
     pub edge_type: EdgeType,
     pub fuel: i64,
+    // credits of antimatter, for jumps
+    #[serde(default)]
+    pub cost: i64,
+}
+
+impl WarpEdge {
+    // Credits a hop costs: ship time at `time_value` credits per second, fuel and antimatter
+    pub fn weight(&self, time_value: f64, fuel_price: f64) -> i64 {
+        (self.duration as f64 * time_value + self.fuel as f64 * fuel_price).round() as i64
+            + self.cost
+    }
+}
+
+// A hop of a warp route, with the fuel it burns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WarpSegment {
+    pub src: SystemSymbol,
+    pub dest: SystemSymbol,
+    pub edge_type: EdgeType,
+    pub flight_mode: Option<ShipFlightMode>,
+    pub duration: i64,
+    pub fuel: i64,
+    pub cost: i64,
+}
+
+// A planned route between systems, so a long haul's drift legs are known before it's assigned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WarpRoute {
+    pub segments: Vec<WarpSegment>,
+    pub duration: i64,
+    pub fuel: i64,
+    pub cost: i64,
+}
+
+impl WarpRoute {
+    pub fn from_path(
+        path: &[SystemSymbol],
+        graph: &BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>,
+    ) -> WarpRoute {
+        let segments = path
+            .windows(2)
+            .map(|pair| {
+                let edge = &graph[&pair[0]][&pair[1]];
+                WarpSegment {
+                    src: pair[0].clone(),
+                    dest: pair[1].clone(),
+                    edge_type: edge.edge_type.clone(),
+                    flight_mode: edge.edge_type.flight_mode(),
+                    duration: edge.duration,
+                    fuel: edge.fuel,
+                    cost: edge.cost,
+                }
+            })
+            .collect::<Vec<_>>();
+        WarpRoute {
+            duration: segments.iter().map(|s| s.duration).sum(),
+            fuel: segments.iter().map(|s| s.fuel).sum(),
+            cost: segments.iter().map(|s| s.cost).sum(),
+            segments,
+        }
+    }
+
+    // Seconds spent drifting
+    pub fn drift_duration(&self) -> i64 {
+        self.segments
+            .iter()
+            .filter(|s| s.edge_type == EdgeType::Drift)
+            .map(|s| s.duration)
+            .sum()
+    }
+}
+
+// Warp edges between systems, persisted so a restart doesn't recompute them for every system.
+// Jumpgate edges change as gates are charted and built, so they're merged in on each rebuild
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WarpGraph {
+    // coordinates the edges were computed from
+    systems: BTreeMap<SystemSymbol, (i64, i64)>,
+    edges: BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>,
+}
+
+impl Versioned for WarpGraph {
+    const TYPE_NAME: &'static str = "WarpGraph";
+    // v2: drift edges
+    const VERSION: u32 = 2;
+}
+
+impl WarpGraph {
+    // Add the warp edges of systems not in the graph yet, returns whether the graph changed.
+    // A system that moved or disappeared (e.g. after a reset) rebuilds the whole graph
+    pub fn update(
+        &mut self,
+        systems: &[(SystemSymbol, i64, i64)],
+        warp_range: i64,
+        engine_speed: i64,
+    ) -> bool {
+        let current = systems
+            .iter()
+            .map(|(symbol, x, y)| (symbol.clone(), (*x, *y)))
+            .collect::<BTreeMap<_, _>>();
+        let stale = self
+            .systems
+            .iter()
+            .any(|(symbol, coords)| current.get(symbol) != Some(coords));
+        if stale {
+            info!("Rebuilding warp graph");
+            *self = WarpGraph::default();
+        }
+        let new_systems = systems
+            .iter()
+            .filter(|(symbol, _, _)| !self.systems.contains_key(symbol))
+            .collect::<Vec<_>>();
+        if new_systems.is_empty() {
+            return stale;
+        }
+        debug!("Adding {} systems to warp graph", new_systems.len());
+
+        let mut qt = Quadtree::<i64, SystemSymbol>::new_with_anchor(
+            Point {
+                // 2^18 = 262144
+                x: -262144,
+                y: -262144,
+            },
+            19,
+        );
+        for (symbol, x, y) in systems.iter() {
+            qt.insert_pt(Point { x: *x, y: *y }, symbol.clone());
+        }
+
+        // warp edges are symmetric, so a new system also adds edges to its existing neighbours
+        let drift_range = warp_range * DRIFT_RANGE_MULTIPLIER;
+        for (symbol, x, y) in new_systems {
+            let neighbours = qt.query(
+                AreaBuilder::default()
+                    .anchor(Point {
+                        x: x - drift_range,
+                        y: y - drift_range,
+                    })
+                    .dimensions((2 * drift_range + 1, 2 * drift_range + 1))
+                    .build()
+                    .unwrap(),
+            );
+            for pt in neighbours {
+                let coords = pt.anchor();
+                let distance: i64 = {
+                    let distance2 = (x - coords.x).pow(2) + (y - coords.y).pow(2);
+                    max(1, (distance2 as f64).sqrt().round() as i64)
+                };
+                // drift burns a single unit of fuel, at six times the travel time
+                let edge = if distance <= warp_range {
+                    Some(WarpEdge {
+                        duration: (15f64 + (distance as f64) * 50f64 / (engine_speed as f64))
+                            .round() as i64,
+                        edge_type: EdgeType::Warp,
+                        fuel: distance,
+                        cost: 0,
+                    })
+                } else if distance <= drift_range {
+                    Some(WarpEdge {
+                        duration: (15f64 + (distance as f64) * 300f64 / (engine_speed as f64))
+                            .round() as i64,
+                        edge_type: EdgeType::Drift,
+                        fuel: 1,
+                        cost: 0,
+                    })
+                } else {
+                    None
+                };
+                if let Some(edge) = edge {
+                    let neighbour = pt.value_ref();
+                    self.edges
+                        .entry(neighbour.clone())
+                        .or_default()
+                        .insert(symbol.clone(), edge.clone());
+                    self.edges
+                        .entry(symbol.clone())
+                        .or_default()
+                        .insert(neighbour.clone(), edge);
+                }
+            }
+            self.systems.insert(symbol.clone(), (*x, *y));
+        }
+        true
+    }
 }
 
 impl Universe {
@@ -81,7 +286,9 @@ impl Universe {
                     continue;
                 }
                 let distance = src_system.distance(&dst_system);
-                let cooldown = 60 + distance;
+                let cooldown =
+                    self.jump_ledger
+                        .cooldown(&src_system.symbol, &dst_system.symbol, distance);
 
                 // src -> dst
                 let src_entry = graph.get_mut(src_symbol).unwrap();
@@ -92,6 +299,9 @@ impl Universe {
                 // for dst -> src, insert unless 'complete'
                 let entry = graph.get_mut(dst_symbol).unwrap();
                 if !entry.all_connections_known {
+                    let cooldown =
+                        self.jump_ledger
+                            .cooldown(&dst_system.symbol, &src_system.symbol, distance);
                     entry
                         .active_connections
                         .push((src_symbol.clone(), cooldown));
@@ -139,62 +349,30 @@ impl Universe {
                     1 => Some(filtered.first().unwrap().clone()),
                     _ => panic!("Multiple jumpgates in system {}", s.symbol),
                 };
-                (s.symbol, s.x, s.y, jumpgate)
+                (s.symbol.clone(), s.x, s.y, jumpgate)
             })
             .collect::<Vec<_>>();
 
-        info!("Constructing quadtree");
-        let mut qt = Quadtree::<i64, SystemSymbol>::new_with_anchor(
-            Point {
-                // 2^18 = 262144
-                x: -262144,
-                y: -262144,
-            },
-            19,
-        );
-        for (symbol, x, y, _jumpgate) in systems.iter() {
-            qt.insert_pt(Point { x: *x, y: *y }, symbol.clone());
-        }
-        info!("Constructing quadtree done");
-
-        // Construct graph
-        let mut warp_graph: BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>> =
-            BTreeMap::new();
-        for (symbol, x, y, jumpgate) in systems.iter() {
-            let mut edges: BTreeMap<SystemSymbol, WarpEdge> = BTreeMap::new();
-
-            // Add warp edges
-            let neighbours = qt.query(
-                AreaBuilder::default()
-                    .anchor(Point {
-                        x: x - warp_range,
-                        y: y - warp_range,
-                    })
-                    .dimensions((2 * warp_range + 1, 2 * warp_range + 1))
-                    .build()
-                    .unwrap(),
+        let key = format!("warp_graph/{}_{}", warp_range, engine_speed);
+        let stored = self.db.get_versioned(&key).await;
+        let mut warp_graph: WarpGraph = ok_or_warn(stored, &format!("load {}", key))
+            .flatten()
+            .unwrap_or_default();
+        let coords = systems
+            .iter()
+            .map(|(symbol, x, y, _jumpgate)| (symbol.clone(), *x, *y))
+            .collect::<Vec<_>>();
+        if warp_graph.update(&coords, warp_range, engine_speed) {
+            ok_or_warn(
+                self.db.set_versioned(&key, &warp_graph).await,
+                &format!("save {}", key),
             );
-            for pt in neighbours {
-                let coords = pt.anchor();
-                let distance: i64 = {
-                    let distance2 = (x - coords.x).pow(2) + (y - coords.y).pow(2);
-                    max(1, (distance2 as f64).sqrt().round() as i64)
-                };
-                let duration =
-                    (15f64 + (distance as f64) * 50f64 / (engine_speed as f64)).round() as i64;
-                if distance <= warp_range {
-                    edges.insert(
-                        pt.value_ref().clone(),
-                        WarpEdge {
-                            duration,
-                            edge_type: EdgeType::Warp,
-                            fuel: distance,
-                        },
-                    );
-                }
-            }
+        }
 
-            // Add jumpgate edges (overwrites warp edges if edge already exists)
+        // Add jumpgate edges (overwrites warp edges if edge already exists)
+        let mut graph = warp_graph.edges;
+        for (symbol, _x, _y, jumpgate) in systems.iter() {
+            let edges = graph.entry(symbol.clone()).or_default();
             if let Some(jumpgate) = jumpgate {
                 for conn in jumpgate_graph
                     .get(jumpgate)
@@ -209,13 +387,135 @@ impl Universe {
                             duration: *cooldown,
                             edge_type: EdgeType::Jumpgate,
                             fuel: 0,
+                            cost: self.jump_ledger.jump_cost(symbol, &dest_symbol.system()),
                         },
                     );
                 }
             }
-            warp_graph.insert(symbol.clone(), edges);
         }
+        graph
+    }
+
+    // The cheapest explorer route between two systems, None if it's unreachable
+    pub async fn warp_route(&self, src: &SystemSymbol, dest: &SystemSymbol) -> Option<WarpRoute> {
+        let graph = self.warp_jump_graph().await;
+        self.cheapest_warp_route(&graph, src, dest)
+    }
+
+    // As warp_route, if the graph has already been built
+    pub async fn known_warp_route(
+        &self,
+        src: &SystemSymbol,
+        dest: &SystemSymbol,
+    ) -> Option<WarpRoute> {
+        let graph = self.warp_jump_graph.get(&()).await?;
+        self.cheapest_warp_route(&graph, src, dest)
+    }
+
+    fn cheapest_warp_route(
+        &self,
+        graph: &BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>,
+        src: &SystemSymbol,
+        dest: &SystemSymbol,
+    ) -> Option<WarpRoute> {
+        let fuel_price = self.fleet_fuel_price();
+        let (path, _cost) = dijkstra(
+            src,
+            |node| {
+                graph
+                    .get(node)
+                    .into_iter()
+                    .flatten()
+                    .map(|(s, d)| (s.clone(), d.weight(CONFIG.burn_time_value, fuel_price)))
+            },
+            |node| node == dest,
+        )?;
+        Some(WarpRoute::from_path(&path, graph))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_warp_graph_update() {
+        let system = |symbol: &str, x: i64, y: i64| (SystemSymbol::new(symbol), x, y);
+        let mut systems = vec![
+            system("X1-A", 0, 0),
+            system("X1-B", 300, 400),
+            system("X1-C", 5000, 0),
+        ];
+        let mut graph = WarpGraph::default();
+        assert!(graph.update(&systems, 800, 30));
+        assert!(!graph.update(&systems, 800, 30));
+        let a = SystemSymbol::new("X1-A");
+        let b = SystemSymbol::new("X1-B");
+        assert_eq!(graph.edges[&a][&b].fuel, 500);
+        assert_eq!(graph.edges[&a][&b].duration, 15 + 833);
+        assert!(!graph.edges[&a].contains_key(&SystemSymbol::new("X1-C")));
+
+        // a discovered system gets its edges, and its neighbours an edge back, same as a full build
+        systems.push(system("X1-D", 5100, 100));
+        assert!(graph.update(&systems, 800, 30));
+        let mut full = WarpGraph::default();
+        full.update(&systems, 800, 30);
+        assert_eq!(graph, full);
+        assert!(graph.edges[&SystemSymbol::new("X1-C")].contains_key(&SystemSymbol::new("X1-D")));
+
+        // a moved system rebuilds the graph
+        systems[1] = system("X1-B", 3000, 0);
+        assert!(graph.update(&systems, 800, 30));
+        assert!(!graph.edges[&a].contains_key(&b));
+    }
+
+    #[test]
+    fn test_warp_route() {
+        let system = |symbol: &str, x: i64, y: i64| (SystemSymbol::new(symbol), x, y);
+        let systems = vec![
+            system("X1-A", 0, 0),
+            system("X1-B", 600, 0),
+            system("X1-C", 2000, 0),
+        ];
+        let mut graph = WarpGraph::default();
+        graph.update(&systems, 800, 30);
+        let a = SystemSymbol::new("X1-A");
+        let b = SystemSymbol::new("X1-B");
+        let c = SystemSymbol::new("X1-C");
+
+        // B -> C is beyond cruise range, but within drift range
+        let drift = &graph.edges[&b][&c];
+        assert_eq!(drift.edge_type, EdgeType::Drift);
+        assert_eq!(drift.fuel, 1);
+        assert_eq!(drift.duration, 15 + 14_000);
+        assert!(!graph.edges[&a].contains_key(&c));
+
+        let route = WarpRoute::from_path(&[a.clone(), b.clone(), c.clone()], &graph.edges);
+        assert_eq!(route.segments.len(), 2);
+        assert_eq!(route.segments[0].flight_mode, Some(ShipFlightMode::Cruise));
+        assert_eq!(route.segments[1].flight_mode, Some(ShipFlightMode::Drift));
+        assert_eq!(route.fuel, 601);
+        assert_eq!(route.duration, 15 + 1000 + 15 + 14_000);
+        assert_eq!(route.drift_duration(), 15 + 14_000);
+    }
 
-        warp_graph
+    #[test]
+    fn test_edge_weight() {
+        let warp = WarpEdge {
+            duration: 848,
+            edge_type: EdgeType::Warp,
+            fuel: 500,
+            cost: 0,
+        };
+        let jump = WarpEdge {
+            duration: 560,
+            edge_type: EdgeType::Jumpgate,
+            fuel: 0,
+            cost: 10_000,
+        };
+        assert_eq!(warp.weight(2.0, 1.0), 2196);
+        assert_eq!(jump.weight(2.0, 1.0), 11_120);
+        // time valuable enough that jumping pays for the antimatter
+        assert!(jump.weight(40.0, 1.0) < warp.weight(40.0, 1.0));
     }
 }
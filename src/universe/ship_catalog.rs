@@ -0,0 +1,245 @@
+/// Ship model catalog.
+/// Seeded with the models we know how to identify, and updated with the stats and price range of
+/// every model seen in a shipyard listing. Models that are never sold (the command frigate) only
+/// have their built-in stats.
+use crate::models::{Ship, ShipModel, ShipyardShip};
+use dashmap::DashMap;
+
+fn builtin(
+    ship_type: &str,
+    frame: &str,
+    reactor: &str,
+    engine: &str,
+    modules: &[&str],
+    mounts: &[&str],
+    cargo_capacity: i64,
+) -> ShipModel {
+    ShipModel {
+        ship_type: ship_type.to_string(),
+        frame: frame.to_string(),
+        reactor: reactor.to_string(),
+        engine: engine.to_string(),
+        modules: modules.iter().map(|m| m.to_string()).collect(),
+        mounts: mounts.iter().map(|m| m.to_string()).collect(),
+        cargo_capacity,
+        fuel_capacity: None,
+        speed: None,
+        min_price: None,
+        max_price: None,
+    }
+}
+
+fn builtin_models() -> Vec<ShipModel> {
+    vec![
+        builtin(
+            "SHIP_COMMAND_FRIGATE",
+            "FRAME_FRIGATE",
+            "REACTOR_FISSION_I",
+            "ENGINE_ION_DRIVE_II",
+            &[],
+            &[],
+            40,
+        ),
+        builtin(
+            "SHIP_PROBE",
+            "FRAME_PROBE",
+            "REACTOR_SOLAR_I",
+            "ENGINE_IMPULSE_DRIVE_I",
+            &[],
+            &[],
+            0,
+        ),
+        builtin(
+            "SHIP_LIGHT_SHUTTLE",
+            "FRAME_SHUTTLE",
+            "REACTOR_CHEMICAL_I",
+            "ENGINE_IMPULSE_DRIVE_I",
+            &[],
+            &[],
+            40,
+        ),
+        builtin(
+            "SHIP_LIGHT_HAULER",
+            "FRAME_LIGHT_FREIGHTER",
+            "REACTOR_CHEMICAL_I",
+            "ENGINE_ION_DRIVE_I",
+            &[],
+            &[],
+            80,
+        ),
+        builtin(
+            "SHIP_MINING_DRONE",
+            "FRAME_DRONE",
+            "REACTOR_CHEMICAL_I",
+            "ENGINE_IMPULSE_DRIVE_I",
+            &["MODULE_MINERAL_PROCESSOR_I"],
+            &["MOUNT_MINING_LASER_I"],
+            15,
+        ),
+        builtin(
+            "SHIP_SURVEYOR",
+            "FRAME_DRONE",
+            "REACTOR_CHEMICAL_I",
+            "ENGINE_IMPULSE_DRIVE_I",
+            &[],
+            &["MOUNT_SURVEYOR_I"],
+            0,
+        ),
+        builtin(
+            "SHIP_SIPHON_DRONE",
+            "FRAME_DRONE",
+            "REACTOR_CHEMICAL_I",
+            "ENGINE_IMPULSE_DRIVE_I",
+            &["MODULE_GAS_PROCESSOR_I"],
+            &["MOUNT_GAS_SIPHON_I"],
+            15,
+        ),
+        builtin(
+            "SHIP_REFINING_FREIGHTER",
+            "FRAME_HEAVY_FREIGHTER",
+            "REACTOR_FUSION_I",
+            "ENGINE_ION_DRIVE_II",
+            &["MODULE_CARGO_HOLD_III", "MODULE_ORE_REFINERY_I"],
+            &["MOUNT_MISSILE_LAUNCHER_I"],
+            150,
+        ),
+        builtin(
+            "SHIP_ORE_HOUND",
+            "FRAME_MINER",
+            "REACTOR_FISSION_I",
+            "ENGINE_ION_DRIVE_I",
+            &["MODULE_MINERAL_PROCESSOR_I"],
+            &["MOUNT_MINING_LASER_II", "MOUNT_SURVEYOR_I"],
+            40,
+        ),
+        builtin(
+            "SHIP_EXPLORER",
+            "FRAME_EXPLORER",
+            "REACTOR_FUSION_I",
+            "ENGINE_ION_DRIVE_II",
+            &["MODULE_WARP_DRIVE_I"],
+            &["MOUNT_SENSOR_ARRAY_II", "MOUNT_GAS_SIPHON_II"],
+            40,
+        ),
+    ]
+}
+
+#[derive(Debug)]
+pub struct ShipCatalog {
+    models: DashMap<String, ShipModel>,
+}
+
+impl ShipCatalog {
+    pub fn builtin() -> Self {
+        let catalog = Self {
+            models: DashMap::new(),
+        };
+        for model in builtin_models() {
+            catalog.insert(model);
+        }
+        catalog
+    }
+
+    pub fn insert(&self, model: ShipModel) {
+        self.models.insert(model.ship_type.clone(), model);
+    }
+
+    // Merge a shipyard listing into the catalog. Returns the updated model if anything changed.
+    pub fn observe(&self, listing: &ShipyardShip) -> Option<ShipModel> {
+        let mut model = ShipModel::from_listing(listing);
+        if let Some(prev) = self.models.get(&listing.ship_type) {
+            if let (Some(min_price), Some(max_price)) = (prev.min_price, prev.max_price) {
+                model.min_price = Some(listing.purchase_price.min(min_price));
+                model.max_price = Some(listing.purchase_price.max(max_price));
+            }
+            if *prev == model {
+                return None;
+            }
+        }
+        self.insert(model.clone());
+        Some(model)
+    }
+
+    pub fn get(&self, ship_type: &str) -> Option<ShipModel> {
+        self.models.get(ship_type).map(|m| m.clone())
+    }
+
+    pub fn cargo_capacity(&self, ship_type: &str) -> Option<i64> {
+        self.models.get(ship_type).map(|m| m.cargo_capacity)
+    }
+
+    pub fn all(&self) -> Vec<ShipModel> {
+        let mut models = self.models.iter().map(|m| m.clone()).collect::<Vec<_>>();
+        models.sort_by(|a, b| a.ship_type.cmp(&b.ship_type));
+        models
+    }
+
+    // The model of a ship, which must match exactly one model in the catalog
+    pub fn identify(&self, ship: &Ship) -> Result<String, String> {
+        let matching_models = self
+            .models
+            .iter()
+            .filter(|m| m.matches(ship))
+            .map(|m| m.ship_type.clone())
+            .collect::<Vec<_>>();
+        if matching_models.len() == 1 {
+            return Ok(matching_models[0].clone());
+        }
+        Err(format!(
+            "{} matching models for ship {} with frame: {}, reactor: {}, engine: {}",
+            matching_models.len(),
+            ship.symbol,
+            ship.frame.symbol,
+            ship.reactor.symbol,
+            ship.engine.symbol
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::WaypointSymbol;
+    use crate::test_fixtures;
+
+    fn hauler_listing(purchase_price: i64) -> ShipyardShip {
+        serde_json::from_value(serde_json::json!({
+            "type": "SHIP_LIGHT_HAULER",
+            "purchasePrice": purchase_price,
+            "frame": {"symbol": "FRAME_LIGHT_FREIGHTER", "fuelCapacity": 2000, "condition": 1.0, "moduleSlots": 8, "mountingPoints": 2, "requirements": {}},
+            "reactor": {"symbol": "REACTOR_CHEMICAL_I", "condition": 1.0, "powerOutput": 15, "requirements": {}},
+            "engine": {"symbol": "ENGINE_ION_DRIVE_I", "condition": 1.0, "speed": 15, "requirements": {}},
+            "modules": [
+                {"symbol": "MODULE_CARGO_HOLD_II", "capacity": 40, "requirements": {}},
+                {"symbol": "MODULE_CARGO_HOLD_II", "capacity": 40, "requirements": {}},
+                {"symbol": "MODULE_CREW_QUARTERS_I", "capacity": 40, "requirements": {}}
+            ],
+            "mounts": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ship_catalog() {
+        let catalog = ShipCatalog::builtin();
+        let probe = test_fixtures::probe("AGENT-1", &WaypointSymbol::new("X1-AB12-A1"));
+        assert_eq!(catalog.identify(&probe).unwrap(), "SHIP_PROBE");
+        assert_eq!(catalog.cargo_capacity("SHIP_LIGHT_HAULER"), Some(80));
+        assert_eq!(catalog.get("SHIP_LIGHT_HAULER").unwrap().speed, None);
+
+        let model = catalog.observe(&hauler_listing(400_000)).unwrap();
+        assert_eq!(model.cargo_capacity, 80);
+        assert_eq!(model.fuel_capacity, Some(2000));
+        assert_eq!(model.speed, Some(15));
+        assert_eq!(model.modules.len(), 3);
+        assert!(catalog.observe(&hauler_listing(400_000)).is_none());
+
+        // price range widens with each observation
+        catalog.observe(&hauler_listing(350_000)).unwrap();
+        catalog.observe(&hauler_listing(450_000)).unwrap();
+        assert!(catalog.observe(&hauler_listing(420_000)).is_none());
+        let model = catalog.get("SHIP_LIGHT_HAULER").unwrap();
+        assert_eq!(model.min_price, Some(350_000));
+        assert_eq!(model.max_price, Some(450_000));
+    }
+}
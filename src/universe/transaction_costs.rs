@@ -0,0 +1,95 @@
+/// Transaction costs observed in our own trades, per market and good.
+/// The cost of a trade is how much worse it executed than the listed price: paid above the purchase
+/// price, or received below the sell price, including any fee in the total. Trades that only look
+/// profitable on the listed spread are rejected once these costs are taken into account.
+use crate::models::WaypointSymbol;
+use dashmap::DashMap;
+
+// Weight of the newest observation in the moving average
+const SMOOTHING: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TradeSide {
+    Purchase,
+    Sell,
+}
+
+impl TradeSide {
+    pub fn from_transaction_type(_type: &str) -> Option<Self> {
+        match _type {
+            "PURCHASE" => Some(TradeSide::Purchase),
+            "SELL" => Some(TradeSide::Sell),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TransactionCosts {
+    // (market, good, side) -> moving average cost per unit
+    per_unit: DashMap<(WaypointSymbol, String, TradeSide), f64>,
+}
+
+impl TransactionCosts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(
+        &self,
+        market: &WaypointSymbol,
+        good: &str,
+        side: TradeSide,
+        listed_price: i64,
+        units: i64,
+        total_price: i64,
+    ) {
+        if units <= 0 {
+            return;
+        }
+        let executed = total_price as f64 / units as f64;
+        let cost = match side {
+            TradeSide::Purchase => executed - listed_price as f64,
+            TradeSide::Sell => listed_price as f64 - executed,
+        };
+        self.per_unit
+            .entry((market.clone(), good.to_string(), side))
+            .and_modify(|avg| *avg = SMOOTHING * cost + (1.0 - SMOOTHING) * *avg)
+            .or_insert(cost);
+    }
+
+    // Expected cost per unit on top of the listed price. Never negative, so a lucky fill doesn't
+    // make marginal trades look better than their spread.
+    pub fn per_unit(&self, market: &WaypointSymbol, good: &str, side: TradeSide) -> i64 {
+        self.per_unit
+            .get(&(market.clone(), good.to_string(), side))
+            .map(|avg| avg.max(0.0).round() as i64)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_transaction_costs() {
+        let costs = TransactionCosts::new();
+        let market = WaypointSymbol::new("X1-AB12-A1");
+        assert_eq!(costs.per_unit(&market, "IRON", TradeSide::Purchase), 0);
+
+        // listed at 100, paid 110 per unit
+        costs.record(&market, "IRON", TradeSide::Purchase, 100, 10, 1100);
+        assert_eq!(costs.per_unit(&market, "IRON", TradeSide::Purchase), 10);
+        // a fill at the listed price pulls the average down
+        costs.record(&market, "IRON", TradeSide::Purchase, 100, 10, 1000);
+        assert_eq!(costs.per_unit(&market, "IRON", TradeSide::Purchase), 7);
+        assert_eq!(costs.per_unit(&market, "IRON", TradeSide::Sell), 0);
+
+        // sold above the listed price: no negative cost
+        costs.record(&market, "FUEL", TradeSide::Sell, 50, 10, 600);
+        assert_eq!(costs.per_unit(&market, "FUEL", TradeSide::Sell), 0);
+        costs.record(&market, "COPPER", TradeSide::Sell, 50, 10, 450);
+        assert_eq!(costs.per_unit(&market, "COPPER", TradeSide::Sell), 5);
+    }
+}
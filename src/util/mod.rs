@@ -0,0 +1,15 @@
+pub mod retry;
+pub mod single_flight_cache;
+
+use std::any::Any;
+
+// Message of a caught panic, e.g. from a failed JoinHandle
+pub fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
@@ -0,0 +1,160 @@
+/// Retries of fallible async operations, shared by the API and database clients.
+/// A policy sets the number of attempts and the delay between them, and the caller decides which
+/// errors are worth retrying. The last error is returned once the attempts run out.
+use log::*;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    // base, 2 * base, 4 * base, ...
+    Exponential,
+    // random between base and 3x the previous delay, spreads out clients that failed together
+    DecorrelatedJitter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub backoff: Backoff,
+    pub base: Duration,
+    pub max_delay: Duration,
+    // including the first attempt
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    pub const fn exponential(base: Duration, max_delay: Duration, max_attempts: u32) -> Self {
+        RetryPolicy {
+            backoff: Backoff::Exponential,
+            base,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    pub const fn decorrelated_jitter(
+        base: Duration,
+        max_delay: Duration,
+        max_attempts: u32,
+    ) -> Self {
+        RetryPolicy {
+            backoff: Backoff::DecorrelatedJitter,
+            base,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    // Delay after the failed `attempt` (1-based), given the previous delay
+    pub fn delay(&self, attempt: u32, prev: Duration) -> Duration {
+        let delay = match self.backoff {
+            Backoff::Exponential => self.base.saturating_mul(1 << (attempt - 1).min(16)),
+            Backoff::DecorrelatedJitter => {
+                let upper = prev.saturating_mul(3).max(self.base);
+                rand::thread_rng().gen_range(self.base..=upper)
+            }
+        };
+        delay.min(self.max_delay)
+    }
+}
+
+// Run `op` until it succeeds, fails with an error `should_retry` rejects, or runs out of attempts
+pub async fn retry<T, E, F, Fut, P>(
+    policy: &RetryPolicy,
+    label: &str,
+    should_retry: P,
+    mut op: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    P: Fn(&E) -> bool,
+    E: std::fmt::Debug,
+{
+    let mut attempt = 1;
+    let mut delay = Duration::ZERO;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.max_attempts && should_retry(&e) => {
+                delay = policy.delay(attempt, delay);
+                warn!(
+                    "{} failed (attempt {}/{}), retrying in {:?}: {:?}",
+                    label, attempt, policy.max_attempts, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_retry() {
+        let ms = Duration::from_millis;
+        let policy = RetryPolicy::exponential(ms(1), ms(3), 4);
+        assert_eq!(policy.delay(1, Duration::ZERO), ms(1));
+        assert_eq!(policy.delay(2, ms(1)), ms(2));
+        assert_eq!(policy.delay(3, ms(2)), ms(3));
+
+        let jitter = RetryPolicy::decorrelated_jitter(ms(10), ms(100), 4);
+        for _ in 0..20 {
+            let delay = jitter.delay(2, ms(20));
+            assert!(delay >= ms(10) && delay <= ms(60));
+        }
+        assert!(jitter.delay(5, ms(90)) <= ms(100));
+
+        // succeeds on the third attempt
+        let calls = AtomicU32::new(0);
+        let result: Result<u32, &str> = retry(
+            &policy,
+            "op",
+            |_| true,
+            || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    n if n < 2 => Err("transient"),
+                    n => Ok(n),
+                }
+            },
+        )
+        .await;
+        assert_eq!(result, Ok(2));
+
+        // stops at max attempts
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), &str> = retry(
+            &policy,
+            "op",
+            |_| true,
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err("down")
+            },
+        )
+        .await;
+        assert_eq!(result, Err("down"));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        // errors the predicate rejects aren't retried
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<(), &str> = retry(
+            &policy,
+            "op",
+            |e| *e != "fatal",
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err("fatal")
+            },
+        )
+        .await;
+        assert_eq!(result, Err("fatal"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
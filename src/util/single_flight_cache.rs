@@ -0,0 +1,107 @@
+/// Cache-aside map where concurrent misses on a key share a single load.
+/// The first caller to miss runs the loader while later callers for the same key wait on it, and then
+/// read what it cached. Other keys load independently. Derefs to the underlying map for direct reads
+/// and updates
+use dashmap::DashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+pub struct SingleFlightCache<K: Hash + Eq, V> {
+    values: DashMap<K, V>,
+    // held while a key is being loaded
+    in_flight: DashMap<K, Arc<Mutex<()>>>,
+}
+
+impl<K: Hash + Eq, V> Default for SingleFlightCache<K, V> {
+    fn default() -> Self {
+        Self {
+            values: DashMap::new(),
+            in_flight: DashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlightCache<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get_or_load<F, Fut>(&self, key: &K, load: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        if let Some(value) = self.values.get(key) {
+            return value.clone();
+        }
+        let lock = self.in_flight.entry(key.clone()).or_default().clone();
+        let _guard = lock.lock().await;
+        // loaded while we waited
+        if let Some(value) = self.values.get(key) {
+            return value.clone();
+        }
+        let value = load().await;
+        self.values.insert(key.clone(), value.clone());
+        self.in_flight.remove(key);
+        value
+    }
+}
+
+impl<K: Hash + Eq, V> Deref for SingleFlightCache<K, V> {
+    type Target = DashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let cache: Arc<SingleFlightCache<String, u32>> = Arc::new(SingleFlightCache::new());
+        let loads = Arc::new(AtomicU32::new(0));
+        let mut handles = vec![];
+        for i in 0..10 {
+            let cache = cache.clone();
+            let loads = loads.clone();
+            let key = match i % 2 {
+                0 => "a".to_string(),
+                _ => "b".to_string(),
+            };
+            handles.push(tokio::spawn(async move {
+                cache
+                    .get_or_load(&key, || async {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        loads.fetch_add(1, Ordering::SeqCst) + 100
+                    })
+                    .await
+            }));
+        }
+        let mut values = vec![];
+        for handle in handles {
+            values.push(handle.await.unwrap());
+        }
+        // one load per key, every caller gets its key's value
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert!(values.iter().step_by(2).all(|v| *v == values[0]));
+        assert!(values.iter().skip(1).step_by(2).all(|v| *v == values[1]));
+        assert_ne!(values[0], values[1]);
+        assert!(cache.in_flight.is_empty());
+
+        // cached from then on, and direct updates are seen
+        let value = cache.get_or_load(&"a".to_string(), || async { 0 }).await;
+        assert_eq!(value, values[0]);
+        cache.insert("a".to_string(), 7);
+        assert_eq!(cache.get_or_load(&"a".to_string(), || async { 0 }).await, 7);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+}
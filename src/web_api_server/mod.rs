@@ -1,13 +1,41 @@
 use crate::{
-    agent_controller::{AgentController, Event},
-    api_client::api_models::WaypointDetailed,
-    db::DbClient,
-    models::Agent,
-    universe::Universe,
+    agent_controller::{
+        credit_forecast::CreditForecast,
+        event_bus::{EventSubscriber, SubscriberMetrics},
+        expansion::{ExpansionCandidate, ExpansionOverride},
+        goals::GoalStatus,
+        job_filter::JobFilter,
+        ledger::NetWorth,
+        wind_down::{WindDown, WindDownStatus},
+        AgentController, Event,
+    },
+    api_client::{api_models::WaypointDetailed, rate_limiter::RateLimitMetrics},
+    cargo_valuer::{CargoValuation, CargoValuer},
+    db::{
+        db_models::{
+            ConstructionContribution, JobAssignment, NetWorthSample, ShipListingSample,
+            SurveyConsumption,
+        },
+        DbClient, DbError,
+    },
+    logistics_planner::{Action, Task},
+    market_health::{self, MarketHealthReport},
+    models::{Agent, Ship, ShipFlightMode, ShipNavRoute, SystemSymbol, WaypointSymbol},
+    pathfinding::{edge, Urgency},
+    survey_manager::{survey_balance, SurveyBalance},
+    universe::{
+        access::UniverseReader, fuel_ledger::FuelReport, market_deltas::MarketDeltas,
+        pathfinding::WarpRoute, UniverseHandle,
+    },
 };
 use axum::{debug_handler, http::StatusCode};
-use axum::{extract::State, routing::get};
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use log::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use socketioxide::{
     extract::{Data, SocketRef},
@@ -19,15 +47,29 @@ use tower_http::cors::CorsLayer;
 pub struct WebApiServer {
     agent_controller: AgentController,
     db_client: DbClient,
-    universe: Arc<Universe>,
+    universe: Arc<dyn UniverseReader>,
+    port: u16,
 }
 
+pub const DEFAULT_PORT: u16 = 8080;
+
 struct AppState {
     agent_controller: AgentController,
-    #[allow(dead_code)]
     db_client: DbClient,
-    #[allow(dead_code)]
-    universe: Arc<Universe>,
+    universe: Arc<dyn UniverseReader>,
+}
+
+fn db_error(e: DbError) -> StatusCode {
+    warn!("Web API database error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+// System symbols in request paths, a malformed one is the client's error
+fn parse_system(symbol: &str) -> Result<SystemSymbol, StatusCode> {
+    SystemSymbol::parse(symbol).map_err(|e| {
+        debug!("Web API bad system symbol {}: {}", symbol, e);
+        StatusCode::BAD_REQUEST
+    })
 }
 
 #[debug_handler]
@@ -41,13 +83,16 @@ async fn ships_handler(State(state): State<Arc<AppState>>) -> axum::Json<Vec<ser
     let ships = state.agent_controller.ships();
     let ships = ships
         .into_iter()
-        .map(|(symbol, ship, job_id, desc)| {
+        .map(|(symbol, ship, job_id, state, status)| {
+            let desc = state.as_ref().map(|s| s.step.clone()).unwrap_or_default();
             json!(
                 {
                     "symbol": symbol,
                     "ship": ship,
                     "job_id": job_id,
-                    "desc": desc
+                    "desc": desc,
+                    "state": state,
+                    "status": status
                 }
             )
         })
@@ -55,12 +100,477 @@ async fn ships_handler(State(state): State<Arc<AppState>>) -> axum::Json<Vec<ser
     axum::Json(ships)
 }
 
+#[debug_handler]
+async fn goals_handler(State(state): State<Arc<AppState>>) -> axum::Json<Vec<GoalStatus>> {
+    let goals = state.agent_controller.goals();
+    axum::Json(goals)
+}
+
+#[debug_handler]
+async fn credit_forecast_handler(State(state): State<Arc<AppState>>) -> axum::Json<CreditForecast> {
+    axum::Json(state.agent_controller.credit_forecast().await)
+}
+
+#[derive(Debug, Serialize)]
+struct PlannedHop {
+    waypoint: WaypointSymbol,
+    flight_mode: ShipFlightMode,
+    arrival: DateTime<Utc>,
+    fuel_on_arrival: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct PlannedAction {
+    waypoint: WaypointSymbol,
+    action: Action,
+    eta: DateTime<Utc>,
+    hops: Vec<PlannedHop>,
+}
+
+#[derive(Debug, Serialize)]
+struct ShipRouteView {
+    symbol: String,
+    nav_route: ShipNavRoute,
+    in_transit: bool,
+    planned_actions: Vec<PlannedAction>,
+}
+
+// Project ETAs and fuel levels for the ship's remaining scheduled actions, starting from its current nav
+async fn project_actions(
+    universe: &dyn UniverseReader,
+    ship: &Ship,
+    actions: Vec<(WaypointSymbol, Action)>,
+) -> Vec<PlannedAction> {
+    let mut time = std::cmp::max(Utc::now(), ship.nav.route.arrival);
+    let mut position = ship.nav.waypoint_symbol.clone();
+    let mut fuel = ship.fuel.current;
+    let speed = ship.engine.speed;
+    let fuel_capacity = ship.fuel.capacity;
+
+    let mut planned = Vec::new();
+    for (waypoint, action) in actions {
+        let mut hops = Vec::new();
+        if waypoint != position {
+            if fuel_capacity == 0 {
+                let (Some(a), Some(b)) = (
+                    universe.known_waypoint(&position),
+                    universe.known_waypoint(&waypoint),
+                ) else {
+                    break;
+                };
+                let e = edge(&a, &b, speed, a.distance(&b)).unwrap();
+                time += ChronoDuration::try_seconds(e.travel_duration).unwrap();
+                hops.push(PlannedHop {
+                    waypoint: waypoint.clone(),
+                    flight_mode: e.flight_mode,
+                    arrival: time,
+                    fuel_on_arrival: 0,
+                });
+            } else {
+                let Some(route) = universe
+                    .known_route(
+                        &position,
+                        &waypoint,
+                        speed,
+                        fuel,
+                        fuel_capacity,
+                        Urgency::Normal,
+                    )
+                    .await
+                else {
+                    break;
+                };
+                for (hop_waypoint, e, a_market, b_market) in route.hops {
+                    let required_fuel = if b_market {
+                        e.fuel_cost
+                    } else {
+                        e.fuel_cost + route.req_terminal_fuel
+                    };
+                    if fuel < required_fuel && a_market {
+                        fuel = required_fuel;
+                    }
+                    fuel -= e.fuel_cost;
+                    time += ChronoDuration::try_seconds(e.travel_duration).unwrap();
+                    hops.push(PlannedHop {
+                        waypoint: hop_waypoint,
+                        flight_mode: e.flight_mode,
+                        arrival: time,
+                        fuel_on_arrival: fuel,
+                    });
+                }
+            }
+            position = waypoint.clone();
+        }
+        planned.push(PlannedAction {
+            waypoint,
+            action,
+            eta: time,
+            hops,
+        });
+    }
+    planned
+}
+
+#[debug_handler]
+async fn ship_route_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<axum::Json<ShipRouteView>, StatusCode> {
+    let ship = state
+        .agent_controller
+        .ship(&symbol)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let schedule = state
+        .db_client
+        .load_schedule(&symbol)
+        .await
+        .map_err(db_error)?;
+    let progress = state
+        .db_client
+        .load_schedule_progress(&symbol)
+        .await
+        .map_err(db_error)?
+        .map(|p| p.finished_count())
+        .unwrap_or(0);
+    let remaining = match schedule {
+        Some(schedule) => schedule
+            .actions
+            .into_iter()
+            .skip(progress)
+            .map(|a| (a.waypoint, a.action))
+            .collect(),
+        None => vec![],
+    };
+    let planned_actions = project_actions(state.universe.as_ref(), &ship, remaining).await;
+    Ok(axum::Json(ShipRouteView {
+        symbol,
+        in_transit: ship.nav.route.arrival > Utc::now(),
+        nav_route: ship.nav.route.clone(),
+        planned_actions,
+    }))
+}
+
+#[debug_handler]
+async fn ship_cargo_value_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<axum::Json<CargoValuation>, StatusCode> {
+    let ship = state
+        .agent_controller
+        .ship(&symbol)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let system = ship.nav.route.destination.system_symbol.clone();
+    let valuation = CargoValuer::new(state.universe.clone())
+        .value_cargo(&system, &ship.cargo)
+        .await;
+    Ok(axum::Json(valuation))
+}
+
+#[derive(Debug, Serialize)]
+struct ChartStats {
+    submitted: i64,
+    queued: usize,
+    // waypoints charted by us this reset
+    charted: Vec<WaypointSymbol>,
+}
+
+#[debug_handler]
+async fn charts_handler(State(state): State<Arc<AppState>>) -> axum::Json<ChartStats> {
+    axum::Json(ChartStats {
+        submitted: state.agent_controller.charts_submitted(),
+        queued: state.agent_controller.chart_queue.len(),
+        charted: state
+            .universe
+            .charted_by(&state.agent_controller.agent().symbol),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct SurveySharing {
+    balance: SurveyBalance,
+    consumption: Vec<SurveyConsumption>,
+}
+
+#[debug_handler]
+async fn survey_sharing_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<axum::Json<SurveySharing>, StatusCode> {
+    let callsign = state.agent_controller.agent().symbol;
+    let consumption = state
+        .db_client
+        .get_survey_consumption()
+        .await
+        .map_err(db_error)?;
+    Ok(axum::Json(SurveySharing {
+        balance: survey_balance(&consumption, &callsign),
+        consumption,
+    }))
+}
+
+#[debug_handler]
+async fn system_health_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<axum::Json<MarketHealthReport>, StatusCode> {
+    let system = parse_system(&symbol)?;
+    let saved = state
+        .db_client
+        .get_market_health(&system)
+        .await
+        .map_err(db_error)?;
+    let report = match saved {
+        Some(report) => report,
+        None => {
+            market_health::generate_report(state.universe.as_ref(), &state.db_client, &system).await
+        }
+    };
+    Ok(axum::Json(report))
+}
+
+#[debug_handler]
+async fn warp_route_handler(
+    State(state): State<Arc<AppState>>,
+    Path((src, dest)): Path<(String, String)>,
+) -> Result<axum::Json<WarpRoute>, StatusCode> {
+    let (src, dest) = (parse_system(&src)?, parse_system(&dest)?);
+    let route = state
+        .universe
+        .known_warp_route(&src, &dest)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(axum::Json(route))
+}
+
+#[derive(Debug, Deserialize)]
+struct MarketDeltasQuery {
+    since: DateTime<Utc>,
+}
+
+#[debug_handler]
+async fn market_deltas_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(query): Query<MarketDeltasQuery>,
+) -> Result<axum::Json<MarketDeltas>, StatusCode> {
+    let system = parse_system(&symbol)?;
+    Ok(axum::Json(
+        state.universe.market_deltas_since(&system, query.since),
+    ))
+}
+
+#[debug_handler]
+async fn expansion_candidates_handler(
+    State(state): State<Arc<AppState>>,
+) -> axum::Json<Vec<ExpansionCandidate>> {
+    axum::Json(state.agent_controller.expansion_candidates().await)
+}
+
+#[debug_handler]
+async fn expansion_override_handler(
+    State(state): State<Arc<AppState>>,
+) -> axum::Json<ExpansionOverride> {
+    axum::Json(state.agent_controller.expansion_override().await)
+}
+
+#[debug_handler]
+async fn set_expansion_override_handler(
+    State(state): State<Arc<AppState>>,
+    axum::Json(expansion_override): axum::Json<ExpansionOverride>,
+) -> axum::Json<ExpansionOverride> {
+    state
+        .agent_controller
+        .set_expansion_override(&expansion_override)
+        .await;
+    axum::Json(expansion_override)
+}
+
+#[debug_handler]
+async fn job_filter_handler(State(state): State<Arc<AppState>>) -> axum::Json<JobFilter> {
+    axum::Json(state.agent_controller.job_filter())
+}
+
+#[debug_handler]
+async fn set_job_filter_handler(
+    State(state): State<Arc<AppState>>,
+    axum::Json(job_filter): axum::Json<JobFilter>,
+) -> Result<axum::Json<JobFilter>, (StatusCode, String)> {
+    state
+        .agent_controller
+        .set_job_filter(job_filter.clone())
+        .await
+        .map(|()| axum::Json(job_filter))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[debug_handler]
+async fn wind_downs_handler(State(state): State<Arc<AppState>>) -> axum::Json<Vec<WindDownStatus>> {
+    axum::Json(state.agent_controller.wind_down_statuses())
+}
+
+#[derive(Debug, Deserialize)]
+struct WindDownRequest {
+    park_at: Option<WaypointSymbol>,
+}
+
+#[debug_handler]
+async fn wind_down_system_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    axum::Json(request): axum::Json<WindDownRequest>,
+) -> Result<axum::Json<WindDown>, (StatusCode, String)> {
+    let system_symbol =
+        parse_system(&symbol).map_err(|status| (status, "Invalid system symbol".to_string()))?;
+    let wind_down = WindDown {
+        system_symbol,
+        park_at: request.park_at,
+        since: Utc::now(),
+        completed_at: None,
+    };
+    state
+        .agent_controller
+        .wind_down_system(wind_down.clone())
+        .await
+        .map(|()| axum::Json(wind_down))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[debug_handler]
+async fn resume_system_handler(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> StatusCode {
+    let system_symbol = match parse_system(&symbol) {
+        Ok(system_symbol) => system_symbol,
+        Err(status) => return status,
+    };
+    match state.agent_controller.resume_system(&system_symbol).await {
+        true => StatusCode::NO_CONTENT,
+        false => StatusCode::NOT_FOUND,
+    }
+}
+
+#[debug_handler]
+async fn manual_tasks_handler(State(state): State<Arc<AppState>>) -> axum::Json<Vec<Task>> {
+    axum::Json(state.agent_controller.task_manager.manual_tasks())
+}
+
+#[debug_handler]
+async fn submit_manual_task_handler(
+    State(state): State<Arc<AppState>>,
+    axum::Json(task): axum::Json<Task>,
+) -> Result<axum::Json<Task>, (StatusCode, String)> {
+    state
+        .agent_controller
+        .task_manager
+        .submit_manual_task(task)
+        .await
+        .map(axum::Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[derive(Debug, Deserialize)]
+struct ConvoyRequest {
+    ships: Vec<String>,
+    target: WaypointSymbol,
+}
+
+#[debug_handler]
+async fn dispatch_convoy_handler(
+    State(state): State<Arc<AppState>>,
+    axum::Json(request): axum::Json<ConvoyRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .agent_controller
+        .dispatch_convoy(&request.ships, &request.target)
+        .await
+        .map(|()| StatusCode::ACCEPTED)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+#[debug_handler]
+async fn fuel_handler(State(state): State<Arc<AppState>>) -> axum::Json<FuelReport> {
+    axum::Json(state.universe.fuel_report())
+}
+
+#[debug_handler]
+async fn net_worth_handler(State(state): State<Arc<AppState>>) -> axum::Json<NetWorth> {
+    axum::Json(state.agent_controller.net_worth().await)
+}
+
+#[debug_handler]
+async fn net_worth_history_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<axum::Json<Vec<NetWorthSample>>, StatusCode> {
+    let since = Utc::now() - ChronoDuration::try_days(14).unwrap();
+    let callsign = state.agent_controller.agent().symbol;
+    let history = state
+        .db_client
+        .get_net_worth_history(&callsign, since)
+        .await
+        .map_err(db_error)?;
+    Ok(axum::Json(history))
+}
+
+#[derive(Debug, Deserialize)]
+struct ConstructionDeliveriesQuery {
+    waypoint: Option<WaypointSymbol>,
+}
+
+#[debug_handler]
+async fn construction_deliveries_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ConstructionDeliveriesQuery>,
+) -> Result<axum::Json<Vec<ConstructionContribution>>, StatusCode> {
+    let contributions = state
+        .db_client
+        .get_construction_contributions(query.waypoint.as_ref())
+        .await
+        .map_err(db_error)?;
+    Ok(axum::Json(contributions))
+}
+
+#[derive(Debug, Deserialize)]
+struct AssignmentHistoryQuery {
+    ship: Option<String>,
+}
+
+#[debug_handler]
+async fn assignment_history_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AssignmentHistoryQuery>,
+) -> Result<axum::Json<Vec<JobAssignment>>, StatusCode> {
+    let callsign = state.agent_controller.agent().symbol;
+    let history = state
+        .db_client
+        .get_job_assignment_history(&callsign, query.ship.as_deref())
+        .await
+        .map_err(db_error)?;
+    Ok(axum::Json(history))
+}
+
+#[debug_handler]
+async fn ship_prices_handler(
+    State(state): State<Arc<AppState>>,
+    Path(ship_type): Path<String>,
+) -> Result<axum::Json<Vec<ShipListingSample>>, StatusCode> {
+    let since = Utc::now() - ChronoDuration::try_days(7).unwrap();
+    let history = state
+        .db_client
+        .get_ship_listing_history(&ship_type, since)
+        .await
+        .map_err(db_error)?;
+    Ok(axum::Json(history))
+}
+
 #[debug_handler]
 async fn starting_waypoints_handler(
     State(state): State<Arc<AppState>>,
 ) -> Result<axum::Json<Vec<WaypointDetailed>>, StatusCode> {
     let system_symbol = state.agent_controller.starting_system();
-    let waypoints = state.universe.get_system_waypoints(&system_symbol).await;
+    let waypoints = state
+        .universe
+        .known_system_waypoints(&system_symbol)
+        .ok_or(StatusCode::NOT_FOUND)?;
     Ok(axum::Json(waypoints))
 }
 
@@ -69,22 +579,63 @@ async fn capital_waypoints_handler(
     State(state): State<Arc<AppState>>,
 ) -> Result<axum::Json<Vec<WaypointDetailed>>, StatusCode> {
     let system_symbol = state.agent_controller.faction_capital().await;
-    let waypoints = state.universe.get_system_waypoints(&system_symbol).await;
+    let waypoints = state
+        .universe
+        .known_system_waypoints(&system_symbol)
+        .ok_or(StatusCode::NOT_FOUND)?;
     Ok(axum::Json(waypoints))
 }
 
+#[debug_handler]
+async fn event_metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> axum::Json<Vec<SubscriberMetrics>> {
+    axum::Json(state.agent_controller.event_metrics())
+}
+
+#[debug_handler]
+async fn rate_limit_metrics_handler(
+    State(state): State<Arc<AppState>>,
+) -> axum::Json<RateLimitMetrics> {
+    axum::Json(state.agent_controller.rate_limit_metrics())
+}
+
 #[debug_handler]
 async fn handler() -> () {}
 
-async fn background_task(io: SocketIo, mut rx: tokio::sync::mpsc::Receiver<Event>) {
-    while let Some(event) = rx.recv().await {
+async fn background_task(io: SocketIo, mut events: EventSubscriber) {
+    while let Some(event) = events.recv().await {
         match event {
-            Event::ShipUpdate(ship) => {
-                io.of("/").unwrap().emit("ship_upd", ship).unwrap();
+            Event::ShipUpdate(diff) => {
+                io.of("/").unwrap().emit("ship_upd", &*diff).unwrap();
             }
             Event::AgentUpdate(agent) => {
                 io.of("/").unwrap().emit("agent_upd", agent).unwrap();
             }
+            Event::GoalCompleted(goal) => {
+                io.of("/").unwrap().emit("goal_completed", goal).unwrap();
+            }
+            Event::NetWorthMilestone(milestone) => {
+                io.of("/")
+                    .unwrap()
+                    .emit("net_worth_milestone", milestone)
+                    .unwrap();
+            }
+            Event::OpsReport(report) => {
+                io.of("/").unwrap().emit("ops_report", &*report).unwrap();
+            }
+            Event::ShipStatus(update) => {
+                io.of("/").unwrap().emit("ship_status", update).unwrap();
+            }
+            Event::ShipState(update) => {
+                io.of("/").unwrap().emit("ship_state", update).unwrap();
+            }
+            Event::Onboarding(progress) => {
+                io.of("/").unwrap().emit("onboarding", progress).unwrap();
+            }
+            Event::ConvoyArrived(arrival) => {
+                io.of("/").unwrap().emit("convoy_arrived", arrival).unwrap();
+            }
         }
     }
 }
@@ -93,15 +644,20 @@ impl WebApiServer {
     pub fn new(
         agent_controller: &AgentController,
         db_client: &DbClient,
-        universe: &Arc<Universe>,
+        universe: &UniverseHandle,
     ) -> Self {
         Self {
             agent_controller: agent_controller.clone(),
             db_client: db_client.clone(),
-            universe: universe.clone(),
+            universe: universe.reader(),
+            port: DEFAULT_PORT,
         }
     }
 
+    pub fn with_port(self, port: u16) -> Self {
+        Self { port, ..self }
+    }
+
     pub async fn run(&self) {
         info!("Starting server");
 
@@ -126,13 +682,11 @@ impl WebApiServer {
             });
         });
 
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
-
+        let events = self.agent_controller.subscribe_events("web_server");
         let hdl = {
             let io = io.clone();
-            tokio::spawn(background_task(io, rx))
+            tokio::spawn(background_task(io, events))
         };
-        self.agent_controller.add_event_listener(tx);
 
         let shared_state = Arc::new(AppState {
             agent_controller: self.agent_controller.clone(),
@@ -143,6 +697,52 @@ impl WebApiServer {
         let app = axum::Router::new()
             .route("/api/agent", get(agent_handler))
             .route("/api/ships", get(ships_handler))
+            .route("/api/ships/:symbol/route", get(ship_route_handler))
+            .route(
+                "/api/ships/:symbol/cargo_value",
+                get(ship_cargo_value_handler),
+            )
+            .route("/api/fuel", get(fuel_handler))
+            .route(
+                "/api/expansion/candidates",
+                get(expansion_candidates_handler),
+            )
+            .route(
+                "/api/expansion/override",
+                get(expansion_override_handler).post(set_expansion_override_handler),
+            )
+            .route("/api/net_worth", get(net_worth_handler))
+            .route("/api/net_worth/history", get(net_worth_history_handler))
+            .route("/api/assignments/history", get(assignment_history_handler))
+            .route(
+                "/api/construction/deliveries",
+                get(construction_deliveries_handler),
+            )
+            .route("/api/ship_prices/:ship_type", get(ship_prices_handler))
+            .route("/api/goals", get(goals_handler))
+            .route("/api/credit_forecast", get(credit_forecast_handler))
+            .route(
+                "/api/tasks/manual",
+                get(manual_tasks_handler).post(submit_manual_task_handler),
+            )
+            .route("/api/convoy", post(dispatch_convoy_handler))
+            .route(
+                "/api/job_filter",
+                get(job_filter_handler).post(set_job_filter_handler),
+            )
+            .route("/api/charts", get(charts_handler))
+            .route("/api/survey_sharing", get(survey_sharing_handler))
+            .route("/api/warp_route/:src/:dest", get(warp_route_handler))
+            .route("/api/systems/:symbol/health", get(system_health_handler))
+            .route(
+                "/api/systems/:symbol/wind_down",
+                post(wind_down_system_handler).delete(resume_system_handler),
+            )
+            .route("/api/wind_downs", get(wind_downs_handler))
+            .route(
+                "/api/systems/:symbol/market-deltas",
+                get(market_deltas_handler),
+            )
             .route(
                 "/api/starter_system/waypoints",
                 get(starting_waypoints_handler),
@@ -151,11 +751,15 @@ impl WebApiServer {
                 "/api/capital_system/waypoints",
                 get(capital_waypoints_handler),
             )
+            .route("/api/events/metrics", get(event_metrics_handler))
+            .route("/api/rate_limit/metrics", get(rate_limit_metrics_handler))
             .route("/api/events", get(handler).layer(socketio_layer))
             .with_state(shared_state)
             .layer(CorsLayer::permissive());
 
-        let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", self.port))
+            .await
+            .unwrap();
         let server = async {
             info!("Listening on {}", listener.local_addr().unwrap());
             axum::serve(listener, app).await.unwrap();
@@ -164,3 +768,28 @@ impl WebApiServer {
         let _ = tokio::join!(hdl, server);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Needs a database: DATABASE_URL=... cargo test --features mock_server -- --ignored
+    #[cfg(feature = "mock_server")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_malformed_system_symbol() {
+        use crate::test_harness::TestAgent;
+        let test = TestAgent::builder().build().await;
+        let state = Arc::new(AppState {
+            agent_controller: test.agent_controller.clone(),
+            db_client: test.db.clone(),
+            universe: test.universe.reader(),
+        });
+        let status = resume_system_handler(State(state.clone()), Path("X1TEST".to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let status = resume_system_handler(State(state.clone()), Path("X1-TEST".to_string())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let report = system_health_handler(State(state), Path("X1-TEST-A1".to_string())).await;
+        assert_eq!(report.err(), Some(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "mock_server")]
+
+use serde_json::{json, Value};
+use st::api_client::ApiClient;
+use st::mock_server::{world, MockServer};
+use st::models::*;
+
+#[tokio::test]
+async fn test_mock_server_trade_loop() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    // read by CONFIG, which the client's request alerts depend on
+    std::env::set_var("API_BASE_URL", &base_url);
+    tokio::spawn(MockServer::new(0.0).serve(listener));
+
+    let api_client = ApiClient::with_base_url(&base_url);
+    let status = api_client.status().await.unwrap();
+    assert_eq!(status.stats.systems, 1);
+
+    let token = api_client.register("", "MOCK").await.unwrap();
+    api_client.set_agent_token(&token);
+    let agent = api_client.get_agent().await.unwrap();
+    assert_eq!(agent.headquarters, WaypointSymbol::new(world::HEADQUARTERS));
+    let ships = api_client.get_all_ships().await.unwrap();
+    assert_eq!(ships.len(), 2);
+    let waypoints = api_client
+        .get_system_waypoints(&SystemSymbol::new(world::SYSTEM_SYMBOL))
+        .await
+        .unwrap();
+    assert_eq!(waypoints.len(), 8);
+
+    // fly the command ship to the IRON export and buy twice: the second purchase costs more
+    let ship = "MOCK-1";
+    let _: Value = api_client
+        .try_post(&format!("/my/ships/{}/orbit", ship), &json!({}))
+        .await
+        .unwrap();
+    let mut response: Value = api_client
+        .try_post(
+            &format!("/my/ships/{}/navigate", ship),
+            &json!({ "waypointSymbol": "X1-TEST-A2" }),
+        )
+        .await
+        .unwrap();
+    // A2 shares A1's coordinates: the minimum distance of 1
+    let fuel: ShipFuel = serde_json::from_value(response["data"]["fuel"].take()).unwrap();
+    assert_eq!(fuel.consumed.amount, 1);
+    let _: Value = api_client
+        .try_post(&format!("/my/ships/{}/dock", ship), &json!({}))
+        .await
+        .unwrap();
+    let market: Data<Market> = api_client
+        .try_get("/systems/X1-TEST/waypoints/X1-TEST-A2/market")
+        .await
+        .unwrap();
+    assert!(market.data.trade_goods.iter().any(|g| g.symbol == "IRON"));
+
+    let mut prices = vec![];
+    for _ in 0..2 {
+        let mut response: Value = api_client
+            .try_post(
+                &format!("/my/ships/{}/purchase", ship),
+                &json!({ "symbol": "IRON", "units": 20 }),
+            )
+            .await
+            .unwrap();
+        let transaction: MarketTransaction =
+            serde_json::from_value(response["data"]["transaction"].take()).unwrap();
+        prices.push(transaction.price_per_unit);
+    }
+    assert!(prices[1] > prices[0]);
+
+    // sell at the import
+    let _: Value = api_client
+        .try_post(&format!("/my/ships/{}/orbit", ship), &json!({}))
+        .await
+        .unwrap();
+    let _: Value = api_client
+        .try_post(
+            &format!("/my/ships/{}/navigate", ship),
+            &json!({ "waypointSymbol": "X1-TEST-A1" }),
+        )
+        .await
+        .unwrap();
+    let _: Value = api_client
+        .try_post(&format!("/my/ships/{}/dock", ship), &json!({}))
+        .await
+        .unwrap();
+    let mut response: Value = api_client
+        .try_post(
+            &format!("/my/ships/{}/sell", ship),
+            &json!({ "symbol": "IRON", "units": 40 }),
+        )
+        .await
+        .unwrap();
+    let cargo: ShipCargo = serde_json::from_value(response["data"]["cargo"].take()).unwrap();
+    assert_eq!(cargo.units, 0);
+    let agent = api_client.get_agent().await.unwrap();
+    assert!(agent.credits > 175_000);
+}